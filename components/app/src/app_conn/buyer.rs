@@ -2,7 +2,7 @@ use proto::crypto::{InvoiceId, PaymentId, PublicKey, Uid};
 
 use proto::app_server::messages::AppRequest;
 use proto::funder::messages::{
-    AckClosePayment, CreatePayment, CreateTransaction, Currency, FriendsRoute,
+    AckClosePayment, CreatePayment, CreateTransaction, Currency, CurrencyExchange, FriendsRoute,
 };
 
 pub fn create_payment(
@@ -23,12 +23,33 @@ pub fn create_payment(
     AppRequest::CreatePayment(create_payment)
 }
 
+/// Create a push payment: A payment that does not require a prior invoice from the seller.
+/// `invoice_id` should be randomly generated by the buyer.
+pub fn create_push_payment(
+    payment_id: PaymentId,
+    invoice_id: InvoiceId,
+    currency: Currency,
+    total_dest_payment: u128,
+    dest_public_key: PublicKey,
+) -> AppRequest {
+    let create_payment = CreatePayment {
+        payment_id,
+        invoice_id,
+        currency,
+        total_dest_payment,
+        dest_public_key,
+    };
+
+    AppRequest::CreatePushPayment(create_payment)
+}
+
 pub fn create_transaction(
     payment_id: PaymentId,
     request_id: Uid,
     route: FriendsRoute,
     dest_payment: u128,
     fees: u128,
+    exchanges: Vec<CurrencyExchange>,
 ) -> AppRequest {
     let create_transaction = CreateTransaction {
         payment_id,
@@ -36,6 +57,7 @@ pub fn create_transaction(
         route,
         dest_payment,
         fees,
+        exchanges,
     };
 
     AppRequest::CreateTransaction(create_transaction)
@@ -1,10 +1,11 @@
-use proto::crypto::{PublicKey, Signature};
+use proto::crypto::{PublicKey, Signature, Uid};
 
 use proto::app_server::messages::{
     AppRequest, CloseFriendCurrency, NamedRelayAddress, OpenFriendCurrency, RelayAddress,
 };
 use proto::funder::messages::{
-    AddFriend, Currency, Rate, RemoveFriendCurrency, ResetFriendChannel, SetFriendCurrencyMaxDebt,
+    AddFriend, CancelFriendSwap, Currency, ExchangeRate, FriendSwap, Rate, RemoveExchangeRate,
+    RemoveFriendCurrency, ResetFriendChannel, SetExchangeRate, SetFriendCurrencyMaxDebt,
     SetFriendCurrencyRate, SetFriendName, SetFriendRelays,
 };
 use proto::index_server::messages::NamedIndexServerAddress;
@@ -105,6 +106,93 @@ pub fn set_friend_currency_rate(
     AppRequest::SetFriendCurrencyRate(set_friend_currency_rate)
 }
 
+/// Forward requests received in `src_currency` as requests in `dest_currency`.
+pub fn set_exchange_rate(
+    src_currency: Currency,
+    dest_currency: Currency,
+    rate: ExchangeRate,
+) -> AppRequest {
+    let set_exchange_rate = SetExchangeRate {
+        src_currency,
+        dest_currency,
+        rate,
+    };
+    AppRequest::SetExchangeRate(set_exchange_rate)
+}
+
+pub fn remove_exchange_rate(src_currency: Currency, dest_currency: Currency) -> AppRequest {
+    let remove_exchange_rate = RemoveExchangeRate {
+        src_currency,
+        dest_currency,
+    };
+    AppRequest::RemoveExchangeRate(remove_exchange_rate)
+}
+
+/// Propose to a friend to give `give_amount` of `give_currency` in return for `take_amount`
+/// of `take_currency`.
+pub fn propose_swap(
+    friend_public_key: PublicKey,
+    swap_id: Uid,
+    give_currency: Currency,
+    give_amount: u128,
+    take_currency: Currency,
+    take_amount: u128,
+) -> AppRequest {
+    let friend_swap = FriendSwap {
+        friend_public_key,
+        swap_id,
+        give_currency,
+        give_amount,
+        take_currency,
+        take_amount,
+    };
+    AppRequest::ProposeSwap(friend_swap)
+}
+
+/// Accept a swap proposed by a friend.
+/// The swap is only performed if the proposed terms match the given terms.
+pub fn accept_swap(
+    friend_public_key: PublicKey,
+    swap_id: Uid,
+    give_currency: Currency,
+    give_amount: u128,
+    take_currency: Currency,
+    take_amount: u128,
+) -> AppRequest {
+    let friend_swap = FriendSwap {
+        friend_public_key,
+        swap_id,
+        give_currency,
+        give_amount,
+        take_currency,
+        take_amount,
+    };
+    AppRequest::AcceptSwap(friend_swap)
+}
+
+/// Withdraw a local swap proposal, or decline a swap proposed by a friend.
+pub fn cancel_swap(friend_public_key: PublicKey, swap_id: Uid) -> AppRequest {
+    let cancel_friend_swap = CancelFriendSwap {
+        friend_public_key,
+        swap_id,
+    };
+    AppRequest::CancelSwap(cancel_friend_swap)
+}
+
+/// Cooperatively close the channel with a friend.
+/// No new transactions are allowed with this friend. Once all pending transactions are resolved,
+/// both sides sign over the final balances.
+pub fn close_friend(friend_public_key: PublicKey) -> AppRequest {
+    AppRequest::CloseFriend(friend_public_key)
+}
+
+/// Abort an ongoing cooperative close with a friend.
+/// Currencies closed during the close attempt remain closed for new requests, and should be
+/// reopened explicitly.
+pub fn abort_close_friend(friend_public_key: PublicKey) -> AppRequest {
+    AppRequest::AbortCloseFriend(friend_public_key)
+}
+
 pub fn reset_friend_channel(friend_public_key: PublicKey, reset_token: Signature) -> AppRequest {
     // TODO: Check if a reset confusion attack is possible here.
     // Maybe we (locally) should be the ones generating the reset token.
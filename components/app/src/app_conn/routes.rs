@@ -4,9 +4,25 @@ use proto::app_server::messages::AppRequest;
 use proto::funder::messages::Currency;
 use proto::index_server::messages::{Edge, RequestRoutes};
 
+/// Constraints over the routes returned for a routes request.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RouteConstraints {
+    /// Directed edges (from, to) that must not show up in any route.
+    /// At most `MAX_EXCLUDE_EDGES` edges may be specified.
+    pub exclude_edges: Vec<(PublicKey, PublicKey)>,
+    /// Nodes that must not show up in any route.
+    /// At most `MAX_EXCLUDE_NODES` nodes may be specified.
+    pub exclude_nodes: Vec<PublicKey>,
+    /// Maximum amount of hops (Edges) in a route.
+    pub opt_max_hops: Option<u32>,
+    /// Maximum total fees for sending the requested capacity along a route.
+    pub opt_max_fees: Option<u128>,
+}
+
 pub fn request_routes(
     request_routes_id: Uid,
     currency: Currency,
+    dest_currency: Currency,
     capacity: u128,
     source: PublicKey,
     destination: PublicKey,
@@ -20,10 +36,51 @@ pub fn request_routes(
     let request_routes = RequestRoutes {
         request_id: request_routes_id,
         currency,
+        dest_currency,
         capacity,
         source,
         destination,
         opt_exclude,
+        exclude_edges: Vec::new(),
+        exclude_nodes: Vec::new(),
+        opt_max_hops: None,
+        opt_max_fees: None,
+    };
+
+    AppRequest::RequestRoutes(request_routes)
+}
+
+/// Request routes that satisfy the given constraints.
+pub fn request_routes_with_constraints(
+    request_routes_id: Uid,
+    currency: Currency,
+    dest_currency: Currency,
+    capacity: u128,
+    source: PublicKey,
+    destination: PublicKey,
+    constraints: RouteConstraints,
+) -> AppRequest {
+    let exclude_edges = constraints
+        .exclude_edges
+        .into_iter()
+        .map(|(from_public_key, to_public_key)| Edge {
+            from_public_key,
+            to_public_key,
+        })
+        .collect();
+
+    let request_routes = RequestRoutes {
+        request_id: request_routes_id,
+        currency,
+        dest_currency,
+        capacity,
+        source,
+        destination,
+        opt_exclude: None,
+        exclude_edges,
+        exclude_nodes: constraints.exclude_nodes,
+        opt_max_hops: constraints.opt_max_hops,
+        opt_max_fees: constraints.opt_max_fees,
     };
 
     AppRequest::RequestRoutes(request_routes)
@@ -1,7 +1,7 @@
 use proto::crypto::InvoiceId;
 
 use proto::app_server::messages::AppRequest;
-use proto::funder::messages::{AddInvoice, Commit, Currency};
+use proto::funder::messages::{AddInvoice, Commit, Currency, EnablePushPayments, HoldInvoice};
 
 pub fn add_invoice(
     invoice_id: InvoiceId,
@@ -23,3 +23,27 @@ pub fn cancel_invoice(invoice_id: InvoiceId) -> AppRequest {
 pub fn commit_invoice(commit: Commit) -> AppRequest {
     AppRequest::CommitInvoice(commit)
 }
+
+/// Keep a fully paid invoice held (Funds locked along the route) until it is committed or
+/// canceled. The invoice is canceled automatically after `max_hold_ticks`.
+pub fn hold_invoice(commit: Commit, max_hold_ticks: u64) -> AppRequest {
+    let hold_invoice = HoldInvoice {
+        commit,
+        max_hold_ticks,
+    };
+    AppRequest::HoldInvoice(hold_invoice)
+}
+
+/// Accept push payments (Payments without a prior invoice) in the given currency,
+/// up to `max_total_dest_payment` credits per payment.
+pub fn enable_push_payments(currency: Currency, max_total_dest_payment: u128) -> AppRequest {
+    let enable_push_payments = EnablePushPayments {
+        currency,
+        max_total_dest_payment,
+    };
+    AppRequest::EnablePushPayments(enable_push_payments)
+}
+
+pub fn disable_push_payments(currency: Currency) -> AppRequest {
+    AppRequest::DisablePushPayments(currency)
+}
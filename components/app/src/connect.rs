@@ -1,8 +1,11 @@
+#[cfg(unix)]
+use std::convert::TryInto;
+use std::net::SocketAddr;
 use std::time::Duration;
 
 use futures::task::Spawn;
 
-use common::conn::ConnPair;
+use common::conn::{ConnPair, FuncFutTransform, FutTransform};
 use common::int_convert::usize_to_u64;
 
 use proto::app_server::messages::{AppPermissions, AppServerToApp, AppToAppServer, NodeReport};
@@ -15,6 +18,8 @@ use crypto::rand::system_random;
 
 use identity::IdentityClient;
 use net::TcpConnector;
+#[cfg(unix)]
+use net::UnixConnector;
 use timer::create_timer;
 
 use app_client::app_connect_to_node;
@@ -28,6 +33,10 @@ pub type AppConnTuple = (AppPermissions, NodeReport, ConnPairApp);
 #[derive(Debug)]
 pub struct ConnectError;
 
+/// A node address with this prefix is a path to a Unix domain socket.
+/// For example: `unix:/run/offset/stnode.sock`
+pub const UNIX_ADDRESS_PREFIX: &str = "unix:";
+
 /// Connect to a remote offset-node.
 pub async fn connect<S>(
     node_public_key: PublicKey,
@@ -35,6 +44,28 @@ pub async fn connect<S>(
     app_identity_client: IdentityClient,
     spawner: S,
 ) -> Result<AppConnTuple, ConnectError>
+where
+    S: Spawn + Clone + Send + 'static,
+{
+    connect_with_proxy(
+        node_public_key,
+        node_net_address,
+        app_identity_client,
+        None,
+        spawner,
+    )
+    .await
+}
+
+/// Connect to a remote offset-node, optionally through a SOCKS5 proxy (For example: Tor).
+/// The proxy is not used for Unix domain socket addresses.
+pub async fn connect_with_proxy<S>(
+    node_public_key: PublicKey,
+    node_net_address: NetAddress,
+    app_identity_client: IdentityClient,
+    opt_socks5_proxy: Option<SocketAddr>,
+    spawner: S,
+) -> Result<AppConnTuple, ConnectError>
 where
     S: Spawn + Clone + Send + 'static,
 {
@@ -46,10 +77,34 @@ where
     let timer_client = create_timer(dur, spawner.clone()).map_err(|_| ConnectError)?;
 
     // A tcp connector, Used to connect to remote servers:
-    let tcp_connector = TcpConnector::new(MAX_FRAME_LENGTH, spawner.clone());
+    let tcp_connector =
+        TcpConnector::new_with_proxy(MAX_FRAME_LENGTH, opt_socks5_proxy, spawner.clone());
+    // A Unix domain socket connector, Used to connect to local nodes:
+    #[cfg(unix)]
+    let unix_connector = UnixConnector::new(MAX_FRAME_LENGTH, spawner.clone());
+
+    // Pick a connector according to the address of the node:
+    let connector = FuncFutTransform::new(move |net_address: NetAddress| {
+        let mut c_tcp_connector = tcp_connector.clone();
+        #[cfg(unix)]
+        let mut c_unix_connector = unix_connector.clone();
+        Box::pin(async move {
+            let address = net_address.as_str();
+            if address.starts_with(UNIX_ADDRESS_PREFIX) {
+                #[cfg(unix)]
+                {
+                    let path = address[UNIX_ADDRESS_PREFIX.len()..].to_owned();
+                    return c_unix_connector.transform(path.try_into().ok()?).await;
+                }
+                #[cfg(not(unix))]
+                return None;
+            }
+            c_tcp_connector.transform(net_address).await
+        })
+    });
 
     let secure_connector = create_secure_connector(
-        tcp_connector,
+        connector,
         timer_client,
         app_identity_client,
         rng,
@@ -17,6 +17,9 @@ mod types;
 /// Utils for random generation of types
 pub mod gen;
 
+/// Compact URI encoding of invoices
+pub mod payment_uri;
+
 /// Utils for serializing and deserializing
 pub mod ser_utils {
     pub use common::ser_utils::*;
@@ -32,10 +35,11 @@ pub mod common {
         Signature, Uid,
     };
     pub use proto::funder::messages::{
-        Commit, Currency, FriendsRoute, PaymentStatus, PaymentStatusSuccess, Rate, Receipt,
+        Commit, Currency, CurrencyExchange, ExchangeRate, FriendsRoute, PaymentStatus,
+        PaymentStatusSuccess, Rate, Receipt,
     };
     pub use proto::index_server::messages::{
-        MultiRoute, NamedIndexServerAddress, RouteCapacityRate,
+        MultiRoute, NamedIndexServerAddress, RouteCapacityRate, RouteExchange,
     };
     pub use proto::net::messages::NetAddress;
 }
@@ -46,7 +50,9 @@ pub use proto::file;
 /// Offset connection
 pub mod conn {
     pub use super::app_conn::{buyer, config, routes, seller};
-    pub use super::connect::{connect, AppConnTuple, ConnPairApp, ConnectError};
+    pub use super::connect::{
+        connect, connect_with_proxy, AppConnTuple, ConnPairApp, ConnectError, UNIX_ADDRESS_PREFIX,
+    };
     pub use super::identity::{identity_from_file, IdentityFromFileError};
     pub use proto::app_server::messages::{
         AppPermissions, AppRequest, AppServerToApp, AppToAppServer,
@@ -60,13 +66,15 @@ pub mod conn {
 pub mod report {
     pub use proto::report::messages::{
         AddFriendReport, ChannelConsistentReport, ChannelInconsistentReport, ChannelStatusReport,
-        CurrencyConfigReport, CurrencyReport, FriendLivenessReport, FriendReport,
-        FriendStatusReport, FunderReport, McBalanceReport, MoveTokenHashedReport,
+        ClosedFriendReport, CurrencyConfigReport, CurrencyReport, ExchangeRateReport,
+        FriendCloseStatusReport, FriendLivenessReport, FriendReport, FriendStatusReport,
+        FunderReport, McBalanceReport, MoveTokenHashedReport, RelayHealthReport,
         RequestsStatusReport, ResetTermsReport,
     };
 
     pub use proto::funder::messages::{
-        BalanceInfo, CountersInfo, CurrencyBalance, CurrencyBalanceInfo, McInfo, TokenInfo,
+        BalanceInfo, CountersInfo, CurrencyBalance, CurrencyBalanceInfo, FriendSettlement, McInfo,
+        TokenInfo,
     };
 
     pub use proto::app_server::messages::NodeReport;
@@ -75,5 +83,7 @@ pub mod report {
 
 /// Verification functions
 pub mod verify {
-    pub use signature::verify::{verify_commit, verify_move_token_hashed_report, verify_receipt};
+    pub use signature::verify::{
+        verify_commit, verify_friend_settlement, verify_move_token_hashed_report, verify_receipt,
+    };
 }
@@ -0,0 +1,261 @@
+use std::convert::TryFrom;
+
+use crypto::hash::sha_512_256;
+use proto::crypto::{InvoiceId, PublicKey};
+use proto::funder::messages::Currency;
+
+/// Prefix of every payment URI
+pub const PAYMENT_URI_SCHEME: &str = "offset:";
+
+/// Current version of the payment URI encoding
+const PAYMENT_URI_VERSION: u8 = 0;
+
+/// Maximum length (in bytes) of an invoice description
+pub const MAX_DESCRIPTION_LEN: usize = 0x100;
+
+/// Amount of bytes of the checksum appended to the encoded payload
+const CHECKSUM_LEN: usize = 4;
+
+const FLAG_EXPIRY: u8 = 0x1;
+const FLAG_DESCRIPTION: u8 = 0x2;
+
+/// An invoice, as handed by a seller to a buyer.
+/// Can be rendered as a compact, checksummed URI (Suitable for QR codes).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PaymentUri {
+    pub invoice_id: InvoiceId,
+    pub currency: Currency,
+    pub dest_public_key: PublicKey,
+    pub dest_payment: u128,
+    /// Free text description of the invoice
+    pub opt_description: Option<String>,
+    /// Expiry time, in seconds since UNIX epoch
+    pub opt_expiry: Option<u64>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PaymentUriError {
+    InvalidScheme,
+    Base64Error,
+    InvalidChecksum,
+    UnsupportedVersion,
+    Truncated,
+    InvalidCurrency,
+    DescriptionTooLong,
+    InvalidDescription,
+    TrailingData,
+}
+
+fn checksum(data: &[u8]) -> [u8; CHECKSUM_LEN] {
+    let hash = sha_512_256(data);
+    let mut res = [0u8; CHECKSUM_LEN];
+    res.copy_from_slice(&hash[..CHECKSUM_LEN]);
+    res
+}
+
+/// Render a payment URI into a string of the form `offset:<payload>`.
+/// `payload` is a base64 (url safe) encoding of the invoice fields, followed by a checksum.
+pub fn render_payment_uri(payment_uri: &PaymentUri) -> Result<String, PaymentUriError> {
+    let mut data = Vec::new();
+    data.push(PAYMENT_URI_VERSION);
+    data.extend_from_slice(&payment_uri.invoice_id);
+    data.extend_from_slice(&payment_uri.dest_public_key);
+    data.extend_from_slice(&payment_uri.dest_payment.to_be_bytes());
+
+    let currency_bytes = payment_uri.currency.as_str().as_bytes();
+    // Currency length is bounded by MAX_CURRENCY_LEN, which fits in a byte:
+    data.push(u8::try_from(currency_bytes.len()).map_err(|_| PaymentUriError::InvalidCurrency)?);
+    data.extend_from_slice(currency_bytes);
+
+    let mut flags = 0u8;
+    if payment_uri.opt_expiry.is_some() {
+        flags |= FLAG_EXPIRY;
+    }
+    if payment_uri.opt_description.is_some() {
+        flags |= FLAG_DESCRIPTION;
+    }
+    data.push(flags);
+
+    if let Some(expiry) = payment_uri.opt_expiry {
+        data.extend_from_slice(&expiry.to_be_bytes());
+    }
+    if let Some(description) = &payment_uri.opt_description {
+        let description_bytes = description.as_bytes();
+        if description_bytes.len() > MAX_DESCRIPTION_LEN {
+            return Err(PaymentUriError::DescriptionTooLong);
+        }
+        data.extend_from_slice(&(description_bytes.len() as u16).to_be_bytes());
+        data.extend_from_slice(description_bytes);
+    }
+
+    let checksum = checksum(&data);
+    data.extend_from_slice(&checksum);
+
+    Ok(format!(
+        "{}{}",
+        PAYMENT_URI_SCHEME,
+        base64::encode_config(&data, base64::URL_SAFE_NO_PAD)
+    ))
+}
+
+/// A simple reader over a slice of bytes
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn read(&mut self, len: usize) -> Result<&'a [u8], PaymentUriError> {
+        if self.data.len() < len {
+            return Err(PaymentUriError::Truncated);
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn read_u8(&mut self) -> Result<u8, PaymentUriError> {
+        Ok(self.read(1)?[0])
+    }
+
+    fn read_array<T>(&mut self, len: usize) -> Result<T, PaymentUriError>
+    where
+        T: TryFrom<&'a [u8]>,
+    {
+        T::try_from(self.read(len)?).map_err(|_| PaymentUriError::Truncated)
+    }
+}
+
+/// Parse a payment URI previously created using `render_payment_uri`.
+pub fn parse_payment_uri(uri: &str) -> Result<PaymentUri, PaymentUriError> {
+    let uri = uri.trim();
+    if !uri.starts_with(PAYMENT_URI_SCHEME) {
+        return Err(PaymentUriError::InvalidScheme);
+    }
+    let data = base64::decode_config(&uri[PAYMENT_URI_SCHEME.len()..], base64::URL_SAFE_NO_PAD)
+        .map_err(|_| PaymentUriError::Base64Error)?;
+
+    if data.len() < CHECKSUM_LEN {
+        return Err(PaymentUriError::Truncated);
+    }
+    let (data, data_checksum) = data.split_at(data.len() - CHECKSUM_LEN);
+    if checksum(data) != data_checksum {
+        return Err(PaymentUriError::InvalidChecksum);
+    }
+
+    let mut reader = Reader { data };
+    if reader.read_u8()? != PAYMENT_URI_VERSION {
+        return Err(PaymentUriError::UnsupportedVersion);
+    }
+
+    let invoice_id: InvoiceId = reader.read_array(InvoiceId::len())?;
+    let dest_public_key: PublicKey = reader.read_array(PublicKey::len())?;
+    let dest_payment: [u8; 16] = reader.read_array(16)?;
+    let dest_payment = u128::from_be_bytes(dest_payment);
+
+    let currency_len = usize::from(reader.read_u8()?);
+    let currency_str = std::str::from_utf8(reader.read(currency_len)?)
+        .map_err(|_| PaymentUriError::InvalidCurrency)?;
+    let currency = Currency::try_from(currency_str.to_owned())
+        .map_err(|_| PaymentUriError::InvalidCurrency)?;
+
+    let flags = reader.read_u8()?;
+
+    let opt_expiry = if flags & FLAG_EXPIRY != 0 {
+        let expiry: [u8; 8] = reader.read_array(8)?;
+        Some(u64::from_be_bytes(expiry))
+    } else {
+        None
+    };
+
+    let opt_description = if flags & FLAG_DESCRIPTION != 0 {
+        let description_len: [u8; 2] = reader.read_array(2)?;
+        let description_len = usize::from(u16::from_be_bytes(description_len));
+        if description_len > MAX_DESCRIPTION_LEN {
+            return Err(PaymentUriError::DescriptionTooLong);
+        }
+        let description = std::str::from_utf8(reader.read(description_len)?)
+            .map_err(|_| PaymentUriError::InvalidDescription)?;
+        Some(description.to_owned())
+    } else {
+        None
+    };
+
+    if !reader.data.is_empty() {
+        return Err(PaymentUriError::TrailingData);
+    }
+
+    Ok(PaymentUri {
+        invoice_id,
+        currency,
+        dest_public_key,
+        dest_payment,
+        opt_description,
+        opt_expiry,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example_payment_uri() -> PaymentUri {
+        PaymentUri {
+            invoice_id: InvoiceId::from(&[1u8; InvoiceId::len()]),
+            currency: Currency::try_from("FST".to_owned()).unwrap(),
+            dest_public_key: PublicKey::from(&[0xbb; PublicKey::len()]),
+            dest_payment: 10u128,
+            opt_description: None,
+            opt_expiry: None,
+        }
+    }
+
+    #[test]
+    fn test_payment_uri_render_parse() {
+        let payment_uri = example_payment_uri();
+        let uri = render_payment_uri(&payment_uri).unwrap();
+        assert!(uri.starts_with(PAYMENT_URI_SCHEME));
+        assert_eq!(parse_payment_uri(&uri).unwrap(), payment_uri);
+
+        let mut payment_uri = example_payment_uri();
+        payment_uri.opt_description = Some("Two cups of coffee".to_owned());
+        payment_uri.opt_expiry = Some(1_600_000_000);
+        let uri = render_payment_uri(&payment_uri).unwrap();
+        assert_eq!(parse_payment_uri(&uri).unwrap(), payment_uri);
+    }
+
+    #[test]
+    fn test_payment_uri_checksum() {
+        let uri = render_payment_uri(&example_payment_uri()).unwrap();
+
+        // Flip one character of the payload:
+        let mut chars: Vec<char> = uri.chars().collect();
+        let index = PAYMENT_URI_SCHEME.len() + 5;
+        chars[index] = if chars[index] == 'A' { 'B' } else { 'A' };
+        let corrupt_uri: String = chars.into_iter().collect();
+
+        assert_eq!(
+            parse_payment_uri(&corrupt_uri),
+            Err(PaymentUriError::InvalidChecksum)
+        );
+    }
+
+    #[test]
+    fn test_payment_uri_invalid_scheme() {
+        let uri = render_payment_uri(&example_payment_uri()).unwrap();
+        let other_uri = uri.replacen(PAYMENT_URI_SCHEME, "bitcoin:", 1);
+        assert_eq!(
+            parse_payment_uri(&other_uri),
+            Err(PaymentUriError::InvalidScheme)
+        );
+    }
+
+    #[test]
+    fn test_payment_uri_description_too_long() {
+        let mut payment_uri = example_payment_uri();
+        payment_uri.opt_description = Some("a".repeat(MAX_DESCRIPTION_LEN + 1));
+        assert_eq!(
+            render_payment_uri(&payment_uri),
+            Err(PaymentUriError::DescriptionTooLong)
+        );
+    }
+}
@@ -130,6 +130,7 @@ fn check_request_permissions<B>(
         AppRequest::AddInvoice(_) => app_permissions.seller,
         AppRequest::CancelInvoice(_) => app_permissions.seller,
         AppRequest::CommitInvoice(_) => app_permissions.seller,
+        AppRequest::HoldInvoice(_) => app_permissions.seller,
 
         AppRequest::AddFriend(_) => app_permissions.config,
         AppRequest::SetFriendRelays(_) => app_permissions.config,
@@ -146,6 +147,16 @@ fn check_request_permissions<B>(
         AppRequest::RequestRoutes(_) => app_permissions.routes,
         AppRequest::AddIndexServer(_) => app_permissions.config,
         AppRequest::RemoveIndexServer(_) => app_permissions.config,
+        AppRequest::CreatePushPayment(_) => app_permissions.buyer,
+        AppRequest::EnablePushPayments(_) => app_permissions.seller,
+        AppRequest::DisablePushPayments(_) => app_permissions.seller,
+        AppRequest::SetExchangeRate(_) => app_permissions.config,
+        AppRequest::RemoveExchangeRate(_) => app_permissions.config,
+        AppRequest::ProposeSwap(_) => app_permissions.config,
+        AppRequest::AcceptSwap(_) => app_permissions.config,
+        AppRequest::CancelSwap(_) => app_permissions.config,
+        AppRequest::CloseFriend(_) => app_permissions.config,
+        AppRequest::AbortCloseFriend(_) => app_permissions.config,
     }
 }
 
@@ -464,6 +475,10 @@ where
             AddInvoice(x) => to_funder!(AddInvoice(x)),
             CancelInvoice(x) => to_funder!(CancelInvoice(x)),
             CommitInvoice(x) => to_funder!(CommitInvoice(x)),
+            HoldInvoice(x) => to_funder!(HoldInvoice(x)),
+            CreatePushPayment(x) => to_funder!(CreatePushPayment(x)),
+            EnablePushPayments(x) => to_funder!(EnablePushPayments(x)),
+            DisablePushPayments(x) => to_funder!(DisablePushPayments(x)),
             AddFriend(x) => to_funder!(AddFriend(x)),
             SetFriendRelays(x) => to_funder!(SetFriendRelays(x)),
             SetFriendName(x) => to_funder!(SetFriendName(x)),
@@ -471,6 +486,11 @@ where
             SetFriendCurrencyRate(x) => to_funder!(SetFriendCurrencyRate(x)),
             RemoveFriendCurrency(x) => to_funder!(RemoveFriendCurrency(x)),
             ResetFriendChannel(x) => to_funder!(ResetFriendChannel(x)),
+            SetExchangeRate(x) => to_funder!(SetExchangeRate(x)),
+            RemoveExchangeRate(x) => to_funder!(RemoveExchangeRate(x)),
+            ProposeSwap(x) => to_funder!(ProposeSwap(x)),
+            AcceptSwap(x) => to_funder!(AcceptSwap(x)),
+            CancelSwap(x) => to_funder!(CancelSwap(x)),
             CreateTransaction(create_transaction) => {
                 // Keep track of which application issued this request:
                 self.transactions
@@ -481,6 +501,15 @@ where
                 let remove_friend = proto::funder::messages::RemoveFriend { friend_public_key };
                 to_funder!(RemoveFriend(remove_friend))
             }
+            CloseFriend(friend_public_key) => {
+                let close_friend = proto::funder::messages::CloseFriend { friend_public_key };
+                to_funder!(CloseFriend(close_friend))
+            }
+            AbortCloseFriend(friend_public_key) => {
+                let abort_close_friend =
+                    proto::funder::messages::AbortCloseFriend { friend_public_key };
+                to_funder!(AbortCloseFriend(abort_close_friend))
+            }
             EnableFriend(friend_public_key) => {
                 let set_friend_status = SetFriendStatus {
                     friend_public_key,
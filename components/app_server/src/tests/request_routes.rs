@@ -92,10 +92,15 @@ where
     let request_routes = RequestRoutes {
         request_id: Uid::from(&[3; Uid::len()]),
         currency: currency1.clone(),
+        dest_currency: currency1.clone(),
         capacity: 250,
         source: PublicKey::from(&[0xee; PublicKey::len()]),
         destination: PublicKey::from(&[0xff; PublicKey::len()]),
         opt_exclude: None,
+        exclude_edges: Vec::new(),
+        exclude_nodes: Vec::new(),
+        opt_max_hops: None,
+        opt_max_fees: None,
     };
 
     let to_app_server = AppToAppServer::new(
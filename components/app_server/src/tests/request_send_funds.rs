@@ -125,6 +125,7 @@ where
         },
         dest_payment: 20,
         fees: 4,
+        exchanges: Vec::new(),
     };
     let to_app_server = AppToAppServer::new(
         Uid::from(&[23; Uid::len()]),
@@ -63,6 +63,8 @@ where
             .into_iter()
             .collect(),
         friends: HashMap::new(),
+        exchange_rates: Vec::new(),
+        closed_friends: Vec::new(),
     };
 
     let server100 = NamedIndexServerAddress {
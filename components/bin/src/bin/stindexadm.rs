@@ -0,0 +1,28 @@
+#![deny(trivial_numeric_casts, warnings)]
+#![allow(broken_intra_doc_links)]
+#![allow(
+    clippy::too_many_arguments,
+    clippy::implicit_hasher,
+    clippy::module_inception,
+    clippy::new_without_default
+)]
+
+#[macro_use]
+extern crate log;
+
+use structopt::StructOpt;
+
+use bin::stindexadm::{stindexadm, IndexAdmError, StIndexAdmCmd};
+
+fn run() -> Result<(), IndexAdmError> {
+    env_logger::init();
+
+    let st_index_adm_cmd = StIndexAdmCmd::from_args();
+    stindexadm(st_index_adm_cmd)
+}
+
+fn main() {
+    if let Err(e) = run() {
+        error!("run() error: {:?}", e);
+    }
+}
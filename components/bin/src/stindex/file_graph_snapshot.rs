@@ -0,0 +1,92 @@
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use futures::channel::mpsc;
+use futures::StreamExt;
+
+use derive_more::From;
+
+use proto::file::{IndexGraphEdgeFile, IndexGraphSnapshotFile};
+use proto::ser_string::{deserialize_from_string, serialize_to_string, StringSerdeError};
+
+use index_server::{CapacityEdge, IndexGraphSnapshot, SnapshotEdge};
+
+#[derive(Debug, From)]
+pub enum GraphSnapshotFileError {
+    IoError(std::io::Error),
+    StringSerdeError(StringSerdeError),
+}
+
+fn snapshot_to_file(graph_snapshot: IndexGraphSnapshot) -> IndexGraphSnapshotFile {
+    let mut edges = Vec::new();
+    for (currency, snapshot_edges) in graph_snapshot {
+        for snapshot_edge in snapshot_edges {
+            edges.push(IndexGraphEdgeFile {
+                currency: currency.clone(),
+                from_public_key: snapshot_edge.from,
+                to_public_key: snapshot_edge.to,
+                recv_capacity: snapshot_edge.capacity_edge.recv_capacity,
+                rate: snapshot_edge.capacity_edge.rate,
+                age: snapshot_edge.age,
+            });
+        }
+    }
+    IndexGraphSnapshotFile { edges }
+}
+
+fn snapshot_from_file(graph_snapshot_file: IndexGraphSnapshotFile) -> IndexGraphSnapshot {
+    let mut graph_snapshot = IndexGraphSnapshot::new();
+    for edge_file in graph_snapshot_file.edges {
+        graph_snapshot
+            .entry(edge_file.currency)
+            .or_insert_with(Vec::new)
+            .push(SnapshotEdge {
+                from: edge_file.from_public_key,
+                to: edge_file.to_public_key,
+                capacity_edge: CapacityEdge::new(edge_file.recv_capacity, edge_file.rate),
+                age: edge_file.age,
+            });
+    }
+    graph_snapshot
+}
+
+/// Load a graph snapshot from file.
+/// Returns an empty snapshot if the file does not exist (Cold start).
+pub fn load_graph_snapshot(path: &Path) -> Result<IndexGraphSnapshot, GraphSnapshotFileError> {
+    if !path.exists() {
+        return Ok(IndexGraphSnapshot::new());
+    }
+    let graph_snapshot_file: IndexGraphSnapshotFile =
+        deserialize_from_string(&fs::read_to_string(path)?)?;
+    Ok(snapshot_from_file(graph_snapshot_file))
+}
+
+/// Write a graph snapshot to file.
+/// We first write to a temporary file and then rename it, so that a crash during the write will
+/// not leave us with a corrupt snapshot.
+async fn store_graph_snapshot(
+    path: &Path,
+    graph_snapshot: IndexGraphSnapshot,
+) -> Result<(), GraphSnapshotFileError> {
+    let data = serialize_to_string(&snapshot_to_file(graph_snapshot))?;
+
+    let mut temp_path = OsString::from(path);
+    temp_path.push(".tmp");
+
+    async_std::fs::write(&temp_path, data).await?;
+    async_std::fs::rename(&temp_path, path.as_os_str()).await?;
+    Ok(())
+}
+
+/// Store every incoming graph snapshot to file, overwriting the previous one.
+pub async fn store_graph_snapshots_loop(
+    path: PathBuf,
+    mut incoming_snapshots: mpsc::Receiver<IndexGraphSnapshot>,
+) {
+    while let Some(graph_snapshot) = incoming_snapshots.next().await {
+        if let Err(e) = store_graph_snapshot(&path, graph_snapshot).await {
+            error!("store_graph_snapshot() failed: {:?}", e);
+        }
+    }
+}
@@ -1,3 +1,4 @@
+mod file_graph_snapshot;
 mod net_index;
 mod stindexlib;
 
@@ -1,10 +1,9 @@
-use std::collections::HashMap;
-use std::fmt::Debug;
+use std::collections::{HashMap, HashSet};
 use std::marker::Unpin;
 
 use futures::channel::mpsc;
 use futures::task::{Spawn, SpawnExt};
-use futures::{FutureExt, SinkExt, Stream, StreamExt, TryFutureExt};
+use futures::{Future, FutureExt, SinkExt, Stream, StreamExt, TryFutureExt};
 
 use common::conn::{BoxFuture, ConnPair, ConnPairVec, FuncFutTransform, FutTransform};
 use common::transform_pool::transform_pool_loop;
@@ -12,8 +11,10 @@ use common::transform_pool::transform_pool_loop;
 use proto::consts::INDEX_NODE_TIMEOUT_TICKS;
 use proto::crypto::PublicKey;
 use proto::index_server::messages::{
-    IndexClientToServer, IndexServerToClient, IndexServerToServer,
+    IndexAdminToServer, IndexClientToServer, IndexServerToAdmin, IndexServerToClient,
+    IndexServerToServer,
 };
+use proto::net::messages::NetAddress;
 
 use proto::proto_ser::{ProtoDeserialize, ProtoSerialize};
 
@@ -25,7 +26,7 @@ use identity::IdentityClient;
 
 use connection::create_version_encrypt_keepalive;
 
-use index_server::{index_server, IndexServerError};
+use index_server::{index_server, ClientRateLimits, IndexGraphSnapshot, IndexServerError};
 
 #[derive(Clone)]
 struct ConnTransformer<CT, S> {
@@ -117,6 +118,54 @@ where
         })
     }
 
+    /// Transform a raw connection from an admin into connection with the following layers:
+    /// - Version prefix
+    /// - Encryption
+    /// - keepalives
+    /// - Serialization
+    pub fn incoming_index_admin_conn_transform(
+        &mut self,
+        conn_pair: ConnPairVec,
+    ) -> BoxFuture<'_, Option<(PublicKey, ConnPair<IndexServerToAdmin, IndexAdminToServer>)>> {
+        let mut c_self = self.clone();
+        Box::pin(async move {
+            let (public_key, conn_pair) = c_self.version_enc_keepalive(None, conn_pair).await?;
+
+            let (mut sender, mut receiver) = conn_pair.split();
+
+            let (user_sender, mut from_user_sender) = mpsc::channel::<IndexServerToAdmin>(0);
+            let (mut to_user_receiver, user_receiver) = mpsc::channel(0);
+
+            // Deserialize received data
+            let _ = c_self.spawner.spawn(async move {
+                while let Some(data) = receiver.next().await {
+                    let message = match IndexAdminToServer::proto_deserialize(&data) {
+                        Ok(message) => message,
+                        Err(_) => {
+                            error!("Error deserializing index_admin_to_server");
+                            return;
+                        }
+                    };
+                    if to_user_receiver.send(message).await.is_err() {
+                        return;
+                    }
+                }
+            });
+
+            // Serialize sent data:
+            let _ = c_self.spawner.spawn(async move {
+                while let Some(message) = from_user_sender.next().await {
+                    let data = message.proto_serialize();
+                    if sender.send(data).await.is_err() {
+                        return;
+                    }
+                }
+            });
+
+            Some((public_key, ConnPair::from_raw(user_sender, user_receiver)))
+        })
+    }
+
     pub fn incoming_index_server_conn_transform(
         &mut self,
         conn_pair: ConnPairVec,
@@ -222,25 +271,35 @@ pub enum NetIndexServerError {
     SpawnError,
 }
 
-pub async fn net_index_server<A, ICC, ISC, SC, R, GS, S>(
+pub async fn net_index_server<ICC, ISC, IAC, SC, R, SD, GS, S>(
     incoming_client_raw_conns: ICC,
     incoming_server_raw_conns: ISC,
+    incoming_admin_raw_conns: IAC,
+    admins: HashSet<PublicKey>,
     raw_server_net_connector: SC,
     identity_client: IdentityClient,
     timer_client: TimerClient,
     rng: R,
-    trusted_servers: HashMap<PublicKey, A>,
+    trusted_servers: HashMap<PublicKey, NetAddress>,
     max_concurrent_encrypt: usize,
     backoff_ticks: usize,
+    client_rate_limits: ClientRateLimits,
+    announce_ticks: usize,
+    graph_snapshot: IndexGraphSnapshot,
+    stale_ticks: usize,
+    snapshot_ticks: usize,
+    opt_snapshot_sender: Option<mpsc::Sender<IndexGraphSnapshot>>,
+    shutdown: SD,
     graph_service_spawner: GS,
     spawner: S,
 ) -> Result<(), NetIndexServerError>
 where
-    A: Clone + Send + Sync + Debug + 'static,
-    SC: FutTransform<Input = A, Output = Option<ConnPairVec>> + Clone + Send + 'static,
+    SC: FutTransform<Input = NetAddress, Output = Option<ConnPairVec>> + Clone + Send + 'static,
     ICC: Stream<Item = ConnPairVec> + Unpin + Send + 'static,
     ISC: Stream<Item = ConnPairVec> + Unpin + Send + 'static,
+    IAC: Stream<Item = ConnPairVec> + Unpin + Send + 'static,
     R: CryptoRandom + Clone + Send + Sync + 'static,
+    SD: Future<Output = ()> + Send + Unpin,
     GS: Spawn + Send + 'static,
     S: Spawn + Clone + Send + Sync + 'static,
 {
@@ -304,6 +363,29 @@ where
         .spawn(pool_fut)
         .map_err(|_| NetIndexServerError::SpawnError)?;
 
+    // Transform incoming admin connections:
+    let c_conn_transformer = conn_transformer.clone();
+    let incoming_admin_transform = FuncFutTransform::new(move |raw_conn| {
+        let mut c_conn_transformer = c_conn_transformer.clone();
+        Box::pin(async move {
+            c_conn_transformer
+                .incoming_index_admin_conn_transform(raw_conn)
+                .await
+        })
+    });
+    let (admin_conns_sender, incoming_admin_conns) = mpsc::channel(0);
+    let pool_fut = transform_pool_loop(
+        incoming_admin_raw_conns,
+        admin_conns_sender,
+        incoming_admin_transform,
+        max_concurrent_encrypt,
+    )
+    .map_err(|e| error!("admin incoming transform_pool_loop() error: {:?}", e))
+    .map(|_| ());
+    spawner
+        .spawn(pool_fut)
+        .map_err(|_| NetIndexServerError::SpawnError)?;
+
     // Apply transform to create server connector:
     let c_conn_transformer = conn_transformer.clone();
     let server_connector = FuncFutTransform::new(move |(public_key, net_address)| {
@@ -322,11 +404,20 @@ where
         trusted_servers,
         incoming_server_conns,
         incoming_client_conns,
+        incoming_admin_conns,
+        admins,
         server_connector,
         timer_client,
         INDEX_NODE_TIMEOUT_TICKS,
         backoff_ticks,
+        client_rate_limits,
+        announce_ticks,
         rng,
+        graph_snapshot,
+        stale_ticks,
+        snapshot_ticks,
+        opt_snapshot_sender,
+        shutdown,
         graph_service_spawner,
         spawner.clone(),
     )
@@ -1,12 +1,14 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use std::fs;
 use std::net::SocketAddr;
 use std::path::{Path, PathBuf};
 use std::time::Duration;
 
+use futures::channel::{mpsc, oneshot};
 use futures::executor::{block_on, ThreadPool};
 use futures::task::SpawnExt;
+use futures::{future, FutureExt};
 
 use structopt::StructOpt;
 
@@ -20,21 +22,31 @@ use identity::{create_identity, IdentityClient};
 
 use derive_more::From;
 
+use crate::stindex::file_graph_snapshot::{
+    load_graph_snapshot, store_graph_snapshots_loop, GraphSnapshotFileError,
+};
 use crate::stindex::net_index::{net_index_server, NetIndexServerError};
 use proto::consts::{MAX_FRAME_LENGTH, TICK_MS};
 use timer::create_timer;
 
 use net::{TcpConnector, TcpListener};
 
-use proto::file::{IdentityFile, IndexServerFile};
+use proto::crypto::PublicKey;
+use proto::file::{IdentityFile, IndexAdminFile, IndexServerFile};
 use proto::ser_string::{deserialize_from_string, StringSerdeError};
 
+use index_server::{ClientRateLimits, TokenBucketConfig};
+
 // TODO: Maybe take as a command line argument in the future?
 /// Maximum amount of concurrent encrypted channel set-ups.
 /// We set this number to avoid DoS from half finished encrypted channel negotiations.
 pub const MAX_CONCURRENT_ENCRYPT: usize = 0x200;
 /// Amount of ticks we wait before attempting to reconnect to a remote index server.
 pub const BACKOFF_TICKS: usize = 0x8;
+/// Amount of ticks between two snapshots of the index server's graphs.
+pub const SNAPSHOT_TICKS: usize = 5 * 60 * (1000 / TICK_MS); // 5 minutes
+/// Amount of ticks between two announcements of our trusted servers to the connected servers.
+pub const ANNOUNCE_TICKS: usize = 60 * (1000 / TICK_MS); // 1 minute
 
 /// stindex: Offset Index Server
 /// A server used to index the Offset network. Collects topology information from nodes, and serves
@@ -54,6 +66,40 @@ pub struct StIndexCmd {
     /// Directory path of trusted index servers
     #[structopt(parse(from_os_str), short = "t", long = "trusted")]
     pub trusted: PathBuf,
+    /// SOCKS5 proxy address (For example, Tor: 127.0.0.1:9050).
+    /// If specified, all outgoing connections to other index servers are made through the proxy.
+    #[structopt(long = "socks5")]
+    pub socks5: Option<SocketAddr>,
+    /// Graph snapshot file path.
+    /// If specified, the index server's graphs are periodically saved to this file (And once
+    /// more on shutdown), and loaded from it on startup.
+    #[structopt(parse(from_os_str), long = "snapshot")]
+    pub snapshot: Option<PathBuf>,
+    /// Amount of ticks (Seconds) to keep edges loaded from a graph snapshot if they are not
+    /// updated by their nodes
+    #[structopt(long = "stale-ticks", default_value = "600")]
+    pub stale_ticks: usize,
+    /// Listening address for admins
+    #[structopt(long = "ladmin")]
+    pub ladmin: Option<SocketAddr>,
+    /// Directory path of index server admins.
+    /// Only admins from this directory are allowed to connect to the admins listening address.
+    #[structopt(parse(from_os_str), long = "admins")]
+    pub admins: Option<PathBuf>,
+    /// Maximum burst of route requests from a single client
+    #[structopt(long = "routes-burst", default_value = "16")]
+    pub routes_burst: u64,
+    /// Amount of route requests a single client may send every tick (One second)
+    #[structopt(long = "routes-per-tick", default_value = "4")]
+    pub routes_per_tick: u64,
+    /// Maximum burst of mutations updates from a single client.
+    /// Should fit a client's full state (One update per friend currency), as a client exceeding
+    /// this limit is disconnected and sends its full state again on reconnection.
+    #[structopt(long = "mutations-burst", default_value = "256")]
+    pub mutations_burst: u64,
+    /// Amount of mutations updates a single client may send every tick (One second)
+    #[structopt(long = "mutations-per-tick", default_value = "8")]
+    pub mutations_per_tick: u64,
 }
 
 #[allow(clippy::enum_variant_names)]
@@ -68,6 +114,9 @@ pub enum IndexServerBinError {
     IoError(std::io::Error),
     ListenError,
     StringSerdeError(StringSerdeError),
+    GraphSnapshotFileError(GraphSnapshotFileError),
+    SpawnSnapshotStoreError,
+    SetShutdownHandlerError,
 }
 
 /// Load a directory of index server address files, and return a map representing
@@ -85,14 +134,50 @@ pub fn load_trusted_servers(dir_path: &Path) -> Result<Vec<IndexServerFile>, Ind
     Ok(res_trusted)
 }
 
+/// Load a directory of index admin files, and return the set of all admins public keys
+pub fn load_admins(dir_path: &Path) -> Result<HashSet<PublicKey>, IndexServerBinError> {
+    let mut res_admins = HashSet::new();
+    for entry in fs::read_dir(dir_path)? {
+        let entry = entry?;
+        let path = entry.path();
+        if path.is_dir() {
+            continue;
+        }
+        let index_admin_file: IndexAdminFile =
+            deserialize_from_string(&fs::read_to_string(&path)?)?;
+        res_admins.insert(index_admin_file.public_key);
+    }
+    Ok(res_admins)
+}
+
 pub fn stindex(st_index_cmd: StIndexCmd) -> Result<(), IndexServerBinError> {
     let StIndexCmd {
         idfile,
         lclient,
         lserver,
         trusted,
+        socks5,
+        snapshot,
+        stale_ticks,
+        ladmin,
+        admins,
+        routes_burst,
+        routes_per_tick,
+        mutations_burst,
+        mutations_per_tick,
     } = st_index_cmd;
 
+    let client_rate_limits = ClientRateLimits {
+        request_routes: TokenBucketConfig {
+            capacity: routes_burst,
+            refill_per_tick: routes_per_tick,
+        },
+        mutations_update: TokenBucketConfig {
+            capacity: mutations_burst,
+            refill_per_tick: mutations_per_tick,
+        },
+    };
+
     let identity_file: IdentityFile = deserialize_from_string(&fs::read_to_string(&idfile)?)?;
     let identity = SoftwareEd25519Identity::from_private_key(&identity_file.private_key)
         .map_err(|_| IndexServerBinError::LoadIdentityError)?;
@@ -139,14 +224,65 @@ pub fn stindex(st_index_cmd: StIndexCmd) -> Result<(), IndexServerBinError> {
     } = block_on(server_tcp_listener.listen(lserver))
         .map_err(|_| IndexServerBinError::ListenError)?;
 
+    // Start listening to admins (If required):
+    let (incoming_admin_raw_conns, admins) = if let Some(ladmin) = ladmin {
+        let admins = match admins {
+            Some(admins) => load_admins(&admins)?,
+            None => HashSet::new(),
+        };
+        let admin_tcp_listener = TcpListener::new(MAX_FRAME_LENGTH, thread_pool.clone());
+        let ListenerClient {
+            config_sender: _,
+            conn_receiver: incoming_admin_raw_conns,
+        } = block_on(admin_tcp_listener.listen(ladmin))
+            .map_err(|_| IndexServerBinError::ListenError)?;
+        (incoming_admin_raw_conns, admins)
+    } else {
+        // No admin connections:
+        let (_, incoming_admin_raw_conns) = mpsc::channel(0);
+        (incoming_admin_raw_conns, HashSet::new())
+    };
+
     // A tcp connector, Used to connect to remote servers:
-    let raw_server_net_connector = TcpConnector::new(MAX_FRAME_LENGTH, thread_pool.clone());
+    let raw_server_net_connector =
+        TcpConnector::new_with_proxy(MAX_FRAME_LENGTH, socks5, thread_pool.clone());
+
+    // Load the last graph snapshot (If exists), and keep storing new snapshots.
+    // On shutdown (SIGINT or SIGTERM), a final snapshot is stored before exiting:
+    let (graph_snapshot, opt_snapshot_sender, opt_store_handle, shutdown) =
+        if let Some(snapshot_path) = snapshot {
+            let graph_snapshot = load_graph_snapshot(&snapshot_path)?;
+            let (snapshot_sender, snapshot_receiver) = mpsc::channel(0);
+            let store_handle = thread_pool
+                .spawn_with_handle(store_graph_snapshots_loop(snapshot_path, snapshot_receiver))
+                .map_err(|_| IndexServerBinError::SpawnSnapshotStoreError)?;
+
+            let (shutdown_sender, shutdown_receiver) = oneshot::channel::<()>();
+            let mut opt_shutdown_sender = Some(shutdown_sender);
+            ctrlc::set_handler(move || {
+                if let Some(shutdown_sender) = opt_shutdown_sender.take() {
+                    let _ = shutdown_sender.send(());
+                }
+            })
+            .map_err(|_| IndexServerBinError::SetShutdownHandlerError)?;
+
+            (
+                graph_snapshot,
+                Some(snapshot_sender),
+                Some(store_handle),
+                shutdown_receiver.map(|_| ()).boxed(),
+            )
+        } else {
+            (HashMap::new(), None, None, future::pending().boxed())
+        };
 
     let rng = system_random();
 
     let index_server_fut = net_index_server(
         incoming_client_raw_conns,
         incoming_server_raw_conns,
+        incoming_admin_raw_conns,
+        admins,
         raw_server_net_connector,
         identity_client,
         timer_client,
@@ -154,11 +290,24 @@ pub fn stindex(st_index_cmd: StIndexCmd) -> Result<(), IndexServerBinError> {
         trusted_servers,
         MAX_CONCURRENT_ENCRYPT,
         BACKOFF_TICKS,
+        client_rate_limits,
+        ANNOUNCE_TICKS,
+        graph_snapshot,
+        stale_ticks,
+        SNAPSHOT_TICKS,
+        opt_snapshot_sender,
+        shutdown,
         graph_service_thread_pool,
         thread_pool,
     );
 
-    block_on(index_server_fut).map_err(IndexServerBinError::NetIndexServerError)?;
+    let res = block_on(index_server_fut);
+
+    // Wait until the last snapshot is stored:
+    if let Some(store_handle) = opt_store_handle {
+        block_on(store_handle);
+    }
 
+    res.map_err(IndexServerBinError::NetIndexServerError)?;
     Ok(())
 }
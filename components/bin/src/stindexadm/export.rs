@@ -0,0 +1,151 @@
+use std::collections::BTreeSet;
+use std::fmt::{self, Write};
+use std::str::FromStr;
+
+use proto::funder::messages::Currency;
+use proto::index_server::messages::AdminEdge;
+use proto::ser_string::public_key_to_string;
+
+/// A format for exporting a capacity graph
+#[derive(Debug, Clone, Copy)]
+pub enum ExportFormat {
+    Dot,
+    GraphMl,
+}
+
+#[derive(Debug)]
+pub struct ParseExportFormatError;
+
+impl fmt::Display for ParseExportFormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid export format. Expected dot or graphml")
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = ParseExportFormatError;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "dot" => Ok(ExportFormat::Dot),
+            "graphml" => Ok(ExportFormat::GraphMl),
+            _ => Err(ParseExportFormatError),
+        }
+    }
+}
+
+fn escape_dot(input: &str) -> String {
+    input.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+fn escape_xml(input: &str) -> String {
+    input
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Export a capacity graph in the DOT format (Graphviz)
+fn export_dot(currency: &Currency, edges: &[AdminEdge]) -> String {
+    let mut output = String::new();
+    writeln!(
+        output,
+        "digraph \"{}\" {{",
+        escape_dot(&currency.to_string())
+    )
+    .unwrap();
+    for edge in edges {
+        writeln!(
+            output,
+            "    \"{}\" -> \"{}\" [recv_capacity=\"{}\", rate_mul=\"{}\", rate_add=\"{}\", age=\"{}\"];",
+            public_key_to_string(&edge.from_public_key),
+            public_key_to_string(&edge.to_public_key),
+            edge.recv_capacity,
+            edge.rate.mul,
+            edge.rate.add,
+            edge.age
+        )
+        .unwrap();
+    }
+    writeln!(output, "}}").unwrap();
+    output
+}
+
+/// Export a capacity graph in the GraphML format
+fn export_graphml(currency: &Currency, edges: &[AdminEdge]) -> String {
+    let nodes: BTreeSet<_> = edges
+        .iter()
+        .flat_map(|edge| {
+            vec![
+                public_key_to_string(&edge.from_public_key),
+                public_key_to_string(&edge.to_public_key),
+            ]
+        })
+        .collect();
+
+    let mut output = String::new();
+    writeln!(output, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>").unwrap();
+    writeln!(
+        output,
+        "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">"
+    )
+    .unwrap();
+    for key in &["recv_capacity", "rate_mul", "rate_add", "age"] {
+        writeln!(
+            output,
+            "  <key id=\"{}\" for=\"edge\" attr.name=\"{}\" attr.type=\"string\"/>",
+            key, key
+        )
+        .unwrap();
+    }
+    writeln!(
+        output,
+        "  <graph id=\"{}\" edgedefault=\"directed\">",
+        escape_xml(&currency.to_string())
+    )
+    .unwrap();
+    for node in &nodes {
+        writeln!(output, "    <node id=\"{}\"/>", node).unwrap();
+    }
+    for edge in edges {
+        writeln!(
+            output,
+            "    <edge source=\"{}\" target=\"{}\">",
+            public_key_to_string(&edge.from_public_key),
+            public_key_to_string(&edge.to_public_key)
+        )
+        .unwrap();
+        writeln!(
+            output,
+            "      <data key=\"recv_capacity\">{}</data>",
+            edge.recv_capacity
+        )
+        .unwrap();
+        writeln!(
+            output,
+            "      <data key=\"rate_mul\">{}</data>",
+            edge.rate.mul
+        )
+        .unwrap();
+        writeln!(
+            output,
+            "      <data key=\"rate_add\">{}</data>",
+            edge.rate.add
+        )
+        .unwrap();
+        writeln!(output, "      <data key=\"age\">{}</data>", edge.age).unwrap();
+        writeln!(output, "    </edge>").unwrap();
+    }
+    writeln!(output, "  </graph>").unwrap();
+    writeln!(output, "</graphml>").unwrap();
+    output
+}
+
+/// Export a capacity graph in the requested format
+pub fn export_graph(currency: &Currency, edges: &[AdminEdge], format: ExportFormat) -> String {
+    match format {
+        ExportFormat::Dot => export_dot(currency, edges),
+        ExportFormat::GraphMl => export_graphml(currency, edges),
+    }
+}
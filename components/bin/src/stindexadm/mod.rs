@@ -0,0 +1,4 @@
+mod export;
+mod stindexadmlib;
+
+pub use self::stindexadmlib::{stindexadm, IndexAdmError, StIndexAdmCmd};
@@ -0,0 +1,339 @@
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use futures::executor::{block_on, ThreadPool};
+use futures::task::SpawnExt;
+use futures::{SinkExt, StreamExt};
+
+use structopt::StructOpt;
+
+use derive_more::From;
+
+use common::conn::{ConnPairVec, FutTransform};
+use common::int_convert::usize_to_u64;
+
+use crypto::identity::SoftwareEd25519Identity;
+use crypto::rand::{system_random, RandGen};
+
+use identity::{create_identity, IdentityClient};
+
+use proto::consts::{MAX_FRAME_LENGTH, TICK_MS};
+use proto::crypto::Uid;
+use proto::file::{IdentityFile, IndexServerFile};
+use proto::funder::messages::Currency;
+use proto::index_server::messages::{
+    FederationServer, IndexAdminToServer, IndexServerAddress, IndexServerToAdmin, RequestAddServer,
+    RequestExportGraph, RequestNodeEdges, RequestRemoveServer,
+};
+use proto::proto_ser::{ProtoDeserialize, ProtoSerialize};
+use proto::ser_string::{
+    deserialize_from_string, public_key_to_string, string_to_public_key, StringSerdeError,
+};
+
+use connection::create_secure_connector;
+use net::TcpConnector;
+use timer::create_timer;
+
+use crate::stindexadm::export::{export_graph, ExportFormat};
+
+/// Print graph statistics for every currency
+#[derive(Debug, StructOpt)]
+pub struct StatsCmd {}
+
+/// Show all edges going out of a node or into a node
+#[derive(Debug, StructOpt)]
+pub struct NodeEdgesCmd {
+    /// Public key of the node
+    #[structopt(short = "n", long = "node")]
+    pub node_public_key: String,
+}
+
+/// Export the capacity graph of a currency
+#[derive(Debug, StructOpt)]
+pub struct ExportCmd {
+    /// Currency of the exported graph
+    #[structopt(short = "c", long = "currency")]
+    pub currency: String,
+    /// Export format (dot or graphml)
+    #[structopt(short = "f", long = "format", default_value = "dot")]
+    pub format: ExportFormat,
+    /// Output file path. If not specified, the graph is printed to stdout.
+    #[structopt(parse(from_os_str), short = "o", long = "output")]
+    pub output_path: Option<PathBuf>,
+}
+
+/// Show the federation topology: Trusted servers and the servers they announced
+#[derive(Debug, StructOpt)]
+pub struct FederationCmd {}
+
+/// Add a trusted index server at runtime
+#[derive(Debug, StructOpt)]
+pub struct AddServerCmd {
+    /// Index server ticket file path.
+    /// The address in the ticket should be the servers listening address of the added server.
+    #[structopt(parse(from_os_str), short = "t", long = "ticket")]
+    pub ticket: PathBuf,
+}
+
+/// Remove a trusted index server at runtime
+#[derive(Debug, StructOpt)]
+pub struct RemoveServerCmd {
+    /// Public key of the removed server
+    #[structopt(short = "n", long = "server")]
+    pub server_public_key: String,
+}
+
+#[derive(Debug, StructOpt)]
+pub enum AdminSubcommand {
+    /// Print graph statistics for every currency
+    #[structopt(name = "stats")]
+    Stats(StatsCmd),
+    /// Show all edges going out of a node or into a node
+    #[structopt(name = "node-edges")]
+    NodeEdges(NodeEdgesCmd),
+    /// Export the capacity graph of a currency
+    #[structopt(name = "export")]
+    Export(ExportCmd),
+    /// Show the federation topology: Trusted servers and the servers they announced
+    #[structopt(name = "federation")]
+    Federation(FederationCmd),
+    /// Add a trusted index server at runtime
+    #[structopt(name = "add-server")]
+    AddServer(AddServerCmd),
+    /// Remove a trusted index server at runtime
+    #[structopt(name = "remove-server")]
+    RemoveServer(RemoveServerCmd),
+}
+
+/// stindexadm: Offset Index Server Admin
+/// Query a running index server about the graph it holds, and manage its trusted servers.
+#[derive(Debug, StructOpt)]
+#[structopt(name = "stindexadm")]
+pub struct StIndexAdmCmd {
+    /// Admin identity file path
+    #[structopt(parse(from_os_str), short = "i", long = "idfile")]
+    pub idfile: PathBuf,
+    /// Index server ticket file path.
+    /// The address in the ticket should be the admins listening address of the index server.
+    #[structopt(parse(from_os_str), short = "t", long = "ticket")]
+    pub ticket: PathBuf,
+    /// SOCKS5 proxy address (For example, Tor: 127.0.0.1:9050).
+    /// If specified, the connection to the index server is made through the proxy.
+    #[structopt(long = "socks5")]
+    pub socks5: Option<SocketAddr>,
+    #[structopt(subcommand)]
+    pub subcommand: AdminSubcommand,
+}
+
+#[derive(Debug, From)]
+pub enum IndexAdmError {
+    CreateThreadPoolError,
+    CreateTimerError,
+    LoadIdentityError,
+    CreateIdentityError,
+    ConnectError,
+    SendRequestError,
+    ConnectionClosed,
+    DeserializeResponseError,
+    UnexpectedResponse,
+    InvalidPublicKey,
+    InvalidCurrency,
+    IoError(io::Error),
+    StringSerdeError(StringSerdeError),
+}
+
+/// Print the trusted servers of the index server, and the servers each of them announced.
+/// Announced servers that are not trusted are marked as discovered.
+fn print_federation(servers: &[FederationServer]) {
+    let trusted: HashSet<_> = servers
+        .iter()
+        .map(|server| &server.server_address.public_key)
+        .collect();
+    for server in servers {
+        println!(
+            "{} {} ({})",
+            public_key_to_string(&server.server_address.public_key),
+            server.server_address.address.as_str(),
+            if server.is_connected {
+                "connected"
+            } else {
+                "not connected"
+            }
+        );
+        for known_server in &server.known_servers {
+            println!(
+                "    -> {} {}{}",
+                public_key_to_string(&known_server.public_key),
+                known_server.address.as_str(),
+                if trusted.contains(&known_server.public_key) {
+                    ""
+                } else {
+                    " (discovered)"
+                }
+            );
+        }
+    }
+}
+
+async fn admin_request(
+    mut conn_pair: ConnPairVec,
+    request: IndexAdminToServer,
+) -> Result<IndexServerToAdmin, IndexAdmError> {
+    conn_pair
+        .sender
+        .send(request.proto_serialize())
+        .await
+        .map_err(|_| IndexAdmError::SendRequestError)?;
+    let data = conn_pair
+        .receiver
+        .next()
+        .await
+        .ok_or(IndexAdmError::ConnectionClosed)?;
+    IndexServerToAdmin::proto_deserialize(&data)
+        .map_err(|_| IndexAdmError::DeserializeResponseError)
+}
+
+pub fn stindexadm(st_index_adm_cmd: StIndexAdmCmd) -> Result<(), IndexAdmError> {
+    let StIndexAdmCmd {
+        idfile,
+        ticket,
+        socks5,
+        subcommand,
+    } = st_index_adm_cmd;
+
+    let identity_file: IdentityFile = deserialize_from_string(&fs::read_to_string(&idfile)?)?;
+    let identity = SoftwareEd25519Identity::from_private_key(&identity_file.private_key)
+        .map_err(|_| IndexAdmError::LoadIdentityError)?;
+
+    let index_server_file: IndexServerFile =
+        deserialize_from_string(&fs::read_to_string(&ticket)?)?;
+
+    let thread_pool = ThreadPool::new().map_err(|_| IndexAdmError::CreateThreadPoolError)?;
+
+    // Spawn identity service:
+    let (sender, identity_loop) = create_identity(identity);
+    thread_pool
+        .spawn(identity_loop)
+        .map_err(|_| IndexAdmError::CreateIdentityError)?;
+    let identity_client = IdentityClient::new(sender);
+
+    // Get a timer client:
+    let dur = Duration::from_millis(usize_to_u64(TICK_MS).unwrap());
+    let timer_client =
+        create_timer(dur, thread_pool.clone()).map_err(|_| IndexAdmError::CreateTimerError)?;
+
+    let mut rng = system_random();
+    let request_id = Uid::rand_gen(&mut rng);
+
+    let request = match &subcommand {
+        AdminSubcommand::Stats(_) => IndexAdminToServer::RequestGraphStats(request_id.clone()),
+        AdminSubcommand::NodeEdges(node_edges_cmd) => {
+            IndexAdminToServer::RequestNodeEdges(RequestNodeEdges {
+                request_id: request_id.clone(),
+                node_public_key: string_to_public_key(&node_edges_cmd.node_public_key)
+                    .map_err(|_| IndexAdmError::InvalidPublicKey)?,
+            })
+        }
+        AdminSubcommand::Export(export_cmd) => {
+            IndexAdminToServer::RequestExportGraph(RequestExportGraph {
+                request_id: request_id.clone(),
+                currency: Currency::try_from(export_cmd.currency.clone())
+                    .map_err(|_| IndexAdmError::InvalidCurrency)?,
+            })
+        }
+        AdminSubcommand::Federation(_) => IndexAdminToServer::RequestFederation(request_id.clone()),
+        AdminSubcommand::AddServer(add_server_cmd) => {
+            let added_server_file: IndexServerFile =
+                deserialize_from_string(&fs::read_to_string(&add_server_cmd.ticket)?)?;
+            IndexAdminToServer::RequestAddServer(RequestAddServer {
+                request_id: request_id.clone(),
+                server_address: IndexServerAddress {
+                    public_key: added_server_file.public_key,
+                    address: added_server_file.address,
+                },
+            })
+        }
+        AdminSubcommand::RemoveServer(remove_server_cmd) => {
+            IndexAdminToServer::RequestRemoveServer(RequestRemoveServer {
+                request_id: request_id.clone(),
+                public_key: string_to_public_key(&remove_server_cmd.server_public_key)
+                    .map_err(|_| IndexAdmError::InvalidPublicKey)?,
+            })
+        }
+    };
+
+    let tcp_connector = TcpConnector::new_with_proxy(MAX_FRAME_LENGTH, socks5, thread_pool.clone());
+    let mut secure_connector = create_secure_connector(
+        tcp_connector,
+        timer_client,
+        identity_client,
+        rng,
+        thread_pool.clone(),
+    );
+
+    let response = block_on(async move {
+        let conn_pair = secure_connector
+            .transform((index_server_file.public_key, index_server_file.address))
+            .await
+            .ok_or(IndexAdmError::ConnectError)?;
+        admin_request(conn_pair, request).await
+    })?;
+
+    match (subcommand, response) {
+        (AdminSubcommand::Stats(_), IndexServerToAdmin::ResponseGraphStats(response))
+            if response.request_id == request_id =>
+        {
+            for currency_stats in response.currencies_stats {
+                println!(
+                    "{}: {} nodes, {} edges",
+                    currency_stats.currency, currency_stats.num_nodes, currency_stats.num_edges
+                );
+            }
+        }
+        (AdminSubcommand::NodeEdges(_), IndexServerToAdmin::ResponseEdges(response))
+            if response.request_id == request_id =>
+        {
+            for edge in response.edges {
+                println!(
+                    "{}: {} -> {} recv_capacity={} rate=(mul={}, add={}) age={}",
+                    edge.currency,
+                    public_key_to_string(&edge.from_public_key),
+                    public_key_to_string(&edge.to_public_key),
+                    edge.recv_capacity,
+                    edge.rate.mul,
+                    edge.rate.add,
+                    edge.age
+                );
+            }
+        }
+        (AdminSubcommand::Export(export_cmd), IndexServerToAdmin::ResponseEdges(response))
+            if response.request_id == request_id =>
+        {
+            let currency = Currency::try_from(export_cmd.currency)
+                .map_err(|_| IndexAdmError::InvalidCurrency)?;
+            let output = export_graph(&currency, &response.edges, export_cmd.format);
+            match export_cmd.output_path {
+                Some(output_path) => {
+                    let mut file = File::create(output_path)?;
+                    file.write_all(output.as_bytes())?;
+                }
+                None => print!("{}", output),
+            }
+        }
+        (AdminSubcommand::Federation(_), IndexServerToAdmin::ResponseFederation(response))
+        | (AdminSubcommand::AddServer(_), IndexServerToAdmin::ResponseFederation(response))
+        | (AdminSubcommand::RemoveServer(_), IndexServerToAdmin::ResponseFederation(response))
+            if response.request_id == request_id =>
+        {
+            print_federation(&response.servers);
+        }
+        _ => return Err(IndexAdmError::UnexpectedResponse),
+    }
+
+    Ok(())
+}
@@ -18,7 +18,8 @@ use database::file_db::FileDb;
 use node::NodeState;
 
 use proto::file::{
-    IdentityFile, IndexServerFile, NodeAddressFile, NodeEntryFile, RelayAddressFile, TrustedAppFile,
+    IdentityFile, IndexAdminFile, IndexServerFile, NodeAddressFile, NodeEntryFile,
+    RelayAddressFile, RelayListenerFile, TrustedAppFile,
 };
 use proto::ser_string::{deserialize_from_string, serialize_to_string, StringSerdeError};
 
@@ -125,6 +126,26 @@ pub struct NodeEntryCmd {
     pub output_path: PathBuf,
 }
 
+#[derive(Debug, StructOpt)]
+pub struct IndexAdminTicketCmd {
+    /// StIndexAdm admin identity file path
+    #[structopt(parse(from_os_str), short = "i", long = "idfile")]
+    pub idfile_path: PathBuf,
+    /// Index admin ticket output file path
+    #[structopt(parse(from_os_str), short = "o", long = "output")]
+    pub output_path: PathBuf,
+}
+
+#[derive(Debug, StructOpt)]
+pub struct RelayListenerTicketCmd {
+    /// StNode node identity file path
+    #[structopt(parse(from_os_str), short = "i", long = "idfile")]
+    pub idfile_path: PathBuf,
+    /// Relay listener ticket output file path
+    #[structopt(parse(from_os_str), short = "o", long = "output")]
+    pub output_path: PathBuf,
+}
+
 /// stmgr: offSeT ManaGeR
 /// A util for managing Offset entities and files
 #[derive(Debug, StructOpt)]
@@ -142,9 +163,17 @@ pub enum StMgrCmd {
     /// Create a relay ticket
     #[structopt(name = "relay-ticket")]
     RelayTicket(RelayTicketCmd),
+    /// Create a relay listener ticket.
+    /// A private relay only allows listening to nodes with a ticket.
+    #[structopt(name = "relay-listener-ticket")]
+    RelayListenerTicket(RelayListenerTicketCmd),
     /// Create an index server ticket
     #[structopt(name = "index-ticket")]
     IndexTicket(IndexTicketCmd),
+    /// Create an index server admin ticket.
+    /// An index server only answers admin queries from admins with a ticket.
+    #[structopt(name = "index-admin-ticket")]
+    IndexAdminTicket(IndexAdminTicketCmd),
     /// Create a node server ticket
     #[structopt(name = "node-ticket")]
     NodeTicket(NodeTicketCmd),
@@ -343,6 +372,74 @@ fn index_ticket(
     Ok(())
 }
 
+#[derive(Debug, From)]
+pub enum RelayListenerTicketError {
+    OutputAlreadyExists,
+    LoadIdentityError,
+    IoError(std::io::Error),
+    StringSerdeError(StringSerdeError),
+}
+
+/// Create a relay listener ticket
+/// The ticket can be put in the listeners directory of a private relay
+fn relay_listener_ticket(
+    RelayListenerTicketCmd {
+        idfile_path,
+        output_path,
+    }: RelayListenerTicketCmd,
+) -> Result<(), RelayListenerTicketError> {
+    // Make sure that output does not exist.
+    if output_path.exists() {
+        return Err(RelayListenerTicketError::OutputAlreadyExists);
+    }
+
+    // Parse identity file:
+    let identity_file: IdentityFile = deserialize_from_string(&fs::read_to_string(&idfile_path)?)?;
+    let identity = SoftwareEd25519Identity::from_private_key(&identity_file.private_key)
+        .map_err(|_| RelayListenerTicketError::LoadIdentityError)?;
+    let public_key = identity.get_public_key();
+
+    let relay_listener_file = RelayListenerFile { public_key };
+
+    let mut file = File::create(output_path)?;
+    file.write_all(&serialize_to_string(&relay_listener_file)?.as_bytes())?;
+    Ok(())
+}
+
+#[derive(Debug, From)]
+pub enum IndexAdminTicketError {
+    OutputAlreadyExists,
+    LoadIdentityError,
+    IoError(std::io::Error),
+    StringSerdeError(StringSerdeError),
+}
+
+/// Create an index admin ticket
+/// The ticket can be put in the admins directory of an index server
+fn index_admin_ticket(
+    IndexAdminTicketCmd {
+        idfile_path,
+        output_path,
+    }: IndexAdminTicketCmd,
+) -> Result<(), IndexAdminTicketError> {
+    // Make sure that output does not exist.
+    if output_path.exists() {
+        return Err(IndexAdminTicketError::OutputAlreadyExists);
+    }
+
+    // Parse identity file:
+    let identity_file: IdentityFile = deserialize_from_string(&fs::read_to_string(&idfile_path)?)?;
+    let identity = SoftwareEd25519Identity::from_private_key(&identity_file.private_key)
+        .map_err(|_| IndexAdminTicketError::LoadIdentityError)?;
+    let public_key = identity.get_public_key();
+
+    let index_admin_file = IndexAdminFile { public_key };
+
+    let mut file = File::create(output_path)?;
+    file.write_all(&serialize_to_string(&index_admin_file)?.as_bytes())?;
+    Ok(())
+}
+
 #[derive(Debug, From)]
 pub enum NodeTicketError {
     OutputAlreadyExists,
@@ -425,7 +522,9 @@ pub enum StmError {
     GenIdentityError(GenIdentityError),
     AppTicketError(AppTicketError),
     RelayTicketError(RelayTicketError),
+    RelayListenerTicketError(RelayListenerTicketError),
     IndexTicketError(IndexTicketError),
+    IndexAdminTicketError(IndexAdminTicketError),
     NodeTicketError(NodeTicketError),
 }
 
@@ -435,7 +534,9 @@ pub fn stmgr(st_mgr_cmd: StMgrCmd) -> Result<(), StmError> {
         StMgrCmd::GenIdent(i) => gen_identity(i)?,
         StMgrCmd::AppTicket(i) => app_ticket(i)?,
         StMgrCmd::RelayTicket(i) => relay_ticket(i)?,
+        StMgrCmd::RelayListenerTicket(i) => relay_listener_ticket(i)?,
         StMgrCmd::IndexTicket(i) => index_ticket(i)?,
+        StMgrCmd::IndexAdminTicket(i) => index_admin_ticket(i)?,
         StMgrCmd::NodeTicket(i) => node_ticket(i)?,
         StMgrCmd::NodeEntry(i) => node_entry(i)?,
     }
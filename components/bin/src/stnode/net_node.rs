@@ -19,7 +19,7 @@ use proto::crypto::PublicKey;
 use proto::net::messages::NetAddress;
 use proto::proto_ser::{ProtoDeserialize, ProtoSerialize};
 
-use connection::{create_encrypt_keepalive, create_version_encrypt_keepalive};
+use connection::{create_encrypt_keepalive, create_version_encrypt_keepalive, PaddingConfig};
 
 use timer::TimerClient;
 
@@ -198,13 +198,16 @@ pub trait TrustedApps {
     ) -> BoxFuture<'a, Option<AppPermissions>>;
 }
 
-pub async fn net_node<IAC, C, R, TA, S>(
+pub async fn net_node<IAC, IDC, C, R, TA, S>(
     incoming_app_raw_conns: IAC,
+    incoming_direct_raw_conns: IDC,
     connector: C,
     timer_client: TimerClient,
     identity_client: IdentityClient,
     rng: R,
     node_config: NodeConfig,
+    opt_padding_config: Option<PaddingConfig>,
+    resume_sessions: bool,
     trusted_apps: TA,
     node_state: NodeState<NetAddress>,
     database_client: DatabaseClient<NodeMutation<NetAddress>>,
@@ -212,6 +215,7 @@ pub async fn net_node<IAC, C, R, TA, S>(
 ) -> Result<(), NetNodeError>
 where
     IAC: Stream<Item = ConnPairVec> + Unpin + Send + 'static,
+    IDC: Stream<Item = ConnPairVec> + Unpin + Send + 'static,
     C: FutTransform<Input = NetAddress, Output = Option<ConnPairVec>> + Clone + Send + 'static,
     R: CryptoRandom + Clone + Send + Sync + 'static,
     TA: TrustedApps + Send + Clone + 'static,
@@ -236,8 +240,9 @@ where
         spawner.clone(),
     );
 
+    let c_connector = connector.clone();
     let secure_connector = FuncFutTransform::new(move |(public_key, net_address)| {
-        let mut c_connector = connector.clone();
+        let mut c_connector = c_connector.clone();
         let mut c_conn_transform = conn_transform.clone();
         Box::pin(async move {
             let conn_pair = c_connector.transform(net_address).await?;
@@ -252,6 +257,8 @@ where
         timer_client.clone(),
         identity_client.clone(),
         rng.clone(),
+        opt_padding_config,
+        resume_sessions,
         spawner.clone(),
     );
 
@@ -262,7 +269,11 @@ where
         node_state,
         database_client,
         secure_connector,
+        // Direct connections to friends are only protected by the secure channel
+        // (encrypt_keepalive) set up with the friend:
+        connector,
         encrypt_keepalive,
+        incoming_direct_raw_conns,
         incoming_apps,
         rng,
         spawner.clone(),
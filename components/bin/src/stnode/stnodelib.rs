@@ -1,3 +1,4 @@
+use std::convert::TryInto;
 use std::fmt::Debug;
 use std::fs;
 use std::net::SocketAddr;
@@ -8,6 +9,7 @@ use derive_more::From;
 
 use futures::channel::mpsc;
 use futures::executor::{block_on, ThreadPool};
+use futures::stream::select_all;
 use futures::task::SpawnExt;
 use futures::{FutureExt, TryFutureExt};
 
@@ -22,10 +24,14 @@ use crypto::rand::system_random;
 use identity::{create_identity, IdentityClient};
 use timer::create_timer;
 
+use connection::PaddingConfig;
+
 use database::file_db::FileDb;
 use database::{database_loop, AtomicDb, DatabaseClient};
 
-use net::{TcpConnector, TcpListener};
+#[cfg(unix)]
+use net::UnixListener;
+use net::{TcpConnector, TcpListener, WsListener};
 use proto::consts::{
     KEEPALIVE_TICKS, MAX_FRAME_LENGTH, MAX_NODE_RELAYS, MAX_OPERATIONS_IN_BATCH, TICKS_TO_REKEY,
     TICK_MS,
@@ -69,6 +75,8 @@ pub enum NodeBinError {
     LoadDbError,
     SpawnError,
     ListenError,
+    NoListenAddress,
+    InvalidDirectAddress,
     NetNodeError(NetNodeError),
     // SerializeError(SerializeError),
     StringSerdeError(StringSerdeError),
@@ -86,25 +94,79 @@ pub struct StNodeCmd {
     /// StCtrl app identity file path
     #[structopt(parse(from_os_str), short = "i", long = "idfile")]
     pub idfile: PathBuf,
-    /// Listening address (Used for communication with apps)
+    /// Listening TCP address (Used for communication with apps).
+    /// May be specified multiple times.
     #[structopt(short = "l", long = "laddr")]
-    pub laddr: SocketAddr,
+    pub laddr: Vec<SocketAddr>,
+    /// Listening Unix domain socket path (Used for communication with local apps).
+    /// May be specified multiple times.
+    #[structopt(parse(from_os_str), long = "lunix")]
+    pub lunix: Vec<PathBuf>,
+    /// Listening WebSocket address (Used for communication with web apps).
+    /// May be specified multiple times.
+    #[structopt(long = "lws")]
+    pub lws: Vec<SocketAddr>,
     /// Database file path
     #[structopt(parse(from_os_str), short = "d", long = "database")]
     pub database: PathBuf,
     /// Directory path of trusted applications
     #[structopt(parse(from_os_str), short = "t", long = "trusted")]
     pub trusted: PathBuf,
+    /// SOCKS5 proxy address (For example, Tor: 127.0.0.1:9050).
+    /// If specified, all outgoing connections to relays and index servers are made through the proxy.
+    #[structopt(long = "socks5")]
+    pub socks5: Option<SocketAddr>,
+    /// Listening TCP address for direct connections from friends.
+    /// May be specified multiple times.
+    #[structopt(long = "ldirect")]
+    pub ldirect: Vec<SocketAddr>,
+    /// Public address where friends can connect to us directly (For example: example.com:1337).
+    /// Advertised to friends. May be specified multiple times.
+    #[structopt(long = "direct-address")]
+    pub direct_address: Vec<String>,
+    /// Pad all messages sent to friends to fixed sizes, to hide the kind of messages sent.
+    /// Used only with friends that support padding.
+    #[structopt(long = "pad-traffic")]
+    pub pad_traffic: bool,
+    /// Send cover traffic to friends every this amount of ticks (Only used with --pad-traffic)
+    #[structopt(long = "cover-ticks")]
+    pub cover_ticks: Option<usize>,
+    /// Resume previous sessions when reconnecting to friends, skipping the full handshake.
+    /// Resumed sessions can be linked to each other by an observer of the traffic.
+    #[structopt(long = "resume-sessions")]
+    pub resume_sessions: bool,
 }
 
 pub fn stnode(st_node_cmd: StNodeCmd) -> Result<(), NodeBinError> {
     let StNodeCmd {
         idfile,
         laddr,
+        lunix,
+        lws,
         database,
         trusted,
+        socks5,
+        ldirect,
+        direct_address,
+        pad_traffic,
+        cover_ticks,
+        resume_sessions,
     } = st_node_cmd;
 
+    let direct_addresses = direct_address
+        .into_iter()
+        .map(|address| address.try_into())
+        .collect::<Result<Vec<NetAddress>, _>>()
+        .map_err(|_| NodeBinError::InvalidDirectAddress)?;
+
+    let opt_padding_config = if pad_traffic {
+        Some(PaddingConfig {
+            opt_cover_ticks: cover_ticks,
+        })
+    } else {
+        None
+    };
+
     // Parse identity file:
     let identity_file: IdentityFile = deserialize_from_string(&fs::read_to_string(&idfile)?)?;
     let identity = SoftwareEd25519Identity::from_private_key(&identity_file.private_key)
@@ -153,6 +215,8 @@ pub fn stnode(st_node_cmd: StNodeCmd) -> Result<(), NodeBinError> {
         max_open_index_client_requests: MAX_OPEN_INDEX_CLIENT_REQUESTS,
         /// Maximum amount of relays a node may use.
         max_node_relays: MAX_NODE_RELAYS,
+        /// Addresses where we accept direct connections from friends.
+        direct_addresses,
         /*
         /// Maximum amount of incoming app connections we set up at the same time
         // max_concurrent_incoming_apps: MAX_CONCURRENT_INCOMING_APPS,
@@ -160,7 +224,7 @@ pub fn stnode(st_node_cmd: StNodeCmd) -> Result<(), NodeBinError> {
     };
 
     // A tcp connector, Used to connect to remote servers:
-    let tcp_connector = TcpConnector::new(MAX_FRAME_LENGTH, thread_pool.clone());
+    let tcp_connector = TcpConnector::new_with_proxy(MAX_FRAME_LENGTH, socks5, thread_pool.clone());
 
     // Obtain secure cryptographic random:
     let rng = system_random();
@@ -169,12 +233,69 @@ pub fn stnode(st_node_cmd: StNodeCmd) -> Result<(), NodeBinError> {
     let atomic_db =
         FileDb::<NodeState<NetAddress>>::load(database).map_err(|_| NodeBinError::LoadDbError)?;
 
-    // Start listening to apps:
-    let app_tcp_listener = TcpListener::new(MAX_FRAME_LENGTH, thread_pool.clone());
-    let ListenerClient {
-        config_sender: _config_sender,
-        conn_receiver: incoming_app_raw_conns,
-    } = block_on(app_tcp_listener.listen(laddr)).map_err(|_| NodeBinError::ListenError)?;
+    // Start listening to apps on all listening addresses:
+    let mut app_conn_receivers = Vec::new();
+    // We keep the config senders, to make sure the listeners are not closed:
+    let mut config_senders = Vec::new();
+    for socket_addr in laddr {
+        let app_tcp_listener = TcpListener::new(MAX_FRAME_LENGTH, thread_pool.clone());
+        let ListenerClient {
+            config_sender,
+            conn_receiver,
+        } = block_on(app_tcp_listener.listen(socket_addr))
+            .map_err(|_| NodeBinError::ListenError)?;
+        config_senders.push(config_sender);
+        app_conn_receivers.push(conn_receiver);
+    }
+
+    for socket_addr in lws {
+        let app_ws_listener = WsListener::new(MAX_FRAME_LENGTH, thread_pool.clone());
+        let ListenerClient {
+            config_sender,
+            conn_receiver,
+        } = block_on(app_ws_listener.listen(socket_addr)).map_err(|_| NodeBinError::ListenError)?;
+        config_senders.push(config_sender);
+        app_conn_receivers.push(conn_receiver);
+    }
+
+    #[cfg(unix)]
+    for socket_path in lunix {
+        let app_unix_listener = UnixListener::new(MAX_FRAME_LENGTH, thread_pool.clone());
+        let ListenerClient {
+            config_sender,
+            conn_receiver,
+        } = block_on(app_unix_listener.listen(socket_path))
+            .map_err(|_| NodeBinError::ListenError)?;
+        config_senders.push(config_sender);
+        app_conn_receivers.push(conn_receiver);
+    }
+
+    // Unix domain sockets are not available on this platform:
+    #[cfg(not(unix))]
+    {
+        if !lunix.is_empty() {
+            return Err(NodeBinError::ListenError);
+        }
+    }
+
+    if app_conn_receivers.is_empty() {
+        return Err(NodeBinError::NoListenAddress);
+    }
+    let incoming_app_raw_conns = select_all(app_conn_receivers);
+
+    // Start listening to direct connections from friends:
+    let mut direct_conn_receivers = Vec::new();
+    for socket_addr in ldirect {
+        let direct_tcp_listener = TcpListener::new(MAX_FRAME_LENGTH, thread_pool.clone());
+        let ListenerClient {
+            config_sender,
+            conn_receiver,
+        } = block_on(direct_tcp_listener.listen(socket_addr))
+            .map_err(|_| NodeBinError::ListenError)?;
+        config_senders.push(config_sender);
+        direct_conn_receivers.push(conn_receiver);
+    }
+    let incoming_direct_raw_conns = select_all(direct_conn_receivers);
 
     let trusted_apps = FileTrustedApps::new(trusted.into());
 
@@ -196,11 +317,14 @@ pub fn stnode(st_node_cmd: StNodeCmd) -> Result<(), NodeBinError> {
 
     let node_fut = net_node(
         incoming_app_raw_conns,
+        incoming_direct_raw_conns,
         tcp_connector,
         timer_client,
         identity_client,
         rng,
         node_config,
+        opt_padding_config,
+        resume_sessions,
         trusted_apps,
         node_state,
         database_client,
@@ -0,0 +1,145 @@
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+
+use futures::StreamExt;
+
+use async_std::fs;
+use async_std::path::{Path, PathBuf};
+
+use derive_more::From;
+
+use common::conn::BoxFuture;
+
+use proto::crypto::PublicKey;
+use proto::file::RelayListenerFile;
+use proto::ser_string::{deserialize_from_string, StringSerdeError};
+
+use relay::ListenAccess;
+
+#[derive(Debug, From)]
+enum FileListenAccessError {
+    AsyncStdIoError(async_std::io::Error),
+    StringSerdeError(StringSerdeError),
+}
+
+/// Reload the allow-list at least this often, even if the directory did not change.
+/// (Modifying a file in place does not change the modification time of the directory).
+const RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Load all relay listener files from a given directory.
+/// Invalid files are skipped.
+async fn load_listeners(dir_path: &Path) -> Result<HashSet<PublicKey>, FileListenAccessError> {
+    let mut res_listeners = HashSet::new();
+    let mut dir = fs::read_dir(dir_path).await?;
+    while let Some(entry) = dir.next().await {
+        let entry = entry?;
+        let path = entry.path();
+        if path.is_dir().await {
+            continue;
+        }
+
+        match load_listener_file(&path).await {
+            Ok(public_key) => {
+                res_listeners.insert(public_key);
+            }
+            Err(e) => warn!("load_listeners(): Skipping {:?}: {:?}", path, e),
+        }
+    }
+    Ok(res_listeners)
+}
+
+/// Load the public key of a single relay listener file.
+async fn load_listener_file(path: &Path) -> Result<PublicKey, FileListenAccessError> {
+    let relay_listener_file: RelayListenerFile =
+        deserialize_from_string(&fs::read_to_string(path).await?)?;
+    Ok(relay_listener_file.public_key)
+}
+
+/// Modification time of the listeners directory.
+/// Returns None if not available on this platform.
+async fn dir_modified(dir_path: &Path) -> Option<SystemTime> {
+    fs::metadata(dir_path).await.ok()?.modified().ok()
+}
+
+#[derive(Debug, Default)]
+struct ListenersCache {
+    listeners: HashSet<PublicKey>,
+    /// Modification time of the directory at the last load
+    opt_dir_modified: Option<SystemTime>,
+    /// Time of the last load. None if never loaded.
+    opt_loaded_at: Option<Instant>,
+}
+
+impl ListenersCache {
+    fn needs_reload(&self, opt_dir_modified: &Option<SystemTime>) -> bool {
+        match self.opt_loaded_at {
+            None => true,
+            Some(loaded_at) => {
+                loaded_at.elapsed() >= RELOAD_INTERVAL || opt_dir_modified != &self.opt_dir_modified
+            }
+        }
+    }
+}
+
+/// Listeners allow-list that is stored as files in a directory.
+/// Directory structure:
+///
+/// - root_dir
+///     - relay_listener_file1
+///     - relay_listener_file2
+///     - relay_listener_file3
+///     - ...
+///
+/// Where each relay_listener_file contains the public key of one node that may listen on the
+/// relay. The directory is loaded on the first check, and reloaded when its modification time
+/// changes or after `RELOAD_INTERVAL`, so it may be modified at runtime.
+#[derive(Debug, Clone)]
+pub struct FileListenAccess {
+    listeners_path: PathBuf,
+    cache: Arc<Mutex<ListenersCache>>,
+}
+
+impl FileListenAccess {
+    pub fn new(listeners_path: PathBuf) -> Self {
+        Self {
+            listeners_path,
+            cache: Arc::new(Mutex::new(ListenersCache::default())),
+        }
+    }
+
+    /// Reload the listeners from the directory if required.
+    /// If the directory can not be read, the previously loaded listeners are kept.
+    async fn maybe_reload(&self) {
+        let opt_dir_modified = dir_modified(&self.listeners_path).await;
+        let needs_reload = self.cache.lock().unwrap().needs_reload(&opt_dir_modified);
+        if !needs_reload {
+            return;
+        }
+
+        let opt_listeners = match load_listeners(&self.listeners_path).await {
+            Ok(listeners) => Some(listeners),
+            Err(e) => {
+                error!("load_listeners() failed: {:?}", e);
+                None
+            }
+        };
+
+        let mut cache = self.cache.lock().unwrap();
+        if let Some(listeners) = opt_listeners {
+            cache.listeners = listeners;
+        }
+        // We also update the load time on failure, to avoid reading the directory on every check:
+        cache.opt_dir_modified = opt_dir_modified;
+        cache.opt_loaded_at = Some(Instant::now());
+    }
+}
+
+impl ListenAccess for FileListenAccess {
+    fn is_listen_allowed<'a>(&'a mut self, public_key: &'a PublicKey) -> BoxFuture<'a, bool> {
+        Box::pin(async move {
+            self.maybe_reload().await;
+            self.cache.lock().unwrap().listeners.contains(public_key)
+        })
+    }
+}
@@ -1,3 +1,4 @@
+mod file_listen_access;
 mod net_relay;
 mod strelaylib;
 
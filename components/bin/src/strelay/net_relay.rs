@@ -19,7 +19,7 @@ use timer::TimerClient;
 
 use connection::create_version_encrypt_keepalive;
 
-use relay::{relay_server, RelayServerError};
+use relay::{relay_server, ListenAccess, RelayServerError};
 
 #[derive(Debug, From)]
 pub enum NetRelayServerError {
@@ -76,16 +76,18 @@ where
     }
 }
 
-pub async fn net_relay_server<IRC, R, S>(
+pub async fn net_relay_server<IRC, LA, R, S>(
     incoming_raw_conns: IRC,
     identity_client: IdentityClient,
     timer_client: TimerClient,
     rng: R,
     max_concurrent_encrypt: usize,
+    listen_access: LA,
     spawner: S,
 ) -> Result<(), NetRelayServerError>
 where
     IRC: Stream<Item = ConnPairVec> + Unpin + Send + 'static,
+    LA: ListenAccess + Clone + Send + 'static,
     R: CryptoRandom + Clone + Send + Sync + 'static,
     S: Spawn + Clone + Send + Sync + 'static,
 {
@@ -116,6 +118,7 @@ where
         timer_client,
         RELAY_CONN_TIMEOUT_TICKS,
         KEEPALIVE_TICKS,
+        listen_access,
         spawner.clone(),
     )
     .await?;
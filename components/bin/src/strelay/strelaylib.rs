@@ -7,6 +7,7 @@ use derive_more::From;
 
 use futures::executor::{block_on, ThreadPool};
 use futures::task::SpawnExt;
+use futures::FutureExt;
 
 use structopt::StructOpt;
 
@@ -20,8 +21,10 @@ use proto::consts::{MAX_FRAME_LENGTH, TICK_MS};
 
 use common::int_convert::usize_to_u64;
 
+use crate::strelay::file_listen_access::FileListenAccess;
 use crate::strelay::net_relay::{net_relay_server, NetRelayServerError};
 use net::TcpListener;
+use relay::AllowAllListeners;
 use timer::create_timer;
 
 use proto::file::IdentityFile;
@@ -56,10 +59,20 @@ pub struct StRelayCmd {
     /// Listening address (Example: 0.0.0.0:1337)
     #[structopt(short = "l", long = "laddr")]
     pub laddr: SocketAddr,
+    /// Directory path of relay listener files (A private relay).
+    /// If specified, only the listed nodes may listen on the relay. Anyone may still connect to
+    /// the listening nodes. The directory may be modified while the relay is running.
+    /// Changes are applied within a minute. Invalid files are skipped.
+    #[structopt(parse(from_os_str), long = "listeners")]
+    pub listeners: Option<PathBuf>,
 }
 
 pub fn strelay(st_relay_cmd: StRelayCmd) -> Result<(), RelayServerBinError> {
-    let StRelayCmd { idfile, laddr } = st_relay_cmd;
+    let StRelayCmd {
+        idfile,
+        laddr,
+        listeners,
+    } = st_relay_cmd;
 
     // Parse identity file:
     let identity_file: IdentityFile = deserialize_from_string(&fs::read_to_string(&idfile)?)?;
@@ -89,14 +102,28 @@ pub fn strelay(st_relay_cmd: StRelayCmd) -> Result<(), RelayServerBinError> {
         conn_receiver: incoming_raw_conns,
     } = block_on(tcp_listener.listen(laddr)).map_err(|_| RelayServerBinError::ListenError)?;
 
-    let relay_server_fut = net_relay_server(
-        incoming_raw_conns,
-        identity_client,
-        timer_client,
-        rng,
-        MAX_CONCURRENT_ENCRYPT,
-        thread_pool,
-    );
+    let relay_server_fut = match listeners {
+        Some(listeners) => net_relay_server(
+            incoming_raw_conns,
+            identity_client,
+            timer_client,
+            rng,
+            MAX_CONCURRENT_ENCRYPT,
+            FileListenAccess::new(listeners.into()),
+            thread_pool,
+        )
+        .boxed(),
+        None => net_relay_server(
+            incoming_raw_conns,
+            identity_client,
+            timer_client,
+            rng,
+            MAX_CONCURRENT_ENCRYPT,
+            AllowAllListeners,
+            thread_pool,
+        )
+        .boxed(),
+    };
 
     block_on(relay_server_fut).map_err(RelayServerBinError::NetRelayServerError)
 }
@@ -2,9 +2,11 @@ use std::fmt::Debug;
 use std::hash::Hash;
 
 use futures::channel::mpsc;
-use futures::task::Spawn;
+use futures::task::{Spawn, SpawnExt};
+use futures::{FutureExt, Stream, StreamExt, TryFutureExt};
 
 use common::conn::{BoxFuture, ConnPairVec, FutTransform};
+use common::transform_pool::transform_pool_loop;
 use timer::TimerClient;
 
 use proto::crypto::PublicKey;
@@ -91,27 +93,35 @@ pub enum SpawnChannelerError {
     SpawnError,
 }
 
-pub async fn channeler_loop<RA, C, EKT, S>(
+/// `connector` is used for connecting to relays.
+/// `direct_connector` is used for connecting directly to friends, and returns raw connections.
+/// `incoming_direct_conns` are raw connections made directly to our direct addresses.
+/// Direct connections are only protected by `encrypt_keepalive`, like relayed connections.
+pub async fn channeler_loop<RA, C, DC, EKT, ID, S>(
     local_public_key: PublicKey,
     timer_client: TimerClient,
     backoff_ticks: usize,
     conn_timeout_ticks: usize,
     max_concurrent_encrypt: usize,
     connector: C,
+    direct_connector: DC,
     encrypt_keepalive: EKT,
+    incoming_direct_conns: ID,
     from_funder: mpsc::Receiver<FunderToChanneler<RA>>,
-    to_funder: mpsc::Sender<ChannelerToFunder>,
+    to_funder: mpsc::Sender<ChannelerToFunder<RA>>,
     spawner: S,
 ) -> Result<(), ChannelerError>
 where
     RA: Eq + Hash + Clone + Send + Sync + Debug + 'static,
     C: FutTransform<Input = RA, Output = Option<ConnPairVec>> + Clone + Send + 'static,
+    DC: FutTransform<Input = RA, Output = Option<ConnPairVec>> + Clone + Send + 'static,
     EKT: FutTransform<
             Input = (Option<PublicKey>, ConnPairVec),
             Output = Option<(PublicKey, ConnPairVec)>,
         > + Clone
         + Send
         + 'static,
+    ID: Stream<Item = ConnPairVec> + Unpin + Send + 'static,
     S: Spawn + Clone + Send + 'static,
 {
     let client_connector = ClientConnector::new(connector.clone());
@@ -121,6 +131,7 @@ where
     let pool_connector = PoolConnector::new(
         timer_client.clone(),
         client_connector.clone(),
+        direct_connector,
         connect_encrypt_transform,
         backoff_ticks,
         spawner.clone(),
@@ -144,6 +155,24 @@ where
         spawner.clone(),
     );
 
+    // Encrypt incoming direct connections.
+    // The public key of the remote side is only known after the encryption handshake:
+    let (direct_conns_sender, incoming_enc_direct_conns) = mpsc::channel(0);
+    let enc_direct_fut = transform_pool_loop(
+        incoming_direct_conns.map(|conn_pair| (None, conn_pair)),
+        direct_conns_sender,
+        encrypt_keepalive.clone(),
+        max_concurrent_encrypt,
+    )
+    .map_err(|e| error!("transform_pool_loop() error: {:?}", e))
+    .map(|_| ());
+
+    // We spawn with handle here to make sure that this
+    // future is dropped when this async function ends.
+    let _enc_direct_handle = spawner
+        .spawn_with_handle(enc_direct_fut)
+        .map_err(|_| ChannelerError::SpawnError)?;
+
     // A hack to explain to the compiler that spawner (S) doesn't need to be Sync.
     let c_spawner = spawner.clone();
     channeler_loop_inner(
@@ -152,6 +181,7 @@ where
         to_funder,
         pool_connector,
         pool_listener,
+        incoming_enc_direct_conns,
         c_spawner,
     )
     .await
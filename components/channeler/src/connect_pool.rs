@@ -1,4 +1,4 @@
-use std::collections::{HashSet, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fmt::Debug;
 use std::hash::Hash;
 use std::marker::{PhantomData, Unpin};
@@ -13,6 +13,13 @@ use common::select_streams::select_streams;
 use timer::TimerClient;
 
 use proto::crypto::PublicKey;
+use proto::funder::messages::RelayHealth;
+
+use crate::overwrite_channel::overwrite_send_all;
+
+/// Maximum exponent used for exponential backoff from a failing relay.
+/// The longest backoff will be `backoff_ticks * 2^MAX_BACKOFF_EXP` ticks.
+const MAX_BACKOFF_EXP: u64 = 6;
 
 #[derive(Debug)]
 pub struct ConnectPoolClientError;
@@ -27,16 +34,44 @@ pub struct CpConnectClient {
     request_sender: mpsc::Sender<CpConnectRequest>,
 }
 
+/// An address we may use to reach a friend
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CpAddress<RA> {
+    /// Connect directly to the friend
+    Direct(RA),
+    /// Connect to the friend through a relay
+    Relay(RA),
+}
+
+impl<RA> CpAddress<RA> {
+    fn is_relay(&self) -> bool {
+        match self {
+            CpAddress::Direct(_) => false,
+            CpAddress::Relay(_) => true,
+        }
+    }
+}
+
 pub struct CpConfigClient<RA> {
-    request_sender: mpsc::Sender<Vec<RA>>,
+    request_sender: mpsc::Sender<Vec<CpAddress<RA>>>,
 }
 
 impl<RA> CpConfigClient<RA> {
-    pub fn new(request_sender: mpsc::Sender<Vec<RA>>) -> Self {
+    pub fn new(request_sender: mpsc::Sender<Vec<CpAddress<RA>>>) -> Self {
         CpConfigClient { request_sender }
     }
 
-    pub async fn config(&mut self, config: Vec<RA>) -> Result<(), ConnectPoolClientError> {
+    /// Set the relays and the direct addresses of the friend.
+    pub async fn config(
+        &mut self,
+        relays: Vec<RA>,
+        direct_addresses: Vec<RA>,
+    ) -> Result<(), ConnectPoolClientError> {
+        let config = direct_addresses
+            .into_iter()
+            .map(CpAddress::Direct)
+            .chain(relays.into_iter().map(CpAddress::Relay))
+            .collect();
         self.request_sender
             .send(config)
             .await
@@ -72,7 +107,7 @@ pub enum ConnectPoolError {
 enum CpEvent<RA> {
     ConnectRequest(CpConnectRequest),
     ConnectRequestClosed,
-    ConfigRequest(Vec<RA>),
+    ConfigRequest(Vec<CpAddress<RA>>),
     ConfigRequestClosed,
     ConnectAttemptDone(Option<ConnPairVec>),
     TimerTick,
@@ -82,37 +117,88 @@ enum CpEvent<RA> {
 enum CpStatus<RA> {
     NoRequest,
     Waiting((usize, oneshot::Sender<ConnPairVec>)),
-    Connecting((RA, oneshot::Sender<()>, oneshot::Sender<ConnPairVec>)),
+    Connecting(
+        (
+            CpAddress<RA>,
+            oneshot::Sender<()>,
+            oneshot::Sender<ConnPairVec>,
+        ),
+    ),
 }
 
-struct ConnectPool<RA, C, ET, S> {
+/// Information about the connection attempts through an address
+#[derive(Debug, Clone, Default)]
+struct AddressHealth {
+    success_count: u64,
+    failure_count: u64,
+    consecutive_failures: u64,
+    /// Time ticks it took for the last successful connection attempt
+    latency_ticks: u64,
+    /// We will not attempt to connect through this address before this tick
+    backoff_until: u64,
+    /// Sequence number of the last attempt through this address (0 if never attempted).
+    /// Used to rotate between equally healthy addresses.
+    last_attempt: u64,
+}
+
+impl AddressHealth {
+    /// Addresses with a lower score are preferred.
+    /// Between addresses that failed the same amount of consecutive times, direct addresses are
+    /// preferred over relays.
+    fn score(&self, is_relay: bool) -> (u64, bool, u64, u64) {
+        (
+            self.consecutive_failures,
+            is_relay,
+            self.latency_ticks,
+            self.last_attempt,
+        )
+    }
+}
+
+struct ConnectPool<RA, C, DC, ET, S> {
     friend_public_key: PublicKey,
-    addresses: VecDeque<RA>,
+    /// Relay and direct addresses, in the order they were added
+    addresses: VecDeque<CpAddress<RA>>,
+    health: HashMap<CpAddress<RA>, AddressHealth>,
+    /// Amount of time ticks passed since the pool was created
+    cur_tick: u64,
+    /// Time tick in which the current connection attempt started
+    attempt_start_tick: u64,
+    /// Amount of connection attempts made so far
+    attempt_counter: u64,
     status: CpStatus<RA>,
     conn_done_sender: mpsc::Sender<Option<ConnPairVec>>,
     backoff_ticks: usize,
     client_connector: C,
+    direct_connector: DC,
     encrypt_transform: ET,
     spawner: S,
 }
 
-async fn conn_attempt<RA, C, ET>(
+async fn conn_attempt<RA, C, DC, ET>(
     friend_public_key: PublicKey,
-    address: RA,
+    address: CpAddress<RA>,
     mut client_connector: C,
+    mut direct_connector: DC,
     mut encrypt_transform: ET,
     canceler: oneshot::Receiver<()>,
 ) -> Option<ConnPairVec>
 where
     RA: Eq,
     C: FutTransform<Input = (RA, PublicKey), Output = Option<ConnPairVec>> + Clone,
+    DC: FutTransform<Input = RA, Output = Option<ConnPairVec>> + Clone,
     ET: FutTransform<Input = (PublicKey, ConnPairVec), Output = Option<ConnPairVec>> + Clone,
 {
     // TODO: How to remove this Box::pin?
     let connect_fut = Box::pin(async move {
-        let raw_conn = client_connector
-            .transform((address, friend_public_key.clone()))
-            .await?;
+        let raw_conn = match address {
+            CpAddress::Direct(address) => direct_connector.transform(address).await?,
+            CpAddress::Relay(address) => {
+                client_connector
+                    .transform((address, friend_public_key.clone()))
+                    .await?
+            }
+        };
         encrypt_transform
             .transform((friend_public_key.clone(), raw_conn))
             .await
@@ -125,7 +211,7 @@ where
     }
 }
 
-impl<RA, C, ET, S> ConnectPool<RA, C, ET, S>
+impl<RA, C, DC, ET, S> ConnectPool<RA, C, DC, ET, S>
 where
     RA: Hash + Clone + Eq + Send + Debug + 'static,
     S: Spawn,
@@ -134,36 +220,91 @@ where
         + Send
         + 'static,
     C: FutTransform<Input = (RA, PublicKey), Output = Option<ConnPairVec>> + Clone + Send + 'static,
+    DC: FutTransform<Input = RA, Output = Option<ConnPairVec>> + Clone + Send + 'static,
 {
     pub fn new(
         friend_public_key: PublicKey,
         conn_done_sender: mpsc::Sender<Option<ConnPairVec>>,
         backoff_ticks: usize,
         client_connector: C,
+        direct_connector: DC,
         encrypt_transform: ET,
         spawner: S,
     ) -> Self {
         ConnectPool {
             friend_public_key,
             addresses: VecDeque::new(),
+            health: HashMap::new(),
+            cur_tick: 0,
+            attempt_start_tick: 0,
+            attempt_counter: 0,
             status: CpStatus::NoRequest,
             conn_done_sender,
             backoff_ticks,
             client_connector,
+            direct_connector,
             encrypt_transform,
             spawner,
         }
     }
 
-    /// Start a connection attempt through a relay with a given address.
+    /// Choose the next address to attempt connecting through.
+    /// We prefer addresses that did not fail recently and have lower latency. Between equally
+    /// healthy addresses we pick the one that was least recently attempted. Addresses that are
+    /// still backing off are skipped.
+    fn next_address(&self) -> Option<CpAddress<RA>> {
+        self.addresses
+            .iter()
+            .filter_map(|address| {
+                let health = self.health.get(address)?;
+                if health.backoff_until > self.cur_tick {
+                    None
+                } else {
+                    Some((address, health.score(address.is_relay())))
+                }
+            })
+            .min_by_key(|(_address, score)| *score)
+            .map(|(address, _score)| address.clone())
+    }
+
+    /// Current health of all known relays.
+    /// Direct addresses are not reported.
+    fn relays_health(&self) -> Vec<RelayHealth<RA>> {
+        self.addresses
+            .iter()
+            .filter_map(|address| {
+                let relay_address = match address {
+                    CpAddress::Direct(_) => return None,
+                    CpAddress::Relay(relay_address) => relay_address,
+                };
+                let health = self.health.get(address)?;
+                Some(RelayHealth {
+                    relay_address: relay_address.clone(),
+                    success_count: health.success_count,
+                    failure_count: health.failure_count,
+                    consecutive_failures: health.consecutive_failures,
+                    latency_ticks: health.latency_ticks,
+                })
+            })
+            .collect()
+    }
+
+    /// Start a connection attempt through a given address.
     /// Returns a canceler.
     fn create_conn_attempt(
         &mut self,
-        address: RA,
+        address: CpAddress<RA>,
     ) -> Result<oneshot::Sender<()>, ConnectPoolError> {
+        self.attempt_counter = self.attempt_counter.wrapping_add(1);
+        self.attempt_start_tick = self.cur_tick;
+        if let Some(health) = self.health.get_mut(&address) {
+            health.last_attempt = self.attempt_counter;
+        }
+
         let (cancel_sender, cancel_receiver) = oneshot::channel();
         let c_friend_public_key = self.friend_public_key.clone();
         let c_client_connector = self.client_connector.clone();
+        let c_direct_connector = self.direct_connector.clone();
         let c_encrypt_transform = self.encrypt_transform.clone();
 
         let mut c_conn_done_sender = self.conn_done_sender.clone();
@@ -172,6 +313,7 @@ where
                 c_friend_public_key,
                 address,
                 c_client_connector,
+                c_direct_connector,
                 c_encrypt_transform,
                 cancel_receiver,
             )
@@ -186,49 +328,65 @@ where
         Ok(cancel_sender)
     }
 
-    pub fn handle_connect_request(
+    /// Attempt to connect through the healthiest available address.
+    /// If no address is available, wait `wait_ticks` before trying again.
+    fn try_connect(
         &mut self,
-        connect_request: CpConnectRequest,
+        response_sender: oneshot::Sender<ConnPairVec>,
+        wait_ticks: usize,
     ) -> Result<(), ConnectPoolError> {
-        if let CpStatus::NoRequest = self.status {
+        if let Some(address) = self.next_address() {
+            let canceler = self.create_conn_attempt(address.clone())?;
+            self.status = CpStatus::Connecting((address, canceler, response_sender));
         } else {
-            return Err(ConnectPoolError::MultipleConnectRequests);
+            self.status = CpStatus::Waiting((wait_ticks, response_sender));
         }
+        Ok(())
+    }
 
-        let address = match self.addresses.pop_front() {
-            None => {
-                // We can't connect yet, because we don't know of any address.
-                self.status = CpStatus::Waiting((0, connect_request.response_sender));
+    pub fn handle_connect_request(
+        &mut self,
+        connect_request: CpConnectRequest,
+    ) -> Result<(), ConnectPoolError> {
+        // A previous request might have been abandoned by the requester (For example, if the
+        // friend connected to us directly in the meanwhile). In that case the new request takes
+        // its place, and the ongoing connection attempt (if any) is kept.
+        match &mut self.status {
+            CpStatus::NoRequest => {}
+            CpStatus::Waiting((_, response_sender))
+            | CpStatus::Connecting((_, _, response_sender)) => {
+                if !response_sender.is_canceled() {
+                    return Err(ConnectPoolError::MultipleConnectRequests);
+                }
+                *response_sender = connect_request.response_sender;
                 return Ok(());
             }
-            Some(address) => address,
-        };
+        }
 
-        let canceler = self.create_conn_attempt(address.clone())?;
-        self.status = CpStatus::Connecting((address, canceler, connect_request.response_sender));
-        Ok(())
+        // If we can't connect yet (We don't know of any available address), we wait:
+        self.try_connect(connect_request.response_sender, 0)
     }
 
-    fn add_address(&mut self, address: RA) -> Result<(), ConnectPoolError> {
+    fn add_address(&mut self, address: CpAddress<RA>) -> Result<(), ConnectPoolError> {
         let was_empty = self.addresses.is_empty();
         if !self.addresses.contains(&address) {
-            self.addresses.push_back(address);
+            self.addresses.push_back(address.clone());
+            self.health.insert(address, AddressHealth::default());
         }
 
         let status = mem::replace(&mut self.status, CpStatus::NoRequest);
         match (was_empty, status) {
             (true, CpStatus::Waiting((_remaining_ticks, response_sender))) => {
-                let address = self.addresses.pop_front().unwrap();
-                let canceler = self.create_conn_attempt(address.clone())?;
-                self.status = CpStatus::Connecting((address, canceler, response_sender));
+                self.try_connect(response_sender, 0)?;
             }
             (_, status) => self.status = status,
         };
         Ok(())
     }
 
-    fn remove_address(&mut self, address: RA) -> Result<(), ConnectPoolError> {
+    fn remove_address(&mut self, address: CpAddress<RA>) -> Result<(), ConnectPoolError> {
         self.addresses.retain(|cur_address| cur_address != &address);
+        self.health.remove(&address);
         match mem::replace(&mut self.status, CpStatus::NoRequest) {
             CpStatus::NoRequest => {}
             CpStatus::Waiting(waiting) => {
@@ -238,14 +396,8 @@ where
                 if address == cur_address {
                     // We were trying to connect to the address being removed:
                     let _ = canceler.send(());
-                    if let Some(address) = self.addresses.pop_front() {
-                        // There is another address we can use:
-                        let canceler = self.create_conn_attempt(address.clone())?;
-                        self.status = CpStatus::Connecting((address, canceler, response_sender));
-                    } else {
-                        // There is no other address:
-                        self.status = CpStatus::Waiting((0, response_sender));
-                    }
+                    // Try another address if there is one:
+                    self.try_connect(response_sender, 0)?;
                 } else {
                     self.status = CpStatus::Connecting((cur_address, canceler, response_sender));
                 }
@@ -254,10 +406,13 @@ where
         Ok(())
     }
 
-    pub fn handle_config_request(&mut self, config: Vec<RA>) -> Result<(), ConnectPoolError> {
+    pub fn handle_config_request(
+        &mut self,
+        config: Vec<CpAddress<RA>>,
+    ) -> Result<(), ConnectPoolError> {
         let old_addresses = self.addresses.iter().cloned().collect::<HashSet<_>>();
 
-        let new_addresses: HashSet<RA> = config.into_iter().collect::<HashSet<_>>();
+        let new_addresses: HashSet<CpAddress<RA>> = config.into_iter().collect::<HashSet<_>>();
 
         for removed_address in old_addresses.difference(&new_addresses) {
             self.remove_address(removed_address.clone())?;
@@ -270,6 +425,8 @@ where
     }
 
     pub fn handle_timer_tick(&mut self) -> Result<(), ConnectPoolError> {
+        self.cur_tick = self.cur_tick.saturating_add(1);
+
         let waiting = match mem::replace(&mut self.status, CpStatus::NoRequest) {
             CpStatus::Waiting(waiting) => waiting,
             other_status => {
@@ -281,11 +438,12 @@ where
         let (mut backoff_ticks, response_sender) = waiting;
         backoff_ticks = backoff_ticks.saturating_sub(1);
         if backoff_ticks == 0 {
-            if let Some(address) = self.addresses.pop_front() {
-                let canceler = self.create_conn_attempt(address.clone())?;
-                self.status = CpStatus::Connecting((address, canceler, response_sender));
-            } else {
+            if self.addresses.is_empty() {
                 self.status = CpStatus::Waiting((self.backoff_ticks, response_sender));
+            } else {
+                // All known addresses might be backing off. In that case we check again on the
+                // next tick:
+                self.try_connect(response_sender, 1)?;
             }
         } else {
             self.status = CpStatus::Waiting((backoff_ticks, response_sender));
@@ -293,6 +451,32 @@ where
         Ok(())
     }
 
+    /// Update the health of an address according to the result of a connection attempt
+    fn update_health(&mut self, address: &CpAddress<RA>, success: bool) {
+        let cur_tick = self.cur_tick;
+        let latency_ticks = cur_tick.saturating_sub(self.attempt_start_tick);
+        let backoff_ticks = self.backoff_ticks as u64;
+
+        let health = match self.health.get_mut(address) {
+            Some(health) => health,
+            // The address was removed during the connection attempt:
+            None => return,
+        };
+
+        if success {
+            health.success_count = health.success_count.saturating_add(1);
+            health.consecutive_failures = 0;
+            health.latency_ticks = latency_ticks;
+            health.backoff_until = 0;
+        } else {
+            health.failure_count = health.failure_count.saturating_add(1);
+            health.consecutive_failures = health.consecutive_failures.saturating_add(1);
+            // Exponential backoff from failing addresses:
+            let exp = std::cmp::min(health.consecutive_failures - 1, MAX_BACKOFF_EXP);
+            health.backoff_until = cur_tick.saturating_add(backoff_ticks.saturating_mul(1 << exp));
+        }
+    }
+
     pub fn handle_connect_attempt_done(&mut self, opt_conn: Option<ConnPairVec>) {
         let connecting = match mem::replace(&mut self.status, CpStatus::NoRequest) {
             CpStatus::NoRequest | CpStatus::Waiting(_) => unreachable!(),
@@ -300,7 +484,7 @@ where
         };
 
         let (address, _canceler, response_sender) = connecting;
-        self.addresses.push_back(address);
+        self.update_health(&address, opt_conn.is_some());
 
         if let Some(conn) = opt_conn {
             if let Err(e) = response_sender.send(conn) {
@@ -316,20 +500,23 @@ where
     }
 }
 
-async fn connect_pool_loop<RA, ET, TS, C, S>(
+async fn connect_pool_loop<RA, ET, TS, C, DC, S>(
     incoming_requests: mpsc::Receiver<CpConnectRequest>,
-    incoming_config: mpsc::Receiver<Vec<RA>>,
+    incoming_config: mpsc::Receiver<Vec<CpAddress<RA>>>,
     timer_stream: TS,
     encrypt_transform: ET,
     friend_public_key: PublicKey,
     backoff_ticks: usize,
     client_connector: C,
+    direct_connector: DC,
     spawner: S,
+    mut relays_health_sender: mpsc::Sender<Vec<RelayHealth<RA>>>,
     mut opt_event_sender: Option<mpsc::Sender<()>>,
 ) -> Result<(), ConnectPoolError>
 where
     RA: Hash + Clone + Eq + Send + Debug + 'static,
     C: FutTransform<Input = (RA, PublicKey), Output = Option<ConnPairVec>> + Clone + Send + 'static,
+    DC: FutTransform<Input = RA, Output = Option<ConnPairVec>> + Clone + Send + 'static,
     TS: Stream + Unpin + Send,
     ET: FutTransform<Input = (PublicKey, ConnPairVec), Output = Option<ConnPairVec>>
         + Clone
@@ -343,6 +530,7 @@ where
         conn_done_sender,
         backoff_ticks,
         client_connector,
+        direct_connector,
         encrypt_transform,
         spawner.clone(),
     );
@@ -388,7 +576,11 @@ where
                 break;
             }
             CpEvent::ConnectAttemptDone(opt_conn) => {
-                connect_pool.handle_connect_attempt_done(opt_conn)
+                connect_pool.handle_connect_attempt_done(opt_conn);
+                // Report the updated relays health. Failure to report is not fatal:
+                let _ = relays_health_sender
+                    .send(connect_pool.relays_health())
+                    .await;
             }
         }
         if let Some(ref mut event_sender) = opt_event_sender {
@@ -399,19 +591,26 @@ where
     Ok(())
 }
 
-pub type ConnectPoolControl<RA> = (CpConfigClient<RA>, CpConnectClient);
+/// Configuration client, connect client and a stream of relays health updates.
+pub type ConnectPoolControl<RA> = (
+    CpConfigClient<RA>,
+    CpConnectClient,
+    mpsc::Receiver<Vec<RelayHealth<RA>>>,
+);
 
-pub fn create_connect_pool<RA, ET, TS, C, S>(
+pub fn create_connect_pool<RA, ET, TS, C, DC, S>(
     timer_stream: TS,
     encrypt_transform: ET,
     friend_public_key: PublicKey,
     backoff_ticks: usize,
     client_connector: C,
+    direct_connector: DC,
     spawner: S,
 ) -> Result<ConnectPoolControl<RA>, ConnectPoolError>
 where
     RA: Hash + Clone + Eq + Send + Debug + 'static,
     C: FutTransform<Input = (RA, PublicKey), Output = Option<ConnPairVec>> + Clone + Send + 'static,
+    DC: FutTransform<Input = RA, Output = Option<ConnPairVec>> + Clone + Send + 'static,
     TS: Stream + Unpin + Send + 'static,
     ET: FutTransform<Input = (PublicKey, ConnPairVec), Output = Option<ConnPairVec>>
         + Clone
@@ -422,6 +621,17 @@ where
     let (connect_request_sender, incoming_requests) = mpsc::channel(0);
     let (config_request_sender, incoming_config) = mpsc::channel(0);
 
+    // We use an overwrite channel for relays health updates, to make sure the pool is never
+    // stuck on reporting. Only the most recent relays health is relevant.
+    let (relays_health_sender, overwrite_receiver) = mpsc::channel(0);
+    let (overwrite_sender, relays_health_receiver) = mpsc::channel(0);
+    let overwrite_fut = overwrite_send_all(overwrite_sender, overwrite_receiver)
+        .map_err(|e| debug!("connect pool overwrite_send_all() error: {:?}", e))
+        .map(|_| ());
+    spawner
+        .spawn(overwrite_fut)
+        .map_err(|_| ConnectPoolError::SpawnError)?;
+
     let loop_fut = connect_pool_loop(
         incoming_requests,
         incoming_config,
@@ -430,7 +640,9 @@ where
         friend_public_key,
         backoff_ticks,
         client_connector,
+        direct_connector,
         spawner.clone(),
+        relays_health_sender,
         None,
     )
     .map_err(|e| error!("connect_pool_loop() error: {:?}", e))
@@ -443,23 +655,26 @@ where
     Ok((
         CpConfigClient::new(config_request_sender),
         CpConnectClient::new(connect_request_sender),
+        relays_health_receiver,
     ))
 }
 
 #[derive(Clone)]
-pub struct PoolConnector<RA, C, ET, S> {
+pub struct PoolConnector<RA, C, DC, ET, S> {
     timer_client: TimerClient,
     client_connector: C,
+    direct_connector: DC,
     encrypt_transform: ET,
     backoff_ticks: usize,
     spawner: S,
     phantom_b: PhantomData<RA>,
 }
 
-impl<RA, C, ET, S> PoolConnector<RA, C, ET, S>
+impl<RA, C, DC, ET, S> PoolConnector<RA, C, DC, ET, S>
 where
     RA: Hash + Clone + Eq + Send + 'static,
     C: FutTransform<Input = (RA, PublicKey), Output = Option<ConnPairVec>> + Clone + Send + 'static,
+    DC: FutTransform<Input = RA, Output = Option<ConnPairVec>> + Clone + Send + 'static,
     ET: FutTransform<Input = (PublicKey, ConnPairVec), Output = Option<ConnPairVec>>
         + Clone
         + Send
@@ -469,6 +684,7 @@ where
     pub fn new(
         timer_client: TimerClient,
         client_connector: C,
+        direct_connector: DC,
         encrypt_transform: ET,
         backoff_ticks: usize,
         spawner: S,
@@ -476,6 +692,7 @@ where
         PoolConnector {
             timer_client,
             client_connector,
+            direct_connector,
             encrypt_transform,
             backoff_ticks,
             spawner,
@@ -484,10 +701,11 @@ where
     }
 }
 
-impl<RA, C, ET, S> FutTransform for PoolConnector<RA, C, ET, S>
+impl<RA, C, DC, ET, S> FutTransform for PoolConnector<RA, C, DC, ET, S>
 where
     RA: Hash + Clone + Eq + Send + Debug + 'static,
     C: FutTransform<Input = (RA, PublicKey), Output = Option<ConnPairVec>> + Clone + Send + 'static,
+    DC: FutTransform<Input = RA, Output = Option<ConnPairVec>> + Clone + Send + 'static,
     ET: FutTransform<Input = (PublicKey, ConnPairVec), Output = Option<ConnPairVec>>
         + Clone
         + Send
@@ -511,6 +729,7 @@ where
                 friend_public_key,
                 self.backoff_ticks,
                 self.client_connector.clone(),
+                self.direct_connector.clone(),
                 self.spawner.clone(),
             )
             .unwrap()
@@ -525,7 +744,7 @@ mod tests {
     use futures::future::join;
 
     use common::conn::FuncFutTransform;
-    use common::dummy_connector::DummyConnector;
+    use common::dummy_connector::{ConnRequest, DummyConnector};
 
     use timer::{dummy_timer_multi_sender, TimerTick};
 
@@ -541,25 +760,33 @@ mod tests {
         let (conn_request_sender, mut conn_request_receiver) = mpsc::channel(0);
         let client_connector = DummyConnector::new(conn_request_sender);
 
+        // We don't use direct addresses in this test:
+        let direct_connector = FuncFutTransform::new(|_address: u32| Box::pin(future::ready(None)));
+
         // We don't need encryption for this test:
         let encrypt_transform = FuncFutTransform::new(|(_opt_public_key, conn_pair)| {
             Box::pin(future::ready(Some(conn_pair)))
         });
 
-        let mut pool_connector = PoolConnector::<u32, _, _, _>::new(
+        let mut pool_connector = PoolConnector::<u32, _, _, _, _>::new(
             timer_client,
             client_connector,
+            direct_connector,
             encrypt_transform,
             backoff_ticks,
             spawner,
         );
 
         let pk_b = PublicKey::from(&[0xbb; PublicKey::len()]);
-        let (mut config_client, mut connect_client) = pool_connector.transform(pk_b.clone()).await;
+        let (mut config_client, mut connect_client, _) =
+            pool_connector.transform(pk_b.clone()).await;
         let _tick_sender = tick_sender_receiver.next().await.unwrap();
 
         let addresses = vec![0x0u32, 0x1u32, 0x2u32];
-        config_client.config(addresses.clone()).await.unwrap();
+        config_client
+            .config(addresses.clone(), Vec::new())
+            .await
+            .unwrap();
 
         // Addresses that we have seen an attempt to connect to:
         let mut observed_addresses = Vec::new();
@@ -658,6 +885,79 @@ mod tests {
         block_on(task_pool_connector_cyclic_connect(thread_pool.clone()));
     }
 
+    async fn task_pool_connector_abandoned_request<S>(spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        // Create a mock time service:
+        let (mut tick_sender_receiver, timer_client) = dummy_timer_multi_sender(spawner.clone());
+
+        let backoff_ticks = 2;
+
+        let (conn_request_sender, mut conn_request_receiver) = mpsc::channel(0);
+        let client_connector = DummyConnector::new(conn_request_sender);
+
+        // We don't use direct addresses in this test:
+        let direct_connector = FuncFutTransform::new(|_address: u32| Box::pin(future::ready(None)));
+
+        // We don't need encryption for this test:
+        let encrypt_transform = FuncFutTransform::new(|(_opt_public_key, conn_pair)| {
+            Box::pin(future::ready(Some(conn_pair)))
+        });
+
+        let mut pool_connector = PoolConnector::<u32, _, _, _, _>::new(
+            timer_client,
+            client_connector,
+            direct_connector,
+            encrypt_transform,
+            backoff_ticks,
+            spawner,
+        );
+
+        let pk_b = PublicKey::from(&[0xbb; PublicKey::len()]);
+        let (mut config_client, mut connect_client, _) =
+            pool_connector.transform(pk_b.clone()).await;
+        let _tick_sender = tick_sender_receiver.next().await.unwrap();
+
+        config_client
+            .config(vec![0x0u32], Vec::new())
+            .await
+            .unwrap();
+
+        // Send a connect request, and abandon it:
+        let (response_sender, response_receiver) = oneshot::channel();
+        connect_client
+            .request_sender
+            .send(CpConnectRequest { response_sender })
+            .await
+            .unwrap();
+        drop(response_receiver);
+
+        // A connection attempt starts:
+        let conn_request = conn_request_receiver.next().await.unwrap();
+        let (address, pk) = &conn_request.address;
+        assert_eq!(address, &0x0u32);
+        assert_eq!(pk, &pk_b);
+
+        // A new connect request takes the place of the abandoned request, and receives the
+        // connection of the ongoing attempt:
+        let connect_fut = connect_client.connect();
+        let handle_connect_fut = async {
+            let (local_sender, remote_receiver) = mpsc::channel(0);
+            let (remote_sender, local_receiver) = mpsc::channel(0);
+            conn_request.reply(Some(ConnPairVec::from_raw(local_sender, local_receiver)));
+            (remote_sender, remote_receiver)
+        };
+        let (conn_res, _remote_conn) = join(connect_fut, handle_connect_fut).await;
+        assert!(conn_res.is_ok());
+    }
+
+    #[test]
+    fn test_pool_connector_abandoned_request() {
+        let thread_pool = ThreadPool::new().unwrap();
+        block_on(task_pool_connector_abandoned_request(thread_pool.clone()));
+    }
+
     async fn task_pool_connector_backoff_ticks<S>(spawner: S)
     where
         S: Spawn + Clone + Send + 'static,
@@ -671,6 +971,9 @@ mod tests {
         let (conn_request_sender, mut conn_request_receiver) = mpsc::channel(0);
         let client_connector = DummyConnector::new(conn_request_sender);
 
+        // We don't use direct addresses in this test:
+        let direct_connector = FuncFutTransform::new(|_address: u32| Box::pin(future::ready(None)));
+
         // We don't need encryption for this test:
         let encrypt_transform = FuncFutTransform::new(|(_public_key, conn_pair)| {
             Box::pin(future::ready(Some(conn_pair)))
@@ -688,6 +991,9 @@ mod tests {
         let (request_sender, incoming_requests) = mpsc::channel(0);
         let (config_sender, incoming_config) = mpsc::channel(0);
 
+        // We don't check relays health in this test:
+        let (relays_health_sender, _) = mpsc::channel(0);
+
         let pk_b = PublicKey::from(&[0xbb; PublicKey::len()]);
 
         // We call connect_pool_loop directly instead of using the wrapper here.
@@ -704,7 +1010,9 @@ mod tests {
             pk_b.clone(), // friend_public_key
             backoff_ticks,
             client_connector,
+            direct_connector,
             spawner.clone(),
+            relays_health_sender,
             Some(event_sender),
         )
         .map_err(|e| error!("connect_pool_loop() error: {:?}", e))
@@ -716,7 +1024,10 @@ mod tests {
         let mut config_client = CpConfigClient::new(config_sender);
 
         let addresses = vec![0x0u32, 0x1u32, 0x2u32];
-        config_client.config(addresses.clone()).await.unwrap();
+        config_client
+            .config(addresses.clone(), Vec::new())
+            .await
+            .unwrap();
         event_receiver.next().await.unwrap();
 
         // Addresses that we have seen an attempt to connect to:
@@ -773,4 +1084,334 @@ mod tests {
         let thread_pool = ThreadPool::new().unwrap();
         block_on(task_pool_connector_backoff_ticks(thread_pool.clone()));
     }
+
+    /// Create a connect pool loop for testing, with an event_sender for precise timer ticks.
+    async fn spawn_test_connect_pool<S>(
+        pk_b: PublicKey,
+        backoff_ticks: usize,
+        spawner: S,
+    ) -> (
+        CpConfigClient<u32>,
+        CpConnectClient,
+        mpsc::Receiver<Vec<RelayHealth<u32>>>,
+        mpsc::Receiver<ConnRequest<(u32, PublicKey), Option<ConnPairVec>>>,
+        mpsc::Receiver<ConnRequest<u32, Option<ConnPairVec>>>,
+        mpsc::Sender<TimerTick>,
+        mpsc::Receiver<()>,
+    )
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let (mut tick_sender_receiver, mut timer_client) =
+            dummy_timer_multi_sender(spawner.clone());
+
+        let (conn_request_sender, conn_request_receiver) = mpsc::channel(0);
+        let client_connector = DummyConnector::new(conn_request_sender);
+
+        let (direct_request_sender, direct_request_receiver) = mpsc::channel(0);
+        let direct_connector = DummyConnector::new(direct_request_sender);
+
+        // We don't need encryption for this test:
+        let encrypt_transform = FuncFutTransform::new(|(_public_key, conn_pair)| {
+            Box::pin(future::ready(Some(conn_pair)))
+        });
+
+        let timer_stream = timer_client
+            .request_timer_stream("spawn_test_connect_pool".to_owned())
+            .await
+            .unwrap();
+        let tick_sender = tick_sender_receiver.next().await.unwrap();
+
+        let (event_sender, event_receiver) = mpsc::channel(0);
+        let (request_sender, incoming_requests) = mpsc::channel(0);
+        let (config_sender, incoming_config) = mpsc::channel(0);
+        let (relays_health_sender, relays_health_receiver) = mpsc::channel(0);
+
+        let loop_fut = connect_pool_loop(
+            incoming_requests,
+            incoming_config,
+            timer_stream,
+            encrypt_transform,
+            pk_b,
+            backoff_ticks,
+            client_connector,
+            direct_connector,
+            spawner.clone(),
+            relays_health_sender,
+            Some(event_sender),
+        )
+        .map_err(|e| error!("connect_pool_loop() error: {:?}", e))
+        .map(|_| ());
+
+        spawner.spawn(loop_fut).unwrap();
+
+        (
+            CpConfigClient::new(config_sender),
+            CpConnectClient::new(request_sender),
+            relays_health_receiver,
+            conn_request_receiver,
+            direct_request_receiver,
+            tick_sender,
+            event_receiver,
+        )
+    }
+
+    async fn task_pool_connector_prefer_healthy<S>(spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let backoff_ticks = 2;
+        let pk_b = PublicKey::from(&[0xbb; PublicKey::len()]);
+
+        let (
+            mut config_client,
+            mut connect_client,
+            mut relays_health_receiver,
+            mut conn_request_receiver,
+            _direct_request_receiver,
+            mut tick_sender,
+            mut event_receiver,
+        ) = spawn_test_connect_pool(pk_b.clone(), backoff_ticks, spawner).await;
+
+        config_client
+            .config(vec![0x0u32, 0x1u32, 0x2u32], Vec::new())
+            .await
+            .unwrap();
+        event_receiver.next().await.unwrap();
+
+        let connect_fut = connect_client.connect();
+        let handle_connect_fut = async {
+            event_receiver.next().await.unwrap(); // Connection request event
+
+            // First attempt fails:
+            let conn_request = conn_request_receiver.next().await.unwrap();
+            let (failed_address, pk) = conn_request.address.clone();
+            assert_eq!(pk, pk_b);
+            conn_request.reply(None);
+
+            let relays_health = relays_health_receiver.next().await.unwrap();
+            event_receiver.next().await.unwrap(); // connection attempt done event
+            let failed_health = relays_health
+                .iter()
+                .find(|relay_health| relay_health.relay_address == failed_address)
+                .unwrap();
+            assert_eq!(failed_health.failure_count, 1);
+            assert_eq!(failed_health.consecutive_failures, 1);
+
+            for _ in 0..backoff_ticks {
+                tick_sender.send(TimerTick).await.unwrap();
+                event_receiver.next().await.unwrap(); // timer tick event
+            }
+
+            // Second attempt goes through another address and succeeds:
+            let conn_request = conn_request_receiver.next().await.unwrap();
+            assert_ne!(conn_request.address.0, failed_address);
+            let (local_sender, _remote_receiver) = mpsc::channel(0);
+            let (_remote_sender, local_receiver) = mpsc::channel(0);
+            conn_request.reply(Some(ConnPairVec::from_raw(local_sender, local_receiver)));
+
+            let relays_health = relays_health_receiver.next().await.unwrap();
+            event_receiver.next().await.unwrap(); // connection attempt done event
+            assert_eq!(
+                relays_health
+                    .iter()
+                    .map(|relay_health| relay_health.success_count)
+                    .sum::<u64>(),
+                1
+            );
+            failed_address
+        };
+        let (_local_conn, failed_address) = join(connect_fut, handle_connect_fut).await;
+
+        // The failed address should not be attempted while healthy addresses exist,
+        // even after its backoff has passed:
+        for _ in 0..2 {
+            let connect_fut = connect_client.connect();
+            let handle_connect_fut = async {
+                event_receiver.next().await.unwrap(); // Connection request event
+                let conn_request = conn_request_receiver.next().await.unwrap();
+                assert_ne!(conn_request.address.0, failed_address);
+                let (local_sender, _remote_receiver) = mpsc::channel(0);
+                let (_remote_sender, local_receiver) = mpsc::channel(0);
+                conn_request.reply(Some(ConnPairVec::from_raw(local_sender, local_receiver)));
+                relays_health_receiver.next().await.unwrap();
+                event_receiver.next().await.unwrap(); // connection attempt done event
+            };
+            let (_local_conn, ()) = join(connect_fut, handle_connect_fut).await;
+        }
+    }
+
+    #[test]
+    fn test_pool_connector_prefer_healthy() {
+        let thread_pool = ThreadPool::new().unwrap();
+        block_on(task_pool_connector_prefer_healthy(thread_pool.clone()));
+    }
+
+    async fn task_pool_connector_exponential_backoff<S>(spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let backoff_ticks = 2;
+        let pk_b = PublicKey::from(&[0xbb; PublicKey::len()]);
+
+        let (
+            mut config_client,
+            mut connect_client,
+            mut relays_health_receiver,
+            mut conn_request_receiver,
+            _direct_request_receiver,
+            mut tick_sender,
+            mut event_receiver,
+        ) = spawn_test_connect_pool(pk_b.clone(), backoff_ticks, spawner).await;
+
+        config_client
+            .config(vec![0x0u32], Vec::new())
+            .await
+            .unwrap();
+        event_receiver.next().await.unwrap();
+
+        let connect_fut = connect_client.connect();
+        let handle_connect_fut = async {
+            event_receiver.next().await.unwrap(); // Connection request event
+
+            // Every failure doubles the amount of ticks we wait before the next attempt:
+            for i in 0..3 {
+                let conn_request = conn_request_receiver.next().await.unwrap();
+                assert_eq!(conn_request.address, (0x0u32, pk_b.clone()));
+                conn_request.reply(None);
+
+                let relays_health = relays_health_receiver.next().await.unwrap();
+                event_receiver.next().await.unwrap(); // connection attempt done event
+                assert_eq!(relays_health[0].consecutive_failures, i + 1);
+
+                let wait_ticks = backoff_ticks << i;
+                for _ in 0..wait_ticks - 1 {
+                    tick_sender.send(TimerTick).await.unwrap();
+                    event_receiver.next().await.unwrap(); // timer tick event
+                }
+                // We should not attempt to connect before the backoff is over:
+                assert!(conn_request_receiver.try_next().is_err());
+
+                tick_sender.send(TimerTick).await.unwrap();
+                event_receiver.next().await.unwrap(); // timer tick event
+            }
+
+            // Finally, the connection attempt succeeds:
+            let conn_request = conn_request_receiver.next().await.unwrap();
+            let (local_sender, _remote_receiver) = mpsc::channel(0);
+            let (_remote_sender, local_receiver) = mpsc::channel(0);
+            conn_request.reply(Some(ConnPairVec::from_raw(local_sender, local_receiver)));
+
+            let relays_health = relays_health_receiver.next().await.unwrap();
+            event_receiver.next().await.unwrap(); // connection attempt done event
+            assert_eq!(
+                relays_health,
+                vec![RelayHealth {
+                    relay_address: 0x0u32,
+                    success_count: 1,
+                    failure_count: 3,
+                    consecutive_failures: 0,
+                    latency_ticks: 0,
+                }]
+            );
+        };
+        let (_local_conn, ()) = join(connect_fut, handle_connect_fut).await;
+    }
+
+    #[test]
+    fn test_pool_connector_exponential_backoff() {
+        let thread_pool = ThreadPool::new().unwrap();
+        block_on(task_pool_connector_exponential_backoff(thread_pool.clone()));
+    }
+
+    async fn task_pool_connector_prefer_direct<S>(spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let backoff_ticks = 2;
+        let pk_b = PublicKey::from(&[0xbb; PublicKey::len()]);
+
+        let (
+            mut config_client,
+            mut connect_client,
+            mut relays_health_receiver,
+            mut conn_request_receiver,
+            mut direct_request_receiver,
+            mut tick_sender,
+            mut event_receiver,
+        ) = spawn_test_connect_pool(pk_b.clone(), backoff_ticks, spawner).await;
+
+        config_client
+            .config(vec![0x0u32], vec![0x100u32])
+            .await
+            .unwrap();
+        event_receiver.next().await.unwrap();
+
+        let connect_fut = connect_client.connect();
+        let handle_connect_fut = async {
+            event_receiver.next().await.unwrap(); // Connection request event
+
+            // The direct address is attempted first, and fails:
+            let direct_request = direct_request_receiver.next().await.unwrap();
+            assert_eq!(direct_request.address, 0x100u32);
+            direct_request.reply(None);
+
+            // Direct addresses are not reported as relays:
+            let relays_health = relays_health_receiver.next().await.unwrap();
+            event_receiver.next().await.unwrap(); // connection attempt done event
+            assert_eq!(
+                relays_health,
+                vec![RelayHealth {
+                    relay_address: 0x0u32,
+                    success_count: 0,
+                    failure_count: 0,
+                    consecutive_failures: 0,
+                    latency_ticks: 0,
+                }]
+            );
+
+            for _ in 0..backoff_ticks {
+                tick_sender.send(TimerTick).await.unwrap();
+                event_receiver.next().await.unwrap(); // timer tick event
+            }
+
+            // We fall back to the relay:
+            let conn_request = conn_request_receiver.next().await.unwrap();
+            assert_eq!(conn_request.address, (0x0u32, pk_b.clone()));
+            let (local_sender, _remote_receiver) = mpsc::channel(0);
+            let (_remote_sender, local_receiver) = mpsc::channel(0);
+            conn_request.reply(Some(ConnPairVec::from_raw(local_sender, local_receiver)));
+
+            relays_health_receiver.next().await.unwrap();
+            event_receiver.next().await.unwrap(); // connection attempt done event
+        };
+        let (_local_conn, ()) = join(connect_fut, handle_connect_fut).await;
+
+        // Wait until the direct address is not backing off anymore:
+        for _ in 0..backoff_ticks {
+            tick_sender.send(TimerTick).await.unwrap();
+            event_receiver.next().await.unwrap(); // timer tick event
+        }
+
+        // The relay is now healthier than the direct address, so it is preferred:
+        let connect_fut = connect_client.connect();
+        let handle_connect_fut = async {
+            event_receiver.next().await.unwrap(); // Connection request event
+            let conn_request = conn_request_receiver.next().await.unwrap();
+            assert_eq!(conn_request.address, (0x0u32, pk_b.clone()));
+            let (local_sender, _remote_receiver) = mpsc::channel(0);
+            let (_remote_sender, local_receiver) = mpsc::channel(0);
+            conn_request.reply(Some(ConnPairVec::from_raw(local_sender, local_receiver)));
+            relays_health_receiver.next().await.unwrap();
+            event_receiver.next().await.unwrap(); // connection attempt done event
+        };
+        let (_local_conn, ()) = join(connect_fut, handle_connect_fut).await;
+        assert!(direct_request_receiver.try_next().is_err());
+    }
+
+    #[test]
+    fn test_pool_connector_prefer_direct() {
+        let thread_pool = ThreadPool::new().unwrap();
+        block_on(task_pool_connector_prefer_direct(thread_pool.clone()));
+    }
 }
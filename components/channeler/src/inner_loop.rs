@@ -4,6 +4,7 @@ use std::fmt::Debug;
 use std::marker::Unpin;
 
 use futures::channel::{mpsc, oneshot};
+use futures::future::RemoteHandle;
 use futures::task::{Spawn, SpawnExt};
 use futures::{future, select, stream, FutureExt, Sink, SinkExt, Stream, StreamExt, TryFutureExt};
 
@@ -12,7 +13,9 @@ use common::select_streams::select_streams;
 use crypto::identity::compare_public_key;
 
 use proto::crypto::PublicKey;
-use proto::funder::messages::{ChannelerToFunder, ChannelerUpdateFriend, FunderToChanneler};
+use proto::funder::messages::{
+    ChannelerToFunder, ChannelerUpdateFriend, FunderToChanneler, RelayHealth,
+};
 
 use crate::connect_pool::{ConnectPoolControl, CpConfigClient, CpConnectClient};
 use crate::listen_pool::LpConfig;
@@ -22,7 +25,9 @@ use crate::overwrite_channel::overwrite_send_all;
 pub enum ChannelerEvent<RA> {
     FromFunder(FunderToChanneler<RA>),
     Connection((PublicKey, ConnPairVec)),
+    DirectConnection((PublicKey, ConnPairVec)),
     FriendEvent(FriendEvent),
+    RelaysHealth((PublicKey, Vec<RelayHealth<RA>>)),
     ListenerClosed,
     FunderClosed,
 }
@@ -90,7 +95,8 @@ enum InFriend {
 }
 
 enum OutFriendStatus {
-    Connecting,
+    /// Dropping the handle cancels the connection attempt
+    Connecting(RemoteHandle<()>),
     Connected(FriendConnected),
 }
 
@@ -100,6 +106,37 @@ struct OutFriend<RA> {
     status: OutFriendStatus,
 }
 
+/// Start a connection attempt to an out friend.
+/// Dropping the returned handle cancels the connection attempt.
+fn connect_out_friend<RA, S>(
+    friend_public_key: PublicKey,
+    mut connect_client: CpConnectClient,
+    mut event_sender: mpsc::Sender<ChannelerEvent<RA>>,
+    spawner: &S,
+) -> Result<RemoteHandle<()>, ChannelerError>
+where
+    RA: Send + 'static,
+    S: Spawn,
+{
+    let connect_fut = async move {
+        match connect_client.connect().await {
+            Ok(raw_conn) => {
+                let event = ChannelerEvent::Connection((friend_public_key, raw_conn));
+                let _ = event_sender.send(event).await;
+            }
+            Err(e) => {
+                // This probably happened because the friend was removed
+                // during connection attempt.
+                warn!("connect_out_friend(): connect() error: {:?}", e);
+            }
+        };
+    };
+
+    spawner
+        .spawn_with_handle(connect_fut)
+        .map_err(|_| ChannelerError::SpawnError)
+}
+
 struct Friends<RA> {
     /// Friends that should connect to us:
     in_friends: HashMap<PublicKey, InFriend>,
@@ -127,7 +164,7 @@ impl<RA> Friends<RA> {
 
         if let Some(out_friend) = self.out_friends.get_mut(public_key) {
             match &mut out_friend.status {
-                OutFriendStatus::Connecting => {}
+                OutFriendStatus::Connecting(_) => {}
                 OutFriendStatus::Connected(friend_connected) => return Some(friend_connected),
             }
         }
@@ -152,7 +189,7 @@ where
     RA: Clone + Send + Sync + 'static,
     C: FutTransform<Input = PublicKey, Output = ConnectPoolControl<RA>> + Clone + Send + 'static,
     S: Spawn + Clone + Send + 'static,
-    TF: Sink<ChannelerToFunder> + Send + Unpin,
+    TF: Sink<ChannelerToFunder<RA>> + Send + Unpin,
 {
     fn new(
         local_public_key: PublicKey,
@@ -179,37 +216,6 @@ where
         compare_public_key(&self.local_public_key, friend_public_key) == Ordering::Less
     }
 
-    fn connect_out_friend(&mut self, friend_public_key: &PublicKey) -> Result<(), ChannelerError> {
-        let out_friend = match self.friends.out_friends.get_mut(friend_public_key) {
-            Some(out_friend) => out_friend,
-            None => unreachable!(), // We assert that the out_friend exists.
-        };
-
-        let mut c_connect_client = out_friend.connect_client.clone();
-        let c_friend_public_key = friend_public_key.clone();
-        let mut c_event_sender = self.event_sender.clone();
-        let connect_fut = async move {
-            match c_connect_client.connect().await {
-                Ok(raw_conn) => {
-                    let event = ChannelerEvent::Connection((c_friend_public_key, raw_conn));
-                    let _ = c_event_sender.send(event).await;
-                }
-                Err(e) => {
-                    // This probably happened because the friend was removed
-                    // during connection attempt.
-                    warn!("connect_out_friend(): connect() error: {:?}", e);
-                }
-            };
-        };
-
-        self.spawner
-            .clone()
-            .spawn(connect_fut)
-            .map_err(|_| ChannelerError::SpawnError)?;
-
-        Ok(())
-    }
-
     /// Add friend if does not yet exist
     async fn try_create_friend<'a>(
         &'a mut self,
@@ -228,17 +234,40 @@ where
                 .in_friends
                 .insert(friend_public_key.clone(), InFriend::Listening);
         } else {
-            let (config_client, connect_client) =
+            let (config_client, connect_client, relays_health_receiver) =
                 self.connector.transform(friend_public_key.clone()).await;
+
+            // Forward relays health updates from the connect pool.
+            // This task ends when the connect pool is closed:
+            let c_friend_public_key = friend_public_key.clone();
+            let mut c_event_sender = self.event_sender.clone();
+            let mut relays_health_events = relays_health_receiver.map(move |relays_health| {
+                Ok(ChannelerEvent::RelaysHealth((
+                    c_friend_public_key.clone(),
+                    relays_health,
+                )))
+            });
+            let relays_health_fut = async move {
+                let _ = c_event_sender.send_all(&mut relays_health_events).await;
+            };
+            self.spawner
+                .spawn(relays_health_fut)
+                .map_err(|_| ChannelerError::SpawnError)?;
+
+            let connect_handle = connect_out_friend(
+                friend_public_key.clone(),
+                connect_client.clone(),
+                self.event_sender.clone(),
+                &self.spawner,
+            )?;
             let out_friend = OutFriend {
                 config_client,
                 connect_client,
-                status: OutFriendStatus::Connecting,
+                status: OutFriendStatus::Connecting(connect_handle),
             };
             self.friends
                 .out_friends
                 .insert(friend_public_key.clone(), out_friend);
-            self.connect_out_friend(friend_public_key)?;
         }
         Ok(())
     }
@@ -278,6 +307,7 @@ where
                 let ChannelerUpdateFriend {
                     friend_public_key,
                     friend_relays,
+                    friend_direct_addresses,
                     local_relays,
                 } = channeler_update_friend;
 
@@ -295,7 +325,7 @@ where
                 {
                     out_friend
                         .config_client
-                        .config(friend_relays)
+                        .config(friend_relays, friend_direct_addresses)
                         .await
                         .map_err(|_| ChannelerError::ConnectorConfigError)?;
                 }
@@ -319,6 +349,27 @@ where
         }
     }
 
+    /// Handle incoming direct connection (Not through a relay).
+    /// Anyone may connect to our direct addresses, so we only accept connections from friends.
+    /// Friends we should connect to are accepted too, as they may reach our direct addresses
+    /// before our own connection attempt succeeds.
+    async fn handle_direct_connection(
+        &mut self,
+        friend_public_key: PublicKey,
+        conn_pair: ConnPairVec,
+    ) -> Result<(), ChannelerError> {
+        if !self.friends.in_friends.contains_key(&friend_public_key)
+            && !self.friends.out_friends.contains_key(&friend_public_key)
+        {
+            warn!(
+                "handle_direct_connection(): {:?} is not a friend. Aborting",
+                friend_public_key
+            );
+            return Ok(());
+        }
+        self.handle_connection(friend_public_key, conn_pair).await
+    }
+
     /// Handle incoming connection from a remote friend
     async fn handle_connection(
         &mut self,
@@ -364,7 +415,9 @@ where
                     );
                     return Ok(());
                 }
-                OutFriendStatus::Connecting => {
+                OutFriendStatus::Connecting(_) => {
+                    // Dropping the connect handle cancels our own connection attempt, in case the
+                    // friend connected to us directly.
                     out_friend.status =
                         OutFriendStatus::Connected(Connected::new(friend_sender, closer))
                 }
@@ -413,6 +466,22 @@ where
         Ok(())
     }
 
+    async fn handle_relays_health(
+        &mut self,
+        relays_health: (PublicKey, Vec<RelayHealth<RA>>),
+    ) -> Result<(), ChannelerError> {
+        let (friend_public_key, relays_health) = relays_health;
+        if !self.friends.out_friends.contains_key(&friend_public_key) {
+            // The friend was removed in the meanwhile:
+            return Ok(());
+        }
+        let to_funder = ChannelerToFunder::RelaysHealth((friend_public_key, relays_health));
+        self.to_funder
+            .send(to_funder)
+            .await
+            .map_err(|_| ChannelerError::SendToFunderFailed)
+    }
+
     async fn handle_friend_event(
         &mut self,
         friend_event: FriendEvent,
@@ -452,8 +521,13 @@ where
                     self.friends.out_friends.get_mut(&friend_public_key)
                 {
                     // Request a new connection
-                    out_friend.status = OutFriendStatus::Connecting;
-                    self.connect_out_friend(&friend_public_key)?;
+                    let connect_handle = connect_out_friend(
+                        friend_public_key.clone(),
+                        out_friend.connect_client.clone(),
+                        self.event_sender.clone(),
+                        &self.spawner,
+                    )?;
+                    out_friend.status = OutFriendStatus::Connecting(connect_handle);
                 }
             }
         }
@@ -461,22 +535,24 @@ where
     }
 }
 
-pub async fn channeler_loop_inner<FF, TF, RA, C, L, S>(
+pub async fn channeler_loop_inner<FF, TF, RA, C, L, ID, S>(
     local_public_key: PublicKey,
     from_funder: FF,
     to_funder: TF,
     connector: C,
     listener: L,
+    incoming_direct_conns: ID,
     spawner: S,
 ) -> Result<(), ChannelerError>
 where
     FF: Stream<Item = FunderToChanneler<RA>> + Send + Unpin,
-    TF: Sink<ChannelerToFunder> + Send + Unpin,
+    TF: Sink<ChannelerToFunder<RA>> + Send + Unpin,
     RA: Clone + Send + Sync + Debug + 'static,
     C: FutTransform<Input = PublicKey, Output = ConnectPoolControl<RA>> + Clone + Send + 'static,
     L: Listener<Connection = (PublicKey, ConnPairVec), Config = LpConfig<RA>, Arg = ()>
         + Clone
         + Send,
+    ID: Stream<Item = (PublicKey, ConnPairVec)> + Unpin + Send,
     S: Spawn + Clone + Send + 'static,
 {
     let (event_sender, event_receiver) = mpsc::channel(0);
@@ -518,7 +594,11 @@ where
         .map(ChannelerEvent::FromFunder)
         .chain(stream::once(future::ready(ChannelerEvent::FunderClosed)));
 
-    let mut events = select_streams![event_receiver, from_funder];
+    // Incoming direct connections are handled inside the loop, so that closing the direct
+    // listener does not close the Channeler:
+    let incoming_direct_conns = incoming_direct_conns.map(ChannelerEvent::DirectConnection);
+
+    let mut events = select_streams![event_receiver, from_funder, incoming_direct_conns];
 
     while let Some(event) = events.next().await {
         match event {
@@ -528,9 +608,17 @@ where
             ChannelerEvent::Connection((public_key, raw_conn)) => {
                 channeler.handle_connection(public_key, raw_conn).await?
             }
+            ChannelerEvent::DirectConnection((public_key, raw_conn)) => {
+                channeler
+                    .handle_direct_connection(public_key, raw_conn)
+                    .await?
+            }
             ChannelerEvent::FriendEvent(friend_event) => {
                 channeler.handle_friend_event(friend_event).await?
             }
+            ChannelerEvent::RelaysHealth(relays_health) => {
+                channeler.handle_relays_health(relays_health).await?
+            }
             ChannelerEvent::ListenerClosed => return Err(ChannelerError::ListenerClosed),
             ChannelerEvent::FunderClosed => return Err(ChannelerError::FunderClosed),
         };
@@ -543,6 +631,8 @@ mod tests {
     use super::*;
     use futures::executor::{block_on, ThreadPool};
 
+    use crate::connect_pool::CpAddress;
+
     use common::dummy_connector::DummyConnector;
     use common::dummy_listener::DummyListener;
     use proto::crypto::PublicKey;
@@ -579,6 +669,7 @@ mod tests {
                     to_funder,
                     connector,
                     listener,
+                    stream::empty(),
                     spawner.clone(),
                 )
                 .map_err(|e| error!("Error in channeler_loop(): {:?}", e))
@@ -628,6 +719,7 @@ mod tests {
         let channeler_update_friend = ChannelerUpdateFriend {
             friend_public_key: pks[0].clone(),
             friend_relays: vec![0x0u32],
+            friend_direct_addresses: vec![],
             local_relays: vec![0x2u32, 0x3u32],
         };
 
@@ -639,13 +731,14 @@ mod tests {
         assert_eq!(conn_request.address, pks[0]);
         let (connect_sender0, mut connect_receiver0) = mpsc::channel(0);
         let (config_sender0, mut config_receiver0) = mpsc::channel(0);
+        let (mut relays_health_sender0, relays_health_receiver0) = mpsc::channel(0);
 
         let config_client0 = CpConfigClient::new(config_sender0);
         let connect_client0 = CpConnectClient::new(connect_sender0);
-        conn_request.reply((config_client0, connect_client0));
+        conn_request.reply((config_client0, connect_client0, relays_health_receiver0));
 
         let config0 = config_receiver0.next().await.unwrap();
-        assert_eq!(config0, vec![0x0u32]);
+        assert_eq!(config0, vec![CpAddress::Relay(0x0u32)]);
 
         let connect_req0 = connect_receiver0.next().await.unwrap();
 
@@ -684,6 +777,29 @@ mod tests {
             _ => unreachable!(),
         };
 
+        // The connect pool reports the health of pks[0] relays:
+        let relays_health = vec![RelayHealth {
+            relay_address: 0x0u32,
+            success_count: 1,
+            failure_count: 0,
+            consecutive_failures: 0,
+            latency_ticks: 0,
+        }];
+        relays_health_sender0
+            .send(relays_health.clone())
+            .await
+            .unwrap();
+
+        // Relays health should be forwarded to the funder:
+        let channeler_to_funder = funder_receiver.next().await.unwrap();
+        match channeler_to_funder {
+            ChannelerToFunder::RelaysHealth((public_key, received_relays_health)) => {
+                assert_eq!(public_key, pks[0]);
+                assert_eq!(received_relays_health, relays_health);
+            }
+            _ => unreachable!(),
+        };
+
         // Drop pks[0] connection:
         drop(pk0_sender);
         drop(pk0_receiver);
@@ -785,6 +901,7 @@ mod tests {
                     to_funder,
                     connector,
                     listener,
+                    stream::empty(),
                     spawner.clone(),
                 )
                 .map_err(|e| error!("Error in channeler_loop(): {:?}", e))
@@ -806,6 +923,7 @@ mod tests {
         let channeler_update_friend = ChannelerUpdateFriend {
             friend_public_key: pks[2].clone(),
             friend_relays: vec![0x0u32],
+            friend_direct_addresses: vec![],
             local_relays: vec![0x2u32, 0x3u32],
         };
         funder_sender
@@ -883,6 +1001,157 @@ mod tests {
         block_on(task_channeler_loop_listen_friend(thread_pool.clone()));
     }
 
+    /// Test incoming direct connections (Not through a relay)
+    async fn task_channeler_loop_direct_connection<S>(spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let (mut funder_sender, from_funder) = mpsc::channel(0);
+        let (to_funder, mut funder_receiver) = mpsc::channel(0);
+
+        // Our local public key will be pks[1]. pks[0] < pks[1] < pks[2]
+        // pks[2] will be a listen friend, pks[0] is not a friend at first.
+        // Later pks[0] is added as a friend we connect to.
+        let mut pks = (0..3)
+            .map(|i| PublicKey::from(&[i; PublicKey::len()]))
+            .collect::<Vec<PublicKey>>();
+        pks.sort_by(compare_public_key);
+
+        let (conn_request_sender, mut conn_request_receiver) = mpsc::channel(0);
+        let connector = DummyConnector::new(conn_request_sender);
+
+        let (listener_req_sender, mut listener_req_receiver) = mpsc::channel(1);
+        let listener = DummyListener::new(listener_req_sender);
+
+        let (mut direct_conns_sender, incoming_direct_conns) = mpsc::channel(0);
+
+        spawner
+            .spawn(
+                channeler_loop_inner(
+                    pks[1].clone(),
+                    from_funder,
+                    to_funder,
+                    connector,
+                    listener,
+                    incoming_direct_conns,
+                    spawner.clone(),
+                )
+                .map_err(|e| error!("Error in channeler_loop(): {:?}", e))
+                .map(|_| ()),
+            )
+            .unwrap();
+
+        let mut listener_request = listener_req_receiver.next().await.unwrap();
+
+        // Add a friend:
+        let channeler_update_friend = ChannelerUpdateFriend {
+            friend_public_key: pks[2].clone(),
+            friend_relays: vec![0x0u32],
+            friend_direct_addresses: vec![],
+            local_relays: vec![0x2u32],
+        };
+        funder_sender
+            .send(FunderToChanneler::UpdateFriend(channeler_update_friend))
+            .await
+            .unwrap();
+
+        let lp_config = listener_request.config_receiver.next().await.unwrap();
+        assert_eq!(
+            lp_config,
+            LpConfig::UpdateFriend((pks[2].clone(), vec![0x2u32]))
+        );
+
+        // A direct connection from someone who is not a friend is dropped:
+        let (_pk0_sender, receiver) = mpsc::channel::<Vec<u8>>(0);
+        let (sender, mut pk0_receiver) = mpsc::channel(0);
+        direct_conns_sender
+            .send((pks[0].clone(), ConnPairVec::from_raw(sender, receiver)))
+            .await
+            .unwrap();
+        assert!(pk0_receiver.next().await.is_none());
+
+        // A direct connection from pks[2]:
+        let (mut pk2_sender, receiver) = mpsc::channel(0);
+        let (sender, mut pk2_receiver) = mpsc::channel(0);
+        direct_conns_sender
+            .send((pks[2].clone(), ConnPairVec::from_raw(sender, receiver)))
+            .await
+            .unwrap();
+
+        // Friend should be reported as online:
+        let channeler_to_funder = funder_receiver.next().await.unwrap();
+        match channeler_to_funder {
+            ChannelerToFunder::Online(public_key) => assert_eq!(public_key, pks[2]),
+            _ => unreachable!(),
+        };
+
+        // Send a message to pks[2]:
+        funder_sender
+            .send(FunderToChanneler::Message((pks[2].clone(), vec![1, 2, 3])))
+            .await
+            .unwrap();
+        assert_eq!(pk2_receiver.next().await.unwrap(), vec![1, 2, 3]);
+
+        // Send a message from pks[2]:
+        pk2_sender.send(vec![3, 2, 1]).await.unwrap();
+        let channeler_to_funder = funder_receiver.next().await.unwrap();
+        match channeler_to_funder {
+            ChannelerToFunder::Message((public_key, message)) => {
+                assert_eq!(public_key, pks[2]);
+                assert_eq!(message, vec![3, 2, 1]);
+            }
+            _ => unreachable!(),
+        };
+
+        // Add pks[0] as a friend. We should connect to pks[0]:
+        let channeler_update_friend = ChannelerUpdateFriend {
+            friend_public_key: pks[0].clone(),
+            friend_relays: vec![0x0u32],
+            friend_direct_addresses: vec![],
+            local_relays: vec![0x2u32],
+        };
+        funder_sender
+            .send(FunderToChanneler::UpdateFriend(channeler_update_friend))
+            .await
+            .unwrap();
+
+        let conn_request = conn_request_receiver.next().await.unwrap();
+        assert_eq!(conn_request.address, pks[0]);
+        let (connect_sender0, mut connect_receiver0) = mpsc::channel(1);
+        let (config_sender0, _config_receiver0) = mpsc::channel(1);
+        let (_relays_health_sender0, relays_health_receiver0) = mpsc::channel(0);
+
+        let config_client0 = CpConfigClient::new(config_sender0);
+        let connect_client0 = CpConnectClient::new(connect_sender0);
+        conn_request.reply((config_client0, connect_client0, relays_health_receiver0));
+
+        // The channeler attempts to connect to pks[0]:
+        let mut connect_request = connect_receiver0.next().await.unwrap();
+
+        // pks[0] connects to us directly before our connection attempt succeeds:
+        let (_pk0_sender, receiver) = mpsc::channel::<Vec<u8>>(0);
+        let (sender, _pk0_receiver) = mpsc::channel(0);
+        direct_conns_sender
+            .send((pks[0].clone(), ConnPairVec::from_raw(sender, receiver)))
+            .await
+            .unwrap();
+
+        let channeler_to_funder = funder_receiver.next().await.unwrap();
+        match channeler_to_funder {
+            ChannelerToFunder::Online(public_key) => assert_eq!(public_key, pks[0]),
+            _ => unreachable!(),
+        };
+
+        // Our own connection attempt is abandoned:
+        connect_request.response_sender.cancellation().await;
+    }
+
+    #[test]
+    fn test_channeler_loop_direct_connection() {
+        let thread_pool = ThreadPool::new().unwrap();
+        block_on(task_channeler_loop_direct_connection(thread_pool.clone()));
+    }
+
     // ------------------------------------------------------------
     // ------------------------------------------------------------
 
@@ -920,6 +1189,7 @@ mod tests {
                     to_funder,
                     connector,
                     listener,
+                    stream::empty(),
                     spawner.clone(),
                 )
                 .map_err(|e| error!("Error in channeler_loop(): {:?}", e))
@@ -942,6 +1212,7 @@ mod tests {
             let channeler_update_friend = ChannelerUpdateFriend {
                 friend_public_key: pks[2].clone(),
                 friend_relays: vec![0x0u32],
+                friend_direct_addresses: vec![],
                 local_relays: vec![0x2u32, 0x3u32],
             };
             funder_sender
@@ -1030,6 +1301,7 @@ mod tests {
                     to_funder,
                     connector,
                     listener,
+                    stream::empty(),
                     spawner.clone(),
                 )
                 .map_err(|e| error!("Error in channeler_loop(): {:?}", e))
@@ -1055,6 +1327,7 @@ mod tests {
         let channeler_update_friend = ChannelerUpdateFriend {
             friend_public_key: pks[0].clone(),
             friend_relays: vec![0x0u32],
+            friend_direct_addresses: vec![],
             local_relays: vec![0x2u32, 0x3u32],
         };
 
@@ -1076,10 +1349,11 @@ mod tests {
         // Reply to the conn request too late:
         let (connect_sender0, _connect_receiver0) = mpsc::channel(1);
         let (config_sender0, _config_receiver0) = mpsc::channel(1);
+        let (_relays_health_sender0, relays_health_receiver0) = mpsc::channel(0);
 
         let config_client0 = CpConfigClient::new(config_sender0);
         let connect_client0 = CpConnectClient::new(connect_sender0);
-        conn_request.reply((config_client0, connect_client0));
+        conn_request.reply((config_client0, connect_client0, relays_health_receiver0));
 
         // UpdateFriend again, to make sure channeler is still alive:
         funder_sender
@@ -1093,10 +1367,11 @@ mod tests {
         // Reply to the conn request, to avoid panic on exit:
         let (connect_sender0, _connect_receiver0) = mpsc::channel(1);
         let (config_sender0, _config_receiver0) = mpsc::channel(1);
+        let (_relays_health_sender0, relays_health_receiver0) = mpsc::channel(0);
 
         let config_client0 = CpConfigClient::new(config_sender0);
         let connect_client0 = CpConnectClient::new(connect_sender0);
-        conn_request.reply((config_client0, connect_client0));
+        conn_request.reply((config_client0, connect_client0, relays_health_receiver0));
     }
 
     #[test]
@@ -16,3 +16,5 @@ mod transforms;
 pub use self::transforms::{
     create_encrypt_keepalive, create_secure_connector, create_version_encrypt_keepalive,
 };
+
+pub use secure_channel::PaddingConfig;
@@ -1,8 +1,9 @@
 use futures::task::Spawn;
+use futures::StreamExt;
 
 use common::conn::{ConnPairVec, FuncFutTransform, FutTransform};
 
-use proto::consts::{KEEPALIVE_TICKS, PROTOCOL_VERSION, TICKS_TO_REKEY};
+use proto::consts::{KEEPALIVE_TICKS, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION, TICKS_TO_REKEY};
 use proto::crypto::PublicKey;
 use proto::net::messages::NetAddress;
 
@@ -12,7 +13,8 @@ use identity::IdentityClient;
 use timer::TimerClient;
 
 use keepalive::KeepAliveChannel;
-use secure_channel::SecureChannel;
+use mux::create_mux;
+use secure_channel::{PaddingConfig, SecureChannel};
 use version::VersionPrefix;
 
 use crate::timeout::TimeoutFutTransform;
@@ -20,12 +22,52 @@ use crate::timeout::TimeoutFutTransform;
 /// Amount of ticks we allocate to perform a complete handshake.
 pub const CONN_TIMEOUT_TICKS: usize = 8;
 
-/// Create an encrypt-keepalive transformation:
-/// Composes: Encryption * Keepalive
+/// Maximum amount of messages sent on a multiplexed friend stream before they are read by the
+/// remote side.
+const FRIEND_STREAM_WINDOW: usize = 0x10;
+/// Maximum amount of streams each side of a friend connection may open.
+/// Only the funder stream is used for now.
+const FRIEND_MAX_STREAMS: usize = 1;
+
+/// Multiplex a friend connection, and get the stream that carries the funder messages.
+/// The side with the lower public key opens the stream, and the other side accepts it.
+async fn open_funder_stream<S>(
+    local_public_key: &PublicKey,
+    remote_public_key: &PublicKey,
+    conn_pair: ConnPairVec,
+    spawner: S,
+) -> Option<ConnPairVec>
+where
+    S: Spawn + Clone + Send + 'static,
+{
+    let (mut mux_client, mut incoming_streams) =
+        create_mux(conn_pair, FRIEND_STREAM_WINDOW, FRIEND_MAX_STREAMS, spawner).ok()?;
+    if local_public_key < remote_public_key {
+        mux_client.open_stream().await.ok()
+    } else {
+        incoming_streams.next().await
+    }
+}
+
+/// Create an encrypt-keepalive transformation for connections between friends:
+/// Composes: Encryption * Keepalive * (Multiplexing)
+///
+/// Connections are multiplexed if both friends support it, and the returned connection is the
+/// stream that carries the funder messages. Friends running older versions do not support
+/// multiplexing, and use the connection as a single stream.
+///
+/// `opt_padding_config` enables traffic padding (and possibly cover traffic) for remote sides that
+/// support it.
+///
+/// `resume_sessions` enables session resumption when reconnecting to a friend. Resumed sessions
+/// can be linked to each other by an observer, because resumption ticket ids are sent in plain
+/// text.
 pub fn create_encrypt_keepalive<R, S>(
     timer_client: TimerClient,
     identity_client: IdentityClient,
     rng: R,
+    opt_padding_config: Option<PaddingConfig>,
+    resume_sessions: bool,
     spawner: S,
 ) -> impl FutTransform<
     Input = (Option<PublicKey>, ConnPairVec),
@@ -36,26 +78,40 @@ where
     S: Spawn + Clone + Send + 'static,
     R: CryptoRandom + Clone + Send + Sync + 'static,
 {
-    // Wrap the connection (Version * Encrypt * Keepalive):
+    // Wrap the connection (Encrypt * Keepalive * Mux):
     let encrypt_transform = SecureChannel::new(
-        identity_client,
+        identity_client.clone(),
         rng,
         timer_client.clone(),
         TICKS_TO_REKEY,
+        opt_padding_config,
+        resume_sessions,
+        true,
         spawner.clone(),
     );
-    let keepalive_transform = KeepAliveChannel::new(timer_client.clone(), KEEPALIVE_TICKS, spawner);
+    let keepalive_transform =
+        KeepAliveChannel::new(timer_client.clone(), KEEPALIVE_TICKS, spawner.clone());
 
     // Note that this transform does not contain the version prefix, as it is applied to a
     // connection between two nodes, relayed using a relay server.
+    // Support for multiplexing is negotiated during the encryption handshake instead.
     let fut_transform = FuncFutTransform::new(move |(opt_public_key, conn_pair_vec)| {
+        let c_identity_client = identity_client.clone();
         let mut c_encrypt_transform = encrypt_transform.clone();
         let mut c_keepalive_transform = keepalive_transform.clone();
+        let c_spawner = spawner.clone();
         Box::pin(async move {
-            let (public_key, conn_pair_vec) = c_encrypt_transform
+            let (public_key, multiplexed, conn_pair_vec) = c_encrypt_transform
                 .transform((opt_public_key, conn_pair_vec))
                 .await?;
             let conn_pair_vec = c_keepalive_transform.transform(conn_pair_vec).await;
+            if !multiplexed {
+                return Some((public_key, conn_pair_vec));
+            }
+            let local_public_key = c_identity_client.request_public_key().await.ok()?;
+            let conn_pair_vec =
+                open_funder_stream(&local_public_key, &public_key, conn_pair_vec, c_spawner)
+                    .await?;
             Some((public_key, conn_pair_vec))
         })
     });
@@ -79,12 +135,18 @@ where
     R: CryptoRandom + Clone + Send + Sync + 'static,
 {
     // Wrap the connection (Version * Encrypt * Keepalive):
-    let version_transform = VersionPrefix::new(PROTOCOL_VERSION, spawner.clone());
+    let version_transform = VersionPrefix::new(MIN_PROTOCOL_VERSION, PROTOCOL_VERSION)
+        .expect("MIN_PROTOCOL_VERSION is larger than PROTOCOL_VERSION");
     let encrypt_transform = SecureChannel::new(
         identity_client,
         rng,
         timer_client.clone(),
         TICKS_TO_REKEY,
+        None,
+        // Servers accept connections from any remote side, so sessions can never be resumed:
+        false,
+        // Connections to servers are never multiplexed:
+        false,
         spawner.clone(),
     );
     let keepalive_transform = KeepAliveChannel::new(timer_client.clone(), KEEPALIVE_TICKS, spawner);
@@ -94,8 +156,10 @@ where
         let mut c_encrypt_transform = encrypt_transform.clone();
         let mut c_keepalive_transform = keepalive_transform.clone();
         Box::pin(async move {
-            let conn_pair = c_version_transform.transform(conn_pair).await;
-            let (public_key, conn_pair) = c_encrypt_transform
+            // No layer above depends on the negotiated version yet. Layers that need it can
+            // compose `VersionPrefix` directly:
+            let (_version, conn_pair) = c_version_transform.transform(conn_pair).await?;
+            let (public_key, _multiplexed, conn_pair) = c_encrypt_transform
                 .transform((opt_public_key, conn_pair))
                 .await?;
             let conn_pair = c_keepalive_transform.transform(conn_pair).await;
@@ -1,19 +1,45 @@
+use im::hashmap::HashMap as ImHashMap;
+
+use proto::crypto::{InvoiceId, PublicKey, Uid};
+use proto::report::messages::RelayHealthReport;
+
 use super::liveness::{Liveness, LivenessMutation};
 
 #[derive(Clone, Default)]
 pub struct Ephemeral {
     pub liveness: Liveness,
+    /// Health of the relays used to connect to each friend, as reported by the Channeler
+    pub relays_health: ImHashMap<PublicKey, Vec<RelayHealthReport>>,
+    /// Amount of timer ticks since the funder was started
+    pub ticks: u64,
+    /// Tick (See `ticks`) in which a swap proposed by a friend expires.
+    /// Remote swaps that were not accepted by then are canceled.
+    pub remote_swap_deadlines: ImHashMap<(PublicKey, Uid), u64>,
+    /// Tick (See `ticks`) in which a held invoice expires.
+    /// Held invoices that were not committed by then are canceled.
+    pub invoice_hold_deadlines: ImHashMap<InvoiceId, u64>,
 }
 
 #[derive(Debug)]
 pub enum EphemeralMutation {
     LivenessMutation(LivenessMutation),
+    SetRelaysHealth((PublicKey, Vec<RelayHealthReport>)),
+    ClearRelaysHealth(PublicKey),
+    Tick,
+    SetRemoteSwapDeadline((PublicKey, Uid, u64)),
+    RemoveRemoteSwapDeadline((PublicKey, Uid)),
+    SetInvoiceHoldDeadline((InvoiceId, u64)),
+    RemoveInvoiceHoldDeadline(InvoiceId),
 }
 
 impl Ephemeral {
     pub fn new() -> Ephemeral {
         Ephemeral {
             liveness: Liveness::new(),
+            relays_health: ImHashMap::new(),
+            ticks: 0,
+            remote_swap_deadlines: ImHashMap::new(),
+            invoice_hold_deadlines: ImHashMap::new(),
         }
     }
 
@@ -22,6 +48,35 @@ impl Ephemeral {
             EphemeralMutation::LivenessMutation(liveness_mutation) => {
                 self.liveness.mutate(liveness_mutation)
             }
+            EphemeralMutation::SetRelaysHealth((friend_public_key, relays_health)) => {
+                let _ = self
+                    .relays_health
+                    .insert(friend_public_key.clone(), relays_health.clone());
+            }
+            EphemeralMutation::ClearRelaysHealth(friend_public_key) => {
+                let _ = self.relays_health.remove(friend_public_key);
+            }
+            EphemeralMutation::Tick => {
+                self.ticks = self.ticks.saturating_add(1);
+            }
+            EphemeralMutation::SetRemoteSwapDeadline((friend_public_key, swap_id, deadline)) => {
+                let _ = self
+                    .remote_swap_deadlines
+                    .insert((friend_public_key.clone(), swap_id.clone()), *deadline);
+            }
+            EphemeralMutation::RemoveRemoteSwapDeadline((friend_public_key, swap_id)) => {
+                let _ = self
+                    .remote_swap_deadlines
+                    .remove(&(friend_public_key.clone(), swap_id.clone()));
+            }
+            EphemeralMutation::SetInvoiceHoldDeadline((invoice_id, deadline)) => {
+                let _ = self
+                    .invoice_hold_deadlines
+                    .insert(invoice_id.clone(), *deadline);
+            }
+            EphemeralMutation::RemoveInvoiceHoldDeadline(invoice_id) => {
+                let _ = self.invoice_hold_deadlines.remove(invoice_id);
+            }
         }
     }
 }
@@ -7,10 +7,11 @@ use common::ser_utils::{ser_b64, ser_map_str_any, ser_string};
 use signature::canonical::CanonicalSerialize;
 
 use proto::app_server::messages::{NamedRelayAddress, RelayAddress};
-use proto::crypto::PublicKey;
+use proto::crypto::{PublicKey, Signature};
 use proto::funder::messages::{
-    CancelSendFundsOp, CollectSendFundsOp, Currency, FriendStatus, Rate, RequestSendFundsOp,
-    ResetTerms, ResponseSendFundsOp,
+    AcceptSwapOp, CancelSendFundsOp, CancelSwapOp, CollectSendFundsOp, Currency, FriendSettlement,
+    FriendStatus, Rate, RequestSendFundsOp, RequestSwapOp, ResetTerms, ResponseSendFundsOp,
+    TokenInfo,
 };
 
 use crate::token_channel::{TcMutation, TokenChannel};
@@ -24,6 +25,14 @@ pub enum BackwardsOp {
     Collect(CollectSendFundsOp),
 }
 
+/// A currency swap operation initiated by the local user
+#[derive(Arbitrary, Clone, Serialize, Deserialize, PartialEq, Eq, Debug)]
+pub enum SwapOp {
+    Request(RequestSwapOp),
+    Accept(AcceptSwapOp),
+    Cancel(CancelSwapOp),
+}
+
 #[derive(Arbitrary, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum SentLocalRelays<B>
 where
@@ -84,6 +93,10 @@ pub struct ChannelConsistent<B> {
     /// We care more about these requests, because those are payments that our user wants to make.
     /// This queue should be bounded in size (TODO: Check this)
     pub pending_user_requests: ImVec<(Currency, RequestSendFundsOp)>,
+    /// A queue of currency swap operations (Request, Accept, Cancel) that need to be sent to the
+    /// remote side
+    #[serde(default)]
+    pub pending_swap_ops: ImVec<(Currency, SwapOp)>,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -110,6 +123,42 @@ where
     }
 }
 
+/// A signature over the final state of a token channel
+#[derive(Arbitrary, Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct SettlementSignature {
+    /// Final state of the token channel, from our point of view
+    pub token_info: TokenInfo,
+    #[serde(with = "ser_b64")]
+    pub signature: Signature,
+}
+
+#[derive(Arbitrary, Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct FriendClosing {
+    /// Our signature over the final state, if we have already sent it to the remote side
+    pub opt_local_signature: Option<SettlementSignature>,
+    /// Signature of the remote side over the final state
+    pub opt_remote_signature: Option<SettlementSignature>,
+}
+
+/// Progress of a cooperative close of the channel with a friend.
+#[allow(clippy::large_enum_variant)]
+#[derive(Arbitrary, Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub enum FriendCloseStatus {
+    /// The channel is in normal use
+    Open,
+    /// New requests are not allowed. We wait for all pending transactions to resolve, and then
+    /// exchange signatures over the final balances with the remote side.
+    Closing(FriendClosing),
+    /// Both sides have signed over the final balances. The friend can now be removed.
+    Closed(FriendSettlement),
+}
+
+impl Default for FriendCloseStatus {
+    fn default() -> Self {
+        FriendCloseStatus::Open
+    }
+}
+
 #[derive(Arbitrary, Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct CurrencyConfig {
     /// Rate of forwarding transactions that arrived from this friend to any other friend
@@ -137,6 +186,13 @@ pub struct FriendState<B: Clone> {
     /// The last list of our used relays we have sent to the remote friend.
     /// We maintain this list to deal with relays drift.
     pub sent_local_relays: SentLocalRelays<B>,
+    /// Addresses on which the friend node accepts direct connections.
+    /// This list corresponds to the last report of direct addresses we got from the remote friend.
+    #[serde(default)]
+    pub remote_direct_addresses: Vec<B>,
+    /// The last list of our direct addresses we have sent to the remote friend.
+    #[serde(default)]
+    pub sent_direct_addresses: Vec<B>,
     /// Locally maintained name of the remote friend node.
     pub name: String,
     /// Local configurations for currencies relationship with this friend
@@ -147,6 +203,9 @@ pub struct FriendState<B: Clone> {
     pub status: FriendStatus,
     /// Mutual credit channel information
     pub channel_status: ChannelStatus<B>,
+    /// Progress of a cooperative close of the channel
+    #[serde(default)]
+    pub close_status: FriendCloseStatus,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -163,6 +222,8 @@ pub enum FriendMutation<B: Clone> {
     PopFrontPendingBackwardsOp,
     PushBackPendingUserRequest((Currency, RequestSendFundsOp)),
     PopFrontPendingUserRequest,
+    PushBackPendingSwapOp((Currency, SwapOp)),
+    PopFrontPendingSwapOp,
     RemovePendingRequestsCurrency(Currency),
     RemovePendingUserRequestsCurrency(Currency),
     RemovePendingRequests,
@@ -170,6 +231,18 @@ pub enum FriendMutation<B: Clone> {
     SetRemoteRelays(Vec<RelayAddress<B>>),
     SetName(String),
     SetSentLocalRelays(SentLocalRelays<B>),
+    SetRemoteDirectAddresses(Vec<B>),
+    SetSentDirectAddresses(Vec<B>),
+    SetCloseStatus(FriendCloseStatus),
+}
+
+impl FriendClosing {
+    pub fn new() -> Self {
+        Self {
+            opt_local_signature: None,
+            opt_remote_signature: None,
+        }
+    }
 }
 
 impl CurrencyConfig {
@@ -197,6 +270,7 @@ where
             pending_requests: ImVec::new(),
             pending_backwards_ops: ImVec::new(),
             pending_user_requests: ImVec::new(),
+            pending_swap_ops: ImVec::new(),
         };
 
         FriendState {
@@ -204,10 +278,33 @@ where
             remote_public_key: remote_public_key.clone(),
             remote_relays,
             sent_local_relays: SentLocalRelays::NeverSent,
+            remote_direct_addresses: Vec::new(),
+            sent_direct_addresses: Vec::new(),
             name,
             currency_configs: ImHashMap::new(),
             status: FriendStatus::Disabled,
             channel_status: ChannelStatus::Consistent(channel_consistent),
+            close_status: FriendCloseStatus::Open,
+        }
+    }
+
+    /// Direct addresses of the remote friend, in the form of addresses we can connect to.
+    /// The public key of every address is the public key of the remote friend.
+    pub fn remote_direct_relay_addresses(&self) -> Vec<RelayAddress<B>> {
+        self.remote_direct_addresses
+            .iter()
+            .cloned()
+            .map(|address| RelayAddress {
+                public_key: self.remote_public_key.clone(),
+                address,
+            })
+            .collect()
+    }
+
+    pub fn is_closing(&self) -> bool {
+        match &self.close_status {
+            FriendCloseStatus::Open => false,
+            FriendCloseStatus::Closing(_) | FriendCloseStatus::Closed(_) => true,
         }
     }
 
@@ -257,6 +354,7 @@ where
                     pending_requests: ImVec::new(),
                     pending_backwards_ops: ImVec::new(),
                     pending_user_requests: ImVec::new(),
+                    pending_swap_ops: ImVec::new(),
                 };
                 self.channel_status = ChannelStatus::Consistent(channel_consistent);
             }
@@ -329,6 +427,22 @@ where
                     unreachable!();
                 }
             }
+            FriendMutation::PushBackPendingSwapOp((currency, swap_op)) => {
+                if let ChannelStatus::Consistent(channel_consistent) = &mut self.channel_status {
+                    channel_consistent
+                        .pending_swap_ops
+                        .push_back((currency.clone(), swap_op.clone()));
+                } else {
+                    unreachable!();
+                }
+            }
+            FriendMutation::PopFrontPendingSwapOp => {
+                if let ChannelStatus::Consistent(channel_consistent) = &mut self.channel_status {
+                    channel_consistent.pending_swap_ops.pop_front();
+                } else {
+                    unreachable!();
+                }
+            }
             FriendMutation::RemovePendingRequestsCurrency(currency) => {
                 // Remove all pending outgoing messages for a certain currency.
                 if let ChannelStatus::Consistent(channel_consistent) = &mut self.channel_status {
@@ -369,6 +483,15 @@ where
             FriendMutation::SetSentLocalRelays(sent_local_relays) => {
                 self.sent_local_relays = sent_local_relays.clone();
             }
+            FriendMutation::SetRemoteDirectAddresses(remote_direct_addresses) => {
+                self.remote_direct_addresses = remote_direct_addresses.clone();
+            }
+            FriendMutation::SetSentDirectAddresses(sent_direct_addresses) => {
+                self.sent_direct_addresses = sent_direct_addresses.clone();
+            }
+            FriendMutation::SetCloseStatus(close_status) => {
+                self.close_status = close_status.clone();
+            }
         }
     }
 }
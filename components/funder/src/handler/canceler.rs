@@ -1,20 +1,23 @@
+use std::collections::HashSet;
 use std::fmt::Debug;
 
 use signature::canonical::CanonicalSerialize;
 
 use crypto::rand::{CryptoRandom, RandGen};
 
-use proto::crypto::{PublicKey, Uid};
+use proto::crypto::{InvoiceId, PublicKey, Uid};
 use proto::funder::messages::{
-    Currency, FunderOutgoingControl, PaymentStatus, PaymentStatusSuccess, RequestResult,
-    RequestSendFundsOp, ResponseClosePayment, TransactionResult,
+    CancelSwapOp, Currency, FunderOutgoingControl, PaymentStatus, PaymentStatusSuccess,
+    RequestResult, RequestSendFundsOp, ResponseClosePayment, TransactionResult,
 };
 
 use crate::handler::state_wrap::MutableFunderState;
 use crate::handler::types::SendCommands;
 use crate::handler::utils::find_request_origin;
 
-use crate::friend::{BackwardsOp, ChannelStatus, FriendMutation};
+use crate::friend::{
+    BackwardsOp, ChannelStatus, FriendCloseStatus, FriendClosing, FriendMutation, SwapOp,
+};
 use crate::state::{FunderMutation, Payment, PaymentStage};
 use crate::types::{create_cancel_send_funds, create_pending_transaction};
 
@@ -48,6 +51,47 @@ pub fn reply_with_cancel<B>(
 }
 
 /// Remove a local transaction (Where this node is the buyer side)
+/// Cancel all pending transactions related to an open invoice, and remove the invoice.
+pub fn cancel_invoice<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    invoice_id: &InvoiceId,
+) where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    let open_invoice = m_state
+        .state()
+        .open_invoices
+        .get(invoice_id)
+        .unwrap()
+        .clone();
+
+    // Cancel all pending transactions related to this invoice
+    for request_id in &open_invoice.incoming_transactions {
+        // The origin of this request might not exist anymore if the token channel with the
+        // friend was reset. In that case there is nothing left to cancel.
+        let (friend_public_key, currency) = if let Some((friend_public_key, currency)) =
+            find_request_origin(m_state.state(), &request_id)
+        {
+            (friend_public_key.clone(), currency.clone())
+        } else {
+            warn!("cancel_invoice(): Failed to find request origin");
+            continue;
+        };
+        reply_with_cancel(
+            m_state,
+            send_commands,
+            &friend_public_key,
+            &currency,
+            &request_id,
+        );
+    }
+
+    // Remove invoice:
+    let funder_mutation = FunderMutation::RemoveInvoice(invoice_id.clone());
+    m_state.mutate(funder_mutation);
+}
+
 pub fn remove_transaction<B, R>(
     m_state: &mut MutableFunderState<B>,
     outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
@@ -189,16 +233,19 @@ pub fn cancel_local_pending_transactions<B, R>(
 
         // Prepare a list of all remote requests that we need to cancel:
         for (local_request_id, pending_local_transaction) in pending_local_transactions {
-            let opt_origin_public_key =
-                find_request_origin(m_state.state(), &currency, &local_request_id).cloned();
-            match opt_origin_public_key {
-                Some(origin_public_key) => {
+            let opt_origin = find_request_origin(m_state.state(), &local_request_id).map(
+                |(origin_public_key, origin_currency)| {
+                    (origin_public_key.clone(), origin_currency.clone())
+                },
+            );
+            match opt_origin {
+                Some((origin_public_key, origin_currency)) => {
                     // We have found the friend that is the origin of this request.
                     // We send him a cancel message.
                     let cancel_send_funds =
                         create_cancel_send_funds(pending_local_transaction.request_id);
                     let friend_mutation = FriendMutation::PushBackPendingBackwardsOp((
-                        currency.clone(),
+                        origin_currency,
                         BackwardsOp::Cancel(cancel_send_funds),
                     ));
                     let funder_mutation = FunderMutation::FriendMutation((
@@ -236,20 +283,20 @@ pub fn cancel_request<B, R>(
     send_commands: &mut SendCommands,
     outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
     rng: &mut R,
-    currency: &Currency,
     pending_request: &RequestSendFundsOp,
 ) where
     B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
     R: CryptoRandom,
 {
-    let opt_origin_public_key =
-        find_request_origin(m_state.state(), &currency, &pending_request.request_id).cloned();
-    match opt_origin_public_key {
-        Some(origin_public_key) => {
+    let opt_origin = find_request_origin(m_state.state(), &pending_request.request_id).map(
+        |(origin_public_key, origin_currency)| (origin_public_key.clone(), origin_currency.clone()),
+    );
+    match opt_origin {
+        Some((origin_public_key, origin_currency)) => {
             let pending_local_transaction = create_pending_transaction(&pending_request);
             let cancel_send_funds = create_cancel_send_funds(pending_local_transaction.request_id);
             let friend_mutation = FriendMutation::PushBackPendingBackwardsOp((
-                currency.clone(),
+                origin_currency,
                 BackwardsOp::Cancel(cancel_send_funds),
             ));
             let funder_mutation =
@@ -325,7 +372,6 @@ pub fn cancel_pending_requests<B, R>(
             send_commands,
             outgoing_control,
             rng,
-            &currency,
             &pending_request,
         );
     }
@@ -344,7 +390,6 @@ pub fn cancel_pending_requests<B, R>(
             send_commands,
             outgoing_control,
             rng,
-            &currency,
             &pending_user_request,
         );
     }
@@ -403,7 +448,6 @@ pub fn cancel_nonuser_pending_requests<B, R>(
             send_commands,
             outgoing_control,
             rng,
-            &currency,
             &pending_request,
         );
     }
@@ -418,3 +462,149 @@ pub fn cancel_nonuser_pending_requests<B, R>(
         FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
     m_state.mutate(funder_mutation);
 }
+
+/// Queue a cancellation for every swap in progress with a friend.
+/// This includes swaps proposed by the remote side, and swaps we have queued but not yet sent.
+/// Queue a cancellation of a single pending swap with a friend.
+/// Does nothing if a cancellation of this swap is already queued.
+pub fn cancel_swap<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    friend_public_key: &PublicKey,
+    currency: &Currency,
+    swap_id: &Uid,
+) where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    let friend = m_state.state().friends.get(friend_public_key).unwrap();
+    let channel_consistent = match &friend.channel_status {
+        ChannelStatus::Inconsistent(_) => return,
+        ChannelStatus::Consistent(channel_consistent) => channel_consistent,
+    };
+
+    let is_cancel_queued =
+        channel_consistent
+            .pending_swap_ops
+            .iter()
+            .any(|(_currency, swap_op)| match swap_op {
+                SwapOp::Cancel(cancel_swap) => &cancel_swap.swap_id == swap_id,
+                SwapOp::Request(_) | SwapOp::Accept(_) => false,
+            });
+    if is_cancel_queued {
+        return;
+    }
+
+    let friend_mutation = FriendMutation::PushBackPendingSwapOp((
+        currency.clone(),
+        SwapOp::Cancel(CancelSwapOp {
+            swap_id: swap_id.clone(),
+        }),
+    ));
+    let funder_mutation =
+        FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
+    m_state.mutate(funder_mutation);
+    send_commands.set_try_send(friend_public_key);
+}
+
+pub fn cancel_pending_swaps<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    friend_public_key: &PublicKey,
+) where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    let friend = m_state.state().friends.get(friend_public_key).unwrap();
+    let channel_consistent = match &friend.channel_status {
+        ChannelStatus::Inconsistent(_) => return,
+        ChannelStatus::Consistent(channel_consistent) => channel_consistent,
+    };
+
+    // Swaps that already have a queued cancellation:
+    let canceled_swap_ids: HashSet<Uid> = channel_consistent
+        .pending_swap_ops
+        .iter()
+        .filter_map(|(_currency, swap_op)| match swap_op {
+            SwapOp::Cancel(cancel_swap) => Some(cancel_swap.swap_id.clone()),
+            SwapOp::Request(_) | SwapOp::Accept(_) => None,
+        })
+        .collect();
+
+    let mut cancel_swaps = Vec::new();
+    for (currency, mutual_credit) in channel_consistent.token_channel.get_mutual_credits() {
+        let pending_swaps = &mutual_credit.state().pending_swaps;
+        for swap_id in pending_swaps
+            .local
+            .keys()
+            .chain(pending_swaps.remote.keys())
+        {
+            cancel_swaps.push((currency.clone(), swap_id.clone()));
+        }
+    }
+    for (currency, swap_op) in &channel_consistent.pending_swap_ops {
+        if let SwapOp::Request(request_swap) = swap_op {
+            cancel_swaps.push((currency.clone(), request_swap.swap_id.clone()));
+        }
+    }
+    cancel_swaps.retain(|(_currency, swap_id)| !canceled_swap_ids.contains(swap_id));
+
+    if cancel_swaps.is_empty() {
+        return;
+    }
+
+    for (currency, swap_id) in cancel_swaps {
+        let friend_mutation = FriendMutation::PushBackPendingSwapOp((
+            currency,
+            SwapOp::Cancel(CancelSwapOp { swap_id }),
+        ));
+        let funder_mutation =
+            FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
+        m_state.mutate(funder_mutation);
+    }
+    send_commands.set_try_send(friend_public_key);
+}
+
+/// Freeze all activity with a friend, as the first step of cooperatively closing the channel.
+/// Requests are closed for all currencies, and queued requests and swaps are canceled.
+/// Transactions already inside the token channel are left to resolve.
+pub fn start_closing_friend<B, R>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
+    rng: &mut R,
+    friend_public_key: &PublicKey,
+) where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+    R: CryptoRandom,
+{
+    let friend_mutation =
+        FriendMutation::SetCloseStatus(FriendCloseStatus::Closing(FriendClosing::new()));
+    let funder_mutation =
+        FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
+    m_state.mutate(funder_mutation);
+
+    // Close requests for all currencies:
+    let friend = m_state.state().friends.get(friend_public_key).unwrap();
+    for (currency, currency_config) in friend.currency_configs.clone() {
+        if currency_config.is_open {
+            let mut new_currency_config = currency_config;
+            new_currency_config.is_open = false;
+            let friend_mutation =
+                FriendMutation::UpdateCurrencyConfig((currency, new_currency_config));
+            let funder_mutation =
+                FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
+            m_state.mutate(funder_mutation);
+        }
+    }
+
+    cancel_pending_requests(
+        m_state,
+        send_commands,
+        outgoing_control,
+        rng,
+        friend_public_key,
+        &CurrencyChoice::All,
+    );
+    cancel_pending_swaps(m_state, send_commands, friend_public_key);
+
+    send_commands.set_try_send(friend_public_key);
+}
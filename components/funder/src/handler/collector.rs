@@ -0,0 +1,92 @@
+use std::fmt::Debug;
+
+use signature::canonical::CanonicalSerialize;
+
+use proto::crypto::{InvoiceId, PlainLock};
+use proto::funder::messages::{push_payment_src_plain_lock, CollectSendFundsOp};
+
+use crate::handler::state_wrap::MutableFunderState;
+use crate::handler::types::SendCommands;
+use crate::handler::utils::{find_request_origin, invoice_total_paid};
+
+use crate::friend::{BackwardsOp, FriendMutation};
+use crate::state::FunderMutation;
+
+/// Collect all the incoming transactions of an open invoice, and remove the invoice.
+/// `src_plain_lock` must match the `src_hashed_lock` of the invoice.
+pub fn collect_invoice<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    invoice_id: &InvoiceId,
+    src_plain_lock: &PlainLock,
+) where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let open_invoice = m_state
+        .state()
+        .open_invoices
+        .get(invoice_id)
+        .unwrap()
+        .clone();
+
+    // Push collect messages for all pending requests
+    for request_id in &open_invoice.incoming_transactions {
+        let (friend_public_key, currency) = if let Some((friend_public_key, currency)) =
+            find_request_origin(m_state.state(), request_id)
+        {
+            (friend_public_key.clone(), currency.clone())
+        } else {
+            warn!("collect_invoice(): Failed to find request origin");
+            continue;
+        };
+
+        let collect_send_funds = CollectSendFundsOp {
+            request_id: request_id.clone(),
+            src_plain_lock: src_plain_lock.clone(),
+            dest_plain_lock: open_invoice.dest_plain_lock.clone(),
+        };
+
+        let friend_mutation = FriendMutation::PushBackPendingBackwardsOp((
+            currency,
+            BackwardsOp::Collect(collect_send_funds),
+        ));
+        let funder_mutation =
+            FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
+        m_state.mutate(funder_mutation);
+
+        // Signal the sender to attempt to send:
+        send_commands.set_try_send(&friend_public_key);
+    }
+
+    // Remove invoice:
+    let funder_mutation = FunderMutation::RemoveInvoice(invoice_id.clone());
+    m_state.mutate(funder_mutation);
+}
+
+/// Collect all push invoices that were fully paid.
+///
+/// Must be called only after all responses were signed and queued, as a Collect message must
+/// never be sent before the corresponding Response message.
+pub fn collect_push_invoices<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+) where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let complete_invoice_ids: Vec<_> = m_state
+        .state()
+        .open_invoices
+        .iter()
+        .filter(|(_invoice_id, open_invoice)| {
+            open_invoice.is_push
+                && invoice_total_paid(m_state.state(), open_invoice)
+                    == Some(open_invoice.total_dest_payment)
+        })
+        .map(|(invoice_id, _open_invoice)| invoice_id.clone())
+        .collect();
+
+    for invoice_id in complete_invoice_ids {
+        let src_plain_lock = push_payment_src_plain_lock(&invoice_id);
+        collect_invoice(m_state, send_commands, &invoice_id, &src_plain_lock);
+    }
+}
@@ -1,5 +1,7 @@
 use std::fmt::Debug;
 
+use common::safe_arithmetic::SafeSignedArithmetic;
+
 use signature::canonical::CanonicalSerialize;
 
 use crypto::hash_lock::HashLock;
@@ -7,29 +9,35 @@ use crypto::rand::{CryptoRandom, RandGen};
 
 use proto::crypto::{InvoiceId, PaymentId, PlainLock, PublicKey, Uid};
 
-use crate::friend::{BackwardsOp, ChannelStatus, CurrencyConfig, FriendMutation};
-use crate::state::{FunderMutation, NewTransactions, Payment, PaymentStage};
+use crate::friend::{ChannelStatus, CurrencyConfig, FriendCloseStatus, FriendMutation, SwapOp};
+use crate::state::{ClosedFriend, FunderMutation, NewTransactions, Payment, PaymentStage};
 
 use proto::app_server::messages::{NamedRelayAddress, RelayAddress};
 use proto::funder::messages::{
-    AckClosePayment, AddFriend, AddInvoice, ChannelerUpdateFriend, CollectSendFundsOp, Commit,
-    CreatePayment, CreateTransaction, FriendStatus, FunderControl, FunderOutgoingControl,
-    PaymentStatus, PaymentStatusSuccess, RemoveFriend, RemoveFriendCurrency, RequestResult,
-    RequestSendFundsOp, ResetFriendChannel, ResponseClosePayment, SetFriendCurrencyMaxDebt,
+    exchanged_dest_currency, push_payment_src_plain_lock, AbortCloseFriend, AcceptSwapOp,
+    AckClosePayment, AddFriend, AddInvoice, CancelFriendSwap, CancelSwapOp, ChannelerUpdateFriend,
+    CloseFriend, Commit, CreatePayment, CreateTransaction, Currency, CurrencyExchange,
+    EnablePushPayments, FriendStatus, FriendSwap, FriendsRoute, FunderControl,
+    FunderOutgoingControl, HoldInvoice, PaymentStatus, PaymentStatusSuccess, RemoveExchangeRate,
+    RemoveFriend, RemoveFriendCurrency, RequestResult, RequestSendFundsOp, RequestSwapOp,
+    ResetFriendChannel, ResponseClosePayment, SetExchangeRate, SetFriendCurrencyMaxDebt,
     SetFriendCurrencyRate, SetFriendCurrencyRequestsStatus, SetFriendName, SetFriendRelays,
     SetFriendStatus, TransactionResult,
 };
 use signature::verify::verify_commit;
 
-use crate::ephemeral::Ephemeral;
+use crate::ephemeral::{Ephemeral, EphemeralMutation};
 use crate::handler::canceler::{
-    cancel_local_pending_transactions, cancel_nonuser_pending_requests, cancel_pending_requests,
-    reply_with_cancel, CurrencyChoice,
+    cancel_invoice, cancel_local_pending_transactions, cancel_nonuser_pending_requests,
+    cancel_pending_requests, start_closing_friend, CurrencyChoice,
 };
+use crate::handler::collector::collect_invoice;
 use crate::handler::prepare::prepare_commit;
 use crate::handler::state_wrap::{MutableEphemeral, MutableFunderState};
 use crate::handler::types::SendCommands;
-use crate::handler::utils::{find_local_pending_transaction, find_request_origin, is_friend_ready};
+use crate::handler::utils::{
+    find_local_pending_transaction, is_friend_ready, is_remote_debt_allowed,
+};
 
 use crate::types::ChannelerConfig;
 
@@ -53,10 +61,22 @@ pub enum HandleControlError {
     AckMismatch,
     InvoiceAlreadyExists,
     InvoiceDoesNotExist,
+    InvoiceAlreadyHeld,
     InvalidCommit,
     FriendCurrencyDoesNotExist,
     CanNotRemoveActiveCurrency,
     CurrencyNotConfigured,
+    PushPaymentsNotEnabled,
+    InvalidExchanges,
+    ExchangeRateNotConfigured,
+    ChannelInconsistent,
+    InvalidSwap,
+    SwapAlreadyExists,
+    SwapDoesNotExist,
+    SwapTermsMismatch,
+    SwapExceedsMaxDebt,
+    FriendClosing,
+    FriendNotClosing,
 }
 
 fn control_set_friend_currency_max_debt<B>(
@@ -154,6 +174,7 @@ fn enable_friend<B>(
     let channeler_add_friend = ChannelerUpdateFriend {
         friend_public_key: friend_public_key.clone(),
         friend_relays: friend_relays.to_vec(),
+        friend_direct_addresses: friend.remote_direct_relay_addresses(),
         local_relays: friend.sent_local_relays.to_vec(),
     };
     let channeler_config = ChannelerConfig::UpdateFriend(channeler_add_friend);
@@ -276,6 +297,7 @@ where
 /// An inconsistency will occur if the friend is added again.
 fn control_remove_friend<B, R>(
     m_state: &mut MutableFunderState<B>,
+    m_ephemeral: &mut MutableEphemeral,
     send_commands: &mut SendCommands,
     outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
     outgoing_channeler_config: &mut Vec<ChannelerConfig<RelayAddress<B>>>,
@@ -287,12 +309,31 @@ where
     R: CryptoRandom,
 {
     // Make sure that friend exists:
-    let _friend = m_state
+    let friend = m_state
         .state()
         .friends
         .get(&remove_friend.friend_public_key)
         .ok_or(HandleControlError::FriendDoesNotExist)?;
 
+    match &friend.close_status {
+        FriendCloseStatus::Open => {}
+        // Removing the friend in the middle of closing would lose the final settlement.
+        // The close should be completed or aborted first:
+        FriendCloseStatus::Closing(_) => return Err(HandleControlError::FriendClosing),
+        FriendCloseStatus::Closed(settlement) => {
+            // Keep the signed settlement after the friend is removed:
+            let closed_friend = ClosedFriend {
+                name: friend.name.clone(),
+                settlement: settlement.clone(),
+            };
+            let funder_mutation = FunderMutation::AddClosedFriend((
+                remove_friend.friend_public_key.clone(),
+                closed_friend,
+            ));
+            m_state.mutate(funder_mutation);
+        }
+    }
+
     disable_friend(
         m_state,
         send_commands,
@@ -313,6 +354,10 @@ where
     let funder_mutation = FunderMutation::RemoveFriend(remove_friend.friend_public_key.clone());
     m_state.mutate(funder_mutation);
 
+    let ephemeral_mutation =
+        EphemeralMutation::ClearRelaysHealth(remove_friend.friend_public_key.clone());
+    m_ephemeral.mutate(ephemeral_mutation);
+
     Ok(())
 }
 
@@ -461,6 +506,7 @@ where
     }
 
     let local_relays = friend.sent_local_relays.to_vec();
+    let friend_direct_addresses = friend.remote_direct_relay_addresses();
 
     let friend_mutation = FriendMutation::SetRemoteRelays(set_friend_relays.relays.clone());
     let funder_mutation = FunderMutation::FriendMutation((
@@ -474,6 +520,7 @@ where
         let update_friend = ChannelerUpdateFriend {
             friend_public_key: set_friend_relays.friend_public_key.clone(),
             friend_relays: set_friend_relays.relays.clone(),
+            friend_direct_addresses,
             local_relays,
         };
         let channeler_config = ChannelerConfig::UpdateFriend(update_friend);
@@ -602,14 +649,13 @@ where
     Ok(())
 }
 
-fn control_create_payment<B, R>(
+fn control_create_payment<B>(
     m_state: &mut MutableFunderState<B>,
-    rng: &mut R,
     create_payment: CreatePayment,
+    src_plain_lock: PlainLock,
 ) -> Result<(), HandleControlError>
 where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
-    R: CryptoRandom,
 {
     // Make sure that a payment with the same payment_id doesn't exist:
     if m_state
@@ -633,7 +679,7 @@ where
     });
 
     let payment = Payment {
-        src_plain_lock: PlainLock::rand_gen(rng),
+        src_plain_lock,
         stage,
     };
 
@@ -644,6 +690,40 @@ where
     Ok(())
 }
 
+/// Check that the currency exchanges of a transaction are consistent with its route:
+/// Every exchange is performed by a mediator along the route (In route order), and the
+/// currencies chain up to the currency of the payment.
+fn is_valid_exchanges(
+    route: &FriendsRoute,
+    exchanges: &[CurrencyExchange],
+    dest_currency: &Currency,
+) -> bool {
+    // Exchanging nodes must be mediators (Not the source or the destination):
+    let mediators = &route.public_keys[1..route.public_keys.len().saturating_sub(1)];
+    let mut next_index = 0;
+    for currency_exchange in exchanges {
+        match mediators
+            .iter()
+            .skip(next_index)
+            .position(|public_key| public_key == &currency_exchange.public_key)
+        {
+            Some(index) => next_index += index + 1,
+            None => return false,
+        }
+    }
+
+    for pair in exchanges.windows(2) {
+        if pair[0].dest_currency != pair[1].src_currency {
+            return false;
+        }
+    }
+
+    match exchanges.first() {
+        Some(first) => exchanged_dest_currency(&first.src_currency, exchanges) == dest_currency,
+        None => true,
+    }
+}
+
 fn control_create_transaction_inner<B>(
     m_state: &mut MutableFunderState<B>,
     ephemeral: &Ephemeral,
@@ -687,6 +767,14 @@ where
     if !route.is_valid() {
         return Err(HandleControlError::InvalidRoute);
     }
+    if !is_valid_exchanges(
+        route,
+        &create_transaction.exchanges,
+        &new_transactions.currency,
+    ) {
+        return Err(HandleControlError::InvalidExchanges);
+    }
+
     let friend_public_key = route.public_keys[1].clone();
 
     let friend = match m_state.state().friends.get(&friend_public_key) {
@@ -694,7 +782,13 @@ where
         None => Err(HandleControlError::FriendDoesNotExist),
     }?;
 
-    let currency = new_transactions.currency.clone();
+    // The currency of the first hop. Possibly different from the payment's currency if the
+    // route goes through currency exchanges:
+    let currency = create_transaction
+        .exchanges
+        .first()
+        .map(|currency_exchange| currency_exchange.src_currency.clone())
+        .unwrap_or_else(|| new_transactions.currency.clone());
 
     if !is_friend_ready(m_state.state(), ephemeral, &friend_public_key, &currency) {
         return Err(HandleControlError::FriendNotReady);
@@ -774,6 +868,7 @@ where
         total_dest_payment: new_transactions.total_dest_payment,
         invoice_id: new_transactions.invoice_id,
         left_fees: create_transaction.fees,
+        exchanges: create_transaction.exchanges,
     };
 
     let friend_mutation =
@@ -822,11 +917,17 @@ where
         .open_transactions
         .get(&create_transaction.request_id)
     {
+        let currency = create_transaction
+            .exchanges
+            .first()
+            .map(|currency_exchange| &currency_exchange.src_currency)
+            .unwrap_or(&new_transactions.currency);
+
         if let (Some(response_send_funds), Some(pending_transaction)) = (
             &open_transaction.opt_response,
             find_local_pending_transaction(
                 m_state.state(),
-                &new_transactions.currency,
+                currency,
                 &create_transaction.request_id,
             ),
         ) {
@@ -1058,46 +1159,58 @@ fn control_cancel_invoice<B>(
 where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
 {
+    if !m_state.state().open_invoices.contains_key(&invoice_id) {
+        return Err(HandleControlError::InvoiceDoesNotExist);
+    }
+
+    cancel_invoice(m_state, send_commands, &invoice_id);
+    Ok(())
+}
+
+fn control_commit_invoice<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    commit: &Commit,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    // Find matching open invoice:
     let open_invoice = m_state
         .state()
         .open_invoices
-        .get(&invoice_id)
+        .get(&commit.invoice_id)
         .ok_or(HandleControlError::InvoiceDoesNotExist)?
         .clone();
 
-    // Cancel all pending transactions related to this invoice
-    for request_id in &open_invoice.incoming_transactions {
-        // Explaining the unwrap() below:
-        // We expect that the origin of this request must be from an existing friend.
-        // We can not be the originator of this request.
-        let friend_public_key =
-            find_request_origin(m_state.state(), &open_invoice.currency, &request_id)
-                .unwrap()
-                .clone();
-        reply_with_cancel(
-            m_state,
-            send_commands,
-            &friend_public_key,
-            &open_invoice.currency,
-            &request_id,
-        );
+    if !verify_commit(commit, &m_state.state().local_public_key) {
+        return Err(HandleControlError::InvalidCommit);
     }
 
-    // Remove invoice:
-    let funder_mutation = FunderMutation::RemoveInvoice(invoice_id);
-    m_state.mutate(funder_mutation);
+    // The src_plain_lock must match:
+    if Some(commit.src_plain_lock.hash_lock()) != open_invoice.opt_src_hashed_lock {
+        return Err(HandleControlError::InvalidCommit);
+    }
+
+    collect_invoice(
+        m_state,
+        send_commands,
+        &commit.invoice_id,
+        &commit.src_plain_lock,
+    );
 
     Ok(())
 }
 
-fn control_commit_invoice<B>(
+fn control_hold_invoice<B>(
     m_state: &mut MutableFunderState<B>,
-    send_commands: &mut SendCommands,
-    commit: &Commit,
+    hold_invoice: HoldInvoice,
 ) -> Result<(), HandleControlError>
 where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
 {
+    let commit = &hold_invoice.commit;
+
     // Find matching open invoice:
     let open_invoice = m_state
         .state()
@@ -1106,6 +1219,12 @@ where
         .ok_or(HandleControlError::InvoiceDoesNotExist)?
         .clone();
 
+    // A held invoice can not be held again, otherwise the maximum hold duration could be
+    // extended indefinitely:
+    if open_invoice.opt_hold_ticks.is_some() {
+        return Err(HandleControlError::InvoiceAlreadyHeld);
+    }
+
     if !verify_commit(commit, &m_state.state().local_public_key) {
         return Err(HandleControlError::InvalidCommit);
     }
@@ -1115,39 +1234,386 @@ where
         return Err(HandleControlError::InvalidCommit);
     }
 
-    // Push collect messages for all pending requests
-    for request_id in &open_invoice.incoming_transactions {
-        let friend_public_key = if let Some(friend_public_key) =
-            find_request_origin(m_state.state(), &open_invoice.currency, request_id)
+    // Funds remain locked along the route until the invoice is committed or canceled:
+    let funder_mutation =
+        FunderMutation::SetInvoiceHold((commit.invoice_id.clone(), hold_invoice.max_hold_ticks));
+    m_state.mutate(funder_mutation);
+
+    Ok(())
+}
+
+fn control_enable_push_payments<B>(
+    m_state: &mut MutableFunderState<B>,
+    enable_push_payments: EnablePushPayments,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let funder_mutation = FunderMutation::EnablePushPayments((
+        enable_push_payments.currency,
+        enable_push_payments.max_total_dest_payment,
+    ));
+    m_state.mutate(funder_mutation);
+    Ok(())
+}
+
+fn control_set_exchange_rate<B>(
+    m_state: &mut MutableFunderState<B>,
+    set_exchange_rate: SetExchangeRate,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let funder_mutation = FunderMutation::SetExchangeRate((
+        set_exchange_rate.src_currency,
+        set_exchange_rate.dest_currency,
+        set_exchange_rate.rate,
+    ));
+    m_state.mutate(funder_mutation);
+    Ok(())
+}
+
+fn control_remove_exchange_rate<B>(
+    m_state: &mut MutableFunderState<B>,
+    remove_exchange_rate: RemoveExchangeRate,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let is_configured = m_state
+        .state()
+        .exchange_rates
+        .get(&remove_exchange_rate.src_currency)
+        .map(|dest_rates| dest_rates.contains_key(&remove_exchange_rate.dest_currency))
+        .unwrap_or(false);
+
+    if !is_configured {
+        return Err(HandleControlError::ExchangeRateNotConfigured);
+    }
+
+    // Note that requests that are already in progress are not affected.
+    let funder_mutation = FunderMutation::RemoveExchangeRate((
+        remove_exchange_rate.src_currency,
+        remove_exchange_rate.dest_currency,
+    ));
+    m_state.mutate(funder_mutation);
+    Ok(())
+}
+
+fn control_disable_push_payments<B>(
+    m_state: &mut MutableFunderState<B>,
+    currency: Currency,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    if !m_state.state().push_payments.contains_key(&currency) {
+        return Err(HandleControlError::PushPaymentsNotEnabled);
+    }
+
+    let funder_mutation = FunderMutation::DisablePushPayments(currency);
+    m_state.mutate(funder_mutation);
+    Ok(())
+}
+
+fn control_propose_swap<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    friend_swap: FriendSwap,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let friend = m_state
+        .state()
+        .friends
+        .get(&friend_swap.friend_public_key)
+        .ok_or(HandleControlError::FriendDoesNotExist)?;
+
+    // No new swaps are allowed while closing the channel:
+    if friend.is_closing() {
+        return Err(HandleControlError::FriendClosing);
+    }
+
+    let token_channel = match &friend.channel_status {
+        ChannelStatus::Consistent(channel_consistent) => &channel_consistent.token_channel,
+        ChannelStatus::Inconsistent(_) => return Err(HandleControlError::ChannelInconsistent),
+    };
+
+    if friend_swap.give_amount == 0 || friend_swap.take_amount == 0 {
+        return Err(HandleControlError::InvalidSwap);
+    }
+
+    // Both currencies must be active with this friend:
+    let mutual_credits = token_channel.get_mutual_credits();
+    let (give_mutual_credit, take_mutual_credit) = match (
+        mutual_credits.get(&friend_swap.give_currency),
+        mutual_credits.get(&friend_swap.take_currency),
+    ) {
+        (Some(give_mutual_credit), Some(take_mutual_credit))
+            if friend_swap.give_currency != friend_swap.take_currency =>
         {
-            friend_public_key.clone()
-        } else {
-            warn!("control_commit_invoice(): Failed to find request origin");
-            continue;
-        };
+            (give_mutual_credit, take_mutual_credit)
+        }
+        _ => return Err(HandleControlError::InvalidSwap),
+    };
 
-        let collect_send_funds = CollectSendFundsOp {
-            request_id: request_id.clone(),
-            src_plain_lock: commit.src_plain_lock.clone(),
-            dest_plain_lock: open_invoice.dest_plain_lock.clone(),
-        };
+    // Make sure that our balance in the currency we give does not overflow:
+    if give_mutual_credit
+        .state()
+        .balance
+        .balance
+        .checked_sub_unsigned(friend_swap.give_amount)
+        .is_none()
+    {
+        return Err(HandleControlError::InvalidSwap);
+    }
 
-        let friend_mutation = FriendMutation::PushBackPendingBackwardsOp((
-            open_invoice.currency.clone(),
-            BackwardsOp::Collect(collect_send_funds),
-        ));
-        let funder_mutation =
-            FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
-        m_state.mutate(funder_mutation);
+    // Make sure that the friend's debt does not exceed the configured max debt after the swap:
+    let remote_max_debt = friend
+        .currency_configs
+        .get(&friend_swap.take_currency)
+        .map(|currency_config| currency_config.remote_max_debt)
+        .unwrap_or(0);
+    if !is_remote_debt_allowed(
+        &take_mutual_credit.state().balance,
+        friend_swap.take_amount,
+        remote_max_debt,
+    ) {
+        return Err(HandleControlError::SwapExceedsMaxDebt);
+    }
+
+    if mutual_credits.values().any(|mutual_credit| {
+        mutual_credit
+            .state()
+            .pending_swaps
+            .contains_key(&friend_swap.swap_id)
+    }) {
+        return Err(HandleControlError::SwapAlreadyExists);
+    }
+
+    // The proposal is sent through the mutual credit of the currency we give:
+    let request_swap = RequestSwapOp {
+        swap_id: friend_swap.swap_id,
+        src_amount: friend_swap.give_amount,
+        dest_currency: friend_swap.take_currency,
+        dest_amount: friend_swap.take_amount,
+    };
+
+    let friend_mutation = FriendMutation::PushBackPendingSwapOp((
+        friend_swap.give_currency,
+        SwapOp::Request(request_swap),
+    ));
+    let funder_mutation =
+        FunderMutation::FriendMutation((friend_swap.friend_public_key.clone(), friend_mutation));
+    m_state.mutate(funder_mutation);
+
+    send_commands.set_try_send(&friend_swap.friend_public_key);
+    Ok(())
+}
+
+fn control_accept_swap<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    friend_swap: FriendSwap,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let friend = m_state
+        .state()
+        .friends
+        .get(&friend_swap.friend_public_key)
+        .ok_or(HandleControlError::FriendDoesNotExist)?;
+
+    // No new swaps are allowed while closing the channel:
+    if friend.is_closing() {
+        return Err(HandleControlError::FriendClosing);
+    }
+
+    let token_channel = match &friend.channel_status {
+        ChannelStatus::Consistent(channel_consistent) => &channel_consistent.token_channel,
+        ChannelStatus::Inconsistent(_) => return Err(HandleControlError::ChannelInconsistent),
+    };
 
-        // Signal the sender to attempt to send:
-        send_commands.set_try_send(&friend_public_key);
+    // A swap proposed by the friend is kept at the mutual credit of the currency we take:
+    let mutual_credits = token_channel.get_mutual_credits();
+    let mutual_credit = mutual_credits
+        .get(&friend_swap.take_currency)
+        .ok_or(HandleControlError::SwapDoesNotExist)?;
+    let request_swap = mutual_credit
+        .state()
+        .pending_swaps
+        .remote
+        .get(&friend_swap.swap_id)
+        .ok_or(HandleControlError::SwapDoesNotExist)?;
+
+    // We only accept the exact terms the user agreed to:
+    if request_swap.src_amount != friend_swap.take_amount
+        || request_swap.dest_currency != friend_swap.give_currency
+        || request_swap.dest_amount != friend_swap.give_amount
+    {
+        return Err(HandleControlError::SwapTermsMismatch);
     }
 
-    // Remove invoice:
-    let funder_mutation = FunderMutation::RemoveInvoice(commit.invoice_id.clone());
+    // Make sure that our balance in the currency we give does not overflow:
+    let give_mutual_credit = mutual_credits
+        .get(&friend_swap.give_currency)
+        .ok_or(HandleControlError::InvalidSwap)?;
+    if give_mutual_credit
+        .state()
+        .balance
+        .balance
+        .checked_sub_unsigned(friend_swap.give_amount)
+        .is_none()
+    {
+        return Err(HandleControlError::InvalidSwap);
+    }
+
+    // Make sure that the friend's debt does not exceed the configured max debt after the swap:
+    let remote_max_debt = friend
+        .currency_configs
+        .get(&friend_swap.take_currency)
+        .map(|currency_config| currency_config.remote_max_debt)
+        .unwrap_or(0);
+    if !is_remote_debt_allowed(
+        &mutual_credit.state().balance,
+        friend_swap.take_amount,
+        remote_max_debt,
+    ) {
+        return Err(HandleControlError::SwapExceedsMaxDebt);
+    }
+
+    let accept_swap = AcceptSwapOp {
+        swap_id: friend_swap.swap_id,
+    };
+
+    let friend_mutation = FriendMutation::PushBackPendingSwapOp((
+        friend_swap.take_currency,
+        SwapOp::Accept(accept_swap),
+    ));
+    let funder_mutation =
+        FunderMutation::FriendMutation((friend_swap.friend_public_key.clone(), friend_mutation));
     m_state.mutate(funder_mutation);
 
+    send_commands.set_try_send(&friend_swap.friend_public_key);
+    Ok(())
+}
+
+fn control_cancel_swap<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    cancel_friend_swap: CancelFriendSwap,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let friend = m_state
+        .state()
+        .friends
+        .get(&cancel_friend_swap.friend_public_key)
+        .ok_or(HandleControlError::FriendDoesNotExist)?;
+
+    let token_channel = match &friend.channel_status {
+        ChannelStatus::Consistent(channel_consistent) => &channel_consistent.token_channel,
+        ChannelStatus::Inconsistent(_) => return Err(HandleControlError::ChannelInconsistent),
+    };
+
+    // Find the currency through which the swap was proposed.
+    // This could be a swap we have proposed, or a swap proposed by the friend:
+    let currency = token_channel
+        .get_mutual_credits()
+        .iter()
+        .find(|(_currency, mutual_credit)| {
+            mutual_credit
+                .state()
+                .pending_swaps
+                .contains_key(&cancel_friend_swap.swap_id)
+        })
+        .map(|(currency, _mutual_credit)| currency.clone())
+        .ok_or(HandleControlError::SwapDoesNotExist)?;
+
+    let cancel_swap = CancelSwapOp {
+        swap_id: cancel_friend_swap.swap_id,
+    };
+
+    let friend_mutation =
+        FriendMutation::PushBackPendingSwapOp((currency, SwapOp::Cancel(cancel_swap)));
+    let funder_mutation = FunderMutation::FriendMutation((
+        cancel_friend_swap.friend_public_key.clone(),
+        friend_mutation,
+    ));
+    m_state.mutate(funder_mutation);
+
+    send_commands.set_try_send(&cancel_friend_swap.friend_public_key);
+    Ok(())
+}
+
+fn control_close_friend<B, R>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
+    rng: &mut R,
+    close_friend: CloseFriend,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+    R: CryptoRandom,
+{
+    let friend = m_state
+        .state()
+        .friends
+        .get(&close_friend.friend_public_key)
+        .ok_or(HandleControlError::FriendDoesNotExist)?;
+
+    // Closing was already initiated (Possibly by the friend):
+    if friend.is_closing() {
+        return Ok(());
+    }
+
+    start_closing_friend(
+        m_state,
+        send_commands,
+        outgoing_control,
+        rng,
+        &close_friend.friend_public_key,
+    );
+    Ok(())
+}
+
+/// Abort an ongoing cooperative close, returning the friend to normal operation.
+/// Currencies closed for requests when the close started remain closed, and should be reopened
+/// explicitly. Note that if the remote friend is still closing, it might initiate closing again.
+fn control_abort_close_friend<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    abort_close_friend: AbortCloseFriend,
+) -> Result<(), HandleControlError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let friend = m_state
+        .state()
+        .friends
+        .get(&abort_close_friend.friend_public_key)
+        .ok_or(HandleControlError::FriendDoesNotExist)?;
+
+    // A closed friend already has a signed final settlement, and can not be reopened:
+    match &friend.close_status {
+        FriendCloseStatus::Closing(_) => {}
+        FriendCloseStatus::Open | FriendCloseStatus::Closed(_) => {
+            return Err(HandleControlError::FriendNotClosing)
+        }
+    }
+
+    let friend_mutation = FriendMutation::SetCloseStatus(FriendCloseStatus::Open);
+    let funder_mutation = FunderMutation::FriendMutation((
+        abort_close_friend.friend_public_key.clone(),
+        friend_mutation,
+    ));
+    m_state.mutate(funder_mutation);
+
+    send_commands.set_try_send(&abort_close_friend.friend_public_key);
     Ok(())
 }
 
@@ -1204,6 +1670,7 @@ where
 
         FunderControl::RemoveFriend(remove_friend) => control_remove_friend(
             m_state,
+            m_ephemeral,
             send_commands,
             outgoing_control,
             outgoing_channeler_config,
@@ -1247,7 +1714,9 @@ where
 
         // Buyer API:
         FunderControl::CreatePayment(create_payment) => {
-            control_create_payment(m_state, rng, create_payment)
+            // Randomly generate a lock. We only reveal this lock when sending the Commit message.
+            let src_plain_lock = PlainLock::rand_gen(rng);
+            control_create_payment(m_state, create_payment, src_plain_lock)
         }
         FunderControl::CreateTransaction(create_transaction) => control_create_transaction(
             m_state,
@@ -1272,5 +1741,46 @@ where
         FunderControl::CommitInvoice(commit) => {
             control_commit_invoice(m_state, send_commands, &commit)
         }
+        FunderControl::HoldInvoice(hold_invoice) => control_hold_invoice(m_state, hold_invoice),
+
+        // Push payments API:
+        FunderControl::CreatePushPayment(create_payment) => {
+            // The buyer commits in advance, allowing the seller to collect without a Commit:
+            let src_plain_lock = push_payment_src_plain_lock(&create_payment.invoice_id);
+            control_create_payment(m_state, create_payment, src_plain_lock)
+        }
+        FunderControl::EnablePushPayments(enable_push_payments) => {
+            control_enable_push_payments(m_state, enable_push_payments)
+        }
+        FunderControl::DisablePushPayments(currency) => {
+            control_disable_push_payments(m_state, currency)
+        }
+
+        // Currency exchange:
+        FunderControl::SetExchangeRate(set_exchange_rate) => {
+            control_set_exchange_rate(m_state, set_exchange_rate)
+        }
+        FunderControl::RemoveExchangeRate(remove_exchange_rate) => {
+            control_remove_exchange_rate(m_state, remove_exchange_rate)
+        }
+
+        // Currency swaps with friends:
+        FunderControl::ProposeSwap(friend_swap) => {
+            control_propose_swap(m_state, send_commands, friend_swap)
+        }
+        FunderControl::AcceptSwap(friend_swap) => {
+            control_accept_swap(m_state, send_commands, friend_swap)
+        }
+        FunderControl::CancelSwap(cancel_friend_swap) => {
+            control_cancel_swap(m_state, send_commands, cancel_friend_swap)
+        }
+
+        // Cooperative closing of a friend channel:
+        FunderControl::CloseFriend(close_friend) => {
+            control_close_friend(m_state, send_commands, outgoing_control, rng, close_friend)
+        }
+        FunderControl::AbortCloseFriend(abort_close_friend) => {
+            control_abort_close_friend(m_state, send_commands, abort_close_friend)
+        }
     }
 }
@@ -3,20 +3,23 @@ use std::fmt::Debug;
 
 use signature::canonical::CanonicalSerialize;
 
+use crypto::hash_lock::HashLock;
 use crypto::rand::{CryptoRandom, RandGen};
 
-use proto::crypto::{PublicKey, Signature, Uid};
+use proto::crypto::{PlainLock, PublicKey, Signature, Uid};
 
 use proto::app_server::messages::RelayAddress;
+use proto::consts::{MAX_PENDING_REMOTE_SWAPS, PUSH_INVOICE_TIMEOUT_TICKS};
 use proto::funder::messages::{
-    BalanceInfo, CancelSendFundsOp, ChannelerUpdateFriend, CollectSendFundsOp, CountersInfo,
-    Currency, CurrencyBalance, CurrencyBalanceInfo, FriendMessage, FunderOutgoingControl, McInfo,
-    MoveTokenRequest, PaymentStatus, PaymentStatusSuccess, PendingTransaction, RequestResult,
-    RequestSendFundsOp, ResetTerms, ResponseClosePayment, ResponseSendFundsOp, TokenInfo,
-    TransactionResult,
+    exchanged_dest_currency, exchanged_dest_payment, push_payment_src_plain_lock, BalanceInfo,
+    CancelSendFundsOp, ChannelerUpdateFriend, CollectSendFundsOp, CountersInfo, Currency,
+    CurrencyBalance, CurrencyBalanceInfo, ExchangeRate, FriendMessage, FriendSettlement,
+    FunderOutgoingControl, McInfo, MoveTokenRequest, PaymentStatus, PaymentStatusSuccess,
+    PendingTransaction, Rate, RequestResult, RequestSendFundsOp, RequestSwapOp, ResetTerms,
+    ResponseClosePayment, ResponseSendFundsOp, TokenInfo, TransactionResult,
 };
 use signature::signature_buff::hash_token_info;
-use signature::verify::verify_move_token;
+use signature::verify::{verify_move_token, verify_settlement_signature};
 
 use crate::mutual_credit::incoming::{
     IncomingCancelSendFundsOp, IncomingCollectSendFundsOp, IncomingMessage,
@@ -27,22 +30,22 @@ use crate::token_channel::{MoveTokenReceived, ReceiveMoveTokenOutput, TokenChann
 use crate::types::{create_pending_transaction, ChannelerConfig};
 
 use crate::friend::{
-    BackwardsOp, ChannelInconsistent, ChannelStatus, CurrencyConfig, FriendMutation,
-    SentLocalRelays,
+    BackwardsOp, ChannelInconsistent, ChannelStatus, CurrencyConfig, FriendCloseStatus,
+    FriendClosing, FriendMutation, SentLocalRelays, SettlementSignature,
 };
-use crate::state::{FunderMutation, FunderState, Payment, PaymentStage};
+use crate::state::{FunderMutation, FunderState, OpenInvoice, Payment, PaymentStage};
 
 use crate::ephemeral::Ephemeral;
 
 use crate::handler::canceler::{
-    cancel_local_pending_transactions, cancel_pending_requests, remove_transaction,
-    reply_with_cancel, CurrencyChoice,
+    cancel_local_pending_transactions, cancel_pending_requests, cancel_pending_swaps, cancel_swap,
+    remove_transaction, reply_with_cancel, start_closing_friend, CurrencyChoice,
 };
 use crate::handler::prepare::{prepare_commit, prepare_receipt};
 use crate::handler::state_wrap::{MutableEphemeral, MutableFunderState};
 use crate::handler::types::SendCommands;
 use crate::handler::utils::{
-    find_remote_pending_transaction, find_request_origin, is_friend_ready,
+    find_remote_pending_transaction, find_request_origin, is_friend_ready, is_remote_debt_allowed,
 };
 
 #[derive(Debug)]
@@ -185,6 +188,24 @@ pub fn try_reset_channel<B>(
         }
     }
 
+    // Update our knowledge about remote direct addresses if required:
+    if let Some(remote_direct_addresses) = &move_token.opt_direct_addresses {
+        if remote_direct_addresses
+            != &m_state
+                .state()
+                .friends
+                .get(&friend_public_key)
+                .unwrap()
+                .remote_direct_addresses
+        {
+            let friend_mutation =
+                FriendMutation::SetRemoteDirectAddresses(remote_direct_addresses.clone());
+            let funder_mutation =
+                FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
+            m_state.mutate(funder_mutation);
+        }
+    }
+
     // We should send our relays to the remote side:
     send_commands.set_resend_relays(friend_public_key);
 
@@ -221,18 +242,14 @@ enum CheckRequest {
 }
 
 /// Check if we can add a request into a local OpenInvoice
-fn check_request<B>(state: &FunderState<B>, request_send_funds: &RequestSendFundsOp) -> CheckRequest
+fn check_request<B>(
+    state: &FunderState<B>,
+    open_invoice: &OpenInvoice,
+    request_send_funds: &RequestSendFundsOp,
+) -> CheckRequest
 where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
 {
-    // First make sure that we have a matching open invoice for this transaction:
-    let open_invoice =
-        if let Some(open_invoice) = state.open_invoices.get(&request_send_funds.invoice_id) {
-            open_invoice
-        } else {
-            return CheckRequest::Failure;
-        };
-
     if let Some(src_hashed_lock) = &open_invoice.opt_src_hashed_lock {
         if src_hashed_lock != &request_send_funds.src_hashed_lock {
             return CheckRequest::Failure;
@@ -249,8 +266,14 @@ where
     // Calculate the amounts of funds already paid for this OpenInvoice:
     let mut total_paid = 0u128;
     for request_id in &open_invoice.incoming_transactions {
-        let pending_transaction =
-            find_remote_pending_transaction(state, &open_invoice.currency, request_id).unwrap();
+        // An incoming transaction might be lost if the token channel was reset:
+        let pending_transaction = if let Some(pending_transaction) =
+            find_remote_pending_transaction(state, &open_invoice.currency, request_id)
+        {
+            pending_transaction
+        } else {
+            return CheckRequest::Failure;
+        };
         assert_eq!(
             pending_transaction.total_dest_payment,
             open_invoice.total_dest_payment
@@ -292,20 +315,101 @@ where
     */
 }
 
-fn handle_request_send_funds<B>(
+/// Check if an incoming request (for which we are the destination) is a push payment that we
+/// should accept, according to our push payments configuration.
+fn is_acceptable_push_payment<B>(
+    state: &FunderState<B>,
+    currency: &Currency,
+    request_send_funds: &RequestSendFundsOp,
+) -> bool
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    // An existing invoice always takes precedence:
+    if state
+        .open_invoices
+        .contains_key(&request_send_funds.invoice_id)
+    {
+        return false;
+    }
+
+    match state.push_payments.get(currency) {
+        Some(max_total_dest_payment)
+            if request_send_funds.total_dest_payment <= *max_total_dest_payment => {}
+        _ => return false,
+    };
+
+    // The buyer must have committed in advance, otherwise we will not be able to collect:
+    push_payment_src_plain_lock(&request_send_funds.invoice_id).hash_lock()
+        == request_send_funds.src_hashed_lock
+}
+
+fn handle_request_send_funds<B, R>(
     m_state: &mut MutableFunderState<B>,
     ephemeral: &Ephemeral,
     send_commands: &mut SendCommands,
+    rng: &mut R,
     remote_public_key: &PublicKey,
     currency: &Currency,
     mut request_send_funds: RequestSendFundsOp,
 ) where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+    R: CryptoRandom,
 {
+    // No new requests are accepted from a friend we are closing the channel with:
+    let friend = m_state.state().friends.get(remote_public_key).unwrap();
+    if friend.is_closing() {
+        reply_with_cancel(
+            m_state,
+            send_commands,
+            remote_public_key,
+            currency,
+            &request_send_funds.request_id,
+        );
+        return;
+    }
+
     if request_send_funds.route.is_empty() {
         // We are the destination of this request.
 
-        let is_complete = match check_request(m_state.state(), &request_send_funds) {
+        // All exchanges should have been performed before the request reached us:
+        if !request_send_funds.exchanges.is_empty() {
+            reply_with_cancel(
+                m_state,
+                send_commands,
+                remote_public_key,
+                currency,
+                &request_send_funds.request_id,
+            );
+            return;
+        }
+
+        // A push payment gets a new invoice automatically.
+        // The invoice is only added if the request is valid for it.
+        let opt_push_invoice =
+            if is_acceptable_push_payment(m_state.state(), currency, &request_send_funds) {
+                Some(OpenInvoice::new(
+                    currency.clone(),
+                    request_send_funds.total_dest_payment,
+                    PlainLock::rand_gen(rng),
+                ))
+            } else {
+                None
+            };
+
+        // Make sure that we have a matching open invoice for this transaction:
+        let opt_open_invoice = opt_push_invoice.as_ref().or_else(|| {
+            m_state
+                .state()
+                .open_invoices
+                .get(&request_send_funds.invoice_id)
+        });
+        let check_result = match opt_open_invoice {
+            Some(open_invoice) => check_request(m_state.state(), open_invoice, &request_send_funds),
+            None => CheckRequest::Failure,
+        };
+
+        let is_complete = match check_result {
             CheckRequest::Failure => {
                 reply_with_cancel(
                     m_state,
@@ -320,6 +424,24 @@ fn handle_request_send_funds<B>(
             CheckRequest::Complete => true,
         };
 
+        // The push invoice will be collected once it is fully paid.
+        // If it is not fully paid in time, it is canceled.
+        if let Some(push_invoice) = opt_push_invoice {
+            let funder_mutation = FunderMutation::AddPushInvoice((
+                request_send_funds.invoice_id.clone(),
+                currency.clone(),
+                push_invoice.total_dest_payment,
+                push_invoice.dest_plain_lock,
+            ));
+            m_state.mutate(funder_mutation);
+
+            let funder_mutation = FunderMutation::SetInvoiceHold((
+                request_send_funds.invoice_id.clone(),
+                PUSH_INVOICE_TIMEOUT_TICKS,
+            ));
+            m_state.mutate(funder_mutation);
+        }
+
         // Set the src_hashed_lock for the OpenInvoice if required.
         // (Happens only when the first transaction for this invoice is received):
         if m_state
@@ -378,6 +500,53 @@ fn handle_request_send_funds<B>(
         return;
     }
 
+    // Check if we were asked to exchange this request into another currency.
+    // If so, the exchange must match the exchange rate we have configured:
+    let opt_exchange_rate = match request_send_funds.exchanges.first() {
+        Some(currency_exchange)
+            if currency_exchange.public_key == m_state.state().local_public_key =>
+        {
+            match m_state
+                .state()
+                .exchange_rates
+                .get(currency)
+                .and_then(|dest_rates| dest_rates.get(&currency_exchange.dest_currency))
+            {
+                Some(exchange_rate)
+                    if currency_exchange.src_currency == *currency
+                        && *exchange_rate == currency_exchange.rate =>
+                {
+                    Some(Some((
+                        currency_exchange.dest_currency.clone(),
+                        exchange_rate.clone(),
+                    )))
+                }
+                _ => None,
+            }
+        }
+        _ => Some(None),
+    };
+
+    let opt_exchange_rate = match opt_exchange_rate {
+        Some(opt_exchange_rate) => opt_exchange_rate,
+        None => {
+            reply_with_cancel(
+                m_state,
+                send_commands,
+                remote_public_key,
+                currency,
+                &request_send_funds.request_id,
+            );
+            return;
+        }
+    };
+
+    // The currency used to forward the request:
+    let out_currency = match &opt_exchange_rate {
+        Some((dest_currency, _exchange_rate)) => dest_currency.clone(),
+        None => currency.clone(),
+    };
+
     // The node on the route has to be one of our friends:
     let next_public_key = request_send_funds.route.index_to_pk(0).unwrap().clone();
     let opt_next_friend = m_state.state().friends.get(&next_public_key);
@@ -386,9 +555,9 @@ fn handle_request_send_funds<B>(
     // If we forward the request to an offline friend, the request could be stuck for a long
     // time before a response arrives.
     let friend_ready = if let Some(next_friend) = opt_next_friend {
-        if let Some(currency_config) = next_friend.currency_configs.get(currency) {
+        if let Some(currency_config) = next_friend.currency_configs.get(&out_currency) {
             if currency_config.is_open {
-                is_friend_ready(m_state.state(), ephemeral, &next_public_key, currency)
+                is_friend_ready(m_state.state(), ephemeral, &next_public_key, &out_currency)
             } else {
                 false
             }
@@ -414,21 +583,10 @@ fn handle_request_send_funds<B>(
     // // let default_rate = Rate::new();
     let rate = currency_configs.get(currency).unwrap().rate.clone();
 
-    let opt_local_fee = rate.calc_fee(request_send_funds.dest_payment);
-
     let request_id = request_send_funds.request_id.clone();
 
     // Make sure that calc_fee() worked, and that we can take this amount of credits:
-    let opt_request_send_funds = if let Some(local_fee) = opt_local_fee {
-        if let Some(new_left_fees) = request_send_funds.left_fees.checked_sub(local_fee) {
-            request_send_funds.left_fees = new_left_fees;
-            Some(request_send_funds)
-        } else {
-            None
-        }
-    } else {
-        None
-    };
+    let opt_request_send_funds = take_forward_fee(&rate, &opt_exchange_rate, request_send_funds);
 
     let mut request_send_funds = match (opt_request_send_funds, friend_ready) {
         (Some(request_send_funds), true) => request_send_funds,
@@ -451,12 +609,47 @@ fn handle_request_send_funds<B>(
     forward_request(
         m_state,
         send_commands,
-        currency,
+        &out_currency,
         request_send_funds,
         &next_public_key,
     );
 }
 
+/// Take our fee for forwarding a request.
+/// If `opt_exchange_rate` is given, the request is exchanged into the destination currency
+/// instead, and the remaining fees are converted accordingly. When exchanging we profit from the
+/// exchange rate, and do not take a forwarding fee.
+///
+/// Returns None if the request does not carry enough fees, or if an overflow occurred.
+fn take_forward_fee(
+    rate: &Rate,
+    opt_exchange_rate: &Option<(Currency, ExchangeRate)>,
+    mut request_send_funds: RequestSendFundsOp,
+) -> Option<RequestSendFundsOp> {
+    // The amount of credits (in our incoming currency) that represents `dest_payment`:
+    let hop_dest_payment = exchanged_dest_payment(
+        request_send_funds.dest_payment,
+        &request_send_funds.exchanges,
+    )?;
+
+    request_send_funds.left_fees = if let Some((_dest_currency, exchange_rate)) = opt_exchange_rate
+    {
+        // Convert everything we got into the destination currency:
+        let exchanged_total =
+            exchange_rate.convert(hop_dest_payment.checked_add(request_send_funds.left_fees)?)?;
+        request_send_funds.exchanges.remove(0);
+        let next_hop_dest_payment = exchanged_dest_payment(
+            request_send_funds.dest_payment,
+            &request_send_funds.exchanges,
+        )?;
+        exchanged_total.checked_sub(next_hop_dest_payment)?
+    } else {
+        let local_fee = rate.calc_fee(hop_dest_payment)?;
+        request_send_funds.left_fees.checked_sub(local_fee)?
+    };
+    Some(request_send_funds)
+}
+
 fn handle_request_send_funds_cancel<B>(
     m_state: &mut MutableFunderState<B>,
     send_commands: &mut SendCommands,
@@ -475,6 +668,58 @@ fn handle_request_send_funds_cancel<B>(
     );
 }
 
+/// Handle a swap proposed by the remote side.
+/// The swap is canceled if it can never be accepted, or if the remote side has too many pending
+/// swaps with us.
+fn handle_request_swap<B>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    remote_public_key: &PublicKey,
+    currency: &Currency,
+    request_swap: RequestSwapOp,
+) where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let friend = m_state.state().friends.get(remote_public_key).unwrap();
+    let token_channel = match &friend.channel_status {
+        ChannelStatus::Consistent(channel_consistent) => &channel_consistent.token_channel,
+        ChannelStatus::Inconsistent(_) => unreachable!(),
+    };
+    let mutual_credits = token_channel.get_mutual_credits();
+
+    let num_remote_swaps: usize = mutual_credits
+        .values()
+        .map(|mutual_credit| mutual_credit.state().pending_swaps.remote.len())
+        .sum();
+
+    let remote_max_debt = friend
+        .currency_configs
+        .get(currency)
+        .map(|currency_config| currency_config.remote_max_debt)
+        .unwrap_or(0);
+    // The proposed swap was already inserted into this mutual credit:
+    let is_debt_allowed = is_remote_debt_allowed(
+        &mutual_credits.get(currency).unwrap().state().balance,
+        request_swap.src_amount,
+        remote_max_debt,
+    );
+
+    let is_valid = mutual_credits.contains_key(&request_swap.dest_currency)
+        && num_remote_swaps <= MAX_PENDING_REMOTE_SWAPS
+        && is_debt_allowed
+        && !friend.is_closing();
+
+    if !is_valid {
+        cancel_swap(
+            m_state,
+            send_commands,
+            remote_public_key,
+            currency,
+            &request_swap.swap_id,
+        );
+    }
+}
+
 fn handle_response_send_funds<B>(
     m_state: &mut MutableFunderState<B>,
     send_commands: &mut SendCommands,
@@ -485,7 +730,9 @@ fn handle_response_send_funds<B>(
 ) where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
 {
-    match find_request_origin(m_state.state(), currency, &response_send_funds.request_id).cloned() {
+    match find_request_origin(m_state.state(), &response_send_funds.request_id)
+        .map(|(friend_public_key, currency)| (friend_public_key.clone(), currency.clone()))
+    {
         None => {
             // We couldn't find any external origin.
             // It means that we are the origin of this request
@@ -505,8 +752,9 @@ fn handle_response_send_funds<B>(
 
             let payment = m_state.state().payments.get(&payment_id).unwrap();
             let transaction_result = if response_send_funds.is_complete {
+                // The commit is signed over the currency used at the destination:
                 let commit = prepare_commit(
-                    currency.clone(),
+                    exchanged_dest_currency(currency, &pending_transaction.exchanges).clone(),
                     &response_send_funds,
                     &pending_transaction,
                     payment.src_plain_lock.clone(),
@@ -524,11 +772,11 @@ fn handle_response_send_funds<B>(
             };
             outgoing_control.push(FunderOutgoingControl::TransactionResult(transaction_result));
         }
-        Some(friend_public_key) => {
+        Some((friend_public_key, origin_currency)) => {
             // Queue this response message to another token channel:
             let response_op = BackwardsOp::Response(response_send_funds);
             let friend_mutation =
-                FriendMutation::PushBackPendingBackwardsOp((currency.clone(), response_op));
+                FriendMutation::PushBackPendingBackwardsOp((origin_currency, response_op));
             let funder_mutation =
                 FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
             m_state.mutate(funder_mutation);
@@ -550,7 +798,9 @@ fn handle_cancel_send_funds<B, R>(
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
     R: CryptoRandom,
 {
-    match find_request_origin(m_state.state(), currency, &cancel_send_funds.request_id).cloned() {
+    match find_request_origin(m_state.state(), &cancel_send_funds.request_id)
+        .map(|(friend_public_key, currency)| (friend_public_key.clone(), currency.clone()))
+    {
         None => {
             // We are the origin of this request, and we got a cancellation.
 
@@ -570,11 +820,11 @@ fn handle_cancel_send_funds<B, R>(
                 },
             ));
         }
-        Some(friend_public_key) => {
+        Some((friend_public_key, origin_currency)) => {
             // Queue this Cancel message to another token channel:
             let cancel_op = BackwardsOp::Cancel(cancel_send_funds);
             let friend_mutation =
-                FriendMutation::PushBackPendingBackwardsOp((currency.clone(), cancel_op));
+                FriendMutation::PushBackPendingBackwardsOp((origin_currency, cancel_op));
             let funder_mutation =
                 FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
             m_state.mutate(funder_mutation);
@@ -598,7 +848,9 @@ fn handle_collect_send_funds<B, R>(
 {
     // Check if we are the origin of this transaction (Did we send the RequestSendFundsOp
     // message?):
-    match find_request_origin(m_state.state(), currency, &collect_send_funds.request_id).cloned() {
+    match find_request_origin(m_state.state(), &collect_send_funds.request_id)
+        .map(|(friend_public_key, currency)| (friend_public_key.clone(), currency.clone()))
+    {
         None => {
             // We are the origin of this request, and we got a Collect message
             let open_transaction = m_state
@@ -618,7 +870,7 @@ fn handle_collect_send_funds<B, R>(
                 PaymentStage::NewTransactions(new_transactions) => {
                     // Create a Receipt:
                     let receipt = prepare_receipt(
-                        currency,
+                        exchanged_dest_currency(currency, &pending_transaction.exchanges),
                         &collect_send_funds,
                         open_transaction.opt_response.as_ref().unwrap(),
                         &pending_transaction,
@@ -640,7 +892,7 @@ fn handle_collect_send_funds<B, R>(
                     assert!(*num_transactions > 0);
                     // Create a Receipt:
                     let receipt = prepare_receipt(
-                        currency,
+                        exchanged_dest_currency(currency, &pending_transaction.exchanges),
                         &collect_send_funds,
                         open_transaction.opt_response.as_ref().unwrap(),
                         &pending_transaction,
@@ -712,11 +964,11 @@ fn handle_collect_send_funds<B, R>(
                 FunderMutation::RemoveTransaction(collect_send_funds.request_id.clone());
             m_state.mutate(funder_mutation);
         }
-        Some(friend_public_key) => {
+        Some((friend_public_key, origin_currency)) => {
             // Queue this Collect message to another token channel:
             let collect_op = BackwardsOp::Collect(collect_send_funds);
             let friend_mutation =
-                FriendMutation::PushBackPendingBackwardsOp((currency.clone(), collect_op));
+                FriendMutation::PushBackPendingBackwardsOp((origin_currency, collect_op));
             let funder_mutation =
                 FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
             m_state.mutate(funder_mutation);
@@ -747,6 +999,7 @@ fn handle_move_token_output<B, R>(
                     m_state,
                     m_ephemeral.ephemeral(),
                     send_commands,
+                    rng,
                     remote_public_key,
                     currency,
                     request_send_funds,
@@ -761,6 +1014,15 @@ fn handle_move_token_output<B, R>(
                     request_send_funds,
                 );
             }
+            IncomingMessage::RequestSwap(request_swap) => {
+                handle_request_swap(
+                    m_state,
+                    send_commands,
+                    remote_public_key,
+                    currency,
+                    request_swap,
+                );
+            }
             IncomingMessage::Response(IncomingResponseSendFundsOp {
                 pending_transaction,
                 incoming_response,
@@ -890,6 +1152,7 @@ fn handle_move_token_success<B, R>(
                 currencies,
                 // remote_requests_closed,
                 opt_local_relays,
+                opt_direct_addresses,
             } = move_token_received;
 
             // Update address for remote side if necessary:
@@ -908,6 +1171,32 @@ fn handle_move_token_success<B, R>(
                 }
             }
 
+            // Update direct addresses for remote side if necessary:
+            if let Some(new_remote_direct_addresses) = opt_direct_addresses {
+                let friend = m_state.state().friends.get(remote_public_key).unwrap();
+                if friend.remote_direct_addresses != new_remote_direct_addresses {
+                    let friend_mutation =
+                        FriendMutation::SetRemoteDirectAddresses(new_remote_direct_addresses);
+                    let funder_mutation = FunderMutation::FriendMutation((
+                        remote_public_key.clone(),
+                        friend_mutation,
+                    ));
+                    m_state.mutate(funder_mutation);
+
+                    let friend = m_state.state().friends.get(remote_public_key).unwrap();
+
+                    // Notify Channeler to change the friend's direct addresses:
+                    let update_friend = ChannelerUpdateFriend {
+                        friend_public_key: remote_public_key.clone(),
+                        friend_relays: friend.remote_relays.clone(),
+                        friend_direct_addresses: friend.remote_direct_relay_addresses(),
+                        local_relays: friend.sent_local_relays.to_vec(),
+                    };
+                    let channeler_config = ChannelerConfig::UpdateFriend(update_friend);
+                    outgoing_channeler_config.push(channeler_config);
+                }
+            }
+
             // Apply all mutations:
             for tc_mutation in mutations {
                 let friend_mutation = FriendMutation::TcMutation(tc_mutation);
@@ -944,6 +1233,7 @@ fn handle_move_token_success<B, R>(
                     let update_friend = ChannelerUpdateFriend {
                         friend_public_key: remote_public_key.clone(),
                         friend_relays: friend.remote_relays.clone(),
+                        friend_direct_addresses: friend.remote_direct_relay_addresses(),
                         local_relays,
                     };
                     let channeler_config = ChannelerConfig::UpdateFriend(update_friend);
@@ -963,6 +1253,12 @@ fn handle_move_token_success<B, R>(
                     move_token_received_currency.incoming_messages,
                 );
             }
+
+            // The remote side might have proposed new swaps while we are closing the channel:
+            let friend = m_state.state().friends.get(remote_public_key).unwrap();
+            if friend.is_closing() {
+                cancel_pending_swaps(m_state, send_commands, remote_public_key);
+            }
         }
     }
     if token_wanted {
@@ -1120,6 +1416,93 @@ where
     Ok(())
 }
 
+/// Handle a signature over the final state of the channel, sent by the remote side.
+/// Once both sides have signed over the same state, the channel is considered closed.
+fn handle_settlement_signature<B, R>(
+    m_state: &mut MutableFunderState<B>,
+    send_commands: &mut SendCommands,
+    outgoing_control: &mut Vec<FunderOutgoingControl<B>>,
+    rng: &mut R,
+    remote_public_key: &PublicKey,
+    signature: Signature,
+) -> Result<(), HandleFriendError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+    R: CryptoRandom,
+{
+    let friend = m_state.state().friends.get(remote_public_key).unwrap();
+
+    // We already have a settlement signed by both sides:
+    if let FriendCloseStatus::Closed(_) = &friend.close_status {
+        return Ok(());
+    }
+
+    let token_info = match &friend.channel_status {
+        ChannelStatus::Consistent(channel_consistent) => {
+            channel_consistent.token_channel.get_token_info()
+        }
+        // The final state can not be agreed upon while the channel is inconsistent:
+        ChannelStatus::Inconsistent(_) => return Ok(()),
+    };
+
+    // The remote side signs over the state from its own point of view.
+    // The signature will not match if our views of the channel are not the same (For example,
+    // if a move token is on its way). In that case the remote side will sign again once the
+    // channel settles.
+    //
+    // The signature is verified before we agree to close the channel, so that an invalid
+    // signature can not freeze the channel.
+    if !verify_settlement_signature(&token_info.clone().flip(), remote_public_key, &signature) {
+        warn!(
+            "handle_settlement_signature(): Signature does not match current state with {:?}",
+            remote_public_key
+        );
+        return Ok(());
+    }
+
+    let friend_closing = match &friend.close_status {
+        FriendCloseStatus::Open => {
+            // The remote side wants to close the channel. We cooperate:
+            start_closing_friend(
+                m_state,
+                send_commands,
+                outgoing_control,
+                rng,
+                remote_public_key,
+            );
+            FriendClosing::new()
+        }
+        FriendCloseStatus::Closing(friend_closing) => friend_closing.clone(),
+        FriendCloseStatus::Closed(_) => unreachable!(),
+    };
+
+    let close_status = match friend_closing.opt_local_signature {
+        Some(local_signature) if local_signature.token_info == token_info => {
+            FriendCloseStatus::Closed(FriendSettlement {
+                token_info,
+                local_signature: local_signature.signature,
+                remote_signature: signature,
+            })
+        }
+        opt_local_signature => FriendCloseStatus::Closing(FriendClosing {
+            opt_local_signature,
+            opt_remote_signature: Some(SettlementSignature {
+                token_info,
+                signature,
+            }),
+        }),
+    };
+
+    let friend_mutation = FriendMutation::SetCloseStatus(close_status);
+    let funder_mutation =
+        FunderMutation::FriendMutation((remote_public_key.clone(), friend_mutation));
+    m_state.mutate(funder_mutation);
+
+    // We might need to send our own signature:
+    send_commands.set_try_send(remote_public_key);
+    Ok(())
+}
+
 pub fn handle_friend_message<B, R>(
     m_state: &mut MutableFunderState<B>,
     m_ephemeral: &mut MutableEphemeral,
@@ -1160,5 +1543,14 @@ where
             remote_public_key,
             remote_reset_terms,
         ),
+
+        FriendMessage::SettlementSignature(signature) => handle_settlement_signature(
+            m_state,
+            send_commands,
+            outgoing_control,
+            rng,
+            remote_public_key,
+            signature,
+        ),
     }
 }
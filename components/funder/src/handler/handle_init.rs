@@ -20,6 +20,7 @@ pub fn handle_init<B>(
                 let channeler_add_friend = ChannelerUpdateFriend {
                     friend_public_key: friend.remote_public_key.clone(),
                     friend_relays: friend.remote_relays.clone(),
+                    friend_direct_addresses: friend.remote_direct_relay_addresses(),
                     local_relays: friend.sent_local_relays.to_vec(),
                 };
                 enabled_friends.push(channeler_add_friend);
@@ -86,6 +86,16 @@ where
                 &CurrencyChoice::All,
             );
         }
+        IncomingLivenessMessage::RelaysHealth((friend_public_key, relays_health)) => {
+            // Relays health is kept only for existing friends. It is cleared when a friend is
+            // removed, so that a friend added again does not show stale health.
+            if m_state.state().friends.get(&friend_public_key).is_none() {
+                return Ok(());
+            }
+            let ephemeral_mutation =
+                EphemeralMutation::SetRelaysHealth((friend_public_key, relays_health));
+            m_ephemeral.mutate(ephemeral_mutation);
+        }
     };
     Ok(())
 }
@@ -100,8 +110,9 @@ mod tests {
     use crypto::rand::RandGen;
     use crypto::test_utils::DummyRandom;
 
-    use proto::crypto::PrivateKey;
+    use proto::crypto::{PrivateKey, PublicKey};
     use proto::funder::messages::{AddFriend, FriendStatus};
+    use proto::report::messages::RelayHealthReport;
 
     use crate::ephemeral::Ephemeral;
     use crate::friend::{ChannelStatus, FriendMutation};
@@ -186,4 +197,60 @@ mod tests {
         let friend_send_commands = send_commands.send_commands.get(&remote_pk).unwrap();
         assert!(friend_send_commands.resend_outgoing);
     }
+    #[test]
+    fn test_handle_liveness_relays_health() {
+        let mut rng = DummyRandom::new(&[1u8]);
+        let local_pk = PublicKey::from(&[0xaa; PublicKey::len()]);
+        let remote_pk = PublicKey::from(&[0xbb; PublicKey::len()]);
+        let unknown_pk = PublicKey::from(&[0xcc; PublicKey::len()]);
+
+        let relays = vec![dummy_named_relay_address(0)];
+        let mut state = FunderState::<u32>::new(local_pk, relays);
+        let add_friend = AddFriend {
+            friend_public_key: remote_pk.clone(),
+            relays: vec![dummy_relay_address(1)],
+            name: "remote_pk".into(),
+        };
+        state.mutate(&FunderMutation::AddFriend(add_friend));
+
+        let relays_health = vec![RelayHealthReport {
+            relay_public_key: PublicKey::from(&[0xdd; PublicKey::len()]),
+            success_count: 3,
+            failure_count: 1,
+            consecutive_failures: 0,
+            latency_ticks: 2,
+        }];
+
+        let mut m_state = MutableFunderState::new(state);
+        let mut m_ephemeral = MutableEphemeral::new(Ephemeral::new());
+        let mut send_commands = SendCommands::new();
+        let mut outgoing_control = Vec::new();
+
+        for public_key in &[unknown_pk.clone(), remote_pk.clone()] {
+            let liveness_message =
+                IncomingLivenessMessage::RelaysHealth((public_key.clone(), relays_health.clone()));
+            handle_liveness_message(
+                &mut m_state,
+                &mut m_ephemeral,
+                &mut send_commands,
+                &mut outgoing_control,
+                &mut rng,
+                liveness_message,
+            )
+            .unwrap();
+        }
+
+        let (ephemeral_mutations, final_ephemeral_state) = m_ephemeral.done();
+
+        // Relays health of a friend that does not exist is ignored:
+        assert_eq!(ephemeral_mutations.len(), 1);
+        assert!(final_ephemeral_state
+            .relays_health
+            .get(&unknown_pk)
+            .is_none());
+        assert_eq!(
+            final_ephemeral_state.relays_health.get(&remote_pk).unwrap(),
+            &relays_health
+        );
+    }
 }
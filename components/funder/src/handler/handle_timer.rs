@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use signature::canonical::CanonicalSerialize;
+
+use proto::app_server::messages::RelayAddress;
+use proto::consts::REMOTE_SWAP_TIMEOUT_TICKS;
+
+use crate::handler::canceler::{cancel_invoice, cancel_swap};
+use crate::handler::state_wrap::{MutableEphemeral, MutableFunderState};
+use crate::handler::types::SendCommands;
+use crate::handler::utils::invoice_total_paid;
+
+use crate::ephemeral::EphemeralMutation;
+use crate::friend::{ChannelStatus, FriendCloseStatus};
+use crate::state::{ClosedFriend, FunderMutation};
+use crate::types::ChannelerConfig;
+
+/// Remove friends with whom we have finished cooperatively closing the channel.
+/// The final settlement is kept after the friend is removed.
+///
+/// Removal is done here and not right when the channel is closed, to allow our last settlement
+/// signature to be sent to the friend before the Channeler drops the connection.
+fn remove_closed_friends<B>(
+    m_state: &mut MutableFunderState<B>,
+    m_ephemeral: &mut MutableEphemeral,
+    outgoing_channeler_config: &mut Vec<ChannelerConfig<RelayAddress<B>>>,
+) where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    let closed_friends: Vec<_> = m_state
+        .state()
+        .friends
+        .iter()
+        .filter_map(|(friend_public_key, friend)| match &friend.close_status {
+            FriendCloseStatus::Closed(friend_settlement) => Some((
+                friend_public_key.clone(),
+                ClosedFriend {
+                    name: friend.name.clone(),
+                    settlement: friend_settlement.clone(),
+                },
+            )),
+            FriendCloseStatus::Open | FriendCloseStatus::Closing(_) => None,
+        })
+        .collect();
+
+    for (friend_public_key, closed_friend) in closed_friends {
+        let funder_mutation =
+            FunderMutation::AddClosedFriend((friend_public_key.clone(), closed_friend));
+        m_state.mutate(funder_mutation);
+
+        let funder_mutation = FunderMutation::RemoveFriend(friend_public_key.clone());
+        m_state.mutate(funder_mutation);
+
+        let ephemeral_mutation = EphemeralMutation::ClearRelaysHealth(friend_public_key.clone());
+        m_ephemeral.mutate(ephemeral_mutation);
+
+        outgoing_channeler_config.push(ChannelerConfig::RemoveFriend(friend_public_key));
+    }
+}
+
+/// Cancel swaps proposed by friends that were not accepted in time.
+/// Deadlines are kept in the ephemeral state, and are set the first time a remote swap is seen
+/// by this function.
+fn expire_remote_swaps<B>(
+    m_state: &mut MutableFunderState<B>,
+    m_ephemeral: &mut MutableEphemeral,
+    send_commands: &mut SendCommands,
+) where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    let ticks = m_ephemeral.ephemeral().ticks;
+
+    let mut remote_swaps = HashMap::new();
+    for (friend_public_key, friend) in &m_state.state().friends {
+        let token_channel = match &friend.channel_status {
+            ChannelStatus::Consistent(channel_consistent) => &channel_consistent.token_channel,
+            ChannelStatus::Inconsistent(_) => continue,
+        };
+        for (currency, mutual_credit) in token_channel.get_mutual_credits() {
+            for swap_id in mutual_credit.state().pending_swaps.remote.keys() {
+                remote_swaps.insert(
+                    (friend_public_key.clone(), swap_id.clone()),
+                    currency.clone(),
+                );
+            }
+        }
+    }
+
+    // Forget deadlines of swaps that were already accepted or canceled:
+    let stale_deadlines: Vec<_> = m_ephemeral
+        .ephemeral()
+        .remote_swap_deadlines
+        .keys()
+        .filter(|swap_key| !remote_swaps.contains_key(swap_key))
+        .cloned()
+        .collect();
+    for (friend_public_key, swap_id) in stale_deadlines {
+        m_ephemeral.mutate(EphemeralMutation::RemoveRemoteSwapDeadline((
+            friend_public_key,
+            swap_id,
+        )));
+    }
+
+    for ((friend_public_key, swap_id), currency) in remote_swaps {
+        let opt_deadline = m_ephemeral
+            .ephemeral()
+            .remote_swap_deadlines
+            .get(&(friend_public_key.clone(), swap_id.clone()))
+            .cloned();
+        match opt_deadline {
+            None => m_ephemeral.mutate(EphemeralMutation::SetRemoteSwapDeadline((
+                friend_public_key,
+                swap_id,
+                ticks.saturating_add(REMOTE_SWAP_TIMEOUT_TICKS),
+            ))),
+            Some(deadline) if deadline <= ticks => {
+                warn!(
+                    "handle_timer_tick(): Remote swap {:?} expired. Canceling.",
+                    swap_id
+                );
+                cancel_swap(
+                    m_state,
+                    send_commands,
+                    &friend_public_key,
+                    &currency,
+                    &swap_id,
+                );
+                m_ephemeral.mutate(EphemeralMutation::RemoveRemoteSwapDeadline((
+                    friend_public_key,
+                    swap_id,
+                )));
+            }
+            Some(_) => {}
+        }
+    }
+}
+
+/// Cancel held invoices that were not committed in time.
+/// Deadlines are kept in the ephemeral state, and are set the first time a held invoice is seen
+/// by this function. The hold is counted from the previous tick, as it was set some time during
+/// the last tick interval.
+fn expire_held_invoices<B>(
+    m_state: &mut MutableFunderState<B>,
+    m_ephemeral: &mut MutableEphemeral,
+    send_commands: &mut SendCommands,
+) where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    let ticks = m_ephemeral.ephemeral().ticks;
+
+    let held_invoices: HashMap<_, _> = m_state
+        .state()
+        .open_invoices
+        .iter()
+        .filter_map(|(invoice_id, open_invoice)| {
+            open_invoice
+                .opt_hold_ticks
+                .map(|hold_ticks| (invoice_id.clone(), hold_ticks))
+        })
+        .collect();
+
+    // Forget deadlines of invoices that were already committed or canceled:
+    let stale_deadlines: Vec<_> = m_ephemeral
+        .ephemeral()
+        .invoice_hold_deadlines
+        .keys()
+        .filter(|invoice_id| !held_invoices.contains_key(invoice_id))
+        .cloned()
+        .collect();
+    for invoice_id in stale_deadlines {
+        m_ephemeral.mutate(EphemeralMutation::RemoveInvoiceHoldDeadline(invoice_id));
+    }
+
+    for (invoice_id, hold_ticks) in held_invoices {
+        let deadline = match m_ephemeral
+            .ephemeral()
+            .invoice_hold_deadlines
+            .get(&invoice_id)
+            .cloned()
+        {
+            Some(deadline) => deadline,
+            None => {
+                let deadline = ticks.saturating_sub(1).saturating_add(hold_ticks);
+                m_ephemeral.mutate(EphemeralMutation::SetInvoiceHoldDeadline((
+                    invoice_id.clone(),
+                    deadline,
+                )));
+                deadline
+            }
+        };
+
+        if deadline <= ticks {
+            // Maximum hold duration has passed:
+            warn!(
+                "handle_timer_tick(): Held invoice {:?} expired. Canceling.",
+                invoice_id
+            );
+            cancel_invoice(m_state, send_commands, &invoice_id);
+            m_ephemeral.mutate(EphemeralMutation::RemoveInvoiceHoldDeadline(invoice_id));
+        }
+    }
+}
+
+/// Held invoices that were not committed in time are canceled.
+/// Push invoices that lost some of their incoming transactions (For example, due to a token
+/// channel reset) can never be fully paid, and are canceled.
+/// Swaps proposed by friends that were not accepted in time are canceled.
+/// Friends with a closed channel are removed.
+pub fn handle_timer_tick<B>(
+    m_state: &mut MutableFunderState<B>,
+    m_ephemeral: &mut MutableEphemeral,
+    send_commands: &mut SendCommands,
+    outgoing_channeler_config: &mut Vec<ChannelerConfig<RelayAddress<B>>>,
+) where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    m_ephemeral.mutate(EphemeralMutation::Tick);
+    remove_closed_friends(m_state, m_ephemeral, outgoing_channeler_config);
+    expire_remote_swaps(m_state, m_ephemeral, send_commands);
+
+    let broken_push_invoices: Vec<_> = m_state
+        .state()
+        .open_invoices
+        .iter()
+        .filter(|(_invoice_id, open_invoice)| {
+            open_invoice.is_push && invoice_total_paid(m_state.state(), open_invoice).is_none()
+        })
+        .map(|(invoice_id, _open_invoice)| invoice_id.clone())
+        .collect();
+
+    for invoice_id in broken_push_invoices {
+        warn!(
+            "handle_timer_tick(): Push invoice {:?} lost incoming transactions. Canceling.",
+            invoice_id
+        );
+        cancel_invoice(m_state, send_commands, &invoice_id);
+    }
+
+    expire_held_invoices(m_state, m_ephemeral, send_commands);
+}
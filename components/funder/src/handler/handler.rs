@@ -14,10 +14,12 @@ use identity::IdentityClient;
 
 use crate::state::{FunderMutation, FunderState};
 
+use crate::handler::collector::collect_push_invoices;
 use crate::handler::handle_control::handle_control_message;
 use crate::handler::handle_friend::{handle_friend_message, HandleFriendError};
 use crate::handler::handle_init::handle_init;
 use crate::handler::handle_liveness::{handle_liveness_message, HandleLivenessError};
+use crate::handler::handle_timer::handle_timer_tick;
 use crate::handler::sender::create_friend_messages;
 use crate::handler::state_wrap::{MutableEphemeral, MutableFunderState};
 use crate::handler::types::SendCommands;
@@ -71,6 +73,16 @@ where
             None
         }
 
+        FunderIncoming::TimerTick => {
+            handle_timer_tick(
+                &mut m_state,
+                &mut m_ephemeral,
+                &mut send_commands,
+                &mut outgoing_channeler_config,
+            );
+            None
+        }
+
         FunderIncoming::Control(funder_incoming_control) => {
             // Even if an error occurs, we must return an indication to the
             // user that the control request was received.
@@ -167,6 +179,7 @@ pub async fn funder_handle_message<'a, B, R>(
     max_node_relays: usize,
     max_operations_in_batch: usize,
     max_pending_user_requests: usize,
+    local_direct_addresses: &'a [B],
     funder_incoming: FunderIncoming<B>,
 ) -> Result<FunderHandlerOutput<B>, FunderHandlerError>
 where
@@ -177,7 +190,7 @@ where
     let mut m_ephemeral = MutableEphemeral::new(funder_ephemeral);
     let mut outgoing_comms = Vec::new();
 
-    let (send_commands, handle_outgoing_control, outgoing_channeler_config, opt_app_request_id) =
+    let (mut send_commands, handle_outgoing_control, outgoing_channeler_config, opt_app_request_id) =
         funder_handle_incoming(
             &mut m_state,
             &mut m_ephemeral,
@@ -194,6 +207,9 @@ where
     // Sign all unsigned responses and then queue them as mutations
     m_state.sign_responses(identity_client, rng).await;
 
+    // Collect fully paid push invoices. This must happen after the responses were queued.
+    collect_push_invoices(&mut m_state, &mut send_commands);
+
     // Send all possible messages according to SendCommands
     // TODO: Maybe we should output outgoing_comms instead of friend_messages and
     // outgoing_channeler_config. When we merge the two, we might be out of order!
@@ -202,6 +218,7 @@ where
         m_ephemeral.ephemeral(),
         &send_commands,
         max_operations_in_batch,
+        local_direct_addresses,
         identity_client,
         rng,
     )
@@ -8,21 +8,25 @@ use signature::canonical::CanonicalSerialize;
 
 use crypto::rand::{CryptoRandom, RandGen};
 
+use signature::signature_buff::friend_settlement_signature_buff;
+
 use proto::app_server::messages::{NamedRelayAddress, RelayAddress};
 use proto::crypto::{PublicKey, RandValue};
 use proto::funder::messages::{
     BalanceInfo, ChannelerUpdateFriend, CountersInfo, Currency, CurrencyBalanceInfo,
-    CurrencyOperations, FriendMessage, FriendTcOp, McInfo, MoveTokenRequest, TokenInfo,
+    CurrencyOperations, FriendMessage, FriendSettlement, FriendTcOp, McInfo, MoveTokenRequest,
+    TokenInfo,
 };
 
 use identity::IdentityClient;
 
 use crate::mutual_credit::outgoing::{OutgoingMc, QueueOperationError};
+use crate::mutual_credit::types::SwapCounterLeg;
 use crate::types::{create_unsigned_move_token, sign_move_token, ChannelerConfig};
 
 use crate::friend::{
-    BackwardsOp, ChannelInconsistent, ChannelStatus, CurrencyConfig, FriendMutation,
-    SentLocalRelays,
+    BackwardsOp, ChannelInconsistent, ChannelStatus, CurrencyConfig, FriendCloseStatus,
+    FriendClosing, FriendMutation, SentLocalRelays, SettlementSignature, SwapOp,
 };
 use crate::token_channel::{SendMoveTokenOutput, SetDirection, TcMutation, TokenChannel};
 
@@ -58,6 +62,7 @@ struct PendingMoveToken<B> {
     friend_public_key: PublicKey,
     pending_currencies: HashMap<Currency, PendingCurrency>,
     opt_local_relays: Option<Vec<RelayAddress<B>>>,
+    opt_direct_addresses: Option<Vec<B>>,
     opt_active_currencies: Option<Vec<Currency>>,
     token_wanted: bool,
     max_operations_in_batch: usize,
@@ -79,6 +84,7 @@ where
             friend_public_key,
             pending_currencies: HashMap::new(),
             opt_local_relays: None,
+            opt_direct_addresses: None,
             opt_active_currencies: None,
             token_wanted: false,
             max_operations_in_batch,
@@ -128,16 +134,44 @@ where
                     operations: Vec::new(),
                 });
 
-        let _mc_mutations = match pending_currency.outgoing_mc.queue_operation(operation) {
-            Ok(mc_mutations) => Ok(mc_mutations),
+        let orig_outgoing_mc = pending_currency.outgoing_mc.clone();
+        let opt_swap_counter_leg = match pending_currency.outgoing_mc.queue_operation(operation) {
+            Ok(output) => output.opt_swap_counter_leg,
             Err(QueueOperationError::RequestAlreadyExists) => {
                 warn!("Request already exists: {:?}", operation);
-                Ok(vec![])
+                None
+            }
+            Err(QueueOperationError::SwapAlreadyExists)
+            | Err(QueueOperationError::SwapDoesNotExist) => {
+                // The remote side might have accepted or canceled the swap
+                // before we got a chance to send this operation:
+                warn!("Swap operation is no longer relevant: {:?}", operation);
+                return Ok(());
+            }
+            Err(QueueOperationError::CreditsCalcOverflow) if is_accept_swap(operation) => {
+                // Our balance might have changed since the swap was accepted:
+                warn!("Can not accept swap, balance overflow: {:?}", operation);
+                return Ok(());
             }
             Err(_) => unreachable!(),
-        }?;
+        };
+
+        // An accepted swap also changes our balance in another currency:
+        if let Some(swap_counter_leg) = opt_swap_counter_leg {
+            if self
+                .queue_swap_counter_leg(&swap_counter_leg, token_channel)
+                .is_none()
+            {
+                // Undo the accepted swap:
+                let pending_currency = self.pending_currencies.get_mut(currency).unwrap();
+                pending_currency.outgoing_mc = orig_outgoing_mc;
+                warn!("Can not apply swap counter leg: {:?}", operation);
+                return Ok(());
+            }
+        }
 
         // Add operation:
+        let pending_currency = self.pending_currencies.get_mut(currency).unwrap();
         pending_currency.operations.push(operation.clone());
 
         /*
@@ -154,15 +188,62 @@ where
         Ok(())
     }
 
+    /// Apply the counter leg of an accepted swap to the pending state of the counter currency,
+    /// so that later operations in that currency are checked against the new balance.
+    /// Returns None if the counter currency does not exist, or if the balance overflows.
+    fn queue_swap_counter_leg(
+        &mut self,
+        swap_counter_leg: &SwapCounterLeg,
+        token_channel: &TokenChannel<B>,
+    ) -> Option<()> {
+        let counter_currency = match swap_counter_leg {
+            SwapCounterLeg::Increase((currency, _)) | SwapCounterLeg::Decrease((currency, _)) => {
+                currency
+            }
+        };
+
+        if !self.pending_currencies.contains_key(counter_currency) {
+            let outgoing_mc = token_channel
+                .get_incoming()
+                .unwrap()
+                .create_outgoing_mc(counter_currency)?;
+            self.pending_currencies.insert(
+                counter_currency.clone(),
+                PendingCurrency {
+                    outgoing_mc,
+                    operations: Vec::new(),
+                },
+            );
+        }
+
+        let pending_currency = self.pending_currencies.get_mut(counter_currency).unwrap();
+        pending_currency
+            .outgoing_mc
+            .queue_swap_counter_leg(swap_counter_leg)
+            .ok()?;
+        Some(())
+    }
+
     fn set_local_relays(&mut self, local_relays: Vec<RelayAddress<B>>) {
         self.opt_local_relays = Some(local_relays);
     }
 
+    fn set_direct_addresses(&mut self, direct_addresses: Vec<B>) {
+        self.opt_direct_addresses = Some(direct_addresses);
+    }
+
     fn set_active_currencies(&mut self, active_currencies: Vec<Currency>) {
         self.opt_active_currencies = Some(active_currencies);
     }
 }
 
+fn is_accept_swap(operation: &FriendTcOp) -> bool {
+    match operation {
+        FriendTcOp::AcceptSwap(_) => true,
+        _ => false,
+    }
+}
+
 fn transmit_outgoing<B>(
     m_state: &MutableFunderState<B>,
     friend_public_key: &PublicKey,
@@ -197,6 +278,7 @@ pub async fn apply_local_reset<'a, B, R>(
     m_state: &'a mut MutableFunderState<B>,
     friend_public_key: &'a PublicKey,
     channel_inconsistent: &'a ChannelInconsistent,
+    local_direct_addresses: &'a [B],
     identity_client: &'a mut IdentityClient,
     rng: &'a mut R,
 ) where
@@ -250,6 +332,13 @@ pub async fn apply_local_reset<'a, B, R>(
             .collect(),
     );
 
+    // Prepare our current direct addresses:
+    let friend_mutation = FriendMutation::SetSentDirectAddresses(local_direct_addresses.to_vec());
+    let funder_mutation =
+        FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
+    m_state.mutate(funder_mutation);
+    let opt_direct_addresses = Some(local_direct_addresses.to_vec());
+
     let move_token_counter = 0;
     let local_pending_debt = 0;
     let remote_pending_debt = 0;
@@ -285,6 +374,7 @@ pub async fn apply_local_reset<'a, B, R>(
         // No operations are required for a reset move token
         Vec::new(),
         opt_local_relays,
+        opt_direct_addresses,
         opt_active_currencies,
         &token_info,
         remote_reset_terms.reset_token.clone(),
@@ -334,6 +424,7 @@ async fn send_friend_iter1<'a, B, R>(
     identity_client: &'a mut IdentityClient,
     rng: &'a mut R,
     max_operations_in_batch: usize,
+    local_direct_addresses: &'a [B],
     mut outgoing_messages: &'a mut Vec<OutgoingMessage<B>>,
     outgoing_channeler_config: &'a mut Vec<ChannelerConfig<RelayAddress<B>>>,
 ) where
@@ -359,6 +450,7 @@ async fn send_friend_iter1<'a, B, R>(
                 m_state,
                 friend_public_key,
                 &c_channel_inconsistent,
+                local_direct_addresses,
                 identity_client,
                 rng,
             )
@@ -403,10 +495,13 @@ async fn send_friend_iter1<'a, B, R>(
         let tc_outgoing = &tc_out_borrow.tc_outgoing;
         // Do we have anything that we want to send?
         // (Currently the token is at the remote side)
-        let is_pending = estimate_should_send(m_state.state(), friend_public_key);
+        let is_pending =
+            estimate_should_send(m_state.state(), friend_public_key, local_direct_addresses);
         if is_pending || friend_send_commands.resend_outgoing {
-            let is_token_wanted =
-                is_pending || tc_outgoing.move_token_out.opt_local_relays.is_some();
+            let move_token_out = &tc_outgoing.move_token_out;
+            let is_token_wanted = is_pending
+                || move_token_out.opt_local_relays.is_some()
+                || move_token_out.opt_direct_addresses.is_some();
             transmit_outgoing(
                 m_state,
                 &friend_public_key,
@@ -446,13 +541,18 @@ async fn send_friend_iter1<'a, B, R>(
         friend_public_key,
         pending_move_token,
         friend_send_commands.resend_relays,
+        local_direct_addresses,
     );
 }
 
 /// Do we need to send anything to the remote side?
 /// Note that this is only an estimation. It is possible that when the token from remote side
 /// arrives, the state will be different.
-fn estimate_should_send<'a, B>(state: &'a FunderState<B>, friend_public_key: &'a PublicKey) -> bool
+fn estimate_should_send<'a, B>(
+    state: &'a FunderState<B>,
+    friend_public_key: &'a PublicKey,
+    local_direct_addresses: &'a [B],
+) -> bool
 where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
 {
@@ -467,6 +567,11 @@ where
         }
     };
 
+    // Check if notification about direct addresses change is required:
+    if friend.sent_direct_addresses.as_slice() != local_direct_addresses {
+        return true;
+    }
+
     match &friend.channel_status {
         ChannelStatus::Consistent(channel_consistent) => {
             // Check if we need to tell remote side about our local active currencies:
@@ -485,6 +590,7 @@ where
             if !channel_consistent.pending_backwards_ops.is_empty()
                 || !channel_consistent.pending_requests.is_empty()
                 || !channel_consistent.pending_user_requests.is_empty()
+                || !channel_consistent.pending_swap_ops.is_empty()
             {
                 return true;
             }
@@ -517,6 +623,14 @@ where
     }
 }
 
+fn swap_op_to_friend_tc_op(swap_op: SwapOp) -> FriendTcOp {
+    match swap_op {
+        SwapOp::Request(request_swap) => FriendTcOp::RequestSwap(request_swap),
+        SwapOp::Accept(accept_swap) => FriendTcOp::AcceptSwap(accept_swap),
+        SwapOp::Cancel(cancel_swap) => FriendTcOp::CancelSwap(cancel_swap),
+    }
+}
+
 fn backwards_op_to_friend_tc_op(backwards_op: BackwardsOp) -> FriendTcOp {
     match backwards_op {
         BackwardsOp::Response(response_send_funds) => {
@@ -538,6 +652,7 @@ fn collect_outgoing_move_token<'a, B>(
     friend_public_key: &'a PublicKey,
     pending_move_token: &'a mut PendingMoveToken<B>,
     resend_relays: bool,
+    local_direct_addresses: &'a [B],
 ) -> Result<(), CollectOutgoingError>
 where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug + Hash,
@@ -546,8 +661,9 @@ where
     - Check if last sent local address is up to date.
     - Collect as many operations as possible (Not more than max ops per batch)
         1. Responses (response, cancel, collect)
-        2. Pending requests
-        3. User pending requests
+        2. Swaps (request, accept, cancel)
+        3. Pending requests
+        4. User pending requests
     - When adding requests, check the following:
         - Valid from credits point of view.
     - If a request is not valid, queue a Cancel message to relevant friend.
@@ -616,12 +732,24 @@ where
         let update_friend = ChannelerUpdateFriend {
             friend_public_key: friend_public_key.clone(),
             friend_relays: friend.remote_relays.clone(),
+            friend_direct_addresses: friend.remote_direct_relay_addresses(),
             local_relays: friend.sent_local_relays.to_vec(),
         };
         let channeler_config = ChannelerConfig::UpdateFriend(update_friend);
         outgoing_channeler_config.push(channeler_config);
     }
 
+    // Send update about local direct addresses if needed:
+    let friend = m_state.state().friends.get(friend_public_key).unwrap();
+    if resend_relays || friend.sent_direct_addresses.as_slice() != local_direct_addresses {
+        pending_move_token.set_direct_addresses(local_direct_addresses.to_vec());
+        let friend_mutation =
+            FriendMutation::SetSentDirectAddresses(local_direct_addresses.to_vec());
+        let funder_mutation =
+            FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
+        m_state.mutate(funder_mutation);
+    }
+
     // Deal with active currencies:
     let friend = m_state.state().friends.get(friend_public_key).unwrap();
     let token_channel = match &friend.channel_status {
@@ -664,6 +792,24 @@ where
         ChannelStatus::Inconsistent(_) => unreachable!(),
     };
 
+    // Send pending swap operations:
+    let mut pending_swap_ops = channel_consistent.pending_swap_ops.clone();
+    while let Some((currency, pending_swap_op)) = pending_swap_ops.pop_front() {
+        let pending_op = swap_op_to_friend_tc_op(pending_swap_op);
+        queue_operation(m_state, pending_move_token, &currency, &pending_op)?;
+
+        let friend_mutation = FriendMutation::PopFrontPendingSwapOp;
+        let funder_mutation =
+            FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
+        m_state.mutate(funder_mutation);
+    }
+
+    let friend = m_state.state().friends.get(friend_public_key).unwrap();
+    let channel_consistent = match &friend.channel_status {
+        ChannelStatus::Consistent(channel_consistent) => channel_consistent,
+        ChannelStatus::Inconsistent(_) => unreachable!(),
+    };
+
     // Send pending requests:
     // TODO: Possibly replace this clone with something more efficient later:
     let mut pending_requests = channel_consistent.pending_requests.clone();
@@ -738,15 +884,23 @@ async fn send_move_token<'a, B, R>(
     let PendingMoveToken {
         pending_currencies,
         opt_local_relays,
+        opt_direct_addresses,
         opt_active_currencies,
         token_wanted,
         may_send_empty,
         ..
     } = pending_move_token;
 
+    // Operations queued for a currency might have been dropped:
+    let pending_currencies: HashMap<_, _> = pending_currencies
+        .into_iter()
+        .filter(|(_currency, pending_currency)| !pending_currency.operations.is_empty())
+        .collect();
+
     if pending_currencies.is_empty()
         && opt_active_currencies.is_none()
         && opt_local_relays.is_none()
+        && opt_direct_addresses.is_none()
         && !may_send_empty
     {
         return;
@@ -754,7 +908,7 @@ async fn send_move_token<'a, B, R>(
 
     // We want the token back if we just set a new address, to be sure
     // that the remote side knows about the new address.
-    let token_wanted = token_wanted || opt_local_relays.is_some();
+    let token_wanted = token_wanted || opt_local_relays.is_some() || opt_direct_addresses.is_some();
 
     let friend = m_state.state().friends.get(&friend_public_key).unwrap();
 
@@ -783,6 +937,7 @@ async fn send_move_token<'a, B, R>(
         .simulate_send_move_token(
             currencies_operations,
             opt_local_relays,
+            opt_direct_addresses,
             opt_active_currencies,
             rand_nonce,
         )
@@ -854,12 +1009,133 @@ fn init_cancel_pending_move_token<B>(
     }
 }
 
+/// Is the channel with a friend ready to be settled?
+/// This is the case if nothing is left to be sent, and no transactions or swaps are in progress.
+fn is_friend_settled<B>(
+    state: &FunderState<B>,
+    friend_public_key: &PublicKey,
+    local_direct_addresses: &[B],
+) -> bool
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let friend = state.friends.get(friend_public_key).unwrap();
+    let token_channel = match &friend.channel_status {
+        ChannelStatus::Consistent(channel_consistent) => &channel_consistent.token_channel,
+        ChannelStatus::Inconsistent(_) => return false,
+    };
+
+    if estimate_should_send(state, friend_public_key, local_direct_addresses) {
+        return false;
+    }
+
+    token_channel
+        .get_mutual_credits()
+        .values()
+        .all(|mutual_credit| {
+            let mc_state = mutual_credit.state();
+            mc_state.pending_transactions.local.is_empty()
+                && mc_state.pending_transactions.remote.is_empty()
+                && mc_state.pending_swaps.local.is_empty()
+                && mc_state.pending_swaps.remote.is_empty()
+        })
+}
+
+/// Sign over the final state of a channel we are closing, and send the signature to the remote
+/// side.
+async fn send_settlement_signature<'a, B>(
+    m_state: &'a mut MutableFunderState<B>,
+    friend_public_key: &'a PublicKey,
+    resend: bool,
+    local_direct_addresses: &'a [B],
+    identity_client: &'a mut IdentityClient,
+    outgoing_messages: &'a mut Vec<OutgoingMessage<B>>,
+) where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug,
+{
+    let friend = m_state.state().friends.get(friend_public_key).unwrap();
+    let friend_closing = match &friend.close_status {
+        FriendCloseStatus::Open => return,
+        FriendCloseStatus::Closing(friend_closing) => friend_closing.clone(),
+        FriendCloseStatus::Closed(friend_settlement) => {
+            // The remote side might not have received our signature yet:
+            if resend {
+                outgoing_messages.push((
+                    friend_public_key.clone(),
+                    FriendMessage::SettlementSignature(friend_settlement.local_signature.clone()),
+                ));
+            }
+            return;
+        }
+    };
+
+    if !is_friend_settled(m_state.state(), friend_public_key, local_direct_addresses) {
+        return;
+    }
+
+    let token_info = match &friend.channel_status {
+        ChannelStatus::Consistent(channel_consistent) => {
+            channel_consistent.token_channel.get_token_info()
+        }
+        ChannelStatus::Inconsistent(_) => unreachable!(),
+    };
+
+    let local_signature = match friend_closing.opt_local_signature {
+        // We have already signed over this state:
+        Some(local_signature) if local_signature.token_info == token_info => {
+            if resend {
+                outgoing_messages.push((
+                    friend_public_key.clone(),
+                    FriendMessage::SettlementSignature(local_signature.signature),
+                ));
+            }
+            return;
+        }
+        _ => {
+            let signature_buff = friend_settlement_signature_buff(&token_info);
+            let signature = identity_client
+                .request_signature(signature_buff)
+                .await
+                .unwrap();
+            SettlementSignature {
+                token_info: token_info.clone(),
+                signature,
+            }
+        }
+    };
+
+    let close_status = match friend_closing.opt_remote_signature {
+        Some(remote_signature) if remote_signature.token_info == token_info => {
+            FriendCloseStatus::Closed(FriendSettlement {
+                token_info,
+                local_signature: local_signature.signature.clone(),
+                remote_signature: remote_signature.signature,
+            })
+        }
+        opt_remote_signature => FriendCloseStatus::Closing(FriendClosing {
+            opt_local_signature: Some(local_signature.clone()),
+            opt_remote_signature,
+        }),
+    };
+
+    let friend_mutation = FriendMutation::SetCloseStatus(close_status);
+    let funder_mutation =
+        FunderMutation::FriendMutation((friend_public_key.clone(), friend_mutation));
+    m_state.mutate(funder_mutation);
+
+    outgoing_messages.push((
+        friend_public_key.clone(),
+        FriendMessage::SettlementSignature(local_signature.signature),
+    ));
+}
+
 /// Send all possible messages according to SendCommands
 pub async fn create_friend_messages<'a, B, R>(
     m_state: &'a mut MutableFunderState<B>,
     ephemeral: &'a Ephemeral,
     send_commands: &'a SendCommands,
     max_operations_in_batch: usize,
+    local_direct_addresses: &'a [B],
     identity_client: &'a mut IdentityClient,
     rng: &'a mut R,
 ) -> (
@@ -888,6 +1164,7 @@ where
             identity_client,
             rng,
             max_operations_in_batch,
+            local_direct_addresses,
             &mut outgoing_messages,
             &mut outgoing_channeler_config,
         )
@@ -923,5 +1200,21 @@ where
         .await;
     }
 
+    // Sign over the final state of channels we are closing:
+    for (friend_public_key, friend_send_commands) in &send_commands.send_commands {
+        if !ephemeral.liveness.is_online(friend_public_key) {
+            continue;
+        }
+        send_settlement_signature(
+            m_state,
+            friend_public_key,
+            friend_send_commands.resend_outgoing,
+            local_direct_addresses,
+            identity_client,
+            &mut outgoing_messages,
+        )
+        .await;
+    }
+
     (outgoing_messages, outgoing_channeler_config)
 }
@@ -0,0 +1,323 @@
+use super::utils::{
+    apply_funder_incoming, apply_funder_incoming_direct, dummy_named_relay_address,
+    dummy_relay_address,
+};
+
+use std::cmp::Ordering;
+
+use futures::executor::{LocalPool, ThreadPool};
+use futures::task::SpawnExt;
+use futures::{future, FutureExt};
+
+use identity::{create_identity, IdentityClient};
+
+use crypto::identity::{compare_public_key, SoftwareEd25519Identity};
+use crypto::rand::RandGen;
+use crypto::test_utils::DummyRandom;
+
+use proto::app_server::messages::RelayAddress;
+use proto::crypto::{PrivateKey, Uid};
+
+use proto::funder::messages::{
+    AddFriend, FriendMessage, FriendStatus, FunderControl, FunderIncomingControl, SetFriendStatus,
+};
+
+use crate::ephemeral::Ephemeral;
+use crate::state::FunderState;
+use crate::types::{
+    ChannelerConfig, FunderIncoming, FunderIncomingComm, FunderOutgoingComm,
+    IncomingLivenessMessage,
+};
+
+/// Node2 accepts direct connections on this address:
+const DIRECT_ADDRESS2: u32 = 0x200;
+
+async fn task_handler_direct_addresses(
+    identity_client1: IdentityClient,
+    identity_client2: IdentityClient,
+) {
+    // NOTE: We use Box::pin() in order to make sure we don't get a too large Future which will
+    // cause a stack overflow.
+    // See:  https://github.com/rust-lang-nursery/futures-rs/issues/1330
+
+    // Sort the identities. identity_client1 will be the first sender:
+    let pk1 = identity_client1.request_public_key().await.unwrap();
+    let pk2 = identity_client2.request_public_key().await.unwrap();
+    let (mut identity_client1, pk1, mut identity_client2, pk2) =
+        if compare_public_key(&pk1, &pk2) == Ordering::Less {
+            (identity_client1, pk1, identity_client2, pk2)
+        } else {
+            (identity_client2, pk2, identity_client1, pk1)
+        };
+
+    let direct_addresses2 = vec![DIRECT_ADDRESS2];
+
+    let relays1 = vec![dummy_named_relay_address(1)];
+    let mut state1 = FunderState::<u32>::new(pk1.clone(), relays1);
+    let mut ephemeral1 = Ephemeral::new();
+    let relays2 = vec![dummy_named_relay_address(2)];
+    let mut state2 = FunderState::<u32>::new(pk2.clone(), relays2);
+    let mut ephemeral2 = Ephemeral::new();
+
+    let mut rng = DummyRandom::new(&[3u8]);
+
+    // Initialize 1:
+    let funder_incoming = FunderIncoming::Init;
+    Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state1,
+        &mut ephemeral1,
+        &mut rng,
+        &mut identity_client1,
+    ))
+    .await
+    .unwrap();
+
+    // Initialize 2:
+    let funder_incoming = FunderIncoming::Init;
+    Box::pin(apply_funder_incoming_direct(
+        funder_incoming,
+        &direct_addresses2,
+        &mut state2,
+        &mut ephemeral2,
+        &mut rng,
+        &mut identity_client2,
+    ))
+    .await
+    .unwrap();
+
+    // Node1: Add friend 2:
+    let add_friend = AddFriend {
+        friend_public_key: pk2.clone(),
+        relays: vec![dummy_relay_address(2)],
+        name: String::from("pk2"),
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[11; Uid::len()]),
+        FunderControl::AddFriend(add_friend),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state1,
+        &mut ephemeral1,
+        &mut rng,
+        &mut identity_client1,
+    ))
+    .await
+    .unwrap();
+
+    // Node1: Enable friend 2:
+    let set_friend_status = SetFriendStatus {
+        friend_public_key: pk2.clone(),
+        status: FriendStatus::Enabled,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[12; Uid::len()]),
+        FunderControl::SetFriendStatus(set_friend_status),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state1,
+        &mut ephemeral1,
+        &mut rng,
+        &mut identity_client1,
+    ))
+    .await
+    .unwrap();
+
+    // Node2: Add friend 1:
+    let add_friend = AddFriend {
+        friend_public_key: pk1.clone(),
+        relays: vec![dummy_relay_address(1)],
+        name: String::from("pk1"),
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[13; Uid::len()]),
+        FunderControl::AddFriend(add_friend),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    Box::pin(apply_funder_incoming_direct(
+        funder_incoming,
+        &direct_addresses2,
+        &mut state2,
+        &mut ephemeral2,
+        &mut rng,
+        &mut identity_client2,
+    ))
+    .await
+    .unwrap();
+
+    // Node2: enable friend 1:
+    let set_friend_status = SetFriendStatus {
+        friend_public_key: pk1.clone(),
+        status: FriendStatus::Enabled,
+    };
+    let incoming_control_message = FunderIncomingControl::new(
+        Uid::from(&[14; Uid::len()]),
+        FunderControl::SetFriendStatus(set_friend_status),
+    );
+    let funder_incoming = FunderIncoming::Control(incoming_control_message);
+    Box::pin(apply_funder_incoming_direct(
+        funder_incoming,
+        &direct_addresses2,
+        &mut state2,
+        &mut ephemeral2,
+        &mut rng,
+        &mut identity_client2,
+    ))
+    .await
+    .unwrap();
+
+    // Node1: Notify that Node2 is alive
+    let incoming_liveness_message = IncomingLivenessMessage::Online(pk2.clone());
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Liveness(incoming_liveness_message));
+    let (outgoing_comms, _outgoing_control) = Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state1,
+        &mut ephemeral1,
+        &mut rng,
+        &mut identity_client1,
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(outgoing_comms.len(), 1);
+    let friend_message = match &outgoing_comms[0] {
+        FunderOutgoingComm::FriendMessage((pk, friend_message)) => {
+            if let FriendMessage::MoveTokenRequest(move_token_request) = friend_message {
+                assert_eq!(pk, &pk2);
+                // Node1 does not accept direct connections:
+                assert_eq!(move_token_request.move_token.opt_direct_addresses, None);
+            } else {
+                unreachable!();
+            }
+            friend_message.clone()
+        }
+        _ => unreachable!(),
+    };
+
+    // Node2: Notify that Node1 is alive
+    let incoming_liveness_message = IncomingLivenessMessage::Online(pk1.clone());
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Liveness(incoming_liveness_message));
+    Box::pin(apply_funder_incoming_direct(
+        funder_incoming,
+        &direct_addresses2,
+        &mut state2,
+        &mut ephemeral2,
+        &mut rng,
+        &mut identity_client2,
+    ))
+    .await
+    .unwrap();
+
+    // Node2: Receive friend_message from Node1:
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Friend((pk1.clone(), friend_message)));
+    let (outgoing_comms, _outgoing_control) = Box::pin(apply_funder_incoming_direct(
+        funder_incoming,
+        &direct_addresses2,
+        &mut state2,
+        &mut ephemeral2,
+        &mut rng,
+        &mut identity_client2,
+    ))
+    .await
+    .unwrap();
+
+    // Node2 advertises its direct addresses together with its relays:
+    let friend_message = outgoing_comms
+        .iter()
+        .find_map(|outgoing_comm| match outgoing_comm {
+            FunderOutgoingComm::FriendMessage((pk, friend_message)) => {
+                assert_eq!(pk, &pk1);
+                Some(friend_message.clone())
+            }
+            _ => None,
+        })
+        .unwrap();
+    match &friend_message {
+        FriendMessage::MoveTokenRequest(move_token_request) => {
+            assert_eq!(move_token_request.token_wanted, true);
+            let friend_move_token = &move_token_request.move_token;
+            assert_eq!(
+                friend_move_token.opt_local_relays,
+                Some(vec![dummy_relay_address(2)])
+            );
+            assert_eq!(
+                friend_move_token.opt_direct_addresses,
+                Some(direct_addresses2.clone())
+            );
+        }
+        _ => unreachable!(),
+    };
+    let friend2 = state2.friends.get(&pk1).unwrap();
+    assert_eq!(friend2.sent_direct_addresses, direct_addresses2);
+
+    // Node1: Receive friend_message from Node2:
+    let funder_incoming =
+        FunderIncoming::Comm(FunderIncomingComm::Friend((pk2.clone(), friend_message)));
+    let (outgoing_comms, _outgoing_control) = Box::pin(apply_funder_incoming(
+        funder_incoming,
+        &mut state1,
+        &mut ephemeral1,
+        &mut rng,
+        &mut identity_client1,
+    ))
+    .await
+    .unwrap();
+
+    let friend1 = state1.friends.get(&pk2).unwrap();
+    assert_eq!(friend1.remote_direct_addresses, direct_addresses2);
+
+    // Node1 should tell its Channeler to try Node2's direct addresses:
+    let update_friend = outgoing_comms
+        .iter()
+        .find_map(|outgoing_comm| match outgoing_comm {
+            FunderOutgoingComm::ChannelerConfig(ChannelerConfig::UpdateFriend(update_friend)) => {
+                Some(update_friend.clone())
+            }
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(update_friend.friend_public_key, pk2);
+    assert_eq!(update_friend.friend_relays, vec![dummy_relay_address(2)]);
+    assert_eq!(
+        update_friend.friend_direct_addresses,
+        vec![RelayAddress {
+            public_key: pk2.clone(),
+            address: DIRECT_ADDRESS2,
+        }]
+    );
+}
+
+#[test]
+fn test_handler_direct_addresses() {
+    let thread_pool = ThreadPool::new().unwrap();
+
+    let mut rng1 = DummyRandom::new(&[1u8]);
+    let pkcs8 = PrivateKey::rand_gen(&mut rng1);
+    let identity1 = SoftwareEd25519Identity::from_private_key(&pkcs8).unwrap();
+    let (requests_sender1, identity_server1) = create_identity(identity1);
+    let identity_client1 = IdentityClient::new(requests_sender1);
+    thread_pool
+        .spawn(identity_server1.then(|_| future::ready(())))
+        .unwrap();
+
+    let mut rng2 = DummyRandom::new(&[2u8]);
+    let pkcs8 = PrivateKey::rand_gen(&mut rng2);
+    let identity2 = SoftwareEd25519Identity::from_private_key(&pkcs8).unwrap();
+    let (requests_sender2, identity_server2) = create_identity(identity2);
+    let identity_client2 = IdentityClient::new(requests_sender2);
+    thread_pool
+        .spawn(identity_server2.then(|_| future::ready(())))
+        .unwrap();
+
+    LocalPool::new().run_until(task_handler_direct_addresses(
+        identity_client1,
+        identity_client2,
+    ));
+}
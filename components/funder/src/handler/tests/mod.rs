@@ -1,4 +1,5 @@
 mod change_address;
+mod direct_addresses;
 mod pair_basic;
 mod pair_inconsistency;
 pub mod utils;
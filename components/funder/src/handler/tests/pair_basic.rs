@@ -543,6 +543,7 @@ async fn task_handler_pair_basic<'a>(
         },
         dest_payment: 16,
         fees: 4,
+        exchanges: Vec::new(),
     };
 
     let incoming_control_message = FunderIncomingControl::new(
@@ -862,6 +863,7 @@ async fn task_handler_pair_basic<'a>(
         },
         dest_payment: 16,
         fees: 4,
+        exchanges: Vec::new(),
     };
 
     let incoming_control_message = FunderIncomingControl::new(
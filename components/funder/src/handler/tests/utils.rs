@@ -42,6 +42,23 @@ pub async fn apply_funder_incoming<'a, B, R>(
     rng: &'a mut R,
     identity_client: &'a mut IdentityClient,
 ) -> Result<(Vec<FunderOutgoingComm<B>>, Vec<FunderOutgoingControl<B>>), FunderHandlerError>
+where
+    B: Clone + PartialEq + Eq + CanonicalSerialize + Debug + Hash + 'a,
+    R: CryptoRandom + 'a,
+{
+    apply_funder_incoming_direct(funder_incoming, &[], state, ephemeral, rng, identity_client).await
+}
+
+/// Similar to `apply_funder_incoming`, for a node that accepts direct connections on
+/// `local_direct_addresses`.
+pub async fn apply_funder_incoming_direct<'a, B, R>(
+    funder_incoming: FunderIncoming<B>,
+    local_direct_addresses: &'a [B],
+    state: &'a mut FunderState<B>,
+    ephemeral: &'a mut Ephemeral,
+    rng: &'a mut R,
+    identity_client: &'a mut IdentityClient,
+) -> Result<(Vec<FunderOutgoingComm<B>>, Vec<FunderOutgoingControl<B>>), FunderHandlerError>
 where
     B: Clone + PartialEq + Eq + CanonicalSerialize + Debug + Hash + 'a,
     R: CryptoRandom + 'a,
@@ -54,6 +71,7 @@ where
         TEST_MAX_NODE_RELAYS,
         TEST_MAX_OPERATIONS_IN_BATCH,
         TEST_MAX_PENDING_USER_REQUESTS,
+        local_direct_addresses,
         funder_incoming,
     )
     .await?;
@@ -2,26 +2,32 @@ use std::fmt::Debug;
 
 use signature::canonical::CanonicalSerialize;
 
+use common::safe_arithmetic::SafeSignedArithmetic;
+
 use proto::funder::messages::{Currency, PendingTransaction};
 
 use proto::crypto::{PublicKey, Uid};
 
-use crate::state::FunderState;
+use crate::state::{FunderState, OpenInvoice};
 
 use crate::ephemeral::Ephemeral;
 use crate::friend::ChannelStatus;
+use crate::mutual_credit::types::McBalance;
 
 /// Find the originator of a pending local request.
 /// This should be a pending remote request at some other friend.
-/// Returns the public key of a friend. If we are the origin of this request, the function returns None.
+/// The remote request could be of a different currency, if we exchanged the request from one
+/// currency to another.
+///
+/// Returns the public key of a friend, together with the currency of the remote request.
+/// If we are the origin of this request, the function returns None.
 ///
 /// TODO: We need to change this search to be O(1) in the future. Possibly by maintaining a map
 /// between request_id and (friend_public_key, friend).
 pub fn find_request_origin<'a, B>(
     state: &'a FunderState<B>,
-    currency: &Currency,
     request_id: &Uid,
-) -> Option<&'a PublicKey>
+) -> Option<(&'a PublicKey, &'a Currency)>
 where
     B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
 {
@@ -29,23 +35,17 @@ where
         match &friend.channel_status {
             ChannelStatus::Inconsistent(_) => continue,
             ChannelStatus::Consistent(channel_consistent) => {
-                let mutual_credit = if let Some(mutual_credit) = channel_consistent
-                    .token_channel
-                    .get_mutual_credits()
-                    .get(currency)
+                for (currency, mutual_credit) in
+                    channel_consistent.token_channel.get_mutual_credits()
                 {
-                    mutual_credit
-                } else {
-                    continue;
-                };
-
-                if mutual_credit
-                    .state()
-                    .pending_transactions
-                    .remote
-                    .contains_key(request_id)
-                {
-                    return Some(friend_public_key);
+                    if mutual_credit
+                        .state()
+                        .pending_transactions
+                        .remote
+                        .contains_key(request_id)
+                    {
+                        return Some((friend_public_key, currency));
+                    }
                 }
             }
         }
@@ -127,6 +127,21 @@ where
     None
 }
 
+/// Calculate the total amount of credits paid so far for an open invoice.
+/// Returns None if any of the incoming transactions could not be found.
+pub fn invoice_total_paid<B>(state: &FunderState<B>, open_invoice: &OpenInvoice) -> Option<u128>
+where
+    B: Clone + CanonicalSerialize + PartialEq + Eq + Debug,
+{
+    let mut total_paid = 0u128;
+    for request_id in &open_invoice.incoming_transactions {
+        let pending_transaction =
+            find_remote_pending_transaction(state, &open_invoice.currency, request_id)?;
+        total_paid = total_paid.checked_add(pending_transaction.dest_payment)?;
+    }
+    Some(total_paid)
+}
+
 pub fn is_friend_ready<B>(
     state: &FunderState<B>,
     ephemeral: &Ephemeral,
@@ -141,6 +156,11 @@ where
         return false;
     }
 
+    // No new transactions are allowed while closing the channel:
+    if friend.is_closing() {
+        return false;
+    }
+
     // Make sure that the channel is consistent:
     let token_channel = match &friend.channel_status {
         ChannelStatus::Inconsistent(_) => return false,
@@ -159,3 +179,15 @@ where
     // Make sure that the remote side has open requests:
     // mutual_credit.state().requests_status.remote.is_open()
 }
+
+/// Check if the remote side's debt stays within `remote_max_debt` after it takes `amount` more
+/// credits from us (Taking into account credits already frozen by the remote side).
+pub fn is_remote_debt_allowed(balance: &McBalance, amount: u128, remote_max_debt: u128) -> bool {
+    balance
+        .balance
+        .checked_add_unsigned(balance.remote_pending_debt)
+        .and_then(|debt| debt.checked_add_unsigned(amount))
+        .and_then(|debt| debt.checked_sub_unsigned(remote_max_debt))
+        .map(|excess_debt| excess_debt <= 0)
+        .unwrap_or(false)
+}
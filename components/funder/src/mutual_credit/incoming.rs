@@ -4,14 +4,15 @@ use crypto::identity::verify_signature;
 use common::safe_arithmetic::SafeSignedArithmetic;
 
 use proto::funder::messages::{
-    CancelSendFundsOp, CollectSendFundsOp, FriendTcOp, PendingTransaction, RequestSendFundsOp,
+    exchanged_dest_currency, exchanged_dest_payment, AcceptSwapOp, CancelSendFundsOp, CancelSwapOp,
+    CollectSendFundsOp, FriendTcOp, PendingTransaction, RequestSendFundsOp, RequestSwapOp,
     ResponseSendFundsOp, TransactionStage,
 };
 use signature::signature_buff::create_response_signature_buffer;
 
 use crate::types::create_pending_transaction;
 
-use super::types::{McMutation, MutualCredit};
+use super::types::{McMutation, MutualCredit, SwapCounterLeg};
 
 #[derive(Debug)]
 pub struct IncomingResponseSendFundsOp {
@@ -38,12 +39,15 @@ pub enum IncomingMessage {
     Response(IncomingResponseSendFundsOp),
     Cancel(IncomingCancelSendFundsOp),
     Collect(IncomingCollectSendFundsOp),
+    RequestSwap(RequestSwapOp),
 }
 
 /// Resulting tasks to perform after processing an incoming operation.
 pub struct ProcessOperationOutput {
     pub incoming_message: Option<IncomingMessage>,
     pub mc_mutations: Vec<McMutation>,
+    /// Balance change to apply to another currency (Only for accepted swaps)
+    pub opt_swap_counter_leg: Option<SwapCounterLeg>,
 }
 
 #[derive(Debug)]
@@ -59,6 +63,10 @@ pub enum ProcessOperationError {
     InvalidDestPlainLock,
     NotExpectingCollect,
     DestPaymentExceedsTotal,
+    InvalidSwapCurrency,
+    InvalidSwapAmount,
+    SwapAlreadyExists,
+    SwapDoesNotExist,
 }
 
 #[derive(Debug)]
@@ -111,6 +119,9 @@ pub fn process_operation(
         FriendTcOp::CollectSendFunds(collect_send_funds) => {
             process_collect_send_funds(mutual_credit, collect_send_funds)
         }
+        FriendTcOp::RequestSwap(request_swap) => process_request_swap(mutual_credit, request_swap),
+        FriendTcOp::AcceptSwap(accept_swap) => process_accept_swap(mutual_credit, accept_swap),
+        FriendTcOp::CancelSwap(cancel_swap) => process_cancel_swap(mutual_credit, cancel_swap),
     }
 }
 
@@ -142,10 +153,12 @@ fn process_request_send_funds(
     }
 
     // Calculate amount of credits to freeze
-    let own_freeze_credits = request_send_funds
-        .dest_payment
-        .checked_add(request_send_funds.left_fees)
-        .ok_or(ProcessOperationError::CreditsCalcOverflow)?;
+    let own_freeze_credits = exchanged_dest_payment(
+        request_send_funds.dest_payment,
+        &request_send_funds.exchanges,
+    )
+    .and_then(|hop_dest_payment| hop_dest_payment.checked_add(request_send_funds.left_fees))
+    .ok_or(ProcessOperationError::CreditsCalcOverflow)?;
 
     // Make sure we can freeze the credits
     let balance = &mutual_credit.state().balance;
@@ -180,6 +193,7 @@ fn process_request_send_funds(
     let mut op_output = ProcessOperationOutput {
         incoming_message: Some(incoming_message),
         mc_mutations: Vec::new(),
+        opt_swap_counter_leg: None,
     };
 
     let mc_mutation = McMutation::InsertRemotePendingTransaction(pending_transaction);
@@ -216,7 +230,10 @@ fn process_response_send_funds(
     };
 
     let response_signature_buffer = create_response_signature_buffer(
-        &mutual_credit.state().currency,
+        exchanged_dest_currency(
+            &mutual_credit.state().currency,
+            &pending_transaction.exchanges,
+        ),
         response_send_funds.clone(),
         &pending_transaction,
     );
@@ -257,6 +274,7 @@ fn process_response_send_funds(
     Ok(ProcessOperationOutput {
         incoming_message,
         mc_mutations,
+        opt_swap_counter_leg: None,
     })
 }
 
@@ -283,10 +301,12 @@ fn process_cancel_send_funds(
     mutual_credit.mutate(&mc_mutation);
     mc_mutations.push(mc_mutation);
 
-    let freeze_credits = pending_transaction
-        .dest_payment
-        .checked_add(pending_transaction.left_fees)
-        .unwrap();
+    let freeze_credits = exchanged_dest_payment(
+        pending_transaction.dest_payment,
+        &pending_transaction.exchanges,
+    )
+    .and_then(|hop_dest_payment| hop_dest_payment.checked_add(pending_transaction.left_fees))
+    .unwrap();
 
     // Decrease frozen credits:
     let new_local_pending_debt = mutual_credit
@@ -309,6 +329,7 @@ fn process_cancel_send_funds(
     Ok(ProcessOperationOutput {
         incoming_message,
         mc_mutations,
+        opt_swap_counter_leg: None,
     })
 }
 
@@ -342,10 +363,12 @@ fn process_collect_send_funds(
     }
 
     // Calculate amount of credits that were frozen:
-    let freeze_credits = pending_transaction
-        .dest_payment
-        .checked_add(pending_transaction.left_fees)
-        .unwrap();
+    let freeze_credits = exchanged_dest_payment(
+        pending_transaction.dest_payment,
+        &pending_transaction.exchanges,
+    )
+    .and_then(|hop_dest_payment| hop_dest_payment.checked_add(pending_transaction.left_fees))
+    .unwrap();
     // Note: The unwrap() above should never fail, because this was already checked during the
     // request message processing.
 
@@ -388,5 +411,106 @@ fn process_collect_send_funds(
     Ok(ProcessOperationOutput {
         incoming_message,
         mc_mutations,
+        opt_swap_counter_leg: None,
+    })
+}
+
+/// Process an incoming RequestSwapOp.
+/// The remote side offers to give credits in the currency of this mutual credit,
+/// in return for credits of another currency.
+fn process_request_swap(
+    mutual_credit: &mut MutualCredit,
+    request_swap: RequestSwapOp,
+) -> Result<ProcessOperationOutput, ProcessOperationError> {
+    if request_swap.dest_currency == mutual_credit.state().currency {
+        return Err(ProcessOperationError::InvalidSwapCurrency);
+    }
+
+    if request_swap.src_amount == 0 || request_swap.dest_amount == 0 {
+        return Err(ProcessOperationError::InvalidSwapAmount);
+    }
+
+    // Make sure that we don't have this swap as a pending swap already:
+    if mutual_credit
+        .state()
+        .pending_swaps
+        .contains_key(&request_swap.swap_id)
+    {
+        return Err(ProcessOperationError::SwapAlreadyExists);
+    }
+
+    // Nothing happens to the balance until the swap is accepted:
+    let mc_mutation = McMutation::InsertRemotePendingSwap(request_swap.clone());
+    mutual_credit.mutate(&mc_mutation);
+
+    Ok(ProcessOperationOutput {
+        incoming_message: Some(IncomingMessage::RequestSwap(request_swap)),
+        mc_mutations: vec![mc_mutation],
+        opt_swap_counter_leg: None,
+    })
+}
+
+/// Process an incoming AcceptSwapOp.
+/// The remote side accepted a swap we have proposed. We give credits in the currency of this
+/// mutual credit, and receive credits in the swap's destination currency.
+fn process_accept_swap(
+    mutual_credit: &mut MutualCredit,
+    accept_swap: AcceptSwapOp,
+) -> Result<ProcessOperationOutput, ProcessOperationError> {
+    let request_swap = mutual_credit
+        .state()
+        .pending_swaps
+        .local
+        .get(&accept_swap.swap_id)
+        .ok_or(ProcessOperationError::SwapDoesNotExist)?
+        .clone();
+
+    let new_balance = mutual_credit
+        .state()
+        .balance
+        .balance
+        .checked_sub_unsigned(request_swap.src_amount)
+        .ok_or(ProcessOperationError::CreditsCalcOverflow)?;
+
+    let mut mc_mutations = Vec::new();
+
+    let mc_mutation = McMutation::RemoveLocalPendingSwap(accept_swap.swap_id);
+    mutual_credit.mutate(&mc_mutation);
+    mc_mutations.push(mc_mutation);
+
+    let mc_mutation = McMutation::SetBalance(new_balance);
+    mutual_credit.mutate(&mc_mutation);
+    mc_mutations.push(mc_mutation);
+
+    Ok(ProcessOperationOutput {
+        incoming_message: None,
+        mc_mutations,
+        opt_swap_counter_leg: Some(SwapCounterLeg::Increase((
+            request_swap.dest_currency,
+            request_swap.dest_amount,
+        ))),
+    })
+}
+
+/// Process an incoming CancelSwapOp.
+/// The remote side either declines a swap we have proposed, or withdraws its own proposal.
+fn process_cancel_swap(
+    mutual_credit: &mut MutualCredit,
+    cancel_swap: CancelSwapOp,
+) -> Result<ProcessOperationOutput, ProcessOperationError> {
+    let pending_swaps = &mutual_credit.state().pending_swaps;
+    let mc_mutation = if pending_swaps.local.contains_key(&cancel_swap.swap_id) {
+        McMutation::RemoveLocalPendingSwap(cancel_swap.swap_id)
+    } else if pending_swaps.remote.contains_key(&cancel_swap.swap_id) {
+        McMutation::RemoveRemotePendingSwap(cancel_swap.swap_id)
+    } else {
+        return Err(ProcessOperationError::SwapDoesNotExist);
+    };
+    mutual_credit.mutate(&mc_mutation);
+
+    Ok(ProcessOperationOutput {
+        incoming_message: None,
+        mc_mutations: vec![mc_mutation],
+        opt_swap_counter_leg: None,
     })
 }
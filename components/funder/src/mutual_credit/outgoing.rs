@@ -4,18 +4,19 @@ use crypto::identity::verify_signature;
 use common::safe_arithmetic::SafeSignedArithmetic;
 
 use proto::funder::messages::{
-    CancelSendFundsOp, CollectSendFundsOp, FriendTcOp, RequestSendFundsOp, ResponseSendFundsOp,
+    exchanged_dest_currency, exchanged_dest_payment, AcceptSwapOp, CancelSendFundsOp, CancelSwapOp,
+    CollectSendFundsOp, FriendTcOp, RequestSendFundsOp, RequestSwapOp, ResponseSendFundsOp,
     TransactionStage,
 };
 use signature::signature_buff::create_response_signature_buffer;
 
 use crate::types::create_pending_transaction;
 
-use super::types::{McMutation, MutualCredit};
+use super::types::{McMutation, MutualCredit, SwapCounterLeg};
 
 /// Processes outgoing funds for a token channel.
 /// Used to batch as many funds as possible.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct OutgoingMc {
     mutual_credit: MutualCredit,
 }
@@ -34,6 +35,18 @@ pub enum QueueOperationError {
     InvalidSrcPlainLock,
     InvalidDestPlainLock,
     DestPaymentExceedsTotal,
+    InvalidSwapCurrency,
+    InvalidSwapAmount,
+    SwapAlreadyExists,
+    SwapDoesNotExist,
+}
+
+/// Resulting tasks to perform after queueing an outgoing operation.
+#[derive(Debug)]
+pub struct QueueOperationOutput {
+    pub mc_mutations: Vec<McMutation>,
+    /// Balance change to apply to another currency (Only for accepted swaps)
+    pub opt_swap_counter_leg: Option<SwapCounterLeg>,
 }
 
 /// A wrapper over a token channel, accumulating operations to be sent as one transaction.
@@ -48,22 +61,29 @@ impl OutgoingMc {
     pub fn queue_operation(
         &mut self,
         operation: &FriendTcOp,
-    ) -> Result<Vec<McMutation>, QueueOperationError> {
+    ) -> Result<QueueOperationOutput, QueueOperationError> {
         // TODO: Maybe remove clone from here later:
-        match operation.clone() {
+        let mc_mutations = match operation.clone() {
             FriendTcOp::RequestSendFunds(request_send_funds) => {
-                self.queue_request_send_funds(request_send_funds)
+                self.queue_request_send_funds(request_send_funds)?
             }
             FriendTcOp::ResponseSendFunds(response_send_funds) => {
-                self.queue_response_send_funds(response_send_funds)
+                self.queue_response_send_funds(response_send_funds)?
             }
             FriendTcOp::CancelSendFunds(cancel_send_funds) => {
-                self.queue_cancel_send_funds(cancel_send_funds)
+                self.queue_cancel_send_funds(cancel_send_funds)?
             }
             FriendTcOp::CollectSendFunds(collect_send_funds) => {
-                self.queue_collect_send_funds(collect_send_funds)
+                self.queue_collect_send_funds(collect_send_funds)?
             }
-        }
+            FriendTcOp::RequestSwap(request_swap) => self.queue_request_swap(request_swap)?,
+            FriendTcOp::AcceptSwap(accept_swap) => return self.queue_accept_swap(accept_swap),
+            FriendTcOp::CancelSwap(cancel_swap) => self.queue_cancel_swap(cancel_swap)?,
+        };
+        Ok(QueueOperationOutput {
+            mc_mutations,
+            opt_swap_counter_leg: None,
+        })
     }
 
     fn queue_request_send_funds(
@@ -86,10 +106,12 @@ impl OutgoingMc {
         */
 
         // Calculate amount of credits to freeze
-        let own_freeze_credits = request_send_funds
-            .dest_payment
-            .checked_add(request_send_funds.left_fees)
-            .ok_or(QueueOperationError::CreditsCalcOverflow)?;
+        let own_freeze_credits = exchanged_dest_payment(
+            request_send_funds.dest_payment,
+            &request_send_funds.exchanges,
+        )
+        .and_then(|hop_dest_payment| hop_dest_payment.checked_add(request_send_funds.left_fees))
+        .ok_or(QueueOperationError::CreditsCalcOverflow)?;
 
         let balance = &self.mutual_credit.state().balance;
 
@@ -139,7 +161,10 @@ impl OutgoingMc {
 
         // verify signature:
         let response_signature_buffer = create_response_signature_buffer(
-            &self.mutual_credit.state().currency,
+            exchanged_dest_currency(
+                &self.mutual_credit.state().currency,
+                &pending_transaction.exchanges,
+            ),
             response_send_funds.clone(),
             &pending_transaction,
         );
@@ -194,10 +219,12 @@ impl OutgoingMc {
             .get(&cancel_send_funds.request_id)
             .ok_or(QueueOperationError::RequestDoesNotExist)?;
 
-        let freeze_credits = pending_transaction
-            .dest_payment
-            .checked_add(pending_transaction.left_fees)
-            .unwrap();
+        let freeze_credits = exchanged_dest_payment(
+            pending_transaction.dest_payment,
+            &pending_transaction.exchanges,
+        )
+        .and_then(|hop_dest_payment| hop_dest_payment.checked_add(pending_transaction.left_fees))
+        .unwrap();
 
         // Remove entry from remote hashmap:
         let mut mc_mutations = Vec::new();
@@ -252,10 +279,12 @@ impl OutgoingMc {
         }
 
         // Calculate amount of credits that were frozen:
-        let freeze_credits = pending_transaction
-            .dest_payment
-            .checked_add(pending_transaction.left_fees)
-            .unwrap();
+        let freeze_credits = exchanged_dest_payment(
+            pending_transaction.dest_payment,
+            &pending_transaction.exchanges,
+        )
+        .and_then(|hop_dest_payment| hop_dest_payment.checked_add(pending_transaction.left_fees))
+        .unwrap();
 
         // Remove entry from remote_pending hashmap:
         let mut mc_mutations = Vec::new();
@@ -294,4 +323,110 @@ impl OutgoingMc {
 
         Ok(mc_mutations)
     }
+
+    fn queue_request_swap(
+        &mut self,
+        request_swap: RequestSwapOp,
+    ) -> Result<Vec<McMutation>, QueueOperationError> {
+        if request_swap.dest_currency == self.mutual_credit.state().currency {
+            return Err(QueueOperationError::InvalidSwapCurrency);
+        }
+
+        if request_swap.src_amount == 0 || request_swap.dest_amount == 0 {
+            return Err(QueueOperationError::InvalidSwapAmount);
+        }
+
+        // Make sure that we don't have this swap as a pending swap already:
+        if self
+            .mutual_credit
+            .state()
+            .pending_swaps
+            .contains_key(&request_swap.swap_id)
+        {
+            return Err(QueueOperationError::SwapAlreadyExists);
+        }
+
+        let mc_mutation = McMutation::InsertLocalPendingSwap(request_swap);
+        self.mutual_credit.mutate(&mc_mutation);
+
+        Ok(vec![mc_mutation])
+    }
+
+    fn queue_accept_swap(
+        &mut self,
+        accept_swap: AcceptSwapOp,
+    ) -> Result<QueueOperationOutput, QueueOperationError> {
+        let request_swap = self
+            .mutual_credit
+            .state()
+            .pending_swaps
+            .remote
+            .get(&accept_swap.swap_id)
+            .ok_or(QueueOperationError::SwapDoesNotExist)?
+            .clone();
+
+        // The remote side gives us credits in the currency of this mutual credit:
+        let new_balance = self
+            .mutual_credit
+            .state()
+            .balance
+            .balance
+            .checked_add_unsigned(request_swap.src_amount)
+            .ok_or(QueueOperationError::CreditsCalcOverflow)?;
+
+        let mut mc_mutations = Vec::new();
+
+        let mc_mutation = McMutation::RemoveRemotePendingSwap(accept_swap.swap_id);
+        self.mutual_credit.mutate(&mc_mutation);
+        mc_mutations.push(mc_mutation);
+
+        let mc_mutation = McMutation::SetBalance(new_balance);
+        self.mutual_credit.mutate(&mc_mutation);
+        mc_mutations.push(mc_mutation);
+
+        // In return, we give credits in the destination currency:
+        Ok(QueueOperationOutput {
+            mc_mutations,
+            opt_swap_counter_leg: Some(SwapCounterLeg::Decrease((
+                request_swap.dest_currency,
+                request_swap.dest_amount,
+            ))),
+        })
+    }
+
+    /// Apply the other leg of an accepted swap (Queued through the mutual credit of another
+    /// currency) to the balance of this mutual credit.
+    pub fn queue_swap_counter_leg(
+        &mut self,
+        swap_counter_leg: &SwapCounterLeg,
+    ) -> Result<Vec<McMutation>, QueueOperationError> {
+        let balance = self.mutual_credit.state().balance.balance;
+        let new_balance = match swap_counter_leg {
+            SwapCounterLeg::Increase((_currency, amount)) => balance.checked_add_unsigned(*amount),
+            SwapCounterLeg::Decrease((_currency, amount)) => balance.checked_sub_unsigned(*amount),
+        }
+        .ok_or(QueueOperationError::CreditsCalcOverflow)?;
+
+        let mc_mutation = McMutation::SetBalance(new_balance);
+        self.mutual_credit.mutate(&mc_mutation);
+
+        Ok(vec![mc_mutation])
+    }
+
+    fn queue_cancel_swap(
+        &mut self,
+        cancel_swap: CancelSwapOp,
+    ) -> Result<Vec<McMutation>, QueueOperationError> {
+        let pending_swaps = &self.mutual_credit.state().pending_swaps;
+        let mc_mutation = if pending_swaps.local.contains_key(&cancel_swap.swap_id) {
+            McMutation::RemoveLocalPendingSwap(cancel_swap.swap_id)
+        } else if pending_swaps.remote.contains_key(&cancel_swap.swap_id) {
+            McMutation::RemoveRemotePendingSwap(cancel_swap.swap_id)
+        } else {
+            return Err(QueueOperationError::SwapDoesNotExist);
+        };
+        self.mutual_credit.mutate(&mc_mutation);
+
+        Ok(vec![mc_mutation])
+    }
 }
@@ -7,8 +7,8 @@ use crypto::test_utils::DummyRandom;
 
 use proto::crypto::{InvoiceId, PlainLock, PrivateKey, PublicKey, RandValue, Signature, Uid};
 use proto::funder::messages::{
-    CancelSendFundsOp, CollectSendFundsOp, Currency, FriendTcOp, FriendsRoute, RequestSendFundsOp,
-    ResponseSendFundsOp,
+    AcceptSwapOp, CancelSendFundsOp, CancelSwapOp, CollectSendFundsOp, Currency, FriendTcOp,
+    FriendsRoute, RequestSendFundsOp, RequestSwapOp, ResponseSendFundsOp,
 };
 use signature::signature_buff::create_response_signature_buffer;
 
@@ -18,20 +18,20 @@ use crate::mutual_credit::incoming::{
     process_operation, ProcessOperationError, ProcessOperationOutput,
 };
 use crate::mutual_credit::outgoing::{OutgoingMc, QueueOperationError};
-use crate::mutual_credit::types::MutualCredit;
+use crate::mutual_credit::types::{MutualCredit, SwapCounterLeg};
 
 /// Helper function for applying an outgoing operation over a token channel.
 fn apply_outgoing(
     mutual_credit: &mut MutualCredit,
     friend_tc_op: &FriendTcOp,
-) -> Result<(), QueueOperationError> {
+) -> Result<Option<SwapCounterLeg>, QueueOperationError> {
     let mut outgoing = OutgoingMc::new(mutual_credit);
-    let mutations = outgoing.queue_operation(friend_tc_op)?;
+    let output = outgoing.queue_operation(friend_tc_op)?;
 
-    for mutation in mutations {
+    for mutation in output.mc_mutations {
         mutual_credit.mutate(&mutation);
     }
-    Ok(())
+    Ok(output.opt_swap_counter_leg)
 }
 
 /// Helper function for applying an incoming operation over a token channel.
@@ -79,6 +79,7 @@ fn test_request_response_collect_send_funds() {
         total_dest_payment: 10,
         invoice_id,
         left_fees: 5,
+        exchanges: Vec::new(),
     };
 
     let pending_transaction = create_pending_transaction(&request_send_funds);
@@ -181,6 +182,7 @@ fn test_request_cancel_send_funds() {
         total_dest_payment: 10,
         invoice_id,
         left_fees: 5,
+        exchanges: Vec::new(),
     };
 
     apply_outgoing(
@@ -245,6 +247,7 @@ fn test_request_response_cancel_send_funds() {
         total_dest_payment: 10,
         invoice_id,
         left_fees: 5,
+        exchanges: Vec::new(),
     };
 
     let pending_transaction = create_pending_transaction(&request_send_funds);
@@ -305,3 +308,155 @@ fn test_request_response_cancel_send_funds() {
     assert_eq!(mutual_credit.state().balance.local_pending_debt, 0);
     assert_eq!(mutual_credit.state().balance.remote_pending_debt, 0);
 }
+
+#[test]
+fn test_request_accept_swap() {
+    let currency = Currency::try_from("FST1".to_owned()).unwrap();
+    let dest_currency = Currency::try_from("FST2".to_owned()).unwrap();
+
+    let local_public_key = PublicKey::from(&[0xaa; PublicKey::len()]);
+    let remote_public_key = PublicKey::from(&[0xbb; PublicKey::len()]);
+    let balance = 0;
+    let mut mutual_credit =
+        MutualCredit::new(&local_public_key, &remote_public_key, &currency, balance);
+
+    // -----[RequestSwap]--------
+    // --------------------------
+    let swap_id = Uid::from(&[4; Uid::len()]);
+    let request_swap = RequestSwapOp {
+        swap_id: swap_id.clone(),
+        src_amount: 20,
+        dest_currency: dest_currency.clone(),
+        dest_amount: 10,
+    };
+
+    let opt_swap_counter_leg =
+        apply_outgoing(&mut mutual_credit, &FriendTcOp::RequestSwap(request_swap)).unwrap();
+    assert!(opt_swap_counter_leg.is_none());
+
+    // A proposal does not change the balance:
+    assert_eq!(mutual_credit.state().balance.balance, 0);
+    assert!(mutual_credit
+        .state()
+        .pending_swaps
+        .local
+        .contains_key(&swap_id));
+
+    // Swapping a currency with itself is not allowed:
+    let request_swap = RequestSwapOp {
+        swap_id: Uid::from(&[5; Uid::len()]),
+        src_amount: 20,
+        dest_currency: currency.clone(),
+        dest_amount: 10,
+    };
+    assert!(apply_outgoing(&mut mutual_credit, &FriendTcOp::RequestSwap(request_swap)).is_err());
+
+    // -----[AcceptSwap]--------
+    // -------------------------
+    let accept_swap = AcceptSwapOp {
+        swap_id: swap_id.clone(),
+    };
+    let output =
+        apply_incoming(&mut mutual_credit, FriendTcOp::AcceptSwap(accept_swap), 100).unwrap();
+
+    // We gave 20 credits of this currency, and receive 10 credits of the destination currency:
+    assert_eq!(mutual_credit.state().balance.balance, -20);
+    assert!(mutual_credit.state().pending_swaps.local.is_empty());
+    assert_eq!(
+        output.opt_swap_counter_leg,
+        Some(SwapCounterLeg::Increase((dest_currency, 10)))
+    );
+
+    // The counter leg of a swap must not overflow the balance:
+    let mut outgoing = OutgoingMc::new(&mutual_credit);
+    let swap_counter_leg = SwapCounterLeg::Decrease((currency.clone(), u128::max_value()));
+    assert!(outgoing.queue_swap_counter_leg(&swap_counter_leg).is_err());
+    let swap_counter_leg = SwapCounterLeg::Decrease((currency.clone(), 5));
+    assert_eq!(
+        outgoing
+            .queue_swap_counter_leg(&swap_counter_leg)
+            .unwrap()
+            .len(),
+        1
+    );
+
+    // The swap can not be accepted twice:
+    let accept_swap = AcceptSwapOp { swap_id };
+    assert!(apply_incoming(&mut mutual_credit, FriendTcOp::AcceptSwap(accept_swap), 100).is_err());
+}
+
+#[test]
+fn test_remote_request_swap_accept_cancel() {
+    let currency = Currency::try_from("FST1".to_owned()).unwrap();
+    let dest_currency = Currency::try_from("FST2".to_owned()).unwrap();
+
+    let local_public_key = PublicKey::from(&[0xaa; PublicKey::len()]);
+    let remote_public_key = PublicKey::from(&[0xbb; PublicKey::len()]);
+    let balance = 0;
+    let mut mutual_credit =
+        MutualCredit::new(&local_public_key, &remote_public_key, &currency, balance);
+
+    // -----[RequestSwap]--------
+    // --------------------------
+    let swap_id1 = Uid::from(&[6; Uid::len()]);
+    let swap_id2 = Uid::from(&[7; Uid::len()]);
+    for swap_id in &[&swap_id1, &swap_id2] {
+        let request_swap = RequestSwapOp {
+            swap_id: (*swap_id).clone(),
+            src_amount: 30,
+            dest_currency: dest_currency.clone(),
+            dest_amount: 15,
+        };
+        apply_incoming(
+            &mut mutual_credit,
+            FriendTcOp::RequestSwap(request_swap),
+            100,
+        )
+        .unwrap();
+    }
+    assert_eq!(mutual_credit.state().pending_swaps.remote.len(), 2);
+
+    // A swap of zero credits is not allowed:
+    let request_swap = RequestSwapOp {
+        swap_id: Uid::from(&[8; Uid::len()]),
+        src_amount: 0,
+        dest_currency: dest_currency.clone(),
+        dest_amount: 15,
+    };
+    assert!(apply_incoming(
+        &mut mutual_credit,
+        FriendTcOp::RequestSwap(request_swap),
+        100,
+    )
+    .is_err());
+    assert_eq!(mutual_credit.state().pending_swaps.remote.len(), 2);
+
+    // -----[AcceptSwap]--------
+    // -------------------------
+    let accept_swap = AcceptSwapOp {
+        swap_id: swap_id1.clone(),
+    };
+    let opt_swap_counter_leg =
+        apply_outgoing(&mut mutual_credit, &FriendTcOp::AcceptSwap(accept_swap)).unwrap();
+
+    // The remote side gave us 30 credits, we give 15 credits of the destination currency:
+    assert_eq!(mutual_credit.state().balance.balance, 30);
+    assert_eq!(
+        opt_swap_counter_leg,
+        Some(SwapCounterLeg::Decrease((dest_currency, 15)))
+    );
+
+    // -----[CancelSwap]--------
+    // -------------------------
+    let cancel_swap = CancelSwapOp {
+        swap_id: swap_id2.clone(),
+    };
+    apply_outgoing(&mut mutual_credit, &FriendTcOp::CancelSwap(cancel_swap)).unwrap();
+
+    assert_eq!(mutual_credit.state().balance.balance, 30);
+    assert!(mutual_credit.state().pending_swaps.remote.is_empty());
+
+    // Canceling a swap that does not exist is not allowed:
+    let cancel_swap = CancelSwapOp { swap_id: swap_id2 };
+    assert!(apply_incoming(&mut mutual_credit, FriendTcOp::CancelSwap(cancel_swap), 100).is_err());
+}
@@ -4,7 +4,7 @@ use common::safe_arithmetic::SafeSignedArithmetic;
 use common::ser_utils::{ser_b64, ser_map_b64_any, ser_string};
 
 use proto::crypto::{PublicKey, Uid};
-use proto::funder::messages::{Currency, PendingTransaction, TransactionStage};
+use proto::funder::messages::{Currency, PendingTransaction, RequestSwapOp, TransactionStage};
 
 /*
 // TODO: Where do we need to check this value?
@@ -68,6 +68,39 @@ impl McPendingTransactions {
     }
 }
 
+#[derive(Arbitrary, Clone, Serialize, Deserialize, Debug, PartialEq, Eq, Default)]
+pub struct McPendingSwaps {
+    /// Swaps that were proposed locally and were not yet accepted or canceled
+    #[serde(with = "ser_map_b64_any")]
+    pub local: ImHashMap<Uid, RequestSwapOp>,
+    /// Swaps that were proposed remotely and were not yet accepted or canceled
+    #[serde(with = "ser_map_b64_any")]
+    pub remote: ImHashMap<Uid, RequestSwapOp>,
+}
+
+impl McPendingSwaps {
+    fn new() -> McPendingSwaps {
+        McPendingSwaps {
+            local: ImHashMap::new(),
+            remote: ImHashMap::new(),
+        }
+    }
+
+    pub fn contains_key(&self, swap_id: &Uid) -> bool {
+        self.local.contains_key(swap_id) || self.remote.contains_key(swap_id)
+    }
+}
+
+/// A change of balance in another currency of the same token channel.
+/// Produced when a swap is accepted, as a swap moves balance in two currencies at once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SwapCounterLeg {
+    /// Increase our balance in the given currency
+    Increase((Currency, u128)),
+    /// Decrease our balance in the given currency
+    Decrease((Currency, u128)),
+}
+
 #[derive(Arbitrary, Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
 pub struct MutualCreditState {
     /// Public identities of local and remote side
@@ -78,6 +111,9 @@ pub struct MutualCreditState {
     pub balance: McBalance,
     /// Requests in progress
     pub pending_transactions: McPendingTransactions,
+    /// Swaps in progress (Swaps are kept at the mutual credit of the giving side's currency)
+    #[serde(default)]
+    pub pending_swaps: McPendingSwaps,
 }
 
 #[derive(Arbitrary, Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
@@ -96,6 +132,10 @@ pub enum McMutation {
     SetRemotePendingTransactionStage((Uid, TransactionStage)),
     SetLocalPendingDebt(u128),
     SetRemotePendingDebt(u128),
+    InsertLocalPendingSwap(RequestSwapOp),
+    RemoveLocalPendingSwap(Uid),
+    InsertRemotePendingSwap(RequestSwapOp),
+    RemoveRemotePendingSwap(Uid),
 }
 
 impl MutualCredit {
@@ -115,6 +155,7 @@ impl MutualCredit {
                 currency: currency.clone(),
                 balance: McBalance::new(balance),
                 pending_transactions: McPendingTransactions::new(),
+                pending_swaps: McPendingSwaps::new(),
             },
         }
     }
@@ -164,6 +205,16 @@ impl MutualCredit {
             McMutation::SetRemotePendingDebt(remote_pending_debt) => {
                 self.set_remote_pending_debt(*remote_pending_debt)
             }
+            McMutation::InsertLocalPendingSwap(request_swap) => {
+                self.insert_local_pending_swap(request_swap)
+            }
+            McMutation::RemoveLocalPendingSwap(swap_id) => self.remove_local_pending_swap(swap_id),
+            McMutation::InsertRemotePendingSwap(request_swap) => {
+                self.insert_remote_pending_swap(request_swap)
+            }
+            McMutation::RemoveRemotePendingSwap(swap_id) => {
+                self.remove_remote_pending_swap(swap_id)
+            }
         }
     }
 
@@ -201,6 +252,28 @@ impl MutualCredit {
         self.state.balance.local_pending_debt = local_pending_debt;
     }
 
+    fn insert_local_pending_swap(&mut self, request_swap: &RequestSwapOp) {
+        self.state
+            .pending_swaps
+            .local
+            .insert(request_swap.swap_id.clone(), request_swap.clone());
+    }
+
+    fn remove_local_pending_swap(&mut self, swap_id: &Uid) {
+        let _ = self.state.pending_swaps.local.remove(swap_id);
+    }
+
+    fn insert_remote_pending_swap(&mut self, request_swap: &RequestSwapOp) {
+        self.state
+            .pending_swaps
+            .remote
+            .insert(request_swap.swap_id.clone(), request_swap.clone());
+    }
+
+    fn remove_remote_pending_swap(&mut self, swap_id: &Uid) {
+        let _ = self.state.pending_swaps.remote.remove(swap_id);
+    }
+
     fn set_local_pending_transaction_stage(&mut self, request_id: &Uid, stage: TransactionStage) {
         self.state
             .pending_transactions
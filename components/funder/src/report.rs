@@ -4,15 +4,16 @@ use signature::canonical::CanonicalSerialize;
 
 use proto::report::messages::{
     AddFriendReport, ChannelConsistentReport, ChannelInconsistentReport, ChannelStatusReport,
-    CurrencyConfigReport, CurrencyReport, FriendLivenessReport, FriendReport, FriendReportMutation,
+    ClosedFriendReport, CurrencyConfigReport, CurrencyReport, ExchangeRateReport,
+    FriendCloseStatusReport, FriendLivenessReport, FriendReport, FriendReportMutation,
     FriendStatusReport, FunderReport, FunderReportMutation, McBalanceReport, MoveTokenHashedReport,
-    ResetTermsReport,
+    RelayHealthReport, RemoveExchangeRateReport, ResetTermsReport,
 };
 
 use crate::types::MoveTokenHashed;
 
 use crate::ephemeral::{Ephemeral, EphemeralMutation};
-use crate::friend::{ChannelStatus, FriendMutation, FriendState};
+use crate::friend::{ChannelStatus, FriendCloseStatus, FriendMutation, FriendState};
 use crate::liveness::LivenessMutation;
 use crate::mutual_credit::types::McBalance;
 use crate::state::{FunderMutation, FunderState};
@@ -38,6 +39,18 @@ impl From<&MoveTokenHashed> for MoveTokenHashedReport {
     }
 }
 
+impl From<&FriendCloseStatus> for FriendCloseStatusReport {
+    fn from(close_status: &FriendCloseStatus) -> FriendCloseStatusReport {
+        match close_status {
+            FriendCloseStatus::Open => FriendCloseStatusReport::Open,
+            FriendCloseStatus::Closing(_) => FriendCloseStatusReport::Closing,
+            FriendCloseStatus::Closed(friend_settlement) => {
+                FriendCloseStatusReport::Closed(friend_settlement.clone())
+            }
+        }
+    }
+}
+
 impl<B> From<&ChannelStatus<B>> for ChannelStatusReport
 where
     B: Clone + CanonicalSerialize,
@@ -85,6 +98,7 @@ where
 fn create_friend_report<B>(
     friend_state: &FriendState<B>,
     friend_liveness: &FriendLivenessReport,
+    relays_health: &[RelayHealthReport],
 ) -> FriendReport<B>
 where
     B: Clone + CanonicalSerialize,
@@ -113,6 +127,8 @@ where
         liveness: friend_liveness.clone(),
         channel_status,
         status: FriendStatusReport::from(&friend_state.status),
+        relays_health: relays_health.to_vec(),
+        close_status: FriendCloseStatusReport::from(&friend_state.close_status),
     }
 }
 
@@ -127,14 +143,42 @@ where
         } else {
             FriendLivenessReport::Offline
         };
-        let friend_report = create_friend_report(&friend_state, &friend_liveness);
+        let relays_health = ephemeral
+            .relays_health
+            .get(friend_public_key)
+            .cloned()
+            .unwrap_or_default();
+        let friend_report = create_friend_report(&friend_state, &friend_liveness, &relays_health);
         friends.insert(friend_public_key.clone(), friend_report);
     }
 
+    let mut exchange_rates = Vec::new();
+    for (src_currency, dest_rates) in &funder_state.exchange_rates {
+        for (dest_currency, rate) in dest_rates {
+            exchange_rates.push(ExchangeRateReport {
+                src_currency: src_currency.clone(),
+                dest_currency: dest_currency.clone(),
+                rate: rate.clone(),
+            });
+        }
+    }
+
+    let closed_friends = funder_state
+        .closed_friends
+        .iter()
+        .map(|(friend_public_key, closed_friend)| ClosedFriendReport {
+            friend_public_key: friend_public_key.clone(),
+            name: closed_friend.name.clone(),
+            settlement: closed_friend.settlement.clone(),
+        })
+        .collect();
+
     FunderReport {
         local_public_key: funder_state.local_public_key.clone(),
         relays: funder_state.relays.clone().into_iter().collect(),
         friends: friends.into_iter().collect(),
+        exchange_rates,
+        closed_friends,
     }
 }
 
@@ -173,6 +217,8 @@ where
         | FriendMutation::PopFrontPendingBackwardsOp
         | FriendMutation::PushBackPendingUserRequest(_)
         | FriendMutation::PopFrontPendingUserRequest
+        | FriendMutation::PushBackPendingSwapOp(_)
+        | FriendMutation::PopFrontPendingSwapOp
         | FriendMutation::RemovePendingRequests
         | FriendMutation::RemovePendingRequestsCurrency(_)
         | FriendMutation::RemovePendingUserRequestsCurrency(_) => vec![],
@@ -196,7 +242,12 @@ where
         FriendMutation::RemoveCurrencyConfig(currency) => {
             vec![FriendReportMutation::RemoveCurrencyConfig(currency.clone())]
         }
-        FriendMutation::SetSentLocalRelays(_) => vec![],
+        FriendMutation::SetSentLocalRelays(_)
+        | FriendMutation::SetRemoteDirectAddresses(_)
+        | FriendMutation::SetSentDirectAddresses(_) => vec![],
+        FriendMutation::SetCloseStatus(close_status) => vec![FriendReportMutation::SetCloseStatus(
+            FriendCloseStatusReport::from(close_status),
+        )],
         FriendMutation::SetInconsistent(_) | FriendMutation::SetConsistent(_) => {
             let channel_status_report = ChannelStatusReport::from(&friend_after.channel_status);
             let set_channel_status = FriendReportMutation::SetChannelStatus(channel_status_report);
@@ -268,15 +319,41 @@ where
                 friend_public_key.clone(),
             )]
         }
+        FunderMutation::SetExchangeRate((src_currency, dest_currency, rate)) => {
+            vec![FunderReportMutation::SetExchangeRate(ExchangeRateReport {
+                src_currency: src_currency.clone(),
+                dest_currency: dest_currency.clone(),
+                rate: rate.clone(),
+            })]
+        }
+        FunderMutation::RemoveExchangeRate((src_currency, dest_currency)) => {
+            vec![FunderReportMutation::RemoveExchangeRate(
+                RemoveExchangeRateReport {
+                    src_currency: src_currency.clone(),
+                    dest_currency: dest_currency.clone(),
+                },
+            )]
+        }
+        FunderMutation::AddClosedFriend((friend_public_key, closed_friend)) => {
+            vec![FunderReportMutation::AddClosedFriend(ClosedFriendReport {
+                friend_public_key: friend_public_key.clone(),
+                name: closed_friend.name.clone(),
+                settlement: closed_friend.settlement.clone(),
+            })]
+        }
         FunderMutation::AddInvoice(_)
+        | FunderMutation::AddPushInvoice(_)
         | FunderMutation::AddIncomingTransaction(_)
         | FunderMutation::SetInvoiceSrcHashedLock(_)
+        | FunderMutation::SetInvoiceHold(_)
         | FunderMutation::RemoveInvoice(_)
         | FunderMutation::AddTransaction(_)
         | FunderMutation::RemoveTransaction(_)
         | FunderMutation::SetTransactionResponse(_)
         | FunderMutation::UpdatePayment(_)
-        | FunderMutation::RemovePayment(_) => vec![],
+        | FunderMutation::RemovePayment(_)
+        | FunderMutation::EnablePushPayments(_)
+        | FunderMutation::DisablePushPayments(_) => vec![],
     }
 }
 
@@ -325,6 +402,25 @@ where
                 ))]
             }
         },
+        EphemeralMutation::SetRelaysHealth((public_key, relays_health)) => {
+            if !funder_state.friends.contains_key(public_key) {
+                // We ignore the relays health if friend does not exist.
+                return Vec::new();
+            }
+            let friend_report_mutation =
+                FriendReportMutation::SetRelaysHealth(relays_health.clone());
+            vec![FunderReportMutation::PkFriendReportMutation((
+                public_key.clone(),
+                friend_report_mutation,
+            ))]
+        }
+        // Relays health is cleared only for removed friends, which have no report:
+        EphemeralMutation::ClearRelaysHealth(_)
+        | EphemeralMutation::Tick
+        | EphemeralMutation::SetRemoteSwapDeadline(_)
+        | EphemeralMutation::RemoveRemoteSwapDeadline(_)
+        | EphemeralMutation::SetInvoiceHoldDeadline(_)
+        | EphemeralMutation::RemoveInvoiceHoldDeadline(_) => Vec::new(),
     }
 }
 
@@ -369,6 +465,7 @@ mod test {
         let move_token = MoveToken::<u32> {
             currencies_operations: Vec::new(),
             opt_local_relays: None,
+            opt_direct_addresses: None,
             opt_active_currencies: None,
             info_hash: hash_token_info(&token_info),
             old_token: Signature::from(&[0x55; Signature::len()]),
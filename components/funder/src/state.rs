@@ -5,13 +5,17 @@ use std::collections::HashMap as ImHashMap;
 use im::hashset::HashSet as ImHashSet;
 use im::vector::Vector as ImVec;
 
-use common::ser_utils::{ser_b64, ser_map_b64_any, ser_option_b64, ser_string};
+use common::ser_utils::{
+    ser_b64, ser_map_b64_any, ser_map_str_any, ser_map_str_str, ser_option_b64, ser_string,
+};
 use signature::canonical::CanonicalSerialize;
 
 use proto::crypto::{HashedLock, InvoiceId, PaymentId, PlainLock, PublicKey, Uid};
 
 use proto::app_server::messages::NamedRelayAddress;
-use proto::funder::messages::{AddFriend, Currency, Receipt, ResponseSendFundsOp};
+use proto::funder::messages::{
+    AddFriend, Currency, ExchangeRate, FriendSettlement, Receipt, ResponseSendFundsOp,
+};
 
 use crate::friend::{FriendMutation, FriendState};
 
@@ -38,6 +42,26 @@ pub struct FunderState<B: Clone> {
     /// Ongoing payments (For which this node is the buyer):
     #[serde(with = "ser_map_b64_any")]
     pub payments: ImHashMap<PaymentId, Payment>,
+    /// Currencies in which we accept push payments (Payments without a prior invoice),
+    /// mapped to the maximum total payment we accept for a single push payment.
+    #[serde(default, with = "ser_map_str_str")]
+    pub push_payments: ImHashMap<Currency, u128>,
+    /// Exchange rates for forwarding requests between currencies:
+    /// src_currency -> dest_currency -> rate
+    #[serde(default, with = "ser_map_str_any")]
+    pub exchange_rates: ImHashMap<Currency, ImHashMap<Currency, ExchangeRate>>,
+    /// Final settlements with friends that were removed after cooperatively closing the channel
+    #[serde(default, with = "ser_map_b64_any")]
+    pub closed_friends: ImHashMap<PublicKey, ClosedFriend>,
+}
+
+/// A friend that was removed after cooperatively closing the channel.
+#[derive(Arbitrary, Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+pub struct ClosedFriend {
+    /// Name of the friend at the time the channel was closed
+    pub name: String,
+    /// Final balances, signed by both sides
+    pub settlement: FriendSettlement,
 }
 
 /// A state of a Payment where new transactions may still be added.
@@ -106,6 +130,19 @@ pub struct OpenInvoice {
     /// Multiple transactions are possible for a single invoice in case of a multi-route payment.
     // TODO: Add serde hint
     pub incoming_transactions: ImHashSet<Uid>,
+    /// Was this invoice created automatically for an incoming push payment?
+    /// Push invoices are collected as soon as they are fully paid, without waiting for a Commit.
+    #[serde(default)]
+    pub is_push: bool,
+    /// Maximum hold duration (In timer ticks) for a held invoice. A held invoice keeps the funds
+    /// locked along the route until it is committed or canceled, and is canceled automatically
+    /// when the hold duration passes. Push invoices are held when they are created, so that a
+    /// push payment that is never fully paid does not keep the funds locked forever.
+    ///
+    /// This value is set once and never counted down. The deadline itself is kept in the
+    /// ephemeral state, so the hold duration starts over if the node is restarted.
+    #[serde(default)]
+    pub opt_hold_ticks: Option<u64>,
 }
 
 impl OpenInvoice {
@@ -116,6 +153,8 @@ impl OpenInvoice {
             dest_plain_lock,
             opt_src_hashed_lock: None,
             incoming_transactions: ImHashSet::new(),
+            is_push: false,
+            opt_hold_ticks: None,
         }
     }
 }
@@ -138,14 +177,21 @@ pub enum FunderMutation<B: Clone> {
     AddFriend(AddFriend<B>),
     RemoveFriend(PublicKey),
     AddInvoice((InvoiceId, Currency, u128, PlainLock)), // (invoice_id, currency, total_dest_payment, dest_plain_lock)
-    AddIncomingTransaction((InvoiceId, Uid)),           // (invoice_id, request_id)
-    SetInvoiceSrcHashedLock((InvoiceId, HashedLock)),   // (invoice_id, src_hashed_lock)
+    AddPushInvoice((InvoiceId, Currency, u128, PlainLock)), // (invoice_id, currency, total_dest_payment, dest_plain_lock)
+    AddIncomingTransaction((InvoiceId, Uid)),               // (invoice_id, request_id)
+    SetInvoiceSrcHashedLock((InvoiceId, HashedLock)),       // (invoice_id, src_hashed_lock)
+    SetInvoiceHold((InvoiceId, u64)),                       // (invoice_id, hold_ticks)
     RemoveInvoice(InvoiceId),
     AddTransaction((Uid, PaymentId)), // (request_id, payment_id)
     SetTransactionResponse(ResponseSendFundsOp), // (request_id, response_send_funds)
     RemoveTransaction(Uid),           // request_id
     UpdatePayment((PaymentId, Payment)),
     RemovePayment(PaymentId),
+    EnablePushPayments((Currency, u128)), // (currency, max_total_dest_payment)
+    DisablePushPayments(Currency),
+    SetExchangeRate((Currency, Currency, ExchangeRate)), // (src_currency, dest_currency, rate)
+    RemoveExchangeRate((Currency, Currency)),            // (src_currency, dest_currency)
+    AddClosedFriend((PublicKey, ClosedFriend)),
 }
 
 impl<B> FunderState<B>
@@ -163,6 +209,9 @@ where
             open_invoices: ImHashMap::new(),
             open_transactions: ImHashMap::new(),
             payments: ImHashMap::new(),
+            push_payments: ImHashMap::new(),
+            exchange_rates: ImHashMap::new(),
+            closed_friends: ImHashMap::new(),
         }
     }
 
@@ -218,6 +267,20 @@ where
                     ),
                 );
             }
+            FunderMutation::AddPushInvoice((
+                invoice_id,
+                currency,
+                total_dest_payment,
+                dest_plain_lock,
+            )) => {
+                let mut open_invoice = OpenInvoice::new(
+                    currency.clone(),
+                    *total_dest_payment,
+                    dest_plain_lock.clone(),
+                );
+                open_invoice.is_push = true;
+                self.open_invoices.insert(invoice_id.clone(), open_invoice);
+            }
             FunderMutation::AddIncomingTransaction((invoice_id, request_id)) => {
                 let open_invoice = self.open_invoices.get_mut(invoice_id).unwrap();
                 open_invoice
@@ -229,6 +292,10 @@ where
                 assert!(open_invoice.opt_src_hashed_lock.is_none());
                 open_invoice.opt_src_hashed_lock = Some(src_hashed_lock.clone());
             }
+            FunderMutation::SetInvoiceHold((invoice_id, hold_ticks)) => {
+                let open_invoice = self.open_invoices.get_mut(invoice_id).unwrap();
+                open_invoice.opt_hold_ticks = Some(*hold_ticks);
+            }
             FunderMutation::RemoveInvoice(invoice_id) => {
                 let _ = self.open_invoices.remove(invoice_id);
             }
@@ -259,6 +326,34 @@ where
             FunderMutation::RemovePayment(payment_id) => {
                 let _ = self.payments.remove(payment_id);
             }
+            FunderMutation::EnablePushPayments((currency, max_total_dest_payment)) => {
+                let _ = self
+                    .push_payments
+                    .insert(currency.clone(), *max_total_dest_payment);
+            }
+            FunderMutation::DisablePushPayments(currency) => {
+                let _ = self.push_payments.remove(currency);
+            }
+            FunderMutation::SetExchangeRate((src_currency, dest_currency, rate)) => {
+                let _ = self
+                    .exchange_rates
+                    .entry(src_currency.clone())
+                    .or_insert_with(ImHashMap::new)
+                    .insert(dest_currency.clone(), rate.clone());
+            }
+            FunderMutation::RemoveExchangeRate((src_currency, dest_currency)) => {
+                if let Some(dest_rates) = self.exchange_rates.get_mut(src_currency) {
+                    let _ = dest_rates.remove(dest_currency);
+                    if dest_rates.is_empty() {
+                        let _ = self.exchange_rates.remove(src_currency);
+                    }
+                }
+            }
+            FunderMutation::AddClosedFriend((friend_public_key, closed_friend)) => {
+                let _ = self
+                    .closed_friends
+                    .insert(friend_public_key.clone(), closed_friend.clone());
+            }
         }
     }
 }
@@ -126,6 +126,7 @@ async fn task_funder_basic(test_executor: TestExecutor) {
         },
         dest_payment: 3,
         fees: 1,
+        exchanges: Vec::new(),
     };
 
     node_controls[0]
@@ -152,6 +153,7 @@ async fn task_funder_basic(test_executor: TestExecutor) {
         },
         dest_payment: 4,
         fees: 1,
+        exchanges: Vec::new(),
     };
 
     node_controls[0]
@@ -177,6 +179,7 @@ async fn task_funder_basic(test_executor: TestExecutor) {
         },
         dest_payment: 1,
         fees: 1,
+        exchanges: Vec::new(),
     };
 
     node_controls[0]
@@ -0,0 +1,263 @@
+use std::convert::TryFrom;
+
+use common::test_executor::TestExecutor;
+
+use proto::crypto::{PublicKey, Uid};
+use proto::funder::messages::{
+    AbortCloseFriend, CloseFriend, Currency, FriendStatus, FriendSwap, FunderControl,
+};
+use proto::report::messages::{FriendCloseStatusReport, FunderReport};
+
+use signature::verify::verify_friend_settlement;
+
+use super::utils::{create_node_controls, dummy_relay_address};
+
+async fn task_funder_close(test_executor: TestExecutor) {
+    let currency1 = Currency::try_from("FST1".to_owned()).unwrap();
+    let currency2 = Currency::try_from("FST2".to_owned()).unwrap();
+
+    let num_nodes = 2;
+    let mut node_controls = create_node_controls(num_nodes, test_executor.clone()).await;
+
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    let relays0 = vec![dummy_relay_address(0)];
+    let relays1 = vec![dummy_relay_address(1)];
+    node_controls[0]
+        .add_friend(&public_keys[1], relays1, "node1")
+        .await;
+    node_controls[1]
+        .add_friend(&public_keys[0], relays0, "node0")
+        .await;
+
+    node_controls[0]
+        .set_friend_status(&public_keys[1], FriendStatus::Enabled)
+        .await;
+    node_controls[1]
+        .set_friend_status(&public_keys[0], FriendStatus::Enabled)
+        .await;
+
+    node_controls[0]
+        .set_friend_currencies(&public_keys[1], vec![currency1.clone(), currency2.clone()])
+        .await;
+    node_controls[1]
+        .set_friend_currencies(&public_keys[0], vec![currency1.clone(), currency2.clone()])
+        .await;
+
+    for currency in &[&currency1, &currency2] {
+        node_controls[0]
+            .wait_until_currency_active(&public_keys[1], currency)
+            .await;
+        node_controls[1]
+            .wait_until_currency_active(&public_keys[0], currency)
+            .await;
+
+        node_controls[0]
+            .set_remote_max_debt(&public_keys[1], currency, 100)
+            .await;
+        node_controls[1]
+            .set_remote_max_debt(&public_keys[0], currency, 100)
+            .await;
+    }
+
+    test_executor.wait().await;
+
+    // Move some credits using a swap, so that the final balances are not zero:
+    let swap_id = Uid::from(&[1u8; Uid::len()]);
+    let propose_swap = FriendSwap {
+        friend_public_key: public_keys[1].clone(),
+        swap_id: swap_id.clone(),
+        give_currency: currency1.clone(),
+        give_amount: 20,
+        take_currency: currency2.clone(),
+        take_amount: 10,
+    };
+    node_controls[0]
+        .send(FunderControl::ProposeSwap(propose_swap))
+        .await;
+
+    test_executor.wait().await;
+
+    let accept_swap = FriendSwap {
+        friend_public_key: public_keys[0].clone(),
+        swap_id,
+        give_currency: currency2.clone(),
+        give_amount: 10,
+        take_currency: currency1.clone(),
+        take_amount: 20,
+    };
+    node_controls[1]
+        .send(FunderControl::AcceptSwap(accept_swap))
+        .await;
+
+    node_controls[0]
+        .wait_friend_balance(&public_keys[1], &currency1, -20)
+        .await;
+    node_controls[1]
+        .wait_friend_balance(&public_keys[0], &currency1, 20)
+        .await;
+
+    // Node 0 closes the channel. Node 1 should cooperate automatically:
+    let close_friend = CloseFriend {
+        friend_public_key: public_keys[1].clone(),
+    };
+    node_controls[0]
+        .send(FunderControl::CloseFriend(close_friend))
+        .await;
+
+    let settlement0 = node_controls[0]
+        .wait_until_friend_closed(&public_keys[1])
+        .await;
+    let settlement1 = node_controls[1]
+        .wait_until_friend_closed(&public_keys[0])
+        .await;
+
+    // Both sides hold a valid settlement, signed by both sides:
+    assert!(verify_friend_settlement(&settlement0));
+    assert!(verify_friend_settlement(&settlement1));
+    assert_eq!(
+        settlement0.token_info,
+        settlement1.token_info.clone().flip()
+    );
+    assert_eq!(settlement0.local_signature, settlement1.remote_signature);
+    assert_eq!(settlement0.remote_signature, settlement1.local_signature);
+
+    // The settlement contains the final balances:
+    let balances0 = &settlement0.token_info.mc.balances;
+    assert_eq!(balances0.len(), 2);
+    for currency_balance_info in balances0 {
+        let expected_balance = if currency_balance_info.currency == currency1 {
+            -20
+        } else {
+            10
+        };
+        assert_eq!(currency_balance_info.balance_info.balance, expected_balance);
+    }
+
+    // No new requests are accepted from the closed friend:
+    for (i, j) in &[(0, 1), (1, 0)] {
+        let friend_report = node_controls[*i]
+            .report
+            .friends
+            .get(&public_keys[*j])
+            .unwrap();
+        assert!(friend_report
+            .currency_configs
+            .iter()
+            .all(|currency_config| !currency_config.is_open));
+    }
+
+    // On the next tick, closed friends are removed. Only the settlement is kept:
+    for (i, j, settlement) in &[(0, 1, &settlement0), (1, 0, &settlement1)] {
+        node_controls[*i].tick().await;
+        let friend_public_key = &public_keys[*j];
+        let pred = |report: &FunderReport<_>| {
+            !report.friends.contains_key(friend_public_key)
+                && report
+                    .closed_friends
+                    .iter()
+                    .any(|closed_friend| &closed_friend.friend_public_key == friend_public_key)
+        };
+        node_controls[*i].recv_until(pred).await;
+
+        let closed_friend = node_controls[*i]
+            .report
+            .closed_friends
+            .iter()
+            .find(|closed_friend| &closed_friend.friend_public_key == friend_public_key)
+            .unwrap();
+        assert_eq!(&closed_friend.settlement, *settlement);
+    }
+}
+
+#[test]
+fn test_funder_close() {
+    let test_executor = TestExecutor::new();
+    let res = test_executor.run(task_funder_close(test_executor.clone()));
+    assert!(res.is_output());
+}
+
+async fn task_funder_abort_close(test_executor: TestExecutor) {
+    let currency1 = Currency::try_from("FST1".to_owned()).unwrap();
+
+    let num_nodes = 2;
+    let mut node_controls = create_node_controls(num_nodes, test_executor.clone()).await;
+
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    let relays0 = vec![dummy_relay_address(0)];
+    let relays1 = vec![dummy_relay_address(1)];
+    node_controls[0]
+        .add_friend(&public_keys[1], relays1, "node1")
+        .await;
+    node_controls[1]
+        .add_friend(&public_keys[0], relays0, "node0")
+        .await;
+
+    node_controls[0]
+        .set_friend_status(&public_keys[1], FriendStatus::Enabled)
+        .await;
+    node_controls[1]
+        .set_friend_status(&public_keys[0], FriendStatus::Enabled)
+        .await;
+
+    node_controls[0]
+        .set_friend_currencies(&public_keys[1], vec![currency1.clone()])
+        .await;
+    node_controls[1]
+        .set_friend_currencies(&public_keys[0], vec![currency1.clone()])
+        .await;
+
+    node_controls[0]
+        .wait_until_currency_active(&public_keys[1], &currency1)
+        .await;
+    node_controls[1]
+        .wait_until_currency_active(&public_keys[0], &currency1)
+        .await;
+
+    // Node 1 goes offline, so that the close can not complete:
+    node_controls[1]
+        .set_friend_status(&public_keys[0], FriendStatus::Disabled)
+        .await;
+    test_executor.wait().await;
+
+    let close_friend = CloseFriend {
+        friend_public_key: public_keys[1].clone(),
+    };
+    node_controls[0]
+        .send(FunderControl::CloseFriend(close_friend))
+        .await;
+
+    let pred = |report: &FunderReport<_>| {
+        let friend = report.friends.get(&public_keys[1]).unwrap();
+        friend.close_status == FriendCloseStatusReport::Closing
+    };
+    node_controls[0].recv_until(pred).await;
+
+    // Node 0 changes its mind:
+    let abort_close_friend = AbortCloseFriend {
+        friend_public_key: public_keys[1].clone(),
+    };
+    node_controls[0]
+        .send(FunderControl::AbortCloseFriend(abort_close_friend))
+        .await;
+
+    let pred = |report: &FunderReport<_>| {
+        let friend = report.friends.get(&public_keys[1]).unwrap();
+        friend.close_status == FriendCloseStatusReport::Open
+    };
+    node_controls[0].recv_until(pred).await;
+}
+
+#[test]
+fn test_funder_abort_close() {
+    let test_executor = TestExecutor::new();
+    let res = test_executor.run(task_funder_abort_close(test_executor.clone()));
+    assert!(res.is_output());
+}
@@ -185,6 +185,7 @@ async fn task_funder_forward_payment(test_executor: TestExecutor) {
         },
         dest_payment: 15,
         fees: 5,
+        exchanges: Vec::new(),
     };
     node_controls[0]
         .send(FunderControl::CreateTransaction(create_transaction))
@@ -0,0 +1,244 @@
+use std::convert::TryFrom;
+
+use common::test_executor::TestExecutor;
+
+use proto::crypto::{InvoiceId, PaymentId, PublicKey, Uid};
+use proto::funder::messages::{
+    AckClosePayment, AddInvoice, Commit, CreatePayment, CreateTransaction, Currency, FriendStatus,
+    FriendsRoute, FunderControl, HoldInvoice, PaymentStatus, Rate, RequestResult, RequestsStatus,
+};
+
+use signature::verify::verify_receipt;
+
+use super::utils::{create_node_controls, dummy_relay_address, NodeControl};
+
+/// Pay an invoice of 4 credits 0 --> 1, and return the resulting commit.
+async fn pay_invoice(
+    node_controls: &mut [NodeControl<u32>],
+    currency: &Currency,
+    index: u8,
+) -> Commit {
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    // Let node 1 open an invoice:
+    let add_invoice = AddInvoice {
+        invoice_id: InvoiceId::from(&[index; InvoiceId::len()]),
+        currency: currency.clone(),
+        total_dest_payment: 4,
+    };
+    node_controls[1]
+        .send(FunderControl::AddInvoice(add_invoice))
+        .await;
+
+    // Create payment 0 --> 1
+    let create_payment = CreatePayment {
+        payment_id: PaymentId::from(&[index; PaymentId::len()]),
+        invoice_id: InvoiceId::from(&[index; InvoiceId::len()]),
+        currency: currency.clone(),
+        total_dest_payment: 4,
+        dest_public_key: public_keys[1].clone(),
+    };
+    node_controls[0]
+        .send(FunderControl::CreatePayment(create_payment))
+        .await;
+
+    let create_transaction = CreateTransaction {
+        payment_id: PaymentId::from(&[index; PaymentId::len()]),
+        request_id: Uid::from(&[index; Uid::len()]),
+        route: FriendsRoute {
+            public_keys: vec![public_keys[0].clone(), public_keys[1].clone()],
+        },
+        dest_payment: 4,
+        fees: 1,
+        exchanges: Vec::new(),
+    };
+    node_controls[0]
+        .send(FunderControl::CreateTransaction(create_transaction))
+        .await;
+    let transaction_result = node_controls[0]
+        .recv_until_transaction_result()
+        .await
+        .unwrap();
+
+    // Invoice was fully paid. We get a commit message that we can send out of band:
+    match transaction_result.result {
+        RequestResult::Complete(commit) => commit,
+        _ => unreachable!(),
+    }
+}
+
+/// Close a payment and acknowledge it. Returns the payment status.
+async fn close_payment(node_control: &mut NodeControl<u32>, index: u8) -> PaymentStatus {
+    let payment_id = PaymentId::from(&[index; PaymentId::len()]);
+    node_control
+        .send(FunderControl::RequestClosePayment(payment_id.clone()))
+        .await;
+    let response_close_payment = node_control
+        .recv_until_response_close_payment()
+        .await
+        .unwrap();
+
+    let ack_uid = match &response_close_payment.status {
+        PaymentStatus::Success(payment_status_success) => payment_status_success.ack_uid.clone(),
+        PaymentStatus::Canceled(ack_uid) => ack_uid.clone(),
+        PaymentStatus::PaymentNotFound => unreachable!(),
+    };
+    let ack_close_payment = AckClosePayment {
+        payment_id,
+        ack_uid,
+    };
+    node_control
+        .send(FunderControl::AckClosePayment(ack_close_payment))
+        .await;
+
+    response_close_payment.status
+}
+
+async fn task_funder_hold_invoice(test_executor: TestExecutor) {
+    let currency1 = Currency::try_from("FST1".to_owned()).unwrap();
+
+    let num_nodes = 2;
+    let mut node_controls = create_node_controls(num_nodes, test_executor.clone()).await;
+
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    let relays0 = vec![dummy_relay_address(0)];
+    let relays1 = vec![dummy_relay_address(1)];
+    node_controls[0]
+        .add_friend(&public_keys[1], relays1, "node1")
+        .await;
+    node_controls[1]
+        .add_friend(&public_keys[0], relays0, "node0")
+        .await;
+
+    node_controls[0]
+        .set_friend_status(&public_keys[1], FriendStatus::Enabled)
+        .await;
+    node_controls[1]
+        .set_friend_status(&public_keys[0], FriendStatus::Enabled)
+        .await;
+
+    node_controls[0]
+        .set_friend_currency_rate(&public_keys[1], &currency1, Rate::new())
+        .await;
+    node_controls[1]
+        .set_friend_currency_rate(&public_keys[0], &currency1, Rate::new())
+        .await;
+
+    node_controls[0]
+        .wait_until_currency_active(&public_keys[1], &currency1)
+        .await;
+    node_controls[1]
+        .wait_until_currency_active(&public_keys[0], &currency1)
+        .await;
+
+    node_controls[1]
+        .set_remote_max_debt(&public_keys[0], &currency1, 100)
+        .await;
+
+    node_controls[0]
+        .set_requests_status(&public_keys[1], &currency1, RequestsStatus::Open)
+        .await;
+    node_controls[1]
+        .set_requests_status(&public_keys[0], &currency1, RequestsStatus::Open)
+        .await;
+
+    node_controls[0]
+        .wait_until_ready(&public_keys[1], &currency1)
+        .await;
+    node_controls[1]
+        .wait_until_ready(&public_keys[0], &currency1)
+        .await;
+
+    // Pay the first invoice. Node 1 holds it until goods are delivered:
+    let commit = pay_invoice(&mut node_controls, &currency1, 1).await;
+    let hold_invoice = HoldInvoice {
+        commit: commit.clone(),
+        max_hold_ticks: 3,
+    };
+    node_controls[1]
+        .send(FunderControl::HoldInvoice(hold_invoice))
+        .await;
+
+    // Some time passes, but the hold did not expire yet:
+    node_controls[1].tick().await;
+    node_controls[1].tick().await;
+    test_executor.wait().await;
+
+    // Funds are still locked along the route:
+    node_controls[0]
+        .wait_friend_balance(&public_keys[1], &currency1, 0)
+        .await;
+
+    // Goods were delivered. Node 1 commits the held invoice:
+    node_controls[1]
+        .send(FunderControl::CommitInvoice(commit))
+        .await;
+    test_executor.wait().await;
+
+    // 0: Expect a receipt:
+    let receipt = match close_payment(&mut node_controls[0], 1).await {
+        PaymentStatus::Success(payment_status_success) => payment_status_success.receipt,
+        _ => unreachable!(),
+    };
+    assert_eq!(
+        receipt.invoice_id,
+        InvoiceId::from(&[1u8; InvoiceId::len()])
+    );
+    assert_eq!(receipt.total_dest_payment, 4);
+    assert!(verify_receipt(&receipt, &public_keys[1]));
+
+    node_controls[0]
+        .wait_friend_balance(&public_keys[1], &currency1, -5)
+        .await;
+    node_controls[1]
+        .wait_friend_balance(&public_keys[0], &currency1, 5)
+        .await;
+
+    // Pay the second invoice. Node 1 holds it, but never commits:
+    let commit = pay_invoice(&mut node_controls, &currency1, 2).await;
+    let hold_invoice = HoldInvoice {
+        commit,
+        max_hold_ticks: 2,
+    };
+    node_controls[1]
+        .send(FunderControl::HoldInvoice(hold_invoice.clone()))
+        .await;
+
+    // A held invoice can not be held again (This would extend the maximum hold duration).
+    // The request is ignored:
+    node_controls[1]
+        .send(FunderControl::HoldInvoice(hold_invoice))
+        .await;
+
+    // Maximum hold duration passes. The invoice is canceled:
+    node_controls[1].tick().await;
+    node_controls[1].tick().await;
+    test_executor.wait().await;
+
+    match close_payment(&mut node_controls[0], 2).await {
+        PaymentStatus::Canceled(_) => {}
+        _ => unreachable!(),
+    };
+
+    // Balances are left unchanged by the canceled invoice:
+    node_controls[0]
+        .wait_friend_balance(&public_keys[1], &currency1, -5)
+        .await;
+    node_controls[1]
+        .wait_friend_balance(&public_keys[0], &currency1, 5)
+        .await;
+}
+
+#[test]
+fn test_funder_hold_invoice() {
+    let test_executor = TestExecutor::new();
+    let res = test_executor.run(task_funder_hold_invoice(test_executor.clone()));
+    assert!(res.is_output());
+}
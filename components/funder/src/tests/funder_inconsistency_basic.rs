@@ -112,6 +112,7 @@ async fn task_funder_inconsistency_basic(test_executor: TestExecutor) {
         },
         dest_payment: 4,
         fees: 1,
+        exchanges: Vec::new(),
     };
 
     node_controls[0]
@@ -0,0 +1,268 @@
+use std::convert::TryFrom;
+
+use common::test_executor::TestExecutor;
+
+use proto::consts::PUSH_INVOICE_TIMEOUT_TICKS;
+use proto::crypto::{InvoiceId, PaymentId, PublicKey, Uid};
+use proto::funder::messages::{
+    AckClosePayment, CreatePayment, CreateTransaction, Currency, EnablePushPayments, FriendStatus,
+    FriendsRoute, FunderControl, PaymentStatus, Rate, RequestResult, RequestsStatus,
+};
+
+use signature::verify::verify_receipt;
+
+use super::utils::{create_node_controls, dummy_relay_address};
+
+async fn task_funder_push_payment(test_executor: TestExecutor) {
+    let currency1 = Currency::try_from("FST1".to_owned()).unwrap();
+
+    let num_nodes = 2;
+    let mut node_controls = create_node_controls(num_nodes, test_executor.clone()).await;
+
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    let relays0 = vec![dummy_relay_address(0)];
+    let relays1 = vec![dummy_relay_address(1)];
+    node_controls[0]
+        .add_friend(&public_keys[1], relays1, "node1")
+        .await;
+    node_controls[1]
+        .add_friend(&public_keys[0], relays0, "node0")
+        .await;
+
+    node_controls[0]
+        .set_friend_status(&public_keys[1], FriendStatus::Enabled)
+        .await;
+    node_controls[1]
+        .set_friend_status(&public_keys[0], FriendStatus::Enabled)
+        .await;
+
+    node_controls[0]
+        .set_friend_currency_rate(&public_keys[1], &currency1, Rate::new())
+        .await;
+    node_controls[1]
+        .set_friend_currency_rate(&public_keys[0], &currency1, Rate::new())
+        .await;
+
+    node_controls[0]
+        .wait_until_currency_active(&public_keys[1], &currency1)
+        .await;
+    node_controls[1]
+        .wait_until_currency_active(&public_keys[0], &currency1)
+        .await;
+
+    node_controls[1]
+        .set_remote_max_debt(&public_keys[0], &currency1, 100)
+        .await;
+
+    node_controls[0]
+        .set_requests_status(&public_keys[1], &currency1, RequestsStatus::Open)
+        .await;
+    node_controls[1]
+        .set_requests_status(&public_keys[0], &currency1, RequestsStatus::Open)
+        .await;
+
+    node_controls[0]
+        .wait_until_ready(&public_keys[1], &currency1)
+        .await;
+    node_controls[1]
+        .wait_until_ready(&public_keys[0], &currency1)
+        .await;
+
+    // Node 1 accepts push payments of up to 10 credits:
+    let enable_push_payments = EnablePushPayments {
+        currency: currency1.clone(),
+        max_total_dest_payment: 10,
+    };
+    node_controls[1]
+        .send(FunderControl::EnablePushPayments(enable_push_payments))
+        .await;
+
+    // Push payment 0 --> 1 that is too large (It should fail):
+    let create_payment = CreatePayment {
+        payment_id: PaymentId::from(&[2u8; PaymentId::len()]),
+        invoice_id: InvoiceId::from(&[1u8; InvoiceId::len()]),
+        currency: currency1.clone(),
+        total_dest_payment: 20,
+        dest_public_key: node_controls[1].public_key.clone(),
+    };
+    node_controls[0]
+        .send(FunderControl::CreatePushPayment(create_payment))
+        .await;
+
+    let create_transaction = CreateTransaction {
+        payment_id: PaymentId::from(&[2u8; PaymentId::len()]),
+        request_id: Uid::from(&[5u8; Uid::len()]),
+        route: FriendsRoute {
+            public_keys: vec![public_keys[0].clone(), public_keys[1].clone()],
+        },
+        dest_payment: 20,
+        fees: 1,
+        exchanges: Vec::new(),
+    };
+    node_controls[0]
+        .send(FunderControl::CreateTransaction(create_transaction))
+        .await;
+    let transaction_result = node_controls[0]
+        .recv_until_transaction_result()
+        .await
+        .unwrap();
+
+    match transaction_result.result {
+        RequestResult::Failure => {}
+        _ => unreachable!(),
+    };
+
+    // Push payment 0 --> 1 (Without any invoice created by node 1):
+    let create_payment = CreatePayment {
+        payment_id: PaymentId::from(&[3u8; PaymentId::len()]),
+        invoice_id: InvoiceId::from(&[4u8; InvoiceId::len()]),
+        currency: currency1.clone(),
+        total_dest_payment: 4,
+        dest_public_key: node_controls[1].public_key.clone(),
+    };
+    node_controls[0]
+        .send(FunderControl::CreatePushPayment(create_payment))
+        .await;
+
+    let create_transaction = CreateTransaction {
+        payment_id: PaymentId::from(&[3u8; PaymentId::len()]),
+        request_id: Uid::from(&[6u8; Uid::len()]),
+        route: FriendsRoute {
+            public_keys: vec![public_keys[0].clone(), public_keys[1].clone()],
+        },
+        dest_payment: 4,
+        fees: 1,
+        exchanges: Vec::new(),
+    };
+    node_controls[0]
+        .send(FunderControl::CreateTransaction(create_transaction))
+        .await;
+    let transaction_result = node_controls[0]
+        .recv_until_transaction_result()
+        .await
+        .unwrap();
+
+    // Payment is complete. There is no need to send the commit out of band:
+    match transaction_result.result {
+        RequestResult::Complete(_commit) => {}
+        _ => unreachable!(),
+    };
+
+    // Wait until no more progress can be made
+    test_executor.wait().await;
+
+    // 0: Expect a receipt:
+    node_controls[0]
+        .send(FunderControl::RequestClosePayment(PaymentId::from(
+            &[3u8; PaymentId::len()],
+        )))
+        .await;
+    let response_close_payment = node_controls[0]
+        .recv_until_response_close_payment()
+        .await
+        .unwrap();
+    let (receipt, ack_uid) = match response_close_payment.status {
+        PaymentStatus::Success(payment_status_success) => (
+            payment_status_success.receipt,
+            payment_status_success.ack_uid,
+        ),
+        _ => unreachable!(),
+    };
+
+    let ack_close_payment = AckClosePayment {
+        payment_id: PaymentId::from(&[3u8; PaymentId::len()]),
+        ack_uid,
+    };
+    node_controls[0]
+        .send(FunderControl::AckClosePayment(ack_close_payment))
+        .await;
+
+    assert_eq!(
+        receipt.invoice_id,
+        InvoiceId::from(&[4u8; InvoiceId::len()])
+    );
+    assert_eq!(receipt.total_dest_payment, 4);
+    assert!(verify_receipt(&receipt, &public_keys[1]));
+
+    // Verify expected balances:
+    node_controls[0]
+        .wait_friend_balance(&public_keys[1], &currency1, -5)
+        .await;
+    node_controls[1]
+        .wait_friend_balance(&public_keys[0], &currency1, 5)
+        .await;
+
+    // Push payment 0 --> 1 that is only partially paid:
+    let create_payment = CreatePayment {
+        payment_id: PaymentId::from(&[4u8; PaymentId::len()]),
+        invoice_id: InvoiceId::from(&[5u8; InvoiceId::len()]),
+        currency: currency1.clone(),
+        total_dest_payment: 8,
+        dest_public_key: node_controls[1].public_key.clone(),
+    };
+    node_controls[0]
+        .send(FunderControl::CreatePushPayment(create_payment))
+        .await;
+
+    let create_transaction = CreateTransaction {
+        payment_id: PaymentId::from(&[4u8; PaymentId::len()]),
+        request_id: Uid::from(&[7u8; Uid::len()]),
+        route: FriendsRoute {
+            public_keys: vec![public_keys[0].clone(), public_keys[1].clone()],
+        },
+        dest_payment: 4,
+        fees: 1,
+        exchanges: Vec::new(),
+    };
+    node_controls[0]
+        .send(FunderControl::CreateTransaction(create_transaction))
+        .await;
+    let transaction_result = node_controls[0]
+        .recv_until_transaction_result()
+        .await
+        .unwrap();
+
+    match transaction_result.result {
+        RequestResult::Success => {}
+        _ => unreachable!(),
+    };
+
+    // The push payment is not fully paid in time. The push invoice is canceled:
+    for _ in 0..PUSH_INVOICE_TIMEOUT_TICKS {
+        node_controls[1].tick().await;
+    }
+    test_executor.wait().await;
+
+    node_controls[0]
+        .send(FunderControl::RequestClosePayment(PaymentId::from(
+            &[4u8; PaymentId::len()],
+        )))
+        .await;
+    let response_close_payment = node_controls[0]
+        .recv_until_response_close_payment()
+        .await
+        .unwrap();
+    match response_close_payment.status {
+        PaymentStatus::Canceled(_) => {}
+        _ => unreachable!(),
+    };
+
+    // Balances are left unchanged by the canceled push payment:
+    node_controls[0]
+        .wait_friend_balance(&public_keys[1], &currency1, -5)
+        .await;
+    node_controls[1]
+        .wait_friend_balance(&public_keys[0], &currency1, 5)
+        .await;
+}
+
+#[test]
+fn test_funder_push_payment() {
+    let test_executor = TestExecutor::new();
+    let res = test_executor.run(task_funder_push_payment(test_executor.clone()));
+    assert!(res.is_output());
+}
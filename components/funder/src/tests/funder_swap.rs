@@ -0,0 +1,228 @@
+use std::convert::TryFrom;
+
+use common::test_executor::TestExecutor;
+
+use proto::crypto::{PublicKey, Uid};
+use proto::funder::messages::{
+    CancelFriendSwap, Currency, FriendStatus, FriendSwap, FunderControl,
+};
+
+use super::utils::{create_node_controls, dummy_relay_address};
+
+async fn task_funder_swap(test_executor: TestExecutor) {
+    let currency1 = Currency::try_from("FST1".to_owned()).unwrap();
+    let currency2 = Currency::try_from("FST2".to_owned()).unwrap();
+
+    let num_nodes = 2;
+    let mut node_controls = create_node_controls(num_nodes, test_executor.clone()).await;
+
+    let public_keys = node_controls
+        .iter()
+        .map(|nc| nc.public_key.clone())
+        .collect::<Vec<PublicKey>>();
+
+    let relays0 = vec![dummy_relay_address(0)];
+    let relays1 = vec![dummy_relay_address(1)];
+    node_controls[0]
+        .add_friend(&public_keys[1], relays1, "node1")
+        .await;
+    node_controls[1]
+        .add_friend(&public_keys[0], relays0, "node0")
+        .await;
+
+    node_controls[0]
+        .set_friend_status(&public_keys[1], FriendStatus::Enabled)
+        .await;
+    node_controls[1]
+        .set_friend_status(&public_keys[0], FriendStatus::Enabled)
+        .await;
+
+    // Both nodes trade in the two currencies:
+    node_controls[0]
+        .set_friend_currencies(&public_keys[1], vec![currency1.clone(), currency2.clone()])
+        .await;
+    node_controls[1]
+        .set_friend_currencies(&public_keys[0], vec![currency1.clone(), currency2.clone()])
+        .await;
+
+    for currency in &[&currency1, &currency2] {
+        node_controls[0]
+            .wait_until_currency_active(&public_keys[1], currency)
+            .await;
+        node_controls[1]
+            .wait_until_currency_active(&public_keys[0], currency)
+            .await;
+
+        node_controls[0]
+            .set_remote_max_debt(&public_keys[1], currency, 100)
+            .await;
+        node_controls[1]
+            .set_remote_max_debt(&public_keys[0], currency, 100)
+            .await;
+    }
+
+    test_executor.wait().await;
+
+    // Node 0 proposes to give 20 credits of currency1 in return for 10 credits of currency2:
+    let swap_id1 = Uid::from(&[1u8; Uid::len()]);
+    let propose_swap = FriendSwap {
+        friend_public_key: public_keys[1].clone(),
+        swap_id: swap_id1.clone(),
+        give_currency: currency1.clone(),
+        give_amount: 20,
+        take_currency: currency2.clone(),
+        take_amount: 10,
+    };
+    node_controls[0]
+        .send(FunderControl::ProposeSwap(propose_swap))
+        .await;
+
+    test_executor.wait().await;
+
+    // Node 1 attempts to accept the swap with different terms. The swap should not happen:
+    let accept_swap = FriendSwap {
+        friend_public_key: public_keys[0].clone(),
+        swap_id: swap_id1.clone(),
+        give_currency: currency2.clone(),
+        give_amount: 5,
+        take_currency: currency1.clone(),
+        take_amount: 20,
+    };
+    node_controls[1]
+        .send(FunderControl::AcceptSwap(accept_swap))
+        .await;
+
+    // Node 1 accepts the swap with the proposed terms:
+    let accept_swap = FriendSwap {
+        friend_public_key: public_keys[0].clone(),
+        swap_id: swap_id1,
+        give_currency: currency2.clone(),
+        give_amount: 10,
+        take_currency: currency1.clone(),
+        take_amount: 20,
+    };
+    node_controls[1]
+        .send(FunderControl::AcceptSwap(accept_swap))
+        .await;
+
+    test_executor.wait().await;
+
+    // Both currencies were moved in opposite directions:
+    node_controls[0]
+        .wait_friend_balance(&public_keys[1], &currency1, -20)
+        .await;
+    node_controls[0]
+        .wait_friend_balance(&public_keys[1], &currency2, 10)
+        .await;
+    node_controls[1]
+        .wait_friend_balance(&public_keys[0], &currency1, 20)
+        .await;
+    node_controls[1]
+        .wait_friend_balance(&public_keys[0], &currency2, -10)
+        .await;
+
+    // Node 1 proposes another swap, which node 0 declines:
+    let swap_id2 = Uid::from(&[2u8; Uid::len()]);
+    let propose_swap = FriendSwap {
+        friend_public_key: public_keys[0].clone(),
+        swap_id: swap_id2.clone(),
+        give_currency: currency1.clone(),
+        give_amount: 5,
+        take_currency: currency2.clone(),
+        take_amount: 5,
+    };
+    node_controls[1]
+        .send(FunderControl::ProposeSwap(propose_swap))
+        .await;
+
+    test_executor.wait().await;
+
+    let cancel_swap = CancelFriendSwap {
+        friend_public_key: public_keys[1].clone(),
+        swap_id: swap_id2.clone(),
+    };
+    node_controls[0]
+        .send(FunderControl::CancelSwap(cancel_swap))
+        .await;
+
+    test_executor.wait().await;
+
+    // Accepting a declined swap should have no effect:
+    let accept_swap = FriendSwap {
+        friend_public_key: public_keys[1].clone(),
+        swap_id: swap_id2,
+        give_currency: currency2.clone(),
+        give_amount: 5,
+        take_currency: currency1.clone(),
+        take_amount: 5,
+    };
+    node_controls[0]
+        .send(FunderControl::AcceptSwap(accept_swap))
+        .await;
+
+    test_executor.wait().await;
+
+    // Balances did not change:
+    node_controls[0]
+        .wait_friend_balance(&public_keys[1], &currency1, -20)
+        .await;
+    node_controls[0]
+        .wait_friend_balance(&public_keys[1], &currency2, 10)
+        .await;
+    node_controls[1]
+        .wait_friend_balance(&public_keys[0], &currency1, 20)
+        .await;
+    node_controls[1]
+        .wait_friend_balance(&public_keys[0], &currency2, -10)
+        .await;
+
+    // Node 1 proposes a swap that exceeds the max debt node 0 allows in currency1.
+    // Node 0 cancels the swap automatically:
+    let swap_id3 = Uid::from(&[3u8; Uid::len()]);
+    let propose_swap = FriendSwap {
+        friend_public_key: public_keys[0].clone(),
+        swap_id: swap_id3.clone(),
+        give_currency: currency1.clone(),
+        give_amount: 200,
+        take_currency: currency2.clone(),
+        take_amount: 5,
+    };
+    node_controls[1]
+        .send(FunderControl::ProposeSwap(propose_swap))
+        .await;
+
+    test_executor.wait().await;
+
+    // Even after node 0 allows the debt, the canceled swap can not be accepted:
+    node_controls[0]
+        .set_remote_max_debt(&public_keys[1], &currency1, 300)
+        .await;
+    let accept_swap = FriendSwap {
+        friend_public_key: public_keys[1].clone(),
+        swap_id: swap_id3,
+        give_currency: currency2.clone(),
+        give_amount: 5,
+        take_currency: currency1.clone(),
+        take_amount: 200,
+    };
+    node_controls[0]
+        .send(FunderControl::AcceptSwap(accept_swap))
+        .await;
+
+    test_executor.wait().await;
+
+    // Balances did not change:
+    node_controls[0]
+        .wait_friend_balance(&public_keys[1], &currency1, -20)
+        .await;
+    node_controls[1]
+        .wait_friend_balance(&public_keys[0], &currency1, 20)
+        .await;
+}
+
+#[test]
+fn test_funder_swap() {
+    let test_executor = TestExecutor::new();
+    let res = test_executor.run(task_funder_swap(test_executor.clone()));
+    assert!(res.is_output());
+}
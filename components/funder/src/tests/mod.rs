@@ -1,7 +1,12 @@
 mod funder_basic;
+mod funder_close;
 mod funder_error_command;
+mod funder_exchange_payment;
 mod funder_forward_payment;
+mod funder_hold_invoice;
 mod funder_inconsistency_basic;
 mod funder_payment_failure;
+mod funder_push_payment;
+mod funder_swap;
 
 pub mod utils;
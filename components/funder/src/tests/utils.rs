@@ -16,15 +16,16 @@ use crypto::test_utils::DummyRandom;
 use proto::crypto::{PrivateKey, PublicKey, Uid};
 
 use proto::report::messages::{
-    ChannelStatusReport, FriendLivenessReport, FunderReport, FunderReportMutations,
+    ChannelStatusReport, FriendCloseStatusReport, FriendLivenessReport, FunderReport,
+    FunderReportMutations,
 };
 
 use proto::app_server::messages::{NamedRelayAddress, RelayAddress};
 use proto::funder::messages::{
-    AddFriend, Currency, FriendStatus, FunderControl, FunderIncomingControl, FunderOutgoingControl,
-    Rate, RemoveFriend, RemoveFriendCurrency, RequestsStatus, ResponseClosePayment,
-    SetFriendCurrencyMaxDebt, SetFriendCurrencyRate, SetFriendCurrencyRequestsStatus,
-    SetFriendStatus, TransactionResult,
+    AddFriend, Currency, FriendSettlement, FriendStatus, FunderControl, FunderIncomingControl,
+    FunderOutgoingControl, Rate, RemoveFriend, RemoveFriendCurrency, RequestsStatus,
+    ResponseClosePayment, SetFriendCurrencyMaxDebt, SetFriendCurrencyRate,
+    SetFriendCurrencyRequestsStatus, SetFriendStatus, TransactionResult,
 };
 
 use database::DatabaseClient;
@@ -208,6 +209,7 @@ pub struct NodeControl<B: Clone> {
     pub public_key: PublicKey,
     send_control: mpsc::Sender<FunderIncomingControl<B>>,
     recv_control: mpsc::Receiver<FunderOutgoingControl<B>>,
+    send_timer: mpsc::Sender<()>,
     pub report: FunderReport<B>,
     next_app_request_id: u64,
 }
@@ -257,6 +259,11 @@ where
         }
     }
 
+    /// Send a single timer tick to the funder
+    pub async fn tick(&mut self) {
+        self.send_timer.send(()).await.unwrap();
+    }
+
     pub async fn recv(&mut self) -> Option<NodeRecv<B>> {
         let funder_outgoing_control = self.recv_control.next().await?;
         match funder_outgoing_control {
@@ -527,6 +534,28 @@ where
         };
         self.recv_until(pred).await;
     }
+
+    /// Wait until the channel with a friend is closed, and return the final settlement.
+    pub async fn wait_until_friend_closed<'a>(
+        &'a mut self,
+        friend_public_key: &'a PublicKey,
+    ) -> FriendSettlement {
+        let pred = |report: &FunderReport<_>| {
+            let friend = report.friends.get(friend_public_key).unwrap();
+            if let FriendCloseStatusReport::Closed(_) = &friend.close_status {
+                true
+            } else {
+                false
+            }
+        };
+        self.recv_until(pred).await;
+
+        let friend = self.report.friends.get(friend_public_key).unwrap();
+        match &friend.close_status {
+            FriendCloseStatusReport::Closed(friend_settlement) => friend_settlement.clone(),
+            _ => unreachable!(),
+        }
+    }
 }
 
 /// Create a few node_controls, together with a router connecting them all.
@@ -581,11 +610,14 @@ where
         let (send_comm, incoming_comm) = mpsc::channel(CHANNEL_SIZE);
         let (comm_sender, recv_comm) = mpsc::channel(CHANNEL_SIZE);
 
+        let (send_timer, incoming_timer) = mpsc::channel(CHANNEL_SIZE);
+
         let funder_fut = inner_funder_loop(
             identity_client.clone(),
             DummyRandom::new(&[i as u8]),
             incoming_control,
             incoming_comm,
+            incoming_timer,
             control_sender,
             comm_sender,
             funder_state,
@@ -593,6 +625,7 @@ where
             TEST_MAX_NODE_RELAYS,
             TEST_MAX_OPERATIONS_IN_BATCH,
             TEST_MAX_PENDING_USER_REQUESTS,
+            Vec::new(),
             None,
         );
 
@@ -618,6 +651,7 @@ where
             public_key: identity_client.request_public_key().await.unwrap(),
             send_control,
             recv_control,
+            send_timer,
             report: base_report,
             next_app_request_id: 0,
         });
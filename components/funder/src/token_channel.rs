@@ -4,6 +4,7 @@ use std::convert::TryFrom;
 use im::hashset::HashSet as ImHashSet;
 use std::collections::HashMap as ImHashMap;
 
+use common::safe_arithmetic::SafeSignedArithmetic;
 use common::ser_utils::ser_map_str_any;
 
 use signature::canonical::CanonicalSerialize;
@@ -24,8 +25,8 @@ use signature::verify::verify_move_token;
 use crate::mutual_credit::incoming::{
     process_operations_list, IncomingMessage, ProcessOperationOutput, ProcessTransListError,
 };
-use crate::mutual_credit::outgoing::OutgoingMc;
-use crate::mutual_credit::types::{McMutation, MutualCredit};
+use crate::mutual_credit::outgoing::{OutgoingMc, QueueOperationOutput};
+use crate::mutual_credit::types::{McMutation, MutualCredit, SwapCounterLeg};
 
 use crate::types::{create_hashed, create_unsigned_move_token, MoveTokenHashed};
 
@@ -128,6 +129,7 @@ pub enum ReceiveMoveTokenError {
     InvalidCurrency,
     InvalidAddActiveCurrencies,
     CanNotRemoveCurrencyInUse,
+    InvalidSwap,
 }
 
 #[derive(Debug)]
@@ -141,6 +143,7 @@ pub struct MoveTokenReceived<B> {
     pub mutations: Vec<TcMutation<B>>,
     pub currencies: Vec<MoveTokenReceivedCurrency>,
     pub opt_local_relays: Option<Vec<RelayAddress<B>>>,
+    pub opt_direct_addresses: Option<Vec<B>>,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -163,6 +166,39 @@ pub struct SendMoveTokenOutput<B> {
 pub enum SendMoveTokenError {
     LocalCurrencyAlreadyExists,
     CanNotRemoveCurrencyInUse,
+    InvalidSwap,
+}
+
+/// Create a mutation that applies the other leg of a swap to the balance of another currency.
+/// Returns None if the currency does not exist, or if the new balance overflows.
+fn swap_counter_leg_mutation<B>(
+    mutual_credits: &ImHashMap<Currency, MutualCredit>,
+    swap_counter_leg: &SwapCounterLeg,
+) -> Option<TcMutation<B>> {
+    let (currency, new_balance) = match swap_counter_leg {
+        SwapCounterLeg::Increase((currency, amount)) => (
+            currency,
+            mutual_credits
+                .get(currency)?
+                .state()
+                .balance
+                .balance
+                .checked_add_unsigned(*amount)?,
+        ),
+        SwapCounterLeg::Decrease((currency, amount)) => (
+            currency,
+            mutual_credits
+                .get(currency)?
+                .state()
+                .balance
+                .balance
+                .checked_sub_unsigned(*amount)?,
+        ),
+    };
+    Some(TcMutation::McMutation((
+        currency.clone(),
+        McMutation::SetBalance(new_balance),
+    )))
 }
 
 /// Create a token from a public key
@@ -215,6 +251,7 @@ fn initial_move_token<B>(
         old_token: token_from_public_key(&low_public_key),
         currencies_operations: Vec::new(),
         opt_local_relays: None,
+        opt_direct_addresses: None,
         opt_active_currencies: None,
         info_hash: hash_token_info(&token_info),
         rand_nonce: rand_nonce_from_public_key(&high_public_key),
@@ -372,6 +409,18 @@ where
         }
     }
 
+    /// Get the current state of the token channel, from our point of view.
+    /// Both sides agree on this state, once the last move token was received.
+    pub fn get_token_info(&self) -> TokenInfo {
+        match &self.direction {
+            TcDirection::Incoming(tc_incoming) => {
+                // The remote side signed over the token info from its own point of view:
+                tc_incoming.move_token_in.token_info.clone().flip()
+            }
+            TcDirection::Outgoing(tc_outgoing) => tc_outgoing.token_info.clone(),
+        }
+    }
+
     /// Get the last incoming move token
     /// If no such incoming move token exists (Maybe this is the beginning of the relationship),
     /// returns None.
@@ -522,6 +571,7 @@ impl<'a> TcInBorrow<'a> {
         &self,
         currencies_operations: Vec<CurrencyOperations>,
         opt_local_relays: Option<Vec<RelayAddress<B>>>,
+        opt_direct_addresses: Option<Vec<B>>,
         opt_active_currencies: Option<Vec<Currency>>,
         rand_nonce: RandValue,
     ) -> Result<SendMoveTokenOutput<B>, SendMoveTokenError>
@@ -580,7 +630,10 @@ impl<'a> TcInBorrow<'a> {
 
             let mut outgoing_mc = OutgoingMc::new(&mutual_credit);
             for op in &currency_operations.operations {
-                let mc_mutations = outgoing_mc.queue_operation(op).unwrap();
+                let QueueOperationOutput {
+                    mc_mutations,
+                    opt_swap_counter_leg,
+                } = outgoing_mc.queue_operation(op).unwrap();
                 for mc_mutation in mc_mutations {
                     let mutation = TcMutation::McMutation((
                         currency_operations.currency.clone(),
@@ -589,6 +642,14 @@ impl<'a> TcInBorrow<'a> {
                     token_channel.mutate(&mutation);
                     tc_mutations.push(mutation);
                 }
+                // An accepted swap also changes the balance of another currency:
+                if let Some(swap_counter_leg) = opt_swap_counter_leg {
+                    let mutation =
+                        swap_counter_leg_mutation(&token_channel.mutual_credits, &swap_counter_leg)
+                            .ok_or(SendMoveTokenError::InvalidSwap)?;
+                    token_channel.mutate(&mutation);
+                    tc_mutations.push(mutation);
+                }
             }
         }
 
@@ -628,6 +689,7 @@ impl<'a> TcInBorrow<'a> {
         let unsigned_move_token = create_unsigned_move_token(
             currencies_operations,
             opt_local_relays,
+            opt_direct_addresses,
             opt_active_currencies,
             &token_info,
             tc_in_borrow.tc_incoming.move_token_in.new_token.clone(),
@@ -704,6 +766,7 @@ where
             mutations: Vec::new(),
             currencies: Vec::new(),
             opt_local_relays: new_move_token.opt_local_relays.clone(),
+            opt_direct_addresses: new_move_token.opt_direct_addresses.clone(),
         };
 
         // Handle active_currencies:
@@ -771,6 +834,7 @@ where
                 let ProcessOperationOutput {
                     incoming_message,
                     mc_mutations,
+                    opt_swap_counter_leg,
                 } = output;
 
                 if let Some(funds) = incoming_message {
@@ -784,6 +848,14 @@ where
                     token_channel.mutate(&mutation);
                     move_token_received.mutations.push(mutation);
                 }
+                // An accepted swap also changes the balance of another currency:
+                if let Some(swap_counter_leg) = opt_swap_counter_leg {
+                    let mutation =
+                        swap_counter_leg_mutation(&token_channel.mutual_credits, &swap_counter_leg)
+                            .ok_or(ReceiveMoveTokenError::InvalidSwap)?;
+                    token_channel.mutate(&mutation);
+                    move_token_received.mutations.push(mutation);
+                }
             }
 
             let move_token_received_currency = MoveTokenReceivedCurrency {
@@ -897,6 +969,7 @@ mod tests {
             old_token: unsigned_move_token.old_token,
             currencies_operations: unsigned_move_token.currencies_operations,
             opt_local_relays: unsigned_move_token.opt_local_relays,
+            opt_direct_addresses: unsigned_move_token.opt_direct_addresses,
             opt_active_currencies: unsigned_move_token.opt_active_currencies,
             info_hash: unsigned_move_token.info_hash,
             rand_nonce: unsigned_move_token.rand_nonce,
@@ -981,6 +1054,7 @@ mod tests {
 
         let rand_nonce = RandValue::from(&[7; RandValue::len()]);
         let opt_local_relays = None;
+        let opt_direct_addresses = None;
         let opt_active_currencies = Some(currencies.to_vec());
 
         let SendMoveTokenOutput {
@@ -991,6 +1065,7 @@ mod tests {
             .simulate_send_move_token(
                 currencies_operations,
                 opt_local_relays,
+                opt_direct_addresses,
                 opt_active_currencies,
                 rand_nonce,
             )
@@ -1076,6 +1151,7 @@ mod tests {
 
         let rand_nonce = RandValue::from(&[5; RandValue::len()]);
         let opt_local_relays = None;
+        let opt_direct_addresses = None;
         let opt_active_currencies = None;
 
         let SendMoveTokenOutput {
@@ -1086,6 +1162,7 @@ mod tests {
             .simulate_send_move_token(
                 currencies_operations,
                 opt_local_relays,
+                opt_direct_addresses,
                 opt_active_currencies,
                 rand_nonce,
             )
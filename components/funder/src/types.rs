@@ -11,6 +11,7 @@ use proto::funder::messages::{
     RequestSendFundsOp, ResponseSendFundsOp, TokenInfo, TransactionStage, UnsignedMoveToken,
     UnsignedResponseSendFundsOp,
 };
+use proto::report::messages::RelayHealthReport;
 
 use signature::signature_buff::{
     create_response_signature_buffer, hash_token_info, move_token_signature_buff, prefix_hash,
@@ -35,6 +36,7 @@ where
         old_token: unsigned_move_token.old_token,
         currencies_operations: unsigned_move_token.currencies_operations,
         opt_local_relays: unsigned_move_token.opt_local_relays,
+        opt_direct_addresses: unsigned_move_token.opt_direct_addresses,
         opt_active_currencies: unsigned_move_token.opt_active_currencies,
         info_hash: unsigned_move_token.info_hash,
         rand_nonce: unsigned_move_token.rand_nonce,
@@ -89,6 +91,7 @@ pub fn create_pending_transaction(request_send_funds: &RequestSendFundsOp) -> Pe
         invoice_id: request_send_funds.invoice_id.clone(),
         left_fees: request_send_funds.left_fees,
         src_hashed_lock: request_send_funds.src_hashed_lock.clone(),
+        exchanges: request_send_funds.exchanges.clone(),
         stage: TransactionStage::Request,
     }
 }
@@ -122,6 +125,7 @@ pub struct MoveTokenHashed {
 pub fn create_unsigned_move_token<B>(
     currencies_operations: Vec<CurrencyOperations>,
     opt_local_relays: Option<Vec<RelayAddress<B>>>,
+    opt_direct_addresses: Option<Vec<B>>,
     opt_active_currencies: Option<Vec<Currency>>,
     token_info: &TokenInfo,
     old_token: Signature,
@@ -131,6 +135,7 @@ pub fn create_unsigned_move_token<B>(
         old_token,
         currencies_operations,
         opt_local_relays,
+        opt_direct_addresses,
         opt_active_currencies,
         info_hash: hash_token_info(token_info),
         rand_nonce,
@@ -190,6 +195,8 @@ where
 pub enum IncomingLivenessMessage {
     Online(PublicKey),
     Offline(PublicKey),
+    /// Health of the relays used to connect to a friend
+    RelaysHealth((PublicKey, Vec<RelayHealthReport>)),
 }
 
 pub struct FriendInconsistencyError {
@@ -219,6 +226,8 @@ pub enum FunderIncomingComm<B> {
 #[derive(Clone, Debug)]
 pub enum FunderIncoming<B> {
     Init,
+    /// A periodic timer tick
+    TimerTick,
     Control(FunderIncomingControl<B>),
     Comm(FunderIncomingComm<B>),
 }
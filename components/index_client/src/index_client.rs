@@ -1,4 +1,4 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::fmt::Debug;
 use std::marker::Unpin;
 
@@ -12,13 +12,14 @@ use common::never::Never;
 use common::select_streams::select_streams;
 
 use proto::crypto::{PublicKey, Uid};
+use proto::funder::messages::{Currency, ExchangeRate};
 
 use database::DatabaseClient;
 
 use proto::index_client::messages::{
     AppServerToIndexClient, ClientResponseRoutes, IndexClientReportMutation,
     IndexClientReportMutations, IndexClientRequest, IndexClientToAppServer, IndexMutation,
-    RequestRoutes, ResponseRoutesResult,
+    RequestRoutes, ResponseRoutesResult, UpdateExchangeRate,
 };
 use proto::index_server::messages::{IndexServerAddress, NamedIndexServerAddress};
 
@@ -126,6 +127,9 @@ struct IndexClient<ISA, TAS, ICS, S> {
     // We perform the mutations implicitly in the implementation of IndexClient.
     index_servers: VecDeque<IndexServerAddress<ISA>>,
     seq_friends_client: SeqFriendsClient,
+    /// Our exchange rates, (src_currency, dest_currency) -> rate.
+    /// Sent in full to every index server we connect to.
+    exchange_rates: HashMap<(Currency, Currency), ExchangeRate>,
     index_client_session: ICS,
     max_open_requests: usize,
     num_open_requests: usize,
@@ -140,6 +144,7 @@ struct IndexClient<ISA, TAS, ICS, S> {
 /// We do this in a separate task so that we don't block user requests or incoming funder reports.
 async fn send_full_state(
     mut seq_friends_client: SeqFriendsClient,
+    exchange_rates: Vec<UpdateExchangeRate>,
     mut control_sender: ControlSender,
 ) -> Result<(), IndexClientError> {
     seq_friends_client
@@ -147,6 +152,21 @@ async fn send_full_state(
         .await
         .map_err(|_| IndexClientError::SeqFriendsError)?;
 
+    // Exchange rates are usually few, so we send them all in one batch:
+    if !exchange_rates.is_empty() {
+        let mutations = exchange_rates
+            .into_iter()
+            .map(IndexMutation::UpdateExchangeRate)
+            .collect();
+        if control_sender
+            .send(SingleClientControl::SendMutations(mutations))
+            .await
+            .is_err()
+        {
+            return Ok(());
+        }
+    }
+
     loop {
         let next_update_res = seq_friends_client
             .next_update()
@@ -193,6 +213,7 @@ where
         to_app_server: TAS,
         index_client_config: IndexClientConfig<ISA>,
         seq_friends_client: SeqFriendsClient,
+        exchange_rates: HashMap<(Currency, Currency), ExchangeRate>,
         index_client_session: ICS,
         max_open_requests: usize,
         keepalive_ticks: usize,
@@ -214,6 +235,7 @@ where
             to_app_server,
             index_servers,
             seq_friends_client,
+            exchange_rates,
             index_client_session,
             max_open_requests,
             num_open_requests: 0,
@@ -256,6 +278,15 @@ where
         self.conn_status = ConnStatus::Connecting(server_connecting);
 
         let c_seq_friends_client = self.seq_friends_client.clone();
+        let exchange_rates = self
+            .exchange_rates
+            .iter()
+            .map(|((src_currency, dest_currency), rate)| UpdateExchangeRate {
+                src_currency: src_currency.clone(),
+                dest_currency: dest_currency.clone(),
+                rate: rate.clone(),
+            })
+            .collect::<Vec<_>>();
         let c_spawner = self.spawner.clone();
 
         // Canceller for the send_full_state() task:
@@ -270,7 +301,7 @@ where
             let c_control_sender = control_sender.clone();
             let send_full_state_cancellable_fut = async move {
                 let send_full_state_fut = Box::pin(
-                    send_full_state(c_seq_friends_client, c_control_sender)
+                    send_full_state(c_seq_friends_client, exchange_rates, c_control_sender)
                         .map_err(|e| warn!("Error in send_full_state(): {:?}", e))
                         .map(|_| {
                             let _ = sfs_done_sender.send(());
@@ -508,6 +539,25 @@ where
     ) -> Result<(), IndexClientError> {
         // Update state:
         for mutation in &mutations {
+            match mutation {
+                IndexMutation::UpdateExchangeRate(update_exchange_rate) => {
+                    self.exchange_rates.insert(
+                        (
+                            update_exchange_rate.src_currency.clone(),
+                            update_exchange_rate.dest_currency.clone(),
+                        ),
+                        update_exchange_rate.rate.clone(),
+                    );
+                }
+                IndexMutation::RemoveExchangeRate(remove_exchange_rate) => {
+                    self.exchange_rates.remove(&(
+                        remove_exchange_rate.src_currency.clone(),
+                        remove_exchange_rate.dest_currency.clone(),
+                    ));
+                }
+                IndexMutation::UpdateFriendCurrency(_) | IndexMutation::RemoveFriendCurrency(_) => {
+                }
+            }
             self.seq_friends_client
                 .mutate(mutation.clone())
                 .await
@@ -728,6 +778,7 @@ pub async fn index_client_loop<ISA, FAS, TAS, ICS, TS, S>(
     to_app_server: TAS,
     index_client_config: IndexClientConfig<ISA>,
     seq_friends_client: SeqFriendsClient,
+    exchange_rates: HashMap<(Currency, Currency), ExchangeRate>,
     index_client_session: ICS,
     max_open_requests: usize,
     keepalive_ticks: usize,
@@ -753,6 +804,7 @@ where
         to_app_server,
         index_client_config,
         seq_friends_client,
+        exchange_rates,
         index_client_session,
         max_open_requests,
         keepalive_ticks,
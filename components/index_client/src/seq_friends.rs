@@ -48,6 +48,8 @@ fn apply_index_mutation(seq_friends: &mut SeqFriends, index_mutation: &IndexMuta
                 remove_friend_currency.currency.clone(),
             ));
         }
+        // Exchange rates are not part of the friends sequence:
+        IndexMutation::UpdateExchangeRate(_) | IndexMutation::RemoveExchangeRate(_) => {}
     }
 }
 
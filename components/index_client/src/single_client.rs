@@ -350,10 +350,15 @@ mod tests {
         let request_routes = RequestRoutes {
             request_id: Uid::from(&[3; Uid::len()]),
             currency: currency.clone(),
+            dest_currency: currency.clone(),
             capacity: 20,
             source: PublicKey::from(&[0xcc; PublicKey::len()]),
             destination: PublicKey::from(&[0xdd; PublicKey::len()]),
             opt_exclude: None,
+            exclude_edges: Vec::new(),
+            exclude_nodes: Vec::new(),
+            opt_max_hops: None,
+            opt_max_fees: None,
         };
 
         let (response_sender, response_receiver) = oneshot::channel();
@@ -156,6 +156,7 @@ where
         to_app_server,
         index_client_config,
         seq_friends_client,
+        index_client_state.exchange_rates,
         index_client_session,
         max_open_index_client_requests,
         keepalive_ticks,
@@ -1,3 +1,4 @@
+use std::collections::HashMap;
 use std::convert::TryFrom;
 
 use futures::channel::{mpsc, oneshot};
@@ -78,6 +79,7 @@ where
         to_app_server,
         index_client_config,
         seq_friends_client,
+        HashMap::new(),
         index_client_session,
         max_open_requests,
         keepalive_ticks,
@@ -409,10 +411,15 @@ where
     let request_routes = RequestRoutes {
         request_id: Uid::from(&[3; Uid::len()]),
         currency: currency.clone(),
+        dest_currency: currency.clone(),
         capacity: 250,
         source: PublicKey::from(PublicKey::from(&[0xee; PublicKey::len()])),
         destination: PublicKey::from(PublicKey::from(&[0xff; PublicKey::len()])),
         opt_exclude: None,
+        exclude_edges: Vec::new(),
+        exclude_nodes: Vec::new(),
+        opt_max_hops: None,
+        opt_max_fees: None,
     };
 
     // Request routes from IndexClient (From AppServer):
@@ -522,10 +529,15 @@ where
     let request_routes = RequestRoutes {
         request_id: Uid::from(&[3; Uid::len()]),
         currency: currency.clone(),
+        dest_currency: currency.clone(),
         capacity: 250,
         source: PublicKey::from(PublicKey::from(&[0xee; PublicKey::len()])),
         destination: PublicKey::from(PublicKey::from(&[0xff; PublicKey::len()])),
         opt_exclude: None,
+        exclude_edges: Vec::new(),
+        exclude_nodes: Vec::new(),
+        opt_max_hops: None,
+        opt_max_fees: None,
     };
 
     // Request routes from IndexClient (From AppServer):
@@ -0,0 +1,390 @@
+use std::collections::HashSet;
+use std::convert::TryFrom;
+use std::marker::Unpin;
+
+use futures::task::{Spawn, SpawnExt};
+use futures::{FutureExt, SinkExt, Stream, StreamExt, TryFutureExt};
+
+use common::conn::ConnPair;
+
+use proto::crypto::PublicKey;
+use proto::funder::messages::{Currency, ExchangeRate, Rate};
+use proto::index_server::messages::{
+    AdminEdge, CurrencyGraphStats, IndexAdminToServer, IndexServerToAdmin, ResponseEdges,
+    ResponseFederation, ResponseGraphStats,
+};
+
+use crate::federation::FederationClient;
+use crate::graph::capacity_graph::SnapshotEdge;
+use crate::graph::graph_service::{GraphClient, GraphSnapshot};
+
+pub type AdminConn<A> = ConnPair<IndexServerToAdmin<A>, IndexAdminToServer<A>>;
+
+#[derive(Debug)]
+pub enum AdminError {
+    GraphClientError,
+    FederationClientError,
+    AdminSenderError,
+    SpawnError,
+}
+
+fn admin_edge(
+    currency: &Currency,
+    snapshot_edge: SnapshotEdge<PublicKey, u128, Rate>,
+) -> AdminEdge {
+    AdminEdge {
+        currency: currency.clone(),
+        from_public_key: snapshot_edge.from,
+        to_public_key: snapshot_edge.to,
+        recv_capacity: snapshot_edge.capacity_edge.recv_capacity,
+        rate: snapshot_edge.capacity_edge.rate,
+        age: snapshot_edge.age,
+    }
+}
+
+/// Count the nodes and edges of every currency graph.
+fn graph_stats(
+    graph_snapshot: &GraphSnapshot<Currency, PublicKey, u128, Rate>,
+) -> Vec<CurrencyGraphStats> {
+    let mut currencies_stats: Vec<_> = graph_snapshot
+        .iter()
+        .map(|(currency, snapshot_edges)| {
+            let nodes: HashSet<_> = snapshot_edges
+                .iter()
+                .flat_map(|snapshot_edge| vec![&snapshot_edge.from, &snapshot_edge.to])
+                .collect();
+            CurrencyGraphStats {
+                currency: currency.clone(),
+                num_nodes: u64::try_from(nodes.len()).unwrap_or(u64::MAX),
+                num_edges: u64::try_from(snapshot_edges.len()).unwrap_or(u64::MAX),
+            }
+        })
+        .collect();
+    currencies_stats.sort_by(|a, b| a.currency.cmp(&b.currency));
+    currencies_stats
+}
+
+async fn graph_snapshot(
+    graph_client: &mut GraphClient<Currency, PublicKey, u128, Rate, ExchangeRate>,
+) -> Result<GraphSnapshot<Currency, PublicKey, u128, Rate>, AdminError> {
+    graph_client
+        .snapshot()
+        .await
+        .map_err(|_| AdminError::GraphClientError)
+}
+
+async fn handle_admin_request<A>(
+    graph_client: &mut GraphClient<Currency, PublicKey, u128, Rate, ExchangeRate>,
+    federation_client: &mut FederationClient<A>,
+    admin_request: IndexAdminToServer<A>,
+) -> Result<IndexServerToAdmin<A>, AdminError> {
+    // Graph queries are answered from a snapshot of the graphs.
+    // Federation requests are handled by the server loop.
+    Ok(match admin_request {
+        IndexAdminToServer::RequestGraphStats(request_id) => {
+            IndexServerToAdmin::ResponseGraphStats(ResponseGraphStats {
+                request_id,
+                currencies_stats: graph_stats(&graph_snapshot(graph_client).await?),
+            })
+        }
+        IndexAdminToServer::RequestNodeEdges(request_node_edges) => {
+            let node_public_key = &request_node_edges.node_public_key;
+            let mut edges = Vec::new();
+            for (currency, snapshot_edges) in &graph_snapshot(graph_client).await? {
+                edges.extend(
+                    snapshot_edges
+                        .iter()
+                        .filter(|snapshot_edge| {
+                            &snapshot_edge.from == node_public_key
+                                || &snapshot_edge.to == node_public_key
+                        })
+                        .cloned()
+                        .map(|snapshot_edge| admin_edge(currency, snapshot_edge)),
+                );
+            }
+            IndexServerToAdmin::ResponseEdges(ResponseEdges {
+                request_id: request_node_edges.request_id,
+                edges,
+            })
+        }
+        IndexAdminToServer::RequestExportGraph(request_export_graph) => {
+            let currency = request_export_graph.currency;
+            let edges = graph_snapshot(graph_client)
+                .await?
+                .get(&currency)
+                .map(|snapshot_edges| {
+                    snapshot_edges
+                        .iter()
+                        .cloned()
+                        .map(|snapshot_edge| admin_edge(&currency, snapshot_edge))
+                        .collect()
+                })
+                .unwrap_or_default();
+            IndexServerToAdmin::ResponseEdges(ResponseEdges {
+                request_id: request_export_graph.request_id,
+                edges,
+            })
+        }
+        IndexAdminToServer::RequestFederation(request_id) => {
+            IndexServerToAdmin::ResponseFederation(ResponseFederation {
+                request_id,
+                servers: federation_client
+                    .get_topology()
+                    .await
+                    .map_err(|_| AdminError::FederationClientError)?,
+            })
+        }
+        IndexAdminToServer::RequestAddServer(request_add_server) => {
+            IndexServerToAdmin::ResponseFederation(ResponseFederation {
+                request_id: request_add_server.request_id,
+                servers: federation_client
+                    .add_server(request_add_server.server_address)
+                    .await
+                    .map_err(|_| AdminError::FederationClientError)?,
+            })
+        }
+        IndexAdminToServer::RequestRemoveServer(request_remove_server) => {
+            IndexServerToAdmin::ResponseFederation(ResponseFederation {
+                request_id: request_remove_server.request_id,
+                servers: federation_client
+                    .remove_server(request_remove_server.public_key)
+                    .await
+                    .map_err(|_| AdminError::FederationClientError)?,
+            })
+        }
+    })
+}
+
+async fn admin_handler<A>(
+    mut graph_client: GraphClient<Currency, PublicKey, u128, Rate, ExchangeRate>,
+    mut federation_client: FederationClient<A>,
+    admin_conn: AdminConn<A>,
+) -> Result<(), AdminError> {
+    let (mut sender, mut receiver) = admin_conn.split();
+
+    while let Some(admin_request) = receiver.next().await {
+        let admin_response =
+            handle_admin_request(&mut graph_client, &mut federation_client, admin_request).await?;
+        sender
+            .send(admin_response)
+            .await
+            .map_err(|_| AdminError::AdminSenderError)?;
+    }
+    Ok(())
+}
+
+/// Serve queries of index server administrators.
+/// Connections from public keys that are not in `admins` are dropped.
+pub async fn admin_loop<A, IA, S>(
+    graph_client: GraphClient<Currency, PublicKey, u128, Rate, ExchangeRate>,
+    federation_client: FederationClient<A>,
+    mut incoming_admin_connections: IA,
+    admins: HashSet<PublicKey>,
+    spawner: S,
+) -> Result<(), AdminError>
+where
+    A: Clone + Send + 'static,
+    IA: Stream<Item = (PublicKey, AdminConn<A>)> + Unpin,
+    S: Spawn,
+{
+    while let Some((public_key, admin_conn)) = incoming_admin_connections.next().await {
+        if !admins.contains(&public_key) {
+            warn!(
+                "admin_loop(): Non admin {:?} attempted connection. Aborting.",
+                public_key
+            );
+            continue;
+        }
+
+        let admin_fut = admin_handler(graph_client.clone(), federation_client.clone(), admin_conn)
+            .map_err(|e| warn!("admin_handler() error: {:?}", e))
+            .map(|_| ());
+        spawner
+            .spawn(admin_fut)
+            .map_err(|_| AdminError::SpawnError)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::channel::mpsc;
+    use futures::executor::{block_on, ThreadPool};
+
+    use proto::crypto::Uid;
+    use proto::index_server::messages::{RequestExportGraph, RequestNodeEdges};
+    use proto::net::messages::NetAddress;
+
+    use crate::federation::FederationRequest;
+
+    use crate::graph::capacity_graph::CapacityEdge;
+    use crate::graph::graph_service::create_graph_service;
+    use crate::graph::simple_capacity_graph::SimpleCapacityGraph;
+
+    async fn task_admin_loop_basic<S>(spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let currency1 = Currency::try_from("FST".to_owned()).unwrap();
+        let pk_a = PublicKey::from(&[0xaa; PublicKey::len()]);
+        let pk_b = PublicKey::from(&[0xbb; PublicKey::len()]);
+        let pk_c = PublicKey::from(&[0xcc; PublicKey::len()]);
+        let pk_admin = PublicKey::from(&[0xdd; PublicKey::len()]);
+
+        let rate = Rate { mul: 0, add: 1 };
+        let mut graph_snapshot = GraphSnapshot::new();
+        graph_snapshot.insert(
+            currency1.clone(),
+            vec![
+                SnapshotEdge {
+                    from: pk_a.clone(),
+                    to: pk_b.clone(),
+                    capacity_edge: CapacityEdge::new(10, rate.clone()),
+                    age: 0,
+                },
+                SnapshotEdge {
+                    from: pk_b.clone(),
+                    to: pk_c.clone(),
+                    capacity_edge: CapacityEdge::new(20, rate.clone()),
+                    age: 0,
+                },
+            ],
+        );
+
+        let graph_client = create_graph_service::<_, _, _, _, _, SimpleCapacityGraph<_, _>, _, _>(
+            graph_snapshot,
+            0x10,
+            spawner.clone(),
+            spawner.clone(),
+        )
+        .unwrap();
+
+        let (federation_requests_sender, mut federation_requests_receiver) =
+            mpsc::channel::<FederationRequest<NetAddress>>(0);
+        let federation_client = FederationClient::new(federation_requests_sender);
+
+        let (mut incoming_sender, incoming_admin_connections) = mpsc::channel(0);
+        spawner
+            .spawn(
+                admin_loop(
+                    graph_client,
+                    federation_client,
+                    incoming_admin_connections,
+                    vec![pk_admin.clone()].into_iter().collect(),
+                    spawner.clone(),
+                )
+                .map(|_| ()),
+            )
+            .unwrap();
+
+        // A non admin connection is dropped:
+        let (_local_sender, remote_receiver) = mpsc::channel(0);
+        let (remote_sender, mut local_receiver) = mpsc::channel(0);
+        incoming_sender
+            .send((
+                pk_a.clone(),
+                ConnPair::from_raw(remote_sender, remote_receiver),
+            ))
+            .await
+            .unwrap();
+        assert!(local_receiver.next().await.is_none());
+
+        // Admin connection:
+        let (mut local_sender, remote_receiver) = mpsc::channel(0);
+        let (remote_sender, mut local_receiver) = mpsc::channel(0);
+        incoming_sender
+            .send((
+                pk_admin.clone(),
+                ConnPair::from_raw(remote_sender, remote_receiver),
+            ))
+            .await
+            .unwrap();
+
+        let request_id = Uid::from(&[0; Uid::len()]);
+        local_sender
+            .send(IndexAdminToServer::RequestGraphStats(request_id.clone()))
+            .await
+            .unwrap();
+        match local_receiver.next().await.unwrap() {
+            IndexServerToAdmin::ResponseGraphStats(response_graph_stats) => {
+                assert_eq!(response_graph_stats.request_id, request_id);
+                assert_eq!(
+                    response_graph_stats.currencies_stats,
+                    vec![CurrencyGraphStats {
+                        currency: currency1.clone(),
+                        num_nodes: 3,
+                        num_edges: 2,
+                    }]
+                );
+            }
+            _ => unreachable!(),
+        };
+
+        local_sender
+            .send(IndexAdminToServer::RequestNodeEdges(RequestNodeEdges {
+                request_id: request_id.clone(),
+                node_public_key: pk_c.clone(),
+            }))
+            .await
+            .unwrap();
+        match local_receiver.next().await.unwrap() {
+            IndexServerToAdmin::ResponseEdges(response_edges) => {
+                assert_eq!(response_edges.request_id, request_id);
+                assert_eq!(
+                    response_edges.edges,
+                    vec![AdminEdge {
+                        currency: currency1.clone(),
+                        from_public_key: pk_b.clone(),
+                        to_public_key: pk_c.clone(),
+                        recv_capacity: 20,
+                        rate: rate.clone(),
+                        age: 0,
+                    }]
+                );
+            }
+            _ => unreachable!(),
+        };
+
+        local_sender
+            .send(IndexAdminToServer::RequestExportGraph(RequestExportGraph {
+                request_id: request_id.clone(),
+                currency: currency1.clone(),
+            }))
+            .await
+            .unwrap();
+        match local_receiver.next().await.unwrap() {
+            IndexServerToAdmin::ResponseEdges(response_edges) => {
+                assert_eq!(response_edges.request_id, request_id);
+                assert_eq!(response_edges.edges.len(), 2);
+            }
+            _ => unreachable!(),
+        };
+
+        // Federation requests are forwarded to the server loop:
+        local_sender
+            .send(IndexAdminToServer::RequestFederation(request_id.clone()))
+            .await
+            .unwrap();
+        match federation_requests_receiver.next().await.unwrap() {
+            FederationRequest::GetTopology(response_sender) => {
+                response_sender.send(Vec::new()).unwrap();
+            }
+            _ => unreachable!(),
+        };
+        match local_receiver.next().await.unwrap() {
+            IndexServerToAdmin::ResponseFederation(response_federation) => {
+                assert_eq!(response_federation.request_id, request_id);
+                assert!(response_federation.servers.is_empty());
+            }
+            _ => unreachable!(),
+        };
+    }
+
+    #[test]
+    fn test_admin_loop_basic() {
+        let thread_pool = ThreadPool::new().unwrap();
+        block_on(task_admin_loop_basic(thread_pool.clone()));
+    }
+}
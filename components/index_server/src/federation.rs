@@ -0,0 +1,84 @@
+use futures::channel::{mpsc, oneshot};
+use futures::SinkExt;
+
+use proto::crypto::PublicKey;
+use proto::index_server::messages::{FederationServer, IndexServerAddress};
+
+/// Requests for managing the trusted servers of a running index server.
+/// Every request is answered with the resulting federation topology.
+#[derive(Debug)]
+pub enum FederationRequest<A> {
+    /// Get the current federation topology
+    GetTopology(oneshot::Sender<Vec<FederationServer<A>>>),
+    /// Add a trusted server
+    AddServer(
+        IndexServerAddress<A>,
+        oneshot::Sender<Vec<FederationServer<A>>>,
+    ),
+    /// Remove a trusted server
+    RemoveServer(PublicKey, oneshot::Sender<Vec<FederationServer<A>>>),
+}
+
+#[derive(Debug)]
+pub enum FederationClientError {
+    SendRequestError,
+    ResponseReceiverClosed,
+}
+
+impl From<oneshot::Canceled> for FederationClientError {
+    fn from(_from: oneshot::Canceled) -> FederationClientError {
+        FederationClientError::ResponseReceiverClosed
+    }
+}
+
+impl From<mpsc::SendError> for FederationClientError {
+    fn from(_from: mpsc::SendError) -> FederationClientError {
+        FederationClientError::SendRequestError
+    }
+}
+
+#[derive(Clone)]
+pub struct FederationClient<A> {
+    requests_sender: mpsc::Sender<FederationRequest<A>>,
+}
+
+impl<A> FederationClient<A> {
+    pub fn new(requests_sender: mpsc::Sender<FederationRequest<A>>) -> Self {
+        FederationClient { requests_sender }
+    }
+
+    /// Get the current federation topology: All trusted servers, and the servers they announced.
+    pub async fn get_topology(
+        &mut self,
+    ) -> Result<Vec<FederationServer<A>>, FederationClientError> {
+        let (sender, receiver) = oneshot::channel();
+        self.requests_sender
+            .send(FederationRequest::GetTopology(sender))
+            .await?;
+        Ok(receiver.await?)
+    }
+
+    /// Add a trusted server. Returns the resulting federation topology.
+    pub async fn add_server(
+        &mut self,
+        server_address: IndexServerAddress<A>,
+    ) -> Result<Vec<FederationServer<A>>, FederationClientError> {
+        let (sender, receiver) = oneshot::channel();
+        self.requests_sender
+            .send(FederationRequest::AddServer(server_address, sender))
+            .await?;
+        Ok(receiver.await?)
+    }
+
+    /// Remove a trusted server. Returns the resulting federation topology.
+    pub async fn remove_server(
+        &mut self,
+        public_key: PublicKey,
+    ) -> Result<Vec<FederationServer<A>>, FederationClientError> {
+        let (sender, receiver) = oneshot::channel();
+        self.requests_sender
+            .send(FederationRequest::RemoveServer(public_key, sender))
+            .await?;
+        Ok(receiver.await?)
+    }
+}
@@ -43,6 +43,48 @@ pub struct CapacityMultiRoute<N, C, T> {
     pub routes: Vec<CapacityRoute<N, C, T>>,
 }
 
+/// A directed edge, as saved in a snapshot of a CapacityGraph.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SnapshotEdge<N, C, T> {
+    pub from: N,
+    pub to: N,
+    pub capacity_edge: CapacityEdge<C, T>,
+    /// Amount of ticks passed since the edge was last updated.
+    pub age: u128,
+}
+
+/// Constraints over the routes returned from a CapacityGraph.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RouteConstraints<N, C> {
+    /// Directed edges that must not show up in any route.
+    /// This can be useful for finding non trivial loops.
+    pub exclude_edges: Vec<(N, N)>,
+    /// Nodes that must not show up in any route (Except for the source and destination nodes).
+    pub exclude_nodes: Vec<N>,
+    /// Maximum amount of hops (Edges) in a route.
+    pub opt_max_hops: Option<usize>,
+    /// Maximum fees for sending the requested capacity along a route.
+    pub opt_max_fees: Option<C>,
+}
+
+impl<N, C> RouteConstraints<N, C> {
+    /// No constraints
+    pub fn new() -> Self {
+        RouteConstraints {
+            exclude_edges: Vec::new(),
+            exclude_nodes: Vec::new(),
+            opt_max_hops: None,
+            opt_max_fees: None,
+        }
+    }
+}
+
+impl<N, C> Default for RouteConstraints<N, C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub trait CapacityGraph {
     type Node; // Node type
     type Capacity; // Directed capacity between two neighboring nodes
@@ -76,16 +118,34 @@ pub trait CapacityGraph {
     /// - Capacity (Amount of credits we can push along that route)
     /// - Rate: Aggregated rate of how much it costs to send credits along that route.
     ///
-    /// opt_exclude is an optional edge to exclude (All of the returned routes must not go through this
-    /// edge). This can be useful for finding non trivial loops.
+    /// All of the returned routes must satisfy `constraints`.
     fn get_multi_routes(
         &self,
         a: &Self::Node,
         b: &Self::Node,
         capacity: Self::Capacity,
-        opt_exclude: Option<(&Self::Node, &Self::Node)>,
+        constraints: &RouteConstraints<Self::Node, Self::Capacity>,
     ) -> Vec<CapacityMultiRoute<Self::Node, Self::Capacity, Self::Rate>>;
 
     /// Simulate advancement of time. Used to remove old edges.
     fn tick(&mut self, a: &Self::Node);
+
+    /// Export all edges, together with their ages.
+    fn snapshot(&self) -> Vec<SnapshotEdge<Self::Node, Self::Capacity, Self::Rate>>;
+
+    /// Restore an edge from a snapshot.
+    /// A restored edge is considered stale until it is updated, and is removed after
+    /// `stale_ticks` calls to `tick_stale()` if it is not updated by then. A restored edge
+    /// also ages normally whenever its node ticks.
+    /// Does nothing if the edge already exists.
+    fn restore_edge(
+        &mut self,
+        snapshot_edge: SnapshotEdge<Self::Node, Self::Capacity, Self::Rate>,
+        stale_ticks: usize,
+    );
+
+    /// Simulate advancement of time for stale edges. Used to remove restored edges that were
+    /// never updated.
+    /// Returns true if the CapacityGraph is now empty
+    fn tick_stale(&mut self) -> bool;
 }
@@ -5,9 +5,11 @@ use futures::channel::{mpsc, oneshot};
 use futures::task::{Spawn, SpawnError, SpawnExt};
 use futures::{FutureExt, SinkExt, StreamExt, TryFutureExt};
 
-use super::capacity_graph::{CapacityEdge, CapacityGraph, CapacityMultiRoute};
+use super::capacity_graph::{
+    CapacityEdge, CapacityGraph, CapacityMultiRoute, RouteConstraints, SnapshotEdge,
+};
 
-pub enum GraphRequest<G, N, C, T> {
+pub enum GraphRequest<G, N, C, T, X> {
     /// Change capacities on a directed edge:
     UpdateEdge(
         G,
@@ -22,19 +24,36 @@ pub enum GraphRequest<G, N, C, T> {
     /// Note: This will not remove edges going to this node.
     RemoveNode(N, oneshot::Sender<()>),
     /// Get some routes from one node to another of at least certain capacity.
-    /// If an exclude directed edge is provided, the routes must not contain this directed edge.
+    /// The returned routes must satisfy the given route constraints.
     GetMultiRoutes(
         G,
         N,
         N,
         C,
-        Option<(N, N)>,
+        RouteConstraints<N, C>,
         oneshot::Sender<Vec<CapacityMultiRoute<N, C, T>>>,
-    ), // (from, to, capacity, opt_exclude)
+    ), // (from, to, capacity, constraints)
     /// Expire old outgoing edges for the specified node
     Tick(N, oneshot::Sender<()>),
+    /// Set the exchange rate a node offers from one graph (currency) to another:
+    UpdateExchange(G, G, N, X, oneshot::Sender<Option<X>>), // (src, dest, node, rate)
+    /// Remove an exchange offered by a node:
+    RemoveExchange(G, G, N, oneshot::Sender<Option<X>>), // (src, dest, node)
+    /// Get all the nodes that exchange from one graph (currency) to another, together with
+    /// their exchange rates.
+    GetExchanges(G, G, oneshot::Sender<Vec<(N, X)>>), // (src, dest)
+    /// Export all edges of all graphs (currencies), together with their ages.
+    Snapshot(oneshot::Sender<GraphSnapshot<G, N, C, T>>),
+    /// Expire old stale edges (Edges that were restored from a snapshot and not updated since)
+    TickStale(oneshot::Sender<()>),
 }
 
+/// Exchanges offered by nodes: (src, dest) -> (node -> exchange_rate)
+type Exchanges<G, N, X> = HashMap<(G, G), HashMap<N, X>>;
+
+/// Edges of all graphs (currencies): g -> edges
+pub type GraphSnapshot<G, N, C, T> = HashMap<G, Vec<SnapshotEdge<N, C, T>>>;
+
 #[derive(Debug)]
 pub enum GraphServiceError {
     /// Failed to spawn to self ThreadPool
@@ -44,11 +63,14 @@ pub enum GraphServiceError {
 #[allow(clippy::many_single_char_names)]
 /// Process one GraphRequest, and send the response through the provided sender.
 /// This function might perform a long computation and take a long time to complete.
-fn process_request<G, N, C, T, CG>(
+fn process_request<G, N, C, T, X, CG>(
     capacity_graphs: &mut HashMap<G, CG>,
-    graph_request: GraphRequest<G, N, C, T>,
+    exchanges: &mut Exchanges<G, N, X>,
+    graph_request: GraphRequest<G, N, C, T, X>,
 ) where
-    G: Hash + Eq,
+    G: Hash + Eq + Clone,
+    N: Hash + Eq + Clone,
+    X: Clone,
     CG: CapacityGraph<Node = N, Capacity = C, Rate = T>,
 {
     match graph_request {
@@ -63,16 +85,15 @@ fn process_request<G, N, C, T, CG>(
         }
         GraphRequest::RemoveNode(a, sender) => {
             capacity_graphs.retain(|_g, capacity_graph| capacity_graph.remove_node(&a));
+            exchanges.retain(|_gs, node_exchanges| {
+                let _ = node_exchanges.remove(&a);
+                !node_exchanges.is_empty()
+            });
             let _ = sender.send(());
         }
-        GraphRequest::GetMultiRoutes(g, a, b, capacity, opt_exclude, sender) => {
+        GraphRequest::GetMultiRoutes(g, a, b, capacity, constraints, sender) => {
             let routes = if let Some(capacity_graph) = capacity_graphs.get_mut(&g) {
-                match opt_exclude {
-                    Some((c, d)) => {
-                        capacity_graph.get_multi_routes(&a, &b, capacity, Some((&c, &d)))
-                    }
-                    None => capacity_graph.get_multi_routes(&a, &b, capacity, None),
-                }
+                capacity_graph.get_multi_routes(&a, &b, capacity, &constraints)
             } else {
                 vec![]
             };
@@ -84,36 +105,82 @@ fn process_request<G, N, C, T, CG>(
             }
             let _ = sender.send(());
         }
+        GraphRequest::UpdateExchange(g_src, g_dest, a, exchange_rate, sender) => {
+            let node_exchanges = exchanges
+                .entry((g_src, g_dest))
+                .or_insert_with(HashMap::new);
+            let _ = sender.send(node_exchanges.insert(a, exchange_rate));
+        }
+        GraphRequest::RemoveExchange(g_src, g_dest, a, sender) => {
+            let gs = (g_src, g_dest);
+            let opt_exchange_rate = if let Some(node_exchanges) = exchanges.get_mut(&gs) {
+                let opt_exchange_rate = node_exchanges.remove(&a);
+                if node_exchanges.is_empty() {
+                    let _ = exchanges.remove(&gs);
+                }
+                opt_exchange_rate
+            } else {
+                None
+            };
+            let _ = sender.send(opt_exchange_rate);
+        }
+        GraphRequest::GetExchanges(g_src, g_dest, sender) => {
+            let node_exchanges = if let Some(node_exchanges) = exchanges.get(&(g_src, g_dest)) {
+                node_exchanges
+                    .iter()
+                    .map(|(a, exchange_rate)| (a.clone(), exchange_rate.clone()))
+                    .collect()
+            } else {
+                vec![]
+            };
+            let _ = sender.send(node_exchanges);
+        }
+        GraphRequest::Snapshot(sender) => {
+            let graph_snapshot = capacity_graphs
+                .iter()
+                .map(|(g, capacity_graph)| (g.clone(), capacity_graph.snapshot()))
+                .collect();
+            let _ = sender.send(graph_snapshot);
+        }
+        GraphRequest::TickStale(sender) => {
+            capacity_graphs.retain(|_g, capacity_graph| !capacity_graph.tick_stale());
+            let _ = sender.send(());
+        }
     }
 }
 
-async fn graph_service_loop<G, N, C, T, CG, GS>(
+async fn graph_service_loop<G, N, C, T, X, CG, GS>(
     mut capacity_graphs: HashMap<G, CG>,
-    mut incoming_requests: mpsc::Receiver<GraphRequest<G, N, C, T>>,
+    mut incoming_requests: mpsc::Receiver<GraphRequest<G, N, C, T, X>>,
     graph_service_spawner: GS,
 ) -> Result<(), GraphServiceError>
 where
-    G: Send + Hash + Eq + 'static,
-    N: Send + 'static,
+    G: Send + Hash + Eq + Clone + 'static,
+    N: Send + Hash + Eq + Clone + 'static,
     C: Send + 'static,
     T: Send + 'static,
+    X: Send + Clone + 'static,
     CG: CapacityGraph<Node = N, Capacity = C, Rate = T> + Send + 'static,
     GS: Spawn,
 {
     // We use a separate spawner to be used for long graph computations.
     // We don't want to block the external shared thread pool.
 
+    let mut exchanges = Exchanges::<G, N, X>::new();
+
     while let Some(graph_request) = incoming_requests.next().await {
         // Run the graph computation over own pool:
         let process_request_handle = graph_service_spawner
             .spawn_with_handle(async move {
-                process_request(&mut capacity_graphs, graph_request);
-                capacity_graphs
+                process_request(&mut capacity_graphs, &mut exchanges, graph_request);
+                (capacity_graphs, exchanges)
             })
             .map_err(|_| GraphServiceError::LocalSpawnError)?;
 
         // Wait for completion of the computation on the external pool:
-        capacity_graphs = process_request_handle.await;
+        let (new_capacity_graphs, new_exchanges) = process_request_handle.await;
+        capacity_graphs = new_capacity_graphs;
+        exchanges = new_exchanges;
     }
     Ok(())
 }
@@ -137,12 +204,12 @@ impl From<mpsc::SendError> for GraphClientError {
 }
 
 #[derive(Clone)]
-pub struct GraphClient<G, N, C, T> {
-    requests_sender: mpsc::Sender<GraphRequest<G, N, C, T>>,
+pub struct GraphClient<G, N, C, T, X> {
+    requests_sender: mpsc::Sender<GraphRequest<G, N, C, T, X>>,
 }
 
-impl<G, N, C, T> GraphClient<G, N, C, T> {
-    pub fn new(requests_sender: mpsc::Sender<GraphRequest<G, N, C, T>>) -> Self {
+impl<G, N, C, T, X> GraphClient<G, N, C, T, X> {
+    pub fn new(requests_sender: mpsc::Sender<GraphRequest<G, N, C, T, X>>) -> Self {
         GraphClient { requests_sender }
     }
 
@@ -190,15 +257,15 @@ impl<G, N, C, T> GraphClient<G, N, C, T> {
     /// Obtain routes with capacity at least `capacity`.
     /// Returns each route together with the capacity it is possible to send through that route.
     ///
-    /// opt_exclude is an optional edge to exclude (The returned route must not go through this
-    /// edge). This can be useful for finding non trivial loops.
+    /// `constraints` restricts the returned routes: Excluded edges and nodes, maximum amount of
+    /// hops and maximum fees. Excluding edges can be useful for finding non trivial loops.
     pub async fn get_multi_routes(
         &mut self,
         g: G,
         a: N,
         b: N,
         capacity: C,
-        opt_exclude: Option<(N, N)>,
+        constraints: RouteConstraints<N, C>,
     ) -> Result<Vec<CapacityMultiRoute<N, C, T>>, GraphClientError> {
         let (sender, receiver) = oneshot::channel();
         self.requests_sender
@@ -207,7 +274,7 @@ impl<G, N, C, T> GraphClient<G, N, C, T> {
                 a,
                 b,
                 capacity,
-                opt_exclude,
+                constraints,
                 sender,
             ))
             .await?;
@@ -222,26 +289,105 @@ impl<G, N, C, T> GraphClient<G, N, C, T> {
             .await?;
         Ok(receiver.await?)
     }
+
+    /// Set the exchange rate node `a` offers from `g_src` to `g_dest`.
+    /// Returns the previous exchange rate, if existed.
+    pub async fn update_exchange(
+        &mut self,
+        g_src: G,
+        g_dest: G,
+        a: N,
+        exchange_rate: X,
+    ) -> Result<Option<X>, GraphClientError> {
+        let (sender, receiver) = oneshot::channel();
+        self.requests_sender
+            .send(GraphRequest::UpdateExchange(
+                g_src,
+                g_dest,
+                a,
+                exchange_rate,
+                sender,
+            ))
+            .await?;
+        Ok(receiver.await?)
+    }
+
+    /// Remove the exchange node `a` offers from `g_src` to `g_dest`.
+    pub async fn remove_exchange(
+        &mut self,
+        g_src: G,
+        g_dest: G,
+        a: N,
+    ) -> Result<Option<X>, GraphClientError> {
+        let (sender, receiver) = oneshot::channel();
+        self.requests_sender
+            .send(GraphRequest::RemoveExchange(g_src, g_dest, a, sender))
+            .await?;
+        Ok(receiver.await?)
+    }
+
+    /// Get all nodes that exchange from `g_src` to `g_dest`, together with their exchange rates.
+    pub async fn get_exchanges(
+        &mut self,
+        g_src: G,
+        g_dest: G,
+    ) -> Result<Vec<(N, X)>, GraphClientError> {
+        let (sender, receiver) = oneshot::channel();
+        self.requests_sender
+            .send(GraphRequest::GetExchanges(g_src, g_dest, sender))
+            .await?;
+        Ok(receiver.await?)
+    }
+
+    /// Export all edges of all graphs, together with their ages.
+    pub async fn snapshot(&mut self) -> Result<GraphSnapshot<G, N, C, T>, GraphClientError> {
+        let (sender, receiver) = oneshot::channel();
+        self.requests_sender
+            .send(GraphRequest::Snapshot(sender))
+            .await?;
+        Ok(receiver.await?)
+    }
+
+    /// Expire old stale edges
+    pub async fn tick_stale(&mut self) -> Result<(), GraphClientError> {
+        let (sender, receiver) = oneshot::channel();
+        self.requests_sender
+            .send(GraphRequest::TickStale(sender))
+            .await?;
+        Ok(receiver.await?)
+    }
 }
 
 /// Spawn a graph service, returning a GraphClient on success.
 /// GraphClient can be cloned to allow multiple clients.
-pub fn create_graph_service<G, N, C, T, CG, GS, S>(
+///
+/// The graphs are initialized from `graph_snapshot`. All restored edges are considered stale,
+/// and are removed if they are not updated within `stale_ticks` stale ticks.
+pub fn create_graph_service<G, N, C, T, X, CG, GS, S>(
+    graph_snapshot: GraphSnapshot<G, N, C, T>,
+    stale_ticks: usize,
     graph_service_spawner: GS,
     spawner: S,
-) -> Result<GraphClient<G, N, C, T>, SpawnError>
+) -> Result<GraphClient<G, N, C, T, X>, SpawnError>
 where
-    G: Hash + Eq + Send + 'static,
-    N: Send + 'static,
+    G: Hash + Eq + Clone + Send + 'static,
+    N: Hash + Eq + Clone + Send + 'static,
     C: Send + 'static,
     T: Send + 'static,
+    X: Clone + Send + 'static,
     CG: CapacityGraph<Node = N, Capacity = C, Rate = T> + Send + 'static,
     GS: Spawn + Send + 'static,
     S: Spawn,
 {
     let (requests_sender, requests_receiver) = mpsc::channel(0);
 
-    let capacity_graphs = HashMap::<G, CG>::new();
+    let mut capacity_graphs = HashMap::<G, CG>::new();
+    for (g, snapshot_edges) in graph_snapshot {
+        let capacity_graph = capacity_graphs.entry(g).or_insert_with(CG::new);
+        for snapshot_edge in snapshot_edges {
+            capacity_graph.restore_edge(snapshot_edge, stale_ticks);
+        }
+    }
 
     let graph_service_loop_fut =
         graph_service_loop(capacity_graphs, requests_receiver, graph_service_spawner)
@@ -275,10 +421,11 @@ mod tests {
             u32,
             u128,
             ConstRate,
+            u64,
             SimpleCapacityGraph<u32, ConstRate>,
             _,
             _,
-        >(graph_service_spawner, spawner)
+        >(GraphSnapshot::new(), 0, graph_service_spawner, spawner)
         .unwrap();
 
         graph_client
@@ -292,7 +439,7 @@ mod tests {
 
         assert_eq!(
             graph_client
-                .get_multi_routes(currency1, 2, 5, 29, None)
+                .get_multi_routes(currency1, 2, 5, 29, RouteConstraints::new())
                 .await
                 .unwrap(),
             vec![CapacityMultiRoute {
@@ -305,7 +452,7 @@ mod tests {
         );
         assert_eq!(
             graph_client
-                .get_multi_routes(currency1, 2, 5, 30, None)
+                .get_multi_routes(currency1, 2, 5, 30, RouteConstraints::new())
                 .await
                 .unwrap(),
             vec![CapacityMultiRoute {
@@ -318,7 +465,7 @@ mod tests {
         );
         assert_eq!(
             graph_client
-                .get_multi_routes(currency1, 2, 5, 31, None)
+                .get_multi_routes(currency1, 2, 5, 31, RouteConstraints::new())
                 .await
                 .unwrap(),
             vec![]
@@ -326,12 +473,82 @@ mod tests {
 
         graph_client.tick(2).await.unwrap();
 
+        let mut snapshot_edges = graph_client
+            .snapshot()
+            .await
+            .unwrap()
+            .remove(&currency1)
+            .unwrap();
+        snapshot_edges.sort_by_key(|snapshot_edge| snapshot_edge.from);
+        assert_eq!(
+            snapshot_edges,
+            vec![
+                SnapshotEdge {
+                    from: 2,
+                    to: 5,
+                    capacity_edge: CapacityEdge::new(5, ConstRate(1)),
+                    age: 1,
+                },
+                SnapshotEdge {
+                    from: 5,
+                    to: 2,
+                    capacity_edge: CapacityEdge::new(30, ConstRate(1)),
+                    age: 0,
+                },
+            ]
+        );
+
         assert_eq!(
             graph_client.remove_edge(currency1, 2, 5).await.unwrap(),
             Some(CapacityEdge::new(5, ConstRate(1)))
         );
+
+        // Exchanges:
+        let currency2 = 2u8;
+        assert_eq!(
+            graph_client
+                .update_exchange(currency1, currency2, 2, 7u64)
+                .await
+                .unwrap(),
+            None
+        );
+        assert_eq!(
+            graph_client
+                .get_exchanges(currency1, currency2)
+                .await
+                .unwrap(),
+            vec![(2, 7u64)]
+        );
+        assert_eq!(
+            graph_client
+                .get_exchanges(currency2, currency1)
+                .await
+                .unwrap(),
+            vec![]
+        );
+        assert_eq!(
+            graph_client
+                .remove_exchange(currency1, currency2, 2)
+                .await
+                .unwrap(),
+            Some(7u64)
+        );
+        graph_client
+            .update_exchange(currency1, currency2, 5, 8u64)
+            .await
+            .unwrap();
+
         graph_client.remove_node(2).await.unwrap();
         graph_client.remove_node(5).await.unwrap();
+
+        // Removing a node also removes its exchanges:
+        assert_eq!(
+            graph_client
+                .get_exchanges(currency1, currency2)
+                .await
+                .unwrap(),
+            vec![]
+        );
     }
 
     #[test]
@@ -340,6 +557,98 @@ mod tests {
 
         block_on(task_create_graph_service_basic(thread_pool.clone()));
     }
+
+    async fn task_create_graph_service_warm_start<S>(spawner: S)
+    where
+        S: Spawn,
+    {
+        let currency1 = 1u8;
+
+        let mut graph_snapshot = GraphSnapshot::new();
+        graph_snapshot.insert(
+            currency1,
+            vec![
+                SnapshotEdge {
+                    from: 2u32,
+                    to: 5u32,
+                    capacity_edge: CapacityEdge::new(5, ConstRate(1u32)),
+                    age: 0,
+                },
+                SnapshotEdge {
+                    from: 5,
+                    to: 2,
+                    capacity_edge: CapacityEdge::new(30, ConstRate(1)),
+                    age: 0,
+                },
+            ],
+        );
+
+        let graph_service_spawner = ThreadPool::new().unwrap();
+        let mut graph_client = create_graph_service::<
+            u8,
+            u32,
+            u128,
+            ConstRate,
+            u64,
+            SimpleCapacityGraph<u32, ConstRate>,
+            _,
+            _,
+        >(graph_snapshot, 32, graph_service_spawner, spawner)
+        .unwrap();
+
+        // Restored edges can be used for routes right away:
+        assert_eq!(
+            graph_client
+                .get_multi_routes(currency1, 2, 5, 30, RouteConstraints::new())
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+
+        // Refresh one of the edges:
+        graph_client
+            .update_edge(currency1, 2, 5, CapacityEdge::new(5, ConstRate(1)))
+            .await
+            .unwrap();
+
+        // Stale edges expire once the grace period passes:
+        for _ in 0..31 {
+            graph_client.tick_stale().await.unwrap();
+        }
+        assert_eq!(
+            graph_client
+                .get_multi_routes(currency1, 2, 5, 30, RouteConstraints::new())
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        graph_client.tick_stale().await.unwrap();
+        assert_eq!(
+            graph_client
+                .get_multi_routes(currency1, 2, 5, 30, RouteConstraints::new())
+                .await
+                .unwrap(),
+            vec![]
+        );
+
+        let snapshot_edges = graph_client
+            .snapshot()
+            .await
+            .unwrap()
+            .remove(&currency1)
+            .unwrap();
+        assert_eq!(snapshot_edges.len(), 1);
+        assert_eq!(snapshot_edges[0].from, 2);
+    }
+
+    #[test]
+    fn test_create_graph_service_warm_start() {
+        let thread_pool = ThreadPool::new().unwrap();
+
+        block_on(task_create_graph_service_warm_start(thread_pool.clone()));
+    }
 }
 
 // TODO: Add a test for multiple currencies at the same time (Different values for the G type)
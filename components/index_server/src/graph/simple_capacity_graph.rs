@@ -1,9 +1,10 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::{cmp, hash};
 
 use super::bfs::bfs;
 use super::capacity_graph::{
-    CapacityEdge, CapacityGraph, CapacityMultiRoute, CapacityRoute, LinearRate,
+    CapacityEdge, CapacityGraph, CapacityMultiRoute, CapacityRoute, LinearRate, RouteConstraints,
+    SnapshotEdge,
 };
 use super::utils::{option_to_vec, OptionIterator};
 
@@ -15,6 +16,9 @@ const BASE_MAX_EDGE_AGE: u128 = 16;
 struct Edge<T> {
     capacity_edge: CapacityEdge<u128, T>,
     age: u128,
+    /// Remaining timer ticks for an edge that was restored from a snapshot, and not updated
+    /// since. The edge is removed when no ticks remain.
+    opt_stale_ticks: Option<usize>,
 }
 
 impl<T> Edge<T> {
@@ -22,6 +26,7 @@ impl<T> Edge<T> {
         Edge {
             capacity_edge,
             age: 0,
+            opt_stale_ticks: None,
         }
     }
 }
@@ -63,6 +68,18 @@ where
             edge.age < max_edge_age
         });
     }
+
+    /// Count down the remaining ticks of stale edges.
+    pub fn tick_stale(&mut self) {
+        self.edges
+            .retain(|_remote_node, edge| match edge.opt_stale_ticks.as_mut() {
+                None => true,
+                Some(stale_ticks) => {
+                    *stale_ticks = stale_ticks.saturating_sub(1);
+                    *stale_ticks > 0
+                }
+            });
+    }
 }
 
 pub struct SimpleCapacityGraph<N, T> {
@@ -72,7 +89,7 @@ pub struct SimpleCapacityGraph<N, T> {
 impl<N, T> SimpleCapacityGraph<N, T>
 where
     N: cmp::Eq + hash::Hash + Clone + std::fmt::Debug,
-    T: LinearRate + Clone,
+    T: LinearRate<K = u128> + Clone,
 {
     pub fn new() -> SimpleCapacityGraph<N, T> {
         Self {
@@ -143,34 +160,66 @@ where
     /// Get a route with capacity at least `capacity`.
     /// Returns the route together with the capacity it is possible to send through the route.
     ///
-    /// opt_exclude is an optional edge to exclude (The returned route must not go through this
-    /// edge). This can be useful for finding non trivial loops.
+    /// The returned route must satisfy `constraints`. Excluding an edge can be useful for finding
+    /// non trivial loops.
     fn get_multi_route(
         &self,
         a: &N,
         b: &N,
         capacity: u128,
-        opt_exclude: Option<(&N, &N)>,
+        constraints: &RouteConstraints<N, u128>,
     ) -> Option<CapacityMultiRoute<N, u128, T>> {
         // TODO: Update this implementation:
         // Currently get_route does not attemp to find the cheapest route (according to rate)
         // It only finds the shortest route and then calculates the rate.
 
-        let (opt_e_start, opt_e_end) = match opt_exclude {
-            Some((e_start, e_end)) => (Some(e_start), Some(e_end)),
-            None => (None, None),
-        };
+        // Excluded edges, arranged by their starting node:
+        let mut exclude_edges: HashMap<&N, HashSet<&N>> = HashMap::new();
+        for (e_start, e_end) in &constraints.exclude_edges {
+            exclude_edges
+                .entry(e_start)
+                .or_insert_with(HashSet::new)
+                .insert(e_end);
+        }
+        let exclude_edges = &exclude_edges;
+
+        // The source and destination nodes can not be excluded:
+        let exclude_nodes: HashSet<&N> = constraints
+            .exclude_nodes
+            .iter()
+            .filter(|&node| node != a && node != b)
+            .collect();
+        let exclude_nodes = &exclude_nodes;
+
         let get_neighbors = |cur_node: &N| {
-            let cur_node_is_e_start = Some(cur_node) == opt_e_start;
+            let opt_exclude_next = exclude_edges.get(cur_node);
             self.neighbors_with_send_capacity(cur_node.clone(), capacity)
-                .filter(move |&next_node| !cur_node_is_e_start || Some(next_node) != opt_e_end)
+                .filter(move |&next_node| {
+                    !exclude_nodes.contains(next_node)
+                        && !opt_exclude_next
+                            .map_or(false, |exclude_next| exclude_next.contains(next_node))
+                })
         };
         let route = bfs(a, b, get_neighbors)?;
-        // We assert that we will always have valid capacity here:
-        let capacity = self.get_route_capacity(&route).unwrap();
+
+        // We always get a shortest route, hence no route with less hops exists:
+        if let Some(max_hops) = constraints.opt_max_hops {
+            if route.len().saturating_sub(1) > max_hops {
+                return None;
+            }
+        }
 
         let rate = self.get_route_rate(&route)?;
 
+        if let Some(max_fees) = constraints.opt_max_fees {
+            if rate.calc_fee(capacity)? > max_fees {
+                return None;
+            }
+        }
+
+        // We assert that we will always have valid capacity here:
+        let capacity = self.get_route_capacity(&route).unwrap();
+
         let graph_route = CapacityRoute {
             route,
             capacity,
@@ -186,7 +235,7 @@ where
 impl<N, T> CapacityGraph for SimpleCapacityGraph<N, T>
 where
     N: cmp::Eq + hash::Hash + Clone + std::fmt::Debug,
-    T: LinearRate + Clone,
+    T: LinearRate<K = u128> + Clone,
 {
     type Node = N;
     type Capacity = u128;
@@ -242,9 +291,9 @@ where
         a: &N,
         b: &N,
         capacity: u128,
-        opt_exclude: Option<(&N, &N)>,
+        constraints: &RouteConstraints<N, u128>,
     ) -> Vec<CapacityMultiRoute<N, u128, T>> {
-        option_to_vec(self.get_multi_route(a, b, capacity, opt_exclude))
+        option_to_vec(self.get_multi_route(a, b, capacity, constraints))
     }
 
     fn tick(&mut self, a: &N) {
@@ -252,6 +301,44 @@ where
             node_edges.tick();
         }
     }
+
+    fn snapshot(&self) -> Vec<SnapshotEdge<N, u128, T>> {
+        self.nodes
+            .iter()
+            .flat_map(|(a, node_edges)| {
+                node_edges.edges.iter().map(move |(b, edge)| SnapshotEdge {
+                    from: a.clone(),
+                    to: b.clone(),
+                    capacity_edge: edge.capacity_edge.clone(),
+                    age: edge.age,
+                })
+            })
+            .collect()
+    }
+
+    fn restore_edge(&mut self, snapshot_edge: SnapshotEdge<N, u128, T>, stale_ticks: usize) {
+        let SnapshotEdge {
+            from,
+            to,
+            capacity_edge,
+            age,
+        } = snapshot_edge;
+
+        let from_entry = self.nodes.entry(from).or_insert_with(NodeEdges::new);
+        from_entry.edges.entry(to).or_insert(Edge {
+            capacity_edge,
+            age,
+            opt_stale_ticks: Some(stale_ticks),
+        });
+    }
+
+    fn tick_stale(&mut self) -> bool {
+        self.nodes.retain(|_a, node_edges| {
+            node_edges.tick_stale();
+            !node_edges.edges.is_empty()
+        });
+        self.nodes.is_empty()
+    }
 }
 
 #[cfg(test)]
@@ -260,6 +347,12 @@ mod tests {
 
     use super::super::test_utils::ConstRate;
 
+    fn exclude_edge(e_start: u32, e_end: u32) -> RouteConstraints<u32, u128> {
+        let mut constraints = RouteConstraints::new();
+        constraints.exclude_edges.push((e_start, e_end));
+        constraints
+    }
+
     #[test]
     fn test_get_send_capacity_basic() {
         let mut cg = SimpleCapacityGraph::<u32, ConstRate>::new();
@@ -336,50 +429,107 @@ mod tests {
     fn test_get_multi_route() {
         let cg = example_capacity_graph();
 
-        let multi_route = cg.get_multi_route(&2, &5, 29, None).unwrap();
+        let multi_route = cg
+            .get_multi_route(&2, &5, 29, &RouteConstraints::new())
+            .unwrap();
         assert_eq!(multi_route.routes[0].route, vec![2, 5]);
         assert_eq!(multi_route.routes[0].capacity, 30);
 
-        let multi_route = cg.get_multi_route(&2, &5, 30, None).unwrap();
+        let multi_route = cg
+            .get_multi_route(&2, &5, 30, &RouteConstraints::new())
+            .unwrap();
         assert_eq!(multi_route.routes[0].route, vec![2, 5]);
         assert_eq!(multi_route.routes[0].capacity, 30);
 
-        assert!(cg.get_multi_route(&2, &5, 31, None).is_none());
+        assert!(cg
+            .get_multi_route(&2, &5, 31, &RouteConstraints::new())
+            .is_none());
 
-        let multi_route = cg.get_multi_route(&0, &5, 25, None).unwrap();
+        let multi_route = cg
+            .get_multi_route(&0, &5, 25, &RouteConstraints::new())
+            .unwrap();
         assert_eq!(multi_route.routes[0].route, vec![0, 1, 3, 4, 2, 5]);
         assert_eq!(multi_route.routes[0].capacity, 30);
 
-        let multi_route = cg.get_multi_route(&0, &5, 29, None).unwrap();
+        let multi_route = cg
+            .get_multi_route(&0, &5, 29, &RouteConstraints::new())
+            .unwrap();
         assert_eq!(multi_route.routes[0].route, vec![0, 1, 3, 4, 2, 5]);
         assert_eq!(multi_route.routes[0].capacity, 30);
 
-        let multi_route = cg.get_multi_route(&0, &5, 30, None).unwrap();
+        let multi_route = cg
+            .get_multi_route(&0, &5, 30, &RouteConstraints::new())
+            .unwrap();
         assert_eq!(multi_route.routes[0].route, vec![0, 1, 3, 4, 2, 5]);
         assert_eq!(multi_route.routes[0].capacity, 30);
 
-        assert!(cg.get_multi_route(&0, &5, 31, None).is_none());
+        assert!(cg
+            .get_multi_route(&0, &5, 31, &RouteConstraints::new())
+            .is_none());
 
         // Block an essential edge:
-        assert!(cg.get_multi_route(&0, &5, 25, Some((&3, &4))).is_none());
+        assert!(cg
+            .get_multi_route(&0, &5, 25, &exclude_edge(3, 4))
+            .is_none());
 
         // Block an essential edge but the at the reversed direction:
-        let multi_route = cg.get_multi_route(&0, &5, 25, Some((&4, &3))).unwrap();
+        let multi_route = cg.get_multi_route(&0, &5, 25, &exclude_edge(4, 3)).unwrap();
         assert_eq!(multi_route.routes[0].route, vec![0, 1, 3, 4, 2, 5]);
         assert_eq!(multi_route.routes[0].capacity, 30);
 
         // Block an edge not used for the route:
-        let multi_route = cg.get_multi_route(&0, &5, 25, Some((&1, &2))).unwrap();
+        let multi_route = cg.get_multi_route(&0, &5, 25, &exclude_edge(1, 2)).unwrap();
         assert_eq!(multi_route.routes[0].route, vec![0, 1, 3, 4, 2, 5]);
         assert_eq!(multi_route.routes[0].capacity, 30);
 
         // Use excluded edge to find a loop from 1 to 1:
-        let multi_route = cg.get_multi_route(&2, &1, 6, Some((&2, &1))).unwrap();
+        let multi_route = cg.get_multi_route(&2, &1, 6, &exclude_edge(2, 1)).unwrap();
         assert_eq!(multi_route.routes[0].route, vec![2, 4, 3, 1]);
         assert_eq!(multi_route.routes[0].capacity, 6);
 
         // Request for too much capacity:
-        assert!(cg.get_multi_route(&2, &1, 7, Some((&2, &1))).is_none());
+        assert!(cg.get_multi_route(&2, &1, 7, &exclude_edge(2, 1)).is_none());
+    }
+
+    #[test]
+    fn test_get_multi_route_constraints() {
+        let cg = example_capacity_graph();
+
+        // Exclude a node that all routes go through:
+        let mut constraints = RouteConstraints::new();
+        constraints.exclude_nodes.push(3);
+        assert!(cg.get_multi_route(&0, &5, 25, &constraints).is_none());
+
+        // Excluding the source or destination nodes has no effect:
+        let mut constraints = RouteConstraints::new();
+        constraints.exclude_nodes.push(0);
+        constraints.exclude_nodes.push(5);
+        let multi_route = cg.get_multi_route(&0, &5, 25, &constraints).unwrap();
+        assert_eq!(multi_route.routes[0].route, vec![0, 1, 3, 4, 2, 5]);
+
+        // Exclude a set of edges:
+        let mut constraints = exclude_edge(4, 3);
+        constraints.exclude_edges.push((1, 2));
+        let multi_route = cg.get_multi_route(&0, &5, 25, &constraints).unwrap();
+        assert_eq!(multi_route.routes[0].route, vec![0, 1, 3, 4, 2, 5]);
+        constraints.exclude_edges.push((4, 2));
+        assert!(cg.get_multi_route(&0, &5, 25, &constraints).is_none());
+
+        // Limit the amount of hops:
+        let mut constraints = RouteConstraints::new();
+        constraints.opt_max_hops = Some(5);
+        let multi_route = cg.get_multi_route(&0, &5, 25, &constraints).unwrap();
+        assert_eq!(multi_route.routes[0].route, vec![0, 1, 3, 4, 2, 5]);
+        constraints.opt_max_hops = Some(4);
+        assert!(cg.get_multi_route(&0, &5, 25, &constraints).is_none());
+
+        // Limit the fees (No fees are paid for the last hop):
+        let mut constraints = RouteConstraints::new();
+        constraints.opt_max_fees = Some(4);
+        let multi_route = cg.get_multi_route(&0, &5, 25, &constraints).unwrap();
+        assert_eq!(multi_route.routes[0].rate, ConstRate(4));
+        constraints.opt_max_fees = Some(3);
+        assert!(cg.get_multi_route(&0, &5, 25, &constraints).is_none());
     }
 
     #[test]
@@ -392,11 +542,15 @@ mod tests {
         cg.update_edge(2, 3, CapacityEdge::new(10, ConstRate(1)));
         cg.update_edge(3, 2, CapacityEdge::new(30, ConstRate(1)));
 
-        let multi_route = cg.get_multi_route(&0, &1, 30, None).unwrap();
+        let multi_route = cg
+            .get_multi_route(&0, &1, 30, &RouteConstraints::new())
+            .unwrap();
         assert_eq!(multi_route.routes[0].route, vec![0, 1]);
         assert_eq!(multi_route.routes[0].capacity, 30);
 
-        let multi_route = cg.get_multi_route(&2, &3, 30, None).unwrap();
+        let multi_route = cg
+            .get_multi_route(&2, &3, 30, &RouteConstraints::new())
+            .unwrap();
         assert_eq!(multi_route.routes[0].route, vec![2, 3]);
         assert_eq!(multi_route.routes[0].capacity, 30);
 
@@ -404,21 +558,96 @@ mod tests {
         for _ in 0..max_edge_age - 1 {
             cg.tick(&0);
 
-            let multi_route = cg.get_multi_route(&0, &1, 30, None).unwrap();
+            let multi_route = cg
+                .get_multi_route(&0, &1, 30, &RouteConstraints::new())
+                .unwrap();
             assert_eq!(multi_route.routes[0].route, vec![0, 1]);
             assert_eq!(multi_route.routes[0].capacity, 30);
 
-            let multi_route = cg.get_multi_route(&2, &3, 30, None).unwrap();
+            let multi_route = cg
+                .get_multi_route(&2, &3, 30, &RouteConstraints::new())
+                .unwrap();
             assert_eq!(multi_route.routes[0].route, vec![2, 3]);
             assert_eq!(multi_route.routes[0].capacity, 30);
         }
 
         // At this point 0->1 and 1->0 should expire, but 2->3 and 3->2 don't expire:
         cg.tick(&0);
-        assert!(cg.get_multi_route(&0, &1, 30, None).is_none());
+        assert!(cg
+            .get_multi_route(&0, &1, 30, &RouteConstraints::new())
+            .is_none());
 
-        let multi_route = cg.get_multi_route(&2, &3, 30, None).unwrap();
+        let multi_route = cg
+            .get_multi_route(&2, &3, 30, &RouteConstraints::new())
+            .unwrap();
         assert_eq!(multi_route.routes[0].route, vec![2, 3]);
         assert_eq!(multi_route.routes[0].capacity, 30);
     }
+
+    #[test]
+    fn test_simple_capacity_graph_snapshot_restore() {
+        let mut cg = SimpleCapacityGraph::<u32, ConstRate>::new();
+
+        cg.update_edge(0, 1, CapacityEdge::new(30, ConstRate(1)));
+        cg.update_edge(1, 0, CapacityEdge::new(30, ConstRate(1)));
+        cg.tick(&1);
+
+        let mut snapshot = cg.snapshot();
+        snapshot.sort_by_key(|snapshot_edge| snapshot_edge.from);
+        assert_eq!(
+            snapshot,
+            vec![
+                SnapshotEdge {
+                    from: 0,
+                    to: 1,
+                    capacity_edge: CapacityEdge::new(30, ConstRate(1)),
+                    age: 0,
+                },
+                SnapshotEdge {
+                    from: 1,
+                    to: 0,
+                    capacity_edge: CapacityEdge::new(30, ConstRate(1)),
+                    age: 1,
+                },
+            ]
+        );
+
+        // Warm start:
+        let mut cg = SimpleCapacityGraph::<u32, ConstRate>::new();
+        let stale_ticks = 100;
+        for snapshot_edge in snapshot {
+            cg.restore_edge(snapshot_edge, stale_ticks);
+        }
+
+        // Routes are available right away:
+        let multi_route = cg
+            .get_multi_route(&0, &1, 30, &RouteConstraints::new())
+            .unwrap();
+        assert_eq!(multi_route.routes[0].route, vec![0, 1]);
+
+        // Restoring does not override existing edges:
+        cg.restore_edge(
+            SnapshotEdge {
+                from: 0,
+                to: 1,
+                capacity_edge: CapacityEdge::new(10, ConstRate(1)),
+                age: 0,
+            },
+            stale_ticks,
+        );
+        assert_eq!(cg.get_send_capacity(&1, &0), 30);
+
+        // Refresh one of the edges:
+        cg.update_edge(0, 1, CapacityEdge::new(20, ConstRate(1)));
+
+        // Only stale edges expire, once the grace period passes:
+        for _ in 0..stale_ticks - 1 {
+            assert!(!cg.tick_stale());
+        }
+        assert_eq!(cg.get_send_capacity(&1, &0), 20);
+        assert!(!cg.tick_stale());
+        assert_eq!(cg.get_send_capacity(&1, &0), 0);
+        assert!(cg.get_edge(&0, &1).is_some());
+        assert!(cg.get_edge(&1, &0).is_none());
+    }
 }
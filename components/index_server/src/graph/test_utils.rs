@@ -6,14 +6,14 @@ pub struct ConstRate(pub u32);
 
 #[cfg(test)]
 impl LinearRate for ConstRate {
-    type K = u32;
+    type K = u128;
 
     fn zero() -> Self {
         ConstRate(0)
     }
 
     fn calc_fee(&self, _k: Self::K) -> Option<Self::K> {
-        Some(self.0)
+        Some(u128::from(self.0))
     }
 
     fn checked_add(&self, other: &Self) -> Option<Self> {
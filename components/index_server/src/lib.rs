@@ -14,10 +14,16 @@ extern crate log;
 #[macro_use]
 extern crate common;
 
+mod admin;
 mod backoff_connector;
+mod federation;
 mod graph;
+mod rate_limit;
 mod server;
 mod server_loop;
 mod verifier;
 
-pub use server::{index_server, IndexServerError};
+pub use admin::AdminConn;
+pub use graph::capacity_graph::{CapacityEdge, SnapshotEdge};
+pub use rate_limit::{ClientRateLimits, TokenBucketConfig};
+pub use server::{index_server, IndexGraphSnapshot, IndexServerError};
@@ -0,0 +1,87 @@
+/// Configuration of a token bucket
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenBucketConfig {
+    /// Maximum amount of tokens the bucket can hold (Maximum burst size)
+    pub capacity: u64,
+    /// Amount of tokens added to the bucket every tick
+    pub refill_per_tick: u64,
+}
+
+/// Per client rate limits enforced by the index server
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientRateLimits {
+    /// Limit on `RequestRoutes` messages
+    pub request_routes: TokenBucketConfig,
+    /// Limit on `MutationsUpdate` messages.
+    /// A client that exceeds this limit is disconnected, and sends its full state again when it
+    /// reconnects (One `MutationsUpdate` per friend currency). The capacity should be large
+    /// enough to fit the full state of a node.
+    pub mutations_update: TokenBucketConfig,
+}
+
+/// A token bucket, refilled on timer ticks
+#[derive(Debug)]
+pub struct TokenBucket {
+    config: TokenBucketConfig,
+    tokens: u64,
+}
+
+impl TokenBucket {
+    /// Create a new full bucket
+    pub fn new(config: TokenBucketConfig) -> Self {
+        TokenBucket {
+            tokens: config.capacity,
+            config,
+        }
+    }
+
+    /// Try to take one token from the bucket.
+    /// Returns false if the bucket is empty.
+    pub fn try_take(&mut self) -> bool {
+        if self.tokens == 0 {
+            return false;
+        }
+        self.tokens -= 1;
+        true
+    }
+
+    /// Refill the bucket (Called every time tick)
+    pub fn tick(&mut self) {
+        self.tokens = self
+            .tokens
+            .saturating_add(self.config.refill_per_tick)
+            .min(self.config.capacity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket_basic() {
+        let mut token_bucket = TokenBucket::new(TokenBucketConfig {
+            capacity: 3,
+            refill_per_tick: 2,
+        });
+
+        for _ in 0..3 {
+            assert!(token_bucket.try_take());
+        }
+        assert!(!token_bucket.try_take());
+
+        token_bucket.tick();
+        assert!(token_bucket.try_take());
+        assert!(token_bucket.try_take());
+        assert!(!token_bucket.try_take());
+
+        // The bucket never holds more than its capacity:
+        for _ in 0..5 {
+            token_bucket.tick();
+        }
+        for _ in 0..3 {
+            assert!(token_bucket.try_take());
+        }
+        assert!(!token_bucket.try_take());
+    }
+}
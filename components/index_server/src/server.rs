@@ -1,65 +1,199 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
 use std::marker::Unpin;
 
-use futures::task::Spawn;
-use futures::Stream;
+use futures::channel::mpsc;
+use futures::future::{self, Either};
+use futures::task::{Spawn, SpawnExt};
+use futures::{pin_mut, stream, Future, FutureExt, SinkExt, Stream, StreamExt, TryFutureExt};
 
-use common::conn::FutTransform;
+use common::conn::{BoxStream, FutTransform};
+use common::select_streams::select_streams;
 
 use proto::crypto::PublicKey;
+use proto::funder::messages::{Currency, ExchangeRate, Rate};
 
 use timer::TimerClient;
 
 use crypto::identity::compare_public_key;
 use crypto::rand::CryptoRandom;
 
+use crate::admin::{admin_loop, AdminConn};
 use crate::server_loop::{server_loop, ClientConn, ServerConn, ServerLoopError};
 
 use crate::backoff_connector::BackoffConnector;
-use crate::graph::graph_service::create_graph_service;
+use crate::federation::FederationClient;
+use crate::graph::graph_service::{create_graph_service, GraphClient, GraphSnapshot};
 use crate::graph::simple_capacity_graph::SimpleCapacityGraph;
+use crate::rate_limit::ClientRateLimits;
 use crate::verifier::simple_verifier::SimpleVerifier;
 
+/// A snapshot of all the capacity graphs of an index server
+pub type IndexGraphSnapshot = GraphSnapshot<Currency, PublicKey, u128, Rate>;
+
 #[derive(Debug)]
 pub enum IndexServerError {
     RequestTimerStreamError,
     CreateGraphServiceError,
+    GraphClientError,
+    SpawnError,
     ServerLoopError(ServerLoopError),
 }
 
+enum SnapshotEvent {
+    TimerTick,
+    Shutdown,
+}
+
+/// Take a snapshot of the capacity graphs, and send it (If a snapshot sender was provided).
+async fn send_graph_snapshot(
+    graph_client: &mut GraphClient<Currency, PublicKey, u128, Rate, ExchangeRate>,
+    opt_snapshot_sender: &mut Option<mpsc::Sender<IndexGraphSnapshot>>,
+) -> Result<(), IndexServerError> {
+    if let Some(snapshot_sender) = opt_snapshot_sender.as_mut() {
+        let graph_snapshot = graph_client
+            .snapshot()
+            .await
+            .map_err(|_| IndexServerError::GraphClientError)?;
+        if snapshot_sender.send(graph_snapshot).await.is_err() {
+            warn!("graph_snapshot_loop(): Snapshot receiver was closed");
+            *opt_snapshot_sender = None;
+        }
+    }
+    Ok(())
+}
+
+/// Expire stale edges of the capacity graphs, and send a snapshot of the capacity graphs every
+/// `snapshot_ticks` ticks (If a snapshot sender was provided).
+/// A final snapshot is sent when `shutdown` resolves, and then the loop ends.
+async fn graph_snapshot_loop<TS, SD>(
+    mut graph_client: GraphClient<Currency, PublicKey, u128, Rate, ExchangeRate>,
+    timer_stream: TS,
+    snapshot_ticks: usize,
+    mut opt_snapshot_sender: Option<mpsc::Sender<IndexGraphSnapshot>>,
+    shutdown: SD,
+) -> Result<(), IndexServerError>
+where
+    TS: Stream + Send + Unpin,
+    SD: Future<Output = ()> + Send + Unpin,
+{
+    let timer_stream = timer_stream.map(|_| SnapshotEvent::TimerTick);
+    let shutdown_stream = stream::once(shutdown).map(|_| SnapshotEvent::Shutdown);
+    let mut events = select_streams![timer_stream, shutdown_stream];
+
+    let mut ticks_left = snapshot_ticks;
+    while let Some(event) = events.next().await {
+        match event {
+            SnapshotEvent::TimerTick => {
+                graph_client
+                    .tick_stale()
+                    .await
+                    .map_err(|_| IndexServerError::GraphClientError)?;
+
+                ticks_left = ticks_left.saturating_sub(1);
+                if ticks_left > 0 {
+                    continue;
+                }
+                ticks_left = snapshot_ticks;
+
+                send_graph_snapshot(&mut graph_client, &mut opt_snapshot_sender).await?;
+            }
+            SnapshotEvent::Shutdown => {
+                send_graph_snapshot(&mut graph_client, &mut opt_snapshot_sender).await?;
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
 /// Run an index server
-/// Will keep running until an error occurs.
-pub async fn index_server<A, IS, IC, SC, R, GS, S>(
+/// Will keep running until an error occurs, or until `shutdown` resolves.
+///
+/// The capacity graphs are initialized from `graph_snapshot` (Empty for a cold start).
+/// Restored edges are considered stale. They age normally whenever their node ticks, and are
+/// removed if they are not updated within `stale_ticks` ticks.
+/// A final snapshot is sent on shutdown.
+///
+/// Requests of every connected client are limited according to `client_rate_limits`.
+///
+/// Every `announce_ticks` ticks the server announces its trusted servers to all connected
+/// servers. Trusted servers can be added and removed at runtime through the admin connections.
+pub async fn index_server<A, IS, IC, IA, SC, R, SD, GS, S>(
     local_public_key: PublicKey,
     trusted_servers: HashMap<PublicKey, A>,
     incoming_server_connections: IS,
     incoming_client_connections: IC,
+    incoming_admin_connections: IA,
+    admins: HashSet<PublicKey>,
     server_connector: SC,
     mut timer_client: TimerClient,
     ticks_to_live: usize,
     backoff_ticks: usize,
+    client_rate_limits: ClientRateLimits,
+    announce_ticks: usize,
     rng: R,
+    graph_snapshot: IndexGraphSnapshot,
+    stale_ticks: usize,
+    snapshot_ticks: usize,
+    opt_snapshot_sender: Option<mpsc::Sender<IndexGraphSnapshot>>,
+    shutdown: SD,
     graph_service_spawner: GS,
     spawner: S,
 ) -> Result<(), IndexServerError>
 where
     A: Debug + Send + Sync + Clone + 'static,
-    IS: Stream<Item = (PublicKey, ServerConn)> + Unpin + Send,
+    IS: Stream<Item = (PublicKey, ServerConn<A>)> + Unpin + Send,
     IC: Stream<Item = (PublicKey, ClientConn)> + Unpin + Send,
-    SC: FutTransform<Input = (PublicKey, A), Output = Option<ServerConn>> + Clone + Send + 'static,
+    IA: Stream<Item = (PublicKey, AdminConn<A>)> + Unpin + Send + 'static,
+    SC: FutTransform<Input = (PublicKey, A), Output = Option<ServerConn<A>>>
+        + Clone
+        + Send
+        + 'static,
     R: CryptoRandom,
-    S: Spawn + Clone + Send,
+    SD: Future<Output = ()> + Send + Unpin,
+    S: Spawn + Clone + Send + 'static,
     GS: Spawn + Send + 'static,
 {
     let verifier = SimpleVerifier::new(ticks_to_live, rng);
 
-    let graph_client = create_graph_service::<_, _, _, _, SimpleCapacityGraph<_, _>, _, _>(
+    let graph_client = create_graph_service::<_, _, _, _, _, SimpleCapacityGraph<_, _>, _, _>(
+        graph_snapshot,
+        stale_ticks,
         graph_service_spawner,
         spawner.clone(),
     )
     .map_err(|_| IndexServerError::CreateGraphServiceError)?;
 
+    let snapshot_timer_stream = timer_client
+        .request_timer_stream("index_server_snapshot".to_owned())
+        .await
+        .map_err(|_| IndexServerError::RequestTimerStreamError)?;
+
+    let snapshot_fut = graph_snapshot_loop(
+        graph_client.clone(),
+        snapshot_timer_stream,
+        snapshot_ticks,
+        opt_snapshot_sender,
+        shutdown,
+    );
+
+    let (federation_requests_sender, incoming_federation_requests) = mpsc::channel(0);
+    let federation_client = FederationClient::new(federation_requests_sender);
+
+    let admin_fut = admin_loop(
+        graph_client.clone(),
+        federation_client,
+        incoming_admin_connections,
+        admins,
+        spawner.clone(),
+    )
+    .map_err(|e| error!("admin_loop() error: {:?}", e))
+    .map(|_| ());
+    spawner
+        .spawn(admin_fut)
+        .map_err(|_| IndexServerError::SpawnError)?;
+
     let timer_stream = timer_client
         .request_timer_stream("index_server".to_owned())
         .await
@@ -67,19 +201,29 @@ where
 
     let backoff_connector = BackoffConnector::new(server_connector, timer_client, backoff_ticks);
 
-    server_loop(
+    let server_loop_fut = server_loop(
         local_public_key,
         trusted_servers,
         incoming_server_connections,
         incoming_client_connections,
+        incoming_federation_requests,
         backoff_connector,
         graph_client,
         compare_public_key,
         verifier,
         timer_stream,
+        client_rate_limits,
+        announce_ticks,
         spawner,
         None,
-    )
-    .await
-    .map_err(IndexServerError::ServerLoopError)
+    );
+
+    pin_mut!(snapshot_fut);
+    pin_mut!(server_loop_fut);
+
+    // The snapshot loop only ends after shutdown (Once the final snapshot was sent):
+    match future::select(server_loop_fut, snapshot_fut).await {
+        Either::Left((res, _)) => res.map_err(IndexServerError::ServerLoopError),
+        Either::Right((res, _)) => res,
+    }
 }
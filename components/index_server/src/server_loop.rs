@@ -1,5 +1,6 @@
 use std::cmp::Ordering;
 use std::collections::HashMap;
+use std::convert::TryFrom;
 use std::marker::Unpin;
 
 use futures::channel::{mpsc, oneshot};
@@ -9,24 +10,27 @@ use futures::{future, select, stream, FutureExt, SinkExt, Stream, StreamExt, Try
 use common::conn::{sink_to_sender, BoxStream, ConnPair, FutTransform};
 use common::select_streams::select_streams;
 
+use proto::consts::{MAX_EXCLUDE_EDGES, MAX_EXCLUDE_NODES, MAX_ROUTE_LEN};
 use proto::crypto::{PublicKey, Uid};
 
 use proto::index_server::messages::{
-    ForwardMutationsUpdate, IndexClientToServer, IndexMutation, IndexServerToClient,
-    IndexServerToServer, MultiRoute, MutationsUpdate, ResponseRoutes, RouteCapacityRate,
-    TimeProofLink,
+    FederationServer, ForwardMutationsUpdate, IndexClientToServer, IndexMutation,
+    IndexServerAddress, IndexServerToClient, IndexServerToServer, MultiRoute, MutationsUpdate,
+    RequestRoutes, ResponseRoutes, RouteCapacityRate, RouteExchange, TimeProofLink,
 };
 
-use proto::funder::messages::{Currency, FriendsRoute, Rate};
+use proto::funder::messages::{Currency, CurrencyExchange, ExchangeRate, FriendsRoute, Rate};
 
 use signature::verify::verify_mutations_update;
 
-use crate::graph::capacity_graph::{CapacityEdge, LinearRate};
+use crate::federation::FederationRequest;
+use crate::graph::capacity_graph::{CapacityEdge, LinearRate, RouteConstraints};
 use crate::graph::graph_service::{GraphClient, GraphClientError};
+use crate::rate_limit::{ClientRateLimits, TokenBucket};
 
 use crate::verifier::Verifier;
 
-pub type ServerConn = ConnPair<IndexServerToServer, IndexServerToServer>;
+pub type ServerConn<A> = ConnPair<IndexServerToServer<A>, IndexServerToServer<A>>;
 pub type ClientConn = ConnPair<IndexServerToClient, IndexClientToServer>;
 
 // TODO: Find a more scalable solution to the EVENT_BUFFER issue.
@@ -35,6 +39,10 @@ pub type ClientConn = ConnPair<IndexServerToClient, IndexClientToServer>;
 const EVENT_BUFFER: usize = 0x100;
 const SERVER_SENDER_BUFFER: usize = 0x20;
 const CLIENT_SENDER_BUFFER: usize = 0x20;
+/// Maximum amount of exchanging nodes we consider when looking for cross currency routes.
+const MAX_EXCHANGERS: usize = 8;
+/// Maximum amount of announced servers we keep for every trusted server.
+const MAX_KNOWN_SERVERS: usize = 0x100;
 
 impl LinearRate for Rate {
     /// Type used to count credits
@@ -104,8 +112,8 @@ struct ServerInitiating {
 }
 
 #[derive(Debug)]
-enum RemoteServerState {
-    Connected(Connected<IndexServerToServer>),
+enum RemoteServerState<A> {
+    Connected(Connected<IndexServerToServer<A>>),
     Initiating(ServerInitiating),
     Listening,
 }
@@ -113,18 +121,31 @@ enum RemoteServerState {
 #[derive(Debug)]
 struct RemoteServer<A> {
     address: A,
-    state: RemoteServerState,
+    state: RemoteServerState<A>,
+    /// Trusted servers announced by this server
+    known_servers: Vec<IndexServerAddress<A>>,
+}
+
+/// A connected client
+#[derive(Debug)]
+struct RemoteClient {
+    connected: Connected<IndexServerToClient>,
+    /// Used to notify the client handler about time ticks
+    tick_sender: mpsc::Sender<()>,
 }
 
 struct IndexServer<A, S, SC, V, CMP> {
     local_public_key: PublicKey,
     server_connector: SC,
-    graph_client: GraphClient<Currency, PublicKey, u128, Rate>,
+    graph_client: GraphClient<Currency, PublicKey, u128, Rate, ExchangeRate>,
     verifier: V,
     compare_public_key: CMP,
     remote_servers: HashMap<PublicKey, RemoteServer<A>>,
-    clients: HashMap<PublicKey, Connected<IndexServerToClient>>,
-    event_sender: mpsc::Sender<IndexServerEvent>,
+    clients: HashMap<PublicKey, RemoteClient>,
+    event_sender: mpsc::Sender<IndexServerEvent<A>>,
+    /// Amount of ticks between two announcements of our trusted servers
+    announce_ticks: usize,
+    announce_ticks_left: usize,
     spawner: S,
 }
 
@@ -136,9 +157,10 @@ impl From<GraphClientError> for ServerLoopError {
 
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
-enum IndexServerEvent {
-    ServerConnection((PublicKey, ServerConn)),
-    FromServer((PublicKey, Option<IndexServerToServer>)),
+enum IndexServerEvent<A> {
+    ServerConnection((PublicKey, ServerConn<A>)),
+    FromServer((PublicKey, Option<IndexServerToServer<A>>)),
+    FederationRequest(FederationRequest<A>),
     ClientConnection((PublicKey, ClientConn)),
     ClientClosed(PublicKey),
     ClientMutationsUpdate(MutationsUpdate),
@@ -166,7 +188,10 @@ impl<A, S, SC, V, CMP> IndexServer<A, S, SC, V, CMP>
 where
     A: Clone + Send + std::fmt::Debug + 'static,
     S: Spawn + Send,
-    SC: FutTransform<Input = (PublicKey, A), Output = Option<ServerConn>> + Clone + Send + 'static,
+    SC: FutTransform<Input = (PublicKey, A), Output = Option<ServerConn<A>>>
+        + Clone
+        + Send
+        + 'static,
     V: Verifier<Node = PublicKey, Neighbor = PublicKey, SessionId = Uid>,
     CMP: Clone + Fn(&PublicKey, &PublicKey) -> Ordering,
 {
@@ -174,10 +199,11 @@ where
         local_public_key: PublicKey,
         trusted_servers: HashMap<PublicKey, A>,
         server_connector: SC,
-        graph_client: GraphClient<Currency, PublicKey, u128, Rate>,
+        graph_client: GraphClient<Currency, PublicKey, u128, Rate, ExchangeRate>,
         compare_public_key: CMP,
         verifier: V,
-        event_sender: mpsc::Sender<IndexServerEvent>,
+        event_sender: mpsc::Sender<IndexServerEvent<A>>,
+        announce_ticks: usize,
         spawner: S,
     ) -> Result<Self, ServerLoopError> {
         let mut index_server = IndexServer {
@@ -189,6 +215,8 @@ where
             remote_servers: HashMap::new(),
             clients: HashMap::new(),
             event_sender,
+            announce_ticks,
+            announce_ticks_left: announce_ticks,
             spawner,
         };
 
@@ -204,7 +232,7 @@ where
     /// Iterate over all connected servers
     fn iter_connected_servers(
         &mut self,
-    ) -> impl Iterator<Item = (&PublicKey, &mut Connected<IndexServerToServer>)> {
+    ) -> impl Iterator<Item = (&PublicKey, &mut Connected<IndexServerToServer<A>>)> {
         self.remote_servers
             .iter_mut()
             .filter_map(
@@ -226,6 +254,7 @@ where
             return Ok(RemoteServer {
                 address,
                 state: RemoteServerState::Listening,
+                known_servers: Vec::new(),
             });
         }
 
@@ -263,7 +292,11 @@ where
 
         let state = RemoteServerState::Initiating(ServerInitiating { close_sender });
 
-        Ok(RemoteServer { address, state })
+        Ok(RemoteServer {
+            address,
+            state,
+            known_servers: Vec::new(),
+        })
     }
 
     pub async fn handle_forward_mutations_update(
@@ -359,6 +392,25 @@ where
                         )
                         .await?;
                 }
+                IndexMutation::UpdateExchangeRate(update_exchange_rate) => {
+                    self.graph_client
+                        .update_exchange(
+                            update_exchange_rate.src_currency.clone(),
+                            update_exchange_rate.dest_currency.clone(),
+                            mutations_update.node_public_key.clone(),
+                            update_exchange_rate.rate.clone(),
+                        )
+                        .await?;
+                }
+                IndexMutation::RemoveExchangeRate(remove_exchange_rate) => {
+                    self.graph_client
+                        .remove_exchange(
+                            remove_exchange_rate.src_currency.clone(),
+                            remove_exchange_rate.dest_currency.clone(),
+                            mutations_update.node_public_key.clone(),
+                        )
+                        .await?;
+                }
             }
         }
 
@@ -378,7 +430,7 @@ where
     pub async fn handle_from_server(
         &mut self,
         public_key: PublicKey,
-        server_msg: IndexServerToServer,
+        server_msg: IndexServerToServer<A>,
     ) -> Result<(), ServerLoopError> {
         match server_msg {
             IndexServerToServer::TimeHash(time_hash) => {
@@ -388,10 +440,80 @@ where
                 self.handle_forward_mutations_update(Some(public_key), forward_mutations_update)
                     .await?;
             }
+            IndexServerToServer::KnownServers(mut known_servers) => {
+                if let Some(remote_server) = self.remote_servers.get_mut(&public_key) {
+                    known_servers.truncate(MAX_KNOWN_SERVERS);
+                    remote_server.known_servers = known_servers;
+                }
+            }
         };
         Ok(())
     }
 
+    /// All of our trusted servers, and the servers each of them announced.
+    /// Sorted by public key.
+    fn federation_topology(&self) -> Vec<FederationServer<A>> {
+        let mut servers: Vec<_> = self
+            .remote_servers
+            .iter()
+            .map(|(public_key, remote_server)| FederationServer {
+                server_address: IndexServerAddress {
+                    public_key: public_key.clone(),
+                    address: remote_server.address.clone(),
+                },
+                is_connected: matches!(remote_server.state, RemoteServerState::Connected(_)),
+                known_servers: remote_server.known_servers.clone(),
+            })
+            .collect();
+        servers.sort_by(|a, b| {
+            a.server_address
+                .public_key
+                .cmp(&b.server_address.public_key)
+        });
+        servers
+    }
+
+    pub fn handle_federation_request(
+        &mut self,
+        federation_request: FederationRequest<A>,
+    ) -> Result<(), ServerLoopError> {
+        let response_sender = match federation_request {
+            FederationRequest::GetTopology(response_sender) => response_sender,
+            FederationRequest::AddServer(server_address, response_sender) => {
+                let IndexServerAddress {
+                    public_key,
+                    address,
+                } = server_address;
+                if public_key == self.local_public_key {
+                    warn!("handle_federation_request(): Can not add ourselves as a server");
+                } else if self.remote_servers.contains_key(&public_key) {
+                    warn!(
+                        "handle_federation_request(): Server {:?} is already trusted",
+                        public_key
+                    );
+                } else {
+                    let remote_server = self.spawn_server(public_key.clone(), address)?;
+                    self.remote_servers.insert(public_key, remote_server);
+                }
+                response_sender
+            }
+            FederationRequest::RemoveServer(public_key, response_sender) => {
+                // Dropping the remote server closes the connection (Or the connection attempt):
+                if self.remote_servers.remove(&public_key).is_some() {
+                    let _ = self.verifier.remove_neighbor(&public_key);
+                } else {
+                    warn!(
+                        "handle_federation_request(): Server {:?} is not trusted",
+                        public_key
+                    );
+                }
+                response_sender
+            }
+        };
+        let _ = response_sender.send(self.federation_topology());
+        Ok(())
+    }
+
     pub async fn handle_timer_tick(&mut self) -> Result<(), ServerLoopError> {
         let (time_hash, removed_nodes) = self.verifier.tick();
 
@@ -401,8 +523,32 @@ where
         }
 
         // Try to send time tick to all connected clients:
-        for connected_client in self.clients.values_mut() {
-            let _ = connected_client.try_send(IndexServerToClient::TimeHash(time_hash.clone()));
+        for remote_client in self.clients.values_mut() {
+            let _ = remote_client
+                .connected
+                .try_send(IndexServerToClient::TimeHash(time_hash.clone()));
+            // Refill the client's rate limits. Missing a tick (because the client handler is
+            // busy) only delays the refill:
+            let _ = remote_client.tick_sender.try_send(());
+        }
+
+        // Periodically announce our trusted servers to all connected servers:
+        self.announce_ticks_left = self.announce_ticks_left.saturating_sub(1);
+        if self.announce_ticks_left == 0 {
+            self.announce_ticks_left = self.announce_ticks;
+            let mut known_servers: Vec<_> = self
+                .remote_servers
+                .iter()
+                .map(|(public_key, remote_server)| IndexServerAddress {
+                    public_key: public_key.clone(),
+                    address: remote_server.address.clone(),
+                })
+                .collect();
+            known_servers.sort_by(|a, b| a.public_key.cmp(&b.public_key));
+            for (_server_public_key, connected_server) in self.iter_connected_servers() {
+                let _ = connected_server
+                    .try_send(IndexServerToServer::KnownServers(known_servers.clone()));
+            }
         }
 
         // Update the graph service about removed nodes:
@@ -414,17 +560,242 @@ where
     }
 }
 
-async fn client_handler(
-    mut graph_client: GraphClient<Currency, PublicKey, u128, Rate>,
-    _public_key: PublicKey, // TODO: unused?
+/// Collect the route constraints specified in a routes request.
+/// Routes are never allowed to be longer than `MAX_ROUTE_LEN`, as they could not be used for
+/// sending funds.
+fn route_constraints(request_routes: &RequestRoutes) -> RouteConstraints<PublicKey, u128> {
+    let exclude_edges = request_routes
+        .opt_exclude
+        .iter()
+        .chain(request_routes.exclude_edges.iter())
+        .map(|edge| (edge.from_public_key.clone(), edge.to_public_key.clone()))
+        .collect();
+
+    RouteConstraints {
+        exclude_edges,
+        exclude_nodes: request_routes.exclude_nodes.clone(),
+        opt_max_hops: Some(
+            request_routes
+                .opt_max_hops
+                .map(|max_hops| usize::try_from(max_hops).unwrap_or(usize::MAX))
+                .unwrap_or(usize::MAX)
+                .min(MAX_ROUTE_LEN - 1),
+        ),
+        opt_max_fees: request_routes.opt_max_fees,
+    }
+}
+
+/// Find routes in a single currency
+async fn get_currency_routes(
+    graph_client: &mut GraphClient<Currency, PublicKey, u128, Rate, ExchangeRate>,
+    request_routes: RequestRoutes,
+) -> Result<Vec<MultiRoute>, ServerLoopError> {
+    let constraints = route_constraints(&request_routes);
+
+    let graph_multi_routes = graph_client
+        .get_multi_routes(
+            request_routes.currency.clone(),
+            request_routes.source.clone(),
+            request_routes.destination.clone(),
+            request_routes.capacity,
+            constraints,
+        )
+        .await?;
+
+    Ok(graph_multi_routes
+        .into_iter()
+        .map(|graph_multi_route| MultiRoute {
+            routes: graph_multi_route
+                .routes
+                .into_iter()
+                .map(|graph_route| RouteCapacityRate {
+                    route: FriendsRoute {
+                        public_keys: graph_route.route,
+                    },
+                    capacity: graph_route.capacity,
+                    rate: graph_route.rate,
+                    exchanges: vec![],
+                })
+                .collect(),
+        })
+        .collect())
+}
+
+/// Find routes from `request_routes.currency` to `request_routes.dest_currency`, going through
+/// one exchanging node.
+/// Every returned multi route contains a single route.
+async fn get_exchange_routes(
+    graph_client: &mut GraphClient<Currency, PublicKey, u128, Rate, ExchangeRate>,
+    request_routes: RequestRoutes,
+) -> Result<Vec<MultiRoute>, ServerLoopError> {
+    let exchangers = graph_client
+        .get_exchanges(
+            request_routes.currency.clone(),
+            request_routes.dest_currency.clone(),
+        )
+        .await?;
+
+    let constraints = route_constraints(&request_routes);
+    // Excluded edges and nodes apply to each part of the route separately.
+    // Fees can only be checked for the joined route, as every part is in a different currency.
+    let part_constraints = RouteConstraints {
+        opt_max_fees: None,
+        ..constraints.clone()
+    };
+
+    let mut multi_routes = Vec::new();
+    for (exchanger, exchange_rate) in exchangers.into_iter().take(MAX_EXCHANGERS) {
+        // The exchanging node must not be excluded:
+        if constraints.exclude_nodes.contains(&exchanger) {
+            continue;
+        }
+
+        // The exchanging node must be a mediator:
+        if exchanger == request_routes.source || exchanger == request_routes.destination {
+            continue;
+        }
+
+        // Capacity required before the exchange:
+        let src_capacity = match exchange_rate.convert_back(request_routes.capacity) {
+            Some(src_capacity) => src_capacity,
+            None => continue,
+        };
+
+        let src_multi_routes = graph_client
+            .get_multi_routes(
+                request_routes.currency.clone(),
+                request_routes.source.clone(),
+                exchanger.clone(),
+                src_capacity,
+                part_constraints.clone(),
+            )
+            .await?;
+        let dest_multi_routes = graph_client
+            .get_multi_routes(
+                request_routes.dest_currency.clone(),
+                exchanger.clone(),
+                request_routes.destination.clone(),
+                request_routes.capacity,
+                part_constraints.clone(),
+            )
+            .await?;
+
+        let (src_multi_route, dest_multi_route) = match (
+            src_multi_routes.into_iter().next(),
+            dest_multi_routes.into_iter().next(),
+        ) {
+            (Some(src_multi_route), Some(dest_multi_route)) => (src_multi_route, dest_multi_route),
+            _ => continue,
+        };
+
+        for src_route in &src_multi_route.routes {
+            for dest_route in &dest_multi_route.routes {
+                // Join the two routes at the exchanging node:
+                let mut public_keys = src_route.route.clone();
+                public_keys.extend(dest_route.route.iter().skip(1).cloned());
+                let route = FriendsRoute { public_keys };
+
+                // The two parts of the joined route might intersect:
+                if !route.is_valid() {
+                    continue;
+                }
+
+                if let Some(max_hops) = constraints.opt_max_hops {
+                    if route.len() - 1 > max_hops {
+                        continue;
+                    }
+                }
+
+                if let Some(max_fees) = constraints.opt_max_fees {
+                    // Total fees, in the source currency:
+                    let opt_total_fees =
+                        src_route.rate.calc_fee(src_capacity).and_then(|src_fees| {
+                            let dest_fees = dest_route.rate.calc_fee(request_routes.capacity)?;
+                            src_fees.checked_add(exchange_rate.convert_back(dest_fees)?)
+                        });
+                    match opt_total_fees {
+                        Some(total_fees) if total_fees <= max_fees => {}
+                        _ => continue,
+                    }
+                }
+
+                let route_exchange = RouteExchange {
+                    currency_exchange: CurrencyExchange {
+                        public_key: exchanger.clone(),
+                        src_currency: request_routes.currency.clone(),
+                        dest_currency: request_routes.dest_currency.clone(),
+                        rate: exchange_rate.clone(),
+                    },
+                    capacity: dest_route.capacity,
+                    rate: dest_route.rate.clone(),
+                };
+
+                multi_routes.push(MultiRoute {
+                    routes: vec![RouteCapacityRate {
+                        route,
+                        capacity: src_route.capacity,
+                        rate: src_route.rate.clone(),
+                        exchanges: vec![route_exchange],
+                    }],
+                });
+            }
+        }
+    }
+    Ok(multi_routes)
+}
+
+#[derive(Debug)]
+enum ClientHandlerEvent {
+    FromClient(IndexClientToServer),
+    ClientClosed,
+    TimerTick,
+}
+
+async fn client_handler<A>(
+    mut graph_client: GraphClient<Currency, PublicKey, u128, Rate, ExchangeRate>,
+    public_key: PublicKey,
     client_conn: ClientConn,
-    mut event_sender: mpsc::Sender<IndexServerEvent>,
+    incoming_ticks: mpsc::Receiver<()>,
+    client_rate_limits: ClientRateLimits,
+    mut event_sender: mpsc::Sender<IndexServerEvent<A>>,
 ) -> Result<(), ServerLoopError> {
-    let (mut sender, mut receiver) = client_conn.split();
+    let (mut sender, receiver) = client_conn.split();
+
+    let mut request_routes_bucket = TokenBucket::new(client_rate_limits.request_routes);
+    let mut mutations_update_bucket = TokenBucket::new(client_rate_limits.mutations_update);
+
+    let receiver = receiver
+        .map(ClientHandlerEvent::FromClient)
+        .chain(stream::once(future::ready(
+            ClientHandlerEvent::ClientClosed,
+        )));
+    let incoming_ticks = incoming_ticks.map(|_| ClientHandlerEvent::TimerTick);
+    let mut events = select_streams![receiver, incoming_ticks];
+
+    while let Some(event) = events.next().await {
+        let client_msg = match event {
+            ClientHandlerEvent::FromClient(client_msg) => client_msg,
+            ClientHandlerEvent::ClientClosed => break,
+            ClientHandlerEvent::TimerTick => {
+                request_routes_bucket.tick();
+                mutations_update_bucket.tick();
+                continue;
+            }
+        };
 
-    while let Some(client_msg) = receiver.next().await {
         match client_msg {
             IndexClientToServer::MutationsUpdate(mutations_update) => {
+                if !mutations_update_bucket.try_take() {
+                    // Dropping a MutationsUpdate leaves us with a partial view of the client's
+                    // state. We close the connection instead. The client will reconnect and send
+                    // its full state again.
+                    warn!(
+                        "client_handler(): MutationsUpdate from {:?} was rate limited. Closing connection.",
+                        public_key
+                    );
+                    break;
+                }
+
                 // Forward to main server future to process:
                 event_sender
                     .send(IndexServerEvent::ClientMutationsUpdate(mutations_update))
@@ -432,38 +803,42 @@ async fn client_handler(
                     .map_err(|_| ServerLoopError::ClientEventSenderError)?;
             }
             IndexClientToServer::RequestRoutes(request_routes) => {
-                let opt_exclude_edge = request_routes
-                    .opt_exclude
-                    .map(|edge| (edge.from_public_key.clone(), edge.to_public_key));
-
-                let graph_multi_routes = graph_client
-                    .get_multi_routes(
-                        request_routes.currency.clone(),
-                        request_routes.source.clone(),
-                        request_routes.destination.clone(),
-                        request_routes.capacity,
-                        opt_exclude_edge,
-                    )
-                    .await?;
-                let multi_routes = graph_multi_routes
-                    .into_iter()
-                    .map(|graph_multi_route| MultiRoute {
-                        routes: graph_multi_route
-                            .routes
-                            .into_iter()
-                            .map(|graph_route| RouteCapacityRate {
-                                route: FriendsRoute {
-                                    public_keys: graph_route.route,
-                                },
-                                capacity: graph_route.capacity,
-                                rate: graph_route.rate,
-                            })
-                            .collect(),
-                    })
-                    .collect::<Vec<_>>();
+                let request_id = request_routes.request_id.clone();
+                if !request_routes_bucket.try_take() {
+                    // Old clients do not know about rate limiting, so we reply with no routes:
+                    warn!(
+                        "client_handler(): RequestRoutes from {:?} was rate limited",
+                        public_key
+                    );
+                    let response_routes = ResponseRoutes {
+                        request_id,
+                        multi_routes: Vec::new(),
+                    };
+                    sender
+                        .send(IndexServerToClient::ResponseRoutes(response_routes))
+                        .await
+                        .map_err(|_| ServerLoopError::ClientSenderError)?;
+                    continue;
+                }
+
+                // Checking large exclusion lists is expensive. Ignoring some of the exclusions
+                // could return unwanted routes, so no routes are returned instead:
+                let multi_routes = if request_routes.exclude_nodes.len() > MAX_EXCLUDE_NODES
+                    || request_routes.exclude_edges.len() > MAX_EXCLUDE_EDGES
+                {
+                    warn!(
+                        "client_handler(): RequestRoutes from {:?} has too many exclusions",
+                        public_key
+                    );
+                    Vec::new()
+                } else if request_routes.currency == request_routes.dest_currency {
+                    get_currency_routes(&mut graph_client, request_routes).await?
+                } else {
+                    get_exchange_routes(&mut graph_client, request_routes).await?
+                };
 
                 let response_routes = ResponseRoutes {
-                    request_id: request_routes.request_id,
+                    request_id,
                     multi_routes,
                 };
                 let message = IndexServerToClient::ResponseRoutes(response_routes);
@@ -477,24 +852,31 @@ async fn client_handler(
     Ok(())
 }
 
-pub async fn server_loop<A, IS, IC, SC, CMP, V, TS, S>(
+pub async fn server_loop<A, IS, IC, IF, SC, CMP, V, TS, S>(
     local_public_key: PublicKey,
     trusted_servers: HashMap<PublicKey, A>,
     incoming_server_connections: IS,
     incoming_client_connections: IC,
+    incoming_federation_requests: IF,
     server_connector: SC,
-    graph_client: GraphClient<Currency, PublicKey, u128, Rate>,
+    graph_client: GraphClient<Currency, PublicKey, u128, Rate, ExchangeRate>,
     compare_public_key: CMP,
     verifier: V,
     timer_stream: TS,
+    client_rate_limits: ClientRateLimits,
+    announce_ticks: usize,
     spawner: S,
     mut opt_debug_event_sender: Option<mpsc::Sender<()>>,
 ) -> Result<(), ServerLoopError>
 where
     A: Clone + Send + std::fmt::Debug + 'static,
-    IS: Stream<Item = (PublicKey, ServerConn)> + Unpin + Send,
+    IS: Stream<Item = (PublicKey, ServerConn<A>)> + Unpin + Send,
     IC: Stream<Item = (PublicKey, ClientConn)> + Unpin + Send,
-    SC: FutTransform<Input = (PublicKey, A), Output = Option<ServerConn>> + Clone + Send + 'static,
+    IF: Stream<Item = FederationRequest<A>> + Unpin + Send,
+    SC: FutTransform<Input = (PublicKey, A), Output = Option<ServerConn<A>>>
+        + Clone
+        + Send
+        + 'static,
     V: Verifier<Node = PublicKey, Neighbor = PublicKey, SessionId = Uid>,
     CMP: Clone + Fn(&PublicKey, &PublicKey) -> Ordering + Sync,
     TS: Stream + Unpin + Send,
@@ -515,6 +897,7 @@ where
         compare_public_key,
         verifier,
         event_sender,
+        announce_ticks,
         spawner.clone(),
     )?;
 
@@ -539,6 +922,9 @@ where
             IndexServerEvent::ClientListenerClosed,
         )));
 
+    let incoming_federation_requests =
+        incoming_federation_requests.map(IndexServerEvent::FederationRequest);
+
     let timer_stream = timer_stream
         .map(|_| IndexServerEvent::TimerTick)
         .chain(stream::once(future::ready(IndexServerEvent::TimerClosed)));
@@ -547,6 +933,7 @@ where
         event_receiver,
         incoming_server_connections,
         incoming_client_connections,
+        incoming_federation_requests,
         timer_stream
     ];
 
@@ -638,12 +1025,16 @@ where
                 // TODO: Possibly use channel redirection here:
                 let c_sender = sender.clone();
 
+                let (tick_sender, incoming_ticks) = mpsc::channel(0);
+
                 let mut c_event_sender = index_server.event_sender.clone();
                 let c_public_key = public_key.clone();
                 let client_handler_fut = client_handler(
                     index_server.graph_client.clone(),
                     public_key.clone(),
                     ClientConn::from_raw(sender, receiver),
+                    incoming_ticks,
+                    client_rate_limits.clone(),
                     index_server.event_sender.clone(),
                 )
                 .map_err(|e| error!("client_handler() error: {:?}", e))
@@ -657,9 +1048,13 @@ where
                     .spawner
                     .spawn(client_handler_fut)
                     .map_err(|_| ServerLoopError::SpawnError)?;
-                index_server
-                    .clients
-                    .insert(public_key, Connected::new(c_sender));
+                index_server.clients.insert(
+                    public_key,
+                    RemoteClient {
+                        connected: Connected::new(c_sender),
+                        tick_sender,
+                    },
+                );
             }
             IndexServerEvent::ClientMutationsUpdate(mutations_update) => {
                 let forward_mutations_update = ForwardMutationsUpdate {
@@ -677,6 +1072,9 @@ where
                     error!("A non existent client {:?} was closed.", public_key);
                 }
             }
+            IndexServerEvent::FederationRequest(federation_request) => {
+                index_server.handle_federation_request(federation_request)?
+            }
             IndexServerEvent::TimerTick => index_server.handle_timer_tick().await?,
             IndexServerEvent::TimerClosed => {
                 warn!("server_loop() timer closed!");
@@ -714,7 +1112,7 @@ mod tests {
     use crypto::rand::RandGen;
     use crypto::test_utils::DummyRandom;
 
-    use proto::crypto::{PrivateKey, PublicKey, RandValue, Signature};
+    use proto::crypto::{HashResult, PrivateKey, PublicKey, RandValue, Signature};
     use proto::funder::messages::Currency;
     use proto::index_server::messages::{RemoveFriendCurrency, RequestRoutes};
 
@@ -724,6 +1122,7 @@ mod tests {
     use signature::signature_buff::create_mutations_update_signature_buff;
 
     use crate::graph::graph_service::GraphRequest;
+    use crate::rate_limit::TokenBucketConfig;
     use crate::verifier::simple_verifier::SimpleVerifier;
 
     /// Size of channel used for channels between servers, or channels between a server and a
@@ -731,6 +1130,23 @@ mod tests {
     /// forwarding or when sending time hash ticks.
     const CHANNEL_SIZE: usize = 16;
 
+    /// Used for tests that do not expect servers to announce their trusted servers.
+    const NO_ANNOUNCE_TICKS: usize = usize::MAX;
+
+    /// Rate limits that are never reached during the tests
+    fn test_client_rate_limits() -> ClientRateLimits {
+        ClientRateLimits {
+            request_routes: TokenBucketConfig {
+                capacity: 0x100,
+                refill_per_tick: 0x100,
+            },
+            mutations_update: TokenBucketConfig {
+                capacity: 0x100,
+                refill_per_tick: 0x100,
+            },
+        }
+    }
+
     fn create_identity_client<S>(spawner: S, seed: &[u8]) -> IdentityClient
     where
         S: Spawn,
@@ -779,11 +1195,14 @@ mod tests {
             trusted_servers,
             incoming_server_connections,
             incoming_client_connections,
+            stream::empty::<FederationRequest<u8>>(),
             server_connector,
             graph_client,
             compare_public_key,
             verifier,
             timer_stream,
+            test_client_rate_limits(),
+            NO_ANNOUNCE_TICKS,
             spawner.clone(),
             None,
         )
@@ -810,13 +1229,18 @@ mod tests {
         let request_routes = RequestRoutes {
             request_id: request_id.clone(),
             currency: currency1.clone(),
+            dest_currency: currency1.clone(),
             capacity: 100,
             source: PublicKey::from(&[8; PublicKey::len()]),
             destination: PublicKey::from(&[9; PublicKey::len()]),
             opt_exclude: None,
+            exclude_edges: Vec::new(),
+            exclude_nodes: Vec::new(),
+            opt_max_hops: None,
+            opt_max_fees: None,
         };
         client_sender
-            .send(IndexClientToServer::RequestRoutes(request_routes))
+            .send(IndexClientToServer::RequestRoutes(request_routes.clone()))
             .await
             .unwrap();
 
@@ -827,14 +1251,20 @@ mod tests {
                 src,
                 dest,
                 capacity,
-                opt_exclude,
+                constraints,
                 response_sender,
             ) => {
                 assert_eq!(currency, currency1);
                 assert_eq!(src, PublicKey::from(&[8; PublicKey::len()]));
                 assert_eq!(dest, PublicKey::from(&[9; PublicKey::len()]));
                 assert_eq!(capacity, 100);
-                assert_eq!(opt_exclude, None);
+                assert_eq!(
+                    constraints,
+                    RouteConstraints {
+                        opt_max_hops: Some(MAX_ROUTE_LEN - 1),
+                        ..RouteConstraints::new()
+                    }
+                );
                 response_sender.send(Vec::new()).unwrap();
             }
             _ => unreachable!(),
@@ -848,6 +1278,47 @@ mod tests {
             _ => unreachable!(),
         };
 
+        // Requested maximum hops are clamped, so that routes are never too long to be used:
+        let mut request_routes_long = request_routes.clone();
+        request_routes_long.opt_max_hops = Some(u32::MAX);
+        client_sender
+            .send(IndexClientToServer::RequestRoutes(request_routes_long))
+            .await
+            .unwrap();
+
+        match graph_requests_receiver.next().await.unwrap() {
+            GraphRequest::GetMultiRoutes(_, _, _, _, constraints, response_sender) => {
+                assert_eq!(constraints.opt_max_hops, Some(MAX_ROUTE_LEN - 1));
+                response_sender.send(Vec::new()).unwrap();
+            }
+            _ => unreachable!(),
+        }
+
+        match client_receiver.next().await.unwrap() {
+            IndexServerToClient::ResponseRoutes(response_routes) => {
+                assert!(response_routes.multi_routes.is_empty());
+            }
+            _ => unreachable!(),
+        };
+
+        // Too many exclusions. The graph is not queried, and no routes are returned:
+        let mut request_routes_excluded = request_routes;
+        request_routes_excluded.exclude_nodes = (0..=MAX_EXCLUDE_NODES)
+            .map(|i| PublicKey::from(&[i as u8; PublicKey::len()]))
+            .collect();
+        client_sender
+            .send(IndexClientToServer::RequestRoutes(request_routes_excluded))
+            .await
+            .unwrap();
+
+        match client_receiver.next().await.unwrap() {
+            IndexServerToClient::ResponseRoutes(response_routes) => {
+                assert_eq!(response_routes.request_id, request_id);
+                assert!(response_routes.multi_routes.is_empty());
+            }
+            _ => unreachable!(),
+        };
+
         // Server should periodically send time hashes to the client:
         tick_sender.send(()).await.unwrap();
 
@@ -910,21 +1381,176 @@ mod tests {
         block_on(task_index_server_loop_single_server(thread_pool.clone()));
     }
 
+    async fn task_index_server_loop_client_rate_limit<S>(spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let currency1 = Currency::try_from("FST1".to_owned()).unwrap();
+        let server_pk = PublicKey::from(&[0; PublicKey::len()]);
+        let client_public_key = PublicKey::from(&[1; PublicKey::len()]);
+
+        let trusted_servers: HashMap<PublicKey, u8> = HashMap::new();
+
+        let (_server_connections_sender, incoming_server_connections) = mpsc::channel(0);
+        let (mut client_connections_sender, incoming_client_connections) = mpsc::channel(0);
+
+        let (conn_request_sender, _conn_request_receiver) = mpsc::channel(0);
+        let server_connector = DummyConnector::new(conn_request_sender);
+
+        let (_tick_sender, timer_stream) = mpsc::channel::<()>(0);
+
+        let (graph_requests_sender, mut graph_requests_receiver) = mpsc::channel(0);
+        let graph_client = GraphClient::new(graph_requests_sender);
+
+        let compare_public_key = |pk_a: &PublicKey, pk_b: &PublicKey| pk_a.cmp(pk_b);
+
+        let rng = DummyRandom::new(&[0u8]);
+        let verifier = SimpleVerifier::new(8, rng);
+
+        // A single route request is allowed, and no mutations updates are allowed:
+        let client_rate_limits = ClientRateLimits {
+            request_routes: TokenBucketConfig {
+                capacity: 1,
+                refill_per_tick: 1,
+            },
+            mutations_update: TokenBucketConfig {
+                capacity: 0,
+                refill_per_tick: 0,
+            },
+        };
+
+        let server_loop_fut = server_loop(
+            server_pk,
+            trusted_servers,
+            incoming_server_connections,
+            incoming_client_connections,
+            stream::empty::<FederationRequest<u8>>(),
+            server_connector,
+            graph_client,
+            compare_public_key,
+            verifier,
+            timer_stream,
+            client_rate_limits,
+            NO_ANNOUNCE_TICKS,
+            spawner.clone(),
+            None,
+        )
+        .map_err(|e| error!("Error in server_loop(): {:?}", e))
+        .map(|_| ());
+
+        spawner.spawn(server_loop_fut).unwrap();
+
+        let (mut client_sender, server_receiver) = mpsc::channel(CHANNEL_SIZE);
+        let (server_sender, mut client_receiver) = mpsc::channel(CHANNEL_SIZE);
+        client_connections_sender
+            .send((
+                client_public_key.clone(),
+                ConnPair::from_raw(server_sender, server_receiver),
+            ))
+            .await
+            .unwrap();
+
+        let request_routes = RequestRoutes {
+            request_id: Uid::from(&[0; Uid::len()]),
+            currency: currency1.clone(),
+            dest_currency: currency1.clone(),
+            capacity: 100,
+            source: PublicKey::from(&[8; PublicKey::len()]),
+            destination: PublicKey::from(&[9; PublicKey::len()]),
+            opt_exclude: None,
+            exclude_edges: Vec::new(),
+            exclude_nodes: Vec::new(),
+            opt_max_hops: None,
+            opt_max_fees: None,
+        };
+
+        // First request is served:
+        client_sender
+            .send(IndexClientToServer::RequestRoutes(request_routes.clone()))
+            .await
+            .unwrap();
+
+        match graph_requests_receiver.next().await.unwrap() {
+            GraphRequest::GetMultiRoutes(_, _, _, _, _, response_sender) => {
+                response_sender.send(Vec::new()).unwrap();
+            }
+            _ => unreachable!(),
+        }
+
+        match client_receiver.next().await.unwrap() {
+            IndexServerToClient::ResponseRoutes(response_routes) => {
+                assert_eq!(response_routes.request_id, Uid::from(&[0; Uid::len()]));
+            }
+            _ => unreachable!(),
+        };
+
+        // Second request is rate limited:
+        let mut request_routes2 = request_routes.clone();
+        request_routes2.request_id = Uid::from(&[1; Uid::len()]);
+        client_sender
+            .send(IndexClientToServer::RequestRoutes(request_routes2))
+            .await
+            .unwrap();
+
+        // No routes are returned for the rejected request:
+        match client_receiver.next().await.unwrap() {
+            IndexServerToClient::ResponseRoutes(response_routes) => {
+                assert_eq!(response_routes.request_id, Uid::from(&[1; Uid::len()]));
+                assert!(response_routes.multi_routes.is_empty());
+            }
+            _ => unreachable!(),
+        };
+
+        // Mutations update is rejected:
+        let mutations_update = MutationsUpdate {
+            node_public_key: client_public_key.clone(),
+            index_mutations: Vec::new(),
+            time_hash: HashResult::from(&[0; HashResult::len()]),
+            session_id: Uid::from(&[2; Uid::len()]),
+            counter: 3,
+            rand_nonce: RandValue::from(&[0; RandValue::len()]),
+            signature: Signature::from(&[0; Signature::len()]),
+        };
+        client_sender
+            .send(IndexClientToServer::MutationsUpdate(mutations_update))
+            .await
+            .unwrap();
+
+        // The server closes the connection, so that the client will reconnect and send its full
+        // state again:
+        assert!(client_receiver.next().await.is_none());
+    }
+
+    #[test]
+    fn test_index_server_loop_client_rate_limit() {
+        let thread_pool = ThreadPool::new().unwrap();
+        block_on(task_index_server_loop_client_rate_limit(
+            thread_pool.clone(),
+        ));
+    }
+
     // ###########################################################
     // ###########################################################
 
     struct TestServer {
         public_key: PublicKey,
         tick_sender: mpsc::Sender<()>,
-        server_connections_sender: mpsc::Sender<(PublicKey, ServerConn)>,
+        server_connections_sender: mpsc::Sender<(PublicKey, ServerConn<u8>)>,
+        federation_requests_sender: mpsc::Sender<FederationRequest<u8>>,
         client_connections_sender: mpsc::Sender<(PublicKey, ClientConn)>,
-        graph_requests_receiver: mpsc::Receiver<GraphRequest<Currency, PublicKey, u128, Rate>>,
+        graph_requests_receiver:
+            mpsc::Receiver<GraphRequest<Currency, PublicKey, u128, Rate, ExchangeRate>>,
         server_conn_request_receiver:
-            mpsc::Receiver<ConnRequest<(PublicKey, u8), Option<ServerConn>>>,
+            mpsc::Receiver<ConnRequest<(PublicKey, u8), Option<ServerConn<u8>>>>,
         debug_event_receiver: mpsc::Receiver<()>,
     }
 
-    fn create_test_server<S>(index: u8, trusted_servers: &[u8], spawner: S) -> TestServer
+    fn create_test_server<S>(
+        index: u8,
+        trusted_servers: &[u8],
+        announce_ticks: usize,
+        spawner: S,
+    ) -> TestServer
     where
         S: Spawn + Clone + Send + 'static,
     {
@@ -938,6 +1564,7 @@ mod tests {
 
         let (server_connections_sender, incoming_server_connections) = mpsc::channel(0);
         let (client_connections_sender, incoming_client_connections) = mpsc::channel(0);
+        let (federation_requests_sender, incoming_federation_requests) = mpsc::channel(0);
 
         let (server_conn_request_sender, server_conn_request_receiver) = mpsc::channel(0);
         let server_connector = DummyConnector::new(server_conn_request_sender);
@@ -961,11 +1588,14 @@ mod tests {
             trusted_servers,
             incoming_server_connections,
             incoming_client_connections,
+            incoming_federation_requests,
             server_connector,
             graph_client,
             compare_public_key,
             verifier,
             timer_stream,
+            test_client_rate_limits(),
+            announce_ticks,
             spawner.clone(),
             Some(debug_event_sender),
         )
@@ -983,6 +1613,7 @@ mod tests {
             public_key: server_public_key.clone(),
             tick_sender,
             server_connections_sender,
+            federation_requests_sender,
             client_connections_sender,
             graph_requests_receiver,
             server_conn_request_receiver,
@@ -1038,11 +1669,36 @@ mod tests {
         let currency1 = Currency::try_from("FST1".to_owned()).unwrap();
 
         let mut test_servers = Vec::new();
-        test_servers.push(create_test_server(0, &[1, 2], spawner.clone()));
-        test_servers.push(create_test_server(1, &[0, 3], spawner.clone()));
-        test_servers.push(create_test_server(2, &[0, 3], spawner.clone()));
-        test_servers.push(create_test_server(3, &[1, 2, 4], spawner.clone()));
-        test_servers.push(create_test_server(4, &[3], spawner.clone()));
+        test_servers.push(create_test_server(
+            0,
+            &[1, 2],
+            NO_ANNOUNCE_TICKS,
+            spawner.clone(),
+        ));
+        test_servers.push(create_test_server(
+            1,
+            &[0, 3],
+            NO_ANNOUNCE_TICKS,
+            spawner.clone(),
+        ));
+        test_servers.push(create_test_server(
+            2,
+            &[0, 3],
+            NO_ANNOUNCE_TICKS,
+            spawner.clone(),
+        ));
+        test_servers.push(create_test_server(
+            3,
+            &[1, 2, 4],
+            NO_ANNOUNCE_TICKS,
+            spawner.clone(),
+        ));
+        test_servers.push(create_test_server(
+            4,
+            &[3],
+            NO_ANNOUNCE_TICKS,
+            spawner.clone(),
+        ));
 
         // Let all servers connect:
         handle_connect(&mut test_servers[..], 4).await; // 4 connects to {3}
@@ -1108,10 +1764,15 @@ mod tests {
         let request_routes = RequestRoutes {
             request_id: request_id.clone(),
             currency: currency1.clone(),
+            dest_currency: currency1.clone(),
             capacity: 100,
             source: PublicKey::from(&[8; PublicKey::len()]),
             destination: PublicKey::from(&[9; PublicKey::len()]),
             opt_exclude: None,
+            exclude_edges: Vec::new(),
+            exclude_nodes: Vec::new(),
+            opt_max_hops: None,
+            opt_max_fees: None,
         };
         client_sender
             .send(IndexClientToServer::RequestRoutes(request_routes))
@@ -1130,14 +1791,20 @@ mod tests {
                 src,
                 dest,
                 capacity,
-                opt_exclude,
+                constraints,
                 response_sender,
             ) => {
                 assert_eq!(currency, currency1);
                 assert_eq!(src, PublicKey::from(&[8; PublicKey::len()]));
                 assert_eq!(dest, PublicKey::from(&[9; PublicKey::len()]));
                 assert_eq!(capacity, 100);
-                assert_eq!(opt_exclude, None);
+                assert_eq!(
+                    constraints,
+                    RouteConstraints {
+                        opt_max_hops: Some(MAX_ROUTE_LEN - 1),
+                        ..RouteConstraints::new()
+                    }
+                );
                 response_sender.send(Vec::new()).unwrap();
             }
             _ => unreachable!(),
@@ -1253,5 +1920,103 @@ mod tests {
         block_on(task_index_server_loop_multi_server(thread_pool.clone()));
     }
 
+    async fn task_index_server_loop_federation<S>(spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let pk0 = PublicKey::from(&[0; PublicKey::len()]);
+        let pk1 = PublicKey::from(&[1; PublicKey::len()]);
+        let pk2 = PublicKey::from(&[2; PublicKey::len()]);
+
+        // Server 0 announces its trusted servers every tick. Server 2 is never connected.
+        let mut test_servers = Vec::new();
+        test_servers.push(create_test_server(0, &[1, 2], 1, spawner.clone()));
+        test_servers.push(create_test_server(
+            1,
+            &[0],
+            NO_ANNOUNCE_TICKS,
+            spawner.clone(),
+        ));
+
+        handle_connect(&mut test_servers[..], 1).await; // 1 connects to {0}
+
+        // Server 0 sends a time hash and its known servers to server 1:
+        test_servers[0].tick_sender.send(()).await.unwrap();
+        test_servers[0].debug_event_receiver.next().await.unwrap();
+        for _ in 0..2usize {
+            test_servers[1].debug_event_receiver.next().await.unwrap();
+        }
+
+        let (response_sender, response_receiver) = oneshot::channel();
+        test_servers[1]
+            .federation_requests_sender
+            .send(FederationRequest::GetTopology(response_sender))
+            .await
+            .unwrap();
+        let topology = response_receiver.await.unwrap();
+        test_servers[1].debug_event_receiver.next().await.unwrap();
+        assert_eq!(
+            topology,
+            vec![FederationServer {
+                server_address: IndexServerAddress {
+                    public_key: pk0.clone(),
+                    address: 0,
+                },
+                is_connected: true,
+                known_servers: vec![
+                    IndexServerAddress {
+                        public_key: pk1.clone(),
+                        address: 1,
+                    },
+                    IndexServerAddress {
+                        public_key: pk2.clone(),
+                        address: 2,
+                    },
+                ],
+            }]
+        );
+
+        // Add the discovered server 2 to server 1 at runtime:
+        let (response_sender, response_receiver) = oneshot::channel();
+        test_servers[1]
+            .federation_requests_sender
+            .send(FederationRequest::AddServer(
+                IndexServerAddress {
+                    public_key: pk2.clone(),
+                    address: 2,
+                },
+                response_sender,
+            ))
+            .await
+            .unwrap();
+        let topology = response_receiver.await.unwrap();
+        test_servers[1].debug_event_receiver.next().await.unwrap();
+        assert_eq!(topology.len(), 2);
+        assert_eq!(topology[1].server_address.public_key, pk2);
+        assert!(!topology[1].is_connected);
+        assert!(topology[1].known_servers.is_empty());
+
+        // Remove server 0 from server 1 at runtime:
+        let (response_sender, response_receiver) = oneshot::channel();
+        test_servers[1]
+            .federation_requests_sender
+            .send(FederationRequest::RemoveServer(
+                pk0.clone(),
+                response_sender,
+            ))
+            .await
+            .unwrap();
+        let topology = response_receiver.await.unwrap();
+        test_servers[1].debug_event_receiver.next().await.unwrap();
+        assert_eq!(topology.len(), 1);
+        assert_eq!(topology[0].server_address.public_key, pk2);
+    }
+
+    #[test]
+    fn test_index_server_loop_federation() {
+        let thread_pool = ThreadPool::new().unwrap();
+        block_on(task_index_server_loop_federation(thread_pool.clone()));
+    }
+
     // TODO: Add tests.
 }
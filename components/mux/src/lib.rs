@@ -0,0 +1,19 @@
+#![crate_type = "lib"]
+#![deny(trivial_numeric_casts, warnings)]
+#![allow(broken_intra_doc_links)]
+#![allow(
+    clippy::too_many_arguments,
+    clippy::implicit_hasher,
+    clippy::module_inception,
+    clippy::new_without_default
+)]
+
+#[macro_use]
+extern crate log;
+
+#[macro_use]
+extern crate common;
+
+mod mux;
+
+pub use self::mux::{create_mux, MuxClient, MuxClientError, MuxError};
@@ -0,0 +1,863 @@
+//! A multiplexer of logical streams over one connection, with per-stream flow control.
+//!
+//! Friend connections use the multiplexer if both sides agreed on it during the secure channel
+//! handshake. The funder messages are then sent on the first stream.
+
+use std::collections::HashMap;
+
+use futures::channel::{mpsc, oneshot};
+use futures::task::{Spawn, SpawnExt};
+use futures::{future, stream, FutureExt, SinkExt, Stream, StreamExt, TryFutureExt};
+
+use derive_more::From;
+
+use common::conn::{BoxSink, ConnPair, ConnPairVec, SinkError};
+use common::int_convert::{u32_to_usize, usize_to_u32};
+use common::select_streams::select_streams;
+
+use proto::mux::messages::{MuxMessage, StreamCredit, StreamData, StreamId, StreamOpen};
+use proto::proto_ser::{ProtoDeserialize, ProtoSerialize, ProtoSerializeError};
+
+#[derive(Debug, From)]
+pub enum MuxError {
+    ProtoSerializeError(ProtoSerializeError),
+    /// Remote side sent more messages than it was allowed to
+    CreditExceeded,
+    /// Remote side opened a stream with an id that was already used
+    InvalidRemoteStreamId,
+    InvalidStreamWindow,
+    SendToRemoteFailed,
+    SpawnError,
+}
+
+#[derive(Debug)]
+pub enum MuxClientError {
+    SendRequestError,
+    ResponseReceiverClosed,
+    /// We already have the maximum amount of open streams
+    TooManyStreams,
+}
+
+struct OpenStreamRequest {
+    /// Receives `None` if we already have too many open streams
+    response_sender: oneshot::Sender<Option<ConnPairVec>>,
+}
+
+/// A client for opening new logical streams to the remote side.
+#[derive(Clone)]
+pub struct MuxClient {
+    requests_sender: mpsc::Sender<OpenStreamRequest>,
+}
+
+impl MuxClient {
+    fn new(requests_sender: mpsc::Sender<OpenStreamRequest>) -> Self {
+        MuxClient { requests_sender }
+    }
+
+    /// Open a new logical stream to the remote side.
+    pub async fn open_stream(&mut self) -> Result<ConnPairVec, MuxClientError> {
+        let (response_sender, response_receiver) = oneshot::channel();
+        self.requests_sender
+            .send(OpenStreamRequest { response_sender })
+            .await
+            .map_err(|_| MuxClientError::SendRequestError)?;
+        response_receiver
+            .await
+            .map_err(|_| MuxClientError::ResponseReceiverClosed)?
+            .ok_or(MuxClientError::TooManyStreams)
+    }
+}
+
+/// Identifies a stream from our point of view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct LocalStreamId {
+    opened_locally: bool,
+    id: u32,
+}
+
+impl LocalStreamId {
+    /// The stream id as sent to the remote side
+    fn to_remote(self) -> StreamId {
+        StreamId {
+            id: self.id,
+            opened_by_sender: self.opened_locally,
+        }
+    }
+
+    /// Translate a stream id received from the remote side
+    fn from_remote(stream_id: &StreamId) -> Self {
+        LocalStreamId {
+            opened_locally: !stream_id.opened_by_sender,
+            id: stream_id.id,
+        }
+    }
+}
+
+struct StreamState {
+    /// Messages from the remote side, waiting to be received by the user
+    to_user: mpsc::Sender<Vec<u8>>,
+    /// Amount of messages the remote side may still send on this stream
+    recv_credit: usize,
+    /// Amount of messages received by the user that were not yet reported to the remote side
+    consumed: usize,
+    /// Amount of messages we may still send to the remote side on this stream
+    send_credit: usize,
+    /// A message from the user, waiting for send credit
+    opt_pending: Option<(Vec<u8>, oneshot::Sender<()>)>,
+}
+
+enum StreamEvent {
+    /// The user wants to send a message. The sender is notified when the message is sent.
+    UserMessage((LocalStreamId, Vec<u8>, oneshot::Sender<()>)),
+    /// The user received a message
+    Consumed(LocalStreamId),
+    /// The user closed the stream
+    Closed(LocalStreamId),
+}
+
+enum MuxEvent {
+    Remote(Vec<u8>),
+    RemoteClosed,
+    OpenStreamRequest(OpenStreamRequest),
+    /// All the clients were dropped
+    ClientsClosed,
+    Stream(StreamEvent),
+}
+
+/// Forward messages from the user to the mux loop, one message at a time.
+async fn stream_sender_loop(
+    local_stream_id: LocalStreamId,
+    mut from_user: mpsc::Receiver<Vec<u8>>,
+    mut event_sender: mpsc::Sender<StreamEvent>,
+) {
+    while let Some(data) = from_user.next().await {
+        let (sent_sender, sent_receiver) = oneshot::channel();
+        if event_sender
+            .send(StreamEvent::UserMessage((
+                local_stream_id,
+                data,
+                sent_sender,
+            )))
+            .await
+            .is_err()
+        {
+            return;
+        }
+        // Wait until we have enough credit to send the message:
+        if sent_receiver.await.is_err() {
+            // The stream was closed:
+            return;
+        }
+    }
+    let _ = event_sender
+        .send(StreamEvent::Closed(local_stream_id))
+        .await;
+}
+
+/// Report that the user closed the receiving side of the stream.
+async fn stream_receiver_loop(
+    local_stream_id: LocalStreamId,
+    user_closed: oneshot::Receiver<()>,
+    mut event_sender: mpsc::Sender<StreamEvent>,
+) {
+    // Nothing is ever sent, the sender is only dropped together with the user's receiver:
+    let _ = user_closed.await;
+    let _ = event_sender
+        .send(StreamEvent::Closed(local_stream_id))
+        .await;
+}
+
+/// The receiving side of a stream, as given to the user.
+/// Every message is reported when the user reads it, and not earlier, so that we never grant the
+/// remote side credit for messages that are still buffered.
+fn user_receiver(
+    local_stream_id: LocalStreamId,
+    from_mux: mpsc::Receiver<Vec<u8>>,
+    event_sender: mpsc::Sender<StreamEvent>,
+    user_closed_sender: oneshot::Sender<()>,
+) -> impl Stream<Item = Vec<u8>> {
+    stream::unfold(
+        (from_mux, event_sender, user_closed_sender),
+        move |(mut from_mux, mut event_sender, user_closed_sender)| async move {
+            let data = from_mux.next().await?;
+            let _ = event_sender
+                .send(StreamEvent::Consumed(local_stream_id))
+                .await;
+            Some((data, (from_mux, event_sender, user_closed_sender)))
+        },
+    )
+}
+
+struct Mux<S> {
+    to_remote: BoxSink<'static, Vec<u8>, SinkError>,
+    streams: HashMap<LocalStreamId, StreamState>,
+    /// Id for the next stream we open
+    next_local_id: u32,
+    /// Streams opened by the remote side must have an id at least this large
+    next_remote_id: u32,
+    stream_window: usize,
+    /// Maximum amount of open streams opened by each side
+    max_streams: usize,
+    /// Streams opened by the remote side, waiting to be accepted by the user
+    incoming_streams_sender: mpsc::Sender<ConnPairVec>,
+    event_sender: mpsc::Sender<StreamEvent>,
+    spawner: S,
+}
+
+impl<S> Mux<S>
+where
+    S: Spawn,
+{
+    async fn send_to_remote(&mut self, mux_message: MuxMessage) -> Result<(), MuxError> {
+        self.to_remote
+            .send(mux_message.proto_serialize())
+            .await
+            .map_err(|_| MuxError::SendToRemoteFailed)
+    }
+
+    /// Create the state of a new stream, and spawn the tasks serving the user.
+    /// Returns the user side of the stream.
+    fn add_stream(
+        &mut self,
+        local_stream_id: LocalStreamId,
+        send_credit: usize,
+    ) -> Result<ConnPairVec, MuxError> {
+        // Note that the internal channel can hold `stream_window` messages, so we can always push
+        // messages into it if the remote side does not exceed its credit.
+        let (mux_sender, from_mux) = mpsc::channel::<Vec<u8>>(self.stream_window);
+        let (user_sender, from_user) = mpsc::channel::<Vec<u8>>(0);
+        let (user_closed_sender, user_closed) = oneshot::channel::<()>();
+
+        self.spawner
+            .spawn(stream_sender_loop(
+                local_stream_id,
+                from_user,
+                self.event_sender.clone(),
+            ))
+            .map_err(|_| MuxError::SpawnError)?;
+        self.spawner
+            .spawn(stream_receiver_loop(
+                local_stream_id,
+                user_closed,
+                self.event_sender.clone(),
+            ))
+            .map_err(|_| MuxError::SpawnError)?;
+        let user_receiver = user_receiver(
+            local_stream_id,
+            from_mux,
+            self.event_sender.clone(),
+            user_closed_sender,
+        );
+
+        self.streams.insert(
+            local_stream_id,
+            StreamState {
+                to_user: mux_sender,
+                recv_credit: self.stream_window,
+                consumed: 0,
+                send_credit,
+                opt_pending: None,
+            },
+        );
+        Ok(ConnPair::from_raw(user_sender, user_receiver))
+    }
+
+    /// Amount of open streams that were opened by one of the sides
+    fn num_streams(&self, opened_locally: bool) -> usize {
+        self.streams
+            .keys()
+            .filter(|local_stream_id| local_stream_id.opened_locally == opened_locally)
+            .count()
+    }
+
+    fn stream_window_u32(&self) -> u32 {
+        // stream_window was verified to fit into u32 when the mux was created:
+        usize_to_u32(self.stream_window).unwrap()
+    }
+
+    async fn handle_open_stream_request(
+        &mut self,
+        open_stream_request: OpenStreamRequest,
+    ) -> Result<(), MuxError> {
+        if self.num_streams(true) >= self.max_streams {
+            let _ = open_stream_request.response_sender.send(None);
+            return Ok(());
+        }
+        let id = match self.next_local_id.checked_add(1) {
+            Some(next_local_id) => {
+                let id = self.next_local_id;
+                self.next_local_id = next_local_id;
+                id
+            }
+            None => {
+                warn!("Mux: Out of stream ids");
+                return Ok(());
+            }
+        };
+        let local_stream_id = LocalStreamId {
+            opened_locally: true,
+            id,
+        };
+
+        // We may only send messages after the remote side grants us credit:
+        let conn_pair = self.add_stream(local_stream_id, 0)?;
+        let stream_open = StreamOpen {
+            stream_id: local_stream_id.to_remote(),
+            credit: self.stream_window_u32(),
+        };
+        self.send_to_remote(MuxMessage::Open(stream_open)).await?;
+
+        // If the requester is gone, the stream will be closed by the stream tasks:
+        let _ = open_stream_request.response_sender.send(Some(conn_pair));
+        Ok(())
+    }
+
+    async fn handle_remote_open(&mut self, stream_open: StreamOpen) -> Result<(), MuxError> {
+        let local_stream_id = LocalStreamId::from_remote(&stream_open.stream_id);
+        // Stream ids may never be reused, so that messages of a closed stream can not be mistaken
+        // for messages of a new stream:
+        if local_stream_id.opened_locally || local_stream_id.id < self.next_remote_id {
+            return Err(MuxError::InvalidRemoteStreamId);
+        }
+        self.next_remote_id = local_stream_id
+            .id
+            .checked_add(1)
+            .ok_or(MuxError::InvalidRemoteStreamId)?;
+
+        if self.num_streams(false) >= self.max_streams {
+            warn!("Mux: Too many streams opened by the remote side");
+            return self
+                .send_to_remote(MuxMessage::Close(local_stream_id.to_remote()))
+                .await;
+        }
+
+        let send_credit = u32_to_usize(stream_open.credit).unwrap();
+        let conn_pair = self.add_stream(local_stream_id, send_credit)?;
+        // We never wait for the user to accept a stream, as it would block all other streams:
+        if self.incoming_streams_sender.try_send(conn_pair).is_err() {
+            // The user is not accepting incoming streams:
+            self.streams.remove(&local_stream_id);
+            return self
+                .send_to_remote(MuxMessage::Close(local_stream_id.to_remote()))
+                .await;
+        }
+
+        let stream_credit = StreamCredit {
+            stream_id: local_stream_id.to_remote(),
+            credit: self.stream_window_u32(),
+        };
+        self.send_to_remote(MuxMessage::Credit(stream_credit)).await
+    }
+
+    fn handle_remote_data(&mut self, stream_data: StreamData) -> Result<(), MuxError> {
+        let local_stream_id = LocalStreamId::from_remote(&stream_data.stream_id);
+        let stream_state = match self.streams.get_mut(&local_stream_id) {
+            Some(stream_state) => stream_state,
+            None => {
+                // The stream might have been closed recently:
+                return Ok(());
+            }
+        };
+        stream_state.recv_credit = stream_state
+            .recv_credit
+            .checked_sub(1)
+            .ok_or(MuxError::CreditExceeded)?;
+        // If the user is gone, a Closed event will soon arrive:
+        let _ = stream_state.to_user.try_send(stream_data.data);
+        Ok(())
+    }
+
+    async fn handle_remote_credit(&mut self, stream_credit: StreamCredit) -> Result<(), MuxError> {
+        let local_stream_id = LocalStreamId::from_remote(&stream_credit.stream_id);
+        let stream_state = match self.streams.get_mut(&local_stream_id) {
+            Some(stream_state) => stream_state,
+            None => return Ok(()),
+        };
+        stream_state.send_credit = stream_state
+            .send_credit
+            .saturating_add(u32_to_usize(stream_credit.credit).unwrap());
+
+        if stream_state.send_credit == 0 {
+            return Ok(());
+        }
+        if let Some((data, sent_sender)) = stream_state.opt_pending.take() {
+            stream_state.send_credit -= 1;
+            let stream_data = StreamData {
+                stream_id: local_stream_id.to_remote(),
+                data,
+            };
+            self.send_to_remote(MuxMessage::Data(stream_data)).await?;
+            let _ = sent_sender.send(());
+        }
+        Ok(())
+    }
+
+    async fn handle_stream_event(&mut self, stream_event: StreamEvent) -> Result<(), MuxError> {
+        match stream_event {
+            StreamEvent::UserMessage((local_stream_id, data, sent_sender)) => {
+                let stream_state = match self.streams.get_mut(&local_stream_id) {
+                    Some(stream_state) => stream_state,
+                    None => return Ok(()),
+                };
+                if stream_state.send_credit == 0 {
+                    stream_state.opt_pending = Some((data, sent_sender));
+                    return Ok(());
+                }
+                stream_state.send_credit -= 1;
+                let stream_data = StreamData {
+                    stream_id: local_stream_id.to_remote(),
+                    data,
+                };
+                self.send_to_remote(MuxMessage::Data(stream_data)).await?;
+                let _ = sent_sender.send(());
+            }
+            StreamEvent::Consumed(local_stream_id) => {
+                let stream_window = self.stream_window;
+                let stream_state = match self.streams.get_mut(&local_stream_id) {
+                    Some(stream_state) => stream_state,
+                    None => return Ok(()),
+                };
+                stream_state.consumed += 1;
+                // Report credit in batches, to avoid sending a credit message for every message:
+                if stream_state.consumed < (stream_window / 2).max(1) {
+                    return Ok(());
+                }
+                let credit = stream_state.consumed;
+                stream_state.consumed = 0;
+                stream_state.recv_credit += credit;
+                let stream_credit = StreamCredit {
+                    stream_id: local_stream_id.to_remote(),
+                    credit: usize_to_u32(credit).unwrap(),
+                };
+                self.send_to_remote(MuxMessage::Credit(stream_credit))
+                    .await?;
+            }
+            StreamEvent::Closed(local_stream_id) => {
+                if self.streams.remove(&local_stream_id).is_some() {
+                    self.send_to_remote(MuxMessage::Close(local_stream_id.to_remote()))
+                        .await?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+async fn mux_loop<S>(
+    conn_pair: ConnPairVec,
+    incoming_requests: mpsc::Receiver<OpenStreamRequest>,
+    incoming_streams_sender: mpsc::Sender<ConnPairVec>,
+    stream_window: usize,
+    max_streams: usize,
+    spawner: S,
+) -> Result<(), MuxError>
+where
+    S: Spawn,
+{
+    let (to_remote, from_remote) = conn_pair.split();
+    let (event_sender, stream_events) = mpsc::channel::<StreamEvent>(0);
+
+    let mut mux = Mux {
+        to_remote,
+        streams: HashMap::new(),
+        next_local_id: 0,
+        next_remote_id: 0,
+        stream_window,
+        max_streams,
+        incoming_streams_sender,
+        event_sender,
+        spawner,
+    };
+
+    let from_remote = from_remote
+        .map(MuxEvent::Remote)
+        .chain(stream::once(future::ready(MuxEvent::RemoteClosed)));
+    let incoming_requests = incoming_requests
+        .map(MuxEvent::OpenStreamRequest)
+        .chain(stream::once(future::ready(MuxEvent::ClientsClosed)));
+    let stream_events = stream_events.map(MuxEvent::Stream);
+
+    let mut events = select_streams![from_remote, incoming_requests, stream_events];
+
+    let mut clients_closed = false;
+    while let Some(event) = events.next().await {
+        match event {
+            MuxEvent::Remote(data) => match MuxMessage::proto_deserialize(&data)? {
+                MuxMessage::Open(stream_open) => mux.handle_remote_open(stream_open).await?,
+                MuxMessage::Data(stream_data) => mux.handle_remote_data(stream_data)?,
+                MuxMessage::Credit(stream_credit) => {
+                    mux.handle_remote_credit(stream_credit).await?
+                }
+                MuxMessage::Close(stream_id) => {
+                    // Dropping the stream state closes the stream for the user:
+                    mux.streams.remove(&LocalStreamId::from_remote(&stream_id));
+                }
+            },
+            MuxEvent::RemoteClosed => {
+                info!("mux_loop(): Remote closed");
+                break;
+            }
+            MuxEvent::OpenStreamRequest(open_stream_request) => {
+                mux.handle_open_stream_request(open_stream_request).await?
+            }
+            MuxEvent::ClientsClosed => clients_closed = true,
+            MuxEvent::Stream(stream_event) => mux.handle_stream_event(stream_event).await?,
+        }
+        // Nobody can use the connection anymore:
+        if clients_closed && mux.incoming_streams_sender.is_closed() && mux.streams.is_empty() {
+            info!("mux_loop(): All streams closed");
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// Multiplex many logical streams over one connection.
+///
+/// Returns a client that opens new streams to the remote side, and a receiver of the streams
+/// opened by the remote side. Dropping either the sender or the receiver of a stream closes the
+/// stream. The connection is closed once all the clients, the receiver of incoming streams and all
+/// the streams were dropped.
+///
+/// `stream_window` is the maximum amount of messages that can be sent on a stream before they are
+/// received by the remote user. A slow reader of one stream therefore never blocks the other
+/// streams.
+///
+/// `max_streams` is the maximum amount of open streams opened by each side. Streams opened by the
+/// remote side beyond this limit are closed immediately.
+pub fn create_mux<S>(
+    conn_pair: ConnPairVec,
+    stream_window: usize,
+    max_streams: usize,
+    spawner: S,
+) -> Result<(MuxClient, mpsc::Receiver<ConnPairVec>), MuxError>
+where
+    S: Spawn + Clone + Send + 'static,
+{
+    if stream_window == 0 || usize_to_u32(stream_window).is_none() {
+        return Err(MuxError::InvalidStreamWindow);
+    }
+
+    let (requests_sender, incoming_requests) = mpsc::channel(0);
+    // Incoming streams are counted as open streams, so the buffer never holds more than
+    // `max_streams` streams:
+    let (incoming_streams_sender, incoming_streams) = mpsc::channel(max_streams);
+
+    let mux_fut = mux_loop(
+        conn_pair,
+        incoming_requests,
+        incoming_streams_sender,
+        stream_window,
+        max_streams,
+        spawner.clone(),
+    )
+    .map_err(|e| warn!("mux_loop() error: {:?}", e))
+    .map(|_| ());
+
+    spawner.spawn(mux_fut).map_err(|_| MuxError::SpawnError)?;
+
+    Ok((MuxClient::new(requests_sender), incoming_streams))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::executor::{LocalPool, ThreadPool};
+
+    /// Create two muxes connected to each other
+    fn create_mux_pair<S>(
+        stream_window: usize,
+        max_streams: usize,
+        spawner: S,
+    ) -> (
+        (MuxClient, mpsc::Receiver<ConnPairVec>),
+        (MuxClient, mpsc::Receiver<ConnPairVec>),
+    )
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let (a_sender, b_receiver) = mpsc::channel::<Vec<u8>>(16);
+        let (b_sender, a_receiver) = mpsc::channel::<Vec<u8>>(16);
+
+        let mux_a = create_mux(
+            ConnPair::from_raw(a_sender, a_receiver),
+            stream_window,
+            max_streams,
+            spawner.clone(),
+        )
+        .unwrap();
+        let mux_b = create_mux(
+            ConnPair::from_raw(b_sender, b_receiver),
+            stream_window,
+            max_streams,
+            spawner,
+        )
+        .unwrap();
+        (mux_a, mux_b)
+    }
+
+    async fn task_mux_basic(spawner: impl Spawn + Clone + Send + 'static) {
+        let ((mut client_a, mut incoming_a), (mut client_b, mut incoming_b)) =
+            create_mux_pair(4, 8, spawner);
+
+        // A opens a stream to B:
+        let (mut sender_a1, mut receiver_a1) = client_a.open_stream().await.unwrap().split();
+        let (mut sender_b1, mut receiver_b1) = incoming_b.next().await.unwrap().split();
+
+        // B opens a stream to A:
+        let (mut sender_b2, mut receiver_b2) = client_b.open_stream().await.unwrap().split();
+        let (mut sender_a2, mut receiver_a2) = incoming_a.next().await.unwrap().split();
+
+        for i in 0..8u8 {
+            sender_a1.send(vec![i]).await.unwrap();
+            assert_eq!(receiver_b1.next().await.unwrap(), vec![i]);
+
+            sender_b1.send(vec![i, 1]).await.unwrap();
+            assert_eq!(receiver_a1.next().await.unwrap(), vec![i, 1]);
+
+            sender_b2.send(vec![i, 2]).await.unwrap();
+            assert_eq!(receiver_a2.next().await.unwrap(), vec![i, 2]);
+
+            sender_a2.send(vec![i, 3]).await.unwrap();
+            assert_eq!(receiver_b2.next().await.unwrap(), vec![i, 3]);
+        }
+
+        // Closing a stream on one side closes it on the other side:
+        drop(sender_a1);
+        drop(receiver_a1);
+        assert!(receiver_b1.next().await.is_none());
+
+        // Other streams are not affected:
+        sender_b2.send(vec![4]).await.unwrap();
+        assert_eq!(receiver_a2.next().await.unwrap(), vec![4]);
+    }
+
+    #[test]
+    fn test_mux_basic() {
+        let thread_pool = ThreadPool::new().unwrap();
+        LocalPool::new().run_until(task_mux_basic(thread_pool));
+    }
+
+    async fn task_mux_no_head_of_line_blocking(spawner: impl Spawn + Clone + Send + 'static) {
+        let ((mut client_a, _incoming_a), (_client_b, mut incoming_b)) =
+            create_mux_pair(2, 8, spawner.clone());
+
+        let (mut sender_a1, _receiver_a1) = client_a.open_stream().await.unwrap().split();
+        let (_sender_b1, mut receiver_b1) = incoming_b.next().await.unwrap().split();
+
+        // Send more messages than the stream window, without reading them on the other side.
+        // The sending task will be blocked until B reads the messages.
+        let (done_sender, done_receiver) = oneshot::channel::<()>();
+        spawner
+            .spawn(async move {
+                for i in 0..16u8 {
+                    sender_a1.send(vec![i]).await.unwrap();
+                }
+                done_sender.send(()).unwrap();
+            })
+            .unwrap();
+
+        // A second stream still works:
+        let (mut sender_a2, _receiver_a2) = client_a.open_stream().await.unwrap().split();
+        let (_sender_b2, mut receiver_b2) = incoming_b.next().await.unwrap().split();
+        for i in 0..16u8 {
+            sender_a2.send(vec![i]).await.unwrap();
+            assert_eq!(receiver_b2.next().await.unwrap(), vec![i]);
+        }
+
+        // All messages of the first stream arrive, in order:
+        for i in 0..16u8 {
+            assert_eq!(receiver_b1.next().await.unwrap(), vec![i]);
+        }
+        done_receiver.await.unwrap();
+    }
+
+    #[test]
+    fn test_mux_no_head_of_line_blocking() {
+        let thread_pool = ThreadPool::new().unwrap();
+        LocalPool::new().run_until(task_mux_no_head_of_line_blocking(thread_pool));
+    }
+
+    async fn task_mux_credit_exceeded(spawner: impl Spawn + Clone + Send + 'static) {
+        let (mut raw_sender, b_receiver) = mpsc::channel::<Vec<u8>>(16);
+        let (b_sender, mut raw_receiver) = mpsc::channel::<Vec<u8>>(16);
+
+        let (_client_b, mut incoming_b) =
+            create_mux(ConnPair::from_raw(b_sender, b_receiver), 2, 8, spawner).unwrap();
+
+        let stream_id = StreamId {
+            id: 0,
+            opened_by_sender: true,
+        };
+        let stream_open = StreamOpen {
+            stream_id: stream_id.clone(),
+            credit: 1,
+        };
+        raw_sender
+            .send(MuxMessage::Open(stream_open).proto_serialize())
+            .await
+            .unwrap();
+
+        // Accept the stream, but do not read from it yet:
+        let (_sender_b, mut receiver_b) = incoming_b.next().await.unwrap().split();
+
+        let data = raw_receiver.next().await.unwrap();
+        match MuxMessage::proto_deserialize(&data).unwrap() {
+            MuxMessage::Credit(stream_credit) => {
+                assert_eq!(stream_credit.stream_id, stream_id);
+                assert_eq!(stream_credit.credit, 2);
+            }
+            _ => unreachable!(),
+        };
+
+        for i in 0..2u8 {
+            let stream_data = StreamData {
+                stream_id: stream_id.clone(),
+                data: vec![i],
+            };
+            raw_sender
+                .send(MuxMessage::Data(stream_data).proto_serialize())
+                .await
+                .unwrap();
+        }
+
+        // Credit is granted only after the user reads a message:
+        assert_eq!(receiver_b.next().await.unwrap(), vec![0]);
+        let data = raw_receiver.next().await.unwrap();
+        match MuxMessage::proto_deserialize(&data).unwrap() {
+            MuxMessage::Credit(stream_credit) => {
+                assert_eq!(stream_credit.stream_id, stream_id);
+                assert_eq!(stream_credit.credit, 1);
+            }
+            _ => unreachable!(),
+        };
+
+        // Send more messages than the granted credit. The messages that were not read by the user
+        // are not credited:
+        for i in 2..4u8 {
+            let stream_data = StreamData {
+                stream_id: stream_id.clone(),
+                data: vec![i],
+            };
+            // The mux might have already closed the connection:
+            let _ = raw_sender
+                .send(MuxMessage::Data(stream_data).proto_serialize())
+                .await;
+        }
+
+        // The mux closes the connection:
+        assert!(raw_receiver.next().await.is_none());
+    }
+
+    #[test]
+    fn test_mux_credit_exceeded() {
+        let thread_pool = ThreadPool::new().unwrap();
+        LocalPool::new().run_until(task_mux_credit_exceeded(thread_pool));
+    }
+
+    async fn task_mux_max_streams(spawner: impl Spawn + Clone + Send + 'static) {
+        let ((mut client_a, _incoming_a), (mut client_b, mut incoming_b)) =
+            create_mux_pair(2, 2, spawner);
+
+        // B does not accept the incoming streams yet. Opening streams never blocks A:
+        let conn_pair_a1 = client_a.open_stream().await.unwrap();
+        let _conn_pair_a2 = client_a.open_stream().await.unwrap();
+
+        // A may not open more than max_streams streams:
+        match client_a.open_stream().await {
+            Err(MuxClientError::TooManyStreams) => {}
+            _ => unreachable!(),
+        };
+
+        // Streams opened by B are not limited by the streams opened by A:
+        let _conn_pair_b = client_b.open_stream().await.unwrap();
+
+        let (mut sender_a1, _receiver_a1) = conn_pair_a1.split();
+        let (_sender_b1, mut receiver_b1) = incoming_b.next().await.unwrap().split();
+        let _conn_pair_b2 = incoming_b.next().await.unwrap();
+
+        sender_a1.send(vec![1]).await.unwrap();
+        assert_eq!(receiver_b1.next().await.unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn test_mux_max_streams() {
+        let thread_pool = ThreadPool::new().unwrap();
+        LocalPool::new().run_until(task_mux_max_streams(thread_pool));
+    }
+
+    async fn task_mux_close_connection(spawner: impl Spawn + Clone + Send + 'static) {
+        let (_raw_sender, b_receiver) = mpsc::channel::<Vec<u8>>(16);
+        let (b_sender, mut raw_receiver) = mpsc::channel::<Vec<u8>>(16);
+
+        let (mut client_b, incoming_b) =
+            create_mux(ConnPair::from_raw(b_sender, b_receiver), 2, 8, spawner).unwrap();
+        let conn_pair_b = client_b.open_stream().await.unwrap();
+        drop(client_b);
+        drop(incoming_b);
+
+        let data = raw_receiver.next().await.unwrap();
+        match MuxMessage::proto_deserialize(&data).unwrap() {
+            MuxMessage::Open(stream_open) => assert_eq!(stream_open.stream_id.id, 0),
+            _ => unreachable!(),
+        };
+
+        // Closing the last stream closes the connection:
+        drop(conn_pair_b);
+        let data = raw_receiver.next().await.unwrap();
+        match MuxMessage::proto_deserialize(&data).unwrap() {
+            MuxMessage::Close(stream_id) => assert_eq!(stream_id.id, 0),
+            _ => unreachable!(),
+        };
+        assert!(raw_receiver.next().await.is_none());
+    }
+
+    #[test]
+    fn test_mux_close_connection() {
+        let thread_pool = ThreadPool::new().unwrap();
+        LocalPool::new().run_until(task_mux_close_connection(thread_pool));
+    }
+
+    async fn task_mux_remote_exceeds_max_streams(spawner: impl Spawn + Clone + Send + 'static) {
+        let (mut raw_sender, b_receiver) = mpsc::channel::<Vec<u8>>(16);
+        let (b_sender, mut raw_receiver) = mpsc::channel::<Vec<u8>>(16);
+
+        let (_client_b, _incoming_b) =
+            create_mux(ConnPair::from_raw(b_sender, b_receiver), 2, 1, spawner).unwrap();
+
+        for id in 0..2u32 {
+            let stream_open = StreamOpen {
+                stream_id: StreamId {
+                    id,
+                    opened_by_sender: true,
+                },
+                credit: 1,
+            };
+            raw_sender
+                .send(MuxMessage::Open(stream_open).proto_serialize())
+                .await
+                .unwrap();
+        }
+
+        // The first stream is accepted:
+        let data = raw_receiver.next().await.unwrap();
+        match MuxMessage::proto_deserialize(&data).unwrap() {
+            MuxMessage::Credit(stream_credit) => assert_eq!(stream_credit.stream_id.id, 0),
+            _ => unreachable!(),
+        };
+
+        // The second stream is closed immediately:
+        let data = raw_receiver.next().await.unwrap();
+        match MuxMessage::proto_deserialize(&data).unwrap() {
+            MuxMessage::Close(stream_id) => assert_eq!(stream_id.id, 1),
+            _ => unreachable!(),
+        };
+    }
+
+    #[test]
+    fn test_mux_remote_exceeds_max_streams() {
+        let thread_pool = ThreadPool::new().unwrap();
+        LocalPool::new().run_until(task_mux_remote_exceeds_max_streams(thread_pool));
+    }
+}
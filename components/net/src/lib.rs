@@ -11,12 +11,25 @@
 #[macro_use]
 extern crate log;
 
+mod socks5;
 mod tcp_connector;
 mod tcp_listener;
 #[cfg(test)]
 mod tests;
 mod types;
+#[cfg(unix)]
+mod unix_connector;
+#[cfg(unix)]
+mod unix_listener;
 mod utils;
+mod ws_connector;
+mod ws_listener;
 
 pub use self::tcp_connector::TcpConnector;
-pub use self::tcp_listener::TcpListener;
+pub use self::tcp_listener::{TcpListener, TcpListenerError};
+#[cfg(unix)]
+pub use self::unix_connector::UnixConnector;
+#[cfg(unix)]
+pub use self::unix_listener::{bind_unix_socket, SocketFileGuard, UnixListener, UnixListenerError};
+pub use self::ws_connector::WsConnector;
+pub use self::ws_listener::{WsListener, WsListenerError};
@@ -0,0 +1,143 @@
+//! A minimal SOCKS5 client (RFC 1928), supporting only the CONNECT command without
+//! authentication. This is enough to route outgoing connections through Tor.
+
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+const SOCKS5_VERSION: u8 = 0x05;
+const SOCKS5_AUTH_NONE: u8 = 0x00;
+const SOCKS5_CMD_CONNECT: u8 = 0x01;
+const SOCKS5_RESERVED: u8 = 0x00;
+const SOCKS5_REPLY_SUCCEEDED: u8 = 0x00;
+
+const SOCKS5_ATYP_IPV4: u8 = 0x01;
+const SOCKS5_ATYP_DOMAIN: u8 = 0x03;
+const SOCKS5_ATYP_IPV6: u8 = 0x04;
+
+#[derive(Debug)]
+pub enum Socks5Error {
+    InvalidAddress,
+    IoError(std::io::Error),
+    InvalidVersion,
+    AuthMethodRejected,
+    ConnectFailed(u8),
+    InvalidAddressType,
+}
+
+impl From<std::io::Error> for Socks5Error {
+    fn from(e: std::io::Error) -> Self {
+        Socks5Error::IoError(e)
+    }
+}
+
+/// Encode a destination address of the form host:port as a SOCKS5 address
+/// (ATYP, DST.ADDR, DST.PORT)
+fn encode_address(address: &str) -> Result<Vec<u8>, Socks5Error> {
+    let colon_index = address.rfind(':').ok_or(Socks5Error::InvalidAddress)?;
+    let (host, port_str) = (&address[..colon_index], &address[colon_index + 1..]);
+    let port: u16 = port_str.parse().map_err(|_| Socks5Error::InvalidAddress)?;
+
+    let mut data = Vec::new();
+    if let Ok(ipv4_addr) = host.parse::<Ipv4Addr>() {
+        data.push(SOCKS5_ATYP_IPV4);
+        data.extend_from_slice(&ipv4_addr.octets());
+    } else if host.starts_with('[') && host.ends_with(']') {
+        let ipv6_addr = host[1..host.len() - 1]
+            .parse::<Ipv6Addr>()
+            .map_err(|_| Socks5Error::InvalidAddress)?;
+        data.push(SOCKS5_ATYP_IPV6);
+        data.extend_from_slice(&ipv6_addr.octets());
+    } else {
+        // A domain name. We let the proxy resolve it, so that we do not leak DNS queries.
+        if host.is_empty() || host.len() > usize::from(u8::max_value()) {
+            return Err(Socks5Error::InvalidAddress);
+        }
+        data.push(SOCKS5_ATYP_DOMAIN);
+        data.push(host.len() as u8);
+        data.extend_from_slice(host.as_bytes());
+    }
+    data.extend_from_slice(&port.to_be_bytes());
+    Ok(data)
+}
+
+/// Ask a SOCKS5 proxy (Already connected using `stream`) to connect us to `address`.
+/// On success, `stream` can be used to communicate with the remote host.
+pub async fn socks5_connect<T>(stream: &mut T, address: &str) -> Result<(), Socks5Error>
+where
+    T: AsyncRead + AsyncWrite + Unpin,
+{
+    let encoded_address = encode_address(address)?;
+
+    // Greeting: We only support connecting without authentication:
+    stream
+        .write_all(&[SOCKS5_VERSION, 0x01, SOCKS5_AUTH_NONE])
+        .await?;
+    let mut method_selection = [0u8; 2];
+    stream.read_exact(&mut method_selection).await?;
+    if method_selection[0] != SOCKS5_VERSION {
+        return Err(Socks5Error::InvalidVersion);
+    }
+    if method_selection[1] != SOCKS5_AUTH_NONE {
+        return Err(Socks5Error::AuthMethodRejected);
+    }
+
+    // Connect request:
+    let mut request = vec![SOCKS5_VERSION, SOCKS5_CMD_CONNECT, SOCKS5_RESERVED];
+    request.extend_from_slice(&encoded_address);
+    stream.write_all(&request).await?;
+
+    // Reply:
+    let mut reply_header = [0u8; 4];
+    stream.read_exact(&mut reply_header).await?;
+    if reply_header[0] != SOCKS5_VERSION {
+        return Err(Socks5Error::InvalidVersion);
+    }
+    if reply_header[1] != SOCKS5_REPLY_SUCCEEDED {
+        return Err(Socks5Error::ConnectFailed(reply_header[1]));
+    }
+
+    // Read (and discard) the bound address:
+    let bound_address_len = match reply_header[3] {
+        SOCKS5_ATYP_IPV4 => 4,
+        SOCKS5_ATYP_IPV6 => 16,
+        SOCKS5_ATYP_DOMAIN => {
+            let mut domain_len = [0u8; 1];
+            stream.read_exact(&mut domain_len).await?;
+            usize::from(domain_len[0])
+        }
+        _ => return Err(Socks5Error::InvalidAddressType),
+    };
+    // Bound address + port:
+    let mut bound_address = vec![0u8; bound_address_len + 2];
+    stream.read_exact(&mut bound_address).await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_address() {
+        assert_eq!(
+            encode_address("127.0.0.1:1337").unwrap(),
+            vec![SOCKS5_ATYP_IPV4, 127, 0, 0, 1, 0x05, 0x39]
+        );
+
+        let mut expected = vec![SOCKS5_ATYP_DOMAIN, 11];
+        expected.extend_from_slice(b"example.com");
+        expected.extend_from_slice(&[0x01, 0xbb]);
+        assert_eq!(encode_address("example.com:443").unwrap(), expected);
+
+        let mut expected = vec![SOCKS5_ATYP_IPV6];
+        expected.extend_from_slice(&"::1".parse::<Ipv6Addr>().unwrap().octets());
+        expected.extend_from_slice(&[0x00, 0x50]);
+        assert_eq!(encode_address("[::1]:80").unwrap(), expected);
+
+        assert!(encode_address("example.com").is_err());
+        assert!(encode_address(":80").is_err());
+        assert!(encode_address("example.com:port").is_err());
+    }
+}
@@ -1,24 +1,38 @@
+use std::net::SocketAddr;
+
 use common::conn::{BoxFuture, ConnPairVec, FutTransform};
 
 use futures::task::Spawn;
 
-// use std::net::SocketAddr;
 use async_std::net::TcpStream;
 
 use proto::net::messages::NetAddress;
 
-use crate::utils::tcp_stream_to_conn_pair;
+use crate::socks5::socks5_connect;
+use crate::utils::stream_to_conn_pair;
 
 #[derive(Debug, Clone)]
 pub struct TcpConnector<S> {
     max_frame_length: usize,
+    opt_socks5_proxy: Option<SocketAddr>,
     spawner: S,
 }
 
 impl<S> TcpConnector<S> {
     pub fn new(max_frame_length: usize, spawner: S) -> Self {
+        Self::new_with_proxy(max_frame_length, None, spawner)
+    }
+
+    /// Create a connector that optionally routes all connections through a SOCKS5 proxy
+    /// (For example: Tor).
+    pub fn new_with_proxy(
+        max_frame_length: usize,
+        opt_socks5_proxy: Option<SocketAddr>,
+        spawner: S,
+    ) -> Self {
         TcpConnector {
             max_frame_length,
+            opt_socks5_proxy,
             spawner,
         }
     }
@@ -33,10 +47,24 @@ where
 
     fn transform(&mut self, net_address: Self::Input) -> BoxFuture<'_, Self::Output> {
         Box::pin(async move {
-            info!("TcpConnector: Connecting to {:?}", net_address.as_str());
-            let tcp_stream = TcpStream::connect(net_address.as_str()).await.ok()?;
+            let tcp_stream = if let Some(socks5_proxy) = self.opt_socks5_proxy {
+                info!(
+                    "TcpConnector: Connecting to {:?} through SOCKS5 proxy {:?}",
+                    net_address.as_str(),
+                    socks5_proxy
+                );
+                let mut tcp_stream = TcpStream::connect(socks5_proxy).await.ok()?;
+                if let Err(e) = socks5_connect(&mut tcp_stream, net_address.as_str()).await {
+                    warn!("TcpConnector: SOCKS5 connection failed: {:?}", e);
+                    return None;
+                }
+                tcp_stream
+            } else {
+                info!("TcpConnector: Connecting to {:?}", net_address.as_str());
+                TcpStream::connect(net_address.as_str()).await.ok()?
+            };
 
-            Some(tcp_stream_to_conn_pair(
+            Some(stream_to_conn_pair(
                 tcp_stream,
                 self.max_frame_length,
                 &mut self.spawner,
@@ -7,7 +7,7 @@ use futures::channel::mpsc;
 use futures::task::{Spawn, SpawnExt};
 use futures::{SinkExt, StreamExt};
 
-use crate::utils::tcp_stream_to_conn_pair;
+use crate::utils::stream_to_conn_pair;
 use common::conn::{ConnPairVec, FutListenerClient, Listener, ListenerClient};
 
 /// Listen for incoming TCP connections
@@ -63,7 +63,7 @@ where
                             tcp_stream.peer_addr(),
                         );
                         let conn_pair =
-                            tcp_stream_to_conn_pair(tcp_stream, c_max_frame_length, &mut c_spawner);
+                            stream_to_conn_pair(tcp_stream, c_max_frame_length, &mut c_spawner);
                         if let Err(e) = conn_receiver_sender.send(conn_pair).await {
                             warn!("TcpListener::listen(): Send error: {:?}", e);
                             return;
@@ -3,16 +3,24 @@ use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 
 use futures::channel::mpsc;
 use futures::executor::{block_on, ThreadPool};
-use futures::task::Spawn;
-use futures::{SinkExt, StreamExt};
+use futures::future;
+use futures::task::{Spawn, SpawnExt};
+use futures::{AsyncReadExt, AsyncWriteExt, SinkExt, StreamExt};
 
 use common::conn::{ConnPairVec, FutTransform, Listener, ListenerClient};
 use proto::net::messages::NetAddress;
 
 use crate::tcp_connector::TcpConnector;
 use crate::tcp_listener::TcpListener;
+#[cfg(unix)]
+use crate::unix_connector::UnixConnector;
+#[cfg(unix)]
+use crate::unix_listener::{bind_unix_socket, UnixListener};
+use crate::ws_connector::WsConnector;
+use crate::ws_listener::WsListener;
 
 use async_std::net::TcpListener as AsyncStdTcpListener;
+use async_std::net::TcpStream;
 
 /// Get an available port we can listen on
 async fn get_available_port_v4() -> u16 {
@@ -119,3 +127,240 @@ fn test_net_connector_v4_drop_sender() {
     let thread_pool = ThreadPool::new().unwrap();
     block_on(task_net_connector_v4_drop_sender(thread_pool.clone()));
 }
+
+#[cfg(unix)]
+async fn task_unix_client_server<S>(spawner: S)
+where
+    S: Spawn + Clone + Send + 'static,
+{
+    let socket_path =
+        std::env::temp_dir().join(format!("offset_net_test_{}.sock", std::process::id()));
+
+    // Simulate a socket file left behind by a crashed process:
+    drop(std::os::unix::net::UnixListener::bind(&socket_path).unwrap());
+    assert!(socket_path.exists());
+
+    let net_address = NetAddress::try_from(socket_path.to_str().unwrap().to_owned()).unwrap();
+
+    let unix_listener = UnixListener::new(TEST_MAX_FRAME_LEN, spawner.clone());
+    let mut unix_connector = UnixConnector::new(TEST_MAX_FRAME_LEN, spawner.clone());
+
+    let ListenerClient {
+        config_sender: _config_sender,
+        conn_receiver: mut incoming_connections,
+    } = unix_listener.listen(socket_path.clone()).await.unwrap();
+
+    for _ in 0..5usize {
+        let (mut client_sender, mut client_receiver) = unix_connector
+            .transform(net_address.clone())
+            .await
+            .unwrap()
+            .split();
+
+        let (mut server_sender, mut server_receiver) =
+            incoming_connections.next().await.unwrap().split();
+
+        client_sender.send(vec![1, 2, 3]).await.unwrap();
+        assert_eq!(server_receiver.next().await.unwrap(), vec![1, 2, 3]);
+
+        server_sender.send(vec![3, 2, 1]).await.unwrap();
+        assert_eq!(client_receiver.next().await.unwrap(), vec![3, 2, 1]);
+    }
+
+    // The socket is still in use, so we can not bind it again:
+    assert!(bind_unix_socket(&socket_path).await.is_err());
+
+    // Stop listening. The listener notices only on the next incoming connection:
+    drop(incoming_connections);
+    let _ = unix_connector.transform(net_address.clone()).await;
+    // The socket file is removed once the listener stops:
+    while socket_path.exists() {
+        async_std::task::sleep(std::time::Duration::from_millis(10)).await;
+    }
+}
+
+#[cfg(unix)]
+#[test]
+fn test_unix_client_server() {
+    let thread_pool = ThreadPool::new().unwrap();
+    block_on(task_unix_client_server(thread_pool.clone()));
+}
+
+#[cfg(unix)]
+async fn task_bind_unix_socket_guard() {
+    let socket_path =
+        std::env::temp_dir().join(format!("offset_net_test_guard_{}.sock", std::process::id()));
+
+    let (listener, socket_file_guard) = bind_unix_socket(&socket_path).await.unwrap();
+    assert!(socket_path.exists());
+
+    drop(listener);
+    drop(socket_file_guard);
+    assert!(!socket_path.exists());
+
+    // Files that are not sockets are never removed:
+    std::fs::write(&socket_path, b"not a socket").unwrap();
+    assert!(bind_unix_socket(&socket_path).await.is_err());
+    assert!(socket_path.exists());
+    std::fs::remove_file(&socket_path).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_bind_unix_socket_guard() {
+    block_on(task_bind_unix_socket_guard());
+}
+
+async fn task_ws_client_server_v4<S>(spawner: S)
+where
+    S: Spawn + Clone + Send + 'static,
+{
+    let available_port = get_available_port_v4().await;
+    let loopback = Ipv4Addr::new(127, 0, 0, 1);
+    let socket_addr = SocketAddr::new(IpAddr::V4(loopback), available_port);
+    let net_address = NetAddress::try_from(format!("127.0.0.1:{}", available_port)).unwrap();
+
+    let ws_listener = WsListener::new(TEST_MAX_FRAME_LEN, spawner.clone());
+    let mut ws_connector = WsConnector::new(TEST_MAX_FRAME_LEN, spawner.clone());
+
+    let ListenerClient {
+        config_sender: _config_sender,
+        conn_receiver: mut incoming_connections,
+    } = ws_listener.listen(socket_addr).await.unwrap();
+
+    for _ in 0..5usize {
+        let (mut client_sender, mut client_receiver) = ws_connector
+            .transform(net_address.clone())
+            .await
+            .unwrap()
+            .split();
+
+        let (mut server_sender, mut server_receiver) =
+            incoming_connections.next().await.unwrap().split();
+
+        client_sender.send(vec![1, 2, 3]).await.unwrap();
+        assert_eq!(server_receiver.next().await.unwrap(), vec![1, 2, 3]);
+
+        server_sender.send(vec![3, 2, 1]).await.unwrap();
+        assert_eq!(client_receiver.next().await.unwrap(), vec![3, 2, 1]);
+
+        // Drop the client's sender. The server should notice that the connection was closed:
+        drop(client_sender);
+        while let Some(_) = server_receiver.next().await {}
+    }
+}
+
+#[test]
+fn test_ws_client_server_v4() {
+    let thread_pool = ThreadPool::new().unwrap();
+    block_on(task_ws_client_server_v4(thread_pool.clone()));
+}
+
+/// A minimal SOCKS5 proxy stand-in. Serves one connection:
+/// Reports the requested destination address, and then relays traffic to it.
+async fn run_socks5_stand_in(
+    proxy_listener: AsyncStdTcpListener,
+    mut dest_sender: mpsc::Sender<String>,
+) -> Option<()> {
+    let (client, _) = proxy_listener.accept().await.ok()?;
+    let (mut client_reader, mut client_writer) = (client.clone(), client);
+
+    // Greeting: We expect a request to connect without authentication:
+    let mut greeting = [0u8; 3];
+    client_reader.read_exact(&mut greeting).await.ok()?;
+    assert_eq!(greeting, [0x05, 0x01, 0x00]);
+    client_writer.write_all(&[0x05, 0x00]).await.ok()?;
+
+    // Connect request, using a domain name:
+    let mut request_header = [0u8; 5];
+    client_reader.read_exact(&mut request_header).await.ok()?;
+    assert_eq!(request_header[..4], [0x05, 0x01, 0x00, 0x03]);
+    let mut domain = vec![0u8; usize::from(request_header[4])];
+    client_reader.read_exact(&mut domain).await.ok()?;
+    let mut port = [0u8; 2];
+    client_reader.read_exact(&mut port).await.ok()?;
+    let dest = format!(
+        "{}:{}",
+        String::from_utf8(domain).ok()?,
+        u16::from_be_bytes(port)
+    );
+    dest_sender.send(dest).await.ok()?;
+
+    // We resolve every domain name to the loopback address:
+    let target = TcpStream::connect(SocketAddr::new(
+        IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1)),
+        u16::from_be_bytes(port),
+    ))
+    .await
+    .ok()?;
+
+    // Reply with success (Bound address 0.0.0.0:0):
+    client_writer
+        .write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+        .await
+        .ok()?;
+
+    // Relay traffic in both directions:
+    let (mut target_reader, mut target_writer) = (target.clone(), target);
+    let _ = future::join(
+        futures::io::copy(&mut client_reader, &mut target_writer),
+        futures::io::copy(&mut target_reader, &mut client_writer),
+    )
+    .await;
+    Some(())
+}
+
+async fn task_tcp_connector_socks5<S>(spawner: S)
+where
+    S: Spawn + Clone + Send + 'static,
+{
+    // Destination server:
+    let loopback = Ipv4Addr::new(127, 0, 0, 1);
+    let available_port = get_available_port_v4().await;
+    let socket_addr = SocketAddr::new(IpAddr::V4(loopback), available_port);
+    let tcp_listener = TcpListener::new(TEST_MAX_FRAME_LEN, spawner.clone());
+    let ListenerClient {
+        config_sender: _config_sender,
+        conn_receiver: mut incoming_connections,
+    } = tcp_listener.listen(socket_addr).await.unwrap();
+
+    // SOCKS5 proxy stand-in:
+    let proxy_listener = AsyncStdTcpListener::bind(SocketAddr::new(IpAddr::V4(loopback), 0))
+        .await
+        .unwrap();
+    let proxy_addr = proxy_listener.local_addr().unwrap();
+    let (dest_sender, mut dest_receiver) = mpsc::channel(1);
+    spawner
+        .spawn(async move {
+            let _ = run_socks5_stand_in(proxy_listener, dest_sender).await;
+        })
+        .unwrap();
+
+    let mut tcp_connector =
+        TcpConnector::new_with_proxy(TEST_MAX_FRAME_LEN, Some(proxy_addr), spawner.clone());
+
+    // Use a domain name, to make sure that name resolution is left to the proxy:
+    let net_address = NetAddress::try_from(format!("localhost:{}", available_port)).unwrap();
+    let (mut client_sender, mut client_receiver) =
+        tcp_connector.transform(net_address).await.unwrap().split();
+
+    assert_eq!(
+        dest_receiver.next().await.unwrap(),
+        format!("localhost:{}", available_port)
+    );
+
+    let (mut server_sender, mut server_receiver) =
+        incoming_connections.next().await.unwrap().split();
+
+    client_sender.send(vec![1, 2, 3]).await.unwrap();
+    assert_eq!(server_receiver.next().await.unwrap(), vec![1, 2, 3]);
+
+    server_sender.send(vec![3, 2, 1]).await.unwrap();
+    assert_eq!(client_receiver.next().await.unwrap(), vec![3, 2, 1]);
+}
+
+#[test]
+fn test_tcp_connector_socks5() {
+    let thread_pool = ThreadPool::new().unwrap();
+    block_on(task_tcp_connector_socks5(thread_pool.clone()));
+}
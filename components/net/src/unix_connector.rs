@@ -0,0 +1,47 @@
+use common::conn::{BoxFuture, ConnPairVec, FutTransform};
+
+use futures::task::Spawn;
+
+use async_std::os::unix::net::UnixStream;
+
+use proto::net::messages::NetAddress;
+
+use crate::utils::stream_to_conn_pair;
+
+/// Connect to a Unix domain socket.
+/// The given NetAddress is interpreted as a path to the socket file.
+#[derive(Debug, Clone)]
+pub struct UnixConnector<S> {
+    max_frame_length: usize,
+    spawner: S,
+}
+
+impl<S> UnixConnector<S> {
+    pub fn new(max_frame_length: usize, spawner: S) -> Self {
+        UnixConnector {
+            max_frame_length,
+            spawner,
+        }
+    }
+}
+
+impl<S> FutTransform for UnixConnector<S>
+where
+    S: Spawn + Send,
+{
+    type Input = NetAddress;
+    type Output = Option<ConnPairVec>;
+
+    fn transform(&mut self, net_address: Self::Input) -> BoxFuture<'_, Self::Output> {
+        Box::pin(async move {
+            info!("UnixConnector: Connecting to {:?}", net_address.as_str());
+            let unix_stream = UnixStream::connect(net_address.as_str()).await.ok()?;
+
+            Some(stream_to_conn_pair(
+                unix_stream,
+                self.max_frame_length,
+                &mut self.spawner,
+            ))
+        })
+    }
+}
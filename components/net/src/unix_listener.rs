@@ -0,0 +1,121 @@
+use std::io;
+use std::os::unix::fs::FileTypeExt;
+use std::path::{Path, PathBuf};
+
+use async_std::os::unix::net::{
+    UnixListener as AsyncStdUnixListener, UnixStream as AsyncStdUnixStream,
+};
+
+use futures::channel::mpsc;
+use futures::task::{Spawn, SpawnExt};
+use futures::{SinkExt, StreamExt};
+
+use crate::utils::stream_to_conn_pair;
+use common::conn::{ConnPairVec, FutListenerClient, Listener, ListenerClient};
+
+/// Removes a Unix domain socket file when dropped.
+#[derive(Debug)]
+pub struct SocketFileGuard {
+    path: PathBuf,
+}
+
+impl Drop for SocketFileGuard {
+    fn drop(&mut self) {
+        if let Err(e) = std::fs::remove_file(&self.path) {
+            warn!(
+                "SocketFileGuard: Failed to remove socket file {:?}: {:?}",
+                self.path, e
+            );
+        }
+    }
+}
+
+/// Bind a Unix domain socket.
+///
+/// A socket file left behind by a previous process (For example, after a crash) is removed
+/// first. If another process is still listening on the socket, binding fails.
+/// The socket file is removed when the returned guard is dropped.
+pub async fn bind_unix_socket(path: &Path) -> io::Result<(AsyncStdUnixListener, SocketFileGuard)> {
+    let is_socket = std::fs::symlink_metadata(path)
+        .map(|metadata| metadata.file_type().is_socket())
+        .unwrap_or(false);
+    // We never remove files that are not sockets:
+    if is_socket && AsyncStdUnixStream::connect(path).await.is_err() {
+        info!("bind_unix_socket(): Removing stale socket file: {:?}", path);
+        std::fs::remove_file(path)?;
+    }
+    let listener = AsyncStdUnixListener::bind(path).await?;
+    let guard = SocketFileGuard {
+        path: path.to_owned(),
+    };
+    Ok((listener, guard))
+}
+
+/// Listen for incoming Unix domain socket connections
+pub struct UnixListener<S> {
+    max_frame_length: usize,
+    spawner: S,
+}
+
+impl<S> UnixListener<S> {
+    pub fn new(max_frame_length: usize, spawner: S) -> Self {
+        UnixListener {
+            max_frame_length,
+            spawner,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum UnixListenerError {
+    BindError(PathBuf, io::Error),
+    SpawnError,
+}
+
+impl<S> Listener for UnixListener<S>
+where
+    S: Spawn + Send + Clone + 'static,
+{
+    type Connection = ConnPairVec;
+    type Config = ();
+    type Error = UnixListenerError;
+    type Arg = PathBuf;
+
+    fn listen(
+        self,
+        path: Self::Arg,
+    ) -> FutListenerClient<Self::Config, Self::Connection, Self::Error> {
+        let (config_sender, _config_sender_receiver) = mpsc::channel(0);
+        let (mut conn_receiver_sender, conn_receiver) = mpsc::channel(0);
+
+        let mut c_spawner = self.spawner.clone();
+        let c_max_frame_length = self.max_frame_length;
+        Box::pin(async move {
+            let (listener, socket_file_guard) = bind_unix_socket(&path)
+                .await
+                .map_err(|error| UnixListenerError::BindError(path.clone(), error))?;
+
+            self.spawner
+                .spawn(async move {
+                    // Remove the socket file when we stop listening:
+                    let _socket_file_guard = socket_file_guard;
+                    let mut incoming_conns = listener.incoming();
+                    while let Some(Ok(unix_stream)) = incoming_conns.next().await {
+                        info!("UnixListener: Incoming connection on: {:?}", path);
+                        let conn_pair =
+                            stream_to_conn_pair(unix_stream, c_max_frame_length, &mut c_spawner);
+                        if let Err(e) = conn_receiver_sender.send(conn_pair).await {
+                            warn!("UnixListener::listen(): Send error: {:?}", e);
+                            return;
+                        }
+                    }
+                })
+                .map_err(|_| UnixListenerError::SpawnError)?;
+
+            Ok(ListenerClient {
+                config_sender,
+                conn_receiver,
+            })
+        })
+    }
+}
@@ -2,31 +2,69 @@ use bytes::Bytes;
 
 use futures::channel::mpsc;
 use futures::task::{Spawn, SpawnExt};
-use futures::{future, SinkExt, StreamExt};
+use futures::{future, AsyncRead, AsyncWrite, Sink, SinkExt, Stream, StreamExt};
 use futures_codec::{Framed, LengthCodec};
 
-use async_std::net::TcpStream;
+use async_tungstenite::tungstenite::Message;
+use async_tungstenite::WebSocketStream;
 
 use common::conn::ConnPairVec;
 
+/// Turn a sink and a stream of frames into a connection.
+/// Shared by all transports, after they split their data into frames.
+fn sink_stream_to_conn_pair<K, M, S>(
+    mut vec_sender: K,
+    vec_receiver: M,
+    spawner: &mut S,
+) -> ConnPairVec
+where
+    K: Sink<Vec<u8>, Error = ()> + Unpin + Send + 'static,
+    M: Stream<Item = Vec<u8>> + Unpin + Send + 'static,
+    S: Spawn + Send,
+{
+    // Add a mechanism to make sure that the connection is dropped when we drop the sender.
+    // Not fully sure why this doesn't happen automatically.
+    let (user_sender, user_sender_receiver) = mpsc::channel(0);
+    let (mut user_receiver_sender, user_receiver) = mpsc::channel(0);
+
+    let receiver_task = spawner
+        .spawn_with_handle(async move {
+            let _ = user_receiver_sender
+                .send_all(&mut vec_receiver.map(Ok))
+                .await;
+        })
+        .unwrap();
+
+    spawner
+        .spawn(async move {
+            let _ = vec_sender.send_all(&mut user_sender_receiver.map(Ok)).await;
+            drop(receiver_task);
+        })
+        .unwrap();
+
+    ConnPairVec::from_raw(user_sender, user_receiver)
+}
+
 // TODO: Maybe all the logic here of ensuring closing is not required after this fix in async-std:
 // https://github.com/async-rs/async-std/issues/599
 // Check if we can simplify logic here.
-pub fn tcp_stream_to_conn_pair<S>(
-    tcp_stream: TcpStream,
+/// Split a byte stream (TCP, Unix domain socket) into length prefixed frames.
+pub fn stream_to_conn_pair<T, S>(
+    stream: T,
     _max_frame_length: usize,
     spawner: &mut S,
 ) -> ConnPairVec
 where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
     S: Spawn + Send,
 {
     // TODO: Return support for max_frame_length
     let codec = LengthCodec;
     // codec.set_max_frame_length(max_frame_length);
-    let (sender, receiver) = Framed::new(tcp_stream, codec).split();
+    let (sender, receiver) = Framed::new(stream, codec).split();
 
     // Conversion layer between Vec<u8> to Bytes:
-    let mut vec_sender =
+    let vec_sender =
         sender
             .sink_map_err(|_| ())
             .with(|vec: Vec<u8>| -> future::Ready<Result<Bytes, ()>> {
@@ -37,25 +75,38 @@ where
         .take_while(|res| future::ready(res.is_ok()))
         .map(|res| res.unwrap().to_vec());
 
-    // Add a mechanism to make sure that the connection is dropped when we drop the sender.
-    // Not fully sure why this doesn't happen automatically.
-    let (user_sender, user_sender_receiver) = mpsc::channel(0);
-    let (mut user_receiver_sender, user_receiver) = mpsc::channel(0);
+    sink_stream_to_conn_pair(vec_sender, vec_receiver, spawner)
+}
 
-    let receiver_task = spawner
-        .spawn_with_handle(async move {
-            let _ = user_receiver_sender
-                .send_all(&mut vec_receiver.map(Ok))
-                .await;
-        })
-        .unwrap();
+/// Convert a WebSocket stream into a connection of frames.
+/// Every binary WebSocket message carries exactly one frame.
+pub fn ws_stream_to_conn_pair<T, S>(ws_stream: WebSocketStream<T>, spawner: &mut S) -> ConnPairVec
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    S: Spawn + Send,
+{
+    let (sender, receiver) = ws_stream.split();
 
-    spawner
-        .spawn(async move {
-            let _ = vec_sender.send_all(&mut user_sender_receiver.map(Ok)).await;
-            drop(receiver_task);
-        })
-        .unwrap();
+    // Conversion layer between Vec<u8> to binary WebSocket messages:
+    let vec_sender =
+        sender
+            .sink_map_err(|_| ())
+            .with(|vec: Vec<u8>| -> future::Ready<Result<Message, ()>> {
+                future::ready(Ok(Message::Binary(vec)))
+            });
 
-    ConnPairVec::from_raw(user_sender, user_receiver)
+    // Control messages (Ping, Pong) are handled internally by the WebSocket implementation.
+    // We stop at the first error or Close message, and ignore Text messages.
+    let vec_receiver = receiver
+        .take_while(|res| future::ready(res.is_ok()))
+        .map(|res| res.unwrap())
+        .take_while(|message| future::ready(!message.is_close()))
+        .filter_map(|message| {
+            future::ready(match message {
+                Message::Binary(data) => Some(data),
+                _ => None,
+            })
+        });
+
+    sink_stream_to_conn_pair(vec_sender, vec_receiver, spawner)
 }
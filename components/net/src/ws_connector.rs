@@ -0,0 +1,55 @@
+use common::conn::{BoxFuture, ConnPairVec, FutTransform};
+
+use futures::task::Spawn;
+
+use async_std::net::TcpStream;
+use async_tungstenite::client_async_with_config;
+use async_tungstenite::tungstenite::protocol::WebSocketConfig;
+
+use proto::net::messages::NetAddress;
+
+use crate::utils::ws_stream_to_conn_pair;
+
+/// Connect to a remote WebSocket listener (See `WsListener`).
+#[derive(Debug, Clone)]
+pub struct WsConnector<S> {
+    max_frame_length: usize,
+    spawner: S,
+}
+
+impl<S> WsConnector<S> {
+    pub fn new(max_frame_length: usize, spawner: S) -> Self {
+        WsConnector {
+            max_frame_length,
+            spawner,
+        }
+    }
+}
+
+impl<S> FutTransform for WsConnector<S>
+where
+    S: Spawn + Send,
+{
+    type Input = NetAddress;
+    type Output = Option<ConnPairVec>;
+
+    fn transform(&mut self, net_address: Self::Input) -> BoxFuture<'_, Self::Output> {
+        Box::pin(async move {
+            info!("WsConnector: Connecting to {:?}", net_address.as_str());
+            let tcp_stream = TcpStream::connect(net_address.as_str()).await.ok()?;
+
+            let ws_config = WebSocketConfig {
+                max_message_size: Some(self.max_frame_length),
+                max_frame_size: Some(self.max_frame_length),
+                ..WebSocketConfig::default()
+            };
+            let url = format!("ws://{}/", net_address.as_str());
+            let (ws_stream, _response) =
+                client_async_with_config(url.as_str(), tcp_stream, Some(ws_config))
+                    .await
+                    .ok()?;
+
+            Some(ws_stream_to_conn_pair(ws_stream, &mut self.spawner))
+        })
+    }
+}
@@ -0,0 +1,106 @@
+use std::io;
+use std::net::SocketAddr;
+
+use async_std::net::TcpListener as AsyncStdTcpListener;
+use async_tungstenite::accept_async_with_config;
+use async_tungstenite::tungstenite::protocol::WebSocketConfig;
+
+use futures::channel::mpsc;
+use futures::task::{Spawn, SpawnExt};
+use futures::{SinkExt, StreamExt};
+
+use crate::utils::ws_stream_to_conn_pair;
+use common::conn::{ConnPairVec, FutListenerClient, Listener, ListenerClient};
+
+/// Listen for incoming WebSocket connections.
+/// Every binary WebSocket message carries one frame, similar to the frames produced by
+/// `TcpListener`. This allows clients that can not open raw TCP connections (Web browsers) to
+/// speak the same protocol.
+pub struct WsListener<S> {
+    max_frame_length: usize,
+    spawner: S,
+}
+
+impl<S> WsListener<S> {
+    pub fn new(max_frame_length: usize, spawner: S) -> Self {
+        WsListener {
+            max_frame_length,
+            spawner,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum WsListenerError {
+    BindError(SocketAddr, io::Error),
+    SpawnError,
+}
+
+impl<S> Listener for WsListener<S>
+where
+    S: Spawn + Send + Clone + 'static,
+{
+    type Connection = ConnPairVec;
+    type Config = ();
+    type Error = WsListenerError;
+    type Arg = SocketAddr;
+
+    fn listen(
+        self,
+        socket_addr: Self::Arg,
+    ) -> FutListenerClient<Self::Config, Self::Connection, Self::Error> {
+        let (config_sender, _config_sender_receiver) = mpsc::channel(0);
+        let (conn_receiver_sender, conn_receiver) = mpsc::channel(0);
+
+        let c_spawner = self.spawner.clone();
+        let ws_config = WebSocketConfig {
+            max_message_size: Some(self.max_frame_length),
+            max_frame_size: Some(self.max_frame_length),
+            ..WebSocketConfig::default()
+        };
+        Box::pin(async move {
+            let listener = AsyncStdTcpListener::bind(&socket_addr)
+                .await
+                .map_err(|error| WsListenerError::BindError(socket_addr, error))?;
+
+            self.spawner
+                .spawn(async move {
+                    let mut incoming_conns = listener.incoming();
+                    while let Some(Ok(tcp_stream)) = incoming_conns.next().await {
+                        info!(
+                            "WsListener: Incoming connection from: {:?}",
+                            tcp_stream.peer_addr(),
+                        );
+                        // Perform the WebSocket handshake separately for every connection, so
+                        // that a slow client will not block other incoming connections:
+                        let mut c_conn_receiver_sender = conn_receiver_sender.clone();
+                        let mut cc_spawner = c_spawner.clone();
+                        let handshake_fut = async move {
+                            let ws_stream =
+                                match accept_async_with_config(tcp_stream, Some(ws_config)).await {
+                                    Ok(ws_stream) => ws_stream,
+                                    Err(e) => {
+                                        warn!("WsListener: Handshake error: {:?}", e);
+                                        return;
+                                    }
+                                };
+                            let conn_pair = ws_stream_to_conn_pair(ws_stream, &mut cc_spawner);
+                            if let Err(e) = c_conn_receiver_sender.send(conn_pair).await {
+                                warn!("WsListener::listen(): Send error: {:?}", e);
+                            }
+                        };
+                        if let Err(e) = c_spawner.spawn(handshake_fut) {
+                            error!("WsListener::listen(): Spawn error: {:?}", e);
+                            return;
+                        }
+                    }
+                })
+                .map_err(|_| WsListenerError::SpawnError)?;
+
+            Ok(ListenerClient {
+                config_sender,
+                conn_receiver,
+            })
+        })
+    }
+}
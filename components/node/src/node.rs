@@ -35,12 +35,14 @@ use proto::index_client::messages::{AppServerToIndexClient, IndexClientToAppServ
 use proto::index_server::messages::IndexServerAddress;
 use proto::net::messages::NetAddress;
 use proto::report::convert::funder_report_to_index_client_state;
+use proto::report::messages::RelayHealthReport;
 
 use crate::types::{create_node_report, NodeConfig, NodeMutation, NodeState};
 
 #[derive(Debug, From)]
 pub enum NodeError {
     RequestPublicKeyError,
+    RequestTimerStreamError,
     DatabaseIdentityMismatch,
     SpawnError,
     ChannelerError(ChannelerError),
@@ -49,14 +51,16 @@ pub enum NodeError {
     AppServerError(AppServerError),
 }
 
-fn node_spawn_channeler<C, EKT, S>(
+fn node_spawn_channeler<C, DC, EKT, ID, S>(
     node_config: &NodeConfig,
     local_public_key: PublicKey,
     timer_client: TimerClient,
     connector: C,
+    direct_connector: DC,
     encrypt_keepalive: EKT,
+    incoming_direct_conns: ID,
     from_funder: mpsc::Receiver<FunderToChanneler<RelayAddress>>,
-    to_funder: mpsc::Sender<ChannelerToFunder>,
+    to_funder: mpsc::Sender<ChannelerToFunder<RelayAddress>>,
     spawner: S,
 ) -> Result<impl Future<Output = Result<(), ChannelerError>>, NodeError>
 where
@@ -64,12 +68,14 @@ where
         + Clone
         + Send
         + 'static,
+    DC: FutTransform<Input = NetAddress, Output = Option<ConnPairVec>> + Clone + Send + 'static,
     EKT: FutTransform<
             Input = (Option<PublicKey>, ConnPairVec),
             Output = Option<(PublicKey, ConnPairVec)>,
         > + Clone
         + Send
         + 'static,
+    ID: Stream<Item = ConnPairVec> + Unpin + Send + 'static,
     S: Spawn + Clone + Send + 'static,
 {
     let enc_relay_connector = FuncFutTransform::new(move |relay_address: RelayAddress| {
@@ -81,6 +87,12 @@ where
         })
     });
 
+    // A direct address of a friend is represented as a RelayAddress with the friend's public key.
+    let direct_friend_connector = FuncFutTransform::new(move |relay_address: RelayAddress| {
+        let mut c_direct_connector = direct_connector.clone();
+        Box::pin(async move { c_direct_connector.transform(relay_address.address).await })
+    });
+
     spawner
         .spawn_with_handle(channeler_loop(
             local_public_key,
@@ -89,7 +101,9 @@ where
             node_config.conn_timeout_ticks,
             node_config.max_concurrent_encrypt,
             enc_relay_connector,
+            direct_friend_connector,
             encrypt_keepalive,
+            incoming_direct_conns,
             from_funder,
             to_funder,
             spawner.clone(),
@@ -97,12 +111,13 @@ where
         .map_err(|_| NodeError::SpawnError)
 }
 
-fn node_spawn_funder<R, S>(
+async fn node_spawn_funder<R, S>(
     node_config: &NodeConfig,
     identity_client: IdentityClient,
+    mut timer_client: TimerClient,
     funder_state: FunderState<NetAddress>,
     mut database_client: DatabaseClient<NodeMutation<NetAddress>>,
-    mut from_channeler: mpsc::Receiver<ChannelerToFunder>,
+    mut from_channeler: mpsc::Receiver<ChannelerToFunder<RelayAddress>>,
     mut to_channeler: mpsc::Sender<FunderToChanneler<RelayAddress>>,
     from_app_server: mpsc::Receiver<FunderIncomingControl<NetAddress>>,
     to_app_server: mpsc::Sender<FunderOutgoingControl<NetAddress>>,
@@ -150,6 +165,21 @@ where
                 ChannelerToFunder::Offline(public_key) => Some(FunderIncomingComm::Liveness(
                     IncomingLivenessMessage::Offline(public_key),
                 )),
+                ChannelerToFunder::RelaysHealth((public_key, relays_health)) => {
+                    let relays_health = relays_health
+                        .into_iter()
+                        .map(|relay_health| RelayHealthReport {
+                            relay_public_key: relay_health.relay_address.public_key,
+                            success_count: relay_health.success_count,
+                            failure_count: relay_health.failure_count,
+                            consecutive_failures: relay_health.consecutive_failures,
+                            latency_ticks: relay_health.latency_ticks,
+                        })
+                        .collect();
+                    Some(FunderIncomingComm::Liveness(
+                        IncomingLivenessMessage::RelaysHealth((public_key, relays_health)),
+                    ))
+                }
                 ChannelerToFunder::Message((public_key, data)) => {
                     if let Ok(friend_message) = FriendMessage::proto_deserialize(&data[..]) {
                         Some(FunderIncomingComm::Friend((public_key, friend_message)))
@@ -204,16 +234,24 @@ where
         .spawn(funder_to_channeler_adapter)
         .map_err(|_| NodeError::SpawnError)?;
 
+    // Used for expiring held invoices:
+    let timer_stream = timer_client
+        .request_timer_stream("funder".to_owned())
+        .await
+        .map_err(|_| NodeError::RequestTimerStreamError)?;
+
     let funder_fut = funder_loop(
         identity_client,
         rng,
         from_app_server,
         incoming_comm,
+        timer_stream,
         to_app_server,
         outgoing_comm_sender,
         node_config.max_node_relays,
         node_config.max_operations_in_batch,
         node_config.max_pending_user_requests,
+        node_config.direct_addresses.clone(),
         funder_state,
         funder_db_client,
     );
@@ -331,15 +369,20 @@ where
 }
 
 // TODO: Possibly rename this function?
-pub async fn node<C, EKT, IA, R, S>(
+pub async fn node<C, DC, EKT, ID, IA, R, S>(
     node_config: NodeConfig,
     identity_client: IdentityClient,
     timer_client: TimerClient,
     node_state: NodeState<NetAddress>,
     database_client: DatabaseClient<NodeMutation<NetAddress>>,
     connector: C,
-    // encrypt_keepalive is used for encryption of the relayed communication between two nodes.
+    // Raw (unencrypted) connector, used for connecting directly to friends.
+    direct_connector: DC,
+    // encrypt_keepalive is used for encryption of the communication between two nodes
+    // (Relayed or direct).
     encrypt_keepalive: EKT,
+    // Raw (unencrypted) connections made directly to our direct addresses by remote nodes.
+    incoming_direct_conns: ID,
     incoming_apps: IA,
     rng: R,
     spawner: S,
@@ -349,12 +392,14 @@ where
         + Clone
         + Send
         + 'static,
+    DC: FutTransform<Input = NetAddress, Output = Option<ConnPairVec>> + Clone + Send + 'static,
     EKT: FutTransform<
             Input = (Option<PublicKey>, ConnPairVec),
             Output = Option<(PublicKey, ConnPairVec)>,
         > + Clone
         + Send
         + 'static,
+    ID: Stream<Item = ConnPairVec> + Unpin + Send + 'static,
     IA: Stream<Item = IncomingAppConnection<NetAddress>> + Unpin + Send + 'static,
     R: CryptoRandom + Clone + Send + 'static,
     S: Spawn + Clone + Send + 'static,
@@ -384,7 +429,9 @@ where
         local_public_key.clone(),
         timer_client.clone(),
         connector.clone(),
+        direct_connector,
         encrypt_keepalive,
+        incoming_direct_conns,
         funder_to_channeler_receiver,
         channeler_to_funder_sender,
         spawner.clone(),
@@ -399,6 +446,7 @@ where
     let funder_handle = node_spawn_funder(
         &node_config,
         identity_client.clone(),
+        timer_client.clone(),
         node_state.funder_state.clone(),
         database_client.clone(),
         channeler_to_funder_receiver,
@@ -407,7 +455,8 @@ where
         funder_to_app_server_sender,
         rng.clone(),
         spawner.clone(),
-    )?;
+    )
+    .await?;
 
     // AppServer <--> IndexClient
     let (app_server_to_index_client_sender, app_server_to_index_client_receiver) =
@@ -7,6 +7,7 @@ use index_client::{IndexClientConfig, IndexClientConfigMutation};
 use proto::app_server::messages::NodeReport;
 use proto::crypto::PublicKey;
 use proto::index_client::messages::IndexClientReport;
+use proto::net::messages::NetAddress;
 
 use signature::canonical::CanonicalSerialize;
 
@@ -107,6 +108,9 @@ pub struct NodeConfig {
     pub max_open_index_client_requests: usize,
     /// Maximum amount of relays a node may use.
     pub max_node_relays: usize,
+    /// Addresses where we accept direct connections from friends.
+    /// Advertised to our friends, who may use them instead of our relays.
+    pub direct_addresses: Vec<NetAddress>,
     /*
     /// Maximum amount of encryption set ups we allow to occur at the same time
     /// for incoming app connections
@@ -1,3 +1,4 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use capnp_conv::{capnp_conv, CapnpConvError, ReadCapnp, WriteCapnp};
@@ -8,9 +9,11 @@ use common::ser_utils::ser_b64;
 use crate::crypto::{InvoiceId, PaymentId, PublicKey, Uid};
 
 use crate::funder::messages::{
-    AckClosePayment, AddFriend, AddInvoice, Commit, CreatePayment, CreateTransaction, Currency,
-    RemoveFriendCurrency, ResetFriendChannel, ResponseClosePayment, SetFriendCurrencyMaxDebt,
-    SetFriendCurrencyRate, SetFriendName, SetFriendRelays, TransactionResult,
+    AckClosePayment, AddFriend, AddInvoice, CancelFriendSwap, Commit, CreatePayment,
+    CreateTransaction, Currency, EnablePushPayments, FriendSwap, HoldInvoice, RemoveExchangeRate,
+    RemoveFriendCurrency, ResetFriendChannel, ResponseClosePayment, SetExchangeRate,
+    SetFriendCurrencyMaxDebt, SetFriendCurrencyRate, SetFriendName, SetFriendRelays,
+    TransactionResult,
 };
 use crate::index_client::messages::{
     ClientResponseRoutes, IndexClientReport, IndexClientReportMutation,
@@ -22,20 +25,22 @@ use crate::report::messages::{FunderReport, FunderReportMutation};
 // TODO: Move NamedRelayAddress and RelayAddress to another place in offset-proto?
 
 #[capnp_conv(crate::common_capnp::named_relay_address)]
-#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct NamedRelayAddress<B = NetAddress> {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub public_key: PublicKey,
     pub address: B,
     pub name: String,
 }
 
 #[capnp_conv(crate::common_capnp::relay_address)]
-#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct RelayAddress<B = NetAddress> {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub public_key: PublicKey,
     pub address: B,
 }
@@ -98,7 +103,7 @@ pub struct ReportMutations<B = NetAddress> {
 
 #[allow(clippy::large_enum_variant)]
 #[capnp_conv(crate::app_server_capnp::app_server_to_app)]
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AppServerToApp<B = NetAddress> {
     /// Funds:
     TransactionResult(TransactionResult),
@@ -158,11 +163,27 @@ pub enum AppRequest<B = NetAddress> {
     AddInvoice(AddInvoice),
     CancelInvoice(InvoiceId),
     CommitInvoice(Commit),
+    HoldInvoice(HoldInvoice),
     /// Request routes from one node to another:
     RequestRoutes(RequestRoutes),
     /// Manage index servers:
     AddIndexServer(NamedIndexServerAddress<B>),
     RemoveIndexServer(PublicKey),
+    /// Push payments (Payments without a prior invoice):
+    CreatePushPayment(CreatePayment),
+    EnablePushPayments(EnablePushPayments),
+    DisablePushPayments(Currency),
+    /// Currency exchange (Forwarding requests from one currency to another):
+    SetExchangeRate(SetExchangeRate),
+    RemoveExchangeRate(RemoveExchangeRate),
+    /// Currency swaps with friends:
+    ProposeSwap(FriendSwap),
+    AcceptSwap(FriendSwap),
+    CancelSwap(CancelFriendSwap),
+    /// Cooperatively close the channel with a friend, ending with a signed final settlement:
+    CloseFriend(PublicKey),
+    /// Abort an ongoing cooperative close, returning the channel with the friend to normal operation:
+    AbortCloseFriend(PublicKey),
 }
 #[capnp_conv(crate::app_server_capnp::app_to_app_server)]
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -219,7 +240,7 @@ where
 }
 
 #[capnp_conv(crate::app_server_capnp::app_permissions)]
-#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct AppPermissions {
     /// Can request routes
@@ -1,6 +1,11 @@
-/// The current protocol version
+/// The current protocol version.
+/// This is the highest protocol version we support.
 pub const PROTOCOL_VERSION: u32 = 0;
 
+/// The oldest protocol version we are still willing to speak.
+/// When connecting, both sides agree on the highest version they have in common.
+pub const MIN_PROTOCOL_VERSION: u32 = 0;
+
 /// Maximum amount of friend operations sent in one move token message.
 pub const MAX_OPERATIONS_IN_BATCH: usize = 16;
 
@@ -32,6 +37,24 @@ pub const MAX_FRAME_LENGTH: usize = 1 << 20; // 1[MB]
 /// index server database.
 pub const INDEX_NODE_TIMEOUT_TICKS: usize = 60 * (1000 / TICK_MS); // 1 minute
 
+/// Index server: Maximum amount of nodes a routes request may exclude.
+pub const MAX_EXCLUDE_NODES: usize = 0x40;
+
+/// Index server: Maximum amount of edges a routes request may exclude.
+pub const MAX_EXCLUDE_EDGES: usize = 0x40;
+
+/// Funder: The amount of ticks a push payment may take to be fully paid. A push payment that
+/// was not fully paid in time is canceled.
+pub const PUSH_INVOICE_TIMEOUT_TICKS: u64 = 5 * 60 * (1000 / TICK_MS as u64); // 5 minutes
+
+/// Funder: Maximum amount of swaps a friend may have proposed to us that are still pending.
+/// Further proposals are canceled automatically.
+pub const MAX_PENDING_REMOTE_SWAPS: usize = 0x40;
+
+/// Funder: The amount of ticks a swap proposed by a friend remains pending before it is
+/// canceled automatically.
+pub const REMOTE_SWAP_TIMEOUT_TICKS: u64 = 60 * 60 * (1000 / TICK_MS as u64); // 1 hour
+
 /// Maximum length for an address string used in NetAddress
 pub const MAX_NET_ADDRESS_LENGTH: usize = 256;
 
@@ -7,6 +7,7 @@ use mutual_from::mutual_from;
 use common::ser_utils::{ser_b64, ser_string};
 
 use crate::app_server::messages::{AppPermissions, RelayAddress};
+use crate::funder::messages::{Currency, Rate};
 use crate::net::messages::NetAddress;
 
 /// A helper structure for serialize and deserializing IndexServerAddress.
@@ -63,6 +64,24 @@ pub struct IndexServerFile {
     pub address: NetAddress,
 }
 
+/// An index server administrator.
+/// Index servers only answer admin queries from public keys listed in such files.
+#[derive(Arbitrary, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexAdminFile {
+    #[serde(with = "ser_b64")]
+    pub public_key: PublicKey,
+}
+
+/// A node that may listen on a private relay.
+/// Private relays only allow listening to public keys listed in such files.
+#[derive(Arbitrary, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RelayListenerFile {
+    #[serde(with = "ser_b64")]
+    pub public_key: PublicKey,
+}
+
 /// A helper structure for serialize and deserializing NodeAddress.
 #[derive(Arbitrary, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
@@ -82,3 +101,26 @@ pub struct NodeEntryFile {
     #[serde(with = "ser_b64")]
     pub app_private_key: PrivateKey,
 }
+
+/// A directed edge of an index server's capacity graph.
+#[derive(Arbitrary, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexGraphEdgeFile {
+    pub currency: Currency,
+    #[serde(with = "ser_b64")]
+    pub from_public_key: PublicKey,
+    #[serde(with = "ser_b64")]
+    pub to_public_key: PublicKey,
+    #[serde(with = "ser_string")]
+    pub recv_capacity: u128,
+    pub rate: Rate,
+    #[serde(with = "ser_string")]
+    pub age: u128,
+}
+
+/// A snapshot of an index server's capacity graphs. Used for warm restarts.
+#[derive(Arbitrary, Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexGraphSnapshotFile {
+    pub edges: Vec<IndexGraphEdgeFile>,
+}
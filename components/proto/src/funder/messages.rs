@@ -8,6 +8,10 @@ use std::str::FromStr;
 use serde::de::{self, Visitor};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{Schema, SchemaObject};
+use schemars::JsonSchema;
+
 use derive_more::Display;
 
 use num_bigint::BigUint;
@@ -33,6 +37,9 @@ pub struct ChannelerUpdateFriend<RA> {
     pub friend_public_key: PublicKey,
     /// We should try to connect to this address:
     pub friend_relays: Vec<RA>,
+    /// We should try to connect directly to these addresses before using relays.
+    /// The public key of every address is the public key of the friend:
+    pub friend_direct_addresses: Vec<RA>,
     /// We should be listening on this address:
     pub local_relays: Vec<RA>,
 }
@@ -49,14 +56,30 @@ pub enum FunderToChanneler<RA> {
     RemoveFriend(PublicKey), // friend_public_key
 }
 
+/// Health of a relay we use to connect to a friend, as observed by the Channeler.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RelayHealth<RA> {
+    pub relay_address: RA,
+    /// Amount of successful connection attempts through this relay
+    pub success_count: u64,
+    /// Amount of failed connection attempts through this relay
+    pub failure_count: u64,
+    /// Amount of failed connection attempts since the last successful one
+    pub consecutive_failures: u64,
+    /// Time ticks it took for the last successful connection attempt
+    pub latency_ticks: u64,
+}
+
 #[derive(Debug)]
-pub enum ChannelerToFunder {
+pub enum ChannelerToFunder<RA> {
     /// A friend is now online
     Online(PublicKey),
     /// A friend is now offline
     Offline(PublicKey),
     /// Incoming message from a remote friend
     Message((PublicKey, Vec<u8>)), // (friend_public_key, message)
+    /// Updated health of the relays used to connect to a friend
+    RelaysHealth((PublicKey, Vec<RelayHealth<RA>>)), // (friend_public_key, relays_health)
 }
 
 // -------------------------------------------
@@ -93,6 +116,22 @@ pub struct RequestSendFundsOp {
     #[capnp_conv(with = Wrapper<u128>)]
     #[serde(with = "ser_string")]
     pub left_fees: u128,
+    /// Currency exchanges left to be performed along the route (In order).
+    /// Empty if the request is sent in the same currency all the way to the destination.
+    pub exchanges: Vec<CurrencyExchange>,
+}
+
+/// A change of currency along a route:
+/// The node `public_key` receives the request in `src_currency`, and forwards it in
+/// `dest_currency`, according to `rate`.
+#[capnp_conv(crate::funder_capnp::currency_exchange)]
+#[derive(Arbitrary, Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct CurrencyExchange {
+    #[serde(with = "ser_b64")]
+    pub public_key: PublicKey,
+    pub src_currency: Currency,
+    pub dest_currency: Currency,
+    pub rate: ExchangeRate,
 }
 
 #[capnp_conv(crate::funder_capnp::response_send_funds_op)]
@@ -162,6 +201,37 @@ pub struct CollectSendFundsOp {
     pub dest_plain_lock: PlainLock,
 }
 
+/// A proposal to atomically swap balance in two currencies of the same token channel.
+/// The sender gives `src_amount` in the currency of the operations list, and receives
+/// `dest_amount` of `dest_currency` in return.
+#[capnp_conv(crate::funder_capnp::request_swap_op)]
+#[derive(Arbitrary, Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct RequestSwapOp {
+    #[serde(with = "ser_b64")]
+    pub swap_id: Uid,
+    #[capnp_conv(with = Wrapper<u128>)]
+    #[serde(with = "ser_string")]
+    pub src_amount: u128,
+    pub dest_currency: Currency,
+    #[capnp_conv(with = Wrapper<u128>)]
+    #[serde(with = "ser_string")]
+    pub dest_amount: u128,
+}
+
+#[capnp_conv(crate::funder_capnp::accept_swap_op)]
+#[derive(Arbitrary, Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct AcceptSwapOp {
+    #[serde(with = "ser_b64")]
+    pub swap_id: Uid,
+}
+
+#[capnp_conv(crate::funder_capnp::cancel_swap_op)]
+#[derive(Arbitrary, Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct CancelSwapOp {
+    #[serde(with = "ser_b64")]
+    pub swap_id: Uid,
+}
+
 #[capnp_conv(crate::funder_capnp::friend_tc_op)]
 #[derive(Arbitrary, Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub enum FriendTcOp {
@@ -169,6 +239,9 @@ pub enum FriendTcOp {
     ResponseSendFunds(ResponseSendFundsOp),
     CancelSendFunds(CancelSendFundsOp),
     CollectSendFunds(CollectSendFundsOp),
+    RequestSwap(RequestSwapOp),
+    AcceptSwap(AcceptSwapOp),
+    CancelSwap(CancelSwapOp),
 }
 
 #[capnp_conv(crate::funder_capnp::move_token::opt_local_relays)]
@@ -178,6 +251,13 @@ pub enum OptLocalRelays<B = NetAddress> {
     Relays(Vec<RelayAddress<B>>),
 }
 
+#[capnp_conv(crate::funder_capnp::move_token::opt_direct_addresses)]
+#[derive(Arbitrary, Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub enum OptDirectAddresses<B = NetAddress> {
+    Empty,
+    Addresses(Vec<B>),
+}
+
 #[capnp_conv(crate::funder_capnp::move_token::opt_active_currencies)]
 #[derive(Arbitrary, Eq, PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub enum OptActiveCurrencies {
@@ -216,6 +296,24 @@ impl From<OptLocalRelays<NetAddress>> for Option<Vec<RelayAddress<NetAddress>>>
     }
 }
 
+impl<B> From<Option<Vec<B>>> for OptDirectAddresses<B> {
+    fn from(opt: Option<Vec<B>>) -> Self {
+        match opt {
+            Some(addresses) => OptDirectAddresses::Addresses(addresses),
+            None => OptDirectAddresses::Empty,
+        }
+    }
+}
+
+impl From<OptDirectAddresses<NetAddress>> for Option<Vec<NetAddress>> {
+    fn from(opt: OptDirectAddresses<NetAddress>) -> Self {
+        match opt {
+            OptDirectAddresses::Addresses(addresses) => Some(addresses),
+            OptDirectAddresses::Empty => None,
+        }
+    }
+}
+
 impl From<Option<Vec<Currency>>> for OptActiveCurrencies {
     fn from(opt: Option<Vec<Currency>>) -> Self {
         match opt {
@@ -289,6 +387,22 @@ pub struct TokenInfo {
     pub counters: CountersInfo,
 }
 
+/// A final balance statement, signed by both sides of a token channel.
+/// Created when the channel with a friend is cooperatively closed.
+#[capnp_conv(crate::report_capnp::friend_settlement)]
+#[derive(Arbitrary, Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct FriendSettlement {
+    /// Final state of the token channel, from the point of view of the local side.
+    /// All pending debts are zero.
+    pub token_info: TokenInfo,
+    /// Local side signature over the final state
+    #[serde(with = "ser_b64")]
+    pub local_signature: Signature,
+    /// Remote side signature over the final state
+    #[serde(with = "ser_b64")]
+    pub remote_signature: Signature,
+}
+
 #[capnp_conv(crate::funder_capnp::currency_operations)]
 #[derive(Arbitrary, Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct CurrencyOperations {
@@ -305,6 +419,8 @@ pub struct MoveToken<B = NetAddress> {
     pub currencies_operations: Vec<CurrencyOperations>,
     #[capnp_conv(with = OptLocalRelays<NetAddress>)]
     pub opt_local_relays: Option<Vec<RelayAddress<B>>>,
+    #[capnp_conv(with = OptDirectAddresses<NetAddress>)]
+    pub opt_direct_addresses: Option<Vec<B>>,
     #[capnp_conv(with = OptActiveCurrencies)]
     pub opt_active_currencies: Option<Vec<Currency>>,
     #[serde(with = "ser_b64")]
@@ -321,6 +437,7 @@ pub struct UnsignedMoveToken<B = NetAddress> {
     pub old_token: Signature,
     pub currencies_operations: Vec<CurrencyOperations>,
     pub opt_local_relays: Option<Vec<RelayAddress<B>>>,
+    pub opt_direct_addresses: Option<Vec<B>>,
     pub opt_active_currencies: Option<Vec<Currency>>,
     #[serde(with = "ser_b64")]
     pub info_hash: HashResult,
@@ -334,6 +451,7 @@ impl<B> Into<UnsignedMoveToken<B>> for MoveToken<B> {
             old_token: self.old_token,
             currencies_operations: self.currencies_operations,
             opt_local_relays: self.opt_local_relays,
+            opt_direct_addresses: self.opt_direct_addresses,
             opt_active_currencies: self.opt_active_currencies,
             info_hash: self.info_hash,
             rand_nonce: self.rand_nonce,
@@ -376,6 +494,18 @@ impl<'de> Visitor<'de> for CurrencyVisitor {
     }
 }
 
+impl JsonSchema for Currency {
+    fn schema_name() -> String {
+        "Currency".to_owned()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let mut schema: SchemaObject = String::json_schema(gen).into();
+        schema.string().max_length = Some(MAX_CURRENCY_LEN as u32);
+        schema.into()
+    }
+}
+
 impl<'de> Deserialize<'de> for Currency {
     fn deserialize<D>(deserializer: D) -> Result<Currency, D::Error>
     where
@@ -439,32 +569,40 @@ pub struct MoveTokenRequest<B = NetAddress> {
 pub enum FriendMessage<B = NetAddress> {
     MoveTokenRequest(MoveTokenRequest<B>),
     InconsistencyError(ResetTerms),
+    SettlementSignature(Signature),
 }
 
 /// A `Receipt` is received if a `RequestSendFunds` is successful.
 /// It can be used a proof of payment for a specific `invoice_id`.
 #[capnp_conv(crate::common_capnp::receipt)]
-#[derive(Arbitrary, Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Arbitrary, Clone, Serialize, Deserialize, JsonSchema, Debug, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct Receipt {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub response_hash: HashResult,
     // = sha512/256(requestId || randNonce)
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub invoice_id: InvoiceId,
     pub currency: Currency,
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub src_plain_lock: PlainLock,
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub dest_plain_lock: PlainLock,
     pub is_complete: bool,
     #[capnp_conv(with = Wrapper<u128>)]
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub dest_payment: u128,
     #[capnp_conv(with = Wrapper<u128>)]
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub total_dest_payment: u128,
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub signature: Signature,
     /*
     # Signature{key=destinationKey}(
@@ -502,6 +640,7 @@ pub struct PendingTransaction {
     pub left_fees: u128,
     #[serde(with = "ser_b64")]
     pub src_hashed_lock: HashedLock,
+    pub exchanges: Vec<CurrencyExchange>,
     pub stage: TransactionStage,
 }
 
@@ -632,7 +771,7 @@ impl RequestsStatus {
 /// For a transaction of `x` credits, the amount of fees will be:
 /// `(x * mul) / 2^32 + add`
 #[capnp_conv(crate::common_capnp::rate)]
-#[derive(Arbitrary, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Arbitrary, Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct Rate {
     /// Commission
@@ -678,6 +817,67 @@ impl Rate {
     }
 }
 
+/// Rate for exchanging credits of one currency to another currency.
+/// An amount of `x` credits in the source currency is exchanged to
+/// `(x * numerator) / denominator` credits in the destination currency.
+#[capnp_conv(crate::common_capnp::exchange_rate)]
+#[derive(Arbitrary, Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ExchangeRate {
+    pub numerator: u64,
+    pub denominator: u64,
+}
+
+impl ExchangeRate {
+    /// Exchange `amount` credits of the source currency to the destination currency.
+    /// The result is rounded down.
+    pub fn convert(&self, amount: u128) -> Option<u128> {
+        if self.denominator == 0 {
+            return None;
+        }
+        let res = (BigUint::from(amount) * BigUint::from(self.numerator))
+            / BigUint::from(self.denominator);
+        res.to_u128()
+    }
+
+    /// Minimal amount of credits of the source currency that is exchanged to at least `amount`
+    /// credits of the destination currency.
+    pub fn convert_back(&self, amount: u128) -> Option<u128> {
+        if self.numerator == 0 {
+            return None;
+        }
+        // Round up:
+        let res = (BigUint::from(amount) * BigUint::from(self.denominator)
+            + BigUint::from(self.numerator - 1))
+            / BigUint::from(self.numerator);
+        res.to_u128()
+    }
+}
+
+/// Amount of credits (In the currency a request is currently sent in) that has to be passed
+/// along a route so that `dest_payment` credits will arrive at the destination, after going
+/// through all the `exchanges` left. Fees are not included.
+pub fn exchanged_dest_payment(dest_payment: u128, exchanges: &[CurrencyExchange]) -> Option<u128> {
+    exchanges
+        .iter()
+        .rev()
+        .try_fold(dest_payment, |amount, currency_exchange| {
+            currency_exchange.rate.convert_back(amount)
+        })
+}
+
+/// The currency in which the destination of a request is paid, given the currency the request
+/// is currently sent in, and the exchanges left.
+pub fn exchanged_dest_currency<'a>(
+    currency: &'a Currency,
+    exchanges: &'a [CurrencyExchange],
+) -> &'a Currency {
+    exchanges
+        .last()
+        .map(|currency_exchange| &currency_exchange.dest_currency)
+        .unwrap_or(currency)
+}
+
 #[capnp_conv(crate::app_server_capnp::add_friend)]
 #[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct AddFriend<B = NetAddress> {
@@ -692,6 +892,16 @@ pub struct RemoveFriend {
     pub friend_public_key: PublicKey,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CloseFriend {
+    pub friend_public_key: PublicKey,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AbortCloseFriend {
+    pub friend_public_key: PublicKey,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SetFriendCurrencyRequestsStatus {
     pub friend_public_key: PublicKey,
@@ -778,6 +988,15 @@ pub struct CreatePayment {
     pub dest_public_key: PublicKey,
 }
 
+/// The src_plain_lock used by push payments (Payments without a prior invoice).
+///
+/// There is no Commit message sent from the buyer to the seller for push payments. Instead, the
+/// buyer commits in advance by deriving the src_plain_lock from the (buyer chosen) invoice_id,
+/// allowing the destination to collect the funds as soon as the payment is complete.
+pub fn push_payment_src_plain_lock(invoice_id: &InvoiceId) -> PlainLock {
+    PlainLock::from(invoice_id.as_array_ref())
+}
+
 /// Start a payment, possibly by paying through multiple routes.
 #[capnp_conv(crate::app_server_capnp::create_transaction)]
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -792,6 +1011,9 @@ pub struct CreateTransaction {
     pub dest_payment: u128,
     #[capnp_conv(with = Wrapper<u128>)]
     pub fees: u128,
+    /// Currency exchanges along the route (Empty for a single currency route).
+    /// `fees` are given in the currency of the first hop.
+    pub exchanges: Vec<CurrencyExchange>,
 }
 
 /// Start an invoice (A request for payment).
@@ -807,6 +1029,67 @@ pub struct AddInvoice {
     pub total_dest_payment: u128,
 }
 
+/// Hold a fully paid invoice: The funds remain locked along the route until the seller commits
+/// or cancels the invoice explicitly. The invoice is canceled automatically if it was not
+/// committed within `max_hold_ticks`.
+#[capnp_conv(crate::app_server_capnp::hold_invoice)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HoldInvoice {
+    /// A Commit received from the buyer, proving that the invoice was fully paid.
+    pub commit: Commit,
+    /// Maximum amount of timer ticks to hold the invoice before canceling it.
+    pub max_hold_ticks: u64,
+}
+
+/// Accept push payments (Payments without a prior invoice) in a certain currency.
+#[capnp_conv(crate::app_server_capnp::enable_push_payments)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnablePushPayments {
+    pub currency: Currency,
+    /// Maximum total amount of credits accepted for a single push payment.
+    #[capnp_conv(with = Wrapper<u128>)]
+    pub max_total_dest_payment: u128,
+}
+
+/// Forward requests received in `src_currency` as requests in `dest_currency`.
+#[capnp_conv(crate::app_server_capnp::set_exchange_rate)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SetExchangeRate {
+    pub src_currency: Currency,
+    pub dest_currency: Currency,
+    pub rate: ExchangeRate,
+}
+
+/// Stop forwarding requests from `src_currency` to `dest_currency`.
+#[capnp_conv(crate::app_server_capnp::remove_exchange_rate)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoveExchangeRate {
+    pub src_currency: Currency,
+    pub dest_currency: Currency,
+}
+
+/// Terms of a currency swap with a friend, from the point of view of the local node:
+/// We give `give_amount` of `give_currency`, and take `take_amount` of `take_currency`.
+#[capnp_conv(crate::app_server_capnp::friend_swap)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FriendSwap {
+    pub friend_public_key: PublicKey,
+    pub swap_id: Uid,
+    pub give_currency: Currency,
+    #[capnp_conv(with = Wrapper<u128>)]
+    pub give_amount: u128,
+    pub take_currency: Currency,
+    #[capnp_conv(with = Wrapper<u128>)]
+    pub take_amount: u128,
+}
+
+#[capnp_conv(crate::app_server_capnp::cancel_friend_swap)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CancelFriendSwap {
+    pub friend_public_key: PublicKey,
+    pub swap_id: Uid,
+}
+
 /// Start an invoice (A request for payment).
 #[capnp_conv(crate::app_server_capnp::ack_close_payment)]
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -839,6 +1122,21 @@ pub enum FunderControl<B> {
     AddInvoice(AddInvoice),
     CancelInvoice(InvoiceId),
     CommitInvoice(Commit),
+    HoldInvoice(HoldInvoice),
+    // Push payments API:
+    CreatePushPayment(CreatePayment),
+    EnablePushPayments(EnablePushPayments),
+    DisablePushPayments(Currency),
+    // Currency exchange:
+    SetExchangeRate(SetExchangeRate),
+    RemoveExchangeRate(RemoveExchangeRate),
+    // Currency swaps with friends:
+    ProposeSwap(FriendSwap),
+    AcceptSwap(FriendSwap),
+    CancelSwap(CancelFriendSwap),
+    // Cooperative closing of a friend channel:
+    CloseFriend(CloseFriend),
+    AbortCloseFriend(AbortCloseFriend),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -3,9 +3,10 @@ use std::collections::HashMap;
 use capnp_conv::{capnp_conv, CapnpConvError, ReadCapnp, WriteCapnp};
 
 use crate::crypto::{PublicKey, Uid};
-use crate::funder::messages::{Currency, Rate};
+use crate::funder::messages::{Currency, ExchangeRate, Rate};
 pub use crate::index_server::messages::{
-    IndexMutation, RemoveFriendCurrency, RequestRoutes, UpdateFriendCurrency,
+    IndexMutation, RemoveExchangeRate, RemoveFriendCurrency, RequestRoutes, UpdateExchangeRate,
+    UpdateFriendCurrency,
 };
 use crate::index_server::messages::{MultiRoute, NamedIndexServerAddress};
 use crate::net::messages::NetAddress;
@@ -21,6 +22,8 @@ pub struct FriendInfo {
 #[derive(Debug, Clone)]
 pub struct IndexClientState {
     pub friends: HashMap<(PublicKey, Currency), FriendInfo>,
+    /// Exchange rates we offer: (src_currency, dest_currency) -> rate
+    pub exchange_rates: HashMap<(Currency, Currency), ExchangeRate>,
 }
 
 // ---------------------------------------------------
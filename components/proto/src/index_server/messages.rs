@@ -1,3 +1,4 @@
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use capnp_conv::{capnp_conv, CapnpConvError, ReadCapnp, WriteCapnp};
@@ -5,7 +6,7 @@ use capnp_conv::{capnp_conv, CapnpConvError, ReadCapnp, WriteCapnp};
 use common::ser_utils::{ser_b64, ser_string};
 
 use crate::crypto::{HashResult, PublicKey, RandValue, Signature, Uid};
-use crate::funder::messages::{Currency, FriendsRoute, Rate};
+use crate::funder::messages::{Currency, CurrencyExchange, ExchangeRate, FriendsRoute, Rate};
 use crate::net::messages::NetAddress;
 use crate::wrapper::Wrapper;
 
@@ -42,12 +43,66 @@ impl From<OptExclude> for Option<Edge> {
     }
 }
 
+#[capnp_conv(crate::index_capnp::request_routes::opt_max_hops)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum OptMaxHops {
+    Empty,
+    MaxHops(u32),
+}
+
+impl From<Option<u32>> for OptMaxHops {
+    fn from(opt: Option<u32>) -> Self {
+        match opt {
+            Some(max_hops) => OptMaxHops::MaxHops(max_hops),
+            None => OptMaxHops::Empty,
+        }
+    }
+}
+
+impl From<OptMaxHops> for Option<u32> {
+    fn from(opt: OptMaxHops) -> Self {
+        match opt {
+            OptMaxHops::MaxHops(max_hops) => Some(max_hops),
+            OptMaxHops::Empty => None,
+        }
+    }
+}
+
+#[capnp_conv(crate::index_capnp::request_routes::opt_max_fees)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum OptMaxFees {
+    Empty,
+    #[capnp_conv(with = Wrapper<u128>)]
+    MaxFees(u128),
+}
+
+impl From<Option<u128>> for OptMaxFees {
+    fn from(opt: Option<u128>) -> Self {
+        match opt {
+            Some(max_fees) => OptMaxFees::MaxFees(max_fees),
+            None => OptMaxFees::Empty,
+        }
+    }
+}
+
+impl From<OptMaxFees> for Option<u128> {
+    fn from(opt: OptMaxFees) -> Self {
+        match opt {
+            OptMaxFees::MaxFees(max_fees) => Some(max_fees),
+            OptMaxFees::Empty => None,
+        }
+    }
+}
+
 /// IndexClient -> IndexServer
 #[capnp_conv(crate::index_capnp::request_routes)]
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct RequestRoutes {
     pub request_id: Uid,
     pub currency: Currency,
+    /// Currency accepted by the destination. If different from `currency`, the returned routes
+    /// will go through currency exchanges.
+    pub dest_currency: Currency,
     /// Wanted capacity for the route.
     /// 0 means we want to optimize for capacity??
     #[capnp_conv(with = Wrapper<u128>)]
@@ -58,6 +113,20 @@ pub struct RequestRoutes {
     /// Useful for finding non trivial directed loops.
     #[capnp_conv(with = OptExclude)]
     pub opt_exclude: Option<Edge>,
+    /// Additional directed edges that must not show up in any route inside the multi-route.
+    /// At most `MAX_EXCLUDE_EDGES` edges may be specified.
+    pub exclude_edges: Vec<Edge>,
+    /// Nodes that must not show up in any route inside the multi-route.
+    /// Useful for avoiding nodes that are known to be unreliable.
+    /// At most `MAX_EXCLUDE_NODES` nodes may be specified.
+    pub exclude_nodes: Vec<PublicKey>,
+    /// Maximum amount of hops (Edges) in a route.
+    /// Routes are never longer than `MAX_ROUTE_LEN`, even if not specified.
+    #[capnp_conv(with = OptMaxHops)]
+    pub opt_max_hops: Option<u32>,
+    /// Maximum total fees for sending `capacity` credits along a route.
+    #[capnp_conv(with = OptMaxFees)]
+    pub opt_max_fees: Option<u128>,
 }
 
 #[capnp_conv(crate::index_capnp::route_capacity_rate)]
@@ -70,6 +139,22 @@ pub struct RouteCapacityRate {
     pub capacity: u128,
     /// Combined rate of pushing credits along this route.
     pub rate: Rate,
+    /// Currency exchanges along the route.
+    /// If not empty, `capacity` and `rate` only refer to the part of the route before the first
+    /// exchange.
+    pub exchanges: Vec<RouteExchange>,
+}
+
+/// A currency exchange along a route, together with the capacity and rate of the part of the
+/// route that follows it (Up to the next exchange, or the end of the route).
+#[capnp_conv(crate::index_capnp::route_exchange)]
+#[derive(Arbitrary, Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct RouteExchange {
+    pub currency_exchange: CurrencyExchange,
+    #[capnp_conv(with = Wrapper<u128>)]
+    #[serde(with = "ser_string")]
+    pub capacity: u128,
+    pub rate: Rate,
 }
 
 /// Multiple routes that together allow to pass a certain amount of credits to a destination.
@@ -121,12 +206,30 @@ pub struct RemoveFriendCurrency {
     pub currency: Currency,
 }
 
+/// Announce that we are willing to forward requests from one currency to another.
+#[capnp_conv(crate::index_capnp::update_exchange_rate)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpdateExchangeRate {
+    pub src_currency: Currency,
+    pub dest_currency: Currency,
+    pub rate: ExchangeRate,
+}
+
+#[capnp_conv(crate::index_capnp::remove_exchange_rate)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoveExchangeRate {
+    pub src_currency: Currency,
+    pub dest_currency: Currency,
+}
+
 /// IndexClient -> IndexServer
 #[capnp_conv(crate::index_capnp::index_mutation)]
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum IndexMutation {
     UpdateFriendCurrency(UpdateFriendCurrency),
     RemoveFriendCurrency(RemoveFriendCurrency),
+    UpdateExchangeRate(UpdateExchangeRate),
+    RemoveExchangeRate(RemoveExchangeRate),
 }
 
 #[capnp_conv(crate::index_capnp::mutations_update)]
@@ -192,24 +295,141 @@ pub enum IndexClientToServer {
 
 #[capnp_conv(crate::index_capnp::index_server_to_server)]
 #[derive(Debug)]
-pub enum IndexServerToServer {
+pub enum IndexServerToServer<ISA = NetAddress> {
     TimeHash(HashResult),
     ForwardMutationsUpdate(ForwardMutationsUpdate),
+    /// Trusted servers of the sending server. Used for peer discovery.
+    KnownServers(Vec<IndexServerAddress<ISA>>),
+}
+
+/// IndexAdmin -> IndexServer
+#[capnp_conv(crate::index_capnp::request_node_edges)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestNodeEdges {
+    pub request_id: Uid,
+    /// All edges going out of this node or into this node will be returned.
+    pub node_public_key: PublicKey,
+}
+
+/// IndexAdmin -> IndexServer
+#[capnp_conv(crate::index_capnp::request_export_graph)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestExportGraph {
+    pub request_id: Uid,
+    /// All edges of the graph of this currency will be returned.
+    pub currency: Currency,
+}
+
+#[capnp_conv(crate::index_capnp::currency_graph_stats)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CurrencyGraphStats {
+    pub currency: Currency,
+    pub num_nodes: u64,
+    pub num_edges: u64,
+}
+
+/// IndexServer -> IndexAdmin
+#[capnp_conv(crate::index_capnp::response_graph_stats)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseGraphStats {
+    pub request_id: Uid,
+    pub currencies_stats: Vec<CurrencyGraphStats>,
+}
+
+/// A directed edge of the capacity graph, as seen by the index server.
+#[capnp_conv(crate::index_capnp::admin_edge)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AdminEdge {
+    pub currency: Currency,
+    pub from_public_key: PublicKey,
+    pub to_public_key: PublicKey,
+    #[capnp_conv(with = Wrapper<u128>)]
+    pub recv_capacity: u128,
+    pub rate: Rate,
+    /// Amount of ticks passed since the edge was last updated.
+    #[capnp_conv(with = Wrapper<u128>)]
+    pub age: u128,
+}
+
+/// IndexServer -> IndexAdmin
+#[capnp_conv(crate::index_capnp::response_edges)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseEdges {
+    pub request_id: Uid,
+    pub edges: Vec<AdminEdge>,
+}
+
+/// IndexAdmin -> IndexServer
+#[capnp_conv(crate::index_capnp::request_add_server)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestAddServer<ISA = NetAddress> {
+    pub request_id: Uid,
+    /// A new trusted index server.
+    pub server_address: IndexServerAddress<ISA>,
+}
+
+/// IndexAdmin -> IndexServer
+#[capnp_conv(crate::index_capnp::request_remove_server)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestRemoveServer {
+    pub request_id: Uid,
+    /// Public key of a trusted index server to remove.
+    pub public_key: PublicKey,
+}
+
+/// A trusted server of an index server, as seen by the index server.
+#[capnp_conv(crate::index_capnp::federation_server)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FederationServer<ISA = NetAddress> {
+    pub server_address: IndexServerAddress<ISA>,
+    pub is_connected: bool,
+    /// Trusted servers announced by this server.
+    pub known_servers: Vec<IndexServerAddress<ISA>>,
+}
+
+/// IndexServer -> IndexAdmin
+#[capnp_conv(crate::index_capnp::response_federation)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResponseFederation<ISA = NetAddress> {
+    pub request_id: Uid,
+    /// All the trusted servers of the index server.
+    pub servers: Vec<FederationServer<ISA>>,
+}
+
+#[capnp_conv(crate::index_capnp::index_admin_to_server)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndexAdminToServer<ISA = NetAddress> {
+    RequestGraphStats(Uid),
+    RequestNodeEdges(RequestNodeEdges),
+    RequestExportGraph(RequestExportGraph),
+    RequestFederation(Uid),
+    RequestAddServer(RequestAddServer<ISA>),
+    RequestRemoveServer(RequestRemoveServer),
+}
+
+#[capnp_conv(crate::index_capnp::index_server_to_admin)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IndexServerToAdmin<ISA = NetAddress> {
+    ResponseGraphStats(ResponseGraphStats),
+    ResponseEdges(ResponseEdges),
+    ResponseFederation(ResponseFederation<ISA>),
 }
 
 // ----------------------------------------------
 // ----------------------------------------------
 
 #[capnp_conv(crate::common_capnp::named_index_server_address)]
-#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct NamedIndexServerAddress<ISA = NetAddress> {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub public_key: PublicKey,
     pub address: ISA,
     pub name: String,
 }
 
+#[capnp_conv(crate::common_capnp::index_server_address)]
 #[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct IndexServerAddress<ISA = NetAddress> {
     #[serde(with = "ser_b64")]
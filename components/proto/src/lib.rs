@@ -23,6 +23,7 @@ pub mod funder;
 pub mod index_client;
 pub mod index_server;
 pub mod keepalive;
+pub mod mux;
 pub mod net;
 pub mod proto_ser;
 pub mod relay;
@@ -38,4 +39,5 @@ include_schema!(dh_capnp, "dh_capnp");
 include_schema!(relay_capnp, "relay_capnp");
 include_schema!(funder_capnp, "funder_capnp");
 include_schema!(keepalive_capnp, "keepalive_capnp");
+include_schema!(mux_capnp, "mux_capnp");
 include_schema!(index_capnp, "index_capnp");
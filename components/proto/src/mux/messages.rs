@@ -0,0 +1,38 @@
+use capnp_conv::{capnp_conv, CapnpConvError, ReadCapnp, WriteCapnp};
+
+#[capnp_conv(crate::mux_capnp::stream_id)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct StreamId {
+    pub id: u32,
+    pub opened_by_sender: bool,
+}
+
+#[capnp_conv(crate::mux_capnp::stream_open)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct StreamOpen {
+    pub stream_id: StreamId,
+    pub credit: u32,
+}
+
+#[capnp_conv(crate::mux_capnp::stream_data)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct StreamData {
+    pub stream_id: StreamId,
+    pub data: Vec<u8>,
+}
+
+#[capnp_conv(crate::mux_capnp::stream_credit)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct StreamCredit {
+    pub stream_id: StreamId,
+    pub credit: u32,
+}
+
+#[capnp_conv(crate::mux_capnp::mux_message)]
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum MuxMessage {
+    Open(StreamOpen),
+    Data(StreamData),
+    Credit(StreamCredit),
+    Close(StreamId),
+}
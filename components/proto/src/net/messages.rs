@@ -5,6 +5,10 @@ use std::str::FromStr;
 use serde::de::{self, Visitor};
 use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
+use schemars::gen::SchemaGenerator;
+use schemars::schema::{Schema, SchemaObject};
+use schemars::JsonSchema;
+
 use derive_more::Display;
 
 use capnp_conv::{capnp_conv, CapnpConvError, ReadCapnp, WriteCapnp};
@@ -74,6 +78,18 @@ impl<'de> Visitor<'de> for NetAddressVisitor {
     }
 }
 
+impl JsonSchema for NetAddress {
+    fn schema_name() -> String {
+        "NetAddress".to_owned()
+    }
+
+    fn json_schema(gen: &mut SchemaGenerator) -> Schema {
+        let mut schema: SchemaObject = String::json_schema(gen).into();
+        schema.string().max_length = Some(MAX_NET_ADDRESS_LENGTH as u32);
+        schema.into()
+    }
+}
+
 impl<'de> Deserialize<'de> for NetAddress {
     fn deserialize<D>(deserializer: D) -> Result<NetAddress, D::Error>
     where
@@ -6,9 +6,12 @@ use common::safe_arithmetic::{SafeSignedArithmetic, SafeUnsignedArithmetic};
 
 use crate::crypto::PublicKey;
 
-use crate::funder::messages::{Currency, Rate};
+use crate::funder::messages::{Currency, ExchangeRate, Rate};
 use crate::index_client::messages::{FriendInfo, IndexClientState};
-use crate::index_server::messages::{IndexMutation, RemoveFriendCurrency, UpdateFriendCurrency};
+use crate::index_server::messages::{
+    IndexMutation, RemoveExchangeRate, RemoveFriendCurrency, UpdateExchangeRate,
+    UpdateFriendCurrency,
+};
 
 use crate::report::messages::{
     ChannelStatusReport, FriendLivenessReport, FriendReport, FriendStatusReport, FunderReport,
@@ -119,12 +122,33 @@ where
         .map(|(tuple, opt_friend_info)| (tuple, opt_friend_info.unwrap()))
 }
 
+fn calc_exchange_rates<B>(
+    funder_report: &FunderReport<B>,
+) -> impl Iterator<Item = ((Currency, Currency), ExchangeRate)> + '_
+where
+    B: Clone,
+{
+    funder_report
+        .exchange_rates
+        .iter()
+        .map(|exchange_rate_report| {
+            (
+                (
+                    exchange_rate_report.src_currency.clone(),
+                    exchange_rate_report.dest_currency.clone(),
+                ),
+                exchange_rate_report.rate.clone(),
+            )
+        })
+}
+
 pub fn funder_report_to_index_client_state<B>(funder_report: &FunderReport<B>) -> IndexClientState
 where
     B: Clone,
 {
     IndexClientState {
         friends: calc_friends_info(funder_report).collect(),
+        exchange_rates: calc_exchange_rates(funder_report).collect(),
     }
 }
 
@@ -168,6 +192,34 @@ where
             rate: friend_info.rate,
         }));
     }
+
+    let old_exchange_rates: HashMap<(Currency, Currency), ExchangeRate> =
+        calc_exchange_rates(old_funder_report).collect();
+    let new_exchange_rates: HashMap<(Currency, Currency), ExchangeRate> =
+        calc_exchange_rates(new_funder_report).collect();
+
+    // Push exchange rates Remove mutations:
+    for (src_currency, dest_currency) in old_exchange_rates.keys() {
+        if !new_exchange_rates.contains_key(&(src_currency.clone(), dest_currency.clone())) {
+            res_mutations.push(IndexMutation::RemoveExchangeRate(RemoveExchangeRate {
+                src_currency: src_currency.clone(),
+                dest_currency: dest_currency.clone(),
+            }));
+        }
+    }
+
+    // Push exchange rates update mutations:
+    for (currency_pair, rate) in new_exchange_rates {
+        if old_exchange_rates.get(&currency_pair) == Some(&rate) {
+            continue;
+        }
+        let (src_currency, dest_currency) = currency_pair;
+        res_mutations.push(IndexMutation::UpdateExchangeRate(UpdateExchangeRate {
+            src_currency,
+            dest_currency,
+            rate,
+        }));
+    }
     res_mutations
 }
 
@@ -189,7 +241,8 @@ mod tests {
     use super::*;
 
     use crate::report::messages::{
-        ChannelConsistentReport, CurrencyConfigReport, CurrencyReport, McBalanceReport,
+        ChannelConsistentReport, CurrencyConfigReport, CurrencyReport, ExchangeRateReport,
+        FriendCloseStatusReport, McBalanceReport, RemoveExchangeRateReport,
     };
     use std::convert::TryFrom;
 
@@ -260,6 +313,8 @@ mod tests {
                     ],
                 }),
                 status: FriendStatusReport::Enabled,
+                relays_health: Vec::new(),
+                close_status: FriendCloseStatusReport::Open,
             },
         );
 
@@ -287,12 +342,16 @@ mod tests {
                     }],
                 }),
                 status: FriendStatusReport::Enabled,
+                relays_health: Vec::new(),
+                close_status: FriendCloseStatusReport::Open,
             },
         );
         let funder_report = FunderReport {
             local_public_key: pk1.clone(),
             relays: Vec::new(),
             friends,
+            exchange_rates: Vec::new(),
+            closed_friends: Vec::new(),
         };
         let friends_info: HashMap<(PublicKey, Currency), FriendInfo> =
             calc_friends_info(&funder_report).collect();
@@ -395,6 +454,8 @@ mod tests {
                     ],
                 }),
                 status: FriendStatusReport::Enabled,
+                relays_health: Vec::new(),
+                close_status: FriendCloseStatusReport::Open,
             },
         );
 
@@ -402,6 +463,8 @@ mod tests {
             local_public_key: pk1.clone(),
             relays: Vec::new(),
             friends,
+            exchange_rates: Vec::new(),
+            closed_friends: Vec::new(),
         };
 
         let mut friends = HashMap::new();
@@ -461,12 +524,16 @@ mod tests {
                     ],
                 }),
                 status: FriendStatusReport::Enabled,
+                relays_health: Vec::new(),
+                close_status: FriendCloseStatusReport::Open,
             },
         );
         let new_funder_report = FunderReport {
             local_public_key: pk1.clone(),
             relays: Vec::new(),
             friends,
+            exchange_rates: Vec::new(),
+            closed_friends: Vec::new(),
         };
 
         let index_mutations = calc_index_mutations(&old_funder_report, &new_funder_report);
@@ -488,7 +555,81 @@ mod tests {
                         assert_eq!(update_friend_currency.rate, Rate::new());
                     }
                 }
+                _ => unreachable!(),
             }
         }
     }
+
+    #[test]
+    fn test_calc_index_mutations_exchange_rates() {
+        let currency1 = Currency::try_from("FST1".to_owned()).unwrap();
+        let currency2 = Currency::try_from("FST2".to_owned()).unwrap();
+        let currency3 = Currency::try_from("FST3".to_owned()).unwrap();
+
+        let pk1 = PublicKey::from(&[1; PublicKey::len()]);
+
+        let old_funder_report = FunderReport::<u32> {
+            local_public_key: pk1.clone(),
+            relays: Vec::new(),
+            friends: HashMap::new(),
+            exchange_rates: vec![
+                ExchangeRateReport {
+                    src_currency: currency1.clone(),
+                    dest_currency: currency2.clone(),
+                    rate: ExchangeRate {
+                        numerator: 1,
+                        denominator: 2,
+                    },
+                },
+                ExchangeRateReport {
+                    src_currency: currency2.clone(),
+                    dest_currency: currency3.clone(),
+                    rate: ExchangeRate {
+                        numerator: 3,
+                        denominator: 1,
+                    },
+                },
+            ],
+            closed_friends: Vec::new(),
+        };
+
+        let mut new_funder_report = old_funder_report.clone();
+        new_funder_report
+            .mutate(&FunderReportMutation::RemoveExchangeRate(
+                RemoveExchangeRateReport {
+                    src_currency: currency1.clone(),
+                    dest_currency: currency2.clone(),
+                },
+            ))
+            .unwrap();
+        new_funder_report
+            .mutate(&FunderReportMutation::SetExchangeRate(ExchangeRateReport {
+                src_currency: currency2.clone(),
+                dest_currency: currency3.clone(),
+                rate: ExchangeRate {
+                    numerator: 4,
+                    denominator: 1,
+                },
+            }))
+            .unwrap();
+
+        let index_mutations = calc_index_mutations(&old_funder_report, &new_funder_report);
+        assert_eq!(
+            index_mutations,
+            vec![
+                IndexMutation::RemoveExchangeRate(RemoveExchangeRate {
+                    src_currency: currency1,
+                    dest_currency: currency2.clone(),
+                }),
+                IndexMutation::UpdateExchangeRate(UpdateExchangeRate {
+                    src_currency: currency2,
+                    dest_currency: currency3,
+                    rate: ExchangeRate {
+                        numerator: 4,
+                        denominator: 1,
+                    },
+                }),
+            ]
+        );
+    }
 }
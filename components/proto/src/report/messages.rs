@@ -14,7 +14,8 @@ use crate::crypto::{HashResult, PublicKey, RandValue, Signature, Uid};
 
 use crate::app_server::messages::{NamedRelayAddress, RelayAddress};
 use crate::funder::messages::{
-    Currency, CurrencyBalance, FriendStatus, Rate, RequestsStatus, TokenInfo,
+    Currency, CurrencyBalance, ExchangeRate, FriendSettlement, FriendStatus, Rate, RequestsStatus,
+    TokenInfo,
 };
 use crate::net::messages::NetAddress;
 use crate::wrapper::Wrapper;
@@ -35,6 +36,16 @@ pub enum FriendStatusReport {
     Disabled,
 }
 
+/// Progress of a cooperative close of the channel with a friend
+#[allow(clippy::large_enum_variant)]
+#[capnp_conv(crate::report_capnp::friend_close_status_report)]
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum FriendCloseStatusReport {
+    Open,
+    Closing,
+    Closed(FriendSettlement),
+}
+
 #[capnp_conv(crate::report_capnp::requests_status_report)]
 #[derive(Arbitrary, Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
 pub enum RequestsStatusReport {
@@ -184,6 +195,18 @@ pub struct CurrencyConfigReport {
     pub is_open: bool,
 }
 
+#[capnp_conv(crate::report_capnp::relay_health_report)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RelayHealthReport {
+    pub relay_public_key: PublicKey,
+    pub success_count: u64,
+    pub failure_count: u64,
+    /// Amount of failed connection attempts since the last successful one
+    pub consecutive_failures: u64,
+    /// Time ticks it took for the last successful connection attempt
+    pub latency_ticks: u64,
+}
+
 #[capnp_conv(crate::report_capnp::friend_report)]
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct FriendReport<B = NetAddress> {
@@ -199,6 +222,10 @@ pub struct FriendReport<B = NetAddress> {
     pub liveness: FriendLivenessReport, // is the friend online/offline?
     pub channel_status: ChannelStatusReport,
     pub status: FriendStatusReport,
+    /// Health of the relays used to connect to this friend
+    pub relays_health: Vec<RelayHealthReport>,
+    /// Progress of a cooperative close of the channel with this friend
+    pub close_status: FriendCloseStatusReport,
 }
 
 #[capnp_conv(crate::report_capnp::pk_friend_report)]
@@ -243,6 +270,31 @@ impl From<HashMap<PublicKey, FriendReport<NetAddress>>> for PkFriendReportList {
     }
 }
 
+#[capnp_conv(crate::report_capnp::exchange_rate_report)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExchangeRateReport {
+    pub src_currency: Currency,
+    pub dest_currency: Currency,
+    pub rate: ExchangeRate,
+}
+
+#[capnp_conv(crate::report_capnp::remove_exchange_rate_report)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoveExchangeRateReport {
+    pub src_currency: Currency,
+    pub dest_currency: Currency,
+}
+
+/// A friend that was removed after cooperatively closing the channel.
+#[capnp_conv(crate::report_capnp::closed_friend_report)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClosedFriendReport {
+    pub friend_public_key: PublicKey,
+    pub name: String,
+    /// Final balances, signed by both sides
+    pub settlement: FriendSettlement,
+}
+
 /// A FunderReport is a summary of a FunderState.
 /// It contains the information the Funder exposes to the user apps of the Offset node.
 #[capnp_conv(crate::report_capnp::funder_report)]
@@ -253,6 +305,10 @@ pub struct FunderReport<B = NetAddress> {
     pub relays: Vec<NamedRelayAddress<B>>,
     #[capnp_conv(with = PkFriendReportList)]
     pub friends: HashMap<PublicKey, FriendReport<B>>,
+    /// Exchange rates for forwarding requests from one currency to another
+    pub exchange_rates: Vec<ExchangeRateReport>,
+    /// Friends that were removed after cooperatively closing the channel
+    pub closed_friends: Vec<ClosedFriendReport>,
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -268,6 +324,8 @@ pub enum FriendReportMutation<B = NetAddress> {
     #[capnp_conv(with = OptLastIncomingMoveToken)]
     SetOptLastIncomingMoveToken(Option<MoveTokenHashedReport>),
     SetLiveness(FriendLivenessReport),
+    SetRelaysHealth(Vec<RelayHealthReport>),
+    SetCloseStatus(FriendCloseStatusReport),
 }
 
 #[capnp_conv(crate::report_capnp::add_friend_report)]
@@ -315,6 +373,9 @@ pub enum FunderReportMutation<B = NetAddress> {
     RemoveFriend(PublicKey),
     #[capnp_conv(with = PkFriendReportMutation<NetAddress>)]
     PkFriendReportMutation((PublicKey, FriendReportMutation<B>)),
+    SetExchangeRate(ExchangeRateReport),
+    RemoveExchangeRate(RemoveExchangeRateReport),
+    AddClosedFriend(ClosedFriendReport),
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -394,6 +455,12 @@ where
             FriendReportMutation::SetLiveness(friend_liveness_report) => {
                 self.liveness = friend_liveness_report.clone();
             }
+            FriendReportMutation::SetRelaysHealth(relays_health) => {
+                self.relays_health = relays_health.clone();
+            }
+            FriendReportMutation::SetCloseStatus(close_status) => {
+                self.close_status = close_status.clone();
+            }
         };
         Ok(())
     }
@@ -434,6 +501,8 @@ where
                     liveness: FriendLivenessReport::Offline,
                     channel_status: add_friend_report.channel_status.clone(),
                     status: FriendStatusReport::from(&FriendStatus::Disabled),
+                    relays_health: Vec::new(),
+                    close_status: FriendCloseStatusReport::Open,
                 };
                 if self
                     .friends
@@ -465,6 +534,36 @@ where
                     .map_err(|_| unreachable!())?;
                 Ok(())
             }
+            FunderReportMutation::SetExchangeRate(exchange_rate_report) => {
+                // Remove duplicates:
+                self.exchange_rates.retain(|cur_exchange_rate_report| {
+                    cur_exchange_rate_report.src_currency != exchange_rate_report.src_currency
+                        || cur_exchange_rate_report.dest_currency
+                            != exchange_rate_report.dest_currency
+                });
+                // Insert:
+                self.exchange_rates.push(exchange_rate_report.clone());
+                Ok(())
+            }
+            FunderReportMutation::RemoveExchangeRate(remove_exchange_rate_report) => {
+                self.exchange_rates.retain(|cur_exchange_rate_report| {
+                    cur_exchange_rate_report.src_currency
+                        != remove_exchange_rate_report.src_currency
+                        || cur_exchange_rate_report.dest_currency
+                            != remove_exchange_rate_report.dest_currency
+                });
+                Ok(())
+            }
+            FunderReportMutation::AddClosedFriend(closed_friend_report) => {
+                // Remove duplicates:
+                self.closed_friends.retain(|cur_closed_friend_report| {
+                    cur_closed_friend_report.friend_public_key
+                        != closed_friend_report.friend_public_key
+                });
+                // Insert:
+                self.closed_friends.push(closed_friend_report.clone());
+                Ok(())
+            }
         }
     }
 }
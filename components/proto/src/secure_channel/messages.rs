@@ -1,6 +1,6 @@
 use capnp_conv::{capnp_conv, CapnpConvError, ReadCapnp, WriteCapnp};
 
-use crate::crypto::{DhPublicKey, PublicKey, RandValue, Salt, Signature};
+use crate::crypto::{DhPublicKey, HashResult, PublicKey, RandValue, Salt, Signature};
 
 #[capnp_conv(crate::dh_capnp::exchange_rand_nonce::opt_dest_public_key)]
 #[derive(Debug, PartialEq, Eq)]
@@ -27,14 +27,70 @@ impl From<OptDestPublicKey> for Option<PublicKey> {
     }
 }
 
+#[capnp_conv(crate::dh_capnp::exchange_rand_nonce::opt_resume_ticket_id)]
+#[derive(Debug, PartialEq, Eq)]
+enum OptResumeTicketId {
+    Empty,
+    TicketId(HashResult),
+}
+
+impl From<Option<HashResult>> for OptResumeTicketId {
+    fn from(from: Option<HashResult>) -> Self {
+        match from {
+            Some(ticket_id) => Self::TicketId(ticket_id),
+            None => Self::Empty,
+        }
+    }
+}
+
+impl From<OptResumeTicketId> for Option<HashResult> {
+    fn from(from: OptResumeTicketId) -> Self {
+        match from {
+            OptResumeTicketId::TicketId(ticket_id) => Some(ticket_id),
+            OptResumeTicketId::Empty => None,
+        }
+    }
+}
+
 /// First Diffie-Hellman message:
 #[capnp_conv(crate::dh_capnp::exchange_rand_nonce)]
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct ExchangeRandNonce {
     pub rand_nonce: RandValue,
     pub src_public_key: PublicKey,
     #[capnp_conv(with = OptDestPublicKey)]
     pub opt_dest_public_key: Option<PublicKey>,
+    #[capnp_conv(with = OptResumeTicketId)]
+    pub opt_resume_ticket_id: Option<HashResult>,
+    pub pad_messages: bool,
+    pub mux_streams: bool,
+}
+
+impl ExchangeRandNonce {
+    /// A canonical representation of all the fields of the message.
+    /// Used to bind the message to the handshake transcript.
+    pub fn transcript_buffer(&self) -> Vec<u8> {
+        let mut tbuffer = Vec::new();
+        tbuffer.extend_from_slice(&self.rand_nonce);
+        tbuffer.extend_from_slice(&self.src_public_key);
+        match &self.opt_dest_public_key {
+            Some(dest_public_key) => {
+                tbuffer.push(1);
+                tbuffer.extend_from_slice(dest_public_key);
+            }
+            None => tbuffer.push(0),
+        }
+        match &self.opt_resume_ticket_id {
+            Some(resume_ticket_id) => {
+                tbuffer.push(1);
+                tbuffer.extend_from_slice(resume_ticket_id);
+            }
+            None => tbuffer.push(0),
+        }
+        tbuffer.push(self.pad_messages as u8);
+        tbuffer.push(self.mux_streams as u8);
+        tbuffer
+    }
 }
 
 /// Second Diffie-Hellman message:
@@ -48,11 +104,14 @@ pub struct ExchangeDh {
 }
 
 impl ExchangeDh {
-    pub fn signature_buffer(&self) -> Vec<u8> {
+    /// `transcript_hash` is the hash of the ExchangeRandNonce messages of both sides.
+    /// Signing it prevents an attacker from modifying the ExchangeRandNonce messages.
+    pub fn signature_buffer(&self, transcript_hash: &HashResult) -> Vec<u8> {
         let mut sbuffer = Vec::new();
         sbuffer.extend_from_slice(&self.dh_public_key);
         sbuffer.extend_from_slice(&self.rand_nonce);
         sbuffer.extend_from_slice(&self.key_salt);
+        sbuffer.extend_from_slice(transcript_hash);
         sbuffer
     }
 }
@@ -69,6 +128,7 @@ pub struct Rekey {
 pub enum ChannelContent {
     Rekey(Rekey),
     User(Vec<u8>),
+    Cover,
 }
 
 #[capnp_conv(crate::dh_capnp::channel_message)]
@@ -0,0 +1,176 @@
+use futures::future;
+use futures::{Stream, StreamExt};
+
+use common::conn::BoxFuture;
+
+use proto::crypto::PublicKey;
+
+use super::types::{IncomingConn, IncomingConnInner};
+
+/// Decides which nodes may listen on the relay (Using `Listen` and `Accept` connections).
+/// `Connect` connections are always allowed, so that anyone can reach the listening nodes.
+pub trait ListenAccess {
+    /// Is the node with the given public key allowed to listen on the relay?
+    fn is_listen_allowed<'a>(&'a mut self, public_key: &'a PublicKey) -> BoxFuture<'a, bool>;
+}
+
+/// A public relay: Any node may listen.
+#[derive(Debug, Clone)]
+pub struct AllowAllListeners;
+
+impl ListenAccess for AllowAllListeners {
+    fn is_listen_allowed<'a>(&'a mut self, _public_key: &'a PublicKey) -> BoxFuture<'a, bool> {
+        Box::pin(future::ready(true))
+    }
+}
+
+/// Discard `Listen` and `Accept` connections from nodes that are not allowed to listen.
+///
+/// Access is checked for every new connection. If a node loses its access while listening, its
+/// `Listen` connection stays open, but it will not be able to accept new connections.
+pub fn filter_listen_access<T, LA>(
+    incoming_conns: T,
+    listen_access: LA,
+) -> impl Stream<Item = IncomingConn>
+where
+    T: Stream<Item = IncomingConn> + Unpin + Send + 'static,
+    LA: ListenAccess + Clone + Send + 'static,
+{
+    incoming_conns
+        .filter_map(move |incoming_conn| {
+            let mut c_listen_access = listen_access.clone();
+            async move {
+                if let IncomingConnInner::Connect(_) = incoming_conn.inner {
+                    return Some(incoming_conn);
+                }
+                if c_listen_access
+                    .is_listen_allowed(&incoming_conn.public_key)
+                    .await
+                {
+                    Some(incoming_conn)
+                } else {
+                    warn!(
+                        "filter_listen_access(): {:?} is not allowed to listen",
+                        incoming_conn.public_key
+                    );
+                    None
+                }
+            }
+        })
+        // Added boxed because of issue: https://github.com/rust-lang/rust/issues/64496#issuecomment-546874018
+        // We might be able to remove this later
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashSet;
+
+    use futures::channel::mpsc;
+    use futures::executor::LocalPool;
+    use futures::stream;
+
+    use common::conn::{ConnPair, ConnPairVec};
+    use proto::relay::messages::{IncomingConnection, RejectConnection};
+
+    use crate::server::types::{IncomingAccept, IncomingConnect, IncomingListen};
+
+    #[derive(Clone)]
+    struct DummyListenAccess {
+        allowed: HashSet<PublicKey>,
+    }
+
+    impl ListenAccess for DummyListenAccess {
+        fn is_listen_allowed<'a>(&'a mut self, public_key: &'a PublicKey) -> BoxFuture<'a, bool> {
+            Box::pin(future::ready(self.allowed.contains(public_key)))
+        }
+    }
+
+    fn dummy_conn_pair_vec() -> ConnPairVec {
+        let (sender, _) = mpsc::channel::<Vec<u8>>(0);
+        let (_, receiver) = mpsc::channel::<Vec<u8>>(0);
+        ConnPairVec::from_raw(sender, receiver)
+    }
+
+    fn listen_conn(public_key: PublicKey) -> IncomingConn {
+        let (sender, _) = mpsc::channel::<IncomingConnection>(0);
+        let (_, receiver) = mpsc::channel::<RejectConnection>(0);
+        IncomingConn {
+            public_key,
+            inner: IncomingConnInner::Listen(IncomingListen {
+                conn_pair: ConnPair::from_raw(sender, receiver),
+            }),
+        }
+    }
+
+    fn accept_conn(public_key: PublicKey, accept_public_key: PublicKey) -> IncomingConn {
+        IncomingConn {
+            public_key,
+            inner: IncomingConnInner::Accept(IncomingAccept {
+                accept_public_key,
+                conn_pair: dummy_conn_pair_vec(),
+            }),
+        }
+    }
+
+    fn connect_conn(public_key: PublicKey, connect_public_key: PublicKey) -> IncomingConn {
+        IncomingConn {
+            public_key,
+            inner: IncomingConnInner::Connect(IncomingConnect {
+                connect_public_key,
+                conn_pair: dummy_conn_pair_vec(),
+            }),
+        }
+    }
+
+    async fn task_filter_listen_access() {
+        let pk_a = PublicKey::from(&[0xaa; PublicKey::len()]);
+        let pk_b = PublicKey::from(&[0xbb; PublicKey::len()]);
+
+        // Only pk_a may listen:
+        let listen_access = DummyListenAccess {
+            allowed: vec![pk_a.clone()].into_iter().collect(),
+        };
+
+        let incoming_conns = stream::iter(vec![
+            listen_conn(pk_a.clone()),
+            listen_conn(pk_b.clone()),
+            accept_conn(pk_b.clone(), pk_a.clone()),
+            connect_conn(pk_b.clone(), pk_a.clone()),
+            accept_conn(pk_a.clone(), pk_b.clone()),
+        ]);
+        let filtered_conns = filter_listen_access(incoming_conns, listen_access)
+            .collect::<Vec<_>>()
+            .await;
+
+        assert_eq!(filtered_conns.len(), 3);
+
+        // An allowed node may listen:
+        assert_eq!(filtered_conns[0].public_key, pk_a);
+        match filtered_conns[0].inner {
+            IncomingConnInner::Listen(_) => {}
+            _ => unreachable!(),
+        };
+
+        // Anyone may connect:
+        assert_eq!(filtered_conns[1].public_key, pk_b);
+        match filtered_conns[1].inner {
+            IncomingConnInner::Connect(_) => {}
+            _ => unreachable!(),
+        };
+
+        // An allowed node may accept:
+        assert_eq!(filtered_conns[2].public_key, pk_a);
+        match filtered_conns[2].inner {
+            IncomingConnInner::Accept(_) => {}
+            _ => unreachable!(),
+        };
+    }
+
+    #[test]
+    fn test_filter_listen_access() {
+        LocalPool::new().run_until(task_filter_listen_access());
+    }
+}
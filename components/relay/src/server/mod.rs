@@ -1,9 +1,11 @@
 mod conn_limiter;
 mod conn_processor;
+mod listen_access;
 // pub mod net_server;
 mod server;
 mod server_loop;
 mod types;
 
+pub use listen_access::{AllowAllListeners, ListenAccess};
 pub use server::relay_server;
 pub use server_loop::RelayServerError;
@@ -10,6 +10,7 @@ use proto::crypto::PublicKey;
 use timer::TimerClient;
 
 use crate::server::conn_processor::conn_processor;
+use crate::server::listen_access::{filter_listen_access, ListenAccess};
 use crate::server::server_loop::{relay_server_loop, RelayServerError};
 
 /// A relay server loop. Incoming connections should contain both (sender, receiver) and a
@@ -17,16 +18,20 @@ use crate::server::server_loop::{relay_server_loop, RelayServerError};
 ///
 /// `conn_timeout_ticks` is the amount of time we are willing to wait for a connection to identify
 /// its purpose.
-pub async fn relay_server<IC, S>(
+///
+/// `listen_access` decides which nodes may listen on the relay.
+pub async fn relay_server<IC, LA, S>(
     incoming_conns: IC,
     timer_client: TimerClient,
     conn_timeout_ticks: usize,
     half_tunnel_ticks: usize,
+    listen_access: LA,
     spawner: S,
 ) -> Result<(), RelayServerError>
 where
     S: Spawn + Clone + Send + 'static,
     IC: Stream<Item = (PublicKey, ConnPairVec)> + Unpin + Send + 'static,
+    LA: ListenAccess + Clone + Send + 'static,
 {
     // TODO: How to get rid of the Box::pin here?
     let processed_conns = Box::pin(conn_processor(
@@ -34,6 +39,7 @@ where
         timer_client.clone(),
         conn_timeout_ticks,
     ));
+    let allowed_conns = filter_listen_access(processed_conns, listen_access);
 
-    relay_server_loop(timer_client, processed_conns, half_tunnel_ticks, spawner).await
+    relay_server_loop(timer_client, allowed_conns, half_tunnel_ticks, spawner).await
 }
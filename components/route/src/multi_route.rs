@@ -2,7 +2,7 @@ use num_bigint::BigUint;
 use num_traits::cast::ToPrimitive;
 use num_traits::ops::checked::CheckedSub;
 
-use proto::index_server::messages::MultiRoute;
+use proto::index_server::messages::{MultiRoute, RouteCapacityRate};
 
 /// For every route in a multi route: How many credits to push through.
 pub type MultiRouteChoice = Vec<(usize, u128)>; // (route_index, credits_to_push)
@@ -36,6 +36,101 @@ fn fill_amount(amount: u128, sorted_routes: &[(Option<usize>, u128)]) -> Option<
     None
 }
 
+/// Calculate the amounts of credits required for every part of a route (separated by currency
+/// exchanges), if we want `dest_payment` credits to arrive at the destination.
+/// Returns for every part of the route (first part first) the total amount of credits sent into
+/// this part (including fees for the rest of the route), together with the capacity of this part.
+fn route_parts_amounts(route: &RouteCapacityRate, dest_payment: u128) -> Option<Vec<(u128, u128)>> {
+    // We go over the parts of the route from the last to the first:
+    let mut parts_amounts = Vec::new();
+    // Amount of credits representing `dest_payment` in the currency of the current part:
+    let mut hop_dest_payment = dest_payment;
+    // Total amount of credits required for the rest of the route:
+    let mut total = 0u128;
+    let mut opt_next_exchange_rate = None;
+
+    for (rate, capacity, opt_exchange_rate) in route
+        .exchanges
+        .iter()
+        .rev()
+        .map(|route_exchange| {
+            (
+                &route_exchange.rate,
+                route_exchange.capacity,
+                Some(&route_exchange.currency_exchange.rate),
+            )
+        })
+        .chain(std::iter::once((&route.rate, route.capacity, None)))
+    {
+        // Convert amounts into the currency of this part:
+        if let Some(next_exchange_rate) = opt_next_exchange_rate {
+            hop_dest_payment = next_exchange_rate.convert_back(hop_dest_payment)?;
+            total = next_exchange_rate.convert_back(total)?;
+        } else {
+            total = hop_dest_payment;
+        }
+        // Mediators along this part take fees:
+        total = total.checked_add(rate.calc_fee(hop_dest_payment)?)?;
+        parts_amounts.push((total, capacity));
+        opt_next_exchange_rate = opt_exchange_rate;
+    }
+
+    parts_amounts.reverse();
+    Some(parts_amounts)
+}
+
+/// Calculate the total fees required for sending `dest_payment` credits along a route.
+/// The fees are given in the currency of the first part of the route.
+pub fn route_fees(route: &RouteCapacityRate, dest_payment: u128) -> Option<u128> {
+    if route.exchanges.is_empty() {
+        return route.rate.calc_fee(dest_payment);
+    }
+    let hop_dest_payment = route
+        .exchanges
+        .iter()
+        .rev()
+        .try_fold(dest_payment, |amount, route_exchange| {
+            route_exchange.currency_exchange.rate.convert_back(amount)
+        })?;
+    let (total, _capacity) = route_parts_amounts(route, dest_payment)?[0];
+    total.checked_sub(hop_dest_payment)
+}
+
+/// Maximum amount of credits that can be paid to the destination through a route.
+/// The amount is given in the currency of the destination.
+pub fn route_max_payable(route: &RouteCapacityRate) -> u128 {
+    if route.exchanges.is_empty() {
+        return route.rate.max_payable(route.capacity);
+    }
+
+    let is_payable = |dest_payment| {
+        route_parts_amounts(route, dest_payment)
+            .map(|parts_amounts| {
+                parts_amounts
+                    .iter()
+                    .all(|(total, capacity)| total <= capacity)
+            })
+            .unwrap_or(false)
+    };
+
+    if !is_payable(0) {
+        return 0;
+    }
+
+    // Binary search for the maximal payable amount:
+    let mut low = 0u128;
+    let mut high = u128::max_value();
+    while low < high {
+        let mid = low + (high - low) / 2 + 1;
+        if is_payable(mid) {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    low
+}
+
 /// Find a safe choice for how much credits to push through each route in a MultiRoute.
 /// Returns a vector representing how many credits to push through every chosen route (if successful).
 /// For example: (5usize, 100u128) means: push 100 credits through the route that was given in
@@ -46,7 +141,7 @@ fn safe_multi_route_amounts(multi_route: &MultiRoute, amount: u128) -> Option<Mu
         let mut sorted_routes: Vec<_> = routes
             .iter()
             .enumerate()
-            .map(|(j, route)| (Some(j), route.rate.max_payable(route.capacity)))
+            .map(|(j, route)| (Some(j), route_max_payable(route)))
             .collect();
 
         // Reverse sort: (Largest is first)
@@ -106,8 +201,10 @@ mod tests {
     use super::*;
 
     use proto::crypto::PublicKey;
-    use proto::funder::messages::{FriendsRoute, Rate};
-    use proto::index_server::messages::RouteCapacityRate;
+    use std::convert::TryFrom;
+
+    use proto::funder::messages::{Currency, CurrencyExchange, ExchangeRate, FriendsRoute, Rate};
+    use proto::index_server::messages::{RouteCapacityRate, RouteExchange};
 
     /// A helper function to create a test public key
     fn pk(i: u8) -> PublicKey {
@@ -126,6 +223,7 @@ mod tests {
                 add: 1,
                 mul: 0x12345678,
             },
+            exchanges: vec![],
         });
 
         multi_route.routes.push(RouteCapacityRate {
@@ -137,6 +235,7 @@ mod tests {
                 add: 5,
                 mul: 0x00100000,
             },
+            exchanges: vec![],
         });
 
         multi_route.routes.push(RouteCapacityRate {
@@ -148,6 +247,7 @@ mod tests {
                 add: 20,
                 mul: 0x20000000,
             },
+            exchanges: vec![],
         });
         assert!(safe_multi_route_amounts(&multi_route, 601).is_none());
 
@@ -172,7 +272,57 @@ mod tests {
             },
             capacity: 10u128,
             rate: Rate { add: 0, mul: 0 },
+            exchanges: vec![],
         });
         assert!(safe_multi_route_amounts(&multi_route, 10u128).is_some());
     }
+
+    #[test]
+    fn test_route_exchange_fees() {
+        let currency1 = Currency::try_from("FST1".to_owned()).unwrap();
+        let currency2 = Currency::try_from("FST2".to_owned()).unwrap();
+
+        // pk(0) -- pk(1) -- pk(2) -- pk(3) -- pk(4)
+        // pk(2) exchanges every unit of currency1 into 2 units of currency2.
+        let route = RouteCapacityRate {
+            route: FriendsRoute {
+                public_keys: vec![pk(0), pk(1), pk(2), pk(3), pk(4)],
+            },
+            capacity: 100u128,
+            rate: Rate { add: 1, mul: 0 },
+            exchanges: vec![RouteExchange {
+                currency_exchange: CurrencyExchange {
+                    public_key: pk(2),
+                    src_currency: currency1,
+                    dest_currency: currency2,
+                    rate: ExchangeRate {
+                        numerator: 2,
+                        denominator: 1,
+                    },
+                },
+                capacity: 150u128,
+                rate: Rate { add: 4, mul: 0 },
+            }],
+        };
+
+        // Paying 40 units of currency2 requires 44 units of currency2 after the exchange,
+        // 22 units of currency1 before the exchange, and 23 units of currency1 at the source:
+        assert_eq!(route_fees(&route, 40), Some(3));
+
+        // Part after the exchange: x + 4 <= 150 => x <= 146
+        // Part before the exchange: (x + 4) / 2 + 1 <= 100 => x <= 194
+        assert_eq!(route_max_payable(&route), 146);
+
+        // A route without exchanges behaves as before:
+        let route = RouteCapacityRate {
+            route: FriendsRoute {
+                public_keys: vec![pk(0), pk(1), pk(2)],
+            },
+            capacity: 100u128,
+            rate: Rate { add: 1, mul: 0 },
+            exchanges: vec![],
+        };
+        assert_eq!(route_fees(&route, 40), Some(1));
+        assert_eq!(route_max_payable(&route), 99);
+    }
 }
@@ -15,8 +15,10 @@ extern crate common;
 #[macro_use]
 extern crate log;
 
+mod resumption;
 mod secure_channel;
 mod state;
 mod types;
 
 pub use self::secure_channel::SecureChannel;
+pub use self::types::PaddingConfig;
@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crypto::hash::sha_512_256;
+use crypto::sym_encrypt::SymmetricKey;
+
+use proto::crypto::{HashResult, PublicKey};
+
+const TICKET_SECRET_PREFIX: &[u8] = b"SECURE_CHANNEL_TICKET_SECRET";
+const TICKET_ID_PREFIX: &[u8] = b"SECURE_CHANNEL_TICKET_ID";
+const RESUME_KEY_PREFIX: &[u8] = b"SECURE_CHANNEL_RESUME_KEY";
+
+/// A secret shared with the remote side of a previous session.
+/// Allows both sides to derive new symmetric keys without a Diffie-Hellman exchange.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResumptionTicket {
+    /// Public identifier of the ticket. Sent in plain text to the remote side.
+    /// Anyone observing the traffic may use it to link a resumed session to the previous session.
+    pub ticket_id: HashResult,
+    secret: HashResult,
+}
+
+impl ResumptionTicket {
+    /// Derive a resumption ticket from the symmetric keys of a session.
+    /// Both sides of the session derive the same ticket.
+    pub fn from_session_keys(send_key: &SymmetricKey, recv_key: &SymmetricKey) -> Self {
+        // Order the keys, so that both sides will get the same secret:
+        let (key_a, key_b) = if send_key < recv_key {
+            (send_key, recv_key)
+        } else {
+            (recv_key, send_key)
+        };
+
+        let mut secret_buffer = Vec::new();
+        secret_buffer.extend_from_slice(TICKET_SECRET_PREFIX);
+        secret_buffer.extend_from_slice(key_a);
+        secret_buffer.extend_from_slice(key_b);
+        let secret = sha_512_256(&secret_buffer);
+
+        let mut id_buffer = Vec::new();
+        id_buffer.extend_from_slice(TICKET_ID_PREFIX);
+        id_buffer.extend_from_slice(&secret);
+        let ticket_id = sha_512_256(&id_buffer);
+
+        ResumptionTicket { ticket_id, secret }
+    }
+
+    fn derive_key(&self, transcript_hash: &HashResult) -> SymmetricKey {
+        let mut key_buffer = Vec::new();
+        key_buffer.extend_from_slice(RESUME_KEY_PREFIX);
+        key_buffer.extend_from_slice(&self.secret);
+        key_buffer.extend_from_slice(transcript_hash);
+        SymmetricKey::from(sha_512_256(&key_buffer).as_array_ref())
+    }
+
+    /// Derive fresh symmetric keys (send_key, recv_key) for a resumed session.
+    /// Every direction gets its own key, bound to the handshake transcript hash of the sending side.
+    /// A resumed session has no signatures, so a modified handshake message results in
+    /// mismatching keys.
+    pub fn derive_symmetric_keys(
+        &self,
+        local_transcript_hash: &HashResult,
+        remote_transcript_hash: &HashResult,
+    ) -> (SymmetricKey, SymmetricKey) {
+        (
+            self.derive_key(local_transcript_hash),
+            self.derive_key(remote_transcript_hash),
+        )
+    }
+}
+
+/// Resumption tickets of remote sides, shared between all clones of a secure channel transform.
+/// Every ticket can be taken only once, to prevent replay of a resumed handshake.
+#[derive(Clone)]
+pub struct TicketStore {
+    tickets: Arc<Mutex<HashMap<PublicKey, ResumptionTicket>>>,
+    max_tickets: usize,
+}
+
+impl TicketStore {
+    /// A store with `max_tickets == 0` never keeps tickets, disabling resumption.
+    pub fn new(max_tickets: usize) -> Self {
+        TicketStore {
+            tickets: Arc::new(Mutex::new(HashMap::new())),
+            max_tickets,
+        }
+    }
+
+    /// Take the resumption ticket of a remote side, removing it from the store.
+    pub fn take(&self, remote_public_key: &PublicKey) -> Option<ResumptionTicket> {
+        self.tickets.lock().unwrap().remove(remote_public_key)
+    }
+
+    /// Save the resumption ticket of a remote side, replacing any previous ticket.
+    /// If the store is full, an arbitrary ticket is evicted.
+    pub fn insert(&self, remote_public_key: PublicKey, ticket: ResumptionTicket) {
+        if self.max_tickets == 0 {
+            return;
+        }
+        let mut tickets = self.tickets.lock().unwrap();
+        if !tickets.contains_key(&remote_public_key) && tickets.len() >= self.max_tickets {
+            if let Some(evicted_public_key) = tickets.keys().next().cloned() {
+                tickets.remove(&evicted_public_key);
+            }
+        }
+        tickets.insert(remote_public_key, ticket);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ticket_from_session_keys() {
+        let key1 = SymmetricKey::from(&[1; SymmetricKey::len()]);
+        let key2 = SymmetricKey::from(&[2; SymmetricKey::len()]);
+
+        // Both sides derive the same ticket:
+        let ticket1 = ResumptionTicket::from_session_keys(&key1, &key2);
+        let ticket2 = ResumptionTicket::from_session_keys(&key2, &key1);
+        assert_eq!(ticket1, ticket2);
+
+        // The ticket is not one of the session keys:
+        assert_ne!(ticket1.secret.as_ref(), key1.as_ref());
+        assert_ne!(ticket1.secret.as_ref(), key2.as_ref());
+        assert_ne!(ticket1.ticket_id, ticket1.secret);
+
+        let transcript_hash1 = HashResult::from(&[3; HashResult::len()]);
+        let transcript_hash2 = HashResult::from(&[4; HashResult::len()]);
+        let (send_key1, recv_key1) =
+            ticket1.derive_symmetric_keys(&transcript_hash1, &transcript_hash2);
+        let (send_key2, recv_key2) =
+            ticket2.derive_symmetric_keys(&transcript_hash2, &transcript_hash1);
+        assert_eq!(send_key1, recv_key2);
+        assert_eq!(send_key2, recv_key1);
+
+        // Every direction uses a different key, and no key of the previous session is reused:
+        assert_ne!(send_key1, recv_key1);
+        for key in &[&send_key1, &recv_key1] {
+            assert_ne!(*key, &key1);
+            assert_ne!(*key, &key2);
+        }
+    }
+
+    #[test]
+    fn test_ticket_store_take_once() {
+        let ticket_store = TicketStore::new(2);
+        let pk_a = PublicKey::from(&[0xaa; PublicKey::len()]);
+        let pk_b = PublicKey::from(&[0xbb; PublicKey::len()]);
+        let pk_c = PublicKey::from(&[0xcc; PublicKey::len()]);
+
+        let key1 = SymmetricKey::from(&[1; SymmetricKey::len()]);
+        let key2 = SymmetricKey::from(&[2; SymmetricKey::len()]);
+        let ticket = ResumptionTicket::from_session_keys(&key1, &key2);
+
+        ticket_store.insert(pk_a.clone(), ticket.clone());
+        assert_eq!(ticket_store.take(&pk_a), Some(ticket.clone()));
+        // A ticket can not be used twice:
+        assert_eq!(ticket_store.take(&pk_a), None);
+
+        // The store never holds more than max_tickets tickets:
+        ticket_store.insert(pk_a.clone(), ticket.clone());
+        ticket_store.insert(pk_b.clone(), ticket.clone());
+        ticket_store.insert(pk_c.clone(), ticket.clone());
+        let num_tickets = vec![pk_a, pk_b, pk_c]
+            .iter()
+            .filter_map(|public_key| ticket_store.take(public_key))
+            .count();
+        assert_eq!(num_tickets, 2);
+    }
+
+    #[test]
+    fn test_ticket_store_disabled() {
+        let ticket_store = TicketStore::new(0);
+        let pk_a = PublicKey::from(&[0xaa; PublicKey::len()]);
+
+        let key1 = SymmetricKey::from(&[1; SymmetricKey::len()]);
+        let key2 = SymmetricKey::from(&[2; SymmetricKey::len()]);
+        let ticket = ResumptionTicket::from_session_keys(&key1, &key2);
+
+        // Nothing is kept, so no ticket is ever offered:
+        ticket_store.insert(pk_a.clone(), ticket);
+        assert_eq!(ticket_store.take(&pk_a), None);
+    }
+}
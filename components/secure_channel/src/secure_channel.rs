@@ -18,8 +18,12 @@ use proto::crypto::PublicKey;
 use proto::proto_ser::{ProtoDeserialize, ProtoSerialize, ProtoSerializeError};
 use proto::secure_channel::messages::{ExchangeDh, ExchangeRandNonce};
 
-use crate::state::{ScState, ScStateError, ScStateInitial};
-use crate::types::{EncryptedData, PlainData};
+use crate::resumption::TicketStore;
+use crate::state::{HandleRandNonceOutput, ScState, ScStateError, ScStateInitial};
+use crate::types::{EncryptedData, PaddingConfig, PlainData};
+
+/// Maximum amount of remote sides we keep resumption tickets for.
+const MAX_RESUMPTION_TICKETS: usize = 0x400;
 
 #[derive(Debug, From)]
 enum SecureChannelError {
@@ -40,6 +44,9 @@ async fn initial_exchange<EK, M: 'static, K: 'static, R: CryptoRandom + 'static>
     mut reader: M,
     identity_client: IdentityClient,
     opt_expected_remote: Option<PublicKey>,
+    ticket_store: &TicketStore,
+    pad_messages: bool,
+    mux_streams: bool,
     mut rng: R,
 ) -> Result<(ScState, K, M), SecureChannelError>
 where
@@ -52,8 +59,20 @@ where
         .await
         .map_err(|_| SecureChannelError::IdentityFailure)?;
 
-    let (dh_state_initial, exchange_rand_nonce) =
-        ScStateInitial::new(local_public_key, opt_expected_remote.clone(), &mut rng);
+    // We can only offer a resumption ticket if we know who the remote side is.
+    // A ticket is taken out of the store once offered, so that it will never be used twice.
+    let opt_resumption_ticket = opt_expected_remote
+        .as_ref()
+        .and_then(|expected_remote| ticket_store.take(expected_remote));
+
+    let (dh_state_initial, exchange_rand_nonce) = ScStateInitial::new(
+        local_public_key,
+        opt_expected_remote.clone(),
+        opt_resumption_ticket,
+        pad_messages,
+        mux_streams,
+        &mut rng,
+    );
     let ser_exchange_rand_nonce = exchange_rand_nonce.proto_serialize();
     writer
         .send(ser_exchange_rand_nonce)
@@ -66,10 +85,14 @@ where
         .ok_or(SecureChannelError::ReaderClosed)?;
 
     let exchange_rand_nonce = ExchangeRandNonce::proto_deserialize(&reader_message)?;
-    let (dh_state_half, exchange_dh) = dh_state_initial
+    let (dh_state_half, exchange_dh) = match dh_state_initial
         .handle_exchange_rand_nonce(exchange_rand_nonce, identity_client.clone(), rng.clone())
         .await
-        .map_err(SecureChannelError::HandleExchangeRandNonceError)?;
+        .map_err(SecureChannelError::HandleExchangeRandNonceError)?
+    {
+        HandleRandNonceOutput::Resumed(dh_state) => return Ok((dh_state, writer, reader)),
+        HandleRandNonceOutput::ExchangeDh(output) => output,
+    };
 
     if let Some(expected_remote) = opt_expected_remote {
         if expected_remote != dh_state_half.remote_public_key {
@@ -111,6 +134,7 @@ async fn secure_channel_loop<EK, M: 'static, K: 'static, R: CryptoRandom + 'stat
     mut to_user: mpsc::Sender<Vec<u8>>,
     mut rng: R,
     ticks_to_rekey: usize,
+    opt_cover_ticks: Option<usize>,
     mut timer_client: TimerClient,
 ) -> Result<(), SecureChannelError>
 where
@@ -143,6 +167,13 @@ where
         )));
 
     let mut cur_ticks_to_rekey = ticks_to_rekey;
+    // Cover traffic is only sent if both sides agreed on padding:
+    let opt_cover_ticks = if dh_state.is_padded() {
+        opt_cover_ticks
+    } else {
+        None
+    };
+    let mut cur_ticks_to_cover = opt_cover_ticks.unwrap_or(0);
     let mut events = select_streams![reader, from_user, timer_stream];
 
     while let Some(event) = events.next().await {
@@ -175,6 +206,18 @@ where
                     .map_err(|_| SecureChannelError::WriterError)?;
             }
             SecureChannelEvent::TimerTick => {
+                if let Some(cover_ticks) = opt_cover_ticks {
+                    cur_ticks_to_cover = cur_ticks_to_cover.saturating_sub(1);
+                    if cur_ticks_to_cover == 0 {
+                        if let Some(enc_data) = dh_state.create_cover(&mut rng) {
+                            writer
+                                .send(enc_data.0)
+                                .await
+                                .map_err(|_| SecureChannelError::WriterError)?;
+                        }
+                        cur_ticks_to_cover = cover_ticks;
+                    }
+                }
                 if let Some(new_cur_ticks_to_rekey) = cur_ticks_to_rekey.checked_sub(1) {
                     cur_ticks_to_rekey = new_cur_ticks_to_rekey;
                     continue;
@@ -206,18 +249,31 @@ where
 /// opt_expected_remote is the expected identity of the remote side. `None` means that any remote
 /// identity is permitted. `Some(public_key)` means that only the identity `public_key` is allowed.
 ///
+/// `ticket_store` holds resumption tickets from previous sessions. If both sides expect each other
+/// and hold the same ticket, the session is resumed without a Diffie-Hellman exchange.
+/// An empty store with no capacity disables resumption.
+///
 /// `ticks_to_rekey` is the amount of time ticks it takes to issue a rekey, changing the symmetric
 /// key used for the encryption.
+///
+/// `opt_padding_config` asks for padding of all messages to fixed size buckets, possibly together
+/// with cover traffic. Remote sides that do not ask for padding are still able to connect.
+///
+/// `mux_streams` declares that we support multiplexing streams over the channel. The returned flag
+/// tells whether the remote side supports it too.
 async fn create_secure_channel<EK, M, K, R, S>(
     writer: K,
     reader: M,
     identity_client: IdentityClient,
     opt_expected_remote: Option<PublicKey>,
+    ticket_store: TicketStore,
     rng: R,
     timer_client: TimerClient,
     ticks_to_rekey: usize,
+    opt_padding_config: Option<PaddingConfig>,
+    mux_streams: bool,
     spawner: S,
-) -> Result<(PublicKey, ConnPairVec), SecureChannelError>
+) -> Result<(PublicKey, bool, ConnPairVec), SecureChannelError>
 where
     EK: 'static,
     M: Stream<Item = Vec<u8>> + Unpin + Send + 'static,
@@ -230,11 +286,19 @@ where
         reader,
         identity_client,
         opt_expected_remote,
+        &ticket_store,
+        opt_padding_config.is_some(),
+        mux_streams,
         rng.clone(),
     )
     .await?;
 
     let remote_public_key = dh_state.get_remote_public_key().clone();
+    let multiplexed = dh_state.is_multiplexed();
+    ticket_store.insert(
+        remote_public_key.clone(),
+        dh_state.get_resumption_ticket().clone(),
+    );
 
     let (user_sender, from_user) = mpsc::channel::<Vec<u8>>(1);
     let (to_user, user_receiver) = mpsc::channel::<Vec<u8>>(1);
@@ -247,6 +311,7 @@ where
         to_user,
         rng.clone(),
         ticks_to_rekey,
+        opt_padding_config.and_then(|padding_config| padding_config.opt_cover_ticks),
         timer_client,
     );
 
@@ -261,6 +326,7 @@ where
 
     Ok((
         remote_public_key,
+        multiplexed,
         ConnPairVec::from_raw(user_sender, user_receiver),
     ))
 }
@@ -268,25 +334,45 @@ where
 #[derive(Clone)]
 pub struct SecureChannel<R, S> {
     identity_client: IdentityClient,
+    ticket_store: TicketStore,
     rng: R,
     timer_client: TimerClient,
     ticks_to_rekey: usize,
+    opt_padding_config: Option<PaddingConfig>,
+    mux_streams: bool,
     spawner: S,
 }
 
 impl<R, S> SecureChannel<R, S> {
+    /// `resume_sessions` enables session resumption: Tickets from previous sessions are kept, and
+    /// offered when reconnecting to the same remote side. Ticket ids are sent in plain text, so an
+    /// observer of the traffic can link a resumed session to the session it resumes.
+    ///
+    /// `mux_streams` declares to the remote side that we support multiplexing streams over the
+    /// channel.
     pub fn new(
         identity_client: IdentityClient,
         rng: R,
         timer_client: TimerClient,
         ticks_to_rekey: usize,
+        opt_padding_config: Option<PaddingConfig>,
+        resume_sessions: bool,
+        mux_streams: bool,
         spawner: S,
     ) -> SecureChannel<R, S> {
+        let max_tickets = if resume_sessions {
+            MAX_RESUMPTION_TICKETS
+        } else {
+            0
+        };
         SecureChannel {
             identity_client,
+            ticket_store: TicketStore::new(max_tickets),
             rng,
             timer_client,
             ticks_to_rekey,
+            opt_padding_config,
+            mux_streams,
             spawner,
         }
     }
@@ -304,13 +390,14 @@ where
     /// Output:
     /// - Public key of remote side (Must match the expected public key of remote side if
     /// specified).
+    /// - Both sides support multiplexing streams over the channel.
     /// - (sender, receiver) for the resulting encrypted channel.
-    type Output = Option<(PublicKey, ConnPairVec)>;
+    type Output = Option<(PublicKey, bool, ConnPairVec)>;
 
     fn transform(
         &mut self,
         input: (Option<PublicKey>, ConnPairVec),
-    ) -> BoxFuture<'_, Option<(PublicKey, ConnPairVec)>> {
+    ) -> BoxFuture<'_, Option<(PublicKey, bool, ConnPairVec)>> {
         let (opt_expected_remote, conn_pair) = input;
         let (sender, receiver) = conn_pair.split();
 
@@ -322,9 +409,12 @@ where
                 receiver,
                 self.identity_client.clone(),
                 opt_expected_remote.clone(),
+                self.ticket_store.clone(),
                 self.rng.clone(),
                 self.timer_client.clone(),
                 self.ticks_to_rekey,
+                self.opt_padding_config.clone(),
+                self.mux_streams,
                 c_spawner,
             )
             .await
@@ -354,12 +444,14 @@ mod tests {
     use common::test_executor::TestExecutor;
 
     async fn secure_channel1(
-        fut_sc: impl Future<Output = Result<(PublicKey, ConnPairVec), SecureChannelError>> + 'static,
+        fut_sc: impl Future<Output = Result<(PublicKey, bool, ConnPairVec), SecureChannelError>>
+            + 'static,
         mut tick_sender: mpsc::Sender<()>,
         output_sender: oneshot::Sender<bool>,
         test_executor: TestExecutor,
     ) {
-        let (_public_key, conn_pair_vec) = fut_sc.await.unwrap();
+        let (_public_key, multiplexed, conn_pair_vec) = fut_sc.await.unwrap();
+        assert!(!multiplexed);
         let (mut sender, mut receiver) = conn_pair_vec.split();
         sender.send(vec![0, 1, 2, 3, 4, 5]).await.unwrap();
         let data = receiver.next().await.unwrap();
@@ -376,11 +468,13 @@ mod tests {
     }
 
     async fn secure_channel2(
-        fut_sc: impl Future<Output = Result<(PublicKey, ConnPairVec), SecureChannelError>> + 'static,
+        fut_sc: impl Future<Output = Result<(PublicKey, bool, ConnPairVec), SecureChannelError>>
+            + 'static,
         _tick_sender: mpsc::Sender<()>,
         output_sender: oneshot::Sender<bool>,
     ) {
-        let (_public_key, conn_pair_vec) = fut_sc.await.unwrap();
+        let (_public_key, multiplexed, conn_pair_vec) = fut_sc.await.unwrap();
+        assert!(!multiplexed);
         let (mut sender, mut receiver) = conn_pair_vec.split();
         let data = receiver.next().await.unwrap();
         assert_eq!(data, vec![0, 1, 2, 3, 4, 5]);
@@ -432,9 +526,12 @@ mod tests {
             receiver1,
             identity_client1,
             Some(public_key2),
+            TicketStore::new(MAX_RESUMPTION_TICKETS),
             rng1.clone(),
             timer_client.clone(),
             ticks_to_rekey,
+            None,
+            false,
             test_executor.clone(),
         );
 
@@ -443,9 +540,13 @@ mod tests {
             receiver2,
             identity_client2,
             Some(public_key1),
+            TicketStore::new(MAX_RESUMPTION_TICKETS),
             rng2.clone(),
             timer_client.clone(),
             ticks_to_rekey,
+            None,
+            // The other side does not support multiplexing, so it is not used:
+            true,
             test_executor.clone(),
         );
 
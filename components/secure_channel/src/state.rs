@@ -5,11 +5,12 @@ use byteorder::{BigEndian, ByteOrder};
 use derive_more::From;
 
 use crypto::dh::DhPrivateKey;
+use crypto::hash::sha_512_256;
 use crypto::identity::verify_signature;
 use crypto::rand::{CryptoRandom, RandGen};
-use crypto::sym_encrypt::{Decryptor, Encryptor};
+use crypto::sym_encrypt::{Decryptor, Encryptor, SymmetricKey};
 
-use proto::crypto::{PublicKey, RandValue, Salt, Signature};
+use proto::crypto::{HashResult, PublicKey, RandValue, Salt, Signature};
 use proto::proto_ser::{ProtoDeserialize, ProtoSerialize, ProtoSerializeError};
 
 use identity::{IdentityClient, IdentityClientError};
@@ -17,10 +18,17 @@ use proto::secure_channel::messages::{
     ChannelContent, ChannelMessage, ExchangeDh, ExchangeRandNonce, Rekey,
 };
 
+use crate::resumption::ResumptionTicket;
 use crate::types::{EncryptedData, PlainData};
 
 const MAX_RAND_PADDING: u16 = 0x100;
 
+const TRANSCRIPT_PREFIX: &[u8] = b"SECURE_CHANNEL_TRANSCRIPT";
+
+/// Sizes of serialized channel messages when padding is used.
+/// Messages larger than the largest bucket are padded to a multiple of the largest bucket.
+const PADDING_BUCKETS: &[usize] = &[0x100, 0x400, 0x1000, 0x4000];
+
 #[derive(Debug, From)]
 pub enum ScStateError {
     UnexpectedRemotePublicKey,
@@ -42,6 +50,11 @@ pub struct ScStateInitial {
     local_public_key: PublicKey,
     opt_remote_public_key: Option<PublicKey>,
     local_rand_nonce: RandValue,
+    opt_resumption_ticket: Option<ResumptionTicket>,
+    pad_messages: bool,
+    mux_streams: bool,
+    /// The ExchangeRandNonce message we have sent
+    local_exchange_rand_nonce: ExchangeRandNonce,
 }
 
 pub struct ScStateHalf {
@@ -50,6 +63,12 @@ pub struct ScStateHalf {
     local_rand_nonce: RandValue,
     dh_private_key: DhPrivateKey,
     local_salt: Salt,
+    /// Both sides asked for padding
+    padding: bool,
+    /// Both sides support multiplexing streams
+    multiplexed: bool,
+    /// Transcript hash, as signed by the remote side
+    remote_transcript_hash: HashResult,
 }
 
 struct PendingRekey {
@@ -68,35 +87,104 @@ pub struct ScState {
     /// messages for the new receiver.
     opt_old_receiver: Option<Decryptor>,
     opt_pending_rekey: Option<PendingRekey>,
+    /// A ticket that allows to resume this session later, without a Diffie-Hellman exchange.
+    resumption_ticket: ResumptionTicket,
+    /// Pad all outgoing messages to fixed size buckets
+    padding: bool,
+    /// Both sides support multiplexing streams over the channel
+    multiplexed: bool,
+}
+
+pub enum HandleRandNonceOutput {
+    /// Both sides offered the same resumption ticket. New symmetric keys were derived from the
+    /// ticket, and no further handshake messages are required.
+    Resumed(ScState),
+    /// Continue with a full Diffie-Hellman handshake.
+    ExchangeDh((ScStateHalf, ExchangeDh)),
 }
 
 impl ScStateInitial {
+    /// `opt_resumption_ticket` is a ticket from a previous session with the remote side.
+    /// It is offered to the remote side, and used only if the remote side offers the same ticket.
+    ///
+    /// `pad_messages` asks for padding of all messages. Padding is used only if the remote side
+    /// asks for it too, so that we can still communicate with remote sides that do not ask for it.
+    /// The flags of both sides are part of the signed handshake transcript.
+    ///
+    /// `mux_streams` declares that we support multiplexing streams over the channel. Like padding,
+    /// it is used only if the remote side supports it too.
     pub fn new<R: CryptoRandom>(
         local_public_key: PublicKey,
         opt_remote_public_key: Option<PublicKey>,
+        opt_resumption_ticket: Option<ResumptionTicket>,
+        pad_messages: bool,
+        mux_streams: bool,
         rng: &mut R,
     ) -> (ScStateInitial, ExchangeRandNonce) {
         let local_rand_nonce = RandValue::rand_gen(rng);
 
-        let sc_state_initial = ScStateInitial {
-            local_public_key: local_public_key.clone(),
-            opt_remote_public_key: opt_remote_public_key.clone(),
-            local_rand_nonce: local_rand_nonce.clone(),
-        };
+        let opt_resume_ticket_id = opt_resumption_ticket
+            .as_ref()
+            .map(|resumption_ticket| resumption_ticket.ticket_id.clone());
+
         let exchange_rand_nonce = ExchangeRandNonce {
-            rand_nonce: local_rand_nonce,
-            src_public_key: local_public_key,
-            opt_dest_public_key: opt_remote_public_key,
+            rand_nonce: local_rand_nonce.clone(),
+            src_public_key: local_public_key.clone(),
+            opt_dest_public_key: opt_remote_public_key.clone(),
+            opt_resume_ticket_id,
+            pad_messages,
+            mux_streams,
+        };
+
+        let sc_state_initial = ScStateInitial {
+            local_public_key,
+            opt_remote_public_key,
+            local_rand_nonce,
+            opt_resumption_ticket,
+            pad_messages,
+            mux_streams,
+            local_exchange_rand_nonce: exchange_rand_nonce.clone(),
         };
         (sc_state_initial, exchange_rand_nonce)
     }
 
+    /// Resume the session if both sides offered the same resumption ticket.
+    fn try_resume(
+        &mut self,
+        exchange_rand_nonce: &ExchangeRandNonce,
+    ) -> Result<Option<ScState>, ScStateError> {
+        let resumption_ticket = match self.opt_resumption_ticket.take() {
+            Some(resumption_ticket) => resumption_ticket,
+            None => return Ok(None),
+        };
+        if exchange_rand_nonce.opt_resume_ticket_id.as_ref() != Some(&resumption_ticket.ticket_id) {
+            return Ok(None);
+        }
+        // A reflected rand nonce would result in equal send and receive keys:
+        if exchange_rand_nonce.rand_nonce == self.local_rand_nonce {
+            return Ok(None);
+        }
+
+        let (send_key, recv_key) = resumption_ticket.derive_symmetric_keys(
+            &transcript_hash(&self.local_exchange_rand_nonce, exchange_rand_nonce),
+            &transcript_hash(exchange_rand_nonce, &self.local_exchange_rand_nonce),
+        );
+        Ok(Some(ScState::new(
+            self.local_public_key.clone(),
+            exchange_rand_nonce.src_public_key.clone(),
+            &send_key,
+            &recv_key,
+            self.pad_messages && exchange_rand_nonce.pad_messages,
+            self.mux_streams && exchange_rand_nonce.mux_streams,
+        )?))
+    }
+
     pub async fn handle_exchange_rand_nonce<R: CryptoRandom + 'static>(
-        self,
+        mut self,
         exchange_rand_nonce: ExchangeRandNonce,
         identity_client: IdentityClient,
         mut rng: R,
-    ) -> Result<(ScStateHalf, ExchangeDh), ScStateError> {
+    ) -> Result<HandleRandNonceOutput, ScStateError> {
         // In case we expect a specific remote public key, verify it first:
         if let Some(expected_remote_public_key) = &self.opt_remote_public_key {
             if expected_remote_public_key != &exchange_rand_nonce.src_public_key {
@@ -104,6 +192,10 @@ impl ScStateInitial {
             }
         }
 
+        if let Some(sc_state) = self.try_resume(&exchange_rand_nonce)? {
+            return Ok(HandleRandNonceOutput::Resumed(sc_state));
+        }
+
         let dh_private_key =
             DhPrivateKey::new(&mut rng).map_err(|_| ScStateError::PrivateKeyGenFailure)?;
         let dh_public_key = dh_private_key
@@ -111,12 +203,20 @@ impl ScStateInitial {
             .map_err(|_| ScStateError::DhPublicKeyComputeFailure)?;
         let local_salt = Salt::rand_gen(&mut rng);
 
+        let local_transcript_hash =
+            transcript_hash(&self.local_exchange_rand_nonce, &exchange_rand_nonce);
+        let remote_transcript_hash =
+            transcript_hash(&exchange_rand_nonce, &self.local_exchange_rand_nonce);
+
         let sc_state_half = ScStateHalf {
             remote_public_key: exchange_rand_nonce.src_public_key,
             local_public_key: self.local_public_key,
             local_rand_nonce: self.local_rand_nonce,
             dh_private_key,
             local_salt: local_salt.clone(),
+            padding: self.pad_messages && exchange_rand_nonce.pad_messages,
+            multiplexed: self.mux_streams && exchange_rand_nonce.mux_streams,
+            remote_transcript_hash,
         };
 
         let mut exchange_dh = ExchangeDh {
@@ -126,10 +226,13 @@ impl ScStateInitial {
             signature: Signature::default(),
         };
         exchange_dh.signature = identity_client
-            .request_signature(exchange_dh.signature_buffer())
+            .request_signature(exchange_dh.signature_buffer(&local_transcript_hash))
             .await?;
 
-        Ok((sc_state_half, exchange_dh))
+        Ok(HandleRandNonceOutput::ExchangeDh((
+            sc_state_half,
+            exchange_dh,
+        )))
     }
 }
 
@@ -141,7 +244,7 @@ impl ScStateHalf {
             return Err(ScStateError::IncorrectRandNonce);
         }
         // Verify signature:
-        let sbuffer = exchange_dh.signature_buffer();
+        let sbuffer = exchange_dh.signature_buffer(&self.remote_transcript_hash);
         if !verify_signature(&sbuffer, &self.remote_public_key, &exchange_dh.signature) {
             return Err(ScStateError::InvalidSignature);
         }
@@ -160,15 +263,14 @@ impl ScStateHalf {
             )
             .map_err(|_| ScStateError::KeyDerivationFailure)?;
 
-        Ok(ScState {
-            local_public_key: self.local_public_key,
-            remote_public_key: self.remote_public_key,
-            sender: Encryptor::new(&send_key).map_err(|_| ScStateError::CreateEncryptorFailure)?,
-            receiver: Decryptor::new(&recv_key)
-                .map_err(|_| ScStateError::CreateDecryptorFailure)?,
-            opt_old_receiver: None,
-            opt_pending_rekey: None,
-        })
+        ScState::new(
+            self.local_public_key,
+            self.remote_public_key,
+            &send_key,
+            &recv_key,
+            self.padding,
+            self.multiplexed,
+        )
     }
 }
 
@@ -179,16 +281,44 @@ pub struct HandleIncomingOutput {
 }
 
 impl ScState {
+    fn new(
+        local_public_key: PublicKey,
+        remote_public_key: PublicKey,
+        send_key: &SymmetricKey,
+        recv_key: &SymmetricKey,
+        padding: bool,
+        multiplexed: bool,
+    ) -> Result<ScState, ScStateError> {
+        Ok(ScState {
+            local_public_key,
+            remote_public_key,
+            sender: Encryptor::new(send_key).map_err(|_| ScStateError::CreateEncryptorFailure)?,
+            receiver: Decryptor::new(recv_key).map_err(|_| ScStateError::CreateDecryptorFailure)?,
+            opt_old_receiver: None,
+            opt_pending_rekey: None,
+            resumption_ticket: ResumptionTicket::from_session_keys(send_key, recv_key),
+            padding,
+            multiplexed,
+        })
+    }
+
     fn encrypt_outgoing<R: CryptoRandom>(
         &mut self,
         channel_content: ChannelContent,
         rng: &mut R,
     ) -> EncryptedData {
-        let channel_message = ChannelMessage {
-            rand_padding: self.gen_rand_padding(rng),
-            content: channel_content,
+        let ser_channel_message = if self.padding {
+            serialize_padded(ChannelMessage {
+                rand_padding: Vec::new(),
+                content: channel_content,
+            })
+        } else {
+            ChannelMessage {
+                rand_padding: self.gen_rand_padding(rng),
+                content: channel_content,
+            }
+            .proto_serialize()
         };
-        let ser_channel_message = channel_message.proto_serialize();
         let enc_channel_message = self.sender.encrypt(&ser_channel_message).unwrap();
         EncryptedData(enc_channel_message)
     }
@@ -249,6 +379,16 @@ impl ScState {
         rand_padding
     }
 
+    /// Create an encrypted cover message, if padding is used.
+    /// Cover messages are discarded by the remote side.
+    pub fn create_cover<R: CryptoRandom>(&mut self, rng: &mut R) -> Option<EncryptedData> {
+        if !self.padding {
+            // The remote side might not understand cover messages:
+            return None;
+        }
+        Some(self.encrypt_outgoing(ChannelContent::Cover, rng))
+    }
+
     /// Initiate rekeying. Outputs an encrypted message to send to remote side.
     pub fn create_rekey<R: CryptoRandom>(
         &mut self,
@@ -345,6 +485,11 @@ impl ScState {
                 opt_send_message: None,
                 opt_incoming_message: Some(PlainData(content)),
             }),
+            ChannelContent::Cover => Ok(HandleIncomingOutput {
+                rekey_occurred: false,
+                opt_send_message: None,
+                opt_incoming_message: None,
+            }),
         }
     }
 
@@ -352,6 +497,63 @@ impl ScState {
     pub fn get_remote_public_key(&self) -> &PublicKey {
         &self.remote_public_key
     }
+
+    /// Are all outgoing messages padded to fixed size buckets?
+    pub fn is_padded(&self) -> bool {
+        self.padding
+    }
+
+    /// Do both sides support multiplexing streams over the channel?
+    pub fn is_multiplexed(&self) -> bool {
+        self.multiplexed
+    }
+
+    /// Get a ticket that allows to resume this session later
+    pub fn get_resumption_ticket(&self) -> &ResumptionTicket {
+        &self.resumption_ticket
+    }
+}
+
+/// Hash of the ExchangeRandNonce messages of both sides of a handshake.
+/// `sender_exchange_rand_nonce` was sent by the side that uses the hash (for signing or for key
+/// derivation), and `receiver_exchange_rand_nonce` was sent by the other side.
+fn transcript_hash(
+    sender_exchange_rand_nonce: &ExchangeRandNonce,
+    receiver_exchange_rand_nonce: &ExchangeRandNonce,
+) -> HashResult {
+    let mut tbuffer = Vec::new();
+    tbuffer.extend_from_slice(TRANSCRIPT_PREFIX);
+    tbuffer.extend_from_slice(&sender_exchange_rand_nonce.transcript_buffer());
+    tbuffer.extend_from_slice(&receiver_exchange_rand_nonce.transcript_buffer());
+    sha_512_256(&tbuffer)
+}
+
+/// Get the size a serialized message of length `len` is padded to
+fn padded_len(len: usize) -> usize {
+    for &bucket in PADDING_BUCKETS {
+        if len <= bucket {
+            return bucket;
+        }
+    }
+    let max_bucket = PADDING_BUCKETS[PADDING_BUCKETS.len() - 1];
+    ((len + max_bucket - 1) / max_bucket) * max_bucket
+}
+
+/// Serialize a channel message, padding it to the size of a bucket.
+fn serialize_padded(mut channel_message: ChannelMessage) -> Vec<u8> {
+    let mut ser_channel_message = channel_message.proto_serialize();
+    loop {
+        let target_len = padded_len(ser_channel_message.len());
+        if ser_channel_message.len() == target_len {
+            return ser_channel_message;
+        }
+        // The serialized message usually grows together with the padding, up to word alignment.
+        // If the serialization overhead grows too, we will try again with the next bucket.
+        let padding_len =
+            channel_message.rand_padding.len() + (target_len - ser_channel_message.len());
+        channel_message.rand_padding = vec![0; padding_len];
+        ser_channel_message = channel_message.proto_serialize();
+    }
 }
 
 #[cfg(test)]
@@ -373,6 +575,10 @@ mod tests {
     async fn run_basic_sc_state(
         identity_client1: IdentityClient,
         identity_client2: IdentityClient,
+        pad_messages1: bool,
+        pad_messages2: bool,
+        mux_streams1: bool,
+        mux_streams2: bool,
     ) -> Result<(ScState, ScState), ()> {
         let mut rng1 = DummyRandom::new(&[1u8]);
         let mut rng2 = DummyRandom::new(&[2u8]);
@@ -380,27 +586,47 @@ mod tests {
         let local_public_key2 = identity_client2.request_public_key().await.unwrap();
         let opt_dest_public_key1 = Some(local_public_key2.clone());
         let opt_dest_public_key2 = None;
-        let (sc_state_initial1, exchange_rand_nonce1) =
-            ScStateInitial::new(local_public_key1.clone(), opt_dest_public_key1, &mut rng1);
-        let (sc_state_initial2, exchange_rand_nonce2) =
-            ScStateInitial::new(local_public_key2.clone(), opt_dest_public_key2, &mut rng2);
-
-        let (sc_state_half1, exchange_dh1) = sc_state_initial1
+        let (sc_state_initial1, exchange_rand_nonce1) = ScStateInitial::new(
+            local_public_key1.clone(),
+            opt_dest_public_key1,
+            None,
+            pad_messages1,
+            mux_streams1,
+            &mut rng1,
+        );
+        let (sc_state_initial2, exchange_rand_nonce2) = ScStateInitial::new(
+            local_public_key2.clone(),
+            opt_dest_public_key2,
+            None,
+            pad_messages2,
+            mux_streams2,
+            &mut rng2,
+        );
+
+        let (sc_state_half1, exchange_dh1) = match sc_state_initial1
             .handle_exchange_rand_nonce(
                 exchange_rand_nonce2,
                 identity_client1.clone(),
                 rng1.clone(),
             )
             .await
-            .unwrap();
-        let (sc_state_half2, exchange_dh2) = sc_state_initial2
+            .unwrap()
+        {
+            HandleRandNonceOutput::ExchangeDh(output) => output,
+            HandleRandNonceOutput::Resumed(_) => unreachable!(),
+        };
+        let (sc_state_half2, exchange_dh2) = match sc_state_initial2
             .handle_exchange_rand_nonce(
                 exchange_rand_nonce1,
                 identity_client2.clone(),
                 rng2.clone(),
             )
             .await
-            .unwrap();
+            .unwrap()
+        {
+            HandleRandNonceOutput::ExchangeDh(output) => output,
+            HandleRandNonceOutput::Resumed(_) => unreachable!(),
+        };
 
         let sc_state1 = sc_state_half1.handle_exchange_dh(exchange_dh2).unwrap();
         let sc_state2 = sc_state_half2.handle_exchange_dh(exchange_dh1).unwrap();
@@ -473,7 +699,12 @@ mod tests {
         assert_eq!(incoming_output2.opt_incoming_message, None);
     }
 
-    fn prepare_dh_test() -> (ScState, ScState, DummyRandom, DummyRandom) {
+    fn prepare_dh_test(
+        pad_messages1: bool,
+        pad_messages2: bool,
+        mux_streams1: bool,
+        mux_streams2: bool,
+    ) -> (ScState, ScState, DummyRandom, DummyRandom) {
         let mut rng1 = DummyRandom::new(&[1u8]);
         let private_key = PrivateKey::rand_gen(&mut rng1);
         let identity1 = SoftwareEd25519Identity::from_private_key(&private_key).unwrap();
@@ -496,7 +727,14 @@ mod tests {
             .unwrap();
 
         let (sc_state1, sc_state2) = LocalPool::new()
-            .run_until(run_basic_sc_state(identity_client1, identity_client2))
+            .run_until(run_basic_sc_state(
+                identity_client1,
+                identity_client2,
+                pad_messages1,
+                pad_messages2,
+                mux_streams1,
+                mux_streams2,
+            ))
             .unwrap();
 
         (sc_state1, sc_state2, rng1, rng2)
@@ -504,13 +742,524 @@ mod tests {
 
     #[test]
     fn test_basic_sc_state() {
-        let (mut sc_state1, mut sc_state2, mut rng1, mut rng2) = prepare_dh_test();
+        let (mut sc_state1, mut sc_state2, mut rng1, mut rng2) =
+            prepare_dh_test(false, false, false, false);
         send_recv_messages(&mut sc_state1, &mut sc_state2, &mut rng1, &mut rng2);
         rekey_sequential(&mut sc_state1, &mut sc_state2, &mut rng1, &mut rng2);
         send_recv_messages(&mut sc_state1, &mut sc_state2, &mut rng1, &mut rng2);
         rekey_simultaneous(&mut sc_state1, &mut sc_state2, &mut rng1, &mut rng2);
         send_recv_messages(&mut sc_state1, &mut sc_state2, &mut rng1, &mut rng2);
     }
+
+    #[test]
+    fn test_serialize_padded() {
+        for len in (0..0x9000).step_by(0x133) {
+            let channel_message = ChannelMessage {
+                rand_padding: Vec::new(),
+                content: ChannelContent::User(vec![0xaa; len]),
+            };
+            let ser_channel_message = serialize_padded(channel_message);
+            assert_eq!(
+                ser_channel_message.len(),
+                padded_len(ser_channel_message.len())
+            );
+            assert!(ser_channel_message.len() > len);
+
+            let channel_message = ChannelMessage::proto_deserialize(&ser_channel_message).unwrap();
+            assert_eq!(
+                channel_message.content,
+                ChannelContent::User(vec![0xaa; len])
+            );
+        }
+    }
+
+    #[test]
+    fn test_padded_sc_state() {
+        let (mut sc_state1, mut sc_state2, mut rng1, mut rng2) =
+            prepare_dh_test(true, true, false, false);
+        assert!(sc_state1.is_padded());
+        assert!(sc_state2.is_padded());
+
+        // Messages of different sizes are padded to the same size:
+        let mut enc_datas = Vec::new();
+        for len in &[0, 1, 0x10, 0x80] {
+            let plain_data = PlainData(vec![0xbb; *len]);
+            let enc_data = sc_state1.create_outgoing(&plain_data, &mut rng1);
+            let incoming_output = sc_state2.handle_incoming(&enc_data, &mut rng2).unwrap();
+            assert_eq!(incoming_output.opt_incoming_message, Some(plain_data));
+            enc_datas.push(enc_data);
+        }
+        let enc_len = enc_datas[0].0.len();
+        assert!(enc_datas.iter().all(|enc_data| enc_data.0.len() == enc_len));
+
+        // A larger message is padded to the next bucket:
+        let enc_data = sc_state1.create_outgoing(&PlainData(vec![0xbb; 0x200]), &mut rng1);
+        assert_eq!(
+            enc_data.0.len() - enc_len,
+            PADDING_BUCKETS[1] - PADDING_BUCKETS[0]
+        );
+        sc_state2.handle_incoming(&enc_data, &mut rng2).unwrap();
+
+        // Cover messages look like small messages, and are discarded by the remote side:
+        let cover_data = sc_state2.create_cover(&mut rng2).unwrap();
+        assert_eq!(cover_data.0.len(), enc_len);
+        let incoming_output = sc_state1.handle_incoming(&cover_data, &mut rng1).unwrap();
+        assert_eq!(incoming_output.rekey_occurred, false);
+        assert_eq!(incoming_output.opt_send_message, None);
+        assert_eq!(incoming_output.opt_incoming_message, None);
+
+        send_recv_messages(&mut sc_state1, &mut sc_state2, &mut rng1, &mut rng2);
+        rekey_sequential(&mut sc_state1, &mut sc_state2, &mut rng1, &mut rng2);
+        send_recv_messages(&mut sc_state1, &mut sc_state2, &mut rng1, &mut rng2);
+    }
+
+    #[test]
+    fn test_padding_not_supported_by_remote() {
+        let (mut sc_state1, mut sc_state2, mut rng1, mut rng2) =
+            prepare_dh_test(true, false, false, false);
+        assert!(!sc_state1.is_padded());
+        assert!(!sc_state2.is_padded());
+
+        // The remote side might not understand cover messages:
+        assert!(sc_state1.create_cover(&mut rng1).is_none());
+
+        send_recv_messages(&mut sc_state1, &mut sc_state2, &mut rng1, &mut rng2);
+    }
+
+    #[test]
+    fn test_mux_streams_sc_state() {
+        let (mut sc_state1, mut sc_state2, mut rng1, mut rng2) =
+            prepare_dh_test(false, false, true, true);
+        assert!(sc_state1.is_multiplexed());
+        assert!(sc_state2.is_multiplexed());
+        send_recv_messages(&mut sc_state1, &mut sc_state2, &mut rng1, &mut rng2);
+
+        // An older remote side does not support multiplexing:
+        let (sc_state1, sc_state2, _rng1, _rng2) = prepare_dh_test(false, false, true, false);
+        assert!(!sc_state1.is_multiplexed());
+        assert!(!sc_state2.is_multiplexed());
+    }
+
+    async fn run_resume_sc_state(
+        identity_client1: IdentityClient,
+        identity_client2: IdentityClient,
+    ) {
+        let mut rng1 = DummyRandom::new(&[3u8]);
+        let mut rng2 = DummyRandom::new(&[4u8]);
+        let public_key1 = identity_client1.request_public_key().await.unwrap();
+        let public_key2 = identity_client2.request_public_key().await.unwrap();
+
+        // A full handshake:
+        let (mut prev_sc_state1, prev_sc_state2) = run_basic_sc_state(
+            identity_client1.clone(),
+            identity_client2.clone(),
+            false,
+            false,
+            false,
+            false,
+        )
+        .await
+        .unwrap();
+
+        // Both sides derive the same resumption ticket:
+        let resumption_ticket1 = prev_sc_state1.get_resumption_ticket().clone();
+        let resumption_ticket2 = prev_sc_state2.get_resumption_ticket().clone();
+        assert_eq!(resumption_ticket1, resumption_ticket2);
+
+        // Both sides offer the ticket, and resume the session:
+        let (sc_state_initial1, exchange_rand_nonce1) = ScStateInitial::new(
+            public_key1.clone(),
+            Some(public_key2.clone()),
+            Some(resumption_ticket1.clone()),
+            false,
+            false,
+            &mut rng1,
+        );
+        let (sc_state_initial2, exchange_rand_nonce2) = ScStateInitial::new(
+            public_key2.clone(),
+            Some(public_key1.clone()),
+            Some(resumption_ticket2),
+            false,
+            false,
+            &mut rng2,
+        );
+        let recorded_exchange_rand_nonce1 = exchange_rand_nonce1.clone();
+
+        let mut sc_state1 = match sc_state_initial1
+            .handle_exchange_rand_nonce(
+                exchange_rand_nonce2,
+                identity_client1.clone(),
+                rng1.clone(),
+            )
+            .await
+            .unwrap()
+        {
+            HandleRandNonceOutput::Resumed(sc_state) => sc_state,
+            HandleRandNonceOutput::ExchangeDh(_) => unreachable!(),
+        };
+        let mut sc_state2 = match sc_state_initial2
+            .handle_exchange_rand_nonce(
+                exchange_rand_nonce1,
+                identity_client2.clone(),
+                rng2.clone(),
+            )
+            .await
+            .unwrap()
+        {
+            HandleRandNonceOutput::Resumed(sc_state) => sc_state,
+            HandleRandNonceOutput::ExchangeDh(_) => unreachable!(),
+        };
+        assert_eq!(sc_state1.get_remote_public_key(), &public_key2);
+        assert_eq!(sc_state2.get_remote_public_key(), &public_key1);
+
+        // The resumed session can not be read using the keys of the previous session:
+        let enc_data = sc_state1.create_outgoing(&PlainData(vec![1, 2, 3]), &mut rng1);
+        assert!(prev_sc_state1
+            .handle_incoming(&enc_data, &mut rng1)
+            .is_err());
+        // Every direction uses a different key:
+        assert!(sc_state1.handle_incoming(&enc_data, &mut rng1).is_err());
+        let incoming_output = sc_state2.handle_incoming(&enc_data, &mut rng2).unwrap();
+        assert_eq!(
+            incoming_output.opt_incoming_message,
+            Some(PlainData(vec![1, 2, 3]))
+        );
+
+        send_recv_messages(&mut sc_state1, &mut sc_state2, &mut rng1, &mut rng2);
+        rekey_sequential(&mut sc_state1, &mut sc_state2, &mut rng1, &mut rng2);
+        send_recv_messages(&mut sc_state1, &mut sc_state2, &mut rng1, &mut rng2);
+
+        // The resumed session has a new ticket:
+        let resumption_ticket2 = sc_state2.get_resumption_ticket().clone();
+        assert_eq!(sc_state1.get_resumption_ticket(), &resumption_ticket2);
+        assert_ne!(resumption_ticket2, resumption_ticket1);
+
+        // A replayed resumption message falls back to a full handshake:
+        let (sc_state_initial2, _exchange_rand_nonce2) = ScStateInitial::new(
+            public_key2.clone(),
+            Some(public_key1.clone()),
+            Some(resumption_ticket2),
+            false,
+            false,
+            &mut rng2,
+        );
+        match sc_state_initial2
+            .handle_exchange_rand_nonce(
+                recorded_exchange_rand_nonce1.clone(),
+                identity_client2.clone(),
+                rng2.clone(),
+            )
+            .await
+            .unwrap()
+        {
+            HandleRandNonceOutput::ExchangeDh(_) => {}
+            HandleRandNonceOutput::Resumed(_) => unreachable!(),
+        };
+
+        let (sc_state_initial2, _exchange_rand_nonce2) = ScStateInitial::new(
+            public_key2.clone(),
+            Some(public_key1.clone()),
+            None,
+            false,
+            false,
+            &mut rng2,
+        );
+        match sc_state_initial2
+            .handle_exchange_rand_nonce(
+                recorded_exchange_rand_nonce1,
+                identity_client2.clone(),
+                rng2.clone(),
+            )
+            .await
+            .unwrap()
+        {
+            HandleRandNonceOutput::ExchangeDh(_) => {}
+            HandleRandNonceOutput::Resumed(_) => unreachable!(),
+        };
+
+        // Only one side has a ticket. Both sides fall back to a full handshake:
+        let (sc_state_initial1, exchange_rand_nonce1) = ScStateInitial::new(
+            public_key1.clone(),
+            Some(public_key2.clone()),
+            Some(sc_state1.get_resumption_ticket().clone()),
+            false,
+            false,
+            &mut rng1,
+        );
+        let (sc_state_initial2, exchange_rand_nonce2) = ScStateInitial::new(
+            public_key2.clone(),
+            Some(public_key1.clone()),
+            None,
+            false,
+            false,
+            &mut rng2,
+        );
+        let (sc_state_half1, exchange_dh1) = match sc_state_initial1
+            .handle_exchange_rand_nonce(
+                exchange_rand_nonce2,
+                identity_client1.clone(),
+                rng1.clone(),
+            )
+            .await
+            .unwrap()
+        {
+            HandleRandNonceOutput::ExchangeDh(output) => output,
+            HandleRandNonceOutput::Resumed(_) => unreachable!(),
+        };
+        let (sc_state_half2, exchange_dh2) = match sc_state_initial2
+            .handle_exchange_rand_nonce(
+                exchange_rand_nonce1,
+                identity_client2.clone(),
+                rng2.clone(),
+            )
+            .await
+            .unwrap()
+        {
+            HandleRandNonceOutput::ExchangeDh(output) => output,
+            HandleRandNonceOutput::Resumed(_) => unreachable!(),
+        };
+        let mut sc_state1 = sc_state_half1.handle_exchange_dh(exchange_dh2).unwrap();
+        let mut sc_state2 = sc_state_half2.handle_exchange_dh(exchange_dh1).unwrap();
+        send_recv_messages(&mut sc_state1, &mut sc_state2, &mut rng1, &mut rng2);
+    }
+
+    #[test]
+    fn test_resume_sc_state() {
+        let thread_pool = ThreadPool::new().unwrap();
+
+        let mut rng1 = DummyRandom::new(&[1u8]);
+        let private_key = PrivateKey::rand_gen(&mut rng1);
+        let identity1 = SoftwareEd25519Identity::from_private_key(&private_key).unwrap();
+        let (requests_sender1, identity_server1) = create_identity(identity1);
+        let identity_client1 = IdentityClient::new(requests_sender1);
+        thread_pool
+            .spawn(identity_server1.then(|_| future::ready(())))
+            .unwrap();
+
+        let mut rng2 = DummyRandom::new(&[2u8]);
+        let private_key = PrivateKey::rand_gen(&mut rng2);
+        let identity2 = SoftwareEd25519Identity::from_private_key(&private_key).unwrap();
+        let (requests_sender2, identity_server2) = create_identity(identity2);
+        let identity_client2 = IdentityClient::new(requests_sender2);
+        thread_pool
+            .spawn(identity_server2.then(|_| future::ready(())))
+            .unwrap();
+
+        LocalPool::new().run_until(run_resume_sc_state(identity_client1, identity_client2));
+    }
+
+    async fn run_tampered_sc_state(
+        identity_client1: IdentityClient,
+        identity_client2: IdentityClient,
+    ) {
+        let mut rng1 = DummyRandom::new(&[5u8]);
+        let mut rng2 = DummyRandom::new(&[6u8]);
+        let public_key1 = identity_client1.request_public_key().await.unwrap();
+        let public_key2 = identity_client2.request_public_key().await.unwrap();
+
+        let (sc_state_initial1, mut exchange_rand_nonce1) = ScStateInitial::new(
+            public_key1.clone(),
+            Some(public_key2.clone()),
+            None,
+            true,
+            false,
+            &mut rng1,
+        );
+        let (sc_state_initial2, exchange_rand_nonce2) =
+            ScStateInitial::new(public_key2.clone(), None, None, true, false, &mut rng2);
+
+        // An attacker in the middle clears the padding flag:
+        exchange_rand_nonce1.pad_messages = false;
+
+        let (sc_state_half1, exchange_dh1) = match sc_state_initial1
+            .handle_exchange_rand_nonce(
+                exchange_rand_nonce2,
+                identity_client1.clone(),
+                rng1.clone(),
+            )
+            .await
+            .unwrap()
+        {
+            HandleRandNonceOutput::ExchangeDh(output) => output,
+            HandleRandNonceOutput::Resumed(_) => unreachable!(),
+        };
+        let (sc_state_half2, exchange_dh2) = match sc_state_initial2
+            .handle_exchange_rand_nonce(
+                exchange_rand_nonce1,
+                identity_client2.clone(),
+                rng2.clone(),
+            )
+            .await
+            .unwrap()
+        {
+            HandleRandNonceOutput::ExchangeDh(output) => output,
+            HandleRandNonceOutput::Resumed(_) => unreachable!(),
+        };
+
+        // Both sides notice that the handshake was tampered with:
+        match sc_state_half1.handle_exchange_dh(exchange_dh2) {
+            Err(ScStateError::InvalidSignature) => {}
+            _ => unreachable!(),
+        };
+        match sc_state_half2.handle_exchange_dh(exchange_dh1) {
+            Err(ScStateError::InvalidSignature) => {}
+            _ => unreachable!(),
+        };
+    }
+
+    async fn run_tampered_resume_sc_state(
+        identity_client1: IdentityClient,
+        identity_client2: IdentityClient,
+    ) {
+        let mut rng1 = DummyRandom::new(&[7u8]);
+        let mut rng2 = DummyRandom::new(&[8u8]);
+        let public_key1 = identity_client1.request_public_key().await.unwrap();
+        let public_key2 = identity_client2.request_public_key().await.unwrap();
+
+        let (prev_sc_state1, prev_sc_state2) = run_basic_sc_state(
+            identity_client1.clone(),
+            identity_client2.clone(),
+            true,
+            true,
+            false,
+            false,
+        )
+        .await
+        .unwrap();
+        let resumption_ticket1 = prev_sc_state1.get_resumption_ticket().clone();
+        let resumption_ticket2 = prev_sc_state2.get_resumption_ticket().clone();
+
+        // An attacker in the middle removes the resumption ticket ids, forcing a full handshake:
+        let (sc_state_initial1, mut exchange_rand_nonce1) = ScStateInitial::new(
+            public_key1.clone(),
+            Some(public_key2.clone()),
+            Some(resumption_ticket1.clone()),
+            true,
+            false,
+            &mut rng1,
+        );
+        let (sc_state_initial2, mut exchange_rand_nonce2) = ScStateInitial::new(
+            public_key2.clone(),
+            Some(public_key1.clone()),
+            Some(resumption_ticket2.clone()),
+            true,
+            false,
+            &mut rng2,
+        );
+        exchange_rand_nonce1.opt_resume_ticket_id = None;
+        exchange_rand_nonce2.opt_resume_ticket_id = None;
+
+        let (sc_state_half1, exchange_dh1) = match sc_state_initial1
+            .handle_exchange_rand_nonce(
+                exchange_rand_nonce2,
+                identity_client1.clone(),
+                rng1.clone(),
+            )
+            .await
+            .unwrap()
+        {
+            HandleRandNonceOutput::ExchangeDh(output) => output,
+            HandleRandNonceOutput::Resumed(_) => unreachable!(),
+        };
+        let (sc_state_half2, exchange_dh2) = match sc_state_initial2
+            .handle_exchange_rand_nonce(
+                exchange_rand_nonce1,
+                identity_client2.clone(),
+                rng2.clone(),
+            )
+            .await
+            .unwrap()
+        {
+            HandleRandNonceOutput::ExchangeDh(output) => output,
+            HandleRandNonceOutput::Resumed(_) => unreachable!(),
+        };
+        match sc_state_half1.handle_exchange_dh(exchange_dh2) {
+            Err(ScStateError::InvalidSignature) => {}
+            _ => unreachable!(),
+        };
+        match sc_state_half2.handle_exchange_dh(exchange_dh1) {
+            Err(ScStateError::InvalidSignature) => {}
+            _ => unreachable!(),
+        };
+
+        // An attacker in the middle clears the padding flag of a resumed session.
+        // The sides derive mismatching keys:
+        let (sc_state_initial1, mut exchange_rand_nonce1) = ScStateInitial::new(
+            public_key1.clone(),
+            Some(public_key2.clone()),
+            Some(resumption_ticket1),
+            true,
+            false,
+            &mut rng1,
+        );
+        let (sc_state_initial2, exchange_rand_nonce2) = ScStateInitial::new(
+            public_key2.clone(),
+            Some(public_key1.clone()),
+            Some(resumption_ticket2),
+            true,
+            false,
+            &mut rng2,
+        );
+        exchange_rand_nonce1.pad_messages = false;
+
+        let mut sc_state1 = match sc_state_initial1
+            .handle_exchange_rand_nonce(
+                exchange_rand_nonce2,
+                identity_client1.clone(),
+                rng1.clone(),
+            )
+            .await
+            .unwrap()
+        {
+            HandleRandNonceOutput::Resumed(sc_state) => sc_state,
+            HandleRandNonceOutput::ExchangeDh(_) => unreachable!(),
+        };
+        let mut sc_state2 = match sc_state_initial2
+            .handle_exchange_rand_nonce(
+                exchange_rand_nonce1,
+                identity_client2.clone(),
+                rng2.clone(),
+            )
+            .await
+            .unwrap()
+        {
+            HandleRandNonceOutput::Resumed(sc_state) => sc_state,
+            HandleRandNonceOutput::ExchangeDh(_) => unreachable!(),
+        };
+        let enc_data = sc_state1.create_outgoing(&PlainData(vec![1, 2, 3]), &mut rng1);
+        assert!(sc_state2.handle_incoming(&enc_data, &mut rng2).is_err());
+        let enc_data = sc_state2.create_outgoing(&PlainData(vec![1, 2, 3]), &mut rng2);
+        assert!(sc_state1.handle_incoming(&enc_data, &mut rng1).is_err());
+    }
+
+    #[test]
+    fn test_tampered_sc_state() {
+        let thread_pool = ThreadPool::new().unwrap();
+
+        let mut rng1 = DummyRandom::new(&[1u8]);
+        let private_key = PrivateKey::rand_gen(&mut rng1);
+        let identity1 = SoftwareEd25519Identity::from_private_key(&private_key).unwrap();
+        let (requests_sender1, identity_server1) = create_identity(identity1);
+        let identity_client1 = IdentityClient::new(requests_sender1);
+        thread_pool
+            .spawn(identity_server1.then(|_| future::ready(())))
+            .unwrap();
+
+        let mut rng2 = DummyRandom::new(&[2u8]);
+        let private_key = PrivateKey::rand_gen(&mut rng2);
+        let identity2 = SoftwareEd25519Identity::from_private_key(&private_key).unwrap();
+        let (requests_sender2, identity_server2) = create_identity(identity2);
+        let identity_client2 = IdentityClient::new(requests_sender2);
+        thread_pool
+            .spawn(identity_server2.then(|_| future::ready(())))
+            .unwrap();
+
+        LocalPool::new().run_until(async move {
+            run_tampered_sc_state(identity_client1.clone(), identity_client2.clone()).await;
+            run_tampered_resume_sc_state(identity_client1, identity_client2).await;
+        });
+    }
+
     // TODO: Add tests:
     // - Test the usage of old receiver
     // - Test error cases
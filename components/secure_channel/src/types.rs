@@ -2,3 +2,12 @@
 pub struct EncryptedData(pub Vec<u8>);
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct PlainData(pub Vec<u8>);
+
+/// Traffic padding mode of a secure channel.
+/// Padding is used only if the remote side asks for it too.
+#[derive(Debug, Clone)]
+pub struct PaddingConfig {
+    /// Send a cover message every `cover_ticks` timer ticks.
+    /// `None` means that no cover traffic is sent.
+    pub opt_cover_ticks: Option<usize>,
+}
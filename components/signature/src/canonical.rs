@@ -3,11 +3,15 @@ use byteorder::{BigEndian, WriteBytesExt};
 
 use proto::app_server::messages::RelayAddress;
 use proto::funder::messages::{
-    BalanceInfo, CancelSendFundsOp, CollectSendFundsOp, CountersInfo, Currency,
-    CurrencyBalanceInfo, CurrencyOperations, FriendTcOp, FriendsRoute, McInfo, OptLocalRelays,
-    Receipt, RequestSendFundsOp, ResponseSendFundsOp, TokenInfo,
+    AcceptSwapOp, BalanceInfo, CancelSendFundsOp, CancelSwapOp, CollectSendFundsOp, CountersInfo,
+    Currency, CurrencyBalanceInfo, CurrencyExchange, CurrencyOperations, ExchangeRate, FriendTcOp,
+    FriendsRoute, McInfo, OptLocalRelays, Receipt, RequestSendFundsOp, RequestSwapOp,
+    ResponseSendFundsOp, TokenInfo,
+};
+use proto::index_server::messages::{
+    IndexMutation, RemoveExchangeRate, RemoveFriendCurrency, UpdateExchangeRate,
+    UpdateFriendCurrency,
 };
-use proto::index_server::messages::{IndexMutation, RemoveFriendCurrency, UpdateFriendCurrency};
 use proto::net::messages::NetAddress;
 
 use common::int_convert::usize_to_u64;
@@ -167,6 +171,28 @@ impl CanonicalSerialize for RequestSendFundsOp {
             .unwrap();
         res_bytes.extend_from_slice(&self.invoice_id);
         res_bytes.write_u128::<BigEndian>(self.left_fees).unwrap();
+        // Always serialized with a length prefix, so that the canonical form is unambiguous:
+        res_bytes.extend_from_slice(&self.exchanges.canonical_serialize());
+        res_bytes
+    }
+}
+
+impl CanonicalSerialize for ExchangeRate {
+    fn canonical_serialize(&self) -> Vec<u8> {
+        let mut res_bytes = Vec::new();
+        res_bytes.write_u64::<BigEndian>(self.numerator).unwrap();
+        res_bytes.write_u64::<BigEndian>(self.denominator).unwrap();
+        res_bytes
+    }
+}
+
+impl CanonicalSerialize for CurrencyExchange {
+    fn canonical_serialize(&self) -> Vec<u8> {
+        let mut res_bytes = Vec::new();
+        res_bytes.extend_from_slice(&self.public_key);
+        res_bytes.extend_from_slice(&self.src_currency.canonical_serialize());
+        res_bytes.extend_from_slice(&self.dest_currency.canonical_serialize());
+        res_bytes.extend_from_slice(&self.rate.canonical_serialize());
         res_bytes
     }
 }
@@ -201,6 +227,33 @@ impl CanonicalSerialize for CollectSendFundsOp {
     }
 }
 
+impl CanonicalSerialize for RequestSwapOp {
+    fn canonical_serialize(&self) -> Vec<u8> {
+        let mut res_bytes = Vec::new();
+        res_bytes.extend_from_slice(&self.swap_id);
+        res_bytes.write_u128::<BigEndian>(self.src_amount).unwrap();
+        res_bytes.extend_from_slice(&self.dest_currency.canonical_serialize());
+        res_bytes.write_u128::<BigEndian>(self.dest_amount).unwrap();
+        res_bytes
+    }
+}
+
+impl CanonicalSerialize for AcceptSwapOp {
+    fn canonical_serialize(&self) -> Vec<u8> {
+        let mut res_bytes = Vec::new();
+        res_bytes.extend_from_slice(&self.swap_id);
+        res_bytes
+    }
+}
+
+impl CanonicalSerialize for CancelSwapOp {
+    fn canonical_serialize(&self) -> Vec<u8> {
+        let mut res_bytes = Vec::new();
+        res_bytes.extend_from_slice(&self.swap_id);
+        res_bytes
+    }
+}
+
 impl CanonicalSerialize for FriendTcOp {
     fn canonical_serialize(&self) -> Vec<u8> {
         let mut res_bytes = Vec::new();
@@ -221,6 +274,18 @@ impl CanonicalSerialize for FriendTcOp {
                 res_bytes.push(3u8);
                 res_bytes.append(&mut commit_send_funds.canonical_serialize())
             }
+            FriendTcOp::RequestSwap(request_swap) => {
+                res_bytes.push(4u8);
+                res_bytes.append(&mut request_swap.canonical_serialize())
+            }
+            FriendTcOp::AcceptSwap(accept_swap) => {
+                res_bytes.push(5u8);
+                res_bytes.append(&mut accept_swap.canonical_serialize())
+            }
+            FriendTcOp::CancelSwap(cancel_swap) => {
+                res_bytes.push(6u8);
+                res_bytes.append(&mut cancel_swap.canonical_serialize())
+            }
         }
         res_bytes
     }
@@ -308,6 +373,25 @@ impl CanonicalSerialize for RemoveFriendCurrency {
     }
 }
 
+impl CanonicalSerialize for UpdateExchangeRate {
+    fn canonical_serialize(&self) -> Vec<u8> {
+        let mut res_bytes = Vec::new();
+        res_bytes.extend_from_slice(&self.src_currency.canonical_serialize());
+        res_bytes.extend_from_slice(&self.dest_currency.canonical_serialize());
+        res_bytes.extend_from_slice(&self.rate.canonical_serialize());
+        res_bytes
+    }
+}
+
+impl CanonicalSerialize for RemoveExchangeRate {
+    fn canonical_serialize(&self) -> Vec<u8> {
+        let mut res_bytes = Vec::new();
+        res_bytes.extend_from_slice(&self.src_currency.canonical_serialize());
+        res_bytes.extend_from_slice(&self.dest_currency.canonical_serialize());
+        res_bytes
+    }
+}
+
 impl CanonicalSerialize for IndexMutation {
     fn canonical_serialize(&self) -> Vec<u8> {
         let mut res_bytes = Vec::new();
@@ -320,6 +404,14 @@ impl CanonicalSerialize for IndexMutation {
                 res_bytes.push(1);
                 res_bytes.extend(remove_friend_currency.canonical_serialize());
             }
+            IndexMutation::UpdateExchangeRate(update_exchange_rate) => {
+                res_bytes.push(2);
+                res_bytes.extend(update_exchange_rate.canonical_serialize());
+            }
+            IndexMutation::RemoveExchangeRate(remove_exchange_rate) => {
+                res_bytes.push(3);
+                res_bytes.extend(remove_exchange_rate.canonical_serialize());
+            }
         };
         res_bytes
     }
@@ -79,6 +79,17 @@ pub fn hash_token_info(token_info: &TokenInfo) -> HashResult {
     sha_512_256(&token_info.canonical_serialize())
 }
 
+pub const FRIEND_SETTLEMENT_PREFIX: &[u8] = b"FRIEND_SETTLEMENT";
+
+/// Create the buffer we sign over when cooperatively closing a token channel.
+/// `token_info` is the final state of the token channel, from the point of view of the signer.
+pub fn friend_settlement_signature_buff(token_info: &TokenInfo) -> Vec<u8> {
+    let mut sig_buffer = Vec::new();
+    sig_buffer.extend_from_slice(&sha_512_256(FRIEND_SETTLEMENT_PREFIX));
+    sig_buffer.extend_from_slice(&hash_token_info(token_info));
+    sig_buffer
+}
+
 /// Hash operations and local_address:
 pub fn prefix_hash<B, MT>(move_token: MT) -> HashResult
 where
@@ -92,6 +103,7 @@ where
     hash_buff.extend_from_slice(&move_token.currencies_operations.canonical_serialize());
     hash_buff.extend_from_slice(&move_token.opt_local_relays.canonical_serialize());
     hash_buff.extend_from_slice(&move_token.opt_active_currencies.canonical_serialize());
+    hash_buff.extend_from_slice(&move_token.opt_direct_addresses.canonical_serialize());
 
     sha_512_256(&hash_buff)
 }
@@ -4,16 +4,16 @@ use crypto::hash;
 use crypto::hash_lock::HashLock;
 use crypto::identity::verify_signature;
 
-use proto::crypto::PublicKey;
+use proto::crypto::{PublicKey, Signature};
 
-use proto::funder::messages::{Commit, MoveToken, Receipt};
+use proto::funder::messages::{Commit, FriendSettlement, MoveToken, Receipt, TokenInfo};
 use proto::index_server::messages::MutationsUpdate;
 use proto::report::messages::MoveTokenHashedReport;
 
 use crate::canonical::CanonicalSerialize;
 use crate::signature_buff::{
-    create_mutations_update_signature_buff, move_token_hashed_report_signature_buff,
-    move_token_signature_buff, FUNDS_RESPONSE_PREFIX,
+    create_mutations_update_signature_buff, friend_settlement_signature_buff,
+    move_token_hashed_report_signature_buff, move_token_signature_buff, FUNDS_RESPONSE_PREFIX,
 };
 
 // TODO: Add a local test that makes sure verify_receipt is in sync with verify_commit_signature
@@ -94,3 +94,38 @@ pub fn verify_move_token_hashed_report(
     let sig_buffer = move_token_hashed_report_signature_buff(move_token_hashed_report);
     verify_signature(&sig_buffer, public_key, &move_token_hashed_report.new_token)
 }
+
+/// Verify a signature over the final state of a token channel.
+/// `token_info` is the final state from the point of view of the signer.
+pub fn verify_settlement_signature(
+    token_info: &TokenInfo,
+    public_key: &PublicKey,
+    signature: &Signature,
+) -> bool {
+    let sig_buffer = friend_settlement_signature_buff(token_info);
+    verify_signature(&sig_buffer, public_key, signature)
+}
+
+/// Verify that a final settlement of a token channel was signed by both sides.
+pub fn verify_friend_settlement(friend_settlement: &FriendSettlement) -> bool {
+    let token_info = &friend_settlement.token_info;
+
+    // A settlement is only possible after all pending transactions were resolved:
+    if token_info.mc.balances.iter().any(|currency_balance_info| {
+        currency_balance_info.balance_info.local_pending_debt != 0
+            || currency_balance_info.balance_info.remote_pending_debt != 0
+    }) {
+        return false;
+    }
+
+    // Each side signs over the final state from its own point of view:
+    verify_settlement_signature(
+        token_info,
+        &token_info.mc.local_public_key,
+        &friend_settlement.local_signature,
+    ) && verify_settlement_signature(
+        &token_info.clone().flip(),
+        &token_info.mc.remote_public_key,
+        &friend_settlement.remote_signature,
+    )
+}
@@ -0,0 +1,72 @@
+#![deny(trivial_numeric_casts, warnings)]
+#![allow(broken_intra_doc_links)]
+#![allow(
+    clippy::too_many_arguments,
+    clippy::implicit_hasher,
+    clippy::module_inception,
+    clippy::new_without_default
+)]
+
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use derive_more::From;
+use schemars::schema::RootSchema;
+use structopt::StructOpt;
+
+use stcompact::schema::{server_to_user_ack_schema, user_to_server_ack_schema};
+
+/// stcompact_schema_gen: Offset Compact schema generator
+///
+/// Generates JSON Schema definitions for the messages exchanged
+/// between stcompact and a user application
+///
+#[derive(Debug, StructOpt)]
+#[structopt(name = "stcompact_schema_gen")]
+pub struct StCompactSchemaGenCmd {
+    /// Output directory. If not specified, schemas are printed to the console.
+    #[structopt(parse(from_os_str), short = "o", long = "output")]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(Debug, From)]
+enum SchemaGenError {
+    SerializeError(serde_json::Error),
+    IoError(io::Error),
+}
+
+fn output_schema(
+    type_name: &str,
+    root_schema: &RootSchema,
+    opt_output: &Option<PathBuf>,
+) -> Result<(), SchemaGenError> {
+    let schema_str = serde_json::to_string_pretty(root_schema)?;
+    match opt_output {
+        Some(output) => fs::write(
+            output.join(format!("{}.schema.json", type_name)),
+            schema_str,
+        )?,
+        None => {
+            println!("// {}:", type_name);
+            println!("{}\n", schema_str);
+        }
+    }
+    Ok(())
+}
+
+fn main() -> Result<(), SchemaGenError> {
+    env_logger::init();
+
+    // Load argumnets:
+    let st_compact_schema_gen_cmd = StCompactSchemaGenCmd::from_args();
+    let output = st_compact_schema_gen_cmd.output;
+
+    if let Some(output) = &output {
+        fs::create_dir_all(output)?;
+    }
+
+    output_schema("serverToUserAck", &server_to_user_ack_schema(), &output)?;
+    output_schema("userToServerAck", &user_to_server_ack_schema(), &output)?;
+    Ok(())
+}
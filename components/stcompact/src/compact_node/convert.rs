@@ -4,7 +4,8 @@ use crate::compact_node::messages::{
     BalanceInfo, ChannelConsistentReport, ChannelInconsistentReport, ChannelStatusReport, Commit,
     CompactReport, ConfigReport, CountersInfo, CurrencyReport, FriendLivenessReport, FriendReport,
     FriendStatusReport, McInfo, MoveTokenHashedReport, OpenInvoice, OpenPayment, OpenPaymentStatus,
-    RequestsStatusReport, ResetTermsReport, TokenInfo,
+    RelayHealthReport, RequestsStatusReport, ResetTermsReport, Subscription, SubscriptionStatus,
+    TokenInfo,
 };
 
 use crate::compact_node::persist;
@@ -206,6 +207,23 @@ impl From<app::report::FriendReport> for FriendReport {
             liveness: friend_report.liveness.into(),
             channel_status: friend_report.channel_status.into(),
             status: friend_report.status.into(),
+            relays_health: friend_report
+                .relays_health
+                .into_iter()
+                .map(RelayHealthReport::from)
+                .collect(),
+        }
+    }
+}
+
+impl From<app::report::RelayHealthReport> for RelayHealthReport {
+    fn from(from: app::report::RelayHealthReport) -> Self {
+        RelayHealthReport {
+            relay_public_key: from.relay_public_key,
+            success_count: from.success_count,
+            failure_count: from.failure_count,
+            consecutive_failures: from.consecutive_failures,
+            latency_ticks: from.latency_ticks,
         }
     }
 }
@@ -278,6 +296,36 @@ impl From<persist::OpenPayment> for OpenPayment {
             description: from.description,
             generation: from.generation,
             status: from.status.into(),
+            opt_subscription_id: from.opt_subscription_id,
+        }
+    }
+}
+
+impl From<persist::SubscriptionStatus> for SubscriptionStatus {
+    fn from(from: persist::SubscriptionStatus) -> Self {
+        match from {
+            persist::SubscriptionStatus::Active => SubscriptionStatus::Active,
+            persist::SubscriptionStatus::Paused => SubscriptionStatus::Paused,
+            persist::SubscriptionStatus::Exhausted => SubscriptionStatus::Exhausted,
+        }
+    }
+}
+
+impl From<persist::Subscription> for Subscription {
+    fn from(from: persist::Subscription) -> Self {
+        Subscription {
+            currency: from.currency,
+            dest_public_key: from.dest_public_key,
+            dest_payment: from.dest_payment,
+            max_total_payment: from.max_total_payment,
+            max_fees: from.max_fees,
+            period_secs: from.period_secs,
+            next_payment_time: from.next_payment_time,
+            total_paid: from.total_paid,
+            description: from.description,
+            status: from.status.into(),
+            opt_payment_id: from.opt_payment_id,
+            generation: from.generation,
         }
     }
 }
@@ -289,6 +337,7 @@ impl From<persist::OpenInvoice> for OpenInvoice {
             total_dest_payment: from.total_dest_payment,
             description: from.description,
             is_committed: from.opt_commit.is_some(),
+            is_held: from.opt_hold_expiry.is_some(),
             generation: from.generation,
         }
     }
@@ -319,5 +368,10 @@ pub fn create_compact_report(
             .into_iter()
             .map(|(payment_id, open_payment)| (payment_id, open_payment.into()))
             .collect(),
+        subscriptions: compact_state
+            .subscriptions
+            .into_iter()
+            .map(|(subscription_id, subscription)| (subscription_id, subscription.into()))
+            .collect(),
     }
 }
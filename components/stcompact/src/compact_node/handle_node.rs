@@ -6,7 +6,7 @@ use app::conn::{
     ResponseClosePayment, ResponseRoutesResult,
 };
 
-use route::{choose_multi_route, MultiRouteChoice};
+use route::{choose_multi_route, route_fees, MultiRouteChoice};
 
 use crate::compact_node::convert::create_compact_report;
 use crate::compact_node::messages::{
@@ -17,7 +17,7 @@ use crate::compact_node::persist::{OpenPaymentStatus, OpenPaymentStatusFoundRout
 use crate::compact_node::types::{CompactNodeError, CompactServerState};
 use crate::gen::GenUid;
 
-use crate::compact_node::utils::update_send_compact_state;
+use crate::compact_node::utils::{send_payment, update_send_compact_state};
 
 /// Calculate fees if we send credits through the given MultiRoute with the MultiRouteChoice
 /// strategy
@@ -27,9 +27,7 @@ fn calc_multi_route_fees(
 ) -> Option<u128> {
     let mut total_fees = 0u128;
     for (route_index, dest_payment) in multi_route_choice {
-        let fee = multi_route.routes[*route_index]
-            .rate
-            .calc_fee(*dest_payment)?;
+        let fee = route_fees(&multi_route.routes[*route_index], *dest_payment)?;
         total_fees = total_fees.checked_add(fee)?;
     }
     Some(total_fees)
@@ -138,9 +136,16 @@ where
             ) {
                 (RequestResult::Complete(commit), _) => {
                     // Set payment status to Commit:
+                    let is_subscription_payment = open_payment.opt_subscription_id.is_some();
                     open_payment.status = OpenPaymentStatus::Commit(commit.clone(), sending.fees);
                     update_send_compact_state(compact_state, server_state, user_sender).await?;
 
+                    // Subscription payments are push payments. The seller collects the
+                    // payment by itself, so there is no commit to hand over:
+                    if is_subscription_payment {
+                        return Ok(());
+                    }
+
                     // Send commit to user:
                     let payment_commit = PaymentCommit {
                         payment_id,
@@ -369,6 +374,53 @@ where
                     .map_err(|_| CompactNodeError::UserSenderError);
             };
 
+            // Fees of subscription payments are confirmed automatically, up to the subscription's
+            // `max_fees`:
+            if let Some(subscription_id) = open_payment.opt_subscription_id.clone() {
+                let opt_max_fees = compact_state
+                    .subscriptions
+                    .get(&subscription_id)
+                    .map(|subscription| subscription.max_fees);
+
+                match opt_max_fees {
+                    Some(max_fees) if fees <= max_fees => {
+                        // This is an `app_request_id` we don't need to track:
+                        let app_request_id = compact_gen.gen_uid();
+                        return send_payment(
+                            payment_id,
+                            multi_route,
+                            multi_route_choice,
+                            fees,
+                            app_request_id,
+                            compact_state,
+                            server_state,
+                            compact_gen,
+                            user_sender,
+                            app_sender,
+                        )
+                        .await;
+                    }
+                    _ => {
+                        // Fees are too high, or the subscription was cancelled.
+                        // Set payment as failure:
+                        let ack_uid = compact_gen.gen_uid();
+                        open_payment.status = OpenPaymentStatus::Failure(ack_uid.clone());
+                        update_send_compact_state(compact_state, server_state, user_sender).await?;
+
+                        // Inform the user about failure:
+                        let payment_done = PaymentDone {
+                            payment_id,
+                            status: PaymentDoneStatus::Failure(ack_uid),
+                        };
+                        let compact_to_user = CompactToUser::PaymentDone(payment_done);
+                        return user_sender
+                            .send(CompactToUserAck::CompactToUser(compact_to_user))
+                            .await
+                            .map_err(|_| CompactNodeError::UserSenderError);
+                    }
+                }
+            }
+
             // Update compact state (keep the best multiroute):
             let confirm_id = compact_gen.gen_uid();
             let found_route = OpenPaymentStatusFoundRoute {
@@ -1,7 +1,10 @@
 use futures::{Sink, SinkExt};
 
-use app::common::Uid;
-use app::conn::{buyer, config, routes, seller, AppPermissions, AppToAppServer};
+use common::int_convert::usize_to_u64;
+
+use proto::consts::TICK_MS;
+
+use app::conn::{config, routes, seller, AppPermissions, AppToAppServer};
 use app::verify::verify_commit;
 
 // use crate::compact_node::create_compact_report;
@@ -10,12 +13,12 @@ use crate::compact_node::messages::{
     PaymentFeesResponse, ResponseVerifyCommit, UserToCompact, UserToCompactAck, VerifyCommitStatus,
 };
 use crate::compact_node::persist::{
-    OpenInvoice, OpenPayment, OpenPaymentStatus, OpenPaymentStatusSending,
+    OpenInvoice, OpenPayment, OpenPaymentStatus, Subscription, SubscriptionStatus,
 };
 use crate::compact_node::types::{CompactNodeError, CompactServerState};
 use crate::gen::GenUid;
 
-use crate::compact_node::utils::update_send_compact_state;
+use crate::compact_node::utils::{now_secs, send_payment, update_send_compact_state};
 
 // TODO: Should we check permissions here in the future?
 // Permissions are already checked on the node side (offset-app-server). I don't want to have code duplication here for
@@ -308,6 +311,7 @@ where
             let app_request = routes::request_routes(
                 request_routes_id.clone(),
                 init_payment.currency.clone(),
+                init_payment.currency.clone(),
                 init_payment.dest_payment,
                 server_state
                     .node_report()
@@ -335,6 +339,7 @@ where
                 description: init_payment.description,
                 generation: compact_state.generation.advance(),
                 status: OpenPaymentStatus::SearchingRoute(request_routes_id),
+                opt_subscription_id: None,
             };
             compact_state
                 .open_payments
@@ -384,90 +389,21 @@ where
                     .map_err(|_| CompactNodeError::UserSenderError);
             };
 
-            // Order:
-            // - Update local database
-            // - Send requests along routes
-
-            // Update compact_state:
-            let open_transactions: Vec<Uid> = multi_route_choice
-                .iter()
-                .map(|_| compact_gen.gen_uid())
-                .collect();
-
-            let sending = OpenPaymentStatusSending {
+            // We assign `user_request_id` as the request id of `RequestClosePayment`.
+            // This will provide the user with an ack for this request.
+            send_payment(
+                confirm_payment_fees.payment_id,
+                multi_route,
+                multi_route_choice,
                 fees,
-                open_transactions: open_transactions.clone().into_iter().collect(),
-            };
-
-            let open_payment = compact_state
-                .open_payments
-                .get_mut(&confirm_payment_fees.payment_id)
-                .unwrap();
-
-            open_payment.status = OpenPaymentStatus::Sending(sending);
-            let c_open_payment = open_payment.clone();
-
-            // server_state.update_compact_state(compact_state).await?;
-            update_send_compact_state(compact_state, server_state, user_sender).await?;
-
-            // Create a new payment:
-            let app_request = buyer::create_payment(
-                confirm_payment_fees.payment_id.clone(),
-                c_open_payment.invoice_id,
-                c_open_payment.currency,
-                c_open_payment.dest_payment,
-                c_open_payment.dest_public_key,
-            );
-
-            let app_to_app_server = AppToAppServer {
-                // This is an `app_request_id` we don't need to track:
-                app_request_id: compact_gen.gen_uid(),
-                app_request,
-            };
-            app_sender
-                .send(app_to_app_server)
-                .await
-                .map_err(|_| CompactNodeError::AppSenderError)?;
-
-            // Initiate requests along all routes in the multi route, where credits
-            // are allocated according to the strategy in `multi_route_choice`:
-            for ((route_index, dest_payment), request_id) in
-                multi_route_choice.iter().cloned().zip(open_transactions)
-            {
-                let route = &multi_route.routes[route_index];
-
-                let app_request = buyer::create_transaction(
-                    confirm_payment_fees.payment_id.clone(),
-                    request_id,
-                    route.route.clone(),
-                    dest_payment,
-                    route.rate.calc_fee(dest_payment).unwrap(),
-                );
-
-                let app_to_app_server = AppToAppServer {
-                    // We don't really care about app_request_id here, as we can wait on `request_id`
-                    // instead.
-                    app_request_id: compact_gen.gen_uid(),
-                    app_request,
-                };
-                app_sender
-                    .send(app_to_app_server)
-                    .await
-                    .map_err(|_| CompactNodeError::AppSenderError)?;
-            }
-
-            // Send RequestClosePayment, as we are not going to send any more transactions:
-            let app_request = buyer::request_close_payment(confirm_payment_fees.payment_id.clone());
-            let app_to_app_server = AppToAppServer {
-                // We assign `user_request_id` here. This will provide the user with an ack for this
-                // request.
-                app_request_id: user_request_id,
-                app_request,
-            };
-            app_sender
-                .send(app_to_app_server)
-                .await
-                .map_err(|_| CompactNodeError::AppSenderError)?;
+                user_request_id,
+                compact_state,
+                server_state,
+                compact_gen,
+                user_sender,
+                app_sender,
+            )
+            .await?;
         }
         UserToCompact::CancelPayment(payment_id) => {
             let mut compact_state = server_state.compact_state().clone();
@@ -569,6 +505,107 @@ where
             }
         }
         // =======================[Seller]=======================================
+        UserToCompact::AddSubscription(add_subscription) => {
+            let mut compact_state = server_state.compact_state().clone();
+            if compact_state
+                .subscriptions
+                .contains_key(&add_subscription.subscription_id)
+            {
+                warn!(
+                    "AddSubscription: subscription {:?} already exists!",
+                    add_subscription.subscription_id
+                );
+                return user_sender
+                    .send(CompactToUserAck::Ack(user_request_id))
+                    .await
+                    .map_err(|_| CompactNodeError::UserSenderError);
+            }
+
+            // The first execution happens on the next timer tick:
+            let subscription = Subscription {
+                currency: add_subscription.currency,
+                dest_public_key: add_subscription.dest_public_key,
+                dest_payment: add_subscription.dest_payment,
+                max_total_payment: add_subscription.max_total_payment,
+                max_fees: add_subscription.max_fees,
+                period_secs: add_subscription.period_secs,
+                next_payment_time: now_secs(),
+                total_paid: 0,
+                description: add_subscription.description,
+                status: SubscriptionStatus::Active,
+                opt_payment_id: None,
+                generation: compact_state.generation.advance(),
+            };
+            compact_state
+                .subscriptions
+                .insert(add_subscription.subscription_id, subscription);
+            update_send_compact_state(compact_state, server_state, user_sender).await?;
+
+            user_sender
+                .send(CompactToUserAck::Ack(user_request_id))
+                .await
+                .map_err(|_| CompactNodeError::UserSenderError)?;
+        }
+        UserToCompact::PauseSubscription(subscription_id) => {
+            let mut compact_state = server_state.compact_state().clone();
+            if let Some(subscription) = compact_state.subscriptions.get_mut(&subscription_id) {
+                if subscription.status == SubscriptionStatus::Active {
+                    subscription.status = SubscriptionStatus::Paused;
+                }
+            } else {
+                warn!(
+                    "PauseSubscription: subscription {:?} does not exist!",
+                    subscription_id
+                );
+            }
+            update_send_compact_state(compact_state, server_state, user_sender).await?;
+
+            user_sender
+                .send(CompactToUserAck::Ack(user_request_id))
+                .await
+                .map_err(|_| CompactNodeError::UserSenderError)?;
+        }
+        UserToCompact::ResumeSubscription(subscription_id) => {
+            let mut compact_state = server_state.compact_state().clone();
+            if let Some(subscription) = compact_state.subscriptions.get_mut(&subscription_id) {
+                // An exhausted subscription can not be resumed:
+                if subscription.status == SubscriptionStatus::Paused {
+                    subscription.status = SubscriptionStatus::Active;
+                }
+            } else {
+                warn!(
+                    "ResumeSubscription: subscription {:?} does not exist!",
+                    subscription_id
+                );
+            }
+            update_send_compact_state(compact_state, server_state, user_sender).await?;
+
+            user_sender
+                .send(CompactToUserAck::Ack(user_request_id))
+                .await
+                .map_err(|_| CompactNodeError::UserSenderError)?;
+        }
+        UserToCompact::CancelSubscription(subscription_id) => {
+            // Note that a payment that is already in progress is not cancelled. The user may
+            // cancel it using `CancelPayment`.
+            let mut compact_state = server_state.compact_state().clone();
+            if compact_state
+                .subscriptions
+                .remove(&subscription_id)
+                .is_none()
+            {
+                warn!(
+                    "CancelSubscription: subscription {:?} does not exist!",
+                    subscription_id
+                );
+            }
+            update_send_compact_state(compact_state, server_state, user_sender).await?;
+
+            user_sender
+                .send(CompactToUserAck::Ack(user_request_id))
+                .await
+                .map_err(|_| CompactNodeError::UserSenderError)?;
+        }
         UserToCompact::AddInvoice(add_invoice) => {
             let mut compact_state = server_state.compact_state().clone();
             if compact_state
@@ -591,6 +628,7 @@ where
                 total_dest_payment: add_invoice.total_dest_payment,
                 description: add_invoice.description,
                 opt_commit: None,
+                opt_hold_expiry: None,
                 generation: compact_state.generation.advance(),
             };
             compact_state
@@ -720,6 +758,83 @@ where
             // server_state.update_compact_state(compact_state).await?;
             update_send_compact_state(compact_state, server_state, user_sender).await?;
         }
+        UserToCompact::HoldInvoice(hold_invoice) => {
+            // Make sure that the corresponding invoice is open, committed and not already held:
+            let mut compact_state = server_state.compact_state().clone();
+            let opt_commit = match compact_state.open_invoices.get(&hold_invoice.invoice_id) {
+                Some(open_invoice) if open_invoice.opt_hold_expiry.is_none() => {
+                    open_invoice.opt_commit.clone()
+                }
+                _ => None,
+            };
+
+            let commit = if let Some(commit) = opt_commit {
+                commit
+            } else {
+                warn!(
+                    "HoldInvoice: Invoice {:?} can not be held!",
+                    hold_invoice.invoice_id
+                );
+                return user_sender
+                    .send(CompactToUserAck::Ack(user_request_id))
+                    .await
+                    .map_err(|_| CompactNodeError::UserSenderError);
+            };
+
+            // Order:
+            // - Send hold message
+            // - Update local database
+            //
+            // If a crash happens, the invoice is still listed as committed in our local
+            // database, and can be committed or canceled by the user.
+            let max_hold_ticks = hold_invoice
+                .max_hold_secs
+                .saturating_mul(usize_to_u64(1000 / TICK_MS).unwrap());
+            let app_request = seller::hold_invoice(commit, max_hold_ticks);
+
+            let app_to_app_server = AppToAppServer {
+                app_request_id: user_request_id,
+                app_request,
+            };
+            app_sender
+                .send(app_to_app_server)
+                .await
+                .map_err(|_| CompactNodeError::AppSenderError)?;
+
+            // Update local database:
+            let open_invoice = compact_state
+                .open_invoices
+                .get_mut(&hold_invoice.invoice_id)
+                .unwrap();
+            open_invoice.opt_hold_expiry =
+                Some(now_secs().saturating_add(hold_invoice.max_hold_secs));
+            update_send_compact_state(compact_state, server_state, user_sender).await?;
+        }
+        UserToCompact::EnablePushPayments(enable_push_payments) => {
+            let app_request = seller::enable_push_payments(
+                enable_push_payments.currency,
+                enable_push_payments.max_total_dest_payment,
+            );
+            let app_to_app_server = AppToAppServer {
+                app_request_id: user_request_id,
+                app_request,
+            };
+            app_sender
+                .send(app_to_app_server)
+                .await
+                .map_err(|_| CompactNodeError::AppSenderError)?;
+        }
+        UserToCompact::DisablePushPayments(currency) => {
+            let app_request = seller::disable_push_payments(currency);
+            let app_to_app_server = AppToAppServer {
+                app_request_id: user_request_id,
+                app_request,
+            };
+            app_sender
+                .send(app_to_app_server)
+                .await
+                .map_err(|_| CompactNodeError::AppSenderError)?;
+        }
         UserToCompact::RequestVerifyCommit(request_verify_commit) => {
             // Send ack:
             user_sender
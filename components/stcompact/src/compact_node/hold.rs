@@ -0,0 +1,32 @@
+use futures::Sink;
+
+use crate::compact_node::messages::CompactToUserAck;
+use crate::compact_node::types::{CompactNodeError, CompactServerState};
+use crate::compact_node::utils::{now_secs, update_send_compact_state};
+
+/// Remove held invoices whose maximum hold time has passed.
+/// The node cancels those invoices on its own, so there is nothing left to commit.
+pub async fn expire_held_invoices<US>(
+    server_state: &mut CompactServerState,
+    user_sender: &mut US,
+) -> Result<(), CompactNodeError>
+where
+    US: Sink<CompactToUserAck> + Unpin,
+{
+    let mut compact_state = server_state.compact_state().clone();
+
+    let now = now_secs();
+    compact_state
+        .open_invoices
+        .retain(
+            |invoice_id, open_invoice| match open_invoice.opt_hold_expiry {
+                Some(hold_expiry) if hold_expiry <= now => {
+                    warn!("Held invoice {:?} expired", invoice_id);
+                    false
+                }
+                _ => true,
+            },
+        );
+
+    update_send_compact_state(compact_state, server_state, user_sender).await
+}
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use app::common::{
@@ -10,9 +11,13 @@ use common::ser_utils::{
     ser_b64, ser_map_b64_any, ser_map_str_any, ser_map_str_str, ser_option_b64, ser_string,
 };
 
-#[derive(Arbitrary, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Arbitrary, Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
-pub struct Generation(#[serde(with = "ser_string")] pub u64);
+pub struct Generation(
+    #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
+    pub u64,
+);
 
 impl Generation {
     pub fn new() -> Self {
@@ -28,232 +33,354 @@ impl Generation {
     }
 }
 
-#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Commit {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub response_hash: HashResult,
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub src_plain_lock: PlainLock,
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub dest_hashed_lock: HashedLock,
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub dest_payment: u128,
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub total_dest_payment: u128,
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub invoice_id: InvoiceId,
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub currency: Currency,
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub signature: Signature,
 }
 
-#[derive(Arbitrary, Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[derive(Arbitrary, Debug, PartialEq, Eq, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct OpenFriendCurrency {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub friend_public_key: PublicKey,
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub currency: Currency,
 }
 
-#[derive(Arbitrary, Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[derive(Arbitrary, Debug, PartialEq, Eq, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct CloseFriendCurrency {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub friend_public_key: PublicKey,
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub currency: Currency,
 }
 
-#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct AddFriend {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub friend_public_key: PublicKey,
     pub relays: Vec<RelayAddress>,
     pub name: String,
 }
 
-#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SetFriendRelays {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub friend_public_key: PublicKey,
     pub relays: Vec<RelayAddress>,
 }
 
-#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SetFriendName {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub friend_public_key: PublicKey,
     pub name: String,
 }
 
-#[derive(Arbitrary, Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+#[derive(Arbitrary, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct InitPayment {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub payment_id: PaymentId,
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub invoice_id: InvoiceId,
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub currency: Currency,
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub dest_public_key: PublicKey,
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub dest_payment: u128,
     /// Short textual invoice description
     pub description: String,
 }
 
-#[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub enum PaymentFeesResponse {
     Unreachable,
     Fees(
-        #[serde(with = "ser_string")] u128,
-        #[serde(with = "ser_b64")] Uid,
+        #[serde(with = "ser_string")]
+        #[schemars(with = "String")]
+        u128,
+        #[serde(with = "ser_b64")]
+        #[schemars(with = "String")]
+        Uid,
     ), // (fees, confirm_id)
 }
 
-#[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct PaymentFees {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub payment_id: PaymentId,
     pub response: PaymentFeesResponse,
 }
 
 #[allow(clippy::large_enum_variant)]
-#[derive(Arbitrary, Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+#[derive(Arbitrary, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Clone)]
 #[serde(rename_all = "camelCase")]
 pub enum PaymentDoneStatus {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     Failure(Uid), // ack_uid
     Success(
         Receipt,
-        #[serde(with = "ser_string")] u128,
-        #[serde(with = "ser_b64")] Uid,
+        #[serde(with = "ser_string")]
+        #[schemars(with = "String")]
+        u128,
+        #[serde(with = "ser_b64")]
+        #[schemars(with = "String")]
+        Uid,
     ), // (receipt, fees, ack_uid)
 }
 
-#[derive(Arbitrary, Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+#[derive(Arbitrary, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PaymentDone {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub payment_id: PaymentId,
     pub status: PaymentDoneStatus,
 }
 
-#[derive(Arbitrary, Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+/// Hold a committed invoice: Funds remain locked along the route until the invoice is committed
+/// or canceled. The invoice is canceled automatically if it was not committed in time.
+#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HoldInvoice {
+    #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
+    pub invoice_id: InvoiceId,
+    /// Maximum time to hold the invoice, in seconds
+    #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
+    pub max_hold_secs: u64,
+}
+
+#[derive(Arbitrary, Clone, Serialize, Deserialize, JsonSchema, Debug, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct RequestVerifyCommit {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub request_id: Uid,
     // #[serde(with = "ser_b64")]
     // pub seller_public_key: PublicKey,
     pub commit: Commit,
 }
 
-#[derive(Arbitrary, Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+#[derive(Arbitrary, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ResponseVerifyCommit {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub request_id: Uid,
     pub status: VerifyCommitStatus,
 }
 
-#[derive(Arbitrary, Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+#[derive(Arbitrary, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Clone)]
 #[serde(rename_all = "camelCase")]
 pub enum VerifyCommitStatus {
     Failure,
     Success,
 }
 
-#[derive(Arbitrary, Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+#[derive(Arbitrary, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct PaymentCommit {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub payment_id: PaymentId,
     pub commit: Commit,
 }
 
-#[derive(Arbitrary, Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+#[derive(Arbitrary, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ConfirmPaymentFees {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub payment_id: PaymentId,
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub confirm_id: Uid,
 }
 
-#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Arbitrary, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct AddSubscription {
+    /// Randomly generated subscription_id, allows to refer to this subscription.
+    #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
+    pub subscription_id: Uid,
+    #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
+    pub currency: Currency,
+    #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
+    pub dest_public_key: PublicKey,
+    /// Amount of credits paid in every execution
+    #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
+    pub dest_payment: u128,
+    /// Maximum total amount of credits paid over all executions (Not including fees)
+    #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
+    pub max_total_payment: u128,
+    /// Maximum fees automatically confirmed for a single execution
+    #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
+    pub max_fees: u128,
+    /// Time between consecutive executions, in seconds.
+    /// The first execution happens immediately.
+    #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
+    pub period_secs: u64,
+    /// Short textual description for the subscription
+    pub description: String,
+}
+
+/// Notification about a new execution of a subscription.
+/// The result of the execution is reported through `PaymentDone`.
+#[derive(Arbitrary, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct SubscriptionPayment {
+    #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
+    pub subscription_id: Uid,
+    #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
+    pub payment_id: PaymentId,
+}
+
+#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SetFriendCurrencyMaxDebt {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub friend_public_key: PublicKey,
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub currency: Currency,
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub remote_max_debt: u128,
 }
 
-#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct RemoveFriendCurrency {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub friend_public_key: PublicKey,
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub currency: Currency,
 }
 
-#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ResetFriendChannel {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub friend_public_key: PublicKey,
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub reset_token: Signature,
 }
 
-#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SetFriendCurrencyRate {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub friend_public_key: PublicKey,
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub currency: Currency,
     pub rate: Rate,
 }
 
-#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct AddInvoice {
     /// Randomly generated invoice_id, allows to refer to this invoice.
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub invoice_id: InvoiceId,
     /// Currency in use
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub currency: Currency,
     /// Total amount of credits to be paid.
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub total_dest_payment: u128,
     /// Short textual description for the invoice
     pub description: String,
 }
 
+/// Accept push payments (Payments without a prior invoice), for example from subscriptions.
+#[derive(Arbitrary, Debug, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct EnablePushPayments {
+    #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
+    pub currency: Currency,
+    /// Maximum total amount of credits accepted for a single push payment.
+    #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
+    pub max_total_dest_payment: u128,
+}
+
 // TODO; Who uses this enum?
-#[derive(Arbitrary, Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+#[derive(Arbitrary, Clone, PartialEq, Eq, Serialize, Deserialize, JsonSchema, Debug)]
 #[serde(rename_all = "camelCase")]
 pub enum RequestsStatusReport {
     Open,
     Closed,
 }
 
-#[derive(Arbitrary, Clone, Serialize, Deserialize, Debug, PartialEq, Eq)]
+#[derive(Arbitrary, Clone, Serialize, Deserialize, JsonSchema, Debug, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct ConfigReport {
     /// Rate of forwarding transactions that arrived from this friend to any other friend
@@ -261,127 +388,169 @@ pub struct ConfigReport {
     pub rate: Rate,
     /// Credit frame for the remote side (Set by the user of this node)
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub remote_max_debt: u128,
     /// Can requests be sent through this node (Incoming or outgoing)?
     /// If `false`, only the local user may send or receive requests through this node.
     pub is_open: bool,
 }
 
-#[derive(Arbitrary, Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+#[derive(Arbitrary, Clone, Debug, Eq, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub enum FriendLivenessReport {
     Online,
     Offline,
 }
 
-#[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ResetTermsReport {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub reset_token: Signature,
     #[serde(with = "ser_map_str_str")]
+    #[schemars(with = "HashMap<String, String>")]
     pub balance_for_reset: HashMap<Currency, i128>,
 }
 
-#[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ChannelInconsistentReport {
     #[serde(with = "ser_map_str_str")]
+    #[schemars(with = "HashMap<String, String>")]
     pub local_reset_terms: HashMap<Currency, i128>,
     pub opt_remote_reset_terms: Option<ResetTermsReport>,
 }
 
-#[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct CurrencyReport {
     /// Amount of credits this side has against the remote side.
     /// The other side keeps the negation of this value.
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub balance: i128,
     /// Frozen credits by our side
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub local_pending_debt: u128,
     /// Frozen credits by the remote side
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub remote_pending_debt: u128,
 }
 
-#[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ChannelConsistentReport {
     #[serde(with = "ser_map_str_any")]
+    #[schemars(with = "HashMap<String, CurrencyReport>")]
     pub currency_reports: HashMap<Currency, CurrencyReport>,
 }
 
-#[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub enum ChannelStatusReport {
     Inconsistent(ChannelInconsistentReport),
     Consistent(ChannelConsistentReport),
 }
 
-#[derive(Arbitrary, Clone, Serialize, Deserialize, Debug, Eq, PartialEq)]
+#[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RelayHealthReport {
+    #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
+    pub relay_public_key: PublicKey,
+    #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
+    pub success_count: u64,
+    #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
+    pub failure_count: u64,
+    /// Amount of failed connection attempts since the last successful one
+    #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
+    pub consecutive_failures: u64,
+    /// Time ticks it took for the last successful connection attempt
+    #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
+    pub latency_ticks: u64,
+}
+
+#[derive(Arbitrary, Clone, Serialize, Deserialize, JsonSchema, Debug, Eq, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub enum FriendStatusReport {
     Enabled,
     Disabled,
 }
 
-#[derive(Arbitrary, Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[derive(Arbitrary, Debug, PartialEq, Eq, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct BalanceInfo {
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub balance: i128,
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub local_pending_debt: u128,
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub remote_pending_debt: u128,
 }
 
-#[derive(Arbitrary, Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[derive(Arbitrary, Debug, PartialEq, Eq, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct McInfo {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub local_public_key: PublicKey,
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub remote_public_key: PublicKey,
     #[serde(with = "ser_map_str_any")]
+    #[schemars(with = "HashMap<String, BalanceInfo>")]
     pub balances: HashMap<Currency, BalanceInfo>,
 }
 
-#[derive(Arbitrary, Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[derive(Arbitrary, Debug, PartialEq, Eq, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct CountersInfo {
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub inconsistency_counter: u64,
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub move_token_counter: u128,
 }
 
-#[derive(Arbitrary, Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[derive(Arbitrary, Debug, PartialEq, Eq, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct TokenInfo {
     pub mc: McInfo,
     pub counters: CountersInfo,
 }
 
-#[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct MoveTokenHashedReport {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub prefix_hash: HashResult,
     pub token_info: TokenInfo,
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub rand_nonce: RandValue,
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub new_token: Signature,
 }
 
-#[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct FriendReport {
     pub name: String,
     #[serde(with = "ser_map_str_any")]
+    #[schemars(with = "HashMap<String, ConfigReport>")]
     pub currency_configs: HashMap<Currency, ConfigReport>,
     /// Last message signed by the remote side.
     /// Can be used as a proof for the last known balance.
@@ -391,52 +560,88 @@ pub struct FriendReport {
     pub liveness: FriendLivenessReport, // is the friend online/offline?
     pub channel_status: ChannelStatusReport,
     pub status: FriendStatusReport,
+    /// Health of the relays used to connect to this friend
+    #[serde(default)]
+    pub relays_health: Vec<RelayHealthReport>,
 }
 
-#[derive(Arbitrary, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Arbitrary, Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct OpenInvoice {
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub currency: Currency,
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub total_dest_payment: u128,
     /// Invoice description
     pub description: String,
     /// Do we already have a commitment for this invoice?
     pub is_committed: bool,
+    /// Is this invoice held (Funds locked along the route until commit or cancel)?
+    pub is_held: bool,
     /// Chronological counter
     pub generation: Generation,
 }
 
 #[allow(clippy::large_enum_variant)]
-#[derive(Arbitrary, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Arbitrary, Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub enum OpenPaymentStatus {
-    SearchingRoute(#[serde(with = "ser_b64")] Uid), // request_routes_id
+    SearchingRoute(
+        #[serde(with = "ser_b64")]
+        #[schemars(with = "String")]
+        Uid,
+    ), // request_routes_id
     FoundRoute(
-        #[serde(with = "ser_b64")] Uid,
-        #[serde(with = "ser_string")] u128,
+        #[serde(with = "ser_b64")]
+        #[schemars(with = "String")]
+        Uid,
+        #[serde(with = "ser_string")]
+        #[schemars(with = "String")]
+        u128,
     ), // (confirm_id, fees)
-    Sending(#[serde(with = "ser_string")] u128),    // fees
-    Commit(Commit, #[serde(with = "ser_string")] u128), // (commit, fees)
+    Sending(
+        #[serde(with = "ser_string")]
+        #[schemars(with = "String")]
+        u128,
+    ), // fees
+    Commit(
+        Commit,
+        #[serde(with = "ser_string")]
+        #[schemars(with = "String")]
+        u128,
+    ), // (commit, fees)
     Success(
         Receipt,
-        #[serde(with = "ser_string")] u128,
-        #[serde(with = "ser_b64")] Uid,
+        #[serde(with = "ser_string")]
+        #[schemars(with = "String")]
+        u128,
+        #[serde(with = "ser_b64")]
+        #[schemars(with = "String")]
+        Uid,
     ), // (Receipt, fees, ack_uid)
-    Failure(#[serde(with = "ser_b64")] Uid),        // ack_uid
+    Failure(
+        #[serde(with = "ser_b64")]
+        #[schemars(with = "String")]
+        Uid,
+    ), // ack_uid
 }
 
-#[derive(Arbitrary, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Arbitrary, Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct OpenPayment {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub invoice_id: InvoiceId,
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub currency: Currency,
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub dest_public_key: PublicKey,
     #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
     pub dest_payment: u128,
     /// Invoice description (Obtained from the corresponding invoice)
     pub description: String,
@@ -444,39 +649,104 @@ pub struct OpenPayment {
     pub generation: Generation,
     /// Current status of open payment
     pub status: OpenPaymentStatus,
+    /// The subscription that initiated this payment (If any)
+    #[serde(with = "ser_option_b64")]
+    #[schemars(with = "Option<String>")]
+    pub opt_subscription_id: Option<Uid>,
+}
+
+#[derive(Arbitrary, Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum SubscriptionStatus {
+    Active,
+    Paused,
+    Exhausted,
+}
+
+#[derive(Arbitrary, Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Subscription {
+    #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
+    pub currency: Currency,
+    #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
+    pub dest_public_key: PublicKey,
+    #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
+    pub dest_payment: u128,
+    #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
+    pub max_total_payment: u128,
+    #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
+    pub max_fees: u128,
+    #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
+    pub period_secs: u64,
+    /// Time of the next execution, in seconds since the UNIX epoch
+    #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
+    pub next_payment_time: u64,
+    /// Total amount of credits paid so far (Not including fees)
+    #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
+    pub total_paid: u128,
+    /// Subscription description
+    pub description: String,
+    pub status: SubscriptionStatus,
+    /// Payment of the execution currently in progress
+    #[serde(with = "ser_option_b64")]
+    #[schemars(with = "Option<String>")]
+    pub opt_payment_id: Option<PaymentId>,
+    /// Chronological counter
+    pub generation: Generation,
 }
 
-#[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct CompactReport {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub local_public_key: PublicKey,
     pub index_servers: Vec<NamedIndexServerAddress>,
     #[serde(with = "ser_option_b64")]
+    #[schemars(with = "Option<String>")]
     pub opt_connected_index_server: Option<PublicKey>,
     pub relays: Vec<NamedRelayAddress>,
     #[serde(with = "ser_map_b64_any")]
+    #[schemars(with = "HashMap<String, FriendReport>")]
     pub friends: HashMap<PublicKey, FriendReport>,
     /// Seller's open invoices:
     #[serde(with = "ser_map_b64_any")]
+    #[schemars(with = "HashMap<String, OpenInvoice>")]
     pub open_invoices: HashMap<InvoiceId, OpenInvoice>,
     /// Buyer's open payments:
     #[serde(with = "ser_map_b64_any")]
+    #[schemars(with = "HashMap<String, OpenPayment>")]
     pub open_payments: HashMap<PaymentId, OpenPayment>,
+    /// Buyer's subscriptions:
+    #[serde(with = "ser_map_b64_any")]
+    #[schemars(with = "HashMap<String, Subscription>")]
+    pub subscriptions: HashMap<Uid, Subscription>,
 }
 
 #[allow(clippy::large_enum_variant)]
-#[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub enum CompactToUserAck {
     /// Acknowledge the receipt of `UserToCompact`
     /// Should be sent after `Report`, in case any changes occured.
-    Ack(#[serde(with = "ser_b64")] Uid),
+    Ack(
+        #[serde(with = "ser_b64")]
+        #[schemars(with = "String")]
+        Uid,
+    ),
     CompactToUser(CompactToUser),
 }
 
 #[allow(clippy::large_enum_variant)]
-#[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Arbitrary, Clone, Debug, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub enum CompactToUser {
     // TODO: Maybe in the future we will not need most of the message here,
@@ -488,6 +758,8 @@ pub enum CompactToUser {
     PaymentCommit(PaymentCommit),
     /// Done: Possibly returns a Receipt or failure
     PaymentDone(PaymentDone),
+    /// A subscription has started a new payment
+    SubscriptionPayment(SubscriptionPayment),
     // ------------[Reports]-------------------
     /// Reports about current state:
     Report(CompactReport),
@@ -496,27 +768,32 @@ pub enum CompactToUser {
 }
 
 #[allow(clippy::large_enum_variant)]
-#[derive(Arbitrary, Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[derive(Arbitrary, Debug, PartialEq, Eq, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub enum UserToCompact {
     // ----------------[Configuration]-----------------------
     /// Manage locally used relays:
     AddRelay(NamedRelayAddress),
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     RemoveRelay(PublicKey),
     /// Manage index servers:
     AddIndexServer(NamedIndexServerAddress),
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     RemoveIndexServer(PublicKey),
     /// Friend management:
     AddFriend(AddFriend),
     SetFriendRelays(SetFriendRelays),
     SetFriendName(SetFriendName),
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     RemoveFriend(PublicKey),
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     EnableFriend(PublicKey),
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     DisableFriend(PublicKey),
     OpenFriendCurrency(OpenFriendCurrency),
     CloseFriendCurrency(CloseFriendCurrency),
@@ -530,27 +807,51 @@ pub enum UserToCompact {
     // Confirm sending fees:
     ConfirmPaymentFees(ConfirmPaymentFees),
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     CancelPayment(PaymentId),
     AckPaymentDone(
-        #[serde(with = "ser_b64")] PaymentId,
-        #[serde(with = "ser_b64")] Uid,
+        #[serde(with = "ser_b64")]
+        #[schemars(with = "String")]
+        PaymentId,
+        #[serde(with = "ser_b64")]
+        #[schemars(with = "String")]
+        Uid,
     ), // (payment_id, ack_uid)
+    // Repeated payments:
+    AddSubscription(AddSubscription),
+    #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
+    PauseSubscription(Uid),
+    #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
+    ResumeSubscription(Uid),
+    #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
+    CancelSubscription(Uid),
     // ---------------[Seller]------------------------------
     AddInvoice(AddInvoice),
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     CancelInvoice(InvoiceId),
     RequestVerifyCommit(RequestVerifyCommit),
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     CommitInvoice(InvoiceId),
+    HoldInvoice(HoldInvoice),
+    EnablePushPayments(EnablePushPayments),
+    #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
+    DisablePushPayments(Currency),
     // ---------------[Verification]------------------------
     // TODO: Add API for verification of receipt and last token?
 }
 
-#[derive(Arbitrary, Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+#[derive(Arbitrary, Debug, PartialEq, Eq, Clone, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct UserToCompactAck {
     // TODO: Possibly rename to `request_id`?
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub user_request_id: Uid,
     pub inner: UserToCompact,
 }
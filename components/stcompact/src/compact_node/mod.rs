@@ -1,12 +1,14 @@
 mod convert;
 mod handle_node;
 mod handle_user;
+mod hold;
 pub mod messages;
 mod permission;
 mod persist;
 mod server;
 mod server_init;
 mod server_loop;
+mod subscription;
 mod types;
 mod utils;
 
@@ -24,10 +24,17 @@ pub fn check_permission(user_request: &UserToCompact, app_permissions: &AppPermi
         UserToCompact::InitPayment(_)
         | UserToCompact::ConfirmPaymentFees(_)
         | UserToCompact::CancelPayment(_)
-        | UserToCompact::AckPaymentDone(_, _) => app_permissions.buyer,
+        | UserToCompact::AckPaymentDone(_, _)
+        | UserToCompact::AddSubscription(_)
+        | UserToCompact::PauseSubscription(_)
+        | UserToCompact::ResumeSubscription(_)
+        | UserToCompact::CancelSubscription(_) => app_permissions.buyer,
         UserToCompact::AddInvoice(_)
         | UserToCompact::CancelInvoice(_)
-        | UserToCompact::CommitInvoice(_) => app_permissions.seller,
+        | UserToCompact::CommitInvoice(_)
+        | UserToCompact::HoldInvoice(_)
+        | UserToCompact::EnablePushPayments(_)
+        | UserToCompact::DisablePushPayments(_) => app_permissions.seller,
         UserToCompact::RequestVerifyCommit(_) => true,
     }
 }
@@ -4,7 +4,7 @@ use serde::{Deserialize, Serialize};
 
 use common::mutable_state::MutableState;
 use common::never::Never;
-use common::ser_utils::{ser_b64, ser_map_b64_any, ser_string};
+use common::ser_utils::{ser_b64, ser_map_b64_any, ser_option_b64, ser_string};
 
 use app::common::{Commit, Currency, InvoiceId, MultiRoute, PaymentId, PublicKey, Receipt, Uid};
 
@@ -24,6 +24,8 @@ pub struct OpenInvoice {
     /// Optional commit that we got for this invoice
     /// This allows to hold a commit for a while before applying it.
     pub opt_commit: Option<Commit>,
+    /// Time at which a held invoice is canceled by the node, in seconds since the UNIX epoch.
+    pub opt_hold_expiry: Option<u64>,
     /// A counter used to sort items chronologically.
     pub generation: Generation,
 }
@@ -76,6 +78,55 @@ pub struct OpenPayment {
     pub generation: Generation,
     /// Current status of open payment
     pub status: OpenPaymentStatus,
+    /// The subscription that initiated this payment (If any).
+    /// Payments of subscriptions are push payments, and their fees are confirmed automatically.
+    #[serde(default, with = "ser_option_b64")]
+    pub opt_subscription_id: Option<Uid>,
+}
+
+#[derive(Arbitrary, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub enum SubscriptionStatus {
+    /// Payments are executed according to schedule
+    Active,
+    /// Payments are suspended until the subscription is resumed
+    Paused,
+    /// The amount cap was reached. No more payments will be executed.
+    Exhausted,
+}
+
+/// Repeated payments to a destination, executed on a fixed schedule.
+#[derive(Arbitrary, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Subscription {
+    #[serde(with = "ser_string")]
+    pub currency: Currency,
+    #[serde(with = "ser_b64")]
+    pub dest_public_key: PublicKey,
+    /// Amount of credits paid in every execution
+    #[serde(with = "ser_string")]
+    pub dest_payment: u128,
+    /// Maximum total amount of credits paid over all executions (Not including fees)
+    #[serde(with = "ser_string")]
+    pub max_total_payment: u128,
+    /// Maximum fees we automatically agree to pay for a single execution
+    #[serde(with = "ser_string")]
+    pub max_fees: u128,
+    /// Time between consecutive executions, in seconds
+    #[serde(with = "ser_string")]
+    pub period_secs: u64,
+    /// Time of the next execution, in seconds since the UNIX epoch
+    #[serde(with = "ser_string")]
+    pub next_payment_time: u64,
+    /// Total amount of credits paid successfully so far (Not including fees)
+    #[serde(with = "ser_string")]
+    pub total_paid: u128,
+    /// Subscription description
+    pub description: String,
+    pub status: SubscriptionStatus,
+    /// Payment of the execution currently in progress
+    #[serde(with = "ser_option_b64")]
+    pub opt_payment_id: Option<PaymentId>,
+    /// A counter used to sort items chronologically.
+    pub generation: Generation,
 }
 
 #[derive(Arbitrary, Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -86,6 +137,9 @@ pub struct CompactState {
     /// Buyer's open payments:
     #[serde(with = "ser_map_b64_any")]
     pub open_payments: HashMap<PaymentId, OpenPayment>,
+    /// Buyer's subscriptions (Repeated payments):
+    #[serde(default, with = "ser_map_b64_any")]
+    pub subscriptions: HashMap<Uid, Subscription>,
     /// Next generation value for a newly created item.
     pub generation: Generation,
 }
@@ -95,6 +149,7 @@ impl CompactState {
         Self {
             open_invoices: HashMap::new(),
             open_payments: HashMap::new(),
+            subscriptions: HashMap::new(),
             generation: Generation(0),
         }
     }
@@ -1,8 +1,9 @@
 use database::DatabaseClient;
+use timer::TimerClient;
 
 use app::conn::AppConnTuple;
 
-use crate::gen::GenUid;
+use crate::gen::{GenInvoiceId, GenPaymentId, GenUid};
 
 use crate::compact_node::{
     persist::CompactState,
@@ -16,9 +17,10 @@ pub async fn compact_node<CG>(
     mut compact_state: CompactState,
     mut database_client: DatabaseClient<CompactState>,
     mut compact_gen: CG,
+    timer_client: TimerClient,
 ) -> Result<(), CompactNodeError>
 where
-    CG: GenUid,
+    CG: GenUid + GenPaymentId + GenInvoiceId,
 {
     compact_node_init(
         &mut app_conn_tuple,
@@ -34,6 +36,7 @@ where
         compact_state,
         database_client,
         compact_gen,
+        timer_client,
     )
     .await
 }
@@ -108,17 +108,20 @@ where
                     .await
                     .map_err(|_| CompactNodeError::AppSenderError)?;
 
-                // Resend commit to user:
-                let payment_commit = PaymentCommit {
-                    payment_id: payment_id.clone(),
-                    commit: commit.clone().into(),
-                };
-                let compact_to_user = CompactToUser::PaymentCommit(payment_commit);
-                conn_pair_compact
-                    .sender
-                    .send(CompactToUserAck::CompactToUser(compact_to_user))
-                    .await
-                    .map_err(|_| CompactNodeError::UserSenderError)?;
+                // Resend commit to user.
+                // (Subscription payments are push payments, there is no commit to hand over):
+                if open_payment.opt_subscription_id.is_none() {
+                    let payment_commit = PaymentCommit {
+                        payment_id: payment_id.clone(),
+                        commit: commit.clone().into(),
+                    };
+                    let compact_to_user = CompactToUser::PaymentCommit(payment_commit);
+                    conn_pair_compact
+                        .sender
+                        .send(CompactToUserAck::CompactToUser(compact_to_user))
+                        .await
+                        .map_err(|_| CompactNodeError::UserSenderError)?;
+                }
             }
             OpenPaymentStatus::Success(receipt, fees, ack_uid) => {
                 // Resend success to user:
@@ -4,6 +4,7 @@ use common::conn::BoxStream;
 use common::select_streams::select_streams;
 
 use database::DatabaseClient;
+use timer::TimerClient;
 
 use app::conn::AppConnTuple;
 
@@ -14,8 +15,10 @@ use crate::compact_node::types::{
 
 use crate::compact_node::handle_node::handle_node;
 use crate::compact_node::handle_user::handle_user;
+use crate::compact_node::hold::expire_held_invoices;
 use crate::compact_node::permission::check_permission;
-use crate::gen::GenUid;
+use crate::compact_node::subscription::handle_timer_tick;
+use crate::gen::{GenInvoiceId, GenPaymentId, GenUid};
 
 /// The compact server is mediating between the user and the node.
 async fn inner_compact_node_loop<CG>(
@@ -24,10 +27,11 @@ async fn inner_compact_node_loop<CG>(
     compact_state: CompactState,
     database_client: DatabaseClient<CompactState>,
     mut compact_gen: CG,
+    mut timer_client: TimerClient,
     mut opt_event_sender: Option<mpsc::Sender<()>>,
 ) -> Result<(), CompactNodeError>
 where
-    CG: GenUid,
+    CG: GenUid + GenPaymentId + GenInvoiceId,
 {
     // Interaction with the user:
     let (mut user_sender, user_receiver) = conn_pair_compact.split();
@@ -43,7 +47,15 @@ where
         .map(CompactServerEvent::Node)
         .chain(stream::once(future::ready(CompactServerEvent::NodeClosed)));
 
-    let mut incoming_events = select_streams![user_receiver, app_receiver];
+    // Timer ticks are used to trigger subscription payments:
+    let timer_stream = timer_client
+        .request_timer_stream("compact_node_loop".to_owned())
+        .await
+        .map_err(|_| CompactNodeError::RequestTimerStreamError)?
+        .map(|_| CompactServerEvent::TimerTick)
+        .chain(stream::once(future::ready(CompactServerEvent::TimerClosed)));
+
+    let mut incoming_events = select_streams![user_receiver, app_receiver, timer_stream];
 
     let mut server_state = CompactServerState::new(node_report, compact_state, database_client);
 
@@ -77,6 +89,17 @@ where
                 .await?
             }
             CompactServerEvent::NodeClosed => return Ok(()),
+            CompactServerEvent::TimerTick => {
+                expire_held_invoices(&mut server_state, &mut user_sender).await?;
+                handle_timer_tick(
+                    &mut server_state,
+                    &mut compact_gen,
+                    &mut user_sender,
+                    &mut app_sender,
+                )
+                .await?
+            }
+            CompactServerEvent::TimerClosed => return Ok(()),
         }
         if let Some(ref mut event_sender) = opt_event_sender {
             let _ = event_sender.send(()).await;
@@ -91,9 +114,10 @@ pub async fn compact_node_loop<CG>(
     compact_state: CompactState,
     database_client: DatabaseClient<CompactState>,
     compact_gen: CG,
+    timer_client: TimerClient,
 ) -> Result<(), CompactNodeError>
 where
-    CG: GenUid,
+    CG: GenUid + GenPaymentId + GenInvoiceId,
 {
     inner_compact_node_loop(
         app_conn_tuple,
@@ -101,6 +125,7 @@ where
         compact_state,
         database_client,
         compact_gen,
+        timer_client,
         None,
     )
     .await
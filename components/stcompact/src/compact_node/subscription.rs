@@ -0,0 +1,171 @@
+use futures::{Sink, SinkExt};
+
+use app::conn::{routes, AppToAppServer};
+
+use crate::compact_node::messages::{CompactToUser, CompactToUserAck, SubscriptionPayment};
+use crate::compact_node::persist::{
+    CompactState, OpenPayment, OpenPaymentStatus, SubscriptionStatus,
+};
+use crate::compact_node::types::{CompactNodeError, CompactServerState};
+use crate::compact_node::utils::{now_secs, update_send_compact_state};
+use crate::gen::{GenInvoiceId, GenPaymentId, GenUid};
+
+/// Update subscriptions according to the payments they initiated:
+/// - A successful payment is added to the subscription's `total_paid`.
+/// - A done (or forgotten) payment allows the subscription to start a new payment.
+pub fn sync_subscriptions(compact_state: &mut CompactState) {
+    for subscription in compact_state.subscriptions.values_mut() {
+        let payment_id = if let Some(payment_id) = &subscription.opt_payment_id {
+            payment_id
+        } else {
+            continue;
+        };
+
+        match compact_state
+            .open_payments
+            .get(payment_id)
+            .map(|open_payment| &open_payment.status)
+        {
+            Some(OpenPaymentStatus::SearchingRoute(_))
+            | Some(OpenPaymentStatus::FoundRoute(_))
+            | Some(OpenPaymentStatus::Sending(_))
+            | Some(OpenPaymentStatus::Commit(_, _)) => {
+                // Payment is still in progress
+            }
+            Some(OpenPaymentStatus::Success(_, _, _)) => {
+                subscription.total_paid = subscription
+                    .total_paid
+                    .saturating_add(subscription.dest_payment);
+                subscription.opt_payment_id = None;
+            }
+            Some(OpenPaymentStatus::Failure(_)) | None => {
+                subscription.opt_payment_id = None;
+            }
+        }
+    }
+}
+
+/// Calculate the first payment time after `now`, according to the subscription's schedule.
+/// Executions that were missed (For example, if the node was offline) are skipped.
+fn next_payment_time(next_payment_time: u64, period_secs: u64, now: u64) -> u64 {
+    if next_payment_time > now {
+        return next_payment_time;
+    }
+    let period_secs = period_secs.max(1);
+    let missed = (now - next_payment_time) / period_secs + 1;
+    next_payment_time.saturating_add(missed.saturating_mul(period_secs))
+}
+
+/// Start a new payment for every active subscription that is due.
+pub async fn handle_timer_tick<CG, US, AS>(
+    server_state: &mut CompactServerState,
+    compact_gen: &mut CG,
+    user_sender: &mut US,
+    app_sender: &mut AS,
+) -> Result<(), CompactNodeError>
+where
+    CG: GenUid + GenPaymentId + GenInvoiceId,
+    US: Sink<CompactToUserAck> + Unpin,
+    AS: Sink<AppToAppServer> + Unpin,
+{
+    let mut compact_state = server_state.compact_state().clone();
+    sync_subscriptions(&mut compact_state);
+
+    let now = now_secs();
+    let mut new_payments = Vec::new();
+    for (subscription_id, subscription) in &mut compact_state.subscriptions {
+        if subscription.status != SubscriptionStatus::Active
+            || subscription.opt_payment_id.is_some()
+            || subscription.next_payment_time > now
+        {
+            continue;
+        }
+
+        // Make sure we do not pay more than the subscription's cap:
+        let exceeds_cap = subscription
+            .total_paid
+            .checked_add(subscription.dest_payment)
+            .map_or(true, |total| total > subscription.max_total_payment);
+        if exceeds_cap {
+            subscription.status = SubscriptionStatus::Exhausted;
+            continue;
+        }
+
+        let payment_id = compact_gen.gen_payment_id();
+        subscription.opt_payment_id = Some(payment_id.clone());
+        subscription.next_payment_time = next_payment_time(
+            subscription.next_payment_time,
+            subscription.period_secs,
+            now,
+        );
+        new_payments.push((subscription_id.clone(), payment_id, subscription.clone()));
+    }
+
+    let mut requests = Vec::new();
+    for (subscription_id, payment_id, subscription) in new_payments {
+        let request_routes_id = compact_gen.gen_uid();
+        let open_payment = OpenPayment {
+            // The seller does not know about this payment in advance, so we pick an
+            // invoice_id ourselves:
+            invoice_id: compact_gen.gen_invoice_id(),
+            currency: subscription.currency.clone(),
+            dest_public_key: subscription.dest_public_key.clone(),
+            dest_payment: subscription.dest_payment,
+            description: subscription.description.clone(),
+            generation: compact_state.generation.advance(),
+            status: OpenPaymentStatus::SearchingRoute(request_routes_id.clone()),
+            opt_subscription_id: Some(subscription_id.clone()),
+        };
+        compact_state
+            .open_payments
+            .insert(payment_id.clone(), open_payment);
+        requests.push((subscription_id, payment_id, request_routes_id, subscription));
+    }
+
+    // Order:
+    // - Update local database
+    // - Request routes
+    //
+    // If a crash happens before routes are requested, the payment will be cancelled on the next
+    // startup.
+    update_send_compact_state(compact_state, server_state, user_sender).await?;
+
+    for (subscription_id, payment_id, request_routes_id, subscription) in requests {
+        let opt_exclude = None;
+        let app_request = routes::request_routes(
+            request_routes_id,
+            subscription.currency.clone(),
+            subscription.currency,
+            subscription.dest_payment,
+            server_state
+                .node_report()
+                .funder_report
+                .local_public_key
+                .clone(),
+            subscription.dest_public_key,
+            opt_exclude,
+        );
+
+        let app_to_app_server = AppToAppServer {
+            // This is an `app_request_id` we don't need to track:
+            app_request_id: compact_gen.gen_uid(),
+            app_request,
+        };
+        app_sender
+            .send(app_to_app_server)
+            .await
+            .map_err(|_| CompactNodeError::AppSenderError)?;
+
+        // Notify the user about the new payment:
+        let subscription_payment = SubscriptionPayment {
+            subscription_id,
+            payment_id,
+        };
+        let compact_to_user = CompactToUser::SubscriptionPayment(subscription_payment);
+        user_sender
+            .send(CompactToUserAck::CompactToUser(compact_to_user))
+            .await
+            .map_err(|_| CompactNodeError::UserSenderError)?;
+    }
+    Ok(())
+}
@@ -18,6 +18,8 @@ pub enum CompactServerEvent {
     UserClosed,
     Node(AppServerToApp),
     NodeClosed,
+    TimerTick,
+    TimerClosed,
 }
 
 #[derive(Debug)]
@@ -26,6 +28,7 @@ pub enum CompactNodeError {
     UserSenderError,
     ReportMutationError,
     DatabaseMutateError,
+    RequestTimerStreamError,
 }
 
 pub struct CompactServerState {
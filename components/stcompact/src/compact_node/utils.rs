@@ -1,19 +1,39 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
 use futures::{Sink, SinkExt};
 
+use app::common::{MultiRoute, PaymentId, Uid};
+use app::conn::{buyer, AppToAppServer};
+
+use route::{route_fees, MultiRouteChoice};
+
 use crate::compact_node::create_compact_report;
 use crate::compact_node::messages::{CompactToUser, CompactToUserAck};
-use crate::compact_node::persist::CompactState;
+use crate::compact_node::persist::{CompactState, OpenPaymentStatus, OpenPaymentStatusSending};
+use crate::compact_node::subscription::sync_subscriptions;
 use crate::compact_node::types::{CompactNodeError, CompactServerState};
+use crate::gen::GenUid;
+
+/// Current time, in seconds since the UNIX epoch
+pub fn now_secs() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs()
+}
 
 /// Update compact state, and send compact report to user if necessary
 pub async fn update_send_compact_state<US>(
-    compact_state: CompactState,
+    mut compact_state: CompactState,
     server_state: &mut CompactServerState,
     user_sender: &mut US,
 ) -> Result<(), CompactNodeError>
 where
     US: Sink<CompactToUserAck> + Unpin,
 {
+    // Account for subscription payments that were completed:
+    sync_subscriptions(&mut compact_state);
+
     // Check if the old compact state is not the same as the new one
     if server_state.compact_state() != &compact_state {
         server_state.update_compact_state(compact_state).await?;
@@ -30,3 +50,119 @@ where
     }
     Ok(())
 }
+
+/// Send a payment along a multi route, after the fees were confirmed.
+/// `app_request_id` is attached to the final `RequestClosePayment` request.
+#[allow(clippy::too_many_arguments)]
+pub async fn send_payment<CG, US, AS>(
+    payment_id: PaymentId,
+    multi_route: MultiRoute,
+    multi_route_choice: MultiRouteChoice,
+    fees: u128,
+    app_request_id: Uid,
+    mut compact_state: CompactState,
+    server_state: &mut CompactServerState,
+    compact_gen: &mut CG,
+    user_sender: &mut US,
+    app_sender: &mut AS,
+) -> Result<(), CompactNodeError>
+where
+    CG: GenUid,
+    US: Sink<CompactToUserAck> + Unpin,
+    AS: Sink<AppToAppServer> + Unpin,
+{
+    // Order:
+    // - Update local database
+    // - Send requests along routes
+
+    // Update compact_state:
+    let open_transactions: Vec<Uid> = multi_route_choice
+        .iter()
+        .map(|_| compact_gen.gen_uid())
+        .collect();
+
+    let sending = OpenPaymentStatusSending {
+        fees,
+        open_transactions: open_transactions.clone().into_iter().collect(),
+    };
+
+    let open_payment = compact_state.open_payments.get_mut(&payment_id).unwrap();
+
+    open_payment.status = OpenPaymentStatus::Sending(sending);
+    let c_open_payment = open_payment.clone();
+
+    update_send_compact_state(compact_state, server_state, user_sender).await?;
+
+    // Create a new payment.
+    // Payments of subscriptions are sent as push payments, as there is no invoice:
+    let app_request = if c_open_payment.opt_subscription_id.is_some() {
+        buyer::create_push_payment(
+            payment_id.clone(),
+            c_open_payment.invoice_id,
+            c_open_payment.currency,
+            c_open_payment.dest_payment,
+            c_open_payment.dest_public_key,
+        )
+    } else {
+        buyer::create_payment(
+            payment_id.clone(),
+            c_open_payment.invoice_id,
+            c_open_payment.currency,
+            c_open_payment.dest_payment,
+            c_open_payment.dest_public_key,
+        )
+    };
+
+    let app_to_app_server = AppToAppServer {
+        // This is an `app_request_id` we don't need to track:
+        app_request_id: compact_gen.gen_uid(),
+        app_request,
+    };
+    app_sender
+        .send(app_to_app_server)
+        .await
+        .map_err(|_| CompactNodeError::AppSenderError)?;
+
+    // Initiate requests along all routes in the multi route, where credits
+    // are allocated according to the strategy in `multi_route_choice`:
+    for ((route_index, dest_payment), request_id) in
+        multi_route_choice.iter().cloned().zip(open_transactions)
+    {
+        let route = &multi_route.routes[route_index];
+
+        let app_request = buyer::create_transaction(
+            payment_id.clone(),
+            request_id,
+            route.route.clone(),
+            dest_payment,
+            route_fees(route, dest_payment).unwrap(),
+            route
+                .exchanges
+                .iter()
+                .map(|route_exchange| route_exchange.currency_exchange.clone())
+                .collect(),
+        );
+
+        let app_to_app_server = AppToAppServer {
+            // We don't really care about app_request_id here, as we can wait on `request_id`
+            // instead.
+            app_request_id: compact_gen.gen_uid(),
+            app_request,
+        };
+        app_sender
+            .send(app_to_app_server)
+            .await
+            .map_err(|_| CompactNodeError::AppSenderError)?;
+    }
+
+    // Send RequestClosePayment, as we are not going to send any more transactions:
+    let app_request = buyer::request_close_payment(payment_id);
+    let app_to_app_server = AppToAppServer {
+        app_request_id,
+        app_request,
+    };
+    app_sender
+        .send(app_to_app_server)
+        .await
+        .map_err(|_| CompactNodeError::AppSenderError)
+}
@@ -1,18 +1,21 @@
 use crypto::rand::{CryptoRandom, RandGen};
 
-use app::common::{PrivateKey, Uid};
+use app::common::{InvoiceId, PaymentId, PrivateKey, Uid};
 
 pub trait GenUid {
     /// Generate a Uid
     fn gen_uid(&mut self) -> Uid;
 }
 
-/*
 pub trait GenPaymentId {
     /// Generate a PaymentId
     fn gen_payment_id(&mut self) -> PaymentId;
 }
-*/
+
+pub trait GenInvoiceId {
+    /// Generate an InvoiceId
+    fn gen_invoice_id(&mut self) -> InvoiceId;
+}
 
 pub trait GenPrivateKey {
     /// Generate private key
@@ -22,7 +25,7 @@ pub trait GenPrivateKey {
 // TODO: Find a way to eliminate this shim.
 // Possibly have all the crates use traits like GenUid, GenPrivateKey, GenNonce etc?
 /// A wrapper over a random generator that implements
-/// GenUid and GenPrivateKey
+/// GenUid, GenPaymentId, GenInvoiceId and GenPrivateKey
 pub struct GenCryptoRandom<R>(pub R);
 
 impl<R> GenPrivateKey for GenCryptoRandom<R>
@@ -42,3 +45,21 @@ where
         Uid::rand_gen(&mut self.0)
     }
 }
+
+impl<R> GenPaymentId for GenCryptoRandom<R>
+where
+    R: CryptoRandom,
+{
+    fn gen_payment_id(&mut self) -> PaymentId {
+        PaymentId::rand_gen(&mut self.0)
+    }
+}
+
+impl<R> GenInvoiceId for GenCryptoRandom<R>
+where
+    R: CryptoRandom,
+{
+    fn gen_invoice_id(&mut self) -> InvoiceId {
+        InvoiceId::rand_gen(&mut self.0)
+    }
+}
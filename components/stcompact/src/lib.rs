@@ -27,6 +27,8 @@ pub mod compact_node;
 
 mod gen;
 pub mod messages;
+pub mod multi_user;
+pub mod schema;
 pub mod server_loop;
 pub mod store;
 
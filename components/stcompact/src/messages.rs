@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::hash::Hash;
 
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use common::ser_utils::{ser_b64, ser_string};
@@ -10,13 +11,17 @@ use app::conn::AppPermissions;
 
 use crate::compact_node::messages::{CompactReport, CompactToUser, UserToCompact};
 
-#[derive(Arbitrary, Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
+#[derive(Arbitrary, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Hash, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct NodeName(String);
 
-#[derive(Arbitrary, Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone)]
+#[derive(Arbitrary, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq, Hash, Clone)]
 #[serde(rename_all = "camelCase")]
-pub struct NodeId(#[serde(with = "ser_string")] pub u64);
+pub struct NodeId(
+    #[serde(with = "ser_string")]
+    #[schemars(with = "String")]
+    pub u64,
+);
 
 impl NodeName {
     #[allow(unused)]
@@ -29,24 +34,27 @@ impl NodeName {
     }
 }
 
-#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct NodeInfoLocal {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub node_public_key: PublicKey,
 }
 
-#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct NodeInfoRemote {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub app_public_key: PublicKey,
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub node_public_key: PublicKey,
     pub node_address: NetAddress,
 }
 
-#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub enum NodeInfo {
     Local(NodeInfoLocal),
@@ -54,14 +62,14 @@ pub enum NodeInfo {
 }
 
 #[allow(clippy::large_enum_variant)]
-#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub enum NodeMode {
     Open(NodeId),
     Closed,
 }
 
-#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct NodeStatus {
     pub mode: NodeMode,
@@ -69,26 +77,28 @@ pub struct NodeStatus {
     pub info: NodeInfo,
 }
 
-#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateNodeLocal {
     pub node_name: NodeName,
 }
 
-#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct CreateNodeRemote {
     pub node_name: NodeName,
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub app_private_key: PrivateKey,
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub node_public_key: PublicKey,
     pub node_address: NetAddress,
 }
 
 pub type NodesStatus = HashMap<NodeName, NodeStatus>;
 
-#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub enum CreateNode {
     CreateNodeLocal(CreateNodeLocal),
@@ -96,7 +106,7 @@ pub enum CreateNode {
 }
 
 #[allow(clippy::large_enum_variant)]
-#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct NodeOpened {
     pub node_name: NodeName,
@@ -106,7 +116,7 @@ pub struct NodeOpened {
 }
 
 #[allow(clippy::large_enum_variant)]
-#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub enum ServerToUser {
     /// Node was just opened
@@ -118,16 +128,17 @@ pub enum ServerToUser {
 }
 
 #[allow(clippy::large_enum_variant)]
-#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub enum ServerToUserAck {
     ServerToUser(ServerToUser),
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     Ack(Uid),
 }
 
 #[allow(clippy::large_enum_variant)]
-#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub enum UserToServer {
     CreateNode(CreateNode),
@@ -140,10 +151,11 @@ pub enum UserToServer {
     Node(NodeId, UserToCompact), // (node_id, user_to_compact)
 }
 
-#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Arbitrary, Clone, Debug, Serialize, Deserialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct UserToServerAck {
     #[serde(with = "ser_b64")]
+    #[schemars(with = "String")]
     pub request_id: Uid,
     pub inner: UserToServer,
 }
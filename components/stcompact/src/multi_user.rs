@@ -0,0 +1,665 @@
+use std::collections::HashMap;
+
+use futures::channel::mpsc;
+use futures::future::RemoteHandle;
+use futures::task::{Spawn, SpawnExt};
+use futures::{future, stream, FutureExt, SinkExt, Stream, StreamExt, TryFutureExt};
+
+use common::conn::{BoxStream, ConnPair};
+use common::select_streams::select_streams;
+
+use crypto::rand::{CryptoRandom, RandGen};
+
+use app::common::Uid;
+
+use crate::compact_node::messages::CompactToUser;
+use crate::messages::{
+    NodeId, NodeMode, NodeOpened, NodesStatus, ServerToUser, ServerToUserAck, UserToServerAck,
+};
+use crate::server_loop::ConnPairCompactServer;
+
+/// Amount of messages we buffer for a single user.
+/// A user that falls behind more than this amount of messages is disconnected.
+const USER_CHANNEL_LEN: usize = 0x40;
+
+/// Maximum amount of requests a single user may have waiting for an Ack from the server.
+/// A user that sends more requests is disconnected.
+const MAX_USER_PENDING_REQUESTS: usize = 0x40;
+
+/// The server side of a multi user connection: Receives messages from the users and sends
+/// messages to the users.
+pub type ConnPairMultiUser = ConnPair<UserToServerAck, ServerToUserAck>;
+
+#[derive(Debug)]
+pub enum MultiUserError {
+    SpawnError,
+    ServerSenderError,
+}
+
+struct User {
+    sender: mpsc::Sender<ServerToUserAck>,
+    /// Dropping the handle stops receiving messages from the user
+    _recv_handle: RemoteHandle<()>,
+}
+
+type UserId = u64;
+
+#[derive(Debug)]
+enum MultiUserEvent {
+    NewUser(ConnPairCompactServer),
+    IncomingUsersClosed,
+    User((UserId, UserToServerAck)),
+    UserClosed(UserId),
+    Server(ServerToUserAck),
+    ServerClosed,
+}
+
+/// The latest state the server has reported.
+/// Sent to every new user, so that it can catch up with the other users.
+#[derive(Debug, Default)]
+struct ServerView {
+    opt_nodes_status: Option<NodesStatus>,
+    opened_nodes: HashMap<NodeId, NodeOpened>,
+}
+
+impl ServerView {
+    fn update(&mut self, server_to_user: &ServerToUser) {
+        match server_to_user {
+            ServerToUser::NodeOpened(node_opened) => {
+                self.opened_nodes
+                    .insert(node_opened.node_id.clone(), node_opened.clone());
+            }
+            ServerToUser::NodesStatus(nodes_status) => {
+                // Forget about nodes that are not open anymore:
+                let open_node_ids = nodes_status
+                    .values()
+                    .filter_map(|node_status| match &node_status.mode {
+                        NodeMode::Open(node_id) => Some(node_id.clone()),
+                        NodeMode::Closed => None,
+                    })
+                    .collect::<Vec<_>>();
+                self.opened_nodes
+                    .retain(|node_id, _| open_node_ids.contains(node_id));
+                self.opt_nodes_status = Some(nodes_status.clone());
+            }
+            ServerToUser::Node(node_id, CompactToUser::Report(compact_report)) => {
+                if let Some(node_opened) = self.opened_nodes.get_mut(node_id) {
+                    node_opened.compact_report = compact_report.clone();
+                }
+            }
+            ServerToUser::Node(_, _) => {}
+        }
+    }
+
+    /// Messages that bring a new user up to date
+    fn catch_up_messages(&self) -> Vec<ServerToUserAck> {
+        let mut messages = Vec::new();
+        if let Some(nodes_status) = &self.opt_nodes_status {
+            messages.push(ServerToUserAck::ServerToUser(ServerToUser::NodesStatus(
+                nodes_status.clone(),
+            )));
+        }
+        for node_opened in self.opened_nodes.values() {
+            messages.push(ServerToUserAck::ServerToUser(ServerToUser::NodeOpened(
+                node_opened.clone(),
+            )));
+        }
+        messages
+    }
+}
+
+/// A request that was sent to the server, waiting for an Ack
+struct PendingRequest {
+    user_id: UserId,
+    /// The request id chosen by the user
+    user_request_id: Uid,
+}
+
+struct MultiUser<R, S> {
+    users: HashMap<UserId, User>,
+    /// Requests sent to the server, keyed by the request id sent to the server.
+    /// Users choose their own request ids, and two users might choose the same request id.
+    /// Therefore every request is sent to the server with a new random request id, and the
+    /// Ack is translated back to the request id of the user that sent the request.
+    pending_requests: HashMap<Uid, PendingRequest>,
+    next_user_id: UserId,
+    server_view: ServerView,
+    /// Requests waiting to be sent to the server.
+    /// The amount of queued requests is bounded by the amount of pending requests.
+    server_sender: mpsc::UnboundedSender<UserToServerAck>,
+    event_sender: mpsc::Sender<MultiUserEvent>,
+    rng: R,
+    spawner: S,
+}
+
+impl<R, S> MultiUser<R, S>
+where
+    R: CryptoRandom,
+    S: Spawn,
+{
+    fn new(
+        server_sender: mpsc::UnboundedSender<UserToServerAck>,
+        event_sender: mpsc::Sender<MultiUserEvent>,
+        rng: R,
+        spawner: S,
+    ) -> Self {
+        MultiUser {
+            users: HashMap::new(),
+            pending_requests: HashMap::new(),
+            next_user_id: 0,
+            server_view: ServerView::default(),
+            server_sender,
+            event_sender,
+            rng,
+            spawner,
+        }
+    }
+
+    /// Send a message to a user.
+    /// A user that can not keep up with incoming messages is disconnected.
+    fn send_user(&mut self, user_id: UserId, server_to_user_ack: ServerToUserAck) {
+        let user = match self.users.get_mut(&user_id) {
+            Some(user) => user,
+            None => return,
+        };
+        if user.sender.try_send(server_to_user_ack).is_err() {
+            warn!("multi_user: Disconnecting user {}", user_id);
+            self.remove_user(user_id);
+        }
+    }
+
+    fn remove_user(&mut self, user_id: UserId) {
+        self.users.remove(&user_id);
+        self.pending_requests
+            .retain(|_request_id, pending_request| pending_request.user_id != user_id);
+    }
+
+    fn handle_new_user(&mut self, conn_pair: ConnPairCompactServer) -> Result<(), MultiUserError> {
+        let user_id = self.next_user_id;
+        self.next_user_id = self.next_user_id.wrapping_add(1);
+
+        let (mut user_sender, user_receiver) = conn_pair.split();
+
+        // Forward messages to the user:
+        let (sender, receiver) = mpsc::channel(USER_CHANNEL_LEN);
+        let send_fut = async move {
+            let _ = user_sender.send_all(&mut receiver.map(Ok)).await;
+        };
+        self.spawner
+            .spawn(send_fut)
+            .map_err(|_| MultiUserError::SpawnError)?;
+
+        // Forward messages from the user:
+        let mut c_event_sender = self.event_sender.clone();
+        let mut user_events = user_receiver
+            .map(move |user_to_server_ack| MultiUserEvent::User((user_id, user_to_server_ack)))
+            .chain(stream::once(future::ready(MultiUserEvent::UserClosed(
+                user_id,
+            ))))
+            .map(Ok);
+        let recv_fut = async move {
+            let _ = c_event_sender.send_all(&mut user_events).await;
+        };
+        let recv_handle = self
+            .spawner
+            .spawn_with_handle(recv_fut)
+            .map_err(|_| MultiUserError::SpawnError)?;
+
+        self.users.insert(
+            user_id,
+            User {
+                sender,
+                _recv_handle: recv_handle,
+            },
+        );
+
+        // Bring the new user up to date:
+        for server_to_user_ack in self.server_view.catch_up_messages() {
+            self.send_user(user_id, server_to_user_ack);
+        }
+        Ok(())
+    }
+
+    fn handle_user(
+        &mut self,
+        user_id: UserId,
+        user_to_server_ack: UserToServerAck,
+    ) -> Result<(), MultiUserError> {
+        if !self.users.contains_key(&user_id) {
+            // User was disconnected:
+            return Ok(());
+        }
+        let num_pending = self
+            .pending_requests
+            .values()
+            .filter(|pending_request| pending_request.user_id == user_id)
+            .count();
+        if num_pending >= MAX_USER_PENDING_REQUESTS {
+            warn!(
+                "multi_user: Disconnecting user {}: Too many pending requests",
+                user_id
+            );
+            self.remove_user(user_id);
+            return Ok(());
+        }
+        let UserToServerAck {
+            request_id: user_request_id,
+            inner,
+        } = user_to_server_ack;
+        let request_id = Uid::rand_gen(&mut self.rng);
+        self.pending_requests.insert(
+            request_id.clone(),
+            PendingRequest {
+                user_id,
+                user_request_id,
+            },
+        );
+        // We never wait for the server here, as the server might be waiting for us:
+        self.server_sender
+            .unbounded_send(UserToServerAck { request_id, inner })
+            .map_err(|_| MultiUserError::ServerSenderError)
+    }
+
+    fn handle_server(&mut self, server_to_user_ack: ServerToUserAck) {
+        match server_to_user_ack {
+            ServerToUserAck::Ack(request_id) => {
+                // Only the user that sent the request gets the Ack:
+                if let Some(pending_request) = self.pending_requests.remove(&request_id) {
+                    self.send_user(
+                        pending_request.user_id,
+                        ServerToUserAck::Ack(pending_request.user_request_id),
+                    );
+                }
+            }
+            ServerToUserAck::ServerToUser(server_to_user) => {
+                self.server_view.update(&server_to_user);
+                // Broadcast to all users:
+                let user_ids = self.users.keys().cloned().collect::<Vec<_>>();
+                for user_id in user_ids {
+                    self.send_user(
+                        user_id,
+                        ServerToUserAck::ServerToUser(server_to_user.clone()),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Serve multiple users over one server connection.
+/// Messages from the server are broadcast to all users, except for Acks, which are
+/// delivered only to the user that sent the corresponding request.
+pub async fn multi_user_loop<IU, R, S>(
+    server_conn_pair: ConnPairMultiUser,
+    incoming_users: IU,
+    rng: R,
+    spawner: S,
+) -> Result<(), MultiUserError>
+where
+    IU: Stream<Item = ConnPairCompactServer> + Send + Unpin,
+    R: CryptoRandom,
+    S: Spawn,
+{
+    let (mut server_sender, server_receiver) = server_conn_pair.split();
+    let (event_sender, event_receiver) = mpsc::channel(0);
+
+    // Forward requests to the server from a separate task, so that we can keep receiving
+    // messages from the server while it is busy:
+    let (requests_sender, requests_receiver) = mpsc::unbounded();
+    let forward_fut = async move {
+        let _ = server_sender.send_all(&mut requests_receiver.map(Ok)).await;
+    };
+    let _forward_handle = spawner
+        .spawn_with_handle(forward_fut)
+        .map_err(|_| MultiUserError::SpawnError)?;
+
+    let mut multi_user = MultiUser::new(requests_sender, event_sender, rng, spawner);
+
+    let server_receiver = server_receiver
+        .map(MultiUserEvent::Server)
+        .chain(stream::once(future::ready(MultiUserEvent::ServerClosed)));
+
+    let incoming_users = incoming_users
+        .map(MultiUserEvent::NewUser)
+        .chain(stream::once(future::ready(
+            MultiUserEvent::IncomingUsersClosed,
+        )));
+
+    let mut incoming_events = select_streams![event_receiver, server_receiver, incoming_users];
+
+    while let Some(event) = incoming_events.next().await {
+        match event {
+            MultiUserEvent::NewUser(conn_pair) => multi_user.handle_new_user(conn_pair)?,
+            MultiUserEvent::IncomingUsersClosed => {
+                // We keep serving the users that are already connected:
+                info!("multi_user_loop(): incoming users closed");
+            }
+            MultiUserEvent::User((user_id, user_to_server_ack)) => {
+                multi_user.handle_user(user_id, user_to_server_ack)?
+            }
+            MultiUserEvent::UserClosed(user_id) => multi_user.remove_user(user_id),
+            MultiUserEvent::Server(server_to_user_ack) => {
+                multi_user.handle_server(server_to_user_ack)
+            }
+            MultiUserEvent::ServerClosed => {
+                info!("multi_user_loop(): server closed");
+                break;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Spawn a multi user loop.
+/// Returns a connection to be used by the server.
+pub fn create_multi_user<IU, R, S>(
+    incoming_users: IU,
+    rng: R,
+    spawner: &S,
+) -> Result<ConnPairCompactServer, MultiUserError>
+where
+    IU: Stream<Item = ConnPairCompactServer> + Send + Unpin + 'static,
+    R: CryptoRandom + Send + 'static,
+    S: Spawn + Clone + Send + 'static,
+{
+    let (server_sender, multi_user_receiver) = mpsc::channel(1);
+    let (multi_user_sender, server_receiver) = mpsc::channel(1);
+
+    let loop_fut = multi_user_loop(
+        ConnPairMultiUser::from_raw(multi_user_sender, multi_user_receiver),
+        incoming_users,
+        rng,
+        spawner.clone(),
+    )
+    .map_err(|e| error!("multi_user_loop() error: {:?}", e))
+    .map(|_| ());
+
+    spawner
+        .spawn(loop_fut)
+        .map_err(|_| MultiUserError::SpawnError)?;
+
+    Ok(ConnPairCompactServer::from_raw(
+        server_sender,
+        server_receiver,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::executor::{block_on, ThreadPool};
+
+    use crypto::test_utils::DummyRandom;
+
+    use crate::messages::{NodeName, UserToServer};
+
+    /// Create a connected pair of (user side, server side) connections
+    fn create_user_conn_pair() -> (
+        ConnPair<UserToServerAck, ServerToUserAck>,
+        ConnPairCompactServer,
+    ) {
+        let (user_sender, server_receiver) = mpsc::channel(1);
+        let (server_sender, user_receiver) = mpsc::channel(1);
+        (
+            ConnPair::from_raw(user_sender, user_receiver),
+            ConnPairCompactServer::from_raw(server_sender, server_receiver),
+        )
+    }
+
+    async fn task_multi_user_basic<S>(spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let (mut new_user_sender, incoming_users) = mpsc::channel(0);
+        let (mut server_sender, mut server_receiver) =
+            create_multi_user(incoming_users, DummyRandom::new(&[1u8]), &spawner)
+                .unwrap()
+                .split();
+
+        // Server sends initial nodes status, before any user is connected:
+        let nodes_status = NodesStatus::new();
+        server_sender
+            .send(ServerToUserAck::ServerToUser(ServerToUser::NodesStatus(
+                nodes_status.clone(),
+            )))
+            .await
+            .unwrap();
+
+        let (user_a, server_user_a) = create_user_conn_pair();
+        let (mut user_a_sender, mut user_a_receiver) = user_a.split();
+        new_user_sender.send(server_user_a).await.unwrap();
+
+        // User gets the latest nodes status:
+        assert_eq!(
+            user_a_receiver.next().await.unwrap(),
+            ServerToUserAck::ServerToUser(ServerToUser::NodesStatus(nodes_status.clone()))
+        );
+
+        let (user_b, server_user_b) = create_user_conn_pair();
+        let (_user_b_sender, mut user_b_receiver) = user_b.split();
+        new_user_sender.send(server_user_b).await.unwrap();
+        assert_eq!(
+            user_b_receiver.next().await.unwrap(),
+            ServerToUserAck::ServerToUser(ServerToUser::NodesStatus(nodes_status.clone()))
+        );
+
+        // User A sends a request:
+        let request_id = Uid::from(&[1; Uid::len()]);
+        let user_to_server_ack = UserToServerAck {
+            request_id: request_id.clone(),
+            inner: UserToServer::EnableNode(NodeName::new("node".to_owned())),
+        };
+        user_a_sender
+            .send(user_to_server_ack.clone())
+            .await
+            .unwrap();
+        // The request is sent to the server with a request id chosen by the multi user loop:
+        let server_request = server_receiver.next().await.unwrap();
+        assert_eq!(server_request.inner, user_to_server_ack.inner);
+        let server_request_id = server_request.request_id;
+
+        // Server broadcasts a new nodes status, and then acks the request:
+        server_sender
+            .send(ServerToUserAck::ServerToUser(ServerToUser::NodesStatus(
+                nodes_status.clone(),
+            )))
+            .await
+            .unwrap();
+        server_sender
+            .send(ServerToUserAck::Ack(server_request_id))
+            .await
+            .unwrap();
+
+        // Both users get the broadcast:
+        let nodes_status_msg =
+            ServerToUserAck::ServerToUser(ServerToUser::NodesStatus(nodes_status.clone()));
+        assert_eq!(user_a_receiver.next().await.unwrap(), nodes_status_msg);
+        assert_eq!(user_b_receiver.next().await.unwrap(), nodes_status_msg);
+
+        // Only user A gets the ack:
+        assert_eq!(
+            user_a_receiver.next().await.unwrap(),
+            ServerToUserAck::Ack(request_id)
+        );
+
+        // User B should only get the next broadcast:
+        server_sender
+            .send(ServerToUserAck::ServerToUser(ServerToUser::NodesStatus(
+                nodes_status.clone(),
+            )))
+            .await
+            .unwrap();
+        assert_eq!(user_b_receiver.next().await.unwrap(), nodes_status_msg);
+        assert_eq!(user_a_receiver.next().await.unwrap(), nodes_status_msg);
+    }
+
+    #[test]
+    fn test_multi_user_basic() {
+        let thread_pool = ThreadPool::new().unwrap();
+        block_on(task_multi_user_basic(thread_pool.clone()));
+    }
+    async fn task_multi_user_busy_server<S>(spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let (mut new_user_sender, incoming_users) = mpsc::channel(0);
+        let (mut server_sender, mut server_receiver) =
+            create_multi_user(incoming_users, DummyRandom::new(&[1u8]), &spawner)
+                .unwrap()
+                .split();
+
+        let nodes_status_msg =
+            ServerToUserAck::ServerToUser(ServerToUser::NodesStatus(NodesStatus::new()));
+        server_sender.send(nodes_status_msg.clone()).await.unwrap();
+
+        let (user_a, server_user_a) = create_user_conn_pair();
+        let (mut user_a_sender, mut user_a_receiver) = user_a.split();
+        new_user_sender.send(server_user_a).await.unwrap();
+        assert_eq!(user_a_receiver.next().await.unwrap(), nodes_status_msg);
+
+        // User sends requests, while the server is busy sending messages:
+        let requests = (0..8u8)
+            .map(|i| UserToServerAck {
+                request_id: Uid::from(&[i; Uid::len()]),
+                inner: UserToServer::EnableNode(NodeName::new("node".to_owned())),
+            })
+            .collect::<Vec<_>>();
+        let c_requests = requests.clone();
+        spawner
+            .spawn(async move {
+                for request in c_requests {
+                    user_a_sender.send(request).await.unwrap();
+                }
+            })
+            .unwrap();
+
+        for _ in 0..8usize {
+            server_sender.send(nodes_status_msg.clone()).await.unwrap();
+        }
+        for _ in 0..8usize {
+            assert_eq!(user_a_receiver.next().await.unwrap(), nodes_status_msg);
+        }
+
+        // All the requests arrive at the server:
+        for request in requests {
+            assert_eq!(server_receiver.next().await.unwrap().inner, request.inner);
+        }
+    }
+
+    #[test]
+    fn test_multi_user_busy_server() {
+        let thread_pool = ThreadPool::new().unwrap();
+        block_on(task_multi_user_busy_server(thread_pool.clone()));
+    }
+
+    async fn task_multi_user_too_many_pending_requests<S>(spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let (mut new_user_sender, incoming_users) = mpsc::channel(0);
+        let (_server_sender, _server_receiver) =
+            create_multi_user(incoming_users, DummyRandom::new(&[1u8]), &spawner)
+                .unwrap()
+                .split();
+
+        let (user_a, server_user_a) = create_user_conn_pair();
+        let (mut user_a_sender, mut user_a_receiver) = user_a.split();
+        new_user_sender.send(server_user_a).await.unwrap();
+
+        // The server never acks the requests.
+        // The user is disconnected once it has too many pending requests:
+        let mut num_sent = 0usize;
+        loop {
+            let user_to_server_ack = UserToServerAck {
+                request_id: Uid::from(&[(num_sent % 0x100) as u8; Uid::len()]),
+                inner: UserToServer::EnableNode(NodeName::new("node".to_owned())),
+            };
+            if user_a_sender.send(user_to_server_ack).await.is_err() {
+                break;
+            }
+            num_sent += 1;
+            assert!(num_sent <= 2 * MAX_USER_PENDING_REQUESTS);
+        }
+        assert!(user_a_receiver.next().await.is_none());
+    }
+
+    async fn task_multi_user_same_request_id<S>(spawner: S)
+    where
+        S: Spawn + Clone + Send + 'static,
+    {
+        let (mut new_user_sender, incoming_users) = mpsc::channel(0);
+        let (mut server_sender, mut server_receiver) =
+            create_multi_user(incoming_users, DummyRandom::new(&[1u8]), &spawner)
+                .unwrap()
+                .split();
+
+        let (user_a, server_user_a) = create_user_conn_pair();
+        let (mut user_a_sender, mut user_a_receiver) = user_a.split();
+        new_user_sender.send(server_user_a).await.unwrap();
+
+        let (user_b, server_user_b) = create_user_conn_pair();
+        let (mut user_b_sender, mut user_b_receiver) = user_b.split();
+        new_user_sender.send(server_user_b).await.unwrap();
+
+        // Both users send a request with the same request id:
+        let request_id = Uid::from(&[1; Uid::len()]);
+        let user_to_server_ack_a = UserToServerAck {
+            request_id: request_id.clone(),
+            inner: UserToServer::EnableNode(NodeName::new("node_a".to_owned())),
+        };
+        let user_to_server_ack_b = UserToServerAck {
+            request_id: request_id.clone(),
+            inner: UserToServer::EnableNode(NodeName::new("node_b".to_owned())),
+        };
+        user_a_sender
+            .send(user_to_server_ack_a.clone())
+            .await
+            .unwrap();
+        let server_request_a = server_receiver.next().await.unwrap();
+        assert_eq!(server_request_a.inner, user_to_server_ack_a.inner);
+
+        user_b_sender
+            .send(user_to_server_ack_b.clone())
+            .await
+            .unwrap();
+        let server_request_b = server_receiver.next().await.unwrap();
+        assert_eq!(server_request_b.inner, user_to_server_ack_b.inner);
+
+        // The server sees two different request ids:
+        assert_ne!(server_request_a.request_id, server_request_b.request_id);
+
+        // Every user gets the Ack of its own request:
+        server_sender
+            .send(ServerToUserAck::Ack(server_request_b.request_id))
+            .await
+            .unwrap();
+        assert_eq!(
+            user_b_receiver.next().await.unwrap(),
+            ServerToUserAck::Ack(request_id.clone())
+        );
+
+        server_sender
+            .send(ServerToUserAck::Ack(server_request_a.request_id))
+            .await
+            .unwrap();
+        assert_eq!(
+            user_a_receiver.next().await.unwrap(),
+            ServerToUserAck::Ack(request_id)
+        );
+    }
+
+    #[test]
+    fn test_multi_user_same_request_id() {
+        let thread_pool = ThreadPool::new().unwrap();
+        block_on(task_multi_user_same_request_id(thread_pool.clone()));
+    }
+
+    #[test]
+    fn test_multi_user_too_many_pending_requests() {
+        let thread_pool = ThreadPool::new().unwrap();
+        block_on(task_multi_user_too_many_pending_requests(
+            thread_pool.clone(),
+        ));
+    }
+}
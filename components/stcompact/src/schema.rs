@@ -0,0 +1,61 @@
+use schemars::schema::RootSchema;
+use schemars::schema_for;
+
+use crate::messages::{ServerToUserAck, UserToServerAck};
+
+/// JSON Schema for messages sent from the server to the user.
+/// Describes the serialized (JSON) representation of `ServerToUserAck`.
+pub fn server_to_user_ack_schema() -> RootSchema {
+    schema_for!(ServerToUserAck)
+}
+
+/// JSON Schema for messages sent from the user to the server.
+/// Describes the serialized (JSON) representation of `UserToServerAck`.
+pub fn user_to_server_ack_schema() -> RootSchema {
+    schema_for!(UserToServerAck)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use jsonschema::JSONSchema;
+    use quickcheck::Arbitrary;
+    use rand::rngs::StdRng;
+    use serde::Serialize;
+
+    /// Generate random instances of `T` and make sure that the serialized form of every instance
+    /// is valid according to the given schema.
+    fn check_instances_valid<T>(root_schema: RootSchema, iters: usize)
+    where
+        T: Arbitrary + Serialize,
+    {
+        let schema = serde_json::to_value(&root_schema).unwrap();
+        let compiled = JSONSchema::compile(&schema, None).unwrap();
+
+        let size = 3;
+        let rng_seed: [u8; 32] = [1; 32];
+        let rng: StdRng = rand::SeedableRng::from_seed(rng_seed);
+        let mut gen = quickcheck::StdGen::new(rng, size);
+
+        for _ in 0..iters {
+            let msg = T::arbitrary(&mut gen);
+            let instance = serde_json::to_value(&msg).unwrap();
+            assert!(
+                compiled.is_valid(&instance),
+                "Invalid instance: {}",
+                serde_json::to_string_pretty(&instance).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_schema_server_to_user_ack() {
+        check_instances_valid::<ServerToUserAck>(server_to_user_ack_schema(), 0x100);
+    }
+
+    #[test]
+    fn test_schema_user_to_server_ack() {
+        check_instances_valid::<UserToServerAck>(user_to_server_ack_schema(), 0x100);
+    }
+}
@@ -91,6 +91,7 @@ mod tests {
             total_dest_payment: 0x1234u128,
             description: "description".to_owned(),
             is_committed: false,
+            is_held: false,
             generation: Generation(5),
         };
         let mut open_invoices = HashMap::new();
@@ -103,6 +104,7 @@ mod tests {
             friends: HashMap::new(),
             open_invoices,
             open_payments: HashMap::new(),
+            subscriptions: HashMap::new(),
         };
         let compact_to_user = CompactToUser::Report(compact_report);
         let server_to_user = ServerToUser::Node(NodeId(0x100u64), compact_to_user);
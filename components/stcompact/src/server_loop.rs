@@ -148,6 +148,8 @@ struct ServerState<ST, R, C, S> {
     pub rng: R,
     pub timer_client: TimerClient,
     pub connector: C,
+    /// Resume sessions when reconnecting to friends of local nodes
+    pub resume_sessions: bool,
     pub spawner: S,
 }
 
@@ -159,6 +161,7 @@ impl<ST, R, C, S> ServerState<ST, R, C, S> {
         rng: R,
         timer_client: TimerClient,
         connector: C,
+        resume_sessions: bool,
         spawner: S,
     ) -> Self {
         Self {
@@ -171,6 +174,7 @@ impl<ST, R, C, S> ServerState<ST, R, C, S> {
             rng,
             timer_client,
             connector,
+            resume_sessions,
             spawner,
         }
     }
@@ -523,6 +527,8 @@ const NODE_CONFIG: NodeConfig = NodeConfig {
     max_open_index_client_requests: MAX_OPEN_INDEX_CLIENT_REQUESTS,
     /// Maximum amount of relays a node may use.
     max_node_relays: MAX_NODE_RELAYS,
+    /// Addresses where we accept direct connections from friends.
+    direct_addresses: Vec::new(),
 };
 
 async fn open_node_local<ST, R, C, S>(
@@ -565,6 +571,8 @@ where
         server_state.timer_client.clone(),
         local.node_identity_client.clone(),
         server_state.rng.clone(),
+        None,
+        server_state.resume_sessions,
         server_state.spawner.clone(),
     );
 
@@ -575,7 +583,10 @@ where
         local.node_state,
         local.node_db_client,
         secure_connector,
+        server_state.connector.clone(),
         encrypt_keepalive,
+        // Compact nodes do not accept direct connections:
+        stream::empty(),
         incoming_apps,
         server_state.rng.clone(),
         server_state.spawner.clone(),
@@ -621,6 +632,7 @@ where
         local.compact_state,
         local.compact_db_client,
         compact_gen,
+        server_state.timer_client.clone(),
     )
     .map_err(|e| {
         error!("open_node_local(): compact_node() error: {:?}", e);
@@ -752,6 +764,7 @@ where
         remote.compact_state,
         remote.compact_db_client,
         compact_gen,
+        timer_client,
     )
     .map_err(|e| {
         error!("open_node_remote(): compact_node() error: {:?}", e);
@@ -1266,6 +1279,7 @@ async fn inner_server_loop<ST, R, C, S>(
     mut timer_client: TimerClient,
     rng: R,
     connector: C,
+    resume_sessions: bool,
     spawner: S,
     // opt_event_sender is used for testing:
     mut opt_event_sender: Option<mpsc::Sender<()>>,
@@ -1300,6 +1314,7 @@ where
         rng.clone(),
         timer_client,
         connector,
+        resume_sessions,
         spawner,
     );
 
@@ -1399,6 +1414,7 @@ pub async fn compact_server_loop<ST, R, C, S>(
     timer_client: TimerClient,
     rng: R,
     connector: C,
+    resume_sessions: bool,
     spawner: S,
 ) -> Result<(), ServerError>
 where
@@ -1418,6 +1434,7 @@ where
         timer_client,
         rng,
         connector,
+        resume_sessions,
         spawner,
         opt_event_sender,
     )
@@ -1,4 +1,5 @@
-use std::path::PathBuf;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 
 use derive_more::From;
@@ -6,7 +7,8 @@ use derive_more::From;
 use futures::task::{Spawn, SpawnExt};
 
 use futures::channel::mpsc;
-use futures::AsyncWriteExt;
+use futures::io::BufReader;
+use futures::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use futures::{FutureExt, SinkExt, StreamExt};
 
 use structopt::StructOpt;
@@ -18,12 +20,15 @@ use crypto::rand::system_random;
 
 use timer::create_timer;
 
+#[cfg(unix)]
+use net::bind_unix_socket;
 use net::TcpConnector;
 
 use proto::consts::{MAX_FRAME_LENGTH, TICK_MS};
 
+use crate::multi_user::{create_multi_user, MultiUserError};
 use crate::serialize::{serialize_conn_pair, SerializeConnError};
-use crate::server_loop::{compact_server_loop, ServerError};
+use crate::server_loop::{compact_server_loop, ConnPairCompactServer, ServerError};
 use crate::store::open_file_store;
 
 /// Amount of ticks to wait for the next attempt to reconnect to a remote node
@@ -36,7 +41,14 @@ pub enum StCompactError {
     OpenFileStoreError,
     ServerError(ServerError),
     SerializeConnError(SerializeConnError),
+    MultiUserError(MultiUserError),
     SpawnError,
+    /// Only local (loopback) TCP listening addresses are allowed
+    NonLocalListenAddress(SocketAddr),
+    /// Listening on TCP addresses requires an authentication token
+    MissingAuthToken,
+    ReadAuthTokenError,
+    ListenError,
 }
 
 /// stcompact: Offset Compact
@@ -51,6 +63,31 @@ pub struct StCompactCmd {
     /// If directory is nonexistent, a new store will be created.
     #[structopt(parse(from_os_str), short = "s", long = "store")]
     pub store_path: PathBuf,
+    /// SOCKS5 proxy address (For example, Tor: 127.0.0.1:9050).
+    /// If specified, all outgoing connections to nodes, relays and index servers are made through the proxy.
+    #[structopt(long = "socks5")]
+    pub socks5: Option<SocketAddr>,
+    /// Local TCP listening address (For example: 127.0.0.1:9500).
+    /// Allows multiple users to connect simultaneously, instead of using stdin/stdout.
+    /// Must be a loopback address. May be specified multiple times.
+    /// Requires --auth-token-file.
+    #[structopt(short = "l", long = "laddr")]
+    pub laddr: Vec<SocketAddr>,
+    /// Path of a file containing a secret authentication token.
+    /// Every user connecting through a TCP listening address must send this token as its first
+    /// line.
+    #[structopt(parse(from_os_str), long = "auth-token-file")]
+    pub opt_auth_token_file: Option<PathBuf>,
+    /// Listening Unix domain socket path.
+    /// Allows multiple users to connect simultaneously, instead of using stdin/stdout.
+    /// Access is controlled by the file system permissions of the socket.
+    /// May be specified multiple times.
+    #[structopt(parse(from_os_str), long = "lunix")]
+    pub lunix: Vec<PathBuf>,
+    /// Resume previous sessions when reconnecting to friends, skipping the full handshake.
+    /// Resumed sessions can be linked to each other by an observer of the traffic.
+    #[structopt(long = "resume-sessions")]
+    pub resume_sessions: bool,
 }
 
 fn create_stdio_conn_pair<S>(spawner: &S) -> Result<ConnPairString, StCompactError>
@@ -99,6 +136,162 @@ where
     Ok(ConnPairString::from_raw(server_sender, server_receiver))
 }
 
+/// Create a line based (string) communication over a stream.
+/// Every line is one message.
+fn create_stream_conn_pair<T, S>(stream: T, spawner: &S) -> Result<ConnPairString, StCompactError>
+where
+    T: AsyncRead + AsyncWrite + Send + 'static,
+    S: Spawn,
+{
+    let (reader, mut writer) = stream.split();
+
+    let (server_sender, mut receiver) = mpsc::channel::<String>(1);
+    let (mut sender, server_receiver) = mpsc::channel::<String>(1);
+
+    let send_fut = async move {
+        while let Some(line) = receiver.next().await {
+            writer.write_all(line.as_bytes()).await.ok()?;
+            writer.write_all(b"\n").await.ok()?;
+        }
+        Some(())
+    };
+    spawner
+        .spawn(send_fut.map(|_: Option<()>| ()))
+        .map_err(|_| StCompactError::SpawnError)?;
+
+    let recv_fut = async move {
+        let mut lines = BufReader::new(reader).lines();
+        while let Some(line) = lines.next().await {
+            sender.send(line.ok()?).await.ok()?;
+        }
+        Some(())
+    };
+    spawner
+        .spawn(recv_fut.map(|_: Option<()>| ()))
+        .map_err(|_| StCompactError::SpawnError)?;
+
+    Ok(ConnPairString::from_raw(server_sender, server_receiver))
+}
+
+/// Compare two tokens, in time that does not depend on the contents of the tokens.
+fn is_token_equal(token_a: &[u8], token_b: &[u8]) -> bool {
+    if token_a.len() != token_b.len() {
+        return false;
+    }
+    token_a
+        .iter()
+        .zip(token_b.iter())
+        .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+        == 0
+}
+
+/// Wait for the user to send the authentication token as its first line.
+/// Returns the remaining connection if the user sent the correct token.
+async fn authenticate_user(conn_pair: ConnPairString, auth_token: &str) -> Option<ConnPairString> {
+    let (sender, mut receiver) = conn_pair.split();
+    let line = receiver.next().await?;
+    if !is_token_equal(line.trim_end().as_bytes(), auth_token.as_bytes()) {
+        warn!("authenticate_user(): Invalid authentication token");
+        return None;
+    }
+    Some(ConnPairString::from_box(sender, receiver))
+}
+
+/// Read the authentication token from a file
+async fn read_auth_token(auth_token_file: &Path) -> Result<String, StCompactError> {
+    let auth_token = async_std::fs::read_to_string(auth_token_file)
+        .await
+        .map_err(|_| StCompactError::ReadAuthTokenError)?;
+    let auth_token = auth_token.trim().to_owned();
+    if auth_token.is_empty() {
+        return Err(StCompactError::ReadAuthTokenError);
+    }
+    Ok(auth_token)
+}
+
+/// Listen for users on local TCP addresses and Unix domain sockets.
+/// Returns a stream of incoming user connections.
+async fn listen_users<S>(
+    laddr: Vec<SocketAddr>,
+    opt_auth_token: Option<String>,
+    lunix: Vec<PathBuf>,
+    spawner: &S,
+) -> Result<mpsc::Receiver<ConnPairCompactServer>, StCompactError>
+where
+    S: Spawn + Clone + Send + 'static,
+{
+    let (users_sender, users_receiver) = mpsc::channel(0);
+
+    if !laddr.is_empty() && opt_auth_token.is_none() {
+        return Err(StCompactError::MissingAuthToken);
+    }
+
+    for socket_addr in laddr {
+        if !socket_addr.ip().is_loopback() {
+            return Err(StCompactError::NonLocalListenAddress(socket_addr));
+        }
+        let listener = async_std::net::TcpListener::bind(socket_addr)
+            .await
+            .map_err(|_| StCompactError::ListenError)?;
+        let c_spawner = spawner.clone();
+        let c_users_sender = users_sender.clone();
+        let c_auth_token = opt_auth_token.clone().unwrap();
+        let accept_fut = async move {
+            let mut incoming = listener.incoming();
+            while let Some(Ok(tcp_stream)) = incoming.next().await {
+                let conn_pair = create_stream_conn_pair(tcp_stream, &c_spawner).ok()?;
+                // Authenticate every user separately, so that a user that does not send anything
+                // will not block other users:
+                let c_c_spawner = c_spawner.clone();
+                let mut c_c_users_sender = c_users_sender.clone();
+                let c_c_auth_token = c_auth_token.clone();
+                let user_fut = async move {
+                    let conn_pair = authenticate_user(conn_pair, &c_c_auth_token).await?;
+                    let conn_pair = serialize_conn_pair(conn_pair, &c_c_spawner).ok()?;
+                    c_c_users_sender.send(conn_pair).await.ok()
+                };
+                c_spawner.spawn(user_fut.map(|_: Option<()>| ())).ok()?;
+            }
+            Some(())
+        };
+        spawner
+            .spawn(accept_fut.map(|_: Option<()>| ()))
+            .map_err(|_| StCompactError::SpawnError)?;
+    }
+
+    #[cfg(unix)]
+    for socket_path in lunix {
+        let (listener, socket_file_guard) = bind_unix_socket(&socket_path)
+            .await
+            .map_err(|_| StCompactError::ListenError)?;
+        let c_spawner = spawner.clone();
+        let mut c_users_sender = users_sender.clone();
+        let accept_fut = async move {
+            // Remove the socket file when we stop listening:
+            let _socket_file_guard = socket_file_guard;
+            let mut incoming = listener.incoming();
+            while let Some(Ok(unix_stream)) = incoming.next().await {
+                let conn_pair = create_stream_conn_pair(unix_stream, &c_spawner).ok()?;
+                let conn_pair = serialize_conn_pair(conn_pair, &c_spawner).ok()?;
+                c_users_sender.send(conn_pair).await.ok()?;
+            }
+            Some(())
+        };
+        spawner
+            .spawn(accept_fut.map(|_: Option<()>| ()))
+            .map_err(|_| StCompactError::SpawnError)?;
+    }
+
+    #[cfg(not(unix))]
+    {
+        if !lunix.is_empty() {
+            return Err(StCompactError::ListenError);
+        }
+    }
+
+    Ok(users_receiver)
+}
+
 pub async fn stcompact<S, FS>(
     st_compact_cmd: StCompactCmd,
     spawner: S,
@@ -108,7 +301,19 @@ where
     S: Spawn + Clone + Send + Sync + 'static,
     FS: Spawn + Clone + Send + Sync + 'static,
 {
-    let StCompactCmd { store_path } = st_compact_cmd;
+    let StCompactCmd {
+        store_path,
+        socks5,
+        laddr,
+        opt_auth_token_file,
+        lunix,
+        resume_sessions,
+    } = st_compact_cmd;
+
+    let opt_auth_token = match &opt_auth_token_file {
+        Some(auth_token_file) => Some(read_auth_token(auth_token_file).await?),
+        None => None,
+    };
 
     // Get a timer client:
     let dur = Duration::from_millis(usize_to_u64(TICK_MS).unwrap());
@@ -116,7 +321,7 @@ where
         create_timer(dur, spawner.clone()).map_err(|_| StCompactError::CreateTimerError)?;
 
     // A tcp connector, Used to connect to remote servers:
-    let tcp_connector = TcpConnector::new(MAX_FRAME_LENGTH, spawner.clone());
+    let tcp_connector = TcpConnector::new_with_proxy(MAX_FRAME_LENGTH, socks5, spawner.clone());
 
     // Obtain secure cryptographic random:
     let rng = system_random();
@@ -125,11 +330,17 @@ where
         .await
         .map_err(|_| StCompactError::OpenFileStoreError)?;
 
-    // Get line (string) communication with stdio:
-    let stdio_conn_pair = create_stdio_conn_pair(&spawner)?;
+    let conn_pair = if laddr.is_empty() && lunix.is_empty() {
+        // Get line (string) communication with stdio:
+        let stdio_conn_pair = create_stdio_conn_pair(&spawner)?;
 
-    // Serialize communication:
-    let conn_pair = serialize_conn_pair(stdio_conn_pair, &spawner)?;
+        // Serialize communication:
+        serialize_conn_pair(stdio_conn_pair, &spawner)?
+    } else {
+        // Serve multiple users connecting through the listening addresses:
+        let incoming_users = listen_users(laddr, opt_auth_token, lunix, &spawner).await?;
+        create_multi_user(incoming_users, rng.clone(), &spawner)?
+    };
 
     Ok(compact_server_loop(
         conn_pair,
@@ -138,7 +349,46 @@ where
         timer_client,
         rng,
         tcp_connector,
+        resume_sessions,
         spawner.clone(),
     )
     .await?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_is_token_equal() {
+        assert!(is_token_equal(b"token", b"token"));
+        assert!(!is_token_equal(b"token", b"tokem"));
+        assert!(!is_token_equal(b"token", b"token1"));
+        assert!(!is_token_equal(b"", b"token"));
+    }
+
+    async fn task_authenticate_user(first_line: &str) -> Option<Vec<String>> {
+        let (mut user_sender, receiver) = mpsc::channel::<String>(4);
+        let (sender, _user_receiver) = mpsc::channel::<String>(4);
+
+        user_sender.send(first_line.to_owned()).await.unwrap();
+        user_sender.send("request".to_owned()).await.unwrap();
+        drop(user_sender);
+
+        let conn_pair = ConnPairString::from_raw(sender, receiver);
+        let (_sender, receiver) = authenticate_user(conn_pair, "secret").await?.split();
+        Some(receiver.collect().await)
+    }
+
+    #[test]
+    fn test_authenticate_user() {
+        // The token line is consumed, the rest of the messages are forwarded:
+        assert_eq!(
+            block_on(task_authenticate_user("secret\n")),
+            Some(vec!["request".to_owned()])
+        );
+        assert_eq!(block_on(task_authenticate_user("wrong")), None);
+    }
+}
@@ -1,12 +1,16 @@
 use std::collections::HashSet;
+use std::convert::TryFrom;
 use std::fs::{self, File};
 use std::io::{self, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use derive_more::From;
 
+use futures::channel::mpsc;
+use futures::select;
 use futures::sink::SinkExt;
-use futures::stream::StreamExt;
+use futures::stream::{FuturesUnordered, StreamExt};
 
 use structopt::StructOpt;
 
@@ -16,26 +20,71 @@ use app::common::{
 use app::conn::{
     self, AppServerToApp, AppToAppServer, ConnPairApp, RequestResult, ResponseRoutesResult,
 };
-use app::gen::{gen_payment_id, gen_uid};
+use app::gen::{gen_invoice_id, gen_payment_id, gen_uid};
+use app::payment_uri::{parse_payment_uri, PaymentUriError, PAYMENT_URI_SCHEME};
 use app::report::NodeReport;
-use app::ser_utils::{deserialize_from_string, serialize_to_string, StringSerdeError};
+use app::ser_utils::{
+    deserialize_from_string, serialize_to_string, string_to_public_key, StringSerdeError,
+};
 
 use crate::file::{CommitFile, InvoiceFile, PaymentFile, ReceiptFile};
 
-use route::choose_multi_route;
+use route::{choose_multi_route, route_fees};
 
 /// Pay an invoice
 #[derive(Clone, Debug, StructOpt)]
 pub struct PayInvoiceCmd {
     /// Path to invoice file to pay
     #[structopt(parse(from_os_str), short = "i", long = "invoice")]
-    pub invoice_path: PathBuf,
+    pub invoice_path: Option<PathBuf>,
+    /// Payment URI of the invoice to pay (Instead of an invoice file)
+    #[structopt(short = "u", long = "uri")]
+    pub invoice_uri: Option<String>,
     /// Output payment file (Used to track the payment)
     #[structopt(parse(from_os_str), short = "p", long = "payment")]
     pub payment_path: PathBuf,
     /// Output commit file
     #[structopt(parse(from_os_str), short = "c", long = "commit")]
     pub commit_path: PathBuf,
+    /// Currency to pay with, if different from the invoice currency.
+    /// The payment will go through a node that exchanges between the currencies.
+    #[structopt(long = "from-currency")]
+    pub opt_from_currency_name: Option<String>,
+}
+
+/// Send a push payment (A payment that does not require an invoice)
+#[derive(Clone, Debug, StructOpt)]
+pub struct PushPaymentCmd {
+    /// Currency used to send funds
+    #[structopt(short = "c", long = "currency")]
+    pub currency_name: String,
+    /// Amount of credits to pay (A non negative integer)
+    #[structopt(short = "a", long = "amount")]
+    pub amount: u128,
+    /// Public key of the destination node
+    #[structopt(short = "d", long = "dest")]
+    pub dest_public_key: String,
+    /// Output payment file (Used to track the payment)
+    #[structopt(parse(from_os_str), short = "p", long = "payment")]
+    pub payment_path: PathBuf,
+}
+
+/// Pay multiple invoices listed in a batch file.
+/// Payment, commit and receipt files of the n-th entry in the batch file are kept in the output
+/// directory as `n.payment`, `n.commit` and `n.receipt`. Running the command again with the same
+/// arguments resumes an interrupted batch, and retries failed payments.
+#[derive(Clone, Debug, StructOpt)]
+pub struct PayBatchCmd {
+    /// Path to batch file. Every line contains a path to an invoice file or a payment URI.
+    /// Empty lines and lines starting with `#` are ignored.
+    #[structopt(parse(from_os_str), short = "b", long = "batch")]
+    pub batch_path: PathBuf,
+    /// Output directory for payment, commit and receipt files
+    #[structopt(parse(from_os_str), short = "o", long = "output")]
+    pub output_path: PathBuf,
+    /// Maximum amount of payments being sent concurrently
+    #[structopt(short = "j", long = "jobs", default_value = "4")]
+    pub max_concurrent: usize,
 }
 
 /// Check payment status (And obtain receipt if successful)
@@ -52,9 +101,15 @@ pub struct PaymentStatusCmd {
 /// Funds sending related commands
 #[derive(Clone, Debug, StructOpt)]
 pub enum BuyerCmd {
-    /// Pay an invoice (Using an invoice file)
+    /// Pay an invoice (Using an invoice file or a payment URI)
     #[structopt(name = "pay-invoice")]
     PayInvoice(PayInvoiceCmd),
+    /// Send funds to a node without an invoice (The destination must accept push payments)
+    #[structopt(name = "push-payment")]
+    PushPayment(PushPaymentCmd),
+    /// Pay multiple invoices concurrently (Waits until receipts are obtained for all invoices)
+    #[structopt(name = "pay-batch")]
+    PayBatch(PayBatchCmd),
     #[structopt(name = "payment-status")]
     PaymentStatus(PaymentStatusCmd),
 }
@@ -86,13 +141,62 @@ pub enum BuyerError {
     LoadPaymentError,
     RemovePaymentError,
     PaymentIncomplete,
+    /// Exactly one of invoice file or payment URI must be provided
+    InvalidInvoiceArgs,
+    InvalidCurrencyName,
+    InvoiceExpired,
+    PaymentNotFound,
+    PaymentCanceled,
+    InvalidConcurrency,
+    LoadBatchError,
+    ConnectionClosed,
+    /// Some of the payments in the batch have failed
+    BatchIncomplete,
+    PaymentUriError(PaymentUriError),
     IoError(std::io::Error),
     StringSerdeError(StringSerdeError),
 }
 
+/// Load an invoice, either from an invoice file or from a payment URI
+fn load_invoice(
+    opt_invoice_path: Option<PathBuf>,
+    opt_invoice_uri: Option<String>,
+    writer: &mut impl io::Write,
+) -> Result<InvoiceFile, BuyerError> {
+    match (opt_invoice_path, opt_invoice_uri) {
+        (Some(invoice_path), None) => Ok(deserialize_from_string(&fs::read_to_string(
+            &invoice_path,
+        )?)?),
+        (None, Some(invoice_uri)) => {
+            let payment_uri = parse_payment_uri(&invoice_uri)?;
+            if let Some(expiry) = payment_uri.opt_expiry {
+                let now = SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map(|duration| duration.as_secs())
+                    .unwrap_or(0);
+                if now >= expiry {
+                    return Err(BuyerError::InvoiceExpired);
+                }
+            }
+            if let Some(description) = &payment_uri.opt_description {
+                writeln!(writer, "Description: {}", description)
+                    .map_err(|_| BuyerError::WriteError)?;
+            }
+            Ok(InvoiceFile {
+                invoice_id: payment_uri.invoice_id,
+                currency: payment_uri.currency,
+                dest_public_key: payment_uri.dest_public_key,
+                dest_payment: payment_uri.dest_payment,
+            })
+        }
+        _ => Err(BuyerError::InvalidInvoiceArgs),
+    }
+}
+
 async fn request_routes(
     conn_pair: &mut ConnPairApp,
     currency: Currency,
+    dest_currency: Currency,
     dest_payment: u128,
     src_public_key: PublicKey,
     dest_public_key: PublicKey,
@@ -102,6 +206,7 @@ async fn request_routes(
     let app_request = conn::routes::request_routes(
         request_routes_id.clone(),
         currency,
+        dest_currency,
         dest_payment,
         src_public_key,
         dest_public_key,
@@ -141,14 +246,25 @@ async fn create_payment(
     currency: Currency,
     total_dest_payment: u128,
     dest_public_key: PublicKey,
+    is_push: bool,
 ) -> Result<(), BuyerError> {
-    let app_request = conn::buyer::create_payment(
-        payment_id,
-        invoice_id,
-        currency,
-        total_dest_payment,
-        dest_public_key,
-    );
+    let app_request = if is_push {
+        conn::buyer::create_push_payment(
+            payment_id,
+            invoice_id,
+            currency,
+            total_dest_payment,
+            dest_public_key,
+        )
+    } else {
+        conn::buyer::create_payment(
+            payment_id,
+            invoice_id,
+            currency,
+            total_dest_payment,
+            dest_public_key,
+        )
+    };
 
     let app_request_id = gen_uid();
     let app_to_app_server = AppToAppServer {
@@ -282,8 +398,10 @@ async fn buyer_pay_invoice(
 ) -> Result<(), BuyerError> {
     let PayInvoiceCmd {
         invoice_path,
+        invoice_uri,
         payment_path,
         commit_path,
+        opt_from_currency_name,
     } = pay_invoice_cmd;
 
     // Make sure that we will be able to write the Payment file
@@ -298,10 +416,90 @@ async fn buyer_pay_invoice(
         return Err(BuyerError::CommitFileAlreadyExists);
     }
 
-    let invoice_file: InvoiceFile = deserialize_from_string(&fs::read_to_string(&invoice_path)?)?;
+    let opt_from_currency = opt_from_currency_name
+        .map(Currency::try_from)
+        .transpose()
+        .map_err(|_| BuyerError::InvalidCurrencyName)?;
 
-    let multi_routes = request_routes(
+    let invoice_file = load_invoice(invoice_path, invoice_uri, writer)?;
+
+    pay(
         &mut conn_pair,
+        invoice_file,
+        opt_from_currency,
+        local_public_key,
+        payment_path,
+        Some(commit_path),
+        writer,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Send a push payment
+async fn buyer_push_payment(
+    push_payment_cmd: PushPaymentCmd,
+    local_public_key: PublicKey,
+    mut conn_pair: ConnPairApp,
+    writer: &mut impl io::Write,
+) -> Result<(), BuyerError> {
+    let PushPaymentCmd {
+        currency_name,
+        amount,
+        dest_public_key,
+        payment_path,
+    } = push_payment_cmd;
+
+    // Make sure that we will be able to write the Payment file
+    // before we do the actual payment:
+    if payment_path.exists() {
+        return Err(BuyerError::PaymentFileAlreadyExists);
+    }
+
+    let currency =
+        Currency::try_from(currency_name).map_err(|_| BuyerError::InvalidCurrencyName)?;
+    let dest_public_key =
+        string_to_public_key(&dest_public_key).map_err(|_| BuyerError::InvalidDestination)?;
+
+    // For push payments the invoice is created by the buyer:
+    let invoice_file = InvoiceFile {
+        invoice_id: gen_invoice_id(),
+        currency,
+        dest_public_key,
+        dest_payment: amount,
+    };
+
+    pay(
+        &mut conn_pair,
+        invoice_file,
+        None,
+        local_public_key,
+        payment_path,
+        None,
+        writer,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Pay according to the given invoice. Returns the total fees paid.
+/// If `opt_commit_path` is None, a push payment is sent, and no Commit is produced.
+/// If `opt_from_currency` is given, we pay using this currency, through an exchanging node.
+async fn pay(
+    conn_pair: &mut ConnPairApp,
+    invoice_file: InvoiceFile,
+    opt_from_currency: Option<Currency>,
+    local_public_key: PublicKey,
+    payment_path: PathBuf,
+    opt_commit_path: Option<PathBuf>,
+    writer: &mut impl io::Write,
+) -> Result<u128, BuyerError> {
+    let is_push = opt_commit_path.is_none();
+
+    let from_currency = opt_from_currency.unwrap_or_else(|| invoice_file.currency.clone());
+    let multi_routes = request_routes(
+        conn_pair,
+        from_currency,
         invoice_file.currency.clone(),
         invoice_file.dest_payment,
         local_public_key, // source
@@ -320,10 +518,7 @@ async fn buyer_pay_invoice(
     // TODO: Possibly ask the user if he wants to pay this amount of fees at this point.
     let mut total_fees = 0u128;
     for (route_index, dest_payment) in &multi_route_choice {
-        let fee = multi_route.routes[*route_index]
-            .rate
-            .calc_fee(*dest_payment)
-            .unwrap();
+        let fee = route_fees(&multi_route.routes[*route_index], *dest_payment).unwrap();
         total_fees = total_fees.checked_add(fee).unwrap();
     }
     writeln!(writer, "Total fees: {}", total_fees).map_err(|_| BuyerError::WriteError)?;
@@ -339,12 +534,13 @@ async fn buyer_pay_invoice(
     file.write_all(&serialize_to_string(&payment_file)?.as_bytes())?;
 
     create_payment(
-        &mut conn_pair,
+        conn_pair,
         payment_id.clone(),
         invoice_file.invoice_id.clone(),
         invoice_file.currency.clone(),
         invoice_file.dest_payment,
         invoice_file.dest_public_key.clone(),
+        is_push,
     )
     .await?;
 
@@ -361,7 +557,12 @@ async fn buyer_pay_invoice(
             request_id,
             route.route.clone(),
             *dest_payment,
-            route.rate.calc_fee(*dest_payment).unwrap(),
+            route_fees(route, *dest_payment).unwrap(),
+            route
+                .exchanges
+                .iter()
+                .map(|route_exchange| route_exchange.currency_exchange.clone())
+                .collect(),
         );
 
         let app_to_app_server = AppToAppServer {
@@ -378,16 +579,17 @@ async fn buyer_pay_invoice(
     }
 
     // Signal that no new transactions will be created:
-    request_close_payment_nowait(&mut conn_pair, payment_id.clone()).await?;
+    request_close_payment_nowait(conn_pair, payment_id.clone()).await?;
 
     // Wait for all incoming transaction responses:
     let mut opt_commit = None;
     while let Some(app_server_to_app) = conn_pair.receiver.next().await {
         if let AppServerToApp::TransactionResult(transaction_result) = app_server_to_app {
-            // Make sure that we only get transaction results of transactions we have sent,
-            // and that we get every transaction result only once.
+            // Make sure that we only handle transaction results of transactions we have sent,
+            // and that we handle every transaction result only once.
+            // (Results of other payments may arrive when paying a batch of invoices)
             if !requests.remove(&transaction_result.request_id) {
-                return Err(BuyerError::CreateTransactionFailed);
+                continue;
             }
 
             match transaction_result.result {
@@ -410,13 +612,59 @@ async fn buyer_pay_invoice(
 
     writeln!(writer, "Payment successful!").map_err(|_| BuyerError::WriteError)?;
 
-    let commit_file = CommitFile::from(commit);
+    // A push payment is collected by the seller without a Commit:
+    if let Some(commit_path) = opt_commit_path {
+        let commit_file = CommitFile::from(commit);
 
-    // Store Commit to file:
-    let mut file = File::create(commit_path)?;
-    file.write_all(&serialize_to_string(&commit_file)?.as_bytes())?;
+        // Store Commit to file:
+        let mut file = File::create(commit_path)?;
+        file.write_all(&serialize_to_string(&commit_file)?.as_bytes())?;
+    }
 
-    Ok(())
+    Ok(total_fees)
+}
+
+/// The way a payment was closed
+enum ClosedPayment {
+    NotFound,
+    Success,
+    Canceled,
+}
+
+/// Wait for a payment to be closed, and acknowledge the closing.
+/// In case of success, the receipt is saved to `receipt_path`.
+/// The payment file is removed once the payment is closed.
+async fn close_payment(
+    conn_pair: &mut ConnPairApp,
+    payment_path: &Path,
+    receipt_path: &Path,
+) -> Result<ClosedPayment, BuyerError> {
+    let payment_file: PaymentFile = deserialize_from_string(&fs::read_to_string(payment_path)?)?;
+    let payment_id = payment_file.payment_id;
+
+    let payment_status = request_close_payment(conn_pair, payment_id.clone()).await?;
+
+    let (closed_payment, opt_ack_uid) = match payment_status {
+        PaymentStatus::PaymentNotFound => (ClosedPayment::NotFound, None),
+        PaymentStatus::Success(PaymentStatusSuccess { receipt, ack_uid }) => {
+            // Store receipt to file:
+            let mut file = File::create(receipt_path)?;
+            file.write_all(&serialize_to_string(&ReceiptFile::from(receipt))?.as_bytes())?;
+
+            // Note that we must save the receipt to file before we let the node discard it.
+            (ClosedPayment::Success, Some(ack_uid))
+        }
+        PaymentStatus::Canceled(ack_uid) => (ClosedPayment::Canceled, Some(ack_uid)),
+    };
+
+    if let Some(ack_uid) = opt_ack_uid {
+        ack_close_payment(conn_pair, payment_id, ack_uid).await?;
+    }
+
+    // Remove payment file:
+    fs::remove_file(payment_path).map_err(|_| BuyerError::RemovePaymentError)?;
+
+    Ok(closed_payment)
 }
 
 /// Get the current status of a payment
@@ -436,43 +684,281 @@ async fn buyer_payment_status(
         return Err(BuyerError::ReceiptFileAlreadyExists);
     }
 
-    let payment_file: PaymentFile = deserialize_from_string(&fs::read_to_string(&payment_path)?)?;
-    let payment_id = payment_file.payment_id;
+    match close_payment(&mut conn_pair, &payment_path, &receipt_path).await? {
+        ClosedPayment::NotFound => {
+            writeln!(writer, "Payment could not be found").map_err(|_| BuyerError::WriteError)?
+        }
+        ClosedPayment::Success => writeln!(writer, "Payment succeeded. Saving receipt to file.")
+            .map_err(|_| BuyerError::WriteError)?,
+        ClosedPayment::Canceled => {
+            writeln!(writer, "Payment was canceled.").map_err(|_| BuyerError::WriteError)?
+        }
+    }
 
-    let payment_status = request_close_payment(&mut conn_pair, payment_id.clone()).await?;
+    Ok(())
+}
 
-    let opt_ack_uid = match payment_status {
-        PaymentStatus::PaymentNotFound => {
-            writeln!(writer, "Payment could not be found").map_err(|_| BuyerError::WriteError)?;
-            // Remove payment file:
-            fs::remove_file(&payment_path).map_err(|_| BuyerError::RemovePaymentError)?;
-            None
-        }
-        PaymentStatus::Success(PaymentStatusSuccess { receipt, ack_uid }) => {
-            writeln!(writer, "Payment succeeded. Saving receipt to file.")
-                .map_err(|_| BuyerError::WriteError)?;
+/// Status of a single entry in a batch of payments
+enum BatchEntryStatus {
+    /// Payment is in progress
+    Pending,
+    /// Payment succeeded. Contains the fees paid, if the payment was sent during this run.
+    Success(Option<u128>),
+    Failure(BuyerError),
+}
 
-            // Store receipt to file:
-            let mut file = File::create(receipt_path)?;
-            file.write_all(&serialize_to_string(&ReceiptFile::from(receipt))?.as_bytes())?;
+/// Load the entries of a batch file. Every entry is an invoice, given either as a path to an
+/// invoice file or as a payment URI.
+fn load_batch(batch_path: &Path) -> Result<Vec<Result<InvoiceFile, BuyerError>>, BuyerError> {
+    let batch_string = fs::read_to_string(batch_path).map_err(|_| BuyerError::LoadBatchError)?;
+
+    Ok(batch_string
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            if line.starts_with(PAYMENT_URI_SCHEME) {
+                load_invoice(None, Some(line.to_owned()), &mut io::sink())
+            } else {
+                load_invoice(Some(PathBuf::from(line)), None, &mut io::sink())
+            }
+        })
+        .collect())
+}
 
-            // Note that we must save the receipt to file before we let the node discard it.
-            Some(ack_uid)
+/// Create a connection for a single payment in a batch.
+/// All payments share the same connection to the node: Every incoming message is forwarded to all
+/// the payments, and every payment ignores messages that are not related to it.
+fn new_batch_conn_pair(
+    outgoing_sender: &mpsc::Sender<AppToAppServer>,
+    incoming_senders: &mut Vec<mpsc::UnboundedSender<AppServerToApp>>,
+) -> ConnPairApp {
+    let (incoming_sender, incoming_receiver) = mpsc::unbounded();
+    incoming_senders.push(incoming_sender);
+    ConnPairApp::from_raw(outgoing_sender.clone(), incoming_receiver)
+}
+
+/// Pay a single invoice of a batch (Until a commit is obtained)
+async fn batch_pay_entry(
+    index: usize,
+    mut conn_pair: ConnPairApp,
+    invoice_file: InvoiceFile,
+    local_public_key: PublicKey,
+    payment_path: PathBuf,
+    commit_path: PathBuf,
+) -> (usize, Result<u128, BuyerError>) {
+    let res = pay(
+        &mut conn_pair,
+        invoice_file,
+        None,
+        local_public_key,
+        payment_path,
+        Some(commit_path),
+        &mut io::sink(),
+    )
+    .await;
+    (index, res)
+}
+
+/// Wait for the receipt of a single payment of a batch
+async fn batch_close_entry(
+    index: usize,
+    mut conn_pair: ConnPairApp,
+    payment_path: PathBuf,
+    receipt_path: PathBuf,
+) -> (usize, Result<(), BuyerError>) {
+    let res = match close_payment(&mut conn_pair, &payment_path, &receipt_path).await {
+        Ok(ClosedPayment::Success) => Ok(()),
+        Ok(ClosedPayment::NotFound) => Err(BuyerError::PaymentNotFound),
+        Ok(ClosedPayment::Canceled) => Err(BuyerError::PaymentCanceled),
+        Err(e) => Err(e),
+    };
+    (index, res)
+}
+
+/// Pay a batch of invoices concurrently
+async fn buyer_pay_batch(
+    pay_batch_cmd: PayBatchCmd,
+    local_public_key: PublicKey,
+    conn_pair: ConnPairApp,
+    writer: &mut impl io::Write,
+) -> Result<(), BuyerError> {
+    let PayBatchCmd {
+        batch_path,
+        output_path,
+        max_concurrent,
+    } = pay_batch_cmd;
+
+    if max_concurrent == 0 {
+        return Err(BuyerError::InvalidConcurrency);
+    }
+
+    let entries = load_batch(&batch_path)?;
+    fs::create_dir_all(&output_path)?;
+
+    let entry_path = |index: usize, extension: &str| -> PathBuf {
+        output_path.join(format!("{}.{}", index + 1, extension))
+    };
+
+    let (mut sender, receiver) = conn_pair.split();
+    let mut receiver = receiver.fuse();
+    let (outgoing_sender, mut outgoing_receiver) = mpsc::channel(0);
+    let mut incoming_senders = Vec::new();
+
+    let mut statuses = Vec::new();
+    let mut fees: Vec<Option<u128>> = Vec::new();
+    let mut to_pay = Vec::new();
+    let mut closing = FuturesUnordered::new();
+
+    for (index, entry) in entries.into_iter().enumerate() {
+        fees.push(None);
+        let payment_path = entry_path(index, "payment");
+        let receipt_path = entry_path(index, "receipt");
+        let commit_path = entry_path(index, "commit");
+
+        let status = if payment_path.exists() {
+            // Payment was started in a previous run. We wait for its receipt:
+            let conn_pair = new_batch_conn_pair(&outgoing_sender, &mut incoming_senders);
+            closing.push(batch_close_entry(
+                index,
+                conn_pair,
+                payment_path,
+                receipt_path,
+            ));
+            BatchEntryStatus::Pending
+        } else if receipt_path.exists() {
+            // Paid in a previous run:
+            BatchEntryStatus::Success(None)
+        } else if commit_path.exists() {
+            BatchEntryStatus::Failure(BuyerError::CommitFileAlreadyExists)
+        } else {
+            match entry {
+                Ok(invoice_file) => {
+                    to_pay.push((index, invoice_file));
+                    BatchEntryStatus::Pending
+                }
+                Err(e) => BatchEntryStatus::Failure(e),
+            }
+        };
+        statuses.push(status);
+    }
+
+    // Errors of payments that are being closed after failure:
+    let mut pay_errors = Vec::new();
+    let mut to_pay = to_pay.into_iter();
+    let mut paying = FuturesUnordered::new();
+
+    loop {
+        // Start new payments, up to the concurrency limit.
+        // Note that payments waiting for a receipt are not counted.
+        while paying.len() < max_concurrent {
+            let (index, invoice_file) = if let Some(next) = to_pay.next() {
+                next
+            } else {
+                break;
+            };
+            let conn_pair = new_batch_conn_pair(&outgoing_sender, &mut incoming_senders);
+            paying.push(batch_pay_entry(
+                index,
+                conn_pair,
+                invoice_file,
+                local_public_key.clone(),
+                entry_path(index, "payment"),
+                entry_path(index, "commit"),
+            ));
         }
-        PaymentStatus::Canceled(ack_uid) => {
-            writeln!(writer, "Payment was canceled.").map_err(|_| BuyerError::WriteError)?;
 
-            Some(ack_uid)
+        if paying.is_empty() && closing.is_empty() {
+            break;
         }
-    };
 
-    if let Some(ack_uid) = opt_ack_uid {
-        ack_close_payment(&mut conn_pair, payment_id, ack_uid).await?;
+        select! {
+            (index, res) = paying.select_next_some() => {
+                match res {
+                    Ok(total_fees) => fees[index] = Some(total_fees),
+                    Err(e) => {
+                        if !entry_path(index, "payment").exists() {
+                            // Payment was never created:
+                            statuses[index] = BatchEntryStatus::Failure(e);
+                            continue;
+                        }
+                        // Payment was created. We close it to make sure it is canceled:
+                        pay_errors.push((index, e));
+                    }
+                }
+                let conn_pair = new_batch_conn_pair(&outgoing_sender, &mut incoming_senders);
+                closing.push(batch_close_entry(
+                    index,
+                    conn_pair,
+                    entry_path(index, "payment"),
+                    entry_path(index, "receipt"),
+                ));
+            },
+            (index, res) = closing.select_next_some() => {
+                statuses[index] = match res {
+                    Ok(()) => BatchEntryStatus::Success(fees[index]),
+                    Err(e) => {
+                        // Prefer reporting the original payment error, if any:
+                        let opt_pos = pay_errors.iter().position(|(i, _)| *i == index);
+                        BatchEntryStatus::Failure(
+                            opt_pos.map_or(e, |pos| pay_errors.swap_remove(pos).1),
+                        )
+                    }
+                };
+            },
+            opt_app_server_to_app = receiver.next() => {
+                let app_server_to_app = opt_app_server_to_app.ok_or(BuyerError::ConnectionClosed)?;
+                // Forward incoming message to all ongoing payments:
+                incoming_senders.retain(|incoming_sender| {
+                    incoming_sender
+                        .unbounded_send(app_server_to_app.clone())
+                        .is_ok()
+                });
+            },
+            opt_app_to_app_server = outgoing_receiver.next() => {
+                if let Some(app_to_app_server) = opt_app_to_app_server {
+                    sender
+                        .send(app_to_app_server)
+                        .await
+                        .map_err(|_| BuyerError::SendBuyerError)?;
+                }
+            },
+        }
+    }
 
-        // Remove payment file:
-        fs::remove_file(&payment_path).map_err(|_| BuyerError::RemovePaymentError)?;
+    // Summary report:
+    let mut num_success = 0usize;
+    let mut num_failure = 0usize;
+    let mut total_fees = 0u128;
+    for (index, status) in statuses.iter().enumerate() {
+        let write_res = match status {
+            BatchEntryStatus::Pending => unreachable!(),
+            BatchEntryStatus::Success(Some(entry_fees)) => {
+                num_success += 1;
+                total_fees = total_fees.saturating_add(*entry_fees);
+                writeln!(writer, "{}: Success, fees: {}", index + 1, entry_fees)
+            }
+            BatchEntryStatus::Success(None) => {
+                num_success += 1;
+                writeln!(writer, "{}: Success (Paid previously)", index + 1)
+            }
+            BatchEntryStatus::Failure(e) => {
+                num_failure += 1;
+                writeln!(writer, "{}: Failure: {:?}", index + 1, e)
+            }
+        };
+        write_res.map_err(|_| BuyerError::WriteError)?;
     }
+    writeln!(
+        writer,
+        "Succeeded: {}, Failed: {}, Total fees: {}",
+        num_success, num_failure, total_fees
+    )
+    .map_err(|_| BuyerError::WriteError)?;
 
+    if num_failure > 0 {
+        return Err(BuyerError::BatchIncomplete);
+    }
     Ok(())
 }
 
@@ -497,6 +983,12 @@ pub async fn buyer(
         BuyerCmd::PayInvoice(pay_invoice_cmd) => {
             buyer_pay_invoice(pay_invoice_cmd, local_public_key, conn_pair, writer).await?
         }
+        BuyerCmd::PushPayment(push_payment_cmd) => {
+            buyer_push_payment(push_payment_cmd, local_public_key, conn_pair, writer).await?
+        }
+        BuyerCmd::PayBatch(pay_batch_cmd) => {
+            buyer_pay_batch(pay_batch_cmd, local_public_key, conn_pair, writer).await?
+        }
         BuyerCmd::PaymentStatus(payment_status_cmd) => {
             buyer_payment_status(payment_status_cmd, conn_pair, writer).await?
         }
@@ -9,7 +9,9 @@ use structopt::StructOpt;
 
 use derive_more::From;
 
-use app::common::{Currency, NamedIndexServerAddress, NamedRelayAddress, Rate, RelayAddress};
+use app::common::{
+    Currency, ExchangeRate, NamedIndexServerAddress, NamedRelayAddress, Rate, RelayAddress,
+};
 use app::conn::{self, AppRequest, AppServerToApp, AppToAppServer, ConnPairApp};
 use app::gen::gen_uid;
 use app::report::{ChannelStatusReport, NodeReport};
@@ -87,6 +89,22 @@ pub struct RemoveFriendCmd {
     pub friend_name: String,
 }
 
+/// Cooperatively close the channel with a friend
+#[derive(Clone, Debug, StructOpt)]
+pub struct CloseFriendCmd {
+    /// Friend name to close
+    #[structopt(long = "name", short = "n")]
+    pub friend_name: String,
+}
+
+/// Abort an ongoing cooperative close with a friend
+#[derive(Clone, Debug, StructOpt)]
+pub struct AbortCloseFriendCmd {
+    /// Friend name
+    #[structopt(long = "name", short = "n")]
+    pub friend_name: String,
+}
+
 /// Enable friend
 #[derive(Clone, Debug, StructOpt)]
 pub struct EnableFriendCmd {
@@ -182,6 +200,36 @@ pub struct ResetFriendCmd {
     pub friend_name: String,
 }
 
+/// Exchange requests received in one currency into another currency.
+/// Every unit of the source currency is exchanged into (numerator / denominator) units of the
+/// destination currency.
+#[derive(Clone, Debug, StructOpt)]
+pub struct SetExchangeRateCmd {
+    /// Currency of incoming requests
+    #[structopt(long = "src", short = "s")]
+    pub src_currency_name: String,
+    /// Currency of forwarded requests
+    #[structopt(long = "dest", short = "d")]
+    pub dest_currency_name: String,
+    /// Numerator of the exchange rate
+    #[structopt(long = "numerator", short = "n")]
+    pub numerator: u64,
+    /// Denominator of the exchange rate
+    #[structopt(long = "denominator", short = "m")]
+    pub denominator: u64,
+}
+
+/// Stop exchanging requests from one currency to another
+#[derive(Clone, Debug, StructOpt)]
+pub struct RemoveExchangeRateCmd {
+    /// Currency of incoming requests
+    #[structopt(long = "src", short = "s")]
+    pub src_currency_name: String,
+    /// Currency of forwarded requests
+    #[structopt(long = "dest", short = "d")]
+    pub dest_currency_name: String,
+}
+
 #[derive(Clone, Debug, StructOpt)]
 pub enum ConfigCmd {
     /// Add a relay server
@@ -205,6 +253,12 @@ pub enum ConfigCmd {
     /// Remove a friend
     #[structopt(name = "remove-friend")]
     RemoveFriend(RemoveFriendCmd),
+    /// Cooperatively close the channel with a friend, ending with a signed settlement
+    #[structopt(name = "close-friend")]
+    CloseFriend(CloseFriendCmd),
+    /// Abort an ongoing cooperative close with a friend
+    #[structopt(name = "abort-close-friend")]
+    AbortCloseFriend(AbortCloseFriendCmd),
     /// Enable a friend
     #[structopt(name = "enable-friend")]
     EnableFriend(EnableFriendCmd),
@@ -230,6 +284,12 @@ pub enum ConfigCmd {
     /// Reset mutual credit with a friend according to friend's terms
     #[structopt(name = "reset-friend")]
     ResetFriend(ResetFriendCmd),
+    /// Set an exchange rate for forwarding requests from one currency to another
+    #[structopt(name = "set-exchange-rate")]
+    SetExchangeRate(SetExchangeRateCmd),
+    /// Remove an exchange rate
+    #[structopt(name = "remove-exchange-rate")]
+    RemoveExchangeRate(RemoveExchangeRateCmd),
 }
 
 #[derive(Debug, From)]
@@ -469,6 +529,33 @@ async fn config_remove_friend(
     config_request(&mut conn_pair, app_request).await
 }
 
+async fn config_close_friend(
+    close_friend_cmd: CloseFriendCmd,
+    mut conn_pair: ConnPairApp,
+    node_report: &NodeReport,
+) -> Result<(), ConfigError> {
+    let friend_public_key = friend_public_key_by_name(&node_report, &close_friend_cmd.friend_name)
+        .ok_or(ConfigError::FriendNameNotFound)?
+        .clone();
+
+    let app_request = conn::config::close_friend(friend_public_key);
+    config_request(&mut conn_pair, app_request).await
+}
+
+async fn config_abort_close_friend(
+    abort_close_friend_cmd: AbortCloseFriendCmd,
+    mut conn_pair: ConnPairApp,
+    node_report: &NodeReport,
+) -> Result<(), ConfigError> {
+    let friend_public_key =
+        friend_public_key_by_name(&node_report, &abort_close_friend_cmd.friend_name)
+            .ok_or(ConfigError::FriendNameNotFound)?
+            .clone();
+
+    let app_request = conn::config::abort_close_friend(friend_public_key);
+    config_request(&mut conn_pair, app_request).await
+}
+
 async fn config_enable_friend(
     enable_friend_cmd: EnableFriendCmd,
     mut conn_pair: ConnPairApp,
@@ -632,6 +719,49 @@ async fn config_reset_friend(
     config_request(&mut conn_pair, app_request).await
 }
 
+async fn config_set_exchange_rate(
+    set_exchange_rate_cmd: SetExchangeRateCmd,
+    mut conn_pair: ConnPairApp,
+) -> Result<(), ConfigError> {
+    let SetExchangeRateCmd {
+        src_currency_name,
+        dest_currency_name,
+        numerator,
+        denominator,
+    } = set_exchange_rate_cmd;
+
+    let src_currency =
+        Currency::try_from(src_currency_name).map_err(|_| ConfigError::InvalidCurrencyName)?;
+    let dest_currency =
+        Currency::try_from(dest_currency_name).map_err(|_| ConfigError::InvalidCurrencyName)?;
+
+    let rate = ExchangeRate {
+        numerator,
+        denominator,
+    };
+
+    let app_request = conn::config::set_exchange_rate(src_currency, dest_currency, rate);
+    config_request(&mut conn_pair, app_request).await
+}
+
+async fn config_remove_exchange_rate(
+    remove_exchange_rate_cmd: RemoveExchangeRateCmd,
+    mut conn_pair: ConnPairApp,
+) -> Result<(), ConfigError> {
+    let RemoveExchangeRateCmd {
+        src_currency_name,
+        dest_currency_name,
+    } = remove_exchange_rate_cmd;
+
+    let src_currency =
+        Currency::try_from(src_currency_name).map_err(|_| ConfigError::InvalidCurrencyName)?;
+    let dest_currency =
+        Currency::try_from(dest_currency_name).map_err(|_| ConfigError::InvalidCurrencyName)?;
+
+    let app_request = conn::config::remove_exchange_rate(src_currency, dest_currency);
+    config_request(&mut conn_pair, app_request).await
+}
+
 pub async fn config(
     config_cmd: ConfigCmd,
     node_report: &NodeReport,
@@ -659,6 +789,12 @@ pub async fn config(
         ConfigCmd::RemoveFriend(remove_friend_cmd) => {
             config_remove_friend(remove_friend_cmd, conn_pair, node_report).await?
         }
+        ConfigCmd::CloseFriend(close_friend_cmd) => {
+            config_close_friend(close_friend_cmd, conn_pair, node_report).await?
+        }
+        ConfigCmd::AbortCloseFriend(abort_close_friend_cmd) => {
+            config_abort_close_friend(abort_close_friend_cmd, conn_pair, node_report).await?
+        }
         ConfigCmd::EnableFriend(enable_friend_cmd) => {
             config_enable_friend(enable_friend_cmd, conn_pair, node_report).await?
         }
@@ -690,6 +826,12 @@ pub async fn config(
         ConfigCmd::ResetFriend(reset_friend_cmd) => {
             config_reset_friend(reset_friend_cmd, conn_pair, node_report).await?
         }
+        ConfigCmd::SetExchangeRate(set_exchange_rate_cmd) => {
+            config_set_exchange_rate(set_exchange_rate_cmd, conn_pair).await?
+        }
+        ConfigCmd::RemoveExchangeRate(remove_exchange_rate_cmd) => {
+            config_remove_exchange_rate(remove_exchange_rate_cmd, conn_pair).await?
+        }
     }
 
     Ok(())
@@ -4,7 +4,7 @@ use app::common::{
     Commit, Currency, HashResult, HashedLock, InvoiceId, PaymentId, PlainLock, PublicKey,
     RandValue, Receipt, Signature,
 };
-use app::report::{MoveTokenHashedReport, TokenInfo};
+use app::report::{FriendSettlement, MoveTokenHashedReport, TokenInfo};
 
 use mutual_from::mutual_from;
 
@@ -85,6 +85,17 @@ pub struct TokenFile {
     pub token_info: TokenInfo,
 }
 
+/// A helper structure for serialize and deserializing a final settlement with a friend.
+#[mutual_from(FriendSettlement)]
+#[derive(Arbitrary, Clone, Serialize, Deserialize, Debug)]
+pub struct SettlementFile {
+    pub token_info: TokenInfo,
+    #[serde(with = "ser_b64")]
+    pub local_signature: Signature,
+    #[serde(with = "ser_b64")]
+    pub remote_signature: Signature,
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -153,4 +164,35 @@ mod test {
 
         let _ = serialize_to_string(&token_file).unwrap();
     }
+
+    /// Check if we can serialize SettlementFile without crasing
+    #[test]
+    fn test_serialize_settlement_file() {
+        let token_info = TokenInfo {
+            mc: McInfo {
+                local_public_key: PublicKey::from(&[1; PublicKey::len()]),
+                remote_public_key: PublicKey::from(&[2; PublicKey::len()]),
+                balances: vec![CurrencyBalanceInfo {
+                    currency: "FST".parse().unwrap(),
+                    balance_info: BalanceInfo {
+                        balance: -5i128,
+                        local_pending_debt: 0u128,
+                        remote_pending_debt: 0u128,
+                    },
+                }],
+            },
+            counters: CountersInfo {
+                inconsistency_counter: 3u64,
+                move_token_counter: 4u128,
+            },
+        };
+
+        let settlement_file = SettlementFile {
+            token_info,
+            local_signature: Signature::from(&[3; Signature::len()]),
+            remote_signature: Signature::from(&[4; Signature::len()]),
+        };
+
+        let _ = serialize_to_string(&settlement_file).unwrap();
+    }
 }
@@ -9,14 +9,15 @@ use derive_more::From;
 
 use app::common::RelayAddress;
 use app::report::{
-    ChannelStatusReport, CurrencyReport, FriendReport, FriendStatusReport, NodeReport,
+    ChannelStatusReport, CurrencyReport, FriendCloseStatusReport, FriendReport, FriendStatusReport,
+    NodeReport,
 };
 use app::ser_utils::public_key_to_string;
 
 use app::file::{FriendAddressFile, RelayAddressFile};
 use app::ser_utils::{serialize_to_string, StringSerdeError};
 
-use crate::file::TokenFile;
+use crate::file::{SettlementFile, TokenFile};
 
 use crate::utils::friend_public_key_by_name;
 
@@ -49,6 +50,17 @@ pub struct FriendLastTokenCmd {
     pub token_path: PathBuf,
 }
 
+/// Export the final settlement with a closed friend
+#[derive(Clone, Debug, StructOpt)]
+pub struct FriendSettlementCmd {
+    /// Friend's name
+    #[structopt(short = "n", long = "name")]
+    pub friend_name: String,
+    /// Path for output settlement file
+    #[structopt(short = "s", long = "settlement")]
+    pub settlement_path: PathBuf,
+}
+
 /// Display balance summary
 #[derive(Clone, Debug, StructOpt)]
 pub struct BalanceCmd {}
@@ -78,6 +90,9 @@ pub enum InfoCmd {
     /// Export friend's last token
     #[structopt(name = "friend-last-token")]
     FriendLastToken(FriendLastTokenCmd),
+    /// Export the final settlement with a closed friend
+    #[structopt(name = "friend-settlement")]
+    FriendSettlement(FriendSettlementCmd),
     // /// Show current balance
     // #[structopt(name = "balance")]
     // Balance(BalanceCmd),
@@ -94,6 +109,7 @@ pub enum InfoError {
     StoreNodeToFileError,
     FriendNameNotFound,
     MissingLastIncomingMoveToken,
+    FriendNotClosed,
     StoreLastIncomingMoveTokenError,
     WriteError,
     TokenInvalid,
@@ -288,9 +304,17 @@ pub async fn info_friends(
             "-"
         };
 
+        // Is the channel being closed?
+        let close_str = match &friend_report.close_status {
+            FriendCloseStatusReport::Open => "",
+            FriendCloseStatusReport::Closing => "C",
+            FriendCloseStatusReport::Closed(_) => "X",
+        };
+
         let mut status_string = String::new();
         status_string += status_str;
         status_string += liveness_str;
+        status_string += close_str;
 
         table.add_row(row![
             status_string,
@@ -344,6 +368,52 @@ pub async fn info_friend_last_token(
     Ok(())
 }
 
+/// Obtain the final settlement with a closed friend.
+/// The settlement contains the final balances, signed by both sides.
+/// Friends are removed shortly after the channel is closed, so removed friends are searched too.
+pub async fn info_friend_settlement(
+    friend_settlement_cmd: FriendSettlementCmd,
+    node_report: &NodeReport,
+) -> Result<(), InfoError> {
+    let FriendSettlementCmd {
+        friend_name,
+        settlement_path,
+    } = friend_settlement_cmd;
+
+    if settlement_path.exists() {
+        return Err(InfoError::OutputFileAlreadyExists);
+    }
+
+    let friend_settlement =
+        if let Some(friend_public_key) = friend_public_key_by_name(node_report, &friend_name) {
+            let friend_report = node_report
+                .funder_report
+                .friends
+                .get(&friend_public_key)
+                .unwrap();
+            match &friend_report.close_status {
+                FriendCloseStatusReport::Closed(friend_settlement) => friend_settlement,
+                FriendCloseStatusReport::Open | FriendCloseStatusReport::Closing => {
+                    return Err(InfoError::FriendNotClosed)
+                }
+            }
+        } else {
+            // The friend might have already been removed after the channel was closed:
+            &node_report
+                .funder_report
+                .closed_friends
+                .iter()
+                .find(|closed_friend_report| closed_friend_report.name == friend_name)
+                .ok_or(InfoError::FriendNameNotFound)?
+                .settlement
+        };
+    let settlement_file: SettlementFile = friend_settlement.clone().into();
+
+    let mut file = File::create(settlement_path)?;
+    file.write_all(&serialize_to_string(&settlement_file)?.as_bytes())?;
+    Ok(())
+}
+
 /*
 /// Get an approximate value for mutual balance with a friend.
 /// In case of an inconsistency we take the local reset terms to represent the balance.
@@ -418,6 +488,9 @@ pub async fn info(
         InfoCmd::FriendLastToken(friend_last_token_cmd) => {
             info_friend_last_token(friend_last_token_cmd, node_report).await?
         }
+        InfoCmd::FriendSettlement(friend_settlement_cmd) => {
+            info_friend_settlement(friend_settlement_cmd, node_report).await?
+        }
         // InfoCmd::Balance(_balance_cmd) => info_balance(node_report, writer).await?,
         InfoCmd::ExportTicket(export_ticket_cmd) => {
             info_export_ticket(export_ticket_cmd, node_report).await?
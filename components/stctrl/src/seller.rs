@@ -1,7 +1,8 @@
 use std::convert::TryFrom;
 use std::fs::{self, File};
-use std::io::Write;
+use std::io::{self, Write};
 use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use futures::sink::SinkExt;
 use futures::stream::StreamExt;
@@ -11,6 +12,7 @@ use derive_more::From;
 use app::common::{Commit, Currency, PublicKey};
 use app::conn::{self, AppRequest, AppServerToApp, AppToAppServer, ConnPairApp};
 use app::gen::{gen_invoice_id, gen_uid};
+use app::payment_uri::{render_payment_uri, PaymentUri, PaymentUriError};
 use app::report::NodeReport;
 use app::ser_utils::{deserialize_from_string, serialize_to_string, StringSerdeError};
 use app::verify::verify_commit;
@@ -31,6 +33,15 @@ pub struct CreateInvoiceCmd {
     /// Path of output invoice file
     #[structopt(parse(from_os_str), short = "i", long = "invoice")]
     pub invoice_path: PathBuf,
+    /// Also print the invoice as a payment URI
+    #[structopt(long = "uri")]
+    pub uri: bool,
+    /// Invoice description (Included in the payment URI, requires --uri)
+    #[structopt(short = "d", long = "description")]
+    pub description: Option<String>,
+    /// Amount of seconds until the invoice expires (Included in the payment URI, requires --uri)
+    #[structopt(long = "expires-in")]
+    pub expires_in: Option<u64>,
 }
 
 /// Cancel invoice
@@ -52,6 +63,39 @@ pub struct CommitInvoiceCmd {
     pub commit_path: PathBuf,
 }
 
+/// Hold a paid invoice until it is committed or canceled (using a Commit message from buyer)
+#[derive(Clone, Debug, StructOpt)]
+pub struct HoldInvoiceCmd {
+    /// Path to invoice file
+    #[structopt(parse(from_os_str), short = "i", long = "invoice")]
+    pub invoice_path: PathBuf,
+    /// Path to commit file
+    #[structopt(parse(from_os_str), short = "c", long = "commit")]
+    pub commit_path: PathBuf,
+    /// Maximum amount of node timer ticks to hold the invoice before it is canceled
+    #[structopt(short = "t", long = "max-hold-ticks")]
+    pub max_hold_ticks: u64,
+}
+
+/// Accept push payments (Payments without an invoice)
+#[derive(Clone, Debug, StructOpt)]
+pub struct EnablePushPaymentsCmd {
+    /// Currency in which push payments are accepted
+    #[structopt(short = "c", long = "currency")]
+    pub currency_name: String,
+    /// Maximum amount of credits accepted for a single push payment
+    #[structopt(short = "m", long = "max-amount")]
+    pub max_amount: u128,
+}
+
+/// Stop accepting push payments
+#[derive(Clone, Debug, StructOpt)]
+pub struct DisablePushPaymentsCmd {
+    /// Currency in which push payments are no longer accepted
+    #[structopt(short = "c", long = "currency")]
+    pub currency_name: String,
+}
+
 /// Funds sending related commands
 #[derive(Clone, Debug, StructOpt)]
 pub enum SellerCmd {
@@ -64,6 +108,15 @@ pub enum SellerCmd {
     /// Commit an invoice (Using a Commit message from buyer)
     #[structopt(name = "commit-invoice")]
     CommitInvoice(CommitInvoiceCmd),
+    /// Hold a paid invoice until it is committed or canceled (Funds remain locked meanwhile)
+    #[structopt(name = "hold-invoice")]
+    HoldInvoice(HoldInvoiceCmd),
+    /// Accept push payments in a currency
+    #[structopt(name = "enable-push-payments")]
+    EnablePushPayments(EnablePushPaymentsCmd),
+    /// Stop accepting push payments in a currency
+    #[structopt(name = "disable-push-payments")]
+    DisablePushPayments(DisablePushPaymentsCmd),
 }
 
 #[derive(Debug, From)]
@@ -78,6 +131,7 @@ pub enum SellerError {
     CancelInvoiceError,
     LoadCommitError,
     CommitInvoiceError,
+    HoldInvoiceError,
     InvoiceCommitMismatch,
     RemoveInvoiceError,
     IoError(std::io::Error),
@@ -85,6 +139,10 @@ pub enum SellerError {
     InvalidCurrencyName,
     InvalidCommit,
     SellerRequestError,
+    PaymentUriError(PaymentUriError),
+    UriOptionsWithoutUri,
+    WriteError,
+    PushPaymentsError,
 }
 
 async fn seller_request(
@@ -120,13 +178,22 @@ async fn seller_create_invoice(
     create_invoice_cmd: CreateInvoiceCmd,
     local_public_key: PublicKey,
     mut conn_pair: ConnPairApp,
+    writer: &mut impl io::Write,
 ) -> Result<(), SellerError> {
     let CreateInvoiceCmd {
         currency_name,
         amount,
         invoice_path,
+        uri,
+        description,
+        expires_in,
     } = create_invoice_cmd;
 
+    // Description and expiry are only communicated through the payment URI:
+    if !uri && (description.is_some() || expires_in.is_some()) {
+        return Err(SellerError::UriOptionsWithoutUri);
+    }
+
     let currency =
         Currency::try_from(currency_name).map_err(|_| SellerError::InvalidCurrencyName)?;
 
@@ -155,6 +222,27 @@ async fn seller_create_invoice(
 
     let mut file = File::create(invoice_path)?;
     file.write_all(&serialize_to_string(&invoice_file)?.as_bytes())?;
+
+    if uri {
+        let opt_expiry = expires_in.map(|expires_in| {
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or(0);
+            now.saturating_add(expires_in)
+        });
+
+        let payment_uri = PaymentUri {
+            invoice_id: invoice_file.invoice_id,
+            currency: invoice_file.currency,
+            dest_public_key: invoice_file.dest_public_key,
+            dest_payment: invoice_file.dest_payment,
+            opt_description: description,
+            opt_expiry,
+        };
+        writeln!(writer, "{}", render_payment_uri(&payment_uri)?)
+            .map_err(|_| SellerError::WriteError)?;
+    }
     Ok(())
 }
 
@@ -176,22 +264,18 @@ async fn seller_cancel_invoice(
     fs::remove_file(&invoice_path).map_err(|_| SellerError::RemoveInvoiceError)
 }
 
-async fn seller_commit_invoice(
-    commit_invoice_cmd: CommitInvoiceCmd,
-    mut conn_pair: ConnPairApp,
-) -> Result<(), SellerError> {
-    let CommitInvoiceCmd {
-        invoice_path,
-        commit_path,
-    } = commit_invoice_cmd;
-
+/// Load a commit file, and make sure it matches the given invoice file.
+fn load_invoice_commit(
+    invoice_path: &PathBuf,
+    commit_path: &PathBuf,
+) -> Result<Commit, SellerError> {
     // Note: We don't really need the invoice for the internal API.
     // We require it here to enforce the user to understand that the commit file corresponds to a
     // certain invoice file.
 
-    let invoice_file: InvoiceFile = deserialize_from_string(&fs::read_to_string(&invoice_path)?)?;
+    let invoice_file: InvoiceFile = deserialize_from_string(&fs::read_to_string(invoice_path)?)?;
 
-    let commit_file: CommitFile = deserialize_from_string(&fs::read_to_string(&commit_path)?)?;
+    let commit_file: CommitFile = deserialize_from_string(&fs::read_to_string(commit_path)?)?;
     let commit = Commit::from(commit_file);
 
     if !verify_commit(&commit, &invoice_file.dest_public_key) {
@@ -202,15 +286,87 @@ async fn seller_commit_invoice(
         return Err(SellerError::InvoiceCommitMismatch);
     }
 
+    Ok(commit)
+}
+
+async fn seller_commit_invoice(
+    commit_invoice_cmd: CommitInvoiceCmd,
+    mut conn_pair: ConnPairApp,
+) -> Result<(), SellerError> {
+    let CommitInvoiceCmd {
+        invoice_path,
+        commit_path,
+    } = commit_invoice_cmd;
+
+    let commit = load_invoice_commit(&invoice_path, &commit_path)?;
+
     seller_request(&mut conn_pair, conn::seller::commit_invoice(commit))
         .await
         .map_err(|_| SellerError::CommitInvoiceError)
 }
 
+async fn seller_hold_invoice(
+    hold_invoice_cmd: HoldInvoiceCmd,
+    mut conn_pair: ConnPairApp,
+) -> Result<(), SellerError> {
+    let HoldInvoiceCmd {
+        invoice_path,
+        commit_path,
+        max_hold_ticks,
+    } = hold_invoice_cmd;
+
+    let commit = load_invoice_commit(&invoice_path, &commit_path)?;
+
+    seller_request(
+        &mut conn_pair,
+        conn::seller::hold_invoice(commit, max_hold_ticks),
+    )
+    .await
+    .map_err(|_| SellerError::HoldInvoiceError)
+}
+
+async fn seller_enable_push_payments(
+    enable_push_payments_cmd: EnablePushPaymentsCmd,
+    mut conn_pair: ConnPairApp,
+) -> Result<(), SellerError> {
+    let EnablePushPaymentsCmd {
+        currency_name,
+        max_amount,
+    } = enable_push_payments_cmd;
+
+    let currency =
+        Currency::try_from(currency_name).map_err(|_| SellerError::InvalidCurrencyName)?;
+
+    seller_request(
+        &mut conn_pair,
+        conn::seller::enable_push_payments(currency, max_amount),
+    )
+    .await
+    .map_err(|_| SellerError::PushPaymentsError)
+}
+
+async fn seller_disable_push_payments(
+    disable_push_payments_cmd: DisablePushPaymentsCmd,
+    mut conn_pair: ConnPairApp,
+) -> Result<(), SellerError> {
+    let DisablePushPaymentsCmd { currency_name } = disable_push_payments_cmd;
+
+    let currency =
+        Currency::try_from(currency_name).map_err(|_| SellerError::InvalidCurrencyName)?;
+
+    seller_request(
+        &mut conn_pair,
+        conn::seller::disable_push_payments(currency),
+    )
+    .await
+    .map_err(|_| SellerError::PushPaymentsError)
+}
+
 pub async fn seller(
     seller_cmd: SellerCmd,
     node_report: &NodeReport,
     conn_pair: ConnPairApp,
+    writer: &mut impl io::Write,
 ) -> Result<(), SellerError> {
     // Get our local public key:
     let local_public_key = node_report.funder_report.local_public_key.clone();
@@ -225,7 +381,7 @@ pub async fn seller(
 
     match seller_cmd {
         SellerCmd::CreateInvoice(create_invoice_cmd) => {
-            seller_create_invoice(create_invoice_cmd, local_public_key, conn_pair).await?
+            seller_create_invoice(create_invoice_cmd, local_public_key, conn_pair, writer).await?
         }
         SellerCmd::CancelInvoice(cancel_invoice_cmd) => {
             seller_cancel_invoice(cancel_invoice_cmd, conn_pair).await?
@@ -233,6 +389,15 @@ pub async fn seller(
         SellerCmd::CommitInvoice(commit_invoice_cmd) => {
             seller_commit_invoice(commit_invoice_cmd, conn_pair).await?
         }
+        SellerCmd::HoldInvoice(hold_invoice_cmd) => {
+            seller_hold_invoice(hold_invoice_cmd, conn_pair).await?
+        }
+        SellerCmd::EnablePushPayments(enable_push_payments_cmd) => {
+            seller_enable_push_payments(enable_push_payments_cmd, conn_pair).await?
+        }
+        SellerCmd::DisablePushPayments(disable_push_payments_cmd) => {
+            seller_disable_push_payments(disable_push_payments_cmd, conn_pair).await?
+        }
     }
 
     Ok(())
@@ -1,5 +1,6 @@
 use std::fs;
 use std::io;
+use std::net::SocketAddr;
 use std::path::PathBuf;
 
 use futures::executor::{block_on, ThreadPool};
@@ -13,7 +14,7 @@ use crate::config::{config, ConfigCmd, ConfigError};
 use crate::info::{info, InfoCmd, InfoError};
 use crate::seller::{seller, SellerCmd, SellerError};
 
-use app::conn::{connect, identity_from_file};
+use app::conn::{connect_with_proxy, identity_from_file};
 use app::file::NodeAddressFile;
 use app::ser_utils::{deserialize_from_string, StringSerdeError};
 
@@ -64,6 +65,10 @@ pub struct StCtrlCmd {
     /// Node ticket file path
     #[structopt(parse(from_os_str), short = "T", long = "ticket")]
     pub node_ticket: PathBuf,
+    /// SOCKS5 proxy address (For example, Tor: 127.0.0.1:9050).
+    /// If specified, the connection to the node is made through the proxy.
+    #[structopt(long = "socks5")]
+    pub socks5: Option<SocketAddr>,
     #[structopt(flatten)]
     pub subcommand: StCtrlSubcommand,
 }
@@ -74,6 +79,7 @@ pub fn stctrl(st_ctrl_cmd: StCtrlCmd, writer: &mut impl io::Write) -> Result<(),
     let StCtrlCmd {
         idfile,
         node_ticket,
+        socks5,
         subcommand,
     } = st_ctrl_cmd;
 
@@ -96,10 +102,11 @@ pub fn stctrl(st_ctrl_cmd: StCtrlCmd, writer: &mut impl io::Write) -> Result<(),
 
     block_on(async move {
         // Connect to node:
-        let (app_permissions, node_report, conn_pair) = connect(
+        let (app_permissions, node_report, conn_pair) = connect_with_proxy(
             node_address_file.public_key,
             node_address_file.address,
             app_identity_client,
+            socks5,
             thread_pool.clone(),
         )
         .await
@@ -123,7 +130,7 @@ pub fn stctrl(st_ctrl_cmd: StCtrlCmd, writer: &mut impl io::Write) -> Result<(),
             }
             StCtrlSubcommand::Seller(seller_cmd) => {
                 if app_permissions.seller {
-                    seller(seller_cmd, &node_report, conn_pair).await?
+                    seller(seller_cmd, &node_report, conn_pair, writer).await?
                 } else {
                     return Err(StCtrlError::InsufficientPermissions);
                 }
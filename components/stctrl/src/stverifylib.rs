@@ -6,12 +6,12 @@ use derive_more::From;
 
 use structopt::StructOpt;
 
-use crate::file::{InvoiceFile, ReceiptFile, TokenFile};
+use crate::file::{InvoiceFile, ReceiptFile, SettlementFile, TokenFile};
 
 use app::common::Receipt;
-use app::report::MoveTokenHashedReport;
+use app::report::{FriendSettlement, MoveTokenHashedReport};
 use app::ser_utils::{deserialize_from_string, public_key_to_string, StringSerdeError};
-use app::verify::{verify_move_token_hashed_report, verify_receipt};
+use app::verify::{verify_friend_settlement, verify_move_token_hashed_report, verify_receipt};
 
 #[derive(Debug, From)]
 pub enum StVerifyError {
@@ -23,6 +23,7 @@ pub enum StVerifyError {
     InvoiceIdMismatch,
     DestPaymentMismatch,
     InvalidReceipt,
+    InvalidSettlement,
     IoError(std::io::Error),
     StringSerdeError(StringSerdeError),
 }
@@ -47,6 +48,15 @@ pub struct VerifyReceiptCmd {
     pub receipt_path: PathBuf,
 }
 
+/// Verify a final settlement with a friend.
+/// A settlement contains the final balances of a closed channel, signed by both sides.
+#[derive(Clone, Debug, StructOpt)]
+pub struct VerifySettlementCmd {
+    /// Path of settlement file
+    #[structopt(parse(from_os_str), short = "s", long = "settlement")]
+    pub settlement_path: PathBuf,
+}
+
 /// stctrl: offSeT ConTRoL
 /// An application used to interface with the Offset node
 /// Allows to view node's state information, configure node's state and send funds to remote nodes.
@@ -59,6 +69,9 @@ pub enum StVerifyCmd {
     /// Verify a receipt against an invoice
     #[structopt(name = "verify-receipt")]
     VerifyReceipt(VerifyReceiptCmd),
+    /// Verify a final settlement with a friend
+    #[structopt(name = "verify-settlement")]
+    VerifySettlement(VerifySettlementCmd),
 }
 
 /// Verify a given friend token
@@ -160,6 +173,63 @@ fn stverify_verify_receipt(
     }
 }
 
+/// Verify a given settlement
+/// If the given settlement is valid, output the final balances
+fn stverify_verify_settlement(
+    verify_settlement_cmd: VerifySettlementCmd,
+    writer: &mut impl io::Write,
+) -> Result<(), StVerifyError> {
+    let settlement_file: SettlementFile =
+        deserialize_from_string(&fs::read_to_string(&verify_settlement_cmd.settlement_path)?)?;
+
+    let friend_settlement = FriendSettlement::from(settlement_file);
+
+    if !verify_friend_settlement(&friend_settlement) {
+        return Err(StVerifyError::InvalidSettlement);
+    }
+
+    let token_info = &friend_settlement.token_info;
+    writeln!(writer, "Settlement is valid!").map_err(|_| StVerifyError::WriteError)?;
+    writeln!(writer).map_err(|_| StVerifyError::WriteError)?;
+    writeln!(
+        writer,
+        "local_public_key: {}",
+        public_key_to_string(&token_info.mc.local_public_key)
+    )
+    .map_err(|_| StVerifyError::WriteError)?;
+    writeln!(
+        writer,
+        "remote_public_key: {}",
+        public_key_to_string(&token_info.mc.remote_public_key)
+    )
+    .map_err(|_| StVerifyError::WriteError)?;
+    writeln!(
+        writer,
+        "inconsistency_counter: {}",
+        token_info.counters.inconsistency_counter
+    )
+    .map_err(|_| StVerifyError::WriteError)?;
+    writeln!(
+        writer,
+        "move_token_counter: {}",
+        token_info.counters.move_token_counter
+    )
+    .map_err(|_| StVerifyError::WriteError)?;
+
+    writeln!(writer, "balances:\n").map_err(|_| StVerifyError::WriteError)?;
+
+    for currency_balance_info in &token_info.mc.balances {
+        writeln!(
+            writer,
+            "- {}: balance={}",
+            currency_balance_info.currency, currency_balance_info.balance_info.balance,
+        )
+        .map_err(|_| StVerifyError::WriteError)?;
+    }
+
+    Ok(())
+}
+
 pub fn stverify(
     st_verify_cmd: StVerifyCmd,
     writer: &mut impl io::Write,
@@ -171,5 +241,8 @@ pub fn stverify(
         StVerifyCmd::VerifyReceipt(verify_receipt_cmd) => {
             stverify_verify_receipt(verify_receipt_cmd, writer)
         }
+        StVerifyCmd::VerifySettlement(verify_settlement_cmd) => {
+            stverify_verify_settlement(verify_settlement_cmd, writer)
+        }
     }
 }
@@ -52,6 +52,7 @@ pub async fn request_routes(
     let request_routes_id = gen_uid();
     let app_request = conn::routes::request_routes(
         request_routes_id.clone(),
+        currency.clone(),
         currency,
         dest_payment,
         src_public_key,
@@ -200,6 +201,7 @@ pub async fn create_transaction(
         route,
         dest_payment,
         fees,
+        Vec::new(),
     );
     let app_request_id = gen_uid();
     let app_to_app_server = AppToAppServer {
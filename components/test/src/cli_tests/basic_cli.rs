@@ -1,4 +1,4 @@
-use std::{str, thread, time};
+use std::{fs, str, thread, time};
 
 use tempfile::tempdir;
 
@@ -13,7 +13,7 @@ use stctrl::config::{
     SetFriendCurrencyRateCmd,
 };
 
-use stctrl::buyer::{BuyerCmd, BuyerError, PayInvoiceCmd, PaymentStatusCmd};
+use stctrl::buyer::{BuyerCmd, BuyerError, PayBatchCmd, PayInvoiceCmd, PaymentStatusCmd};
 use stctrl::info::{ExportTicketCmd, FriendLastTokenCmd, FriendsCmd, InfoCmd};
 use stctrl::seller::{CancelInvoiceCmd, CommitInvoiceCmd, CreateInvoiceCmd, SellerCmd};
 use stctrl::stctrllib::{stctrl, StCtrlCmd, StCtrlError, StCtrlSubcommand};
@@ -33,6 +33,20 @@ fn spawn_entities(stctrl_setup: &StCtrlSetup) {
         lclient: stctrl_setup.index0_client_addr.parse().unwrap(),
         lserver: stctrl_setup.index0_server_addr.parse().unwrap(),
         trusted: stctrl_setup.temp_dir_path.join("index0").join("trusted"),
+        socks5: None,
+        snapshot: Some(
+            stctrl_setup
+                .temp_dir_path
+                .join("index0")
+                .join("graph_snapshot"),
+        ),
+        stale_ticks: 600,
+        ladmin: None,
+        admins: None,
+        routes_burst: 16,
+        routes_per_tick: 4,
+        mutations_burst: 256,
+        mutations_per_tick: 8,
     };
     // TODO: How can we close this thread?
     thread::spawn(move || {
@@ -49,6 +63,15 @@ fn spawn_entities(stctrl_setup: &StCtrlSetup) {
         lclient: stctrl_setup.index1_client_addr.parse().unwrap(),
         lserver: stctrl_setup.index1_server_addr.parse().unwrap(),
         trusted: stctrl_setup.temp_dir_path.join("index1").join("trusted"),
+        socks5: None,
+        snapshot: None,
+        stale_ticks: 600,
+        ladmin: None,
+        admins: None,
+        routes_burst: 16,
+        routes_per_tick: 4,
+        mutations_burst: 256,
+        mutations_per_tick: 8,
     };
     // TODO: How can we close this thread?
     thread::spawn(move || {
@@ -63,6 +86,7 @@ fn spawn_entities(stctrl_setup: &StCtrlSetup) {
             .join("relay0")
             .join("relay0.ident"),
         laddr: stctrl_setup.relay0_addr.parse().unwrap(),
+        listeners: None,
     };
     // TODO: How can we close this thread?
     thread::spawn(move || {
@@ -77,6 +101,7 @@ fn spawn_entities(stctrl_setup: &StCtrlSetup) {
             .join("relay1")
             .join("relay1.ident"),
         laddr: stctrl_setup.relay1_addr.parse().unwrap(),
+        listeners: None,
     };
     // TODO: How can we close this thread?
     thread::spawn(move || {
@@ -87,9 +112,17 @@ fn spawn_entities(stctrl_setup: &StCtrlSetup) {
     // Spawn node0:
     let st_node_cmd = StNodeCmd {
         idfile: stctrl_setup.temp_dir_path.join("node0").join("node0.ident"),
-        laddr: stctrl_setup.node0_addr.clone().parse().unwrap(),
+        laddr: vec![stctrl_setup.node0_addr.clone().parse().unwrap()],
+        lunix: Vec::new(),
+        lws: Vec::new(),
+        socks5: None,
         database: stctrl_setup.temp_dir_path.join("node0").join("node0.db"),
         trusted: stctrl_setup.temp_dir_path.join("node0").join("trusted"),
+        ldirect: Vec::new(),
+        direct_address: Vec::new(),
+        pad_traffic: false,
+        cover_ticks: None,
+        resume_sessions: false,
     };
     // TODO: How can we close this thread?
     thread::spawn(move || {
@@ -100,9 +133,17 @@ fn spawn_entities(stctrl_setup: &StCtrlSetup) {
     // Spawn node1:
     let st_node_cmd = StNodeCmd {
         idfile: stctrl_setup.temp_dir_path.join("node1").join("node1.ident"),
-        laddr: stctrl_setup.node1_addr.clone().parse().unwrap(),
+        laddr: vec![stctrl_setup.node1_addr.clone().parse().unwrap()],
+        lunix: Vec::new(),
+        lws: Vec::new(),
+        socks5: None,
         database: stctrl_setup.temp_dir_path.join("node1").join("node1.db"),
         trusted: stctrl_setup.temp_dir_path.join("node1").join("trusted"),
+        ldirect: Vec::new(),
+        direct_address: Vec::new(),
+        pad_traffic: false,
+        cover_ticks: None,
+        resume_sessions: false,
     };
     // TODO: How can we close this thread?
     thread::spawn(move || {
@@ -127,6 +168,7 @@ fn get_node_public_key(stctrl_setup: &StCtrlSetup, index: usize) -> String {
             .temp_dir_path
             .join(format!("node{}", index))
             .join(format!("node{}.ticket", index)),
+        socks5: None,
         subcommand,
     };
 
@@ -159,6 +201,7 @@ fn configure_mutual_credit(stctrl_setup: &StCtrlSetup) {
                 .temp_dir_path
                 .join(format!("node{}", j))
                 .join(format!("node{}.ticket", j)),
+            socks5: None,
             subcommand,
         };
 
@@ -196,6 +239,7 @@ fn configure_mutual_credit(stctrl_setup: &StCtrlSetup) {
                 .temp_dir_path
                 .join(format!("node{}", j))
                 .join(format!("node{}.ticket", j)),
+            socks5: None,
             subcommand,
         };
         stctrl(st_ctrl_cmd, &mut Vec::new()).unwrap();
@@ -225,6 +269,7 @@ fn configure_mutual_credit(stctrl_setup: &StCtrlSetup) {
                 .temp_dir_path
                 .join(format!("node{}", j))
                 .join(format!("node{}.ticket", j)),
+            socks5: None,
             subcommand,
         };
         stctrl(st_ctrl_cmd, &mut Vec::new()).unwrap();
@@ -253,6 +298,7 @@ fn configure_mutual_credit(stctrl_setup: &StCtrlSetup) {
                 .temp_dir_path
                 .join(format!("node{}", j))
                 .join(format!("node{}.ticket", j)),
+            socks5: None,
             subcommand,
         };
         stctrl(st_ctrl_cmd, &mut Vec::new()).unwrap();
@@ -282,6 +328,7 @@ fn configure_mutual_credit(stctrl_setup: &StCtrlSetup) {
                 .temp_dir_path
                 .join(format!("node{}", j))
                 .join(format!("node{}.ticket", j)),
+            socks5: None,
             subcommand,
         };
         stctrl(st_ctrl_cmd, &mut Vec::new()).unwrap();
@@ -310,6 +357,7 @@ fn configure_mutual_credit(stctrl_setup: &StCtrlSetup) {
                 .temp_dir_path
                 .join(format!("node{}", j))
                 .join(format!("node{}.ticket", j)),
+            socks5: None,
             subcommand,
         };
         stctrl(st_ctrl_cmd, &mut Vec::new()).unwrap();
@@ -333,6 +381,7 @@ fn configure_mutual_credit(stctrl_setup: &StCtrlSetup) {
                 .temp_dir_path
                 .join(format!("node{}", j))
                 .join(format!("node{}.ticket", j)),
+            socks5: None,
             subcommand,
         };
         stctrl(st_ctrl_cmd, &mut Vec::new()).unwrap();
@@ -353,6 +402,7 @@ fn configure_mutual_credit(stctrl_setup: &StCtrlSetup) {
                 .temp_dir_path
                 .join(format!("node{}", j))
                 .join(format!("node{}.ticket", j)),
+            socks5: None,
             subcommand,
         };
 
@@ -387,6 +437,7 @@ fn configure_mutual_credit(stctrl_setup: &StCtrlSetup) {
                 .temp_dir_path
                 .join(format!("node{}", j))
                 .join(format!("node{}.ticket", j)),
+            socks5: None,
             subcommand,
         };
         stctrl(st_ctrl_cmd, &mut Vec::new()).unwrap();
@@ -428,6 +479,7 @@ fn add_remove_currency(stctrl_setup: &StCtrlSetup) {
                 .temp_dir_path
                 .join(format!("node{}", j))
                 .join(format!("node{}.ticket", j)),
+            socks5: None,
             subcommand,
         };
         stctrl(st_ctrl_cmd, &mut Vec::new()).unwrap();
@@ -454,6 +506,7 @@ fn add_remove_currency(stctrl_setup: &StCtrlSetup) {
                 .temp_dir_path
                 .join(format!("node{}", j))
                 .join(format!("node{}.ticket", j)),
+            socks5: None,
             subcommand,
         };
         stctrl(st_ctrl_cmd, &mut Vec::new()).unwrap();
@@ -477,6 +530,7 @@ fn set_max_debt(stctrl_setup: &StCtrlSetup) {
             .temp_dir_path
             .join("node0")
             .join("node0.ticket"),
+        socks5: None,
         subcommand,
     };
     stctrl(st_ctrl_cmd, &mut Vec::new()).unwrap();
@@ -495,6 +549,9 @@ fn create_cancel_invoice(stctrl_setup: &StCtrlSetup) {
             .temp_dir_path
             .join("node0")
             .join("temp_invoice.invoice"),
+        uri: false,
+        description: None,
+        expires_in: None,
     };
     let seller_cmd = SellerCmd::CreateInvoice(create_invoice_cmd);
     let subcommand = StCtrlSubcommand::Seller(seller_cmd);
@@ -505,6 +562,7 @@ fn create_cancel_invoice(stctrl_setup: &StCtrlSetup) {
             .temp_dir_path
             .join("node0")
             .join("node0.ticket"),
+        socks5: None,
         subcommand,
     };
     stctrl(st_ctrl_cmd.clone(), &mut Vec::new()).unwrap();
@@ -526,6 +584,7 @@ fn create_cancel_invoice(stctrl_setup: &StCtrlSetup) {
             .temp_dir_path
             .join("node0")
             .join("node0.ticket"),
+        socks5: None,
         subcommand,
     };
     stctrl(st_ctrl_cmd.clone(), &mut Vec::new()).unwrap();
@@ -539,19 +598,26 @@ fn create_cancel_invoice(stctrl_setup: &StCtrlSetup) {
 }
 
 /// Node0: generate an invoice
-/// Node1: pay the invoice
+/// Node1: pay the invoice (Using the invoice file, or using a payment URI)
 /// Node0: Commit invoice
 /// Node1: Wait for receipt
-fn pay_invoice(stctrl_setup: &StCtrlSetup) {
+fn pay_single_invoice(stctrl_setup: &StCtrlSetup, invoice_name: &str, amount: u128, uri: bool) {
     // Node0: generate an invoice:
     // ---------------------------
     let create_invoice_cmd = CreateInvoiceCmd {
         currency_name: "FST".to_owned(),
-        amount: 50,
+        amount,
         invoice_path: stctrl_setup
             .temp_dir_path
             .join("node0")
-            .join("test1.invoice"),
+            .join(format!("{}.invoice", invoice_name)),
+        uri,
+        description: if uri {
+            Some("Test invoice".to_owned())
+        } else {
+            None
+        },
+        expires_in: if uri { Some(3600) } else { None },
     };
     let seller_cmd = SellerCmd::CreateInvoice(create_invoice_cmd);
     let subcommand = StCtrlSubcommand::Seller(seller_cmd);
@@ -562,26 +628,44 @@ fn pay_invoice(stctrl_setup: &StCtrlSetup) {
             .temp_dir_path
             .join("node0")
             .join("node0.ticket"),
+        socks5: None,
         subcommand,
     };
-    stctrl(st_ctrl_cmd.clone(), &mut Vec::new()).unwrap();
+    let mut output = Vec::new();
+    stctrl(st_ctrl_cmd.clone(), &mut output).unwrap();
+    let opt_invoice_uri = if uri {
+        Some(String::from_utf8(output).unwrap().trim().to_owned())
+    } else {
+        assert!(output.is_empty());
+        None
+    };
 
     // Node1: pay the invoice:
     // -----------------------
     loop {
+        // Pay using the payment URI created by the seller, or using the invoice file:
+        let opt_invoice_path = if opt_invoice_uri.is_none() {
+            Some(
+                stctrl_setup
+                    .temp_dir_path
+                    .join("node0")
+                    .join(format!("{}.invoice", invoice_name)),
+            )
+        } else {
+            None
+        };
         let pay_invoice_cmd = PayInvoiceCmd {
-            invoice_path: stctrl_setup
-                .temp_dir_path
-                .join("node0")
-                .join("test1.invoice"),
+            invoice_path: opt_invoice_path,
+            invoice_uri: opt_invoice_uri.clone(),
             payment_path: stctrl_setup
                 .temp_dir_path
                 .join("node1")
-                .join("test1.payment"),
+                .join(format!("{}.payment", invoice_name)),
             commit_path: stctrl_setup
                 .temp_dir_path
                 .join("node1")
-                .join("test1.commit"),
+                .join(format!("{}.commit", invoice_name)),
+            opt_from_currency_name: None,
         };
         let buyer_cmd = BuyerCmd::PayInvoice(pay_invoice_cmd);
         let subcommand = StCtrlSubcommand::Buyer(buyer_cmd);
@@ -592,6 +676,7 @@ fn pay_invoice(stctrl_setup: &StCtrlSetup) {
                 .temp_dir_path
                 .join("node1")
                 .join("node1.ticket"),
+            socks5: None,
             subcommand,
         };
 
@@ -612,11 +697,11 @@ fn pay_invoice(stctrl_setup: &StCtrlSetup) {
         invoice_path: stctrl_setup
             .temp_dir_path
             .join("node0")
-            .join("test1.invoice"),
+            .join(format!("{}.invoice", invoice_name)),
         commit_path: stctrl_setup
             .temp_dir_path
             .join("node1")
-            .join("test1.commit"),
+            .join(format!("{}.commit", invoice_name)),
     };
 
     let seller_cmd = SellerCmd::CommitInvoice(commit_invoice_cmd);
@@ -628,6 +713,7 @@ fn pay_invoice(stctrl_setup: &StCtrlSetup) {
             .temp_dir_path
             .join("node0")
             .join("node0.ticket"),
+        socks5: None,
         subcommand,
     };
     stctrl(st_ctrl_cmd.clone(), &mut Vec::new()).unwrap();
@@ -638,11 +724,11 @@ fn pay_invoice(stctrl_setup: &StCtrlSetup) {
         payment_path: stctrl_setup
             .temp_dir_path
             .join("node1")
-            .join("test1.payment"),
+            .join(format!("{}.payment", invoice_name)),
         receipt_path: stctrl_setup
             .temp_dir_path
             .join("node1")
-            .join("test1.receipt"),
+            .join(format!("{}.receipt", invoice_name)),
     };
     let buyer_cmd = BuyerCmd::PaymentStatus(payment_status_cmd);
     let subcommand = StCtrlSubcommand::Buyer(buyer_cmd);
@@ -653,6 +739,7 @@ fn pay_invoice(stctrl_setup: &StCtrlSetup) {
             .temp_dir_path
             .join("node1")
             .join("node1.ticket"),
+        socks5: None,
         subcommand,
     };
 
@@ -671,7 +758,7 @@ fn pay_invoice(stctrl_setup: &StCtrlSetup) {
     assert!(!stctrl_setup
         .temp_dir_path
         .join("node1")
-        .join("test1.payment")
+        .join(format!("{}.payment", invoice_name))
         .exists());
 
     // Verify the receipt:
@@ -680,11 +767,11 @@ fn pay_invoice(stctrl_setup: &StCtrlSetup) {
         invoice_path: stctrl_setup
             .temp_dir_path
             .join("node0")
-            .join("test1.invoice"),
+            .join(format!("{}.invoice", invoice_name)),
         receipt_path: stctrl_setup
             .temp_dir_path
             .join("node1")
-            .join("test1.receipt"),
+            .join(format!("{}.receipt", invoice_name)),
     };
 
     let stverify_cmd = StVerifyCmd::VerifyReceipt(verify_receipt_cmd);
@@ -693,6 +780,144 @@ fn pay_invoice(stctrl_setup: &StCtrlSetup) {
     assert!(str::from_utf8(&output).unwrap().contains("is valid!"));
 }
 
+/// Pay an invoice using an invoice file, and another invoice using a payment URI
+fn pay_invoice(stctrl_setup: &StCtrlSetup) {
+    pay_single_invoice(stctrl_setup, "test1", 30, false);
+    pay_single_invoice(stctrl_setup, "test2", 20, true);
+}
+
+/// Node0: generate two invoices
+/// Node1: pay both invoices using a batch file
+/// Node0: Commit the invoices
+/// Node1: Wait for receipts
+fn pay_batch(stctrl_setup: &StCtrlSetup) {
+    // Node0: generate an invoice file, and an invoice given as a payment URI:
+    // ----------------------------------------------------------------------
+    let mut batch_lines = vec!["# Batch payment test".to_owned()];
+    for (invoice_name, amount, uri) in &[("batch1", 5, false), ("batch2", 7, true)] {
+        let invoice_path = stctrl_setup
+            .temp_dir_path
+            .join("node0")
+            .join(format!("{}.invoice", invoice_name));
+        let create_invoice_cmd = CreateInvoiceCmd {
+            currency_name: "FST".to_owned(),
+            amount: *amount,
+            invoice_path: invoice_path.clone(),
+            uri: *uri,
+            description: None,
+            expires_in: None,
+        };
+        let seller_cmd = SellerCmd::CreateInvoice(create_invoice_cmd);
+        let subcommand = StCtrlSubcommand::Seller(seller_cmd);
+
+        let st_ctrl_cmd = StCtrlCmd {
+            idfile: stctrl_setup.temp_dir_path.join("app0").join("app0.ident"),
+            node_ticket: stctrl_setup
+                .temp_dir_path
+                .join("node0")
+                .join("node0.ticket"),
+            socks5: None,
+            subcommand,
+        };
+        let mut output = Vec::new();
+        stctrl(st_ctrl_cmd.clone(), &mut output).unwrap();
+        if *uri {
+            batch_lines.push(String::from_utf8(output).unwrap().trim().to_owned());
+        } else {
+            batch_lines.push(invoice_path.to_str().unwrap().to_owned());
+        }
+    }
+
+    let batch_path = stctrl_setup.temp_dir_path.join("node1").join("test.batch");
+    fs::write(&batch_path, batch_lines.join("\n")).unwrap();
+    let output_path = stctrl_setup.temp_dir_path.join("node1").join("batch");
+
+    // Node1: pay the batch:
+    // ---------------------
+    let pay_batch_cmd = PayBatchCmd {
+        batch_path,
+        output_path: output_path.clone(),
+        max_concurrent: 2,
+    };
+    let buyer_cmd = BuyerCmd::PayBatch(pay_batch_cmd);
+    let subcommand = StCtrlSubcommand::Buyer(buyer_cmd);
+
+    let st_ctrl_cmd = StCtrlCmd {
+        idfile: stctrl_setup.temp_dir_path.join("app1").join("app1.ident"),
+        node_ticket: stctrl_setup
+            .temp_dir_path
+            .join("node1")
+            .join("node1.ticket"),
+        socks5: None,
+        subcommand,
+    };
+
+    // The batch payment waits for receipts, so we run it in a separate thread.
+    // Failed payments are retried by running the batch payment again.
+    let batch_handle = thread::spawn(move || loop {
+        let mut output = Vec::new();
+        match stctrl(st_ctrl_cmd.clone(), &mut output) {
+            Ok(()) => break String::from_utf8(output).unwrap(),
+            Err(StCtrlError::BuyerError(BuyerError::BatchIncomplete)) => {}
+            Err(_) => unreachable!(),
+        }
+        thread::sleep(time::Duration::from_millis(100));
+    });
+
+    // Node0: Commit the invoices, once the commits are ready:
+    // ------------------------------------------------------
+    for (index, invoice_name) in ["batch1", "batch2"].iter().enumerate() {
+        let commit_invoice_cmd = CommitInvoiceCmd {
+            invoice_path: stctrl_setup
+                .temp_dir_path
+                .join("node0")
+                .join(format!("{}.invoice", invoice_name)),
+            commit_path: output_path.join(format!("{}.commit", index + 1)),
+        };
+
+        let seller_cmd = SellerCmd::CommitInvoice(commit_invoice_cmd);
+        let subcommand = StCtrlSubcommand::Seller(seller_cmd);
+
+        let st_ctrl_cmd = StCtrlCmd {
+            idfile: stctrl_setup.temp_dir_path.join("app0").join("app0.ident"),
+            node_ticket: stctrl_setup
+                .temp_dir_path
+                .join("node0")
+                .join("node0.ticket"),
+            socks5: None,
+            subcommand,
+        };
+
+        // The commit file might not be ready yet:
+        while stctrl(st_ctrl_cmd.clone(), &mut Vec::new()).is_err() {
+            thread::sleep(time::Duration::from_millis(100));
+        }
+    }
+
+    // Node1: Wait for the batch payment to complete:
+    let output = batch_handle.join().unwrap();
+    assert!(output.contains("Succeeded: 2, Failed: 0"));
+
+    for (index, invoice_name) in ["batch1", "batch2"].iter().enumerate() {
+        // Payment files are removed once receipts are obtained:
+        assert!(!output_path.join(format!("{}.payment", index + 1)).exists());
+
+        // Verify the receipt:
+        let verify_receipt_cmd = VerifyReceiptCmd {
+            invoice_path: stctrl_setup
+                .temp_dir_path
+                .join("node0")
+                .join(format!("{}.invoice", invoice_name)),
+            receipt_path: output_path.join(format!("{}.receipt", index + 1)),
+        };
+
+        let stverify_cmd = StVerifyCmd::VerifyReceipt(verify_receipt_cmd);
+        let mut output = Vec::new();
+        stverify(stverify_cmd, &mut output).unwrap();
+        assert!(str::from_utf8(&output).unwrap().contains("is valid!"));
+    }
+}
+
 /*
 /// View balance of node1
 fn check_balance(stctrl_setup: &StCtrlSetup) {
@@ -706,6 +931,7 @@ fn check_balance(stctrl_setup: &StCtrlSetup) {
             .temp_dir_path
             .join("node1")
             .join("node1.ticket"),
+        socks5: None,
         subcommand,
     };
 
@@ -731,6 +957,7 @@ fn export_token(stctrl_setup: &StCtrlSetup) {
             .temp_dir_path
             .join("node1")
             .join("node1.ticket"),
+        socks5: None,
         subcommand,
     };
     stctrl(st_ctrl_cmd, &mut Vec::new()).unwrap();
@@ -770,6 +997,7 @@ fn close_disable(stctrl_setup: &StCtrlSetup) {
                 .temp_dir_path
                 .join(format!("node{}", j))
                 .join(format!("node{}.ticket", j)),
+            socks5: None,
             subcommand,
         };
         stctrl(st_ctrl_cmd, &mut Vec::new()).unwrap();
@@ -795,6 +1023,7 @@ fn close_disable(stctrl_setup: &StCtrlSetup) {
                 .temp_dir_path
                 .join(format!("node{}", j))
                 .join(format!("node{}.ticket", j)),
+            socks5: None,
             subcommand,
         };
         stctrl(st_ctrl_cmd, &mut Vec::new()).unwrap();
@@ -817,6 +1046,7 @@ fn close_disable(stctrl_setup: &StCtrlSetup) {
                 .temp_dir_path
                 .join(format!("node{}", j))
                 .join(format!("node{}.ticket", j)),
+            socks5: None,
             subcommand,
         };
         stctrl(st_ctrl_cmd, &mut Vec::new()).unwrap();
@@ -837,6 +1067,7 @@ fn close_disable(stctrl_setup: &StCtrlSetup) {
                 .temp_dir_path
                 .join(format!("node{}", j))
                 .join(format!("node{}.ticket", j)),
+            socks5: None,
             subcommand,
         };
 
@@ -873,6 +1104,7 @@ fn basic_cli() {
     set_max_debt(&stctrl_setup);
     create_cancel_invoice(&stctrl_setup);
     pay_invoice(&stctrl_setup);
+    pay_batch(&stctrl_setup);
     // check_balance(&stctrl_setup);
     export_token(&stctrl_setup);
     close_disable(&stctrl_setup);
@@ -0,0 +1,466 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+use futures::channel::mpsc;
+use futures::StreamExt;
+
+use tempfile::tempdir;
+
+use common::conn::ConnPair;
+use common::test_executor::TestExecutor;
+
+use proto::app_server::messages::AppPermissions;
+use proto::funder::messages::{Currency, Rate};
+
+use timer::create_timer_incoming;
+
+use app::gen::gen_uid;
+
+use stcompact::compact_node::messages::{
+    AddFriend, AddSubscription, CompactToUser, CompactToUserAck, EnablePushPayments,
+    FriendLivenessReport, OpenFriendCurrency, PaymentDoneStatus, SetFriendCurrencyMaxDebt,
+    SetFriendCurrencyRate, SubscriptionStatus, UserToCompact,
+};
+
+use crate::compact_node_wrapper::send_request;
+use crate::sim_network::create_sim_network;
+use crate::utils::{
+    advance_time, create_compact_node, create_index_server, create_node, create_relay,
+    named_index_server_address, named_relay_address, node_public_key, relay_address, SimDb,
+};
+
+use crate::compact_report_service::compact_report_service;
+
+const TIMER_CHANNEL_LEN: usize = 0;
+
+async fn task_compact_node_subscription(mut test_executor: TestExecutor) {
+    let currency1 = Currency::try_from("FST1".to_owned()).unwrap();
+
+    // Create timer_client:
+    let (mut tick_sender, tick_receiver) = mpsc::channel(TIMER_CHANNEL_LEN);
+    let timer_client = create_timer_incoming(tick_receiver, test_executor.clone()).unwrap();
+
+    // Create a temporary directory.
+    // Should be deleted when gets out of scope:
+    let temp_dir = tempdir().unwrap();
+
+    // Create a database manager at the temporary directory:
+    let sim_db = SimDb::new(temp_dir.path().to_path_buf());
+
+    // A network simulator:
+    let sim_net_client = create_sim_network(&mut test_executor);
+
+    // Create initial database for node 0:
+    sim_db.init_node_db(0).unwrap();
+
+    let mut trusted_apps = HashMap::new();
+    trusted_apps.insert(
+        0,
+        AppPermissions {
+            routes: true,
+            buyer: true,
+            seller: true,
+            config: true,
+        },
+    );
+
+    create_node(
+        0,
+        sim_db.clone(),
+        timer_client.clone(),
+        sim_net_client.clone(),
+        trusted_apps,
+        test_executor.clone(),
+    )
+    .await
+    .forget();
+
+    let (compact_node0, compact_report0) = create_compact_node(
+        0,
+        sim_db.clone(),
+        sim_net_client.clone(),
+        timer_client.clone(),
+        0,
+        test_executor.clone(),
+    )
+    .await
+    .unwrap();
+
+    // Create initial database for node 1:
+    sim_db.init_node_db(1).unwrap();
+
+    let mut trusted_apps = HashMap::new();
+    trusted_apps.insert(
+        1,
+        AppPermissions {
+            routes: true,
+            buyer: true,
+            seller: true,
+            config: true,
+        },
+    );
+    create_node(
+        1,
+        sim_db.clone(),
+        timer_client.clone(),
+        sim_net_client.clone(),
+        trusted_apps,
+        test_executor.clone(),
+    )
+    .await
+    .forget();
+
+    let (compact_node1, compact_report1) = create_compact_node(
+        1,
+        sim_db.clone(),
+        sim_net_client.clone(),
+        timer_client.clone(),
+        1,
+        test_executor.clone(),
+    )
+    .await
+    .unwrap();
+
+    // Handle reports:
+    let (sender0, receiver0) = compact_node0.split();
+    let (receiver0, mut compact_report_client0) =
+        compact_report_service(compact_report0, receiver0, &test_executor);
+    let mut compact_node0 = ConnPair::from_raw(sender0, receiver0);
+
+    let (sender1, receiver1) = compact_node1.split();
+    let (receiver1, mut compact_report_client1) =
+        compact_report_service(compact_report1, receiver1, &test_executor);
+    let mut compact_node1 = ConnPair::from_raw(sender1, receiver1);
+
+    // Create relays:
+    create_relay(
+        0,
+        timer_client.clone(),
+        sim_net_client.clone(),
+        test_executor.clone(),
+    )
+    .await;
+
+    create_relay(
+        1,
+        timer_client.clone(),
+        sim_net_client.clone(),
+        test_executor.clone(),
+    )
+    .await;
+
+    // Create three index servers:
+    // 0 -- 2 -- 1
+    // The only way for information to flow between the two index servers
+    // is by having the middle server forward it.
+    create_index_server(
+        2,
+        timer_client.clone(),
+        sim_net_client.clone(),
+        vec![0, 1],
+        test_executor.clone(),
+    )
+    .await;
+
+    create_index_server(
+        0,
+        timer_client.clone(),
+        sim_net_client.clone(),
+        vec![2],
+        test_executor.clone(),
+    )
+    .await;
+
+    create_index_server(
+        1,
+        timer_client.clone(),
+        sim_net_client.clone(),
+        vec![2],
+        test_executor.clone(),
+    )
+    .await;
+
+    // Configure relays:
+    send_request(
+        &mut compact_node0,
+        UserToCompact::AddRelay(named_relay_address(0)),
+    )
+    .await
+    .unwrap();
+
+    send_request(
+        &mut compact_node1,
+        UserToCompact::AddRelay(named_relay_address(1)),
+    )
+    .await
+    .unwrap();
+
+    // Configure index servers:
+    send_request(
+        &mut compact_node0,
+        UserToCompact::AddIndexServer(named_index_server_address(0)),
+    )
+    .await
+    .unwrap();
+
+    send_request(
+        &mut compact_node1,
+        UserToCompact::AddIndexServer(named_index_server_address(1)),
+    )
+    .await
+    .unwrap();
+
+    // Wait some time:
+    advance_time(40, &mut tick_sender, &test_executor).await;
+
+    // Node0: Add Node1 as a friend:
+    let add_friend = AddFriend {
+        friend_public_key: node_public_key(1),
+        relays: vec![relay_address(1)],
+        name: "node1".to_owned(),
+    };
+    send_request(&mut compact_node0, UserToCompact::AddFriend(add_friend))
+        .await
+        .unwrap();
+
+    // Node1: Add Node0 as a friend:
+    let add_friend = AddFriend {
+        friend_public_key: node_public_key(0),
+        relays: vec![relay_address(0)],
+        name: "node0".to_owned(),
+    };
+    send_request(&mut compact_node1, UserToCompact::AddFriend(add_friend))
+        .await
+        .unwrap();
+
+    // Node0: Enable node1:
+    send_request(
+        &mut compact_node0,
+        UserToCompact::EnableFriend(node_public_key(1)),
+    )
+    .await
+    .unwrap();
+
+    // Node1: Enable node1:
+    send_request(
+        &mut compact_node1,
+        UserToCompact::EnableFriend(node_public_key(0)),
+    )
+    .await
+    .unwrap();
+
+    advance_time(10, &mut tick_sender, &test_executor).await;
+
+    // Wait until both sides see each other as online:
+    loop {
+        let compact_report0 = compact_report_client0.request_report().await;
+        let friend_report = match compact_report0.friends.get(&node_public_key(1)) {
+            None => continue,
+            Some(friend_report) => friend_report,
+        };
+        if friend_report.liveness == FriendLivenessReport::Online {
+            break;
+        }
+        advance_time(5, &mut tick_sender, &test_executor).await;
+    }
+
+    loop {
+        let compact_report1 = compact_report_client1.request_report().await;
+        let friend_report = match compact_report1.friends.get(&node_public_key(0)) {
+            None => continue,
+            Some(friend_report) => friend_report,
+        };
+        if friend_report.liveness == FriendLivenessReport::Online {
+            break;
+        }
+        advance_time(5, &mut tick_sender, &test_executor).await;
+    }
+
+    // Node0: Set active currencies for Node1:
+    for currency in [&currency1].iter() {
+        let set_friend_currency_rate = SetFriendCurrencyRate {
+            friend_public_key: node_public_key(1),
+            currency: (*currency).clone(),
+            rate: Rate::new(),
+        };
+        send_request(
+            &mut compact_node0,
+            UserToCompact::SetFriendCurrencyRate(set_friend_currency_rate),
+        )
+        .await
+        .unwrap();
+    }
+
+    // Node1: Set active currencies for Node0:
+    for currency in [&currency1].iter() {
+        let set_friend_currency_rate = SetFriendCurrencyRate {
+            friend_public_key: node_public_key(0),
+            currency: (*currency).clone(),
+            rate: Rate::new(),
+        };
+        send_request(
+            &mut compact_node1,
+            UserToCompact::SetFriendCurrencyRate(set_friend_currency_rate),
+        )
+        .await
+        .unwrap();
+    }
+
+    // Wait some time, to let the two nodes negotiate currencies:
+    advance_time(10, &mut tick_sender, &test_executor).await;
+
+    for currency in [&currency1].iter() {
+        // Node0: Open currency
+        let open_friend_currency = OpenFriendCurrency {
+            friend_public_key: node_public_key(1),
+            currency: (*currency).clone(),
+        };
+        send_request(
+            &mut compact_node0,
+            UserToCompact::OpenFriendCurrency(open_friend_currency),
+        )
+        .await
+        .unwrap();
+
+        // Node1: Open currency
+        let open_friend_currency = OpenFriendCurrency {
+            friend_public_key: node_public_key(0),
+            currency: (*currency).clone(),
+        };
+        send_request(
+            &mut compact_node1,
+            UserToCompact::OpenFriendCurrency(open_friend_currency),
+        )
+        .await
+        .unwrap();
+    }
+
+    // Wait some time, to let the index servers exchange information:
+    advance_time(10, &mut tick_sender, &test_executor).await;
+
+    // Node1 allows node0 to have maximum debt of currency1=100
+    let set_friend_currency_max_debt = SetFriendCurrencyMaxDebt {
+        friend_public_key: node_public_key(0),
+        currency: currency1.clone(),
+        remote_max_debt: 100,
+    };
+    send_request(
+        &mut compact_node1,
+        UserToCompact::SetFriendCurrencyMaxDebt(set_friend_currency_max_debt),
+    )
+    .await
+    .unwrap();
+
+    // Node1: Accept push payments:
+    let enable_push_payments = EnablePushPayments {
+        currency: currency1.clone(),
+        max_total_dest_payment: 20,
+    };
+    send_request(
+        &mut compact_node1,
+        UserToCompact::EnablePushPayments(enable_push_payments),
+    )
+    .await
+    .unwrap();
+
+    // Wait until the max debt was set:
+    advance_time(10, &mut tick_sender, &test_executor).await;
+
+    // Node0: Subscribe to Node1.
+    // Only one payment fits within `max_total_payment`:
+    let subscription_id = gen_uid();
+    let add_subscription = AddSubscription {
+        subscription_id: subscription_id.clone(),
+        currency: currency1.clone(),
+        dest_public_key: node_public_key(1),
+        dest_payment: 10,
+        max_total_payment: 15,
+        max_fees: 0,
+        period_secs: 3600,
+        description: "Monthly subscription".to_owned(),
+    };
+    send_request(
+        &mut compact_node0,
+        UserToCompact::AddSubscription(add_subscription),
+    )
+    .await
+    .unwrap();
+
+    // The first payment is started on the next timer tick:
+    advance_time(1, &mut tick_sender, &test_executor).await;
+
+    // Node0: Wait for the subscription payment to start:
+    let payment_id = loop {
+        let compact_to_user_ack = compact_node0.receiver.next().await.unwrap();
+        if let CompactToUserAck::CompactToUser(CompactToUser::SubscriptionPayment(
+            subscription_payment,
+        )) = compact_to_user_ack
+        {
+            assert_eq!(subscription_payment.subscription_id, subscription_id);
+            break subscription_payment.payment_id;
+        }
+    };
+
+    // Wait some time:
+    advance_time(5, &mut tick_sender, &test_executor).await;
+
+    // Node0: Wait for PaymentDone:
+    let ack_uid = loop {
+        let compact_to_user_ack = compact_node0.receiver.next().await.unwrap();
+        let payment_done =
+            if let CompactToUserAck::CompactToUser(CompactToUser::PaymentDone(payment_done)) =
+                compact_to_user_ack
+            {
+                payment_done
+            } else {
+                continue;
+            };
+        assert_eq!(payment_done.payment_id, payment_id);
+        match payment_done.status {
+            PaymentDoneStatus::Success(_receipt, fees, ack_uid) => {
+                assert_eq!(fees, 0);
+                break ack_uid;
+            }
+            PaymentDoneStatus::Failure(_) => unreachable!(),
+        };
+    };
+
+    // Node0: AckPaymentDone:
+    send_request(
+        &mut compact_node0,
+        UserToCompact::AckPaymentDone(payment_id, ack_uid),
+    )
+    .await
+    .unwrap();
+
+    // Let the subscription notice that the cap was reached:
+    advance_time(1, &mut tick_sender, &test_executor).await;
+
+    let compact_report0 = compact_report_client0.request_report().await;
+    let subscription = compact_report0.subscriptions.get(&subscription_id).unwrap();
+    assert_eq!(subscription.total_paid, 10);
+    assert_eq!(subscription.opt_payment_id, None);
+    assert_eq!(subscription.status, SubscriptionStatus::Exhausted);
+
+    // Node0: Cancel the subscription:
+    send_request(
+        &mut compact_node0,
+        UserToCompact::CancelSubscription(subscription_id.clone()),
+    )
+    .await
+    .unwrap();
+
+    let compact_report0 = compact_report_client0.request_report().await;
+    assert!(compact_report0
+        .subscriptions
+        .get(&subscription_id)
+        .is_none());
+}
+
+#[test]
+fn test_compact_node_subscription() {
+    // let _ = env_logger::init();
+    let test_executor = TestExecutor::new();
+    let res = test_executor.run(task_compact_node_subscription(test_executor.clone()));
+    assert!(res.is_output());
+}
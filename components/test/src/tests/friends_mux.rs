@@ -0,0 +1,168 @@
+use futures::channel::mpsc;
+use futures::task::SpawnExt;
+use futures::{future, FutureExt, SinkExt, StreamExt};
+
+use common::conn::{ConnPair, ConnPairVec, FutTransform};
+use common::test_executor::TestExecutor;
+
+use crypto::identity::{Identity, SoftwareEd25519Identity};
+use crypto::rand::RandGen;
+use crypto::test_utils::DummyRandom;
+
+use proto::consts::{KEEPALIVE_TICKS, TICKS_TO_REKEY};
+use proto::crypto::{PrivateKey, PublicKey};
+
+use identity::{create_identity, IdentityClient};
+
+use timer::{create_timer_incoming, TimerClient};
+
+use connection::create_encrypt_keepalive;
+use keepalive::KeepAliveChannel;
+use secure_channel::SecureChannel;
+
+const TIMER_CHANNEL_LEN: usize = 0;
+
+fn create_friend_identity(
+    index: u8,
+    test_executor: &TestExecutor,
+) -> (PublicKey, IdentityClient, DummyRandom) {
+    let mut rng = DummyRandom::new(&[0x13, 0x40, index]);
+    let private_key = PrivateKey::rand_gen(&mut rng);
+    let identity = SoftwareEd25519Identity::from_private_key(&private_key).unwrap();
+    let public_key = identity.get_public_key();
+    let (requests_sender, identity_server) = create_identity(identity);
+    test_executor
+        .spawn(identity_server.then(|_| future::ready(())))
+        .unwrap();
+    (public_key, IdentityClient::new(requests_sender), rng)
+}
+
+/// Encrypt and keepalive a friend connection, the way older nodes did:
+/// The connection is never multiplexed.
+async fn legacy_encrypt_keepalive(
+    identity_client: IdentityClient,
+    rng: DummyRandom,
+    timer_client: TimerClient,
+    remote_public_key: PublicKey,
+    conn_pair: ConnPairVec,
+    test_executor: TestExecutor,
+) -> Option<(PublicKey, ConnPairVec)> {
+    let mut encrypt_transform = SecureChannel::new(
+        identity_client,
+        rng,
+        timer_client.clone(),
+        TICKS_TO_REKEY,
+        None,
+        false,
+        false,
+        test_executor.clone(),
+    );
+    let mut keepalive_transform =
+        KeepAliveChannel::new(timer_client, KEEPALIVE_TICKS, test_executor);
+
+    let (public_key, multiplexed, conn_pair) = encrypt_transform
+        .transform((Some(remote_public_key), conn_pair))
+        .await?;
+    assert!(!multiplexed);
+    let conn_pair = keepalive_transform.transform(conn_pair).await;
+    Some((public_key, conn_pair))
+}
+
+/// Send messages in both directions between two connected friends, and then close the
+/// connection.
+async fn exchange_messages(conn_pair0: ConnPairVec, conn_pair1: ConnPairVec) {
+    let (mut sender0, mut receiver0) = conn_pair0.split();
+    let (mut sender1, mut receiver1) = conn_pair1.split();
+
+    for i in 0..0x40u8 {
+        sender0.send(vec![i; 0x100]).await.unwrap();
+        assert_eq!(receiver1.next().await.unwrap(), vec![i; 0x100]);
+
+        sender1.send(vec![i]).await.unwrap();
+        assert_eq!(receiver0.next().await.unwrap(), vec![i]);
+    }
+
+    // Closing the connection on one side closes it on the other side:
+    drop(sender1);
+    drop(receiver1);
+    assert!(receiver0.next().await.is_none());
+}
+
+async fn task_friends_mux(test_executor: TestExecutor) {
+    // Create timer_client:
+    let (_tick_sender, tick_receiver) = mpsc::channel(TIMER_CHANNEL_LEN);
+    let timer_client = create_timer_incoming(tick_receiver, test_executor.clone()).unwrap();
+
+    let (public_key0, identity_client0, rng0) = create_friend_identity(0, &test_executor);
+    let (public_key1, identity_client1, rng1) = create_friend_identity(1, &test_executor);
+
+    let mut encrypt_keepalive0 = create_encrypt_keepalive(
+        timer_client.clone(),
+        identity_client0,
+        rng0,
+        None,
+        false,
+        test_executor.clone(),
+    );
+    let mut encrypt_keepalive1 = create_encrypt_keepalive(
+        timer_client.clone(),
+        identity_client1.clone(),
+        rng1.clone(),
+        None,
+        false,
+        test_executor.clone(),
+    );
+
+    // Both friends support multiplexing.
+    // The funder messages are sent over a multiplexed stream:
+    let (sender0, receiver1) = mpsc::channel::<Vec<u8>>(0);
+    let (sender1, receiver0) = mpsc::channel::<Vec<u8>>(0);
+    let (output0, output1) = future::join(
+        encrypt_keepalive0.transform((
+            Some(public_key1.clone()),
+            ConnPair::from_raw(sender0, receiver0),
+        )),
+        encrypt_keepalive1.transform((
+            Some(public_key0.clone()),
+            ConnPair::from_raw(sender1, receiver1),
+        )),
+    )
+    .await;
+    let (remote_public_key0, conn_pair0) = output0.unwrap();
+    let (remote_public_key1, conn_pair1) = output1.unwrap();
+    assert_eq!(remote_public_key0, public_key1);
+    assert_eq!(remote_public_key1, public_key0);
+    exchange_messages(conn_pair0, conn_pair1).await;
+
+    // Friend 1 runs an older version, and does not support multiplexing.
+    // Friend 0 falls back to a single stream:
+    let (sender0, receiver1) = mpsc::channel::<Vec<u8>>(0);
+    let (sender1, receiver0) = mpsc::channel::<Vec<u8>>(0);
+    let (output0, output1) = future::join(
+        encrypt_keepalive0.transform((
+            Some(public_key1.clone()),
+            ConnPair::from_raw(sender0, receiver0),
+        )),
+        legacy_encrypt_keepalive(
+            identity_client1,
+            rng1,
+            timer_client.clone(),
+            public_key0.clone(),
+            ConnPair::from_raw(sender1, receiver1),
+            test_executor.clone(),
+        ),
+    )
+    .await;
+    let (remote_public_key0, conn_pair0) = output0.unwrap();
+    let (remote_public_key1, conn_pair1) = output1.unwrap();
+    assert_eq!(remote_public_key0, public_key1);
+    assert_eq!(remote_public_key1, public_key0);
+    exchange_messages(conn_pair0, conn_pair1).await;
+}
+
+#[test]
+fn test_friends_mux() {
+    let test_executor = TestExecutor::new();
+    let res = test_executor.run(task_friends_mux(test_executor.clone()));
+    assert!(res.is_output());
+}
@@ -1,5 +1,7 @@
 mod compact_node_payment;
+mod compact_node_subscription;
 mod compact_server_remote_node;
+mod friends_mux;
 mod handle_error_command;
 mod nodes_chain;
 mod relay_migration;
@@ -4,7 +4,7 @@ use std::convert::TryFrom;
 use funder::FunderState;
 use proto::file::{
     FriendAddressFile, FriendFile, IdentityFile, IndexServerFile, NodeAddressFile,
-    RelayAddressFile, TrustedAppFile,
+    RelayAddressFile, RelayListenerFile, TrustedAppFile,
 };
 use proto::funder::messages::Currency;
 use proto::net::messages::NetAddress;
@@ -42,6 +42,7 @@ ser_de_test!(qc_ser_de_identity_file, IdentityFile);
 ser_de_test!(qc_ser_de_index_server_file, IndexServerFile);
 ser_de_test!(qc_ser_de_node_address_file, NodeAddressFile);
 ser_de_test!(qc_ser_de_relay_address_file, RelayAddressFile);
+ser_de_test!(qc_ser_de_relay_listener_file, RelayListenerFile);
 ser_de_test!(qc_ser_de_trusted_app_file, TrustedAppFile);
 
 ser_de_test!(qc_ser_de_compact_state, CompactState);
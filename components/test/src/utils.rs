@@ -1,10 +1,10 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::PathBuf;
 
 use futures::channel::mpsc;
 use futures::future::RemoteHandle;
 use futures::task::{Spawn, SpawnExt};
-use futures::{future, FutureExt, SinkExt, TryFutureExt};
+use futures::{future, stream, FutureExt, SinkExt, TryFutureExt};
 
 use crypto::identity::{Identity, SoftwareEd25519Identity};
 
@@ -13,7 +13,7 @@ use crypto::test_utils::DummyRandom;
 
 use common::test_executor::TestExecutor;
 
-use common::conn::{BoxFuture, ConnPair};
+use common::conn::{BoxFuture, ConnPair, ConnPairVec};
 
 use proto::crypto::{PrivateKey, PublicKey};
 
@@ -23,12 +23,14 @@ use proto::index_server::messages::NamedIndexServerAddress;
 use proto::net::messages::NetAddress;
 
 use identity::{create_identity, IdentityClient};
+use index_server::{ClientRateLimits, IndexGraphSnapshot, TokenBucketConfig};
 
 use app::conn::AppConnTuple;
 use app_client::app_connect_to_node;
 use connection::create_secure_connector;
 
 use node::{NodeConfig, NodeState};
+use relay::AllowAllListeners;
 
 use database::file_db::FileDb;
 use database::{database_loop, AtomicDb, DatabaseClient};
@@ -56,6 +58,12 @@ const BACKOFF_TICKS: usize = 0x8;
 /// Maximum amount of encryption set ups (diffie hellman) that we allow to occur at the same
 /// time.
 const MAX_CONCURRENT_ENCRYPT: usize = 0x8;
+/// The amount of ticks between two snapshots of an index server's graphs
+const SNAPSHOT_TICKS: usize = 0x40;
+/// The amount of ticks an index server keeps edges restored from a snapshot without updates
+const STALE_TICKS: usize = 0x40;
+/// The amount of ticks between two announcements of an index server's trusted servers
+const ANNOUNCE_TICKS: usize = 0x40;
 /// The size we allocate for the user send funds requests queue.
 const MAX_PENDING_USER_REQUESTS: usize = 0x20;
 /// Maximum amount of concurrent index client requests:
@@ -133,6 +141,8 @@ fn default_node_config() -> NodeConfig {
         max_open_index_client_requests: MAX_OPEN_INDEX_CLIENT_REQUESTS,
         /// Maximum amount of relays a node may use.
         max_node_relays: MAX_NODE_RELAYS,
+        /// Addresses where we accept direct connections from friends.
+        direct_addresses: Vec::new(),
         /*
         /// Maximum amount of incoming app connections we set up at the same time
         max_concurrent_incoming_apps: MAX_CONCURRENT_INCOMING_APPS,
@@ -291,7 +301,7 @@ where
     let app_conn_tuple = create_app(
         app_index,
         sim_network_client,
-        timer_client,
+        timer_client.clone(),
         node_index,
         spawner.clone(),
     )
@@ -332,6 +342,7 @@ where
         compact_state,
         database_client,
         compact_gen,
+        timer_client,
     );
     spawner
         .spawn(compact_fut.map(|e| error!("compact_node() error: {:?}", e)))
@@ -374,6 +385,7 @@ where
         timer_client,
         rng,
         sim_network_client,
+        true,
         spawner.clone(),
     );
 
@@ -448,11 +460,14 @@ where
     // Simulating the passage of time becomes more difficult if our code uses a few different executors.
     let net_node_fut = net_node(
         incoming_app_raw_conns,
+        stream::empty(),
         sim_network_client,
         timer_client,
         identity_client,
         rng,
         default_node_config(),
+        None,
+        true,
         dummy_trusted_apps,
         node_state,
         database_client,
@@ -464,6 +479,21 @@ where
     spawner.spawn_with_handle(net_node_fut).unwrap()
 }
 
+/// Client rate limits for test index servers.
+/// Large enough to never be reached during the tests.
+fn test_client_rate_limits() -> ClientRateLimits {
+    ClientRateLimits {
+        request_routes: TokenBucketConfig {
+            capacity: 0x1000,
+            refill_per_tick: 0x1000,
+        },
+        mutations_update: TokenBucketConfig {
+            capacity: 0x1000,
+            refill_per_tick: 0x1000,
+        },
+    }
+}
+
 pub async fn create_index_server<S>(
     index: u8,
     timer_client: TimerClient,
@@ -504,6 +534,8 @@ pub async fn create_index_server<S>(
     let net_index_server_fut = net_index_server(
         incoming_client_raw_conns,
         incoming_server_raw_conns,
+        stream::empty::<ConnPairVec>(),
+        HashSet::new(),
         sim_network_client,
         identity_client,
         timer_client,
@@ -511,6 +543,13 @@ pub async fn create_index_server<S>(
         trusted_servers,
         MAX_CONCURRENT_ENCRYPT,
         BACKOFF_TICKS,
+        test_client_rate_limits(),
+        ANNOUNCE_TICKS,
+        IndexGraphSnapshot::new(),
+        STALE_TICKS,
+        SNAPSHOT_TICKS,
+        None,
+        future::pending(),
         spawner.clone(),
         spawner.clone(),
     )
@@ -541,6 +580,7 @@ pub async fn create_relay<S>(
         timer_client,
         rng,
         MAX_CONCURRENT_ENCRYPT,
+        AllowAllListeners,
         spawner.clone(),
     )
     .map_err(|e| error!("net_relay_server() error: {:?}", e))
@@ -13,4 +13,4 @@ extern crate log;
 
 mod version_prefix;
 
-pub use self::version_prefix::VersionPrefix;
+pub use self::version_prefix::{InvalidVersionRange, VersionPrefix, VersionRange};
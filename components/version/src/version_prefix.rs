@@ -1,119 +1,186 @@
 use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
-use futures::channel::mpsc;
-use futures::task::{Spawn, SpawnExt};
-use futures::{future, SinkExt, StreamExt};
+use futures::{SinkExt, StreamExt};
 
 use common::conn::{BoxFuture, ConnPairVec, FutTransform};
 
-/// Prefix a communication session (Of Vec<u8>) with each side declaring his version.
-/// If the local version does not match the stated remote version, the connection is closed.
-#[derive(Clone)]
-pub struct VersionPrefix<S> {
-    local_version: u32,
-    spawner: S,
+/// Length of a version message that declares a range of supported versions:
+/// (min_version, max_version)
+const VERSION_RANGE_LEN: usize = 8;
+/// Length of a version message sent by older nodes, declaring one single version.
+const LEGACY_VERSION_LEN: usize = 4;
+
+/// A version range where the minimal version is larger than the maximal version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidVersionRange;
+
+/// An inclusive range of supported protocol versions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionRange {
+    pub min_version: u32,
+    pub max_version: u32,
 }
 
-impl<S> VersionPrefix<S>
-where
-    S: Spawn,
-{
-    pub fn new(local_version: u32, spawner: S) -> Self {
-        VersionPrefix {
-            local_version,
-            spawner,
+impl VersionRange {
+    pub fn new(min_version: u32, max_version: u32) -> Result<Self, InvalidVersionRange> {
+        if min_version > max_version {
+            return Err(InvalidVersionRange);
         }
+        Ok(VersionRange {
+            min_version,
+            max_version,
+        })
+    }
+
+    /// Older nodes expect the remote side to declare exactly one version, using a 4 bytes
+    /// message. We keep using this format when we support only one version, so that we can still
+    /// communicate with older nodes that speak this version.
+    fn to_bytes(self) -> Vec<u8> {
+        let mut version_data = Vec::new();
+        if self.min_version == self.max_version {
+            version_data
+                .write_u32::<BigEndian>(self.min_version)
+                .unwrap();
+            return version_data;
+        }
+        version_data
+            .write_u32::<BigEndian>(self.min_version)
+            .unwrap();
+        version_data
+            .write_u32::<BigEndian>(self.max_version)
+            .unwrap();
+        version_data
+    }
+
+    fn from_bytes(version_data: &[u8]) -> Option<Self> {
+        match version_data.len() {
+            VERSION_RANGE_LEN => {
+                let min_version = BigEndian::read_u32(&version_data[0..4]);
+                let max_version = BigEndian::read_u32(&version_data[4..8]);
+                VersionRange::new(min_version, max_version).ok()
+            }
+            // A remote node that only knows about one single version:
+            LEGACY_VERSION_LEN => {
+                let version = BigEndian::read_u32(version_data);
+                VersionRange::new(version, version).ok()
+            }
+            _ => None,
+        }
+    }
+
+    /// Find the highest version supported by both sides.
+    /// Both sides of the connection run the same computation, and therefore agree on the
+    /// result. Returns None if there is no common version.
+    pub fn negotiate(&self, remote: &VersionRange) -> Option<u32> {
+        let highest = std::cmp::min(self.max_version, remote.max_version);
+        let lowest = std::cmp::max(self.min_version, remote.min_version);
+        if highest < lowest {
+            return None;
+        }
+        Some(highest)
+    }
+}
+
+/// Prefix a communication session (Of Vec<u8>) with each side declaring the range of versions he
+/// supports. Both sides then agree on the highest common version, which is returned together
+/// with the connection. If there is no common version, the connection is closed.
+#[derive(Clone)]
+pub struct VersionPrefix {
+    local_range: VersionRange,
+}
+
+impl VersionPrefix {
+    pub fn new(min_version: u32, max_version: u32) -> Result<Self, InvalidVersionRange> {
+        Ok(VersionPrefix {
+            local_range: VersionRange::new(min_version, max_version)?,
+        })
     }
 
-    pub fn spawn_prefix(&mut self, conn_pair: ConnPairVec) -> ConnPairVec {
+    /// Exchange supported version ranges with the remote side.
+    /// Returns the negotiated version together with the remaining connection.
+    pub async fn negotiate(&self, conn_pair: ConnPairVec) -> Option<(u32, ConnPairVec)> {
         let (mut sender, mut receiver) = conn_pair.split();
 
-        let (user_sender, from_user_sender) = mpsc::channel(0);
-        let (mut to_user_receiver, user_receiver) = mpsc::channel(0);
+        // First send our supported versions range to the remote side:
+        if sender.send(self.local_range.to_bytes()).await.is_err() {
+            warn!("Failed to send version information");
+            return None;
+        }
 
-        let local_version = self.local_version;
-        let sender_fut = async move {
-            // First send our protocol version to the remote side:
-            let mut version_data = Vec::new();
-            version_data.write_u32::<BigEndian>(local_version).unwrap();
-            if sender.send(version_data).await.is_err() {
-                warn!("Failed to send version information");
-                return;
+        // Expect version range to be the first received data:
+        let version_data = match receiver.next().await {
+            Some(version_data) => version_data,
+            None => {
+                warn!("Failed to receive version information");
+                return None;
             }
-            // Next send any other message from the user:
-            let mut from_user_sender = from_user_sender.map(Ok);
-            let _ = sender.send_all(&mut from_user_sender).await;
         };
-        // If spawning fails, the user will find out when he tries to send
-        // through user_sender.
-        let _ = self.spawner.spawn(sender_fut);
-
-        let receiver_fut = async move {
-            // Expect version to be the first sent data:
-            let version_data = match receiver.next().await {
-                Some(version_data) => version_data,
-                _ => {
-                    warn!("Failed to receive version information");
-                    return;
-                }
-            };
-
-            if version_data.len() != 4 {
-                warn!("Invalid version_data length");
-                return;
-            }
 
-            let remote_version = BigEndian::read_u32(&version_data);
-            if remote_version != local_version {
-                warn!("Invalid remote version: {}", remote_version);
-                return;
+        let remote_range = match VersionRange::from_bytes(&version_data) {
+            Some(remote_range) => remote_range,
+            None => {
+                warn!("Invalid version_data");
+                return None;
             }
+        };
 
-            let mut receiver = receiver.map(Ok);
-            let _ = to_user_receiver.send_all(&mut receiver).await;
+        let version = match self.local_range.negotiate(&remote_range) {
+            Some(version) => version,
+            None => {
+                warn!(
+                    "No common version. local: {:?}, remote: {:?}",
+                    self.local_range, remote_range
+                );
+                return None;
+            }
         };
-        // If spawning fails, the user will find out when he tries to read
-        // from user_receiver.
-        if let Err(e) = self.spawner.spawn(receiver_fut) {
-            error!("VersionPrefix::spawn_prefix(): spawn() failed: {:?}", e);
-        }
 
-        ConnPairVec::from_raw(user_sender, user_receiver)
+        Some((version, ConnPairVec::from_box(sender, receiver)))
     }
 }
 
-impl<S> FutTransform for VersionPrefix<S>
-where
-    S: Spawn + Send,
-{
+impl FutTransform for VersionPrefix {
     type Input = ConnPairVec;
-    type Output = ConnPairVec;
+    type Output = Option<(u32, ConnPairVec)>;
 
     fn transform(&mut self, input: Self::Input) -> BoxFuture<'_, Self::Output> {
-        Box::pin(future::ready(self.spawn_prefix(input)))
+        Box::pin(self.negotiate(input))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use futures::executor::{LocalPool, ThreadPool};
+    use futures::channel::mpsc;
+    use futures::executor::LocalPool;
+    use futures::future::join;
 
-    async fn task_version_prefix_match<S>(spawner: S)
-    where
-        S: Spawn,
-    {
+    /// Create a connected pair of connections, negotiate versions on both sides.
+    async fn negotiate_pair(
+        a_prefix: VersionPrefix,
+        b_prefix: VersionPrefix,
+    ) -> (Option<(u32, ConnPairVec)>, Option<(u32, ConnPairVec)>) {
         let (a_sender, b_receiver) = mpsc::channel(0);
         let (b_sender, a_receiver) = mpsc::channel(0);
 
+        join(
+            a_prefix.negotiate(ConnPairVec::from_raw(a_sender, a_receiver)),
+            b_prefix.negotiate(ConnPairVec::from_raw(b_sender, b_receiver)),
+        )
+        .await
+    }
+
+    async fn task_version_prefix_match() {
         // Both A and B use version 3:
-        let mut version_prefix_3 = VersionPrefix::new(3u32, spawner);
+        let version_prefix_3 = VersionPrefix::new(3, 3).unwrap();
 
-        let (mut a_sender, mut a_receiver) = version_prefix_3
-            .spawn_prefix(ConnPairVec::from_raw(a_sender, a_receiver))
-            .split();
-        let (mut b_sender, mut b_receiver) = version_prefix_3
-            .spawn_prefix(ConnPairVec::from_raw(b_sender, b_receiver))
-            .split();
+        let (opt_a, opt_b) = negotiate_pair(version_prefix_3.clone(), version_prefix_3).await;
+        let (a_version, a_conn_pair) = opt_a.unwrap();
+        let (b_version, b_conn_pair) = opt_b.unwrap();
+        assert_eq!(a_version, 3);
+        assert_eq!(b_version, 3);
+
+        let (mut a_sender, mut a_receiver) = a_conn_pair.split();
+        let (mut b_sender, mut b_receiver) = b_conn_pair.split();
 
         // We expect the connection to work correctly, as the versions match:
         a_sender.send(vec![1, 2, 3]).await.unwrap();
@@ -125,39 +192,118 @@ mod tests {
 
     #[test]
     fn test_version_prefix_match() {
-        let thread_pool = ThreadPool::new().unwrap();
-        LocalPool::new().run_until(task_version_prefix_match(thread_pool.clone()));
+        LocalPool::new().run_until(task_version_prefix_match());
     }
 
-    async fn task_version_prefix_mismatch<S>(spawner: S)
-    where
-        S: Spawn + Clone,
-    {
-        let (a_sender, b_receiver) = mpsc::channel(0);
-        let (b_sender, a_receiver) = mpsc::channel(0);
-
+    async fn task_version_prefix_mismatch() {
         // Version mismatch between A and B:
-        let mut version_prefix_3 = VersionPrefix::new(3u32, spawner.clone());
-        let mut version_prefix_4 = VersionPrefix::new(4u32, spawner);
-
-        let (mut a_sender, _a_receiver) = version_prefix_3
-            .spawn_prefix(ConnPairVec::from_raw(a_sender, a_receiver))
-            .split();
-        let (_b_sender, mut b_receiver) = version_prefix_4
-            .spawn_prefix(ConnPairVec::from_raw(b_sender, b_receiver))
-            .split();
+        let (opt_a, opt_b) = negotiate_pair(
+            VersionPrefix::new(3, 3).unwrap(),
+            VersionPrefix::new(4, 4).unwrap(),
+        )
+        .await;
 
         // We expect the connection to be closed because of version mismatch:
-        let _ = a_sender.send(vec![1, 2, 3]).await;
-        assert!(b_receiver.next().await.is_none());
-
-        // b_sender.send(vec![3, 2, 1]).await.unwrap();
-        // assert!(a_receiver.next().await.is_none());
+        assert!(opt_a.is_none());
+        assert!(opt_b.is_none());
     }
 
     #[test]
     fn test_version_prefix_mismatch() {
-        let thread_pool = ThreadPool::new().unwrap();
-        LocalPool::new().run_until(task_version_prefix_mismatch(thread_pool.clone()));
+        LocalPool::new().run_until(task_version_prefix_mismatch());
+    }
+
+    async fn task_version_prefix_highest_common() {
+        let (opt_a, opt_b) = negotiate_pair(
+            VersionPrefix::new(1, 5).unwrap(),
+            VersionPrefix::new(3, 7).unwrap(),
+        )
+        .await;
+
+        // Both sides agree on the highest common version:
+        assert_eq!(opt_a.unwrap().0, 5);
+        assert_eq!(opt_b.unwrap().0, 5);
+    }
+
+    #[test]
+    fn test_version_prefix_highest_common() {
+        LocalPool::new().run_until(task_version_prefix_highest_common());
+    }
+
+    async fn task_version_prefix_legacy_remote() {
+        let (a_sender, mut b_receiver) = mpsc::channel(0);
+        let (mut b_sender, a_receiver) = mpsc::channel(0);
+
+        let version_prefix = VersionPrefix::new(0, 2).unwrap();
+        let negotiate_fut = version_prefix.negotiate(ConnPairVec::from_raw(a_sender, a_receiver));
+
+        // The remote side is an older node, declaring a single version:
+        let remote_fut = async move {
+            let mut version_data = Vec::new();
+            version_data.write_u32::<BigEndian>(1).unwrap();
+            b_sender.send(version_data).await.unwrap();
+            b_receiver.next().await.unwrap()
+        };
+
+        let (opt_a, remote_received) = join(negotiate_fut, remote_fut).await;
+        assert_eq!(opt_a.unwrap().0, 1);
+        assert_eq!(
+            VersionRange::from_bytes(&remote_received).unwrap(),
+            VersionRange::new(0, 2).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_version_prefix_legacy_remote() {
+        LocalPool::new().run_until(task_version_prefix_legacy_remote());
+    }
+
+    async fn task_version_prefix_single_version_legacy_format() {
+        let (a_sender, mut b_receiver) = mpsc::channel(0);
+        let (mut b_sender, a_receiver) = mpsc::channel(0);
+
+        let version_prefix = VersionPrefix::new(0, 0).unwrap();
+        let negotiate_fut = version_prefix.negotiate(ConnPairVec::from_raw(a_sender, a_receiver));
+
+        // The remote side is an older node, expecting exactly 4 bytes of version:
+        let remote_fut = async move {
+            let mut version_data = Vec::new();
+            version_data.write_u32::<BigEndian>(0).unwrap();
+            b_sender.send(version_data).await.unwrap();
+            b_receiver.next().await.unwrap()
+        };
+
+        let (opt_a, remote_received) = join(negotiate_fut, remote_fut).await;
+        assert_eq!(opt_a.unwrap().0, 0);
+        assert_eq!(remote_received, vec![0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_version_prefix_single_version_legacy_format() {
+        LocalPool::new().run_until(task_version_prefix_single_version_legacy_format());
+    }
+
+    #[test]
+    fn test_version_range_invalid() {
+        assert_eq!(VersionRange::new(3, 2), Err(InvalidVersionRange));
+        assert!(VersionPrefix::new(3, 2).is_err());
+
+        let mut version_data = Vec::new();
+        version_data.write_u32::<BigEndian>(3).unwrap();
+        version_data.write_u32::<BigEndian>(2).unwrap();
+        assert_eq!(VersionRange::from_bytes(&version_data), None);
+    }
+
+    #[test]
+    fn test_version_range_negotiate() {
+        let range = VersionRange::new(2, 4).unwrap();
+        let negotiate = |min_version, max_version| {
+            range.negotiate(&VersionRange::new(min_version, max_version).unwrap())
+        };
+        assert_eq!(negotiate(0, 1), None);
+        assert_eq!(negotiate(0, 2), Some(2));
+        assert_eq!(negotiate(3, 3), Some(3));
+        assert_eq!(negotiate(0, 9), Some(4));
+        assert_eq!(negotiate(5, 9), None);
     }
 }